@@ -3,11 +3,21 @@
 
 use std::{
     env,
-    path::PathBuf,
+    path::{
+        Path,
+        PathBuf,
+    },
     process::Command,
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
 };
 
 fn main() {
+    emit_build_metadata();
+    emit_panic_reboot_secs();
+
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let src_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
 
@@ -49,3 +59,89 @@ fn main() {
         println!("cargo:rustc-link-arg={}", obj_file.display());
     }
 }
+
+/// Export the git commit hash and build timestamp to the kernel crate
+///
+/// Both values are exposed as `KERNEL_GIT_HASH` and `KERNEL_BUILD_TIME`
+/// through `cargo:rustc-env`. Builds outside a git checkout fall back to
+/// `unknown` instead of failing.
+fn emit_build_metadata() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let build_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| format_utc(d.as_secs()))
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    println!("cargo:rustc-env=KERNEL_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=KERNEL_BUILD_TIME={}", build_time);
+
+    // Pick up new commits without requiring a clean build
+    let git_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("../.git");
+    rerun_if_git_head_changed(&git_dir);
+}
+
+/// Export the panic reboot countdown as `KERNEL_PANIC_REBOOT_SECS`
+///
+/// It is set with `--cfg panic_reboot_secs="N"` in the rustflags, which
+/// cargo hands to build scripts as `CARGO_CFG_PANIC_REBOOT_SECS`. Without
+/// it the countdown is 0 and the kernel halts on panic.
+fn emit_panic_reboot_secs() {
+    let secs = env::var("CARGO_CFG_PANIC_REBOOT_SECS").unwrap_or_else(|_| "0".to_string());
+    if secs.parse::<u64>().is_err() {
+        panic!(
+            "panic_reboot_secs must be a number of seconds, got {:?}",
+            secs
+        );
+    }
+    println!("cargo:rustc-env=KERNEL_PANIC_REBOOT_SECS={}", secs);
+}
+
+/// Emit `rerun-if-changed` for `.git/HEAD` and the ref it points to
+fn rerun_if_git_head_changed(git_dir: &Path) {
+    let head = git_dir.join("HEAD");
+    if !head.exists() {
+        return;
+    }
+    println!("cargo:rerun-if-changed={}", head.display());
+
+    if let Ok(contents) = std::fs::read_to_string(&head) {
+        if let Some(reference) = contents.trim().strip_prefix("ref: ") {
+            let ref_path = git_dir.join(reference);
+            if ref_path.exists() {
+                println!("cargo:rerun-if-changed={}", ref_path.display());
+            }
+        }
+    }
+}
+
+/// Format seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`
+fn format_utc(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (hour, minute, second) = (rem / 3600, (rem % 3600) / 60, rem % 60);
+
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
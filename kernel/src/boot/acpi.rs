@@ -0,0 +1,29 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ACPI table discovery
+//!
+//! The bootloader hands over a copy of the RSDP (see
+//! `Multiboot2Info::acpi_rsdp`), which points at the root table listing
+//! all other ACPI tables. Walking that table is not implemented yet.
+
+use crate::memory::PhysAddr;
+
+/// Parses the root ACPI table (RSDT or XSDT) at `addr`
+///
+/// This is a stub: it only records the address. Devices such as the HPET
+/// are still probed at fixed addresses.
+pub fn parse_rsdt(addr: PhysAddr) {
+    crate::log_debug!("ACPI root table at {:#x} not parsed yet", addr.as_u64());
+}
@@ -1,4 +1,5 @@
 global _start
+global boot_p4_table
 extern kernel_main
 
 section .boot
@@ -130,15 +131,16 @@ enable_paging:
     or eax, 1 << 5
     mov cr4, eax
 
-    ; Enable Long mode
+    ; Enable Long mode and no-execute pages (EFER.LME, EFER.NXE)
     mov ecx, 0xC0000080
     rdmsr
-    or eax, 1 << 8
+    or eax, (1 << 8) | (1 << 11)
     wrmsr
 
-    ; Enable paging
+    ; Enable paging and write protection, so that read-only pages such as
+    ; guard pages also fault on writes from ring 0
     mov eax, cr0
-    or eax, 1 << 31
+    or eax, (1 << 31) | (1 << 16)
     mov cr0, eax
 
     ret
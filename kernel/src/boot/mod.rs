@@ -16,11 +16,21 @@
 ///
 /// This module contains boot protocol implementations and
 /// early initialization code.
+pub mod acpi;
 pub mod multiboot2;
+pub mod phase;
 
 #[allow(unused_imports)]
 pub use multiboot2::{
+    AcpiRsdp,
+    FramebufferInfo,
     MemoryRegion,
     MemoryRegionType,
     Multiboot2Info,
 };
+pub use phase::{
+    BootPhase,
+    boot_phase,
+    phases_reached_in_order,
+    set_boot_phase,
+};
@@ -3,17 +3,55 @@
 //! This module provides types and functions for extracting information
 //! passed by the bootloader (GRUB2) via Multiboot2 protocol.
 
+use crate::memory::{
+    PhysAddr,
+    PhysFrame,
+    PhysRange,
+};
+
 /// Multiboot2 magic number (passed in EAX by bootloader)
 pub const MULTIBOOT2_MAGIC: u32 = 0x36d76289;
 
+/// Tag type terminating the tag list
+pub const TAG_END: u32 = 0;
+/// Tag type of the boot command line
+pub const TAG_BOOT_CMDLINE: u32 = 1;
+/// Tag type of a boot module loaded alongside the kernel
+pub const TAG_MODULE: u32 = 3;
+/// Tag type of the basic memory information
+pub const TAG_BASIC_MEMINFO: u32 = 4;
+/// Tag type of the memory map
+pub const TAG_MEMORY_MAP: u32 = 6;
+/// Tag type of the framebuffer information
+pub const TAG_FRAMEBUFFER: u32 = 8;
+/// Tag type of a copy of the ACPI 1.0 RSDP
+pub const TAG_ACPI_OLD_RSDP: u32 = 14;
+/// Tag type of a copy of the ACPI 2.0 RSDP
+pub const TAG_ACPI_NEW_RSDP: u32 = 15;
+
+/// Size of the fixed header preceding the tags (total_size, reserved)
+const INFO_HEADER_SIZE: usize = 8;
+
+/// Size of a memory map entry as defined by the specification
+const MMAP_ENTRY_MIN_SIZE: usize = 24;
+
+/// Size of the ACPI 1.0 RSDP, covered by its `checksum`
+const RSDP_V1_SIZE: usize = 20;
+
+/// Size of the ACPI 2.0 RSDP, covered by its `extended_checksum`
+const RSDP_V2_SIZE: usize = 36;
+
 /// Multiboot2 information structure
-pub struct Multiboot2Info {
-    /// Address of boot information structure passed by bootloader
-    #[allow(dead_code)]
-    info_addr: usize,
+///
+/// Wraps the boot information as a byte slice; all fields are read with
+/// bounds checks, so a malformed structure yields missing values rather
+/// than out-of-bounds reads.
+pub struct Multiboot2Info<'a> {
+    /// Boot information, `total_size` bytes long
+    bytes: &'a [u8],
 }
 
-impl Multiboot2Info {
+impl Multiboot2Info<'static> {
     /// Initialize from magic number and address
     ///
     /// # Safety
@@ -29,31 +67,251 @@ impl Multiboot2Info {
         if info_addr == 0 || (info_addr & 0x7) != 0 {
             return None;
         }
-        Some(Self { info_addr })
+
+        // SAFETY: the caller guarantees a valid structure at `info_addr`,
+        // which starts with its own size in bytes.
+        let total_size = unsafe { core::ptr::read(info_addr as *const u32) } as usize;
+        if total_size < INFO_HEADER_SIZE {
+            return None;
+        }
+        // SAFETY: the structure spans `total_size` bytes from `info_addr`
+        let bytes = unsafe { core::slice::from_raw_parts(info_addr as *const u8, total_size) };
+        Self::from_bytes(bytes)
+    }
+}
+
+impl<'a> Multiboot2Info<'a> {
+    /// Initialize from a buffer holding the boot information
+    ///
+    /// Returns `None` if the buffer is shorter than the `total_size` field
+    /// claims or too short to hold the fixed header.
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        let total_size = read_u32(bytes, 0)? as usize;
+        if total_size < INFO_HEADER_SIZE {
+            return None;
+        }
+        Some(Self {
+            bytes: bytes.get(..total_size)?,
+        })
+    }
+
+    /// Iterate over all tags up to the end tag
+    pub fn tags(&self) -> TagIterator<'a> {
+        TagIterator {
+            bytes: self.bytes,
+            offset: INFO_HEADER_SIZE,
+        }
+    }
+
+    /// Find the first tag of the given type
+    pub fn find_tag(&self, tag_type: u32) -> Option<Tag<'a>> {
+        self.tags().find(|tag| tag.header.tag_type == tag_type)
+    }
+
+    /// Get the boot command line passed by the bootloader
+    ///
+    /// Returns `None` if the tag is missing or not valid UTF-8.
+    pub fn boot_cmdline(&self) -> Option<&'a str> {
+        let data = self.find_tag(TAG_BOOT_CMDLINE)?.data;
+        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        core::str::from_utf8(&data[..len]).ok()
+    }
+
+    /// Iterate over the boot modules loaded by the bootloader
+    ///
+    /// Module tags with a truncated payload or a command line that is not
+    /// valid UTF-8 are skipped.
+    pub fn modules(&self) -> impl Iterator<Item = BootModule<'a>> + 'a {
+        self.tags()
+            .filter(|tag| tag.header.tag_type == TAG_MODULE)
+            .filter_map(|tag| {
+                let start = read_u32(tag.data, 0)?;
+                let end = read_u32(tag.data, 4)?;
+                let cmdline = tag.data.get(8..)?;
+                let len = cmdline
+                    .iter()
+                    .position(|&b| b == 0)
+                    .unwrap_or(cmdline.len());
+                Some(BootModule {
+                    start: PhysAddr::new(start as u64),
+                    end: PhysAddr::new(end as u64),
+                    cmdline: core::str::from_utf8(&cmdline[..len]).ok()?,
+                })
+            })
+    }
+
+    /// Get the basic memory information
+    pub fn basic_memory_info(&self) -> Option<BasicMemoryInfo> {
+        let data = self.find_tag(TAG_BASIC_MEMINFO)?.data;
+        Some(BasicMemoryInfo {
+            mem_lower: read_u32(data, 0)?,
+            mem_upper: read_u32(data, 4)?,
+        })
     }
 
     /// Get memory map iterator
-    #[allow(dead_code)]
-    pub fn memory_map(&self) -> impl Iterator<Item = MemoryRegion> {
-        // TODO: Parse Multiboot2 information to extract memory map
-        core::iter::empty()
+    ///
+    /// Yields nothing if the memory map tag is missing or malformed.
+    pub fn memory_map(&self) -> impl Iterator<Item = MemoryRegion> + 'a {
+        let (entries, entry_size) = self
+            .find_tag(TAG_MEMORY_MAP)
+            .and_then(|tag| {
+                let entry_size = read_u32(tag.data, 0)? as usize;
+                if entry_size < MMAP_ENTRY_MIN_SIZE {
+                    return None;
+                }
+                Some((tag.data.get(8..)?, entry_size))
+            })
+            .unwrap_or((&[], MMAP_ENTRY_MIN_SIZE));
+
+        entries.chunks_exact(entry_size).filter_map(|entry| {
+            Some(MemoryRegion {
+                base_addr: read_u64(entry, 0)?,
+                length: read_u64(entry, 8)?,
+                region_type: MemoryRegionType::from_u32(read_u32(entry, 16)?),
+            })
+        })
     }
 
     /// Get framebuffer information
-    #[allow(dead_code)]
     pub fn framebuffer_info(&self) -> Option<FramebufferInfo> {
-        // TODO: Parse Multiboot2 information to extract framebuffer info
-        None
+        let data = self.find_tag(TAG_FRAMEBUFFER)?.data;
+        Some(FramebufferInfo {
+            addr: read_u64(data, 0)?,
+            pitch: read_u32(data, 8)?,
+            width: read_u32(data, 12)?,
+            height: read_u32(data, 16)?,
+            bpp: *data.get(20)?,
+            fb_type: FramebufferType::from_u8(*data.get(21)?)?,
+        })
+    }
+
+    /// Get the copy of the ACPI RSDP passed by the bootloader
+    ///
+    /// The ACPI 2.0 tag is preferred over the ACPI 1.0 one. The checksums
+    /// are not checked; see `AcpiRsdp::validate_checksum`.
+    pub fn acpi_rsdp(&self) -> Option<AcpiRsdp> {
+        if let Some(tag) = self.find_tag(TAG_ACPI_NEW_RSDP) {
+            return AcpiRsdp::from_bytes(tag.data);
+        }
+        AcpiRsdp::from_bytes(self.find_tag(TAG_ACPI_OLD_RSDP)?.data)
     }
 
     /// Get total memory size
-    #[allow(dead_code)]
+    ///
+    /// Sums the usable regions of the memory map, falling back to the basic
+    /// memory information if there is no memory map.
     pub fn total_memory(&self) -> Option<usize> {
-        // TODO: Parse Multiboot2 information to extract memory info
-        None
+        if self.find_tag(TAG_MEMORY_MAP).is_some() {
+            let usable = self
+                .memory_map()
+                .filter(|region| region.region_type == MemoryRegionType::Usable)
+                .fold(0u64, |sum, region| sum.saturating_add(region.length));
+            return Some(usable as usize);
+        }
+
+        let info = self.basic_memory_info()?;
+        // mem_upper counts from 1 MiB; the gap below is not usable RAM
+        Some((info.mem_lower as usize + info.mem_upper as usize) * 1024)
+    }
+}
+
+/// Header shared by all tags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct TagHeader {
+    /// Tag type (`TAG_*`)
+    pub tag_type: u32,
+    /// Size of the tag in bytes, including this header
+    pub size: u32,
+}
+
+/// A tag in the boot information
+#[derive(Debug, Clone, Copy)]
+pub struct Tag<'a> {
+    /// Tag header
+    pub header: TagHeader,
+    /// Tag payload following the header
+    pub data: &'a [u8],
+}
+
+/// Iterator over the tags of a Multiboot2 information structure
+///
+/// Tags start 8-byte aligned. Iteration stops at the end tag, or at the
+/// first tag whose size is invalid or runs past the end of the structure.
+pub struct TagIterator<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for TagIterator<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Tag<'a>> {
+        let header = TagHeader {
+            tag_type: read_u32(self.bytes, self.offset)?,
+            size: read_u32(self.bytes, self.offset + 4)?,
+        };
+        let size = header.size as usize;
+        if header.tag_type == TAG_END || size < core::mem::size_of::<TagHeader>() {
+            self.offset = self.bytes.len();
+            return None;
+        }
+
+        let start = self.offset + core::mem::size_of::<TagHeader>();
+        let Some(data) = self.bytes.get(start..self.offset + size) else {
+            self.offset = self.bytes.len();
+            return None;
+        };
+
+        self.offset = (self.offset + size)
+            .checked_next_multiple_of(8)
+            .unwrap_or(self.bytes.len());
+        Some(Tag { header, data })
     }
 }
 
+/// Boot module loaded into physical memory by the bootloader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootModule<'a> {
+    /// Physical address of the first byte of the module
+    pub start: PhysAddr,
+    /// Physical address one past the last byte of the module
+    pub end: PhysAddr,
+    /// Command line given to the module, e.g. its name
+    pub cmdline: &'a str,
+}
+
+impl BootModule<'_> {
+    /// Size of the module in bytes
+    pub fn size(&self) -> u64 {
+        self.end.as_u64().saturating_sub(self.start.as_u64())
+    }
+
+    /// Returns the module contents
+    ///
+    /// # Safety
+    ///
+    /// The module must still be identity-mapped and its memory must not
+    /// have been reused since boot.
+    pub unsafe fn data(&self) -> &'static [u8] {
+        // SAFETY: the bootloader placed `size` bytes at `start` and the
+        // caller guarantees they are still mapped and untouched.
+        unsafe {
+            core::slice::from_raw_parts(self.start.as_u64() as *const u8, self.size() as usize)
+        }
+    }
+}
+
+/// Basic lower/upper memory information
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BasicMemoryInfo {
+    /// Memory below 1 MiB in KiB
+    pub mem_lower: u32,
+    /// Memory above 1 MiB in KiB, up to the first hole
+    pub mem_upper: u32,
+}
+
 /// Memory region descriptor
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
@@ -66,6 +324,16 @@ pub struct MemoryRegion {
     pub region_type: MemoryRegionType,
 }
 
+impl IntoIterator for MemoryRegion {
+    type IntoIter = PhysRange;
+    type Item = PhysFrame;
+
+    /// Iterates over the frames lying entirely in the region
+    fn into_iter(self) -> PhysRange {
+        PhysRange::from_region(&self)
+    }
+}
+
 /// Memory region type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
@@ -83,6 +351,19 @@ pub enum MemoryRegionType {
     BadMemory = 5,
 }
 
+impl MemoryRegionType {
+    /// Convert a raw memory map type; unknown types are treated as reserved
+    pub const fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Usable,
+            3 => Self::AcpiReclaimable,
+            4 => Self::AcpiNvs,
+            5 => Self::BadMemory,
+            _ => Self::Reserved,
+        }
+    }
+}
+
 /// Framebuffer information
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
@@ -113,3 +394,363 @@ pub enum FramebufferType {
     /// EGA text mode
     EgaText = 2,
 }
+
+impl FramebufferType {
+    /// Convert a raw framebuffer type
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Indexed),
+            1 => Some(Self::Rgb),
+            2 => Some(Self::EgaText),
+            _ => None,
+        }
+    }
+}
+
+/// ACPI Root System Description Pointer
+///
+/// ACPI 1.0 RSDPs end after `rsdt_address`; the fields after it are zero
+/// for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct AcpiRsdp {
+    /// `"RSD PTR "`
+    pub signature: [u8; 8],
+    /// Makes the first 20 bytes sum to zero
+    pub checksum: u8,
+    /// OEM identifier
+    pub oem_id: [u8; 6],
+    /// 0 for ACPI 1.0, 2 for ACPI 2.0 and later
+    pub revision: u8,
+    /// Physical address of the RSDT
+    pub rsdt_address: u32,
+    /// Size of the whole structure (ACPI 2.0)
+    pub length: u32,
+    /// Physical address of the XSDT (ACPI 2.0)
+    pub xsdt_address: u64,
+    /// Makes all `length` bytes sum to zero (ACPI 2.0)
+    pub extended_checksum: u8,
+    /// Reserved (ACPI 2.0)
+    pub reserved: [u8; 3],
+}
+
+impl AcpiRsdp {
+    /// Expected `signature`
+    pub const SIGNATURE: [u8; 8] = *b"RSD PTR ";
+
+    /// Parse an RSDP from the payload of an RSDP tag
+    ///
+    /// Returns `None` if the payload is too short for the revision it
+    /// claims.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let v1 = bytes.get(..RSDP_V1_SIZE)?;
+        let mut rsdp = Self {
+            signature: v1[..8].try_into().ok()?,
+            checksum: v1[8],
+            oem_id: v1[9..15].try_into().ok()?,
+            revision: v1[15],
+            rsdt_address: read_u32(v1, 16)?,
+            length: 0,
+            xsdt_address: 0,
+            extended_checksum: 0,
+            reserved: [0; 3],
+        };
+        if rsdp.revision >= 2 {
+            let v2 = bytes.get(..RSDP_V2_SIZE)?;
+            rsdp.length = read_u32(v2, 20)?;
+            rsdp.xsdt_address = read_u64(v2, 24)?;
+            rsdp.extended_checksum = v2[32];
+            rsdp.reserved = v2[33..36].try_into().ok()?;
+        }
+        Some(rsdp)
+    }
+
+    /// Check the signature and checksums
+    ///
+    /// The bytes covered by each checksum must sum to zero modulo 256: the
+    /// first 20 for `checksum`, and the whole structure for
+    /// `extended_checksum` from ACPI 2.0 on.
+    pub fn validate_checksum(&self) -> bool {
+        let bytes = self.to_bytes();
+        let sum = |bytes: &[u8]| bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        self.signature == Self::SIGNATURE
+            && sum(&bytes[..RSDP_V1_SIZE]) == 0
+            && (self.revision < 2 || sum(&bytes) == 0)
+    }
+
+    /// Physical address of the root table: the XSDT if there is one,
+    /// otherwise the RSDT
+    pub fn root_table(&self) -> PhysAddr {
+        if self.revision >= 2 && self.xsdt_address != 0 {
+            PhysAddr::new(self.xsdt_address)
+        } else {
+            PhysAddr::new(self.rsdt_address as u64)
+        }
+    }
+
+    /// Serialize the structure as laid out in memory, without padding
+    fn to_bytes(self) -> [u8; RSDP_V2_SIZE] {
+        let mut bytes = [0; RSDP_V2_SIZE];
+        bytes[..8].copy_from_slice(&self.signature);
+        bytes[8] = self.checksum;
+        bytes[9..15].copy_from_slice(&self.oem_id);
+        bytes[15] = self.revision;
+        bytes[16..20].copy_from_slice(&self.rsdt_address.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.length.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.xsdt_address.to_le_bytes());
+        bytes[32] = self.extended_checksum;
+        bytes[33..].copy_from_slice(&self.reserved);
+        bytes
+    }
+}
+
+/// Read a little-endian u32 at `offset`, if in bounds
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let raw = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(raw.try_into().ok()?))
+}
+
+/// Read a little-endian u64 at `offset`, if in bounds
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let raw = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(raw.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Builds a boot information structure from `(type, payload)` tags
+    fn build_info(tags: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut buf = alloc::vec![0u8; INFO_HEADER_SIZE];
+        for (tag_type, payload) in tags {
+            buf.extend_from_slice(&tag_type.to_le_bytes());
+            buf.extend_from_slice(&(8 + payload.len() as u32).to_le_bytes());
+            buf.extend_from_slice(payload);
+            buf.resize(buf.len().next_multiple_of(8), 0);
+        }
+        buf.extend_from_slice(&TAG_END.to_le_bytes());
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        let total = buf.len() as u32;
+        buf[..4].copy_from_slice(&total.to_le_bytes());
+        buf
+    }
+
+    fn mmap_entry(base: u64, length: u64, region_type: u32) -> [u8; 24] {
+        let mut entry = [0u8; 24];
+        entry[..8].copy_from_slice(&base.to_le_bytes());
+        entry[8..16].copy_from_slice(&length.to_le_bytes());
+        entry[16..20].copy_from_slice(&region_type.to_le_bytes());
+        entry
+    }
+
+    #[test_case]
+    fn test_parse_tags() {
+        let mut mmap = Vec::new();
+        mmap.extend_from_slice(&24u32.to_le_bytes());
+        mmap.extend_from_slice(&0u32.to_le_bytes());
+        mmap.extend_from_slice(&mmap_entry(0, 0x9fc00, 1));
+        mmap.extend_from_slice(&mmap_entry(0xf0000, 0x10000, 2));
+        mmap.extend_from_slice(&mmap_entry(0x100000, 0x7ee0000, 1));
+
+        let mut fb = Vec::new();
+        fb.extend_from_slice(&0xfd00_0000u64.to_le_bytes());
+        for v in [4096u32, 1024, 768] {
+            fb.extend_from_slice(&v.to_le_bytes());
+        }
+        fb.extend_from_slice(&[32, 1, 0, 0]);
+
+        let meminfo = [639u32.to_le_bytes(), 130048u32.to_le_bytes()].concat();
+        let buf = build_info(&[
+            (TAG_BOOT_CMDLINE, b"console=ttyS0\0"),
+            (TAG_BASIC_MEMINFO, &meminfo),
+            (TAG_MEMORY_MAP, &mmap),
+            (TAG_FRAMEBUFFER, &fb),
+        ]);
+        let info = Multiboot2Info::from_bytes(&buf).unwrap();
+
+        assert_eq!(info.tags().count(), 4);
+        assert_eq!(info.boot_cmdline(), Some("console=ttyS0"));
+        assert_eq!(
+            info.basic_memory_info(),
+            Some(BasicMemoryInfo {
+                mem_lower: 639,
+                mem_upper: 130048,
+            })
+        );
+
+        let regions: Vec<_> = info.memory_map().collect();
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[1].base_addr, 0xf0000);
+        assert_eq!(regions[1].region_type, MemoryRegionType::Reserved);
+        assert_eq!(info.total_memory(), Some(0x9fc00 + 0x7ee0000));
+
+        let fb = info.framebuffer_info().unwrap();
+        assert_eq!(fb.addr, 0xfd00_0000);
+        assert_eq!(
+            (fb.pitch, fb.width, fb.height, fb.bpp),
+            (4096, 1024, 768, 32)
+        );
+        assert_eq!(fb.fb_type, FramebufferType::Rgb);
+    }
+
+    #[test_case]
+    fn test_modules() {
+        let mut init = Vec::new();
+        init.extend_from_slice(&0x20_0000u32.to_le_bytes());
+        init.extend_from_slice(&0x20_1000u32.to_le_bytes());
+        init.extend_from_slice(b"init\0");
+        let mut ramdisk = Vec::new();
+        ramdisk.extend_from_slice(&0x30_0000u32.to_le_bytes());
+        ramdisk.extend_from_slice(&0x30_8000u32.to_le_bytes());
+        ramdisk.push(0);
+        let buf = build_info(&[
+            (TAG_MODULE, &init),
+            (TAG_BOOT_CMDLINE, b"quiet\0"),
+            (TAG_MODULE, &ramdisk),
+            (TAG_MODULE, &[0; 6]),
+        ]);
+        let info = Multiboot2Info::from_bytes(&buf).unwrap();
+
+        let modules: Vec<_> = info.modules().collect();
+        assert_eq!(modules.len(), 2);
+        assert_eq!(modules[0], BootModule {
+            start: PhysAddr::new(0x20_0000),
+            end: PhysAddr::new(0x20_1000),
+            cmdline: "init",
+        });
+        assert_eq!(modules[0].size(), 0x1000);
+        assert_eq!(modules[1].cmdline, "");
+        assert_eq!(modules[1].size(), 0x8000);
+    }
+
+    /// Builds an RSDP with valid checksums
+    fn rsdp_bytes(revision: u8) -> Vec<u8> {
+        let mut rsdp = Vec::new();
+        rsdp.extend_from_slice(b"RSD PTR ");
+        rsdp.push(0);
+        rsdp.extend_from_slice(b"BOCHS ");
+        rsdp.push(revision);
+        rsdp.extend_from_slice(&0x7fe_14d2u32.to_le_bytes());
+        if revision >= 2 {
+            rsdp.extend_from_slice(&(RSDP_V2_SIZE as u32).to_le_bytes());
+            rsdp.extend_from_slice(&0x7fe_1500u64.to_le_bytes());
+            rsdp.extend_from_slice(&[0; 4]);
+        }
+        let sum = |bytes: &[u8]| bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        rsdp[8] = 0u8.wrapping_sub(sum(&rsdp[..RSDP_V1_SIZE]));
+        if revision >= 2 {
+            rsdp[32] = 0u8.wrapping_sub(sum(&rsdp));
+        }
+        rsdp
+    }
+
+    #[test_case]
+    fn test_acpi_rsdp() {
+        let v1 = rsdp_bytes(0);
+        let info_bytes = build_info(&[(TAG_ACPI_OLD_RSDP, &v1)]);
+        let info = Multiboot2Info::from_bytes(&info_bytes).unwrap();
+        let rsdp = info.acpi_rsdp().unwrap();
+        assert_eq!(&rsdp.oem_id, b"BOCHS ");
+        assert_eq!(rsdp.rsdt_address, 0x7fe_14d2);
+        assert!(rsdp.validate_checksum());
+        assert_eq!(rsdp.root_table(), PhysAddr::new(0x7fe_14d2));
+
+        // The ACPI 2.0 tag wins and points at the XSDT
+        let v2 = rsdp_bytes(2);
+        let info_bytes = build_info(&[(TAG_ACPI_OLD_RSDP, &v1), (TAG_ACPI_NEW_RSDP, &v2)]);
+        let info = Multiboot2Info::from_bytes(&info_bytes).unwrap();
+        let rsdp = info.acpi_rsdp().unwrap();
+        assert_eq!(rsdp.revision, 2);
+        assert_eq!(rsdp.length, RSDP_V2_SIZE as u32);
+        assert!(rsdp.validate_checksum());
+        assert_eq!(rsdp.root_table(), PhysAddr::new(0x7fe_1500));
+    }
+
+    #[test_case]
+    fn test_acpi_rsdp_bad_checksum() {
+        let mut v1 = rsdp_bytes(0);
+        v1[8] = v1[8].wrapping_add(1);
+        assert!(!AcpiRsdp::from_bytes(&v1).unwrap().validate_checksum());
+
+        // Only the extended checksum covers the XSDT address
+        let mut v2 = rsdp_bytes(2);
+        v2[24] ^= 0x10;
+        assert!(!AcpiRsdp::from_bytes(&v2).unwrap().validate_checksum());
+
+        let mut bad_signature = rsdp_bytes(0);
+        bad_signature[..8].copy_from_slice(b"RSD PTR!");
+        bad_signature[8] = bad_signature[8].wrapping_sub(b'!' - b' ');
+        assert!(
+            !AcpiRsdp::from_bytes(&bad_signature)
+                .unwrap()
+                .validate_checksum()
+        );
+
+        // Truncated payloads
+        assert!(AcpiRsdp::from_bytes(&v1[..19]).is_none());
+        assert!(AcpiRsdp::from_bytes(&v2[..RSDP_V1_SIZE]).is_none());
+    }
+
+    #[test_case]
+    fn test_missing_and_truncated_tags() {
+        let buf = build_info(&[]);
+        let info = Multiboot2Info::from_bytes(&buf).unwrap();
+        assert_eq!(info.tags().count(), 0);
+        assert!(info.boot_cmdline().is_none());
+        assert!(info.framebuffer_info().is_none());
+        assert_eq!(info.modules().count(), 0);
+        assert_eq!(info.memory_map().count(), 0);
+        assert_eq!(info.total_memory(), None);
+        assert!(info.acpi_rsdp().is_none());
+
+        // total_size larger than the buffer
+        let mut buf = build_info(&[(TAG_BASIC_MEMINFO, &[0; 8])]);
+        buf.truncate(buf.len() - 4);
+        assert!(Multiboot2Info::from_bytes(&buf).is_none());
+    }
+
+    #[test_case]
+    fn test_fuzz_random_tags_do_not_panic() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..500 {
+            let len = (next() % 256) as usize;
+            let mut buf: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            if len >= 8 {
+                // Mostly keep total_size in range so tags actually get parsed
+                let total = (next() % (len as u64 + 16)) as u32;
+                buf[..4].copy_from_slice(&total.to_le_bytes());
+                let mut offset = 8;
+                while offset + 8 <= len {
+                    let tag_type = [0, 1, 3, 4, 6, 8, 14, 15, 42][(next() % 9) as usize] as u32;
+                    let size = (next() % 64) as u32;
+                    buf[offset..offset + 4].copy_from_slice(&tag_type.to_le_bytes());
+                    buf[offset + 4..offset + 8].copy_from_slice(&size.to_le_bytes());
+                    offset = (offset + size.max(8) as usize).next_multiple_of(8);
+                }
+            }
+
+            if let Some(info) = Multiboot2Info::from_bytes(&buf) {
+                let _ = info.tags().count();
+                let _ = info.boot_cmdline();
+                for module in info.modules() {
+                    let _ = (module.size(), module.cmdline.len());
+                }
+                let _ = info.basic_memory_info();
+                let _ = info.memory_map().count();
+                let _ = info.framebuffer_info();
+                let _ = info.total_memory();
+                let _ = info.acpi_rsdp();
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Boot phase tracking
+//!
+//! The kernel records how far initialization has progressed in a single
+//! atomic byte. Each transition is also written to the VGA diagnostic area
+//! (`[S0]`, `[S1]`, ...) so a developer can see where boot stopped even when
+//! no serial console is attached. The panic handlers print the current phase
+//! so it is obvious which subsystems were not yet initialized.
+//!
+//! Every phase set is also appended to a log, which the boot self-test
+//! checks against `BootPhase::ALL` to verify the order `kernel_main`
+//! actually went through.
+
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicU8,
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// Boot phases in the order they are reached by `kernel_main`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum BootPhase {
+    /// Nothing but VGA is available
+    PreSerial = 0,
+    /// Serial console initialized
+    SerialReady = 1,
+    /// Kernel heap initialized
+    HeapReady = 2,
+    /// GDT, TSS and IDT loaded
+    IdtReady = 3,
+    /// PIC/PIT configured and interrupts enabled
+    TimerReady = 4,
+    /// Process table and scheduler initialized
+    ProcessesReady = 5,
+    /// Root filesystem mounted
+    FileSystemReady = 6,
+    /// First userspace process started
+    UserSpaceReady = 7,
+}
+
+impl BootPhase {
+    /// All phases in boot order
+    pub const ALL: [Self; 8] = [
+        Self::PreSerial,
+        Self::SerialReady,
+        Self::HeapReady,
+        Self::IdtReady,
+        Self::TimerReady,
+        Self::ProcessesReady,
+        Self::FileSystemReady,
+        Self::UserSpaceReady,
+    ];
+
+    /// Converts a raw phase number back into a `BootPhase`
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::PreSerial),
+            1 => Some(Self::SerialReady),
+            2 => Some(Self::HeapReady),
+            3 => Some(Self::IdtReady),
+            4 => Some(Self::TimerReady),
+            5 => Some(Self::ProcessesReady),
+            6 => Some(Self::FileSystemReady),
+            7 => Some(Self::UserSpaceReady),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the phase
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::PreSerial => "PreSerial",
+            Self::SerialReady => "SerialReady",
+            Self::HeapReady => "HeapReady",
+            Self::IdtReady => "IdtReady",
+            Self::TimerReady => "TimerReady",
+            Self::ProcessesReady => "ProcessesReady",
+            Self::FileSystemReady => "FileSystemReady",
+            Self::UserSpaceReady => "UserSpaceReady",
+        }
+    }
+
+    /// Returns the short tag written to the VGA diagnostic area
+    pub const fn diagnostic_tag(self) -> &'static str {
+        match self {
+            Self::PreSerial => "[S0]",
+            Self::SerialReady => "[S1]",
+            Self::HeapReady => "[S2]",
+            Self::IdtReady => "[S3]",
+            Self::TimerReady => "[S4]",
+            Self::ProcessesReady => "[S5]",
+            Self::FileSystemReady => "[S6]",
+            Self::UserSpaceReady => "[S7]",
+        }
+    }
+}
+
+impl fmt::Display for BootPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Current boot phase
+static BOOT_PHASE: AtomicU8 = AtomicU8::new(BootPhase::PreSerial as u8);
+
+/// Number of entries `PHASE_LOG` holds
+const PHASE_LOG_CAPACITY: usize = BootPhase::ALL.len();
+
+/// Phases in the order they were set; only the first `PHASE_LOG_LEN`
+/// entries are valid
+static PHASE_LOG: [AtomicU8; PHASE_LOG_CAPACITY] = [const { AtomicU8::new(0) }; PHASE_LOG_CAPACITY];
+
+/// Number of phases set so far, including any that did not fit in the log
+static PHASE_LOG_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Records that boot has reached `phase`
+///
+/// The phase is appended to the phase log, and its tag is written to the
+/// top-right corner of the VGA screen. This is a no-op for the screen if
+/// VGA has not been initialized yet.
+pub fn set_boot_phase(phase: BootPhase) {
+    BOOT_PHASE.store(phase as u8, Ordering::SeqCst);
+    let index = PHASE_LOG_LEN.fetch_add(1, Ordering::SeqCst);
+    if let Some(entry) = PHASE_LOG.get(index) {
+        entry.store(phase as u8, Ordering::SeqCst);
+    }
+    crate::vga::write_diagnostic(phase.diagnostic_tag());
+}
+
+/// Returns the most recently reached boot phase
+pub fn boot_phase() -> BootPhase {
+    BootPhase::from_u8(BOOT_PHASE.load(Ordering::SeqCst)).unwrap_or(BootPhase::PreSerial)
+}
+
+/// Returns whether the phases set so far are the first phases of
+/// `BootPhase::ALL`, each set once and in order
+pub fn phases_reached_in_order() -> bool {
+    let len = PHASE_LOG_LEN.load(Ordering::SeqCst);
+    len <= PHASE_LOG_CAPACITY
+        && is_boot_order(
+            PHASE_LOG[..len]
+                .iter()
+                .map(|entry| entry.load(Ordering::SeqCst)),
+        )
+}
+
+/// Returns whether the raw `phases` are the first phases of
+/// `BootPhase::ALL`, in order
+fn is_boot_order(phases: impl IntoIterator<Item = u8>) -> bool {
+    let mut expected = BootPhase::ALL.iter();
+    phases
+        .into_iter()
+        .all(|phase| expected.next().is_some_and(|&next| next as u8 == phase))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test_case]
+    fn test_boot_order() {
+        use BootPhase::*;
+
+        let raw =
+            |phases: &[BootPhase]| phases.iter().map(|&phase| phase as u8).collect::<Vec<_>>();
+        assert!(is_boot_order(raw(&BootPhase::ALL)));
+        assert!(is_boot_order(raw(&[PreSerial, SerialReady, HeapReady])));
+        assert!(is_boot_order([]));
+
+        // Skipped, repeated and swapped phases
+        assert!(!is_boot_order(raw(&[PreSerial, HeapReady])));
+        assert!(!is_boot_order(raw(&[PreSerial, SerialReady, SerialReady])));
+        assert!(!is_boot_order(raw(&[
+            PreSerial,
+            SerialReady,
+            HeapReady,
+            IdtReady,
+            TimerReady,
+            FileSystemReady,
+            ProcessesReady,
+        ])));
+        let mut too_many = raw(&BootPhase::ALL);
+        too_many.push(UserSpaceReady as u8);
+        assert!(!is_boot_order(too_many));
+    }
+
+    #[test_case]
+    fn test_phase_round_trip() {
+        for (i, phase) in BootPhase::ALL.iter().enumerate() {
+            assert_eq!(BootPhase::from_u8(i as u8), Some(*phase));
+            assert_eq!(phase.diagnostic_tag().len(), 4);
+        }
+        assert_eq!(BootPhase::from_u8(8), None);
+    }
+}
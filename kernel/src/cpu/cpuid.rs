@@ -0,0 +1,198 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! CPUID feature detection
+//!
+//! Features are read from the standard feature leaf 1 and, if the CPU
+//! implements it, the structured extended feature leaf 7 (subleaf 0).
+
+use core::arch::x86_64::{
+    __cpuid,
+    __cpuid_count,
+};
+
+use bitflags::bitflags;
+use spin::Once;
+
+/// Standard feature leaf
+const LEAF_FEATURES: u32 = 0x01;
+/// Structured extended feature leaf
+const LEAF_EXTENDED_FEATURES: u32 = 0x07;
+
+/// Features of the running CPU, set by `cpu::init`
+pub static CPU_FEATURES: Once<CpuFeatures> = Once::new();
+
+bitflags! {
+    /// CPU features reported by CPUID
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CpuFeatures: u64 {
+        /// x87 floating point unit
+        const FPU =          1 << 0;
+        /// Time stamp counter
+        const TSC =          1 << 1;
+        /// RDMSR and WRMSR
+        const MSR =          1 << 2;
+        /// Physical address extension
+        const PAE =          1 << 3;
+        /// On-chip local APIC
+        const APIC =         1 << 4;
+        /// Global pages
+        const PGE =          1 << 5;
+        /// Page attribute table
+        const PAT =          1 << 6;
+        /// FXSAVE and FXRSTOR
+        const FXSR =         1 << 7;
+        /// SSE
+        const SSE =          1 << 8;
+        /// SSE2
+        const SSE2 =         1 << 9;
+        /// SSE3
+        const SSE3 =         1 << 10;
+        /// Supplemental SSE3
+        const SSSE3 =        1 << 11;
+        /// SSE4.1
+        const SSE4_1 =       1 << 12;
+        /// SSE4.2
+        const SSE4_2 =       1 << 13;
+        /// Process-context identifiers
+        const PCID =         1 << 14;
+        /// x2APIC mode of the local APIC
+        const X2APIC =       1 << 15;
+        /// TSC deadline mode of the APIC timer
+        const TSC_DEADLINE = 1 << 16;
+        /// XSAVE family of instructions
+        const XSAVE =        1 << 17;
+        /// AVX
+        const AVX =          1 << 18;
+        /// RDRAND
+        const RDRAND =       1 << 19;
+        /// Running under a hypervisor
+        const HYPERVISOR =   1 << 20;
+        /// RDFSBASE and friends
+        const FSGSBASE =     1 << 21;
+        /// AVX2
+        const AVX2 =         1 << 22;
+        /// Supervisor mode execution prevention
+        const SMEP =         1 << 23;
+        /// INVPCID
+        const INVPCID =      1 << 24;
+        /// RDSEED
+        const RDSEED =       1 << 25;
+        /// Supervisor mode access prevention
+        const SMAP =         1 << 26;
+        /// User mode instruction prevention
+        const UMIP =         1 << 27;
+        /// Protection keys for user pages
+        const PKU =          1 << 28;
+    }
+}
+
+/// CPUID.01h:EDX bits
+const LEAF1_EDX: [(u32, CpuFeatures); 10] = [
+    (0, CpuFeatures::FPU),
+    (4, CpuFeatures::TSC),
+    (5, CpuFeatures::MSR),
+    (6, CpuFeatures::PAE),
+    (9, CpuFeatures::APIC),
+    (13, CpuFeatures::PGE),
+    (16, CpuFeatures::PAT),
+    (24, CpuFeatures::FXSR),
+    (25, CpuFeatures::SSE),
+    (26, CpuFeatures::SSE2),
+];
+
+/// CPUID.01h:ECX bits
+const LEAF1_ECX: [(u32, CpuFeatures); 11] = [
+    (0, CpuFeatures::SSE3),
+    (9, CpuFeatures::SSSE3),
+    (17, CpuFeatures::PCID),
+    (19, CpuFeatures::SSE4_1),
+    (20, CpuFeatures::SSE4_2),
+    (21, CpuFeatures::X2APIC),
+    (24, CpuFeatures::TSC_DEADLINE),
+    (26, CpuFeatures::XSAVE),
+    (28, CpuFeatures::AVX),
+    (30, CpuFeatures::RDRAND),
+    (31, CpuFeatures::HYPERVISOR),
+];
+
+/// CPUID.(07h,0):EBX bits
+const LEAF7_EBX: [(u32, CpuFeatures); 6] = [
+    (0, CpuFeatures::FSGSBASE),
+    (5, CpuFeatures::AVX2),
+    (7, CpuFeatures::SMEP),
+    (10, CpuFeatures::INVPCID),
+    (18, CpuFeatures::RDSEED),
+    (20, CpuFeatures::SMAP),
+];
+
+/// CPUID.(07h,0):ECX bits
+const LEAF7_ECX: [(u32, CpuFeatures); 2] = [(2, CpuFeatures::UMIP), (3, CpuFeatures::PKU)];
+
+impl CpuFeatures {
+    /// Queries CPUID for the features of the running CPU
+    pub fn detect() -> Self {
+        let max_leaf = __cpuid(0).eax;
+        let leaf1 = __cpuid(LEAF_FEATURES);
+        let (leaf7_ebx, leaf7_ecx) = if max_leaf >= LEAF_EXTENDED_FEATURES {
+            let leaf7 = __cpuid_count(LEAF_EXTENDED_FEATURES, 0);
+            (leaf7.ebx, leaf7.ecx)
+        } else {
+            (0, 0)
+        };
+        Self::from_registers(leaf1.ecx, leaf1.edx, leaf7_ebx, leaf7_ecx)
+    }
+
+    /// Maps CPUID register values to features
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf1_ecx`, `leaf1_edx` - ECX and EDX of leaf 1
+    /// * `leaf7_ebx`, `leaf7_ecx` - EBX and ECX of leaf 7, subleaf 0
+    pub fn from_registers(leaf1_ecx: u32, leaf1_edx: u32, leaf7_ebx: u32, leaf7_ecx: u32) -> Self {
+        let mut features = Self::empty();
+        let mut map = |reg: u32, table: &[(u32, CpuFeatures)]| {
+            for &(bit, feature) in table {
+                features.set(feature, reg & (1 << bit) != 0);
+            }
+        };
+        map(leaf1_edx, &LEAF1_EDX);
+        map(leaf1_ecx, &LEAF1_ECX);
+        map(leaf7_ebx, &LEAF7_EBX);
+        map(leaf7_ecx, &LEAF7_ECX);
+        features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_detects_sse2() {
+        // Every x86_64 CPU, and so QEMU, has SSE2
+        assert!(CpuFeatures::detect().contains(CpuFeatures::SSE2 | CpuFeatures::APIC));
+        assert!(crate::cpu::features().contains(CpuFeatures::SSE2));
+    }
+
+    #[test_case]
+    fn test_register_bits_map_to_flags() {
+        let features = CpuFeatures::from_registers(1 << 17, (1 << 9) | (1 << 26), 1 << 7, 0);
+        assert_eq!(
+            features,
+            CpuFeatures::PCID | CpuFeatures::APIC | CpuFeatures::SSE2 | CpuFeatures::SMEP
+        );
+        assert!(CpuFeatures::from_registers(0, 0, 0, 0).is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! FPU and SSE control
+//!
+//! `init` turns on SSE so processes can use the XMM registers and
+//! `fxsave`/`fxrstor`. The kernel itself is built without SSE. The CR0 TS
+//! bit makes the next FPU or SSE instruction raise #NM, which the scheduler
+//! uses to give a process FPU state only once it needs it.
+
+use super::security::{
+    read_cr4,
+    write_cr4,
+};
+
+/// CR0 bit making WAIT/FWAIT honour TS
+pub const CR0_MP: u64 = 1 << 1;
+/// CR0 bit making every FPU instruction raise #NM
+pub const CR0_EM: u64 = 1 << 2;
+/// CR0 task switched bit
+pub const CR0_TS: u64 = 1 << 3;
+/// CR4 bit enabling `fxsave`/`fxrstor` of the SSE state and SSE itself
+pub const CR4_OSFXSR: u64 = 1 << 9;
+/// CR4 bit reporting unmasked SIMD floating-point exceptions as #XM
+pub const CR4_OSXMMEXCPT: u64 = 1 << 10;
+
+/// Reads CR0
+pub fn read_cr0() -> u64 {
+    let cr0: u64;
+    // SAFETY: reading CR0 has no side effects
+    unsafe {
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+    }
+    cr0
+}
+
+/// Writes CR0
+///
+/// # Safety
+///
+/// Changing the bits must not break assumptions of running code.
+unsafe fn write_cr0(value: u64) {
+    // SAFETY: guaranteed by the caller
+    unsafe {
+        core::arch::asm!("mov cr0, {}", in(reg) value, options(nostack, preserves_flags));
+    }
+}
+
+/// Enables the FPU and SSE
+///
+/// Every x86_64 CPU has both, so no feature check is needed.
+pub fn init() {
+    // SAFETY: the kernel does not use the FPU, so enabling it changes
+    // nothing for running code
+    unsafe {
+        write_cr0((read_cr0() & !CR0_EM) | CR0_MP);
+        write_cr4(read_cr4() | CR4_OSFXSR | CR4_OSXMMEXCPT);
+    }
+}
+
+/// Sets CR0.TS, so the next FPU or SSE instruction raises #NM
+pub fn set_task_switched() {
+    // SAFETY: only affects FPU instructions, which the kernel does not use
+    unsafe { write_cr0(read_cr0() | CR0_TS) };
+}
+
+/// Clears CR0.TS, allowing FPU and SSE instructions again
+pub fn clear_task_switched() {
+    // SAFETY: CLTS only clears CR0.TS
+    unsafe { core::arch::asm!("clts", options(nomem, nostack, preserves_flags)) };
+}
+
+/// Returns `true` if CR0.TS is set
+pub fn task_switched() -> bool {
+    read_cr0() & CR0_TS != 0
+}
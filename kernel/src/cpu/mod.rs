@@ -0,0 +1,56 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! CPU identification
+//!
+//! Queries what the running processor supports, so that optional hardware
+//! is only used where it exists. `msr` accesses model-specific registers
+//! and `fpu` controls the FPU and SSE.
+
+pub mod cpuid;
+pub mod fpu;
+pub mod msr;
+pub mod security;
+
+pub use cpuid::{
+    CPU_FEATURES,
+    CpuFeatures,
+};
+
+/// Returns the features of the running CPU, detecting them on first use
+pub fn features() -> CpuFeatures {
+    *CPU_FEATURES.call_once(CpuFeatures::detect)
+}
+
+/// Detects the CPU features and logs them
+pub fn init() {
+    let features = features();
+    crate::log_info!("CPU features ({}):", features.iter().count());
+
+    // One line per few flags keeps each message within the log entry size
+    let mut line = alloc::string::String::new();
+    for (name, _) in features.iter_names() {
+        if line.len() + name.len() >= 64 {
+            crate::log_info!("  {}", line);
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(name);
+    }
+    if !line.is_empty() {
+        crate::log_info!("  {}", line);
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Model-specific register access
+//!
+//! `read` and `write` take a raw register number. The architectural
+//! registers the kernel uses are named by `Msr`, whose `read` and `write`
+//! methods should be preferred.
+
+/// Architectural model-specific registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Msr {
+    /// Local APIC base address and enable bit
+    Ia32ApicBase = 0x1b,
+    /// Extended feature enable register
+    Ia32Efer = 0xc000_0080,
+    /// Segment selectors loaded by `syscall` and `sysret`
+    Ia32Star = 0xc000_0081,
+    /// Entry point of `syscall` in 64-bit mode
+    Ia32Lstar = 0xc000_0082,
+    /// RFLAGS bits cleared by `syscall`
+    Ia32Sfmask = 0xc000_0084,
+    /// GS base swapped in by `swapgs`
+    Ia32KernelGsBase = 0xc000_0102,
+}
+
+impl Msr {
+    /// Reads the register
+    ///
+    /// Every variant exists on x86_64 CPUs, so reading cannot fault.
+    pub fn read(self) -> u64 {
+        // SAFETY: see above; reading has no side effects
+        unsafe { read(self as u32) }
+    }
+
+    /// Writes the register
+    ///
+    /// # Safety
+    ///
+    /// See `write`.
+    pub unsafe fn write(self, value: u64) {
+        // SAFETY: forwarded from the caller
+        unsafe { write(self as u32, value) }
+    }
+}
+
+/// Reads a model-specific register
+///
+/// # Safety
+///
+/// `msr` must exist on this CPU, or the read raises #GP.
+pub unsafe fn read(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    core::arch::asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") low,
+        out("edx") high,
+        options(nomem, nostack, preserves_flags)
+    );
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// Writes a model-specific register
+///
+/// # Safety
+///
+/// `msr` must exist on this CPU and accept `value`. Writing control
+/// registers such as `IA32_EFER` can change how the CPU operates.
+pub unsafe fn write(msr: u32, value: u64) {
+    core::arch::asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// APIC global enable bit of `IA32_APIC_BASE`
+    const APIC_GLOBAL_ENABLE: u64 = 1 << 11;
+
+    #[test_case]
+    fn test_apic_base_is_enabled() {
+        assert_ne!(Msr::Ia32ApicBase.read() & APIC_GLOBAL_ENABLE, 0);
+    }
+
+    #[test_case]
+    fn test_typed_read_matches_raw_read() {
+        // SAFETY: IA32_EFER exists on every x86_64 CPU
+        let raw = unsafe { read(0xc000_0080) };
+        assert_eq!(Msr::Ia32Efer.read(), raw);
+    }
+}
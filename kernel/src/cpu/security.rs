@@ -0,0 +1,179 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Supervisor-mode protections
+//!
+//! SMEP makes the kernel fault when it executes code on a user page; SMAP
+//! makes it fault when it reads or writes a user page, except between
+//! `stac` and `clac`. Both turn kernel bugs that follow user pointers into
+//! immediate page faults.
+
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+
+use super::CpuFeatures;
+use crate::memory::{
+    USER_SPACE_END,
+    USER_SPACE_START,
+};
+
+/// CR4 bit enabling supervisor-mode execution prevention
+pub const CR4_SMEP: u64 = 1 << 20;
+/// CR4 bit enabling supervisor-mode access prevention
+pub const CR4_SMAP: u64 = 1 << 21;
+
+/// Page fault error code bits
+const PF_PRESENT: u64 = 1 << 0;
+const PF_USER: u64 = 1 << 2;
+
+/// Whether SMAP is on, so user accesses need `stac`/`clac`
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Reads CR4
+pub fn read_cr4() -> u64 {
+    let cr4: u64;
+    // SAFETY: reading CR4 has no side effects
+    unsafe {
+        core::arch::asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    }
+    cr4
+}
+
+/// Writes CR4
+///
+/// # Safety
+///
+/// Every bit set in `value` must be supported by the CPU, and changing the
+/// bits must not break assumptions of running code.
+pub(crate) unsafe fn write_cr4(value: u64) {
+    // SAFETY: guaranteed by the caller
+    unsafe {
+        core::arch::asm!("mov cr4, {}", in(reg) value, options(nostack, preserves_flags));
+    }
+}
+
+/// Turns on SMEP and SMAP, as far as the CPU supports them
+///
+/// Must be called after `cpu::init` and before user mode is entered.
+///
+/// # Returns
+///
+/// The protections that were enabled
+pub fn enable_smep_smap() -> CpuFeatures {
+    let supported = super::features() & (CpuFeatures::SMEP | CpuFeatures::SMAP);
+    let mut cr4 = read_cr4();
+    if supported.contains(CpuFeatures::SMEP) {
+        cr4 |= CR4_SMEP;
+    }
+    if supported.contains(CpuFeatures::SMAP) {
+        cr4 |= CR4_SMAP;
+    }
+    // SAFETY: only bits reported by CPUID are set. The kernel reaches user
+    // memory only through `with_user_access`, which handles SMAP.
+    unsafe {
+        write_cr4(cr4);
+    }
+    SMAP_ENABLED.store(supported.contains(CpuFeatures::SMAP), Ordering::Relaxed);
+
+    let state = |feature| {
+        if supported.contains(feature) {
+            "enabled"
+        } else {
+            "unsupported"
+        }
+    };
+    crate::log_info!(
+        "SMEP {}, SMAP {}",
+        state(CpuFeatures::SMEP),
+        state(CpuFeatures::SMAP)
+    );
+    supported
+}
+
+/// Runs `f` with access to user pages allowed
+///
+/// All kernel reads and writes of user memory must happen inside `f`.
+pub fn with_user_access<R>(f: impl FnOnce() -> R) -> R {
+    let smap = SMAP_ENABLED.load(Ordering::Relaxed);
+    if smap {
+        // SAFETY: SMAP is supported, so `stac` exists; it only sets RFLAGS.AC
+        unsafe {
+            core::arch::asm!("stac", options(nomem, nostack));
+        }
+    }
+    let result = f();
+    if smap {
+        // SAFETY: as above, clearing RFLAGS.AC
+        unsafe {
+            core::arch::asm!("clac", options(nomem, nostack));
+        }
+    }
+    result
+}
+
+/// Returns `true` if a page fault is the kernel touching a mapped user page
+///
+/// This is what SMEP and SMAP report: a protection violation (not a missing
+/// page) raised in supervisor mode at a user-space address.
+///
+/// # Arguments
+///
+/// * `error_code` - Page fault error code
+/// * `fault_addr` - Faulting address from CR2
+pub const fn is_supervisor_user_access(error_code: u64, fault_addr: u64) -> bool {
+    error_code & PF_PRESENT != 0
+        && error_code & PF_USER == 0
+        && fault_addr >= USER_SPACE_START
+        && fault_addr < USER_SPACE_END
+}
+
+/// Returns `true` if a page fault was raised by SMEP or SMAP
+pub fn is_smep_smap_violation(error_code: u64, fault_addr: u64) -> bool {
+    read_cr4() & (CR4_SMEP | CR4_SMAP) != 0 && is_supervisor_user_access(error_code, fault_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_cr4_bits_follow_cpuid() {
+        let enabled = enable_smep_smap();
+        let cr4 = read_cr4();
+        assert_eq!(cr4 & CR4_SMEP != 0, enabled.contains(CpuFeatures::SMEP));
+        assert_eq!(cr4 & CR4_SMAP != 0, enabled.contains(CpuFeatures::SMAP));
+        assert_eq!(
+            enabled,
+            super::super::features() & (CpuFeatures::SMEP | CpuFeatures::SMAP)
+        );
+    }
+
+    #[test_case]
+    fn test_supervisor_user_access() {
+        // Supervisor write to a present user page
+        assert!(is_supervisor_user_access(0b011, USER_SPACE_START));
+        // Missing page, user-mode fault and kernel addresses are not
+        assert!(!is_supervisor_user_access(0b010, USER_SPACE_START));
+        assert!(!is_supervisor_user_access(0b111, USER_SPACE_START));
+        assert!(!is_supervisor_user_access(0b011, 0xffff_ffff_8000_0000));
+        assert!(!is_supervisor_user_access(0b011, 0x40_0000));
+    }
+
+    #[test_case]
+    fn test_with_user_access_returns_result() {
+        assert_eq!(with_user_access(|| 42), 42);
+    }
+}
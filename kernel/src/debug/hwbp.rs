@@ -0,0 +1,401 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hardware breakpoints
+//!
+//! x86-64 has four debug address registers, `DR0` to `DR3`, each watching
+//! one address. `DR7` enables them and selects what access triggers them
+//! and how many bytes they cover. When one fires the CPU raises a debug
+//! exception (#DB) and sets the matching bit in `DR6`, which the handler
+//! reads with `read_status`.
+//!
+//! The breakpoints are local to this CPU and are not saved across context
+//! switches.
+
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+};
+
+use crate::memory::VirtAddr;
+
+/// Number of hardware breakpoints
+pub const BREAKPOINT_COUNT: u8 = 4;
+
+/// `DR6` bits reporting which breakpoint fired, one per breakpoint
+const DR6_BREAKPOINTS: u64 = 0b1111;
+/// `DR6` bit set when a debug register access was detected
+const DR6_BD: u64 = 1 << 13;
+/// `DR6` bit set by a single step
+const DR6_BS: u64 = 1 << 14;
+/// Value of `DR6` with no debug condition recorded; the reserved bits
+/// read as ones
+const DR6_CLEAR: u64 = 0xffff_0ff0;
+
+/// First bit of the condition and length fields in `DR7`
+const DR7_CONTROL_SHIFT: u64 = 16;
+
+/// Number of debug exceptions taken
+pub static DEBUG_EXCEPTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// `DR6` value of the most recent debug exception, `DR6_CLEAR` before the
+/// first one
+pub static LAST_DEBUG_STATUS: AtomicU64 = AtomicU64::new(DR6_CLEAR);
+
+/// Instruction pointer of the most recent debug exception, 0 before the
+/// first one
+pub static LAST_DEBUG_RIP: AtomicU64 = AtomicU64::new(0);
+
+/// What access triggers a breakpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BpKind {
+    /// Executing the instruction at the address
+    Execute,
+    /// Writing the address
+    Write,
+    /// Reading or writing the address
+    ReadWrite,
+}
+
+impl BpKind {
+    /// Returns the `R/W` field encoding
+    const fn bits(self) -> u64 {
+        match self {
+            Self::Execute => 0b00,
+            Self::Write => 0b01,
+            Self::ReadWrite => 0b11,
+        }
+    }
+}
+
+/// Number of bytes a breakpoint covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BpLen {
+    Byte,
+    Word,
+    Dword,
+    Qword,
+}
+
+impl BpLen {
+    /// Returns the size in bytes, to which the address must be aligned
+    pub const fn size(self) -> u64 {
+        match self {
+            Self::Byte => 1,
+            Self::Word => 2,
+            Self::Dword => 4,
+            Self::Qword => 8,
+        }
+    }
+
+    /// Returns the `LEN` field encoding
+    const fn bits(self) -> u64 {
+        match self {
+            Self::Byte => 0b00,
+            Self::Word => 0b01,
+            Self::Dword => 0b11,
+            Self::Qword => 0b10,
+        }
+    }
+}
+
+/// Errors returned when setting a breakpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HwBpError {
+    /// The index is not below `BREAKPOINT_COUNT`
+    InvalidIndex,
+    /// The address is not aligned to the breakpoint length
+    Misaligned,
+    /// Execute breakpoints must cover a single byte
+    InvalidLength,
+}
+
+impl fmt::Display for HwBpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidIndex => write!(f, "no such hardware breakpoint"),
+            Self::Misaligned => write!(f, "breakpoint address not aligned to its length"),
+            Self::InvalidLength => write!(f, "execute breakpoints must be one byte long"),
+        }
+    }
+}
+
+/// Debug conditions recorded in `DR6`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugStatus(u64);
+
+impl DebugStatus {
+    /// Returns the lowest-numbered breakpoint that fired
+    pub fn breakpoint(self) -> Option<u8> {
+        let hits = self.0 & DR6_BREAKPOINTS;
+        (hits != 0).then(|| hits.trailing_zeros() as u8)
+    }
+
+    /// Returns whether the exception was raised by a single step
+    pub fn single_step(self) -> bool {
+        self.0 & DR6_BS != 0
+    }
+
+    /// Returns whether a debug register access was detected
+    pub fn register_access(self) -> bool {
+        self.0 & DR6_BD != 0
+    }
+}
+
+/// Sets hardware breakpoint `index` on `addr` and enables it
+///
+/// # Errors
+///
+/// Returns `HwBpError::InvalidIndex` for an index of 4 or more,
+/// `HwBpError::Misaligned` if `addr` is not a multiple of the length, or
+/// `HwBpError::InvalidLength` for an execute breakpoint longer than a byte.
+pub fn set_breakpoint(
+    index: u8,
+    addr: VirtAddr,
+    kind: BpKind,
+    len: BpLen,
+) -> Result<(), HwBpError> {
+    if index >= BREAKPOINT_COUNT {
+        return Err(HwBpError::InvalidIndex);
+    }
+    if kind == BpKind::Execute && len != BpLen::Byte {
+        return Err(HwBpError::InvalidLength);
+    }
+    if !addr.as_u64().is_multiple_of(len.size()) {
+        return Err(HwBpError::Misaligned);
+    }
+
+    let control_shift = DR7_CONTROL_SHIFT + 4 * u64::from(index);
+    crate::interrupts::without_interrupts(|| {
+        // SAFETY: the breakpoint only raises debug exceptions, which the
+        // debug handler returns from
+        unsafe {
+            write_address(index, addr.as_u64());
+            let dr7 = read_dr7() & !(0b1111 << control_shift);
+            write_dr7(dr7 | (kind.bits() | len.bits() << 2) << control_shift | local_enable(index));
+        }
+    });
+    Ok(())
+}
+
+/// Disables hardware breakpoint `index`
+///
+/// Out-of-range indices are ignored.
+pub fn clear_breakpoint(index: u8) {
+    if index >= BREAKPOINT_COUNT {
+        return;
+    }
+    crate::interrupts::without_interrupts(|| {
+        // SAFETY: disabling a breakpoint has no other effect
+        unsafe { write_dr7(read_dr7() & !local_enable(index)) };
+    });
+}
+
+/// Returns the address breakpoint `index` watches, or `None` if it is
+/// disabled
+pub fn breakpoint_address(index: u8) -> Option<VirtAddr> {
+    if index >= BREAKPOINT_COUNT || read_dr7() & local_enable(index) == 0 {
+        return None;
+    }
+    Some(VirtAddr::new(read_address(index)))
+}
+
+/// Reads the debug conditions recorded in `DR6`
+pub fn read_status() -> DebugStatus {
+    let dr6: u64;
+    // SAFETY: reading DR6 has no side effects
+    unsafe {
+        core::arch::asm!("mov {}, dr6", out(reg) dr6, options(nomem, nostack, preserves_flags));
+    }
+    DebugStatus(dr6)
+}
+
+/// Resets `DR6`
+///
+/// The CPU never clears the bits itself, so the debug handler does this
+/// before returning.
+pub fn clear_status() {
+    // SAFETY: DR6 only reports debug conditions
+    unsafe {
+        core::arch::asm!("mov dr6, {}", in(reg) DR6_CLEAR, options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Records the cause and location of a debug exception
+///
+/// Only touches atomics, so it is safe in the debug handler, which runs
+/// even while the interrupted code holds a lock.
+pub fn record_exception(status: DebugStatus, instruction_pointer: u64) {
+    LAST_DEBUG_STATUS.store(status.0, Ordering::Relaxed);
+    LAST_DEBUG_RIP.store(instruction_pointer, Ordering::Relaxed);
+    DEBUG_EXCEPTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the cause and instruction pointer of the most recent debug
+/// exception, or `None` if there was none
+pub fn last_exception() -> Option<(DebugStatus, u64)> {
+    if DEBUG_EXCEPTIONS.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    Some((
+        DebugStatus(LAST_DEBUG_STATUS.load(Ordering::Relaxed)),
+        LAST_DEBUG_RIP.load(Ordering::Relaxed),
+    ))
+}
+
+/// Returns the `DR7` local enable bit of breakpoint `index`
+const fn local_enable(index: u8) -> u64 {
+    1 << (2 * index)
+}
+
+fn read_dr7() -> u64 {
+    let dr7: u64;
+    // SAFETY: reading DR7 has no side effects
+    unsafe {
+        core::arch::asm!("mov {}, dr7", out(reg) dr7, options(nomem, nostack, preserves_flags));
+    }
+    dr7
+}
+
+/// Writes `DR7`
+///
+/// # Safety
+///
+/// The enabled breakpoints must not fire where a debug exception cannot be
+/// handled.
+unsafe fn write_dr7(value: u64) {
+    // SAFETY: guaranteed by the caller
+    unsafe {
+        core::arch::asm!("mov dr7, {}", in(reg) value, options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Reads the address register of breakpoint `index`
+fn read_address(index: u8) -> u64 {
+    let addr: u64;
+    // SAFETY: reading a debug address register has no side effects
+    unsafe {
+        match index {
+            0 => {
+                core::arch::asm!("mov {}, dr0", out(reg) addr, options(nomem, nostack, preserves_flags))
+            }
+            1 => {
+                core::arch::asm!("mov {}, dr1", out(reg) addr, options(nomem, nostack, preserves_flags))
+            }
+            2 => {
+                core::arch::asm!("mov {}, dr2", out(reg) addr, options(nomem, nostack, preserves_flags))
+            }
+            _ => {
+                core::arch::asm!("mov {}, dr3", out(reg) addr, options(nomem, nostack, preserves_flags))
+            }
+        }
+    }
+    addr
+}
+
+/// Writes the address register of breakpoint `index`
+///
+/// # Safety
+///
+/// Same as `write_dr7` if the breakpoint is enabled.
+unsafe fn write_address(index: u8, addr: u64) {
+    // SAFETY: guaranteed by the caller
+    unsafe {
+        match index {
+            0 => {
+                core::arch::asm!("mov dr0, {}", in(reg) addr, options(nomem, nostack, preserves_flags))
+            }
+            1 => {
+                core::arch::asm!("mov dr1, {}", in(reg) addr, options(nomem, nostack, preserves_flags))
+            }
+            2 => {
+                core::arch::asm!("mov dr2, {}", in(reg) addr, options(nomem, nostack, preserves_flags))
+            }
+            _ => {
+                core::arch::asm!("mov dr3, {}", in(reg) addr, options(nomem, nostack, preserves_flags))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Watched by the tests but never written, so it does not fire
+    static WATCHED: u64 = 0;
+
+    #[test_case]
+    fn test_set_breakpoint_writes_dr0() {
+        let addr = VirtAddr::from_ptr(&WATCHED);
+        set_breakpoint(0, addr, BpKind::Write, BpLen::Qword).unwrap();
+        assert_eq!(read_address(0), addr.as_u64());
+        assert_eq!(breakpoint_address(0), Some(addr));
+
+        let dr7 = read_dr7();
+        assert_eq!(dr7 & 1, 1);
+        // Write access (01) over eight bytes (10)
+        assert_eq!(dr7 >> DR7_CONTROL_SHIFT & 0b1111, 0b1001);
+
+        clear_breakpoint(0);
+        assert_eq!(read_dr7() & 1, 0);
+        assert_eq!(breakpoint_address(0), None);
+    }
+
+    #[test_case]
+    fn test_set_breakpoint_rejects_bad_arguments() {
+        let addr = VirtAddr::from_ptr(&WATCHED);
+        assert_eq!(
+            set_breakpoint(BREAKPOINT_COUNT, addr, BpKind::Write, BpLen::Byte),
+            Err(HwBpError::InvalidIndex)
+        );
+        assert_eq!(
+            set_breakpoint(1, addr + 1u64, BpKind::ReadWrite, BpLen::Dword),
+            Err(HwBpError::Misaligned)
+        );
+        assert_eq!(
+            set_breakpoint(1, addr, BpKind::Execute, BpLen::Word),
+            Err(HwBpError::InvalidLength)
+        );
+        assert_eq!(breakpoint_address(1), None);
+    }
+
+    #[test_case]
+    fn test_debug_status() {
+        let status = DebugStatus(DR6_CLEAR | 0b0110 | DR6_BS);
+        assert_eq!(status.breakpoint(), Some(1));
+        assert!(status.single_step());
+        assert!(!status.register_access());
+        assert_eq!(DebugStatus(DR6_CLEAR).breakpoint(), None);
+    }
+
+    #[test_case]
+    fn test_record_exception() {
+        let saved_count = DEBUG_EXCEPTIONS.load(Ordering::Relaxed);
+        let saved_status = LAST_DEBUG_STATUS.load(Ordering::Relaxed);
+        let saved_rip = LAST_DEBUG_RIP.load(Ordering::Relaxed);
+
+        let status = DebugStatus(DR6_CLEAR | 0b0100);
+        record_exception(status, 0xffff_8000_0010_0000);
+        assert_eq!(last_exception(), Some((status, 0xffff_8000_0010_0000)));
+        assert_eq!(DEBUG_EXCEPTIONS.load(Ordering::Relaxed), saved_count + 1);
+
+        DEBUG_EXCEPTIONS.store(saved_count, Ordering::Relaxed);
+        LAST_DEBUG_STATUS.store(saved_status, Ordering::Relaxed);
+        LAST_DEBUG_RIP.store(saved_rip, Ordering::Relaxed);
+    }
+}
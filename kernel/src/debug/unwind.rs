@@ -0,0 +1,873 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! DWARF CFI stack unwinder
+//!
+//! The kernel is built without frame pointers, so RBP chaining cannot be used
+//! to walk the stack. Instead, this module reads the `.eh_frame` section that
+//! the linker script keeps between `__eh_frame_start` and `__eh_frame_end`,
+//! finds the FDE (Frame Description Entry) covering a PC, and evaluates the
+//! CFA (Call Frame Address) instructions of its CIE (Common Information
+//! Entry) and FDE to recover the caller's frame.
+//!
+//! Only the subset of DWARF needed for compiler-generated x86_64 code is
+//! supported: register/offset CFA rules and offset/register rules for the
+//! return address and RBP. Expression-based rules stop the unwind.
+
+/// DWARF register number of RBP
+const DW_REG_RBP: u16 = 6;
+/// DWARF register number of RSP
+const DW_REG_RSP: u16 = 7;
+
+/// Maximum depth of the `DW_CFA_remember_state` stack
+const MAX_STATE_STACK: usize = 4;
+
+/// Maximum number of frames walked by `walk_stack`
+pub const MAX_FRAMES: usize = 32;
+
+// Call frame instructions (DWARF 5, section 6.4.2)
+const DW_CFA_ADVANCE_LOC: u8 = 0x40;
+const DW_CFA_OFFSET: u8 = 0x80;
+const DW_CFA_RESTORE: u8 = 0xc0;
+const DW_CFA_NOP: u8 = 0x00;
+const DW_CFA_SET_LOC: u8 = 0x01;
+const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
+const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_OFFSET_EXTENDED: u8 = 0x05;
+const DW_CFA_RESTORE_EXTENDED: u8 = 0x06;
+const DW_CFA_UNDEFINED: u8 = 0x07;
+const DW_CFA_SAME_VALUE: u8 = 0x08;
+const DW_CFA_REGISTER: u8 = 0x09;
+const DW_CFA_REMEMBER_STATE: u8 = 0x0a;
+const DW_CFA_RESTORE_STATE: u8 = 0x0b;
+const DW_CFA_DEF_CFA: u8 = 0x0c;
+const DW_CFA_DEF_CFA_REGISTER: u8 = 0x0d;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0e;
+const DW_CFA_DEF_CFA_EXPRESSION: u8 = 0x0f;
+const DW_CFA_EXPRESSION: u8 = 0x10;
+const DW_CFA_OFFSET_EXTENDED_SF: u8 = 0x11;
+const DW_CFA_DEF_CFA_SF: u8 = 0x12;
+const DW_CFA_DEF_CFA_OFFSET_SF: u8 = 0x13;
+const DW_CFA_VAL_EXPRESSION: u8 = 0x16;
+const DW_CFA_GNU_ARGS_SIZE: u8 = 0x2e;
+
+// Pointer encodings (LSB Core specification, section 10.5)
+const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_ULEB128: u8 = 0x01;
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_UDATA8: u8 = 0x04;
+const DW_EH_PE_SLEB128: u8 = 0x09;
+const DW_EH_PE_SDATA2: u8 = 0x0a;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+const DW_EH_PE_SDATA8: u8 = 0x0c;
+const DW_EH_PE_PCREL: u8 = 0x10;
+
+extern "C" {
+    static __eh_frame_start: u8;
+    static __eh_frame_end: u8;
+}
+
+/// Register state of a single stack frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Instruction pointer (a return address for all but the first frame)
+    pub pc: u64,
+    /// Stack pointer at `pc`
+    pub sp: u64,
+    /// Frame/base pointer at `pc`
+    pub bp: u64,
+}
+
+/// How a register of the caller can be recovered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RegisterRule {
+    /// The register cannot be recovered
+    Undefined,
+    /// The register was not modified by the callee
+    SameValue,
+    /// The register was saved at `CFA + offset`
+    Offset(i64),
+    /// The register was saved in another register
+    Register(u16),
+}
+
+/// One row of the virtual unwind table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct UnwindRow {
+    cfa_register: u16,
+    cfa_offset: i64,
+    rbp: RegisterRule,
+    ra: RegisterRule,
+}
+
+impl UnwindRow {
+    const fn new() -> Self {
+        Self {
+            cfa_register: DW_REG_RSP,
+            cfa_offset: 0,
+            rbp: RegisterRule::SameValue,
+            ra: RegisterRule::Undefined,
+        }
+    }
+}
+
+/// Parsed Common Information Entry
+#[derive(Debug, Clone, Copy)]
+struct Cie<'a> {
+    code_align: u64,
+    data_align: i64,
+    ra_register: u16,
+    fde_encoding: u8,
+    has_augmentation_data: bool,
+    instructions: &'a [u8],
+}
+
+/// Parsed Frame Description Entry
+#[derive(Debug, Clone, Copy)]
+struct Fde<'a> {
+    cie: Cie<'a>,
+    pc_begin: u64,
+    instructions: &'a [u8],
+}
+
+/// Cursor over a byte slice located at a known virtual address
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Virtual address of `data[0]`, used for PC-relative pointers
+    base: u64,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], base: u64) -> Self {
+        Self { data, pos: 0, base }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn uleb128(&mut self) -> Option<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Option<i64> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1i64 << shift;
+                }
+                return Some(result);
+            }
+        }
+    }
+
+    fn register(&mut self) -> Option<u16> {
+        u16::try_from(self.uleb128()?).ok()
+    }
+
+    /// Reads a pointer in the given `DW_EH_PE_*` encoding
+    fn encoded(&mut self, encoding: u8) -> Option<u64> {
+        if encoding == DW_EH_PE_OMIT {
+            return None;
+        }
+
+        let address = self.base.wrapping_add(self.pos as u64);
+        let value = match encoding & 0x0f {
+            DW_EH_PE_ABSPTR | DW_EH_PE_UDATA8 => self.u64()?,
+            DW_EH_PE_ULEB128 => self.uleb128()?,
+            DW_EH_PE_UDATA2 => u64::from(self.u16()?),
+            DW_EH_PE_UDATA4 => u64::from(self.u32()?),
+            DW_EH_PE_SLEB128 => self.sleb128()? as u64,
+            DW_EH_PE_SDATA2 => self.u16()? as i16 as i64 as u64,
+            DW_EH_PE_SDATA4 => self.u32()? as i32 as i64 as u64,
+            DW_EH_PE_SDATA8 => self.u64()?,
+            _ => return None,
+        };
+
+        match encoding & 0x70 {
+            0 => Some(value),
+            DW_EH_PE_PCREL => Some(address.wrapping_add(value)),
+            // Text/data/function-relative and indirect pointers are not
+            // generated for the kernel
+            _ => None,
+        }
+    }
+}
+
+/// Returns the kernel's `.eh_frame` section and its virtual address
+fn kernel_eh_frame() -> (&'static [u8], u64) {
+    // SAFETY: the symbols are defined by the linker script and delimit the
+    // `.eh_frame` output section, which is mapped read-only for the lifetime
+    // of the kernel.
+    unsafe {
+        let start = core::ptr::addr_of!(__eh_frame_start);
+        let end = core::ptr::addr_of!(__eh_frame_end);
+        let len = (end as usize).saturating_sub(start as usize);
+        (core::slice::from_raw_parts(start, len), start as u64)
+    }
+}
+
+/// Parses the CIE at `offset` within `eh_frame`
+fn parse_cie(eh_frame: &[u8], base: u64, offset: usize) -> Option<Cie<'_>> {
+    let mut reader = Reader::new(eh_frame, base);
+    reader.pos = offset;
+
+    let length = reader.u32()? as usize;
+    // 64-bit DWARF is never emitted for the kernel
+    if length == 0 || length == 0xffff_ffff {
+        return None;
+    }
+    let end = reader.pos.checked_add(length)?;
+    if end > eh_frame.len() || reader.u32()? != 0 {
+        return None;
+    }
+
+    let version = reader.u8()?;
+    if version != 1 && version != 3 {
+        return None;
+    }
+
+    let augmentation_start = reader.pos;
+    while reader.u8()? != 0 {}
+    let augmentation = &eh_frame[augmentation_start..reader.pos - 1];
+
+    let code_align = reader.uleb128()?;
+    let data_align = reader.sleb128()?;
+    let ra_register = if version == 1 {
+        u16::from(reader.u8()?)
+    } else {
+        reader.register()?
+    };
+
+    let mut fde_encoding = DW_EH_PE_ABSPTR;
+    let has_augmentation_data = augmentation.first() == Some(&b'z');
+    if has_augmentation_data {
+        let data_len = reader.uleb128()? as usize;
+        let data_end = reader.pos.checked_add(data_len)?;
+        for &c in &augmentation[1..] {
+            match c {
+                b'R' => fde_encoding = reader.u8()?,
+                b'P' => {
+                    let encoding = reader.u8()?;
+                    reader.encoded(encoding & 0x7f)?;
+                }
+                b'L' => {
+                    reader.u8()?;
+                }
+                b'S' => {}
+                _ => break,
+            }
+        }
+        reader.pos = data_end;
+    } else if !augmentation.is_empty() {
+        return None;
+    }
+
+    Some(Cie {
+        code_align,
+        data_align,
+        ra_register,
+        fde_encoding,
+        has_augmentation_data,
+        instructions: eh_frame.get(reader.pos..end)?,
+    })
+}
+
+/// Finds the FDE covering `pc`
+fn find_fde(eh_frame: &[u8], base: u64, pc: u64) -> Option<Fde<'_>> {
+    let mut offset = 0;
+
+    while offset + 4 <= eh_frame.len() {
+        let mut reader = Reader::new(eh_frame, base);
+        reader.pos = offset;
+
+        let length = reader.u32()? as usize;
+        if length == 0 || length == 0xffff_ffff {
+            return None;
+        }
+        let end = reader.pos.checked_add(length)?;
+        if end > eh_frame.len() {
+            return None;
+        }
+
+        let id_pos = reader.pos;
+        let cie_pointer = reader.u32()? as usize;
+        if cie_pointer != 0 {
+            let cie_offset = id_pos.checked_sub(cie_pointer)?;
+            let cie = parse_cie(eh_frame, base, cie_offset)?;
+
+            let pc_begin = reader.encoded(cie.fde_encoding)?;
+            let pc_range = reader.encoded(cie.fde_encoding & 0x0f)?;
+
+            if pc >= pc_begin && pc - pc_begin < pc_range {
+                if cie.has_augmentation_data {
+                    let data_len = reader.uleb128()? as usize;
+                    reader.pos = reader.pos.checked_add(data_len)?;
+                }
+                return Some(Fde {
+                    cie,
+                    pc_begin,
+                    instructions: eh_frame.get(reader.pos..end)?,
+                });
+            }
+        }
+
+        offset = end;
+    }
+
+    None
+}
+
+/// Virtual unwind state machine for one FDE
+struct CfiMachine<'a> {
+    fde: &'a Fde<'a>,
+    base: u64,
+    row: UnwindRow,
+    initial: UnwindRow,
+    stack: [UnwindRow; MAX_STATE_STACK],
+    depth: usize,
+    loc: u64,
+}
+
+impl<'a> CfiMachine<'a> {
+    fn new(fde: &'a Fde<'a>, base: u64) -> Self {
+        Self {
+            fde,
+            base,
+            row: UnwindRow::new(),
+            initial: UnwindRow::new(),
+            stack: [UnwindRow::new(); MAX_STATE_STACK],
+            depth: 0,
+            loc: fde.pc_begin,
+        }
+    }
+
+    /// Computes the unwind row in effect at `pc`
+    fn run(mut self, eh_frame: &'a [u8], pc: u64) -> Option<UnwindRow> {
+        let cie_insns = self.fde.cie.instructions;
+        self.execute(eh_frame, cie_insns, u64::MAX)?;
+        self.initial = self.row;
+
+        let fde_insns = self.fde.instructions;
+        self.execute(eh_frame, fde_insns, pc)?;
+        Some(self.row)
+    }
+
+    fn set_rule(&mut self, register: u16, rule: RegisterRule) {
+        if register == DW_REG_RBP {
+            self.row.rbp = rule;
+        } else if register == self.fde.cie.ra_register {
+            self.row.ra = rule;
+        }
+    }
+
+    fn initial_rule(&self, register: u16) -> RegisterRule {
+        if register == DW_REG_RBP {
+            self.initial.rbp
+        } else if register == self.fde.cie.ra_register {
+            self.initial.ra
+        } else {
+            RegisterRule::Undefined
+        }
+    }
+
+    fn is_tracked(&self, register: u16) -> bool {
+        register == DW_REG_RBP || register == self.fde.cie.ra_register
+    }
+
+    /// Advances the location; returns `false` once `pc` has been passed
+    fn advance(&mut self, delta: u64, pc: u64) -> bool {
+        let loc = self
+            .loc
+            .saturating_add(delta.saturating_mul(self.fde.cie.code_align));
+        if loc > pc {
+            return false;
+        }
+        self.loc = loc;
+        true
+    }
+
+    /// Executes call frame instructions until the row for `pc` is reached
+    fn execute(&mut self, eh_frame: &'a [u8], instructions: &'a [u8], pc: u64) -> Option<()> {
+        // Offset of the instructions within `.eh_frame`, for DW_CFA_set_loc
+        let offset = (instructions.as_ptr() as usize).wrapping_sub(eh_frame.as_ptr() as usize);
+        let mut reader = Reader::new(instructions, self.base.wrapping_add(offset as u64));
+        let data_align = self.fde.cie.data_align;
+
+        while !reader.is_empty() {
+            let opcode = reader.u8()?;
+            let low = opcode & 0x3f;
+
+            match opcode & 0xc0 {
+                DW_CFA_ADVANCE_LOC => {
+                    if !self.advance(u64::from(low), pc) {
+                        return Some(());
+                    }
+                    continue;
+                }
+                DW_CFA_OFFSET => {
+                    let offset = reader.uleb128()? as i64 * data_align;
+                    self.set_rule(u16::from(low), RegisterRule::Offset(offset));
+                    continue;
+                }
+                DW_CFA_RESTORE => {
+                    let rule = self.initial_rule(u16::from(low));
+                    self.set_rule(u16::from(low), rule);
+                    continue;
+                }
+                _ => {}
+            }
+
+            match opcode {
+                DW_CFA_NOP => {}
+                DW_CFA_SET_LOC => {
+                    let loc = reader.encoded(self.fde.cie.fde_encoding)?;
+                    if loc > pc {
+                        return Some(());
+                    }
+                    self.loc = loc;
+                }
+                DW_CFA_ADVANCE_LOC1 => {
+                    let delta = u64::from(reader.u8()?);
+                    if !self.advance(delta, pc) {
+                        return Some(());
+                    }
+                }
+                DW_CFA_ADVANCE_LOC2 => {
+                    let delta = u64::from(reader.u16()?);
+                    if !self.advance(delta, pc) {
+                        return Some(());
+                    }
+                }
+                DW_CFA_ADVANCE_LOC4 => {
+                    let delta = u64::from(reader.u32()?);
+                    if !self.advance(delta, pc) {
+                        return Some(());
+                    }
+                }
+                DW_CFA_OFFSET_EXTENDED => {
+                    let register = reader.register()?;
+                    let offset = reader.uleb128()? as i64 * data_align;
+                    self.set_rule(register, RegisterRule::Offset(offset));
+                }
+                DW_CFA_OFFSET_EXTENDED_SF => {
+                    let register = reader.register()?;
+                    let offset = reader.sleb128()? * data_align;
+                    self.set_rule(register, RegisterRule::Offset(offset));
+                }
+                DW_CFA_RESTORE_EXTENDED => {
+                    let register = reader.register()?;
+                    let rule = self.initial_rule(register);
+                    self.set_rule(register, rule);
+                }
+                DW_CFA_UNDEFINED => {
+                    let register = reader.register()?;
+                    self.set_rule(register, RegisterRule::Undefined);
+                }
+                DW_CFA_SAME_VALUE => {
+                    let register = reader.register()?;
+                    self.set_rule(register, RegisterRule::SameValue);
+                }
+                DW_CFA_REGISTER => {
+                    let register = reader.register()?;
+                    let source = reader.register()?;
+                    self.set_rule(register, RegisterRule::Register(source));
+                }
+                DW_CFA_REMEMBER_STATE => {
+                    if self.depth == MAX_STATE_STACK {
+                        return None;
+                    }
+                    self.stack[self.depth] = self.row;
+                    self.depth += 1;
+                }
+                DW_CFA_RESTORE_STATE => {
+                    self.depth = self.depth.checked_sub(1)?;
+                    // The CFA rule is not part of the remembered state
+                    let (cfa_register, cfa_offset) = (self.row.cfa_register, self.row.cfa_offset);
+                    self.row = self.stack[self.depth];
+                    self.row.cfa_register = cfa_register;
+                    self.row.cfa_offset = cfa_offset;
+                }
+                DW_CFA_DEF_CFA => {
+                    self.row.cfa_register = reader.register()?;
+                    self.row.cfa_offset = reader.uleb128()? as i64;
+                }
+                DW_CFA_DEF_CFA_SF => {
+                    self.row.cfa_register = reader.register()?;
+                    self.row.cfa_offset = reader.sleb128()? * data_align;
+                }
+                DW_CFA_DEF_CFA_REGISTER => {
+                    self.row.cfa_register = reader.register()?;
+                }
+                DW_CFA_DEF_CFA_OFFSET => {
+                    self.row.cfa_offset = reader.uleb128()? as i64;
+                }
+                DW_CFA_DEF_CFA_OFFSET_SF => {
+                    self.row.cfa_offset = reader.sleb128()? * data_align;
+                }
+                DW_CFA_EXPRESSION | DW_CFA_VAL_EXPRESSION => {
+                    let register = reader.register()?;
+                    let len = reader.uleb128()? as usize;
+                    reader.bytes(len)?;
+                    if self.is_tracked(register) {
+                        return None;
+                    }
+                }
+                DW_CFA_GNU_ARGS_SIZE => {
+                    reader.uleb128()?;
+                }
+                // Computing the CFA from an expression is not supported
+                DW_CFA_DEF_CFA_EXPRESSION => return None,
+                _ => return None,
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Unwinds one frame using the given `.eh_frame` data and memory accessor
+///
+/// `lookup_pc` is the address used to find the FDE; for return addresses it
+/// should point inside the call instruction rather than after it.
+fn unwind_with(
+    eh_frame: &[u8],
+    base: u64,
+    frame: Frame,
+    lookup_pc: u64,
+    read: impl Fn(u64) -> Option<u64>,
+) -> Option<Frame> {
+    let fde = find_fde(eh_frame, base, lookup_pc)?;
+    let row = CfiMachine::new(&fde, base).run(eh_frame, lookup_pc)?;
+
+    let register_value = |register: u16| match register {
+        DW_REG_RSP => Some(frame.sp),
+        DW_REG_RBP => Some(frame.bp),
+        _ => None,
+    };
+
+    let cfa = register_value(row.cfa_register)?.wrapping_add(row.cfa_offset as u64);
+
+    let pc = match row.ra {
+        RegisterRule::Offset(offset) => read(cfa.wrapping_add(offset as u64))?,
+        RegisterRule::Register(register) => register_value(register)?,
+        RegisterRule::Undefined | RegisterRule::SameValue => return None,
+    };
+
+    let bp = match row.rbp {
+        RegisterRule::Offset(offset) => read(cfa.wrapping_add(offset as u64))?,
+        RegisterRule::Register(register) => register_value(register)?,
+        RegisterRule::Undefined | RegisterRule::SameValue => frame.bp,
+    };
+
+    // On x86_64 the caller's stack pointer is the CFA by definition
+    Some(Frame { pc, sp: cfa, bp })
+}
+
+/// Reads a saved register from the kernel stack
+fn read_stack(addr: u64) -> Option<u64> {
+    if !addr.is_multiple_of(8) || !is_valid_kernel_address(addr) {
+        return None;
+    }
+    // SAFETY: the address is aligned and lies in the kernel half, where all
+    // kernel stacks live.
+    Some(unsafe { core::ptr::read_volatile(addr as *const u64) })
+}
+
+/// Check if an address is a valid kernel address
+///
+/// Kernel addresses should be in the higher half of the address space.
+fn is_valid_kernel_address(addr: u64) -> bool {
+    (0xffff_8000_0000_0000..0xffff_ffff_ffff_fff8).contains(&addr)
+}
+
+/// Computes the caller's frame for the function executing at `pc`
+///
+/// # Arguments
+///
+/// * `pc` - Instruction pointer within the current function
+/// * `sp` - Stack pointer at `pc`
+/// * `bp` - RBP at `pc` (only needed if the CFA is RBP-based)
+///
+/// # Returns
+///
+/// The return address and the caller's stack pointer, or `None` if no
+/// unwind information covers `pc` or the frame cannot be decoded.
+pub fn unwind_frame(pc: u64, sp: u64, bp: u64) -> Option<(u64, u64)> {
+    let (eh_frame, base) = kernel_eh_frame();
+    let caller = unwind_with(eh_frame, base, Frame { pc, sp, bp }, pc, read_stack)?;
+    Some((caller.pc, caller.sp))
+}
+
+/// Walks the call stack of the current function's caller
+///
+/// `callback` is invoked with the frame index and the frame state for each
+/// return address found, starting with the caller of `walk_stack`. At most
+/// `MAX_FRAMES` frames are visited.
+///
+/// # Returns
+///
+/// The number of frames visited
+#[inline(never)]
+pub fn walk_stack(mut callback: impl FnMut(usize, &Frame)) -> usize {
+    let (pc, sp, bp): (u64, u64, u64);
+    // SAFETY: only reads registers. RSP is captured in the same asm block as
+    // the PC so the CFI row for `pc` applies to it.
+    unsafe {
+        core::arch::asm!(
+            "lea {pc}, [rip]",
+            "mov {sp}, rsp",
+            "mov {bp}, rbp",
+            pc = out(reg) pc,
+            sp = out(reg) sp,
+            bp = out(reg) bp,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+
+    let (eh_frame, base) = kernel_eh_frame();
+    let mut frame = Frame { pc, sp, bp };
+    let mut lookup_pc = pc;
+    let mut count = 0;
+
+    while count < MAX_FRAMES {
+        let Some(caller) = unwind_with(eh_frame, base, frame, lookup_pc, read_stack) else {
+            break;
+        };
+        // The stack grows down, so every caller's frame must be above ours
+        if caller.pc == 0 || caller.sp <= frame.sp {
+            break;
+        }
+
+        callback(count, &caller);
+        count += 1;
+
+        frame = caller;
+        // Look up the call instruction, not the instruction after it, in
+        // case the call is the last instruction of the function
+        lookup_pc = caller.pc - 1;
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Virtual address the test `.eh_frame` pretends to be loaded at
+    const BASE: u64 = 0xffff_ffff_8010_0000;
+    /// Function covered by the test FDE
+    const FUNC: u64 = 0xffff_ffff_8000_1000;
+
+    /// Builds a CIE + FDE describing a function that pushes RBP at +1, sets
+    /// up RBP as frame pointer at +4 and restores RSP-based CFA at +0x20
+    fn build_eh_frame() -> ([u8; 64], usize) {
+        let mut buf = [0u8; 64];
+        let mut len = 0;
+        let mut push = |len: &mut usize, bytes: &[u8]| {
+            buf[*len..*len + bytes.len()].copy_from_slice(bytes);
+            *len += bytes.len();
+        };
+
+        // CIE: version 1, "zR", code align 1, data align -8, RA reg 16,
+        // FDE encoding pcrel|sdata4, def_cfa rsp+8, offset ra at cfa-8
+        push(&mut len, &20u32.to_le_bytes());
+        push(&mut len, &0u32.to_le_bytes());
+        push(&mut len, &[1, b'z', b'R', 0, 1, 0x78, 16, 1, 0x1b]);
+        push(&mut len, &[
+            DW_CFA_DEF_CFA,
+            7,
+            8,
+            DW_CFA_OFFSET | 16,
+            1,
+            0,
+            0,
+        ]);
+
+        // FDE
+        let fde_start = len;
+        push(&mut len, &28u32.to_le_bytes());
+        push(&mut len, &((fde_start + 4) as u32).to_le_bytes());
+        let field = BASE + len as u64;
+        push(&mut len, &(FUNC.wrapping_sub(field) as i32).to_le_bytes());
+        push(&mut len, &0x40u32.to_le_bytes());
+        push(&mut len, &[0]);
+        push(&mut len, &[
+            DW_CFA_ADVANCE_LOC | 1,
+            DW_CFA_DEF_CFA_OFFSET,
+            16,
+            DW_CFA_OFFSET | 6,
+            2,
+            DW_CFA_ADVANCE_LOC | 3,
+            DW_CFA_DEF_CFA_REGISTER,
+            6,
+            DW_CFA_ADVANCE_LOC1,
+            0x1c,
+            DW_CFA_DEF_CFA,
+            7,
+            8,
+        ]);
+        push(&mut len, &[0, 0]);
+
+        (buf, len)
+    }
+
+    #[test_case]
+    fn test_leb128() {
+        let data = [0xe5, 0x8e, 0x26, 0x7f, 0x80, 0x7f];
+        let mut reader = Reader::new(&data, 0);
+        assert_eq!(reader.uleb128(), Some(624485));
+        assert_eq!(reader.sleb128(), Some(-1));
+        assert_eq!(reader.sleb128(), Some(-128));
+        assert!(reader.is_empty());
+    }
+
+    #[test_case]
+    fn test_find_fde() {
+        let (buf, len) = build_eh_frame();
+        let eh_frame = &buf[..len];
+
+        let fde = find_fde(eh_frame, BASE, FUNC + 0x10).expect("FDE not found");
+        assert_eq!(fde.pc_begin, FUNC);
+        assert_eq!(fde.cie.data_align, -8);
+        assert_eq!(fde.cie.ra_register, 16);
+
+        assert!(find_fde(eh_frame, BASE, FUNC + 0x40).is_none());
+        assert!(find_fde(eh_frame, BASE, FUNC - 1).is_none());
+    }
+
+    #[test_case]
+    fn test_unwind_rows() {
+        let (buf, len) = build_eh_frame();
+        let eh_frame = &buf[..len];
+        let sp = 0xffff_ffff_8020_0000u64;
+        let return_address = 0xffff_ffff_8000_2345u64;
+        let saved_rbp = 0xffff_ffff_8020_0100u64;
+
+        // Memory: [sp] = saved RBP (after push), [sp + 8] = return address
+        let read = |addr: u64| match addr {
+            a if a == sp => Some(saved_rbp),
+            a if a == sp + 8 => Some(return_address),
+            _ => None,
+        };
+
+        // Function entry: CFA = RSP + 8, RBP untouched
+        let entry = Frame {
+            pc: FUNC,
+            sp: sp + 8,
+            bp: 0x1234,
+        };
+        let caller = unwind_with(eh_frame, BASE, entry, FUNC, read).unwrap();
+        assert_eq!(caller, Frame {
+            pc: return_address,
+            sp: sp + 16,
+            bp: 0x1234
+        });
+
+        // After `push rbp`: CFA = RSP + 16, RBP saved at CFA - 16
+        let pushed = Frame {
+            pc: FUNC + 1,
+            sp,
+            bp: 0x1234,
+        };
+        let caller = unwind_with(eh_frame, BASE, pushed, FUNC + 1, read).unwrap();
+        assert_eq!(caller, Frame {
+            pc: return_address,
+            sp: sp + 16,
+            bp: saved_rbp
+        });
+
+        // Body: CFA = RBP + 16, RSP is arbitrary
+        let body = Frame {
+            pc: FUNC + 0x10,
+            sp: sp - 0x40,
+            bp: sp,
+        };
+        let caller = unwind_with(eh_frame, BASE, body, FUNC + 0x10, read).unwrap();
+        assert_eq!(caller, Frame {
+            pc: return_address,
+            sp: sp + 16,
+            bp: saved_rbp
+        });
+
+        // Epilogue after `pop rbp`: CFA = RSP + 8 again
+        let epilogue = Frame {
+            pc: FUNC + 0x20,
+            sp: sp + 8,
+            bp: saved_rbp,
+        };
+        let caller = unwind_with(eh_frame, BASE, epilogue, FUNC + 0x20, read).unwrap();
+        assert_eq!(caller.pc, return_address);
+        assert_eq!(caller.sp, sp + 16);
+    }
+
+    #[test_case]
+    fn test_walk_kernel_stack() {
+        let mut first = None;
+        let frames = walk_stack(|index, frame| {
+            if index == 0 {
+                first = Some(frame.pc);
+            }
+        });
+
+        assert!(frames > 0, "no frames unwound from .eh_frame");
+        assert!(is_valid_kernel_address(first.unwrap()));
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! PCI bus enumeration
+//!
+//! Devices are found through configuration space access mechanism #1: the
+//! address of a configuration register is written to `CONFIG_ADDRESS`,
+//! then the register is read through `CONFIG_DATA`.
+
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::interrupts::port::Port;
+
+/// Configuration space address port
+const CONFIG_ADDRESS: u16 = 0xcf8;
+/// Configuration space data port
+const CONFIG_DATA: u16 = 0xcfc;
+
+/// Address bit enabling the configuration space access
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+/// Configuration register offsets
+const REG_VENDOR_DEVICE: u8 = 0x00;
+const REG_CLASS: u8 = 0x08;
+const REG_HEADER: u8 = 0x0c;
+
+/// Header type bit set if the device implements functions 1 to 7
+const HEADER_MULTIFUNCTION: u8 = 1 << 7;
+
+/// Vendor ID read from an empty slot or function
+const NO_VENDOR: u16 = 0xffff;
+
+/// Number of buses, slots per bus and functions per slot
+const BUS_COUNT: u16 = 256;
+const SLOT_COUNT: u8 = 32;
+const FUNCTION_COUNT: u8 = 8;
+
+/// Serializes the address/data port pairs
+static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+
+/// A PCI function found by `enumerate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub slot: u8,
+    pub func: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+}
+
+impl PciDevice {
+    /// Reads the identification registers of a function
+    ///
+    /// # Returns
+    ///
+    /// The device, or `None` if no function answers at the address
+    pub fn probe(bus: u8, slot: u8, func: u8) -> Option<Self> {
+        let ids = read_config_u32(bus, slot, func, REG_VENDOR_DEVICE);
+        let vendor_id = ids as u16;
+        if vendor_id == NO_VENDOR {
+            return None;
+        }
+        let [_revision, prog_if, subclass, class] =
+            read_config_u32(bus, slot, func, REG_CLASS).to_le_bytes();
+        Some(Self {
+            bus,
+            slot,
+            func,
+            vendor_id,
+            device_id: (ids >> 16) as u16,
+            class,
+            subclass,
+            prog_if,
+        })
+    }
+
+    /// Reads a configuration register of this function
+    pub fn read_config_u32(&self, offset: u8) -> u32 {
+        read_config_u32(self.bus, self.slot, self.func, offset)
+    }
+}
+
+/// Encodes the `CONFIG_ADDRESS` value of a configuration register
+///
+/// The offset is rounded down to a multiple of four.
+pub const fn config_address(bus: u8, slot: u8, func: u8, offset: u8) -> u32 {
+    CONFIG_ENABLE
+        | (bus as u32) << 16
+        | ((slot & 0x1f) as u32) << 11
+        | ((func & 0x07) as u32) << 8
+        | (offset & 0xfc) as u32
+}
+
+/// Reads a 32-bit configuration register
+///
+/// # Arguments
+///
+/// * `bus` - Bus number
+/// * `slot` - Device number on the bus (0-31)
+/// * `func` - Function number of the device (0-7)
+/// * `offset` - Register offset, rounded down to a multiple of four
+///
+/// # Returns
+///
+/// The register value, or all ones if no function answers
+pub fn read_config_u32(bus: u8, slot: u8, func: u8, offset: u8) -> u32 {
+    let mut address = Port::<u32>::new(CONFIG_ADDRESS);
+    let mut data = Port::<u32>::new(CONFIG_DATA);
+    crate::interrupts::without_interrupts(|| {
+        let _guard = CONFIG_LOCK.lock();
+        // SAFETY: reading configuration space has no side effects
+        unsafe {
+            address.write(config_address(bus, slot, func, offset));
+            data.read()
+        }
+    })
+}
+
+/// Finds every PCI function on every bus
+///
+/// Functions 1 to 7 of a slot are only probed if function 0 reports a
+/// multi-function device.
+pub fn enumerate() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for bus in 0..BUS_COUNT {
+        let bus = bus as u8;
+        for slot in 0..SLOT_COUNT {
+            let Some(device) = PciDevice::probe(bus, slot, 0) else {
+                continue;
+            };
+            devices.push(device);
+
+            let header = (device.read_config_u32(REG_HEADER) >> 16) as u8;
+            if header & HEADER_MULTIFUNCTION != 0 {
+                devices.extend(
+                    (1..FUNCTION_COUNT).filter_map(|func| PciDevice::probe(bus, slot, func)),
+                );
+            }
+        }
+    }
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_config_address_encoding() {
+        assert_eq!(config_address(0, 0, 0, 0), 0x8000_0000);
+        assert_eq!(config_address(1, 2, 3, 0x08), 0x8001_1308);
+        // Offsets are dword aligned
+        assert_eq!(config_address(0xff, 31, 7, 0xff), 0x80ff_fffc);
+    }
+}
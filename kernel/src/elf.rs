@@ -0,0 +1,609 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ELF64 program loader
+//!
+//! Loads statically linked x86_64 executables into a new address space.
+//! Only `PT_LOAD` segments are handled; each is copied into freshly
+//! allocated frames and mapped user-accessible with the permissions from
+//! its program header. Below `USER_STACK_TOP` the address space also gets
+//! a user stack of `USER_STACK_PAGES` pages.
+
+use core::fmt;
+
+use crate::memory::{
+    FrameAllocator,
+    HeapFrameAllocator,
+    Page,
+    PageTableFlags,
+    PageTableManager,
+    PhysAddr,
+    USER_SPACE_END,
+    USER_SPACE_START,
+    VirtAddr,
+};
+
+/// ELF magic number (`\x7fELF`)
+pub const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+/// `e_machine` value for x86_64
+pub const EM_X86_64: u16 = 62;
+/// Loadable segment
+pub const PT_LOAD: u32 = 1;
+/// Segment is executable
+pub const PF_X: u32 = 1;
+/// Segment is writable
+pub const PF_W: u32 = 2;
+/// Segment is readable
+pub const PF_R: u32 = 4;
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+
+/// Size of the ELF64 file header
+const EHDR_SIZE: usize = 64;
+/// Size of an ELF64 program header
+const PHDR_SIZE: usize = 56;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Top of the user stack of a loaded image
+pub const USER_STACK_TOP: u64 = USER_SPACE_END;
+/// Size of the user stack of a loaded image, in pages
+pub const USER_STACK_PAGES: u64 = 4;
+/// Lowest address of the user stack
+const USER_STACK_BOTTOM: u64 = USER_STACK_TOP - USER_STACK_PAGES * PAGE_SIZE;
+
+/// Flags of the user stack pages
+const USER_STACK_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::USER_ACCESSIBLE)
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// Errors returned when loading an ELF image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// The image is shorter than its headers claim
+    Truncated,
+    /// The image does not start with the ELF magic
+    BadMagic,
+    /// The image is not a little-endian 64-bit ELF
+    UnsupportedClass,
+    /// `e_machine` is not `EM_X86_64`
+    UnsupportedMachine,
+    /// The image is neither an executable nor a shared object
+    UnsupportedType,
+    /// A segment's file size exceeds its memory size, or its range overflows
+    InvalidSegment,
+    /// A segment lies outside user space, between `USER_SPACE_START` and
+    /// `USER_SPACE_END`
+    SegmentNotInUserSpace,
+    /// No frame could be allocated for a segment or page table
+    OutOfMemory,
+    /// Mapping a segment page failed
+    MapFailed(&'static str),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "ELF image is truncated"),
+            Self::BadMagic => write!(f, "not an ELF image"),
+            Self::UnsupportedClass => write!(f, "ELF image is not little-endian 64-bit"),
+            Self::UnsupportedMachine => write!(f, "ELF image is not for x86-64"),
+            Self::UnsupportedType => write!(f, "ELF image is not an executable"),
+            Self::InvalidSegment => write!(f, "ELF image has an invalid segment"),
+            Self::SegmentNotInUserSpace => write!(f, "ELF segment lies outside user space"),
+            Self::OutOfMemory => write!(f, "out of memory loading ELF image"),
+            Self::MapFailed(reason) => write!(f, "cannot map ELF segment: {}", reason),
+        }
+    }
+}
+
+/// A loaded ELF image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadedElf {
+    /// Address of the first instruction
+    pub entry_point: VirtAddr,
+    /// Physical address of the P4 table of the new address space
+    pub page_table: PhysAddr,
+}
+
+/// A program header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+}
+
+impl ProgramHeader {
+    /// Page table flags for this segment's permissions
+    ///
+    /// Segments are always user-accessible; `PF_W` makes them writable and
+    /// segments without `PF_X` are mapped `NO_EXECUTE`.
+    pub fn page_flags(&self) -> PageTableFlags {
+        let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        if self.p_flags & PF_W != 0 {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if self.p_flags & PF_X == 0 {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+        flags
+    }
+}
+
+/// ELF64 loader over an in-memory image
+pub struct Elf64Loader<'a> {
+    data: &'a [u8],
+    entry: u64,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+}
+
+impl<'a> Elf64Loader<'a> {
+    /// Validate the ELF header of `data`
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ElfError` if the image is not a little-endian ELF64
+    /// x86_64 executable or its program header table is out of bounds.
+    pub fn new(data: &'a [u8]) -> Result<Self, ElfError> {
+        let ident = data.get(..16).ok_or(ElfError::Truncated)?;
+        if ident[..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if ident[4] != ELFCLASS64 || ident[5] != ELFDATA2LSB {
+            return Err(ElfError::UnsupportedClass);
+        }
+        if data.len() < EHDR_SIZE {
+            return Err(ElfError::Truncated);
+        }
+
+        let e_type = read_u16(data, 16)?;
+        if e_type != ET_EXEC && e_type != ET_DYN {
+            return Err(ElfError::UnsupportedType);
+        }
+        if read_u16(data, 18)? != EM_X86_64 {
+            return Err(ElfError::UnsupportedMachine);
+        }
+
+        let loader = Self {
+            data,
+            entry: read_u64(data, 24)?,
+            phoff: usize::try_from(read_u64(data, 32)?).map_err(|_| ElfError::Truncated)?,
+            phentsize: read_u16(data, 54)? as usize,
+            phnum: read_u16(data, 56)? as usize,
+        };
+        if loader.phnum > 0 && loader.phentsize < PHDR_SIZE {
+            return Err(ElfError::Truncated);
+        }
+        let table_size = loader.phentsize * loader.phnum;
+        let table_end = loader
+            .phoff
+            .checked_add(table_size)
+            .ok_or(ElfError::Truncated)?;
+        if table_end > data.len() {
+            return Err(ElfError::Truncated);
+        }
+        Ok(loader)
+    }
+
+    /// Address of the first instruction (`e_entry`)
+    pub fn entry_point(&self) -> VirtAddr {
+        VirtAddr::new(self.entry)
+    }
+
+    /// Iterate over all program headers
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let data = self.data;
+        let (phoff, phentsize) = (self.phoff, self.phentsize);
+        (0..self.phnum).map(move |i| {
+            let ph = &data[phoff + i * phentsize..];
+            ProgramHeader {
+                p_type: le_u32(&ph[0..4]),
+                p_flags: le_u32(&ph[4..8]),
+                p_offset: le_u64(&ph[8..16]),
+                p_vaddr: le_u64(&ph[16..24]),
+                p_filesz: le_u64(&ph[32..40]),
+                p_memsz: le_u64(&ph[40..48]),
+            }
+        })
+    }
+
+    /// Load all `PT_LOAD` segments into a new address space
+    ///
+    /// The new address space shares the kernel mappings of the current one.
+    /// Each segment page gets its own zeroed frame, so the part of a segment
+    /// beyond its file size (`.bss`) reads as zero. Segments sharing a page
+    /// share its frame, mapped with the union of their permissions. The
+    /// user stack is mapped last, with zeroed frames too.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ElfError` if a segment is invalid or it or the stack
+    /// cannot be mapped. The frames mapped so far are freed again.
+    pub fn load(&self, frame_allocator: &mut HeapFrameAllocator) -> Result<LoadedElf, ElfError> {
+        for ph in self.program_headers().filter(|ph| ph.p_type == PT_LOAD) {
+            self.validate_segment(&ph)?;
+        }
+
+        // SAFETY: CR3 holds the active, identity-accessible P4 table
+        let current = unsafe { PageTableManager::current() };
+        let mut address_space = current
+            .new_address_space(frame_allocator)
+            .map_err(|_| ElfError::OutOfMemory)?;
+
+        for ph in self.program_headers().filter(|ph| ph.p_type == PT_LOAD) {
+            if let Err(err) = self.map_segment(&ph, &mut address_space, frame_allocator) {
+                self.unmap_segments(&mut address_space, frame_allocator);
+                return Err(err);
+            }
+        }
+        if let Err(err) = map_user_stack(&mut address_space, frame_allocator) {
+            self.unmap_segments(&mut address_space, frame_allocator);
+            return Err(err);
+        }
+
+        Ok(LoadedElf {
+            entry_point: self.entry_point(),
+            page_table: address_space.p4_address(),
+        })
+    }
+
+    /// Check that a segment's file range and memory range are valid
+    fn validate_segment(&self, ph: &ProgramHeader) -> Result<(), ElfError> {
+        if ph.p_filesz > ph.p_memsz {
+            return Err(ElfError::InvalidSegment);
+        }
+        let file_end = ph
+            .p_offset
+            .checked_add(ph.p_filesz)
+            .ok_or(ElfError::InvalidSegment)?;
+        if file_end > self.data.len() as u64 {
+            return Err(ElfError::Truncated);
+        }
+        let mem_end = ph
+            .p_vaddr
+            .checked_add(ph.p_memsz)
+            .ok_or(ElfError::InvalidSegment)?;
+        if ph.p_vaddr < USER_SPACE_START || mem_end > USER_SPACE_END {
+            return Err(ElfError::SegmentNotInUserSpace);
+        }
+        Ok(())
+    }
+
+    /// Copy a validated segment into new frames and map them
+    ///
+    /// A page already mapped by an earlier segment keeps its frame; the
+    /// segment's bytes are copied into it and its flags are merged.
+    fn map_segment(
+        &self,
+        ph: &ProgramHeader,
+        address_space: &mut PageTableManager,
+        frame_allocator: &mut HeapFrameAllocator,
+    ) -> Result<(), ElfError> {
+        let file = &self.data[ph.p_offset as usize..(ph.p_offset + ph.p_filesz) as usize];
+        let flags = ph.page_flags();
+        let start = ph.p_vaddr & !(PAGE_SIZE - 1);
+        let end = ph.p_vaddr + ph.p_memsz;
+
+        let mut page_addr = start;
+        while page_addr < end {
+            let page = Page::containing_address(VirtAddr::new(page_addr));
+            let shared = address_space
+                .page_flags(page)
+                .zip(address_space.translate_addr(page.start_address()));
+            let frame_addr = match shared {
+                Some((existing, frame_addr)) => {
+                    address_space
+                        .set_flags(page, merge_flags(existing, flags))
+                        .map_err(|_| ElfError::MapFailed("shared page is not a 4 KiB page"))?;
+                    frame_addr
+                }
+                None => {
+                    let frame = frame_allocator
+                        .allocate_frame()
+                        .ok_or(ElfError::OutOfMemory)?;
+                    if let Err(reason) = address_space.map_page(page, frame, flags, frame_allocator)
+                    {
+                        // SAFETY: the frame was just allocated and never mapped
+                        unsafe { frame_allocator.deallocate_frame(frame) };
+                        return Err(ElfError::MapFailed(reason));
+                    }
+                    frame.start_address()
+                }
+            };
+
+            // Bytes of the file image that land in this page
+            let copy_start = page_addr.max(ph.p_vaddr);
+            let copy_end = (page_addr + PAGE_SIZE).min(ph.p_vaddr + ph.p_filesz);
+            if copy_start < copy_end {
+                let src =
+                    &file[(copy_start - ph.p_vaddr) as usize..(copy_end - ph.p_vaddr) as usize];
+                let dst = frame_addr.as_u64() + (copy_start - page_addr);
+                // SAFETY: the frame belongs to the new address space only, is
+                // identity-accessible and `src` fits in it starting at `dst`.
+                unsafe {
+                    core::ptr::copy_nonoverlapping(src.as_ptr(), dst as *mut u8, src.len());
+                }
+            }
+            page_addr += PAGE_SIZE;
+        }
+        Ok(())
+    }
+
+    /// Unmap and free every segment and stack page mapped in
+    /// `address_space`
+    ///
+    /// Used to undo a partial load. Pages that were never mapped are
+    /// skipped, as are shared pages already freed with an earlier segment.
+    fn unmap_segments(
+        &self,
+        address_space: &mut PageTableManager,
+        frame_allocator: &mut HeapFrameAllocator,
+    ) {
+        let stack = (USER_STACK_BOTTOM, USER_STACK_TOP);
+        let segments = self
+            .program_headers()
+            .filter(|ph| ph.p_type == PT_LOAD)
+            .map(|ph| (ph.p_vaddr & !(PAGE_SIZE - 1), ph.p_vaddr + ph.p_memsz));
+        for (start, end) in segments.chain(core::iter::once(stack)) {
+            let mut page_addr = start;
+            while page_addr < end {
+                let page = Page::containing_address(VirtAddr::new(page_addr));
+                if let Ok(frame) = address_space.unmap_page(page) {
+                    // SAFETY: the loader allocated the frame and it is no
+                    // longer mapped
+                    unsafe { frame_allocator.deallocate_frame(frame) };
+                }
+                page_addr += PAGE_SIZE;
+            }
+        }
+    }
+}
+
+/// Map the user stack below `USER_STACK_TOP` into `address_space`
+fn map_user_stack(
+    address_space: &mut PageTableManager,
+    frame_allocator: &mut HeapFrameAllocator,
+) -> Result<(), ElfError> {
+    let bottom = Page::containing_address(VirtAddr::new(USER_STACK_BOTTOM));
+    for i in 0..USER_STACK_PAGES {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(ElfError::OutOfMemory)?;
+        if let Err(reason) =
+            address_space.map_page(bottom + i, frame, USER_STACK_FLAGS, frame_allocator)
+        {
+            // SAFETY: the frame was just allocated and never mapped
+            unsafe { frame_allocator.deallocate_frame(frame) };
+            return Err(ElfError::MapFailed(reason));
+        }
+    }
+    Ok(())
+}
+
+/// Flags for a page shared by two segments
+///
+/// The page is writable if either segment is, and executable if either is.
+fn merge_flags(a: PageTableFlags, b: PageTableFlags) -> PageTableFlags {
+    let no_execute = a & b & PageTableFlags::NO_EXECUTE;
+    ((a | b) - PageTableFlags::NO_EXECUTE) | no_execute
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    let raw = data.get(offset..offset + 2).ok_or(ElfError::Truncated)?;
+    Ok(u16::from_le_bytes([raw[0], raw[1]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ElfError> {
+    data.get(offset..offset + 8)
+        .map(le_u64)
+        .ok_or(ElfError::Truncated)
+}
+
+fn le_u32(raw: &[u8]) -> u32 {
+    u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]])
+}
+
+fn le_u64(raw: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&raw[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::memory::HeapFrameAllocator;
+
+    /// Virtual address of the test program's segment
+    const TEST_BASE: u64 = USER_SPACE_START + 0x40_0000;
+    /// Virtual address the test program is linked at
+    pub(crate) const TEST_ENTRY: u64 = TEST_BASE + 0x78;
+
+    /// Builds a minimal executable: one R+X `PT_LOAD` segment holding
+    /// `jmp $` right after the headers
+    pub(crate) fn minimal_elf() -> Vec<u8> {
+        let mut elf = Vec::new();
+        elf.extend_from_slice(&ELF_MAGIC);
+        elf.extend_from_slice(&[ELFCLASS64, ELFDATA2LSB, 1, 0]);
+        elf.resize(16, 0);
+        elf.extend_from_slice(&ET_EXEC.to_le_bytes());
+        elf.extend_from_slice(&EM_X86_64.to_le_bytes());
+        elf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+        elf.extend_from_slice(&TEST_ENTRY.to_le_bytes());
+        elf.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+        elf.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+        elf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+        elf.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+        elf.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        elf.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+        elf.extend_from_slice(&[0; 6]); // e_shentsize, e_shnum, e_shstrndx
+
+        let code = [0xeb, 0xfe];
+        let size = (EHDR_SIZE + PHDR_SIZE + code.len()) as u64;
+        elf.extend_from_slice(&PT_LOAD.to_le_bytes());
+        elf.extend_from_slice(&(PF_R | PF_X).to_le_bytes());
+        elf.extend_from_slice(&0u64.to_le_bytes()); // p_offset
+        elf.extend_from_slice(&TEST_BASE.to_le_bytes()); // p_vaddr
+        elf.extend_from_slice(&TEST_BASE.to_le_bytes()); // p_paddr
+        elf.extend_from_slice(&size.to_le_bytes()); // p_filesz
+        elf.extend_from_slice(&size.to_le_bytes()); // p_memsz
+        elf.extend_from_slice(&PAGE_SIZE.to_le_bytes()); // p_align
+        elf.extend_from_slice(&code);
+        elf
+    }
+
+    #[test_case]
+    fn test_parse_minimal_elf() {
+        let elf = minimal_elf();
+        let loader = Elf64Loader::new(&elf).unwrap();
+        assert_eq!(loader.entry_point(), VirtAddr::new(TEST_ENTRY));
+
+        let ph = loader.program_headers().next().unwrap();
+        assert_eq!(ph.p_type, PT_LOAD);
+        let flags = ph.page_flags();
+        assert!(flags.contains(PageTableFlags::USER_ACCESSIBLE));
+        assert!(!flags.contains(PageTableFlags::WRITABLE));
+        assert!(!flags.contains(PageTableFlags::NO_EXECUTE));
+    }
+
+    #[test_case]
+    fn test_reject_invalid_headers() {
+        let mut elf = minimal_elf();
+        elf[0] = 0;
+        assert_eq!(Elf64Loader::new(&elf).err(), Some(ElfError::BadMagic));
+
+        let mut elf = minimal_elf();
+        elf[18] = 3; // EM_386
+        assert_eq!(
+            Elf64Loader::new(&elf).err(),
+            Some(ElfError::UnsupportedMachine)
+        );
+
+        let elf = minimal_elf();
+        assert_eq!(
+            Elf64Loader::new(&elf[..EHDR_SIZE + 8]).err(),
+            Some(ElfError::Truncated)
+        );
+    }
+
+    #[test_case]
+    fn test_reject_segment_outside_user_space() {
+        // Move the segment to the page at USER_SPACE_END, which user mode
+        // may not use, and into the identity map below USER_SPACE_START
+        for addr in [USER_SPACE_END, 0x40_0000] {
+            let mut elf = minimal_elf();
+            let p_vaddr = EHDR_SIZE + 16;
+            elf[p_vaddr..p_vaddr + 8].copy_from_slice(&addr.to_le_bytes());
+
+            let mut frames = HeapFrameAllocator::new();
+            assert_eq!(
+                Elf64Loader::new(&elf).unwrap().load(&mut frames).err(),
+                Some(ElfError::SegmentNotInUserSpace)
+            );
+        }
+    }
+
+    #[test_case]
+    fn test_load_maps_segment() {
+        let elf = minimal_elf();
+        let mut frames = HeapFrameAllocator::new();
+        let loaded = Elf64Loader::new(&elf).unwrap().load(&mut frames).unwrap();
+        assert_eq!(loaded.entry_point, VirtAddr::new(TEST_ENTRY));
+
+        // SAFETY: the loader returned a valid, identity-accessible P4 table
+        let p4 = unsafe { &mut *(loaded.page_table.as_u64() as *mut crate::memory::PageTable) };
+        // SAFETY: the table is not in use by the CPU
+        let address_space = unsafe { PageTableManager::from_p4_table(p4) };
+        let code = address_space
+            .translate_addr(VirtAddr::new(TEST_ENTRY))
+            .unwrap();
+        // SAFETY: the translated frame is identity-accessible
+        let first = unsafe { *(code.as_u64() as *const u8) };
+        assert_eq!(first, 0xeb);
+
+        let stack = VirtAddr::new(USER_STACK_BOTTOM);
+        assert!(address_space.is_user_accessible(stack, true));
+        assert!(!address_space.is_user_accessible(stack - PAGE_SIZE, false));
+    }
+
+    #[test_case]
+    fn test_load_merges_shared_page() {
+        let mut elf = minimal_elf();
+        let data_offset = elf.len() as u64;
+        elf.extend_from_slice(&[0x11, 0x22, 0x33, 0x44]);
+
+        // Move the program header table to the end and add an R+W segment
+        // sharing the code page
+        let phoff = elf.len() as u64;
+        let code_ph = elf[EHDR_SIZE..EHDR_SIZE + PHDR_SIZE].to_vec();
+        elf.extend_from_slice(&code_ph);
+        elf.extend_from_slice(&PT_LOAD.to_le_bytes());
+        elf.extend_from_slice(&(PF_R | PF_W).to_le_bytes());
+        elf.extend_from_slice(&data_offset.to_le_bytes()); // p_offset
+        elf.extend_from_slice(&(TEST_BASE + data_offset).to_le_bytes()); // p_vaddr
+        elf.extend_from_slice(&(TEST_BASE + data_offset).to_le_bytes()); // p_paddr
+        elf.extend_from_slice(&4u64.to_le_bytes()); // p_filesz
+        elf.extend_from_slice(&0x20u64.to_le_bytes()); // p_memsz
+        elf.extend_from_slice(&PAGE_SIZE.to_le_bytes()); // p_align
+        elf[32..40].copy_from_slice(&phoff.to_le_bytes());
+        elf[56..58].copy_from_slice(&2u16.to_le_bytes());
+
+        let mut frames = HeapFrameAllocator::new();
+        let loaded = Elf64Loader::new(&elf).unwrap().load(&mut frames).unwrap();
+
+        // SAFETY: the loader returned a valid, identity-accessible P4 table
+        let p4 = unsafe { &mut *(loaded.page_table.as_u64() as *mut crate::memory::PageTable) };
+        // SAFETY: the table is not in use by the CPU
+        let mut address_space = unsafe { PageTableManager::from_p4_table(p4) };
+        let flags = address_space
+            .page_flags(Page::containing_address(VirtAddr::new(TEST_ENTRY)))
+            .unwrap();
+        assert!(flags.contains(PageTableFlags::WRITABLE));
+        assert!(!flags.contains(PageTableFlags::NO_EXECUTE));
+
+        let code = address_space
+            .translate_addr(VirtAddr::new(TEST_ENTRY))
+            .unwrap();
+        let data = address_space
+            .translate_addr(VirtAddr::new(TEST_BASE + data_offset))
+            .unwrap();
+        // SAFETY: the translated frame is identity-accessible
+        unsafe {
+            assert_eq!(*(code.as_u64() as *const u8), 0xeb);
+            assert_eq!(*(data.as_u64() as *const u32), 0x4433_2211);
+        }
+    }
+
+    #[test_case]
+    fn test_merge_flags() {
+        let base = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        let code = base;
+        let data = base | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let rodata = base | PageTableFlags::NO_EXECUTE;
+        assert_eq!(merge_flags(code, data), base | PageTableFlags::WRITABLE);
+        assert_eq!(merge_flags(rodata, data), data);
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kernel-wide error type
+//!
+//! Every subsystem has its own error enum. `KernelError` wraps any of them,
+//! so code spanning several subsystems can propagate them all with `?`.
+//! Its `Display` output is that of the wrapped error.
+
+use core::fmt;
+
+use crate::{
+    debug::hwbp::HwBpError,
+    elf::ElfError,
+    fs::{
+        FsError,
+        VfsError,
+    },
+    interrupts::nmi::WatchdogError,
+    memory::{
+        MapError,
+        phys_map::PhysMapError,
+    },
+    process::{
+        CapabilityError,
+        FdError,
+        IpcError,
+        ProcessError,
+        StackError,
+        VmError,
+    },
+    serial::SerialError,
+};
+
+/// An error from any kernel subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelError {
+    Process(ProcessError),
+    Stack(StackError),
+    Capability(CapabilityError),
+    Ipc(IpcError),
+    Fd(FdError),
+    Vm(VmError),
+    Map(MapError),
+    PhysMap(PhysMapError),
+    Fs(FsError),
+    Vfs(VfsError),
+    Elf(ElfError),
+    Serial(SerialError),
+    Watchdog(WatchdogError),
+    HwBp(HwBpError),
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Process(err) => err.fmt(f),
+            Self::Stack(err) => err.fmt(f),
+            Self::Capability(err) => err.fmt(f),
+            Self::Ipc(err) => err.fmt(f),
+            Self::Fd(err) => err.fmt(f),
+            Self::Vm(err) => err.fmt(f),
+            Self::Map(err) => err.fmt(f),
+            Self::PhysMap(err) => err.fmt(f),
+            Self::Fs(err) => err.fmt(f),
+            Self::Vfs(err) => err.fmt(f),
+            Self::Elf(err) => err.fmt(f),
+            Self::Serial(err) => err.fmt(f),
+            Self::Watchdog(err) => err.fmt(f),
+            Self::HwBp(err) => err.fmt(f),
+        }
+    }
+}
+
+/// Implements `From<$error>` for `KernelError` as the given variant
+macro_rules! impl_from_error {
+    ($($variant:ident($error:ty)),* $(,)?) => {
+        $(
+            impl From<$error> for KernelError {
+                fn from(err: $error) -> Self {
+                    Self::$variant(err)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_error!(
+    Process(ProcessError),
+    Stack(StackError),
+    Capability(CapabilityError),
+    Ipc(IpcError),
+    Fd(FdError),
+    Vm(VmError),
+    Map(MapError),
+    PhysMap(PhysMapError),
+    Fs(FsError),
+    Vfs(VfsError),
+    Elf(ElfError),
+    Serial(SerialError),
+    Watchdog(WatchdogError),
+    HwBp(HwBpError),
+);
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    #[test_case]
+    fn test_process_error_display() {
+        let message = format!("{}", ProcessError::TableFull);
+        assert!(!message.contains("ProcessError"));
+        assert!(!message.contains("TableFull"));
+        assert_eq!(message, "process table is full");
+
+        let nested = format!(
+            "{}",
+            ProcessError::KernelStack(StackError::OutOfAddressSpace)
+        );
+        assert!(nested.ends_with(&format!("{}", StackError::OutOfAddressSpace)));
+    }
+
+    #[test_case]
+    fn test_messages_have_no_type_names() {
+        let errors = [
+            KernelError::from(ProcessError::NotFound),
+            StackError::MapFailed.into(),
+            CapabilityError::PermissionDenied.into(),
+            IpcError::Capability(CapabilityError::NotFound).into(),
+            FdError::BadFd.into(),
+            VmError::Overlap.into(),
+            MapError::FrameAllocationFailed.into(),
+            PhysMapError::NotInMap.into(),
+            FsError::InvalidName.into(),
+            VfsError::NotMounted.into(),
+            ElfError::MapFailed("P3 table not present").into(),
+            SerialError::LoopbackFailed.into(),
+            WatchdogError::NoHpet.into(),
+            HwBpError::Misaligned.into(),
+        ];
+        for err in errors {
+            let message = format!("{}", err);
+            assert!(!message.is_empty());
+            assert!(!message.contains("Error"), "{}", message);
+        }
+    }
+
+    #[test_case]
+    fn test_question_mark_converts() {
+        fn find(pid: u64) -> Result<(), KernelError> {
+            if pid == 0 {
+                Err(ProcessError::NotFound)?;
+            }
+            Err(VfsError::NotFound)?
+        }
+
+        assert_eq!(find(0), Err(KernelError::Process(ProcessError::NotFound)));
+        assert_eq!(find(1), Err(KernelError::Vfs(VfsError::NotFound)));
+        assert_eq!(
+            format!("{}", find(0).unwrap_err()),
+            format!("{}", ProcessError::NotFound)
+        );
+    }
+}
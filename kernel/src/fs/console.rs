@@ -0,0 +1,101 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serial console as a file
+//!
+//! `ConsoleFs` is a filesystem with a single file, the serial console:
+//! writes go to COM1 and reads return the bytes received so far. It backs
+//! the standard streams of every process.
+
+use alloc::{
+    boxed::Box,
+    sync::Arc,
+    vec::Vec,
+};
+
+use spin::{
+    Mutex,
+    Once,
+};
+
+use super::{
+    ramfs::{
+        DirEntry,
+        INodeKind,
+    },
+    vfs::{
+        FileHandle,
+        Filesystem,
+        SharedFilesystem,
+        VfsError,
+    },
+};
+use crate::serial;
+
+/// Inode number of the console
+pub const CONSOLE_INO: u64 = 0;
+
+/// The console filesystem shared by all console handles
+static CONSOLE: Once<SharedFilesystem> = Once::new();
+
+/// Filesystem whose only file is the serial console
+#[derive(Debug, Default)]
+pub struct ConsoleFs;
+
+impl Filesystem for ConsoleFs {
+    fn root(&self) -> u64 {
+        CONSOLE_INO
+    }
+
+    fn lookup(&self, _dir: u64, _name: &str) -> Result<u64, VfsError> {
+        Err(VfsError::NotADirectory)
+    }
+
+    /// Returns the received bytes without waiting for more
+    fn read(&self, _ino: u64, _offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        let mut n = 0;
+        while n < buf.len() {
+            match serial::read_byte() {
+                Some(byte) => buf[n] = byte,
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, _ino: u64, _offset: usize, data: &[u8]) -> Result<usize, VfsError> {
+        serial::SERIAL1.lock().write_bytes(data);
+        Ok(data.len())
+    }
+
+    fn create(&mut self, _dir: u64, _name: &str, _kind: INodeKind) -> Result<u64, VfsError> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn readdir(&self, _ino: u64) -> Result<Vec<DirEntry>, VfsError> {
+        Err(VfsError::NotADirectory)
+    }
+}
+
+/// Opens the serial console
+///
+/// The console ignores the handle's offset.
+pub fn open_console() -> FileHandle {
+    let fs = CONSOLE.call_once(|| {
+        let fs: Box<dyn Filesystem> = Box::new(ConsoleFs);
+        Arc::new(Mutex::new(fs))
+    });
+    FileHandle::new(fs.clone(), CONSOLE_INO)
+}
@@ -0,0 +1,57 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Filesystems
+//!
+//! The root filesystem is an in-memory `ramfs`, mounted at `/` of the
+//! VFS at boot. `procfs` exposes kernel state under `/proc`.
+
+pub mod console;
+pub mod procfs;
+pub mod ramfs;
+pub mod vfs;
+
+use alloc::boxed::Box;
+
+pub use procfs::ProcFs;
+pub use ramfs::{
+    DirEntry,
+    FsError,
+    INodeKind,
+    ROOT_FS,
+    ROOT_INO,
+    RamFs,
+};
+pub use vfs::{
+    FileHandle,
+    Filesystem,
+    VFS,
+    Vfs,
+    VfsError,
+};
+
+/// Mounts the root filesystem at `/`
+///
+/// Must be called after the heap is initialized.
+///
+/// # Panics
+///
+/// Panics if called more than once.
+pub fn init() {
+    crate::interrupts::without_interrupts(|| ROOT_FS.lock().mount());
+    VFS.lock()
+        .mount("/", Box::new(&ROOT_FS))
+        .expect("root filesystem already mounted");
+    crate::log_info!("ramfs mounted as root filesystem");
+}
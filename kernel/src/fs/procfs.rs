@@ -0,0 +1,334 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kernel state as files
+//!
+//! `ProcFs` synthesizes read-only files describing the running kernel,
+//! mounted at `/proc`:
+//!
+//! - `uptime`: milliseconds since boot
+//! - `meminfo`: heap usage
+//! - `interrupts`: interrupts handled per IRQ
+//! - `<pid>/status`: state and capabilities of each process
+//!
+//! File contents are generated anew on every read.
+
+use alloc::{
+    format,
+    string::{
+        String,
+        ToString,
+    },
+    vec,
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use super::{
+    ramfs::{
+        DirEntry,
+        INodeKind,
+    },
+    vfs::{
+        Filesystem,
+        VfsError,
+    },
+};
+use crate::{
+    interrupts,
+    memory,
+    process::{
+        ProcessId,
+        SCHEDULER,
+    },
+    time,
+};
+
+/// Inode number of the `/proc` directory
+pub const PROC_ROOT_INO: u64 = 1;
+/// Inode number of `/proc/uptime`
+const UPTIME_INO: u64 = 2;
+/// Inode number of `/proc/meminfo`
+const MEMINFO_INO: u64 = 3;
+/// Inode number of `/proc/interrupts`
+const INTERRUPTS_INO: u64 = 4;
+
+/// Per-process inodes are `pid << PID_SHIFT | PID_*`
+const PID_SHIFT: u32 = 8;
+/// Low bits of the inode number of a process directory
+const PID_DIR: u64 = 0;
+/// Low bits of the inode number of a process's `status` file
+const PID_STATUS: u64 = 1;
+
+/// Files in the `/proc` directory itself
+const ROOT_FILES: [(&str, u64); 3] = [
+    ("uptime", UPTIME_INO),
+    ("meminfo", MEMINFO_INO),
+    ("interrupts", INTERRUPTS_INO),
+];
+
+/// Number of IRQ lines listed in `/proc/interrupts`
+const IRQ_LINES: u8 = 16;
+
+/// Filesystem of generated files describing kernel state
+#[derive(Debug, Default)]
+pub struct ProcFs;
+
+impl ProcFs {
+    /// Generates the contents of file `ino`
+    fn generate(&self, ino: u64) -> Result<String, VfsError> {
+        match ino {
+            PROC_ROOT_INO => Err(VfsError::IsADirectory),
+            UPTIME_INO => Ok(format!("{}\n", time::uptime_ms())),
+            MEMINFO_INO => Ok(meminfo()),
+            INTERRUPTS_INO => Ok(interrupt_counts()),
+            _ => match split_pid_ino(ino) {
+                Some((_, PID_DIR)) => Err(VfsError::IsADirectory),
+                Some((pid, PID_STATUS)) => process_status(pid),
+                _ => Err(VfsError::NotFound),
+            },
+        }
+    }
+}
+
+impl Filesystem for ProcFs {
+    fn root(&self) -> u64 {
+        PROC_ROOT_INO
+    }
+
+    fn lookup(&self, dir: u64, name: &str) -> Result<u64, VfsError> {
+        if dir == PROC_ROOT_INO {
+            if let Some(&(_, ino)) = ROOT_FILES.iter().find(|(file, _)| *file == name) {
+                return Ok(ino);
+            }
+            let pid = name.parse().map_err(|_| VfsError::NotFound)?;
+            let pid = ProcessId::new(pid);
+            if !process_exists(pid) {
+                return Err(VfsError::NotFound);
+            }
+            return Ok(pid_ino(pid, PID_DIR));
+        }
+
+        match split_pid_ino(dir) {
+            Some((pid, PID_DIR)) if process_exists(pid) => match name {
+                "status" => Ok(pid_ino(pid, PID_STATUS)),
+                _ => Err(VfsError::NotFound),
+            },
+            Some((_, PID_DIR)) => Err(VfsError::NotFound),
+            _ => Err(VfsError::NotADirectory),
+        }
+    }
+
+    fn read(&self, ino: u64, offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        let contents = self.generate(ino)?;
+        let Some(rest) = contents.as_bytes().get(offset..) else {
+            return Ok(0);
+        };
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        Ok(n)
+    }
+
+    fn write(&mut self, _ino: u64, _offset: usize, _data: &[u8]) -> Result<usize, VfsError> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn create(&mut self, _dir: u64, _name: &str, _kind: INodeKind) -> Result<u64, VfsError> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn readdir(&self, ino: u64) -> Result<Vec<DirEntry>, VfsError> {
+        if ino == PROC_ROOT_INO {
+            let mut entries: Vec<DirEntry> = ROOT_FILES
+                .iter()
+                .map(|&(name, ino)| DirEntry {
+                    ino,
+                    name: String::from(name),
+                    kind: INodeKind::File,
+                })
+                .collect();
+            let pids: Vec<ProcessId> = crate::interrupts::without_interrupts(|| {
+                SCHEDULER.lock().table().iter().map(|p| p.pid()).collect()
+            });
+            entries.extend(pids.into_iter().map(|pid| DirEntry {
+                ino: pid_ino(pid, PID_DIR),
+                name: pid.to_string(),
+                kind: INodeKind::Dir,
+            }));
+            return Ok(entries);
+        }
+
+        match split_pid_ino(ino) {
+            Some((pid, PID_DIR)) if process_exists(pid) => Ok(vec![DirEntry {
+                ino: pid_ino(pid, PID_STATUS),
+                name: String::from("status"),
+                kind: INodeKind::File,
+            }]),
+            Some((_, PID_DIR)) => Err(VfsError::NotFound),
+            _ => Err(VfsError::NotADirectory),
+        }
+    }
+}
+
+/// Builds the inode number of a per-process file
+fn pid_ino(pid: ProcessId, file: u64) -> u64 {
+    pid.as_u64() << PID_SHIFT | file
+}
+
+/// Splits a per-process inode number into the PID and file
+fn split_pid_ino(ino: u64) -> Option<(ProcessId, u64)> {
+    let pid = ino >> PID_SHIFT;
+    (pid != 0).then(|| (ProcessId::new(pid), ino & ((1 << PID_SHIFT) - 1)))
+}
+
+/// Returns `true` if `pid` is in the process table
+fn process_exists(pid: ProcessId) -> bool {
+    crate::interrupts::without_interrupts(|| SCHEDULER.lock().table().contains(pid))
+}
+
+/// Contents of `/proc/meminfo`
+fn meminfo() -> String {
+    let usage = memory::heap::heap_usage();
+    let mut out = String::new();
+    let _ = writeln!(out, "HeapTotal:\t{}", usage.total);
+    let _ = writeln!(out, "HeapUsed:\t{}", usage.used);
+    let _ = writeln!(out, "HeapFree:\t{}", usage.free);
+    let _ = writeln!(out, "HeapLargestFree:\t{}", usage.largest_free);
+    let _ = writeln!(out, "HeapAllocations:\t{}", usage.allocations);
+    out
+}
+
+/// Contents of `/proc/interrupts`
+fn interrupt_counts() -> String {
+    let mut out = String::new();
+    for irq in 0..IRQ_LINES {
+        let _ = writeln!(
+            out,
+            "{:>2}:\t{}\t{}",
+            irq,
+            interrupts::irq_count(irq),
+            interrupts::pic::spurious_count(irq)
+        );
+    }
+    out
+}
+
+/// Contents of `/proc/<pid>/status`
+fn process_status(pid: ProcessId) -> Result<String, VfsError> {
+    crate::interrupts::without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        let process = scheduler.table().get(pid).ok_or(VfsError::NotFound)?;
+
+        let mut out = String::new();
+        let _ = writeln!(out, "Name:\t{}", process.name());
+        let _ = writeln!(out, "Pid:\t{}", pid);
+        let _ = writeln!(out, "State:\t{:?}", process.state());
+        let _ = writeln!(out, "Capabilities:\t{}", process.capabilities().len());
+        for cap in process.capabilities().iter() {
+            let _ = writeln!(
+                out,
+                "\t{}: {:?} {:#x} {:?}",
+                cap.cap_id(),
+                cap.cap_type,
+                cap.object_id,
+                cap.rights
+            );
+        }
+        Ok(out)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+    use crate::fs::vfs::Vfs;
+
+    fn proc_vfs() -> Vfs {
+        let mut vfs = Vfs::new();
+        vfs.mount("/proc", Box::new(ProcFs)).unwrap();
+        vfs
+    }
+
+    fn read_all(vfs: &Vfs, path: &str) -> String {
+        let mut handle = vfs.open(path).unwrap();
+        let mut out = Vec::new();
+        let mut buf = [0; 64];
+        loop {
+            let n = handle.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test_case]
+    fn test_uptime_is_an_integer() {
+        let vfs = proc_vfs();
+        let uptime = read_all(&vfs, "/proc/uptime");
+        assert!(uptime.ends_with('\n'));
+        assert!(uptime.trim_end().parse::<u64>().is_ok());
+    }
+
+    #[test_case]
+    fn test_meminfo_and_interrupts() {
+        let vfs = proc_vfs();
+        let meminfo = read_all(&vfs, "/proc/meminfo");
+        assert!(meminfo.starts_with("HeapTotal:\t"));
+        assert_eq!(meminfo.lines().count(), 5);
+
+        let interrupts = read_all(&vfs, "/proc/interrupts");
+        assert_eq!(interrupts.lines().count(), IRQ_LINES as usize);
+    }
+
+    #[test_case]
+    fn test_read_only_and_missing_entries() {
+        let vfs = proc_vfs();
+        assert_eq!(
+            vfs.create("/proc/foo", INodeKind::File).unwrap_err(),
+            VfsError::Unsupported
+        );
+        assert_eq!(
+            vfs.open("/proc/uptime").unwrap().write(b"0"),
+            Err(VfsError::Unsupported)
+        );
+        assert_eq!(vfs.open("/proc/bogus").unwrap_err(), VfsError::NotFound);
+        assert_eq!(
+            vfs.open("/proc/uptime/x").unwrap_err(),
+            VfsError::NotADirectory
+        );
+
+        let names: Vec<String> = vfs
+            .readdir("/proc")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert!(names.starts_with(&[
+            String::from("uptime"),
+            String::from("meminfo"),
+            String::from("interrupts"),
+        ]));
+    }
+
+    #[test_case]
+    fn test_pid_inode_round_trip() {
+        let ino = pid_ino(ProcessId::new(42), PID_STATUS);
+        assert_eq!(split_pid_ino(ino), Some((ProcessId::new(42), PID_STATUS)));
+        assert_eq!(split_pid_ino(UPTIME_INO), None);
+    }
+}
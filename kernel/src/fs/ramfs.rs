@@ -0,0 +1,388 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory filesystem
+//!
+//! Every file and directory is an `INode` kept on the heap, found by its
+//! inode number. Directories list the inode numbers of their children;
+//! files hold their contents in a growable buffer.
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    vec::Vec,
+};
+use core::fmt;
+
+use spin::Mutex;
+
+/// Inode number of the root directory
+pub const ROOT_INO: u64 = 1;
+
+/// Maximum length of a file name in bytes
+pub const MAX_NAME_LEN: usize = 255;
+
+/// Maximum size of a file in bytes
+///
+/// File contents live on the kernel heap, which a single file must not be
+/// able to exhaust.
+pub const MAX_FILE_SIZE: usize = 256 * 1024;
+
+/// The root filesystem, mounted by `fs::init`
+pub static ROOT_FS: Mutex<RamFs> = Mutex::new(RamFs::new());
+
+/// Kind of an inode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum INodeKind {
+    File,
+    Dir,
+}
+
+/// A file or directory
+#[derive(Debug)]
+pub struct INode {
+    pub ino: u64,
+    pub kind: INodeKind,
+    /// File contents; empty for directories
+    pub data: Vec<u8>,
+    pub name: String,
+    /// Inode numbers of the entries of a directory; empty for files
+    pub children: Vec<u64>,
+}
+
+/// An entry returned by `RamFs::readdir`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub ino: u64,
+    pub name: String,
+    pub kind: INodeKind,
+}
+
+/// Errors returned by filesystem operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// No inode with the given number exists
+    NotFound,
+    /// A directory was expected
+    NotADirectory,
+    /// A file was expected
+    IsADirectory,
+    /// The directory already has an entry with that name
+    AlreadyExists,
+    /// The name is empty, too long, `.`, `..` or contains `/`
+    InvalidName,
+    /// The write would grow the file beyond `MAX_FILE_SIZE`
+    FileTooLarge,
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no such inode"),
+            Self::NotADirectory => write!(f, "not a directory"),
+            Self::IsADirectory => write!(f, "is a directory"),
+            Self::AlreadyExists => write!(f, "entry already exists"),
+            Self::InvalidName => write!(f, "invalid file name"),
+            Self::FileTooLarge => write!(f, "file too large"),
+        }
+    }
+}
+
+/// In-memory filesystem
+#[derive(Debug)]
+pub struct RamFs {
+    inodes: BTreeMap<u64, INode>,
+    next_ino: u64,
+}
+
+impl RamFs {
+    /// Creates a filesystem without a root directory
+    ///
+    /// Call `mount` before use.
+    pub const fn new() -> Self {
+        Self {
+            inodes: BTreeMap::new(),
+            next_ino: ROOT_INO + 1,
+        }
+    }
+
+    /// Creates the root directory (`ROOT_INO`) if it does not exist yet
+    pub fn mount(&mut self) {
+        self.inodes.entry(ROOT_INO).or_insert_with(|| INode {
+            ino: ROOT_INO,
+            kind: INodeKind::Dir,
+            data: Vec::new(),
+            name: String::from("/"),
+            children: Vec::new(),
+        });
+    }
+
+    /// Returns the inode with number `ino`
+    pub fn get(&self, ino: u64) -> Option<&INode> {
+        self.inodes.get(&ino)
+    }
+
+    /// Creates a file or directory
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_ino` - Directory to create the entry in
+    /// * `name` - Name of the new entry
+    /// * `kind` - Whether to create a file or a directory
+    ///
+    /// # Returns
+    ///
+    /// The inode number of the new entry
+    ///
+    /// # Errors
+    ///
+    /// Returns `FsError::NotFound` or `FsError::NotADirectory` for a bad
+    /// parent, `FsError::InvalidName` for a bad name and
+    /// `FsError::AlreadyExists` if the name is taken.
+    pub fn create(&mut self, parent_ino: u64, name: &str, kind: INodeKind) -> Result<u64, FsError> {
+        if name.is_empty()
+            || name.len() > MAX_NAME_LEN
+            || name == "."
+            || name == ".."
+            || name.contains('/')
+        {
+            return Err(FsError::InvalidName);
+        }
+        self.dir(parent_ino)?;
+        if self.lookup(parent_ino, name).is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.inodes.insert(ino, INode {
+            ino,
+            kind,
+            data: Vec::new(),
+            name: String::from(name),
+            children: Vec::new(),
+        });
+        self.dir_mut(parent_ino)?.children.push(ino);
+        Ok(ino)
+    }
+
+    /// Writes `data` to a file at `offset`
+    ///
+    /// Writing past the end of the file extends it; a gap between the old
+    /// end and `offset` is filled with zeros.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written, which is always `data.len()`
+    ///
+    /// # Errors
+    ///
+    /// Returns `FsError::NotFound` or `FsError::IsADirectory` if `ino` is
+    /// not a file, or `FsError::FileTooLarge` if the write would end past
+    /// `MAX_FILE_SIZE`.
+    pub fn write(&mut self, ino: u64, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        let file = self.file_mut(ino)?;
+        let end = offset
+            .checked_add(data.len())
+            .filter(|&end| end <= MAX_FILE_SIZE)
+            .ok_or(FsError::FileTooLarge)?;
+        if file.data.len() < end {
+            file.data.resize(end, 0);
+        }
+        file.data[offset..end].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    /// Reads from a file at `offset` into `buf`
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read, which is less than `buf.len()` at the end
+    /// of the file and 0 at or past it
+    ///
+    /// # Errors
+    ///
+    /// Returns `FsError::NotFound` or `FsError::IsADirectory` if `ino` is
+    /// not a file.
+    pub fn read(&self, ino: u64, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        let file = self.file(ino)?;
+        let available = file.data.get(offset..).unwrap_or_default();
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        Ok(n)
+    }
+
+    /// Finds an entry of a directory by name
+    ///
+    /// # Returns
+    ///
+    /// The inode number of the entry, or `None` if `parent_ino` is not a
+    /// directory or has no such entry
+    pub fn lookup(&self, parent_ino: u64, name: &str) -> Option<u64> {
+        self.dir(parent_ino)
+            .ok()?
+            .children
+            .iter()
+            .copied()
+            .find(|ino| self.inodes.get(ino).is_some_and(|child| child.name == name))
+    }
+
+    /// Lists the entries of a directory in creation order
+    ///
+    /// Yields nothing if `ino` is not a directory.
+    pub fn readdir(&self, ino: u64) -> impl Iterator<Item = DirEntry> + '_ {
+        self.dir(ino)
+            .map(|dir| dir.children.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|child| self.inodes.get(child))
+            .map(|child| DirEntry {
+                ino: child.ino,
+                name: child.name.clone(),
+                kind: child.kind,
+            })
+    }
+
+    fn inode_of_kind(&self, ino: u64, kind: INodeKind) -> Result<&INode, FsError> {
+        let inode = self.inodes.get(&ino).ok_or(FsError::NotFound)?;
+        match (inode.kind, kind) {
+            (INodeKind::File, INodeKind::Dir) => Err(FsError::NotADirectory),
+            (INodeKind::Dir, INodeKind::File) => Err(FsError::IsADirectory),
+            _ => Ok(inode),
+        }
+    }
+
+    fn inode_of_kind_mut(&mut self, ino: u64, kind: INodeKind) -> Result<&mut INode, FsError> {
+        self.inode_of_kind(ino, kind)?;
+        self.inodes.get_mut(&ino).ok_or(FsError::NotFound)
+    }
+
+    fn dir(&self, ino: u64) -> Result<&INode, FsError> {
+        self.inode_of_kind(ino, INodeKind::Dir)
+    }
+
+    fn dir_mut(&mut self, ino: u64) -> Result<&mut INode, FsError> {
+        self.inode_of_kind_mut(ino, INodeKind::Dir)
+    }
+
+    fn file(&self, ino: u64) -> Result<&INode, FsError> {
+        self.inode_of_kind(ino, INodeKind::File)
+    }
+
+    fn file_mut(&mut self, ino: u64) -> Result<&mut INode, FsError> {
+        self.inode_of_kind_mut(ino, INodeKind::File)
+    }
+}
+
+impl Default for RamFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    fn mounted() -> RamFs {
+        let mut fs = RamFs::new();
+        fs.mount();
+        fs
+    }
+
+    #[test_case]
+    fn test_nested_directories() {
+        let mut fs = mounted();
+        let etc = fs.create(ROOT_INO, "etc", INodeKind::Dir).unwrap();
+        let conf = fs.create(etc, "yomi", INodeKind::Dir).unwrap();
+        let file = fs.create(conf, "boot.cfg", INodeKind::File).unwrap();
+
+        assert_eq!(fs.lookup(ROOT_INO, "etc"), Some(etc));
+        assert_eq!(fs.lookup(etc, "yomi"), Some(conf));
+        assert_eq!(fs.lookup(conf, "boot.cfg"), Some(file));
+        assert_eq!(fs.lookup(ROOT_INO, "boot.cfg"), None);
+
+        let entries: Vec<DirEntry> = fs.readdir(etc).collect();
+        assert_eq!(entries, [DirEntry {
+            ino: conf,
+            name: String::from("yomi"),
+            kind: INodeKind::Dir,
+        }]);
+        assert_eq!(fs.readdir(file).count(), 0);
+    }
+
+    #[test_case]
+    fn test_write_seek_and_read_back() {
+        let mut fs = mounted();
+        let file = fs.create(ROOT_INO, "log", INodeKind::File).unwrap();
+        assert_eq!(fs.write(file, 0, b"hello world"), Ok(11));
+        assert_eq!(fs.write(file, 6, b"yomi!"), Ok(5));
+
+        let mut buf = [0; 5];
+        assert_eq!(fs.read(file, 6, &mut buf), Ok(5));
+        assert_eq!(&buf, b"yomi!");
+
+        // Writing past the end leaves a zero-filled gap
+        assert_eq!(fs.write(file, 13, b"x"), Ok(1));
+        let mut buf = [0xff; 3];
+        assert_eq!(fs.read(file, 11, &mut buf), Ok(3));
+        assert_eq!(buf, [0, 0, b'x']);
+    }
+
+    #[test_case]
+    fn test_partial_read_at_end_of_file() {
+        let mut fs = mounted();
+        let file = fs.create(ROOT_INO, "data", INodeKind::File).unwrap();
+        fs.write(file, 0, b"abcdef").unwrap();
+
+        let mut buf = vec![0; 4];
+        assert_eq!(fs.read(file, 4, &mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"ef");
+        assert_eq!(fs.read(file, 6, &mut buf), Ok(0));
+        assert_eq!(fs.read(file, 100, &mut buf), Ok(0));
+    }
+
+    #[test_case]
+    fn test_errors() {
+        let mut fs = mounted();
+        let file = fs.create(ROOT_INO, "f", INodeKind::File).unwrap();
+
+        assert_eq!(
+            fs.create(ROOT_INO, "f", INodeKind::Dir),
+            Err(FsError::AlreadyExists)
+        );
+        assert_eq!(
+            fs.create(ROOT_INO, "a/b", INodeKind::File),
+            Err(FsError::InvalidName)
+        );
+        assert_eq!(
+            fs.create(ROOT_INO, "..", INodeKind::Dir),
+            Err(FsError::InvalidName)
+        );
+        assert_eq!(
+            fs.create(file, "x", INodeKind::File),
+            Err(FsError::NotADirectory)
+        );
+        assert_eq!(fs.create(99, "x", INodeKind::File), Err(FsError::NotFound));
+        assert_eq!(fs.write(ROOT_INO, 0, b"x"), Err(FsError::IsADirectory));
+        assert_eq!(fs.write(file, usize::MAX, b"x"), Err(FsError::FileTooLarge));
+        assert_eq!(
+            fs.write(file, MAX_FILE_SIZE, b"x"),
+            Err(FsError::FileTooLarge)
+        );
+        assert_eq!(fs.read(99, 0, &mut [0; 1]), Err(FsError::NotFound));
+    }
+}
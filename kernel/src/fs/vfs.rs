@@ -0,0 +1,492 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Virtual filesystem layer
+//!
+//! Filesystems implement `Filesystem` and are mounted at absolute paths.
+//! A path is resolved in the filesystem with the longest mount point that
+//! is a prefix of it, so `/tmp/a` is looked up as `a` in the filesystem
+//! mounted at `/tmp` rather than in the one mounted at `/`.
+
+use alloc::{
+    boxed::Box,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt;
+
+use spin::Mutex;
+
+use super::ramfs::{
+    DirEntry,
+    FsError,
+    INodeKind,
+    ROOT_INO,
+    RamFs,
+};
+
+/// The kernel's mount table, set up by `fs::init`
+pub static VFS: Mutex<Vfs> = Mutex::new(Vfs::new());
+
+/// Errors returned by VFS operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VfsError {
+    /// A path component does not exist
+    NotFound,
+    /// A directory was expected
+    NotADirectory,
+    /// A file was expected
+    IsADirectory,
+    /// The entry already exists
+    AlreadyExists,
+    /// A path component is not a valid name
+    InvalidName,
+    /// The path is not absolute
+    InvalidPath,
+    /// No filesystem is mounted at `/`
+    NotMounted,
+    /// A filesystem is already mounted at the path
+    AlreadyMounted,
+    /// The filesystem does not support the operation
+    Unsupported,
+    /// The file would grow beyond the filesystem's size limit
+    FileTooLarge,
+}
+
+impl From<FsError> for VfsError {
+    fn from(err: FsError) -> Self {
+        match err {
+            FsError::NotFound => VfsError::NotFound,
+            FsError::NotADirectory => VfsError::NotADirectory,
+            FsError::IsADirectory => VfsError::IsADirectory,
+            FsError::AlreadyExists => VfsError::AlreadyExists,
+            FsError::InvalidName => VfsError::InvalidName,
+            FsError::FileTooLarge => VfsError::FileTooLarge,
+        }
+    }
+}
+
+impl fmt::Display for VfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no such file or directory"),
+            Self::NotADirectory => write!(f, "not a directory"),
+            Self::IsADirectory => write!(f, "is a directory"),
+            Self::AlreadyExists => write!(f, "file exists"),
+            Self::InvalidName => write!(f, "invalid file name"),
+            Self::InvalidPath => write!(f, "path is not absolute"),
+            Self::NotMounted => write!(f, "no filesystem mounted at /"),
+            Self::AlreadyMounted => write!(f, "a filesystem is already mounted there"),
+            Self::Unsupported => write!(f, "operation not supported by the filesystem"),
+            Self::FileTooLarge => write!(f, "file too large"),
+        }
+    }
+}
+
+/// Operations a mountable filesystem provides
+///
+/// Files and directories are identified by inode numbers, which only need
+/// to be unique within one filesystem.
+pub trait Filesystem: Send {
+    /// Returns the inode number of the root directory
+    fn root(&self) -> u64;
+
+    /// Finds the entry `name` in directory `dir`
+    fn lookup(&self, dir: u64, name: &str) -> Result<u64, VfsError>;
+
+    /// Reads from file `ino` at `offset`, returning the bytes read
+    fn read(&self, ino: u64, offset: usize, buf: &mut [u8]) -> Result<usize, VfsError>;
+
+    /// Writes to file `ino` at `offset`, returning the bytes written
+    fn write(&mut self, ino: u64, offset: usize, data: &[u8]) -> Result<usize, VfsError>;
+
+    /// Creates the entry `name` in directory `dir`, returning its inode
+    fn create(&mut self, dir: u64, name: &str, kind: INodeKind) -> Result<u64, VfsError>;
+
+    /// Lists directory `ino`
+    fn readdir(&self, ino: u64) -> Result<Vec<DirEntry>, VfsError>;
+}
+
+impl Filesystem for RamFs {
+    fn root(&self) -> u64 {
+        ROOT_INO
+    }
+
+    fn lookup(&self, dir: u64, name: &str) -> Result<u64, VfsError> {
+        match self.get(dir).map(|inode| inode.kind) {
+            Some(INodeKind::Dir) => RamFs::lookup(self, dir, name).ok_or(VfsError::NotFound),
+            Some(INodeKind::File) => Err(VfsError::NotADirectory),
+            None => Err(VfsError::NotFound),
+        }
+    }
+
+    fn read(&self, ino: u64, offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        Ok(RamFs::read(self, ino, offset, buf)?)
+    }
+
+    fn write(&mut self, ino: u64, offset: usize, data: &[u8]) -> Result<usize, VfsError> {
+        Ok(RamFs::write(self, ino, offset, data)?)
+    }
+
+    fn create(&mut self, dir: u64, name: &str, kind: INodeKind) -> Result<u64, VfsError> {
+        Ok(RamFs::create(self, dir, name, kind)?)
+    }
+
+    fn readdir(&self, ino: u64) -> Result<Vec<DirEntry>, VfsError> {
+        match self.get(ino).map(|inode| inode.kind) {
+            Some(INodeKind::Dir) => Ok(RamFs::readdir(self, ino).collect()),
+            Some(INodeKind::File) => Err(VfsError::NotADirectory),
+            None => Err(VfsError::NotFound),
+        }
+    }
+}
+
+/// A shared `RamFs`, such as `ROOT_FS`, mounted without moving it
+impl Filesystem for &'static Mutex<RamFs> {
+    fn root(&self) -> u64 {
+        ROOT_INO
+    }
+
+    fn lookup(&self, dir: u64, name: &str) -> Result<u64, VfsError> {
+        Filesystem::lookup(&*self.lock(), dir, name)
+    }
+
+    fn read(&self, ino: u64, offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        Filesystem::read(&*self.lock(), ino, offset, buf)
+    }
+
+    fn write(&mut self, ino: u64, offset: usize, data: &[u8]) -> Result<usize, VfsError> {
+        Filesystem::write(&mut *self.lock(), ino, offset, data)
+    }
+
+    fn create(&mut self, dir: u64, name: &str, kind: INodeKind) -> Result<u64, VfsError> {
+        Filesystem::create(&mut *self.lock(), dir, name, kind)
+    }
+
+    fn readdir(&self, ino: u64) -> Result<Vec<DirEntry>, VfsError> {
+        Filesystem::readdir(&*self.lock(), ino)
+    }
+}
+
+/// A mounted filesystem, shared with the file handles opened on it
+pub type SharedFilesystem = Arc<Mutex<Box<dyn Filesystem>>>;
+
+/// A filesystem mounted at a path
+pub struct VfsMount {
+    /// Components of the mount point; empty for `/`
+    components: Vec<String>,
+    fs: SharedFilesystem,
+}
+
+impl VfsMount {
+    /// Returns the absolute mount point
+    pub fn path(&self) -> String {
+        if self.components.is_empty() {
+            return String::from("/");
+        }
+        self.components
+            .iter()
+            .fold(String::new(), |path, component| path + "/" + component)
+    }
+}
+
+impl fmt::Debug for VfsMount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VfsMount")
+            .field("path", &self.path())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An open file: a filesystem, an inode on it and a position
+#[derive(Clone)]
+pub struct FileHandle {
+    fs: SharedFilesystem,
+    ino: u64,
+    offset: usize,
+}
+
+impl FileHandle {
+    /// Wraps inode `ino` of `fs`, positioned at the start
+    pub fn new(fs: SharedFilesystem, ino: u64) -> Self {
+        Self { fs, ino, offset: 0 }
+    }
+
+    /// Returns the inode number within its filesystem
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// Returns the position of the next read or write
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Moves the position of the next read or write
+    pub fn seek(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+
+    /// Reads at the current position and advances past the bytes read
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read; 0 at the end of the file
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, VfsError> {
+        let n = self.fs.lock().read(self.ino, self.offset, buf)?;
+        self.offset += n;
+        Ok(n)
+    }
+
+    /// Writes at the current position and advances past the bytes written
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, VfsError> {
+        let n = self.fs.lock().write(self.ino, self.offset, data)?;
+        self.offset += n;
+        Ok(n)
+    }
+
+    /// Lists the entries if the handle refers to a directory
+    pub fn readdir(&self) -> Result<Vec<DirEntry>, VfsError> {
+        self.fs.lock().readdir(self.ino)
+    }
+}
+
+impl fmt::Debug for FileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileHandle")
+            .field("ino", &self.ino)
+            .field("offset", &self.offset)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Mount table and path resolution
+#[derive(Debug)]
+pub struct Vfs {
+    mounts: Vec<VfsMount>,
+}
+
+impl Vfs {
+    /// Creates a VFS with nothing mounted
+    pub const fn new() -> Self {
+        Self { mounts: Vec::new() }
+    }
+
+    /// Mounts `fs` at `path`
+    ///
+    /// The mount point does not need to exist in the parent filesystem.
+    ///
+    /// # Errors
+    ///
+    /// Returns `VfsError::InvalidPath` for a relative path and
+    /// `VfsError::AlreadyMounted` if `path` is taken.
+    pub fn mount(&mut self, path: &str, fs: Box<dyn Filesystem>) -> Result<(), VfsError> {
+        let components = split_path(path)?;
+        if self
+            .mounts
+            .iter()
+            .any(|mount| mount.components == components)
+        {
+            return Err(VfsError::AlreadyMounted);
+        }
+        self.mounts.push(VfsMount {
+            components,
+            fs: Arc::new(Mutex::new(fs)),
+        });
+        Ok(())
+    }
+
+    /// Returns the mounted filesystems
+    pub fn mounts(&self) -> &[VfsMount] {
+        &self.mounts
+    }
+
+    /// Opens the file or directory at `path`, positioned at the start
+    ///
+    /// # Errors
+    ///
+    /// Returns `VfsError::NotFound` if a component does not exist and
+    /// `VfsError::NotADirectory` if a non-final component is a file.
+    pub fn open(&self, path: &str) -> Result<FileHandle, VfsError> {
+        let (fs, rest) = self.resolve_mount(path)?;
+        let ino = {
+            let fs = fs.lock();
+            let mut ino = fs.root();
+            for name in &rest {
+                ino = fs.lookup(ino, name)?;
+            }
+            ino
+        };
+        Ok(FileHandle::new(fs, ino))
+    }
+
+    /// Creates a file or directory at `path`
+    ///
+    /// # Returns
+    ///
+    /// A handle to the new entry
+    ///
+    /// # Errors
+    ///
+    /// Returns `VfsError::AlreadyExists` if `path` exists, or any error of
+    /// resolving its parent directory.
+    pub fn create(&self, path: &str, kind: INodeKind) -> Result<FileHandle, VfsError> {
+        let (fs, mut rest) = self.resolve_mount(path)?;
+        // The mount point itself always exists
+        let name = rest.pop().ok_or(VfsError::AlreadyExists)?;
+        let ino = {
+            let mut fs = fs.lock();
+            let mut dir = fs.root();
+            for component in &rest {
+                dir = fs.lookup(dir, component)?;
+            }
+            fs.create(dir, &name, kind)?
+        };
+        Ok(FileHandle::new(fs, ino))
+    }
+
+    /// Lists the directory at `path`
+    pub fn readdir(&self, path: &str) -> Result<Vec<DirEntry>, VfsError> {
+        self.open(path)?.readdir()
+    }
+
+    /// Finds the mount a path lies on
+    ///
+    /// # Returns
+    ///
+    /// The filesystem and the path components below its mount point
+    fn resolve_mount(&self, path: &str) -> Result<(SharedFilesystem, Vec<String>), VfsError> {
+        let components = split_path(path)?;
+        let mount = self
+            .mounts
+            .iter()
+            .filter(|mount| components.starts_with(&mount.components))
+            .max_by_key(|mount| mount.components.len())
+            .ok_or(VfsError::NotMounted)?;
+        let rest = components[mount.components.len()..].to_vec();
+        Ok((mount.fs.clone(), rest))
+    }
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits an absolute path into its components
+///
+/// Empty components and `.` are skipped and `..` removes the previous
+/// component; `..` at the root stays at the root.
+fn split_path(path: &str) -> Result<Vec<String>, VfsError> {
+    let relative = path.strip_prefix('/').ok_or(VfsError::InvalidPath)?;
+    let mut components: Vec<String> = Vec::new();
+    for component in relative.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(String::from(name)),
+        }
+    }
+    Ok(components)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mounted_ramfs() -> Box<dyn Filesystem> {
+        let mut fs = RamFs::new();
+        fs.mount();
+        Box::new(fs)
+    }
+
+    #[test_case]
+    fn test_split_path() {
+        assert_eq!(split_path("/"), Ok(Vec::<String>::new()));
+        assert_eq!(
+            split_path("/a//b/./c/../d/"),
+            Ok(alloc::vec![
+                String::from("a"),
+                String::from("b"),
+                String::from("d"),
+            ])
+        );
+        assert_eq!(split_path("a/b"), Err(VfsError::InvalidPath));
+    }
+
+    #[test_case]
+    fn test_resolution_crosses_mount_point() {
+        let mut vfs = Vfs::new();
+        assert_eq!(vfs.open("/").unwrap_err(), VfsError::NotMounted);
+        vfs.mount("/", mounted_ramfs()).unwrap();
+        vfs.mount("/tmp", mounted_ramfs()).unwrap();
+        assert_eq!(
+            vfs.mount("/tmp/", mounted_ramfs()).unwrap_err(),
+            VfsError::AlreadyMounted
+        );
+
+        vfs.create("/etc", INodeKind::Dir).unwrap();
+        let mut motd = vfs.create("/etc/motd", INodeKind::File).unwrap();
+        motd.write(b"root").unwrap();
+        let mut scratch = vfs.create("/tmp/scratch", INodeKind::File).unwrap();
+        scratch.write(b"tmp").unwrap();
+
+        // Each file landed on its own filesystem
+        let names = |path| -> Vec<String> {
+            vfs.readdir(path)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect()
+        };
+        assert_eq!(names("/"), ["etc"]);
+        assert_eq!(names("/tmp"), ["scratch"]);
+        assert_eq!(vfs.open("/etc/scratch").unwrap_err(), VfsError::NotFound);
+
+        let mut buf = [0; 8];
+        let mut handle = vfs.open("/tmp/../tmp/scratch").unwrap();
+        assert_eq!(handle.read(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], b"tmp");
+        assert_eq!(handle.read(&mut buf), Ok(0));
+
+        let mut handle = vfs.open("/etc/motd").unwrap();
+        handle.seek(2);
+        assert_eq!(handle.read(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"ot");
+    }
+
+    #[test_case]
+    fn test_path_errors() {
+        let mut vfs = Vfs::new();
+        vfs.mount("/", mounted_ramfs()).unwrap();
+        vfs.create("/file", INodeKind::File).unwrap();
+
+        assert_eq!(vfs.open("/missing").unwrap_err(), VfsError::NotFound);
+        assert_eq!(vfs.open("/file/x").unwrap_err(), VfsError::NotADirectory);
+        assert_eq!(
+            vfs.create("/file", INodeKind::File).unwrap_err(),
+            VfsError::AlreadyExists
+        );
+        assert_eq!(vfs.open("relative").unwrap_err(), VfsError::InvalidPath);
+        assert_eq!(vfs.readdir("/file").unwrap_err(), VfsError::NotADirectory);
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local APIC driver
+//!
+//! The local APIC replaces the 8259 PIC as the interrupt controller of the
+//! CPU. Its registers are memory-mapped at the address held in the
+//! `IA32_APIC_BASE` MSR (normally `0xFEE00000`), which is identity-mapped
+//! as uncached memory before use. The APIC timer is driven by
+//! `apic_timer`.
+
+use spin::Once;
+
+use super::idt::InterruptStackFrame;
+use crate::{
+    cpu::msr::Msr,
+    memory::{
+        HeapFrameAllocator,
+        PageTableManager,
+        PhysAddr,
+        PhysFrame,
+    },
+};
+
+/// Default physical address of the local APIC registers
+pub const DEFAULT_APIC_BASE: u64 = 0xfee0_0000;
+
+/// Vector of the APIC spurious interrupt
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+/// Global enable bit in `IA32_APIC_BASE`
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+/// Base address bits in `IA32_APIC_BASE`
+const APIC_BASE_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Register offsets from the APIC base
+const REG_ID: usize = 0x20;
+const REG_EOI: usize = 0xb0;
+const REG_SPURIOUS: usize = 0xf0;
+pub(super) const REG_LVT_TIMER: usize = 0x320;
+const REG_LVT_LINT0: usize = 0x350;
+pub(super) const REG_TIMER_INITIAL: usize = 0x380;
+pub(super) const REG_TIMER_CURRENT: usize = 0x390;
+pub(super) const REG_TIMER_DIVIDE: usize = 0x3e0;
+
+/// APIC software enable bit in the spurious interrupt vector register
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+/// Mask bit in LVT registers
+pub(super) const LVT_MASKED: u32 = 1 << 16;
+/// ExtINT delivery mode in LVT registers, passing through 8259 interrupts
+const LVT_DELIVERY_EXTINT: u32 = 0b111 << 8;
+/// The local APIC, once `init` has enabled it
+static LOCAL_APIC: Once<LocalApic> = Once::new();
+
+/// Memory-mapped local APIC
+#[derive(Debug)]
+pub struct LocalApic {
+    base: u64,
+}
+
+impl LocalApic {
+    /// Creates a local APIC at the address read from `IA32_APIC_BASE`
+    ///
+    /// The register page is identity-mapped as uncached memory if it is
+    /// not mapped yet. Requires the heap for new page tables.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must have an APIC (see `is_available`).
+    pub unsafe fn from_msr() -> Self {
+        let base = Msr::Ia32ApicBase.read() & APIC_BASE_MASK;
+        map_registers(base);
+        Self { base }
+    }
+
+    /// Enables the APIC and sets the spurious interrupt vector
+    ///
+    /// LINT0 is set up in virtual wire mode, so IRQs the 8259 PIC still
+    /// raises (such as the keyboard) reach the CPU with the PIC's vectors.
+    ///
+    /// # Safety
+    ///
+    /// The IDT must have a handler for `SPURIOUS_VECTOR`.
+    pub unsafe fn enable(&self) {
+        let msr = Msr::Ia32ApicBase.read();
+        Msr::Ia32ApicBase.write(msr | APIC_BASE_ENABLE);
+        self.write(
+            REG_SPURIOUS,
+            SPURIOUS_APIC_ENABLE | u32::from(SPURIOUS_VECTOR),
+        );
+        self.write(REG_LVT_LINT0, LVT_DELIVERY_EXTINT);
+    }
+
+    /// Returns the APIC ID of this CPU
+    pub fn id(&self) -> u32 {
+        self.read(REG_ID) >> 24
+    }
+
+    /// Signals the end of the interrupt being serviced
+    pub fn end_of_interrupt(&self) {
+        self.write(REG_EOI, 0);
+    }
+
+    /// Reads the register at offset `reg`
+    pub(super) fn read(&self, reg: usize) -> u32 {
+        // SAFETY: the register page is mapped and `reg` is a register offset
+        unsafe { core::ptr::read_volatile((self.base as usize + reg) as *const u32) }
+    }
+
+    /// Writes the register at offset `reg`
+    pub(super) fn write(&self, reg: usize, value: u32) {
+        // SAFETY: the register page is mapped and `reg` is a register offset
+        unsafe { core::ptr::write_volatile((self.base as usize + reg) as *mut u32, value) }
+    }
+}
+
+/// Returns `true` if CPUID reports a local APIC
+pub fn is_available() -> bool {
+    crate::cpu::features().contains(crate::cpu::CpuFeatures::APIC)
+}
+
+/// Returns `true` once `init` has switched interrupt delivery to the APIC
+pub fn is_enabled() -> bool {
+    LOCAL_APIC.get().is_some()
+}
+
+/// Returns the local APIC, if enabled
+pub fn local_apic() -> Option<&'static LocalApic> {
+    LOCAL_APIC.get()
+}
+
+/// Enables the local APIC
+///
+/// The 8259 PICs should be masked first, so that only the APIC delivers
+/// interrupts.
+///
+/// # Safety
+///
+/// Must be called once, after the IDT and the heap are initialized and
+/// with interrupts disabled.
+///
+/// # Panics
+///
+/// Panics if CPUID reports no local APIC.
+pub unsafe fn init() -> &'static LocalApic {
+    assert!(is_available(), "CPU has no local APIC");
+    let apic = LOCAL_APIC.call_once(|| LocalApic::from_msr());
+    apic.enable();
+    crate::log_debug!("Local APIC {} enabled at {:#x}", apic.id(), apic.base);
+    apic
+}
+
+/// Handler for the APIC spurious interrupt vector
+///
+/// Spurious interrupts must not be acknowledged with an EOI.
+pub extern "x86-interrupt" fn spurious_handler(_stack_frame: InterruptStackFrame) {}
+
+/// Identity-maps the APIC register page as uncached memory
+unsafe fn map_registers(base: u64) {
+    let result = PageTableManager::current().identity_map_mmio(
+        PhysFrame::containing_address(PhysAddr::new(base)),
+        &mut HeapFrameAllocator::new(),
+    );
+    if let Err(err) = result {
+        crate::log_warn!("APIC registers at {:#x} not mapped: {}", base, err);
+    }
+}
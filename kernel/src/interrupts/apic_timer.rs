@@ -0,0 +1,96 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local APIC timer
+//!
+//! The APIC timer counts down at a rate derived from the bus clock, which
+//! differs between machines, so it is calibrated against the PIT before
+//! use. In periodic mode it then raises the scheduler tick on vector 32,
+//! the vector the PIT uses on IRQ 0, so `timer.rs` works with either.
+
+use core::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+use super::{
+    apic::{
+        LVT_MASKED,
+        LocalApic,
+        REG_LVT_TIMER,
+        REG_TIMER_CURRENT,
+        REG_TIMER_DIVIDE,
+        REG_TIMER_INITIAL,
+    },
+    pit,
+};
+
+/// Vector of the APIC timer, the same as the PIT's IRQ 0
+pub const TIMER_VECTOR: u8 = 32;
+
+/// APIC timer ticks per millisecond, measured by `calibrate`
+///
+/// Counted with the `TIMER_DIVIDE_BY_16` divider; 0 until calibrated.
+pub static APIC_TICKS_PER_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Length of the PIT interval the APIC timer is calibrated against
+const CALIBRATION_MS: u32 = 10;
+
+/// Periodic mode bit in the LVT timer register
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+/// Timer divide configuration for a divisor of 16
+const TIMER_DIVIDE_BY_16: u32 = 0x3;
+
+/// Measures the APIC timer rate against the PIT
+///
+/// Lets the timer count down from its maximum while the PIT runs for
+/// `CALIBRATION_MS`, and stores the result in `APIC_TICKS_PER_MS`.
+///
+/// # Returns
+///
+/// The number of APIC timer ticks per millisecond
+pub fn calibrate(apic: &LocalApic) -> u64 {
+    apic.write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    apic.write(REG_LVT_TIMER, LVT_MASKED);
+    apic.write(REG_TIMER_INITIAL, u32::MAX);
+    pit::busy_wait_ms(CALIBRATION_MS);
+    let elapsed = u32::MAX - apic.read(REG_TIMER_CURRENT);
+    apic.write(REG_TIMER_INITIAL, 0);
+
+    let ticks_per_ms = u64::from(elapsed) / u64::from(CALIBRATION_MS);
+    APIC_TICKS_PER_MS.store(ticks_per_ms, Ordering::Relaxed);
+    ticks_per_ms
+}
+
+/// Starts the timer firing `TIMER_VECTOR` at `frequency` Hz
+///
+/// Calibrates the timer first if that has not been done yet.
+///
+/// # Panics
+///
+/// Panics if `frequency` is 0.
+pub fn start_periodic(apic: &LocalApic, frequency: u32) {
+    assert!(frequency > 0, "APIC timer frequency must be greater than 0");
+
+    let ticks_per_ms = match APIC_TICKS_PER_MS.load(Ordering::Relaxed) {
+        0 => calibrate(apic),
+        ticks => ticks,
+    };
+    let initial = (ticks_per_ms * 1000 / u64::from(frequency)).clamp(1, u64::from(u32::MAX));
+
+    apic.write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    apic.write(REG_LVT_TIMER, LVT_TIMER_PERIODIC | u32::from(TIMER_VECTOR));
+    apic.write(REG_TIMER_INITIAL, initial as u32);
+}
@@ -4,7 +4,28 @@
 
 use core::mem;
 
-use super::tss::TaskStateSegment;
+use super::tss::TssWithIopb;
+
+/// Kernel code segment selector (GDT index 1, RPL 0)
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+
+/// Kernel data segment selector (GDT index 2, RPL 0)
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+
+/// TSS selector (GDT index 3; the descriptor takes two entries)
+pub const TSS_SELECTOR: u16 = 0x18;
+
+/// User data segment selector (GDT index 5, RPL 3)
+pub const USER_DATA_SELECTOR: u16 = 0x28 | 3;
+
+/// User code segment selector (GDT index 6, RPL 3)
+pub const USER_CODE_SELECTOR: u16 = 0x30 | 3;
+
+/// Base selector `sysretq` derives the user selectors from
+///
+/// `sysretq` loads SS from base + 8 and CS from base + 16, so the user
+/// data and code segments must follow each other in that order.
+pub const SYSRET_BASE_SELECTOR: u16 = USER_DATA_SELECTOR - 8;
 
 /// GDT entry structure
 #[derive(Debug, Clone, Copy)]
@@ -76,6 +97,26 @@ impl GdtEntry {
             base_high: 0,
         }
     }
+
+    /// Creates a ring 3 code segment entry
+    ///
+    /// Same as `code_segment` with DPL 3 (access byte 0xFA).
+    const fn user_code_segment() -> Self {
+        Self {
+            access: 0xfa, // Present, Ring 3, Code segment, Executable, Readable
+            ..Self::code_segment()
+        }
+    }
+
+    /// Creates a ring 3 data segment entry
+    ///
+    /// Same as `data_segment` with DPL 3 (access byte 0xF2).
+    const fn user_data_segment() -> Self {
+        Self {
+            access: 0xf2, // Present, Ring 3, Data segment, Writable
+            ..Self::data_segment()
+        }
+    }
 }
 
 /// TSS descriptor (16 bytes in 64-bit mode)
@@ -94,9 +135,11 @@ struct TssDescriptor {
 
 impl TssDescriptor {
     /// Creates a TSS descriptor for the given TSS
-    fn new(tss: &'static TaskStateSegment) -> Self {
+    ///
+    /// The limit covers the I/O permission bitmap after the TSS.
+    fn new(tss: &'static TssWithIopb) -> Self {
         let ptr = tss as *const _ as u64;
-        let limit = mem::size_of::<TaskStateSegment>() - 1;
+        let limit = mem::size_of::<TssWithIopb>() - 1;
 
         Self {
             length: limit as u16,
@@ -118,6 +161,8 @@ struct Gdt {
     code: GdtEntry,
     data: GdtEntry,
     tss: TssDescriptor,
+    user_data: GdtEntry,
+    user_code: GdtEntry,
 }
 
 impl Gdt {
@@ -138,16 +183,22 @@ impl Gdt {
                 base_upper: 0,
                 reserved: 0,
             },
+            user_data: GdtEntry::user_data_segment(),
+            user_code: GdtEntry::user_code_segment(),
         }
     }
 
     /// Sets the TSS descriptor
-    fn set_tss(&mut self, tss: &'static TaskStateSegment) {
+    fn set_tss(&mut self, tss: &'static TssWithIopb) {
         self.tss = TssDescriptor::new(tss);
     }
 }
 
 /// Static GDT instance
+///
+/// Only `init` writes it, once during boot before the GDT is loaded and
+/// before interrupts are enabled; afterwards the CPU alone reads it, so the
+/// `static mut` is sound.
 static mut GDT: Gdt = Gdt::new();
 
 /// GDT pointer structure used by the `lgdt` instruction
@@ -157,11 +208,25 @@ struct GdtPointer {
     base: u64,
 }
 
+/// Returns whether GDTR points at the kernel's GDT
+pub fn is_loaded() -> bool {
+    let mut gdtr = GdtPointer { limit: 0, base: 0 };
+    // SAFETY: sgdt only stores GDTR into `gdtr`
+    unsafe {
+        core::arch::asm!("sgdt [{}]", in(reg) &mut gdtr, options(nostack, preserves_flags));
+    }
+    let (limit, base) = (gdtr.limit, gdtr.base);
+    base == core::ptr::addr_of!(GDT) as u64 && usize::from(limit) == mem::size_of::<Gdt>() - 1
+}
+
 /// Initializes and loads the GDT with TSS
 ///
 /// This function must be called before loading the IDT to ensure the TSS
 /// is properly set up.
-pub fn init(tss: &'static TaskStateSegment) {
+pub fn init(tss: &'static TssWithIopb) {
+    // SAFETY: called once during boot with interrupts disabled, so nothing
+    // else touches GDT. The table is static and its selectors match the
+    // ones reloaded below
     unsafe {
         // Set the TSS descriptor in the GDT
         let gdt_ptr_mut = core::ptr::addr_of_mut!(GDT);
@@ -202,7 +267,7 @@ pub fn init(tss: &'static TaskStateSegment) {
         // TSS selector is 0x18 (offset of TSS in GDT)
         core::arch::asm!(
             "ltr ax",
-            in("ax") 0x18u16,
+            in("ax") TSS_SELECTOR,
             options(nostack, preserves_flags)
         );
     }
@@ -4,6 +4,17 @@
 //! interrupts.
 
 use super::idt::InterruptStackFrame;
+use crate::debug::hwbp;
+
+/// Interrupt enable flag in RFLAGS
+const RFLAGS_IF: u64 = 1 << 9;
+
+/// Resume flag in RFLAGS, which suppresses instruction breakpoints for
+/// one instruction
+const RFLAGS_RF: u64 = 1 << 16;
+
+/// Exit code of a process terminated by a page fault in user mode
+const PAGE_FAULT_EXIT_CODE: i32 = -1;
 
 /// Divide Error (#DE, 0) - Fault
 ///
@@ -16,17 +27,83 @@ pub extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFr
 /// Debug Exception (#DB, 1) - Fault/Trap
 ///
 /// Occurs when a debug event happens (breakpoint, single-step, etc.).
-pub extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
-    crate::log_debug!("EXCEPTION: DEBUG");
-    panic_with_stack_frame("DEBUG", stack_frame);
+/// The cause is read from `DR6`, reported and cleared, and execution
+/// resumes. Setting RF in the saved flags lets an instruction breakpoint,
+/// which fires before the instruction runs, step over it once.
+///
+/// Clearing IF does not mask #DB, and a data breakpoint can fire while the
+/// interrupted code holds the logging or serial locks. As in the NMI
+/// handler, the cause is recorded in atomics and reported with
+/// `_print_unlocked`.
+pub extern "x86-interrupt" fn debug_handler(mut stack_frame: InterruptStackFrame) {
+    let status = hwbp::read_status();
+    hwbp::clear_status();
+
+    let rip = stack_frame.instruction_pointer;
+    hwbp::record_exception(status, rip);
+
+    if let Some(index) = status.breakpoint() {
+        crate::serial::_print_unlocked(format_args!(
+            "[DEBUG] EXCEPTION: DEBUG - hardware breakpoint {} at RIP={:#x}\n",
+            index, rip
+        ));
+    } else if status.single_step() {
+        crate::serial::_print_unlocked(format_args!(
+            "[DEBUG] EXCEPTION: DEBUG - single step at RIP={:#x}\n",
+            rip
+        ));
+    } else if status.register_access() {
+        crate::serial::_print_unlocked(format_args!(
+            "[DEBUG] EXCEPTION: DEBUG - debug register access at RIP={:#x}\n",
+            rip
+        ));
+    } else {
+        crate::serial::_print_unlocked(format_args!("[DEBUG] EXCEPTION: DEBUG\n"));
+    }
+
+    // SAFETY: the frame argument is the one the CPU pushed, which iretq
+    // restores; the volatile write keeps the update from being dropped
+    unsafe {
+        core::ptr::addr_of_mut!(stack_frame.cpu_flags)
+            .write_volatile(stack_frame.cpu_flags | RFLAGS_RF);
+    }
 }
 
 /// Non-Maskable Interrupt (#NMI, 2)
 ///
-/// Hardware NMI interrupt.
+/// Raised periodically by the NMI watchdog (see `nmi::arm_watchdog`). If
+/// the timer has not ticked since the previous NMI the kernel is hung: the
+/// interrupted state is reported, but the kernel is left running.
+///
+/// An NMI can arrive while the hung code holds any lock, including the
+/// logging and serial locks, so the stall is recorded in atomics and
+/// reported with `_print_unlocked`, which takes no lock at all.
+///
+/// `are_enabled()` is always false inside this handler, so whether the
+/// hung code had interrupts enabled is read from the saved flags.
 pub extern "x86-interrupt" fn non_maskable_interrupt_handler(stack_frame: InterruptStackFrame) {
-    crate::log_error!("EXCEPTION: NON-MASKABLE INTERRUPT");
-    panic_with_stack_frame("NON-MASKABLE INTERRUPT", stack_frame);
+    if !super::nmi::check_progress(super::timer::ticks()) {
+        return;
+    }
+    super::nmi::record_stall(stack_frame.instruction_pointer);
+
+    let interrupts_enabled = stack_frame.cpu_flags & RFLAGS_IF != 0;
+    crate::serial::_print_unlocked(format_args!(
+        "[WARN] NMI watchdog: no timer tick since the last NMI (interrupts {})\n",
+        if interrupts_enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    ));
+    crate::serial::_print_unlocked(format_args!(
+        "  RIP={:#x} CS={:#x} RFLAGS={:#x} RSP={:#x} SS={:#x}\n",
+        stack_frame.instruction_pointer,
+        stack_frame.code_segment,
+        stack_frame.cpu_flags,
+        stack_frame.stack_pointer,
+        stack_frame.stack_segment
+    ));
 }
 
 /// Breakpoint Exception (#BP, 3) - Trap
@@ -70,10 +147,13 @@ pub extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStack
 
 /// Device Not Available (#NM, 7) - Fault
 ///
-/// Occurs when an FPU instruction is executed but the FPU is not available.
-pub extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
-    crate::log_error!("EXCEPTION: DEVICE NOT AVAILABLE");
-    panic_with_stack_frame("DEVICE NOT AVAILABLE", stack_frame);
+/// Occurs when an FPU instruction is executed with CR0.TS set, which the
+/// context switch leaves set. Clears TS and loads the running process's
+/// FPU state, which was allocated when it was added to the process table;
+/// the instruction is then retried.
+pub extern "x86-interrupt" fn device_not_available_handler(_stack_frame: InterruptStackFrame) {
+    crate::cpu::fpu::clear_task_switched();
+    crate::process::restore_current_fpu();
 }
 
 /// Double Fault (#DF, 8) - Abort
@@ -160,16 +240,30 @@ pub extern "x86-interrupt" fn general_protection_fault_handler(
 /// - A page directory or page table entry is not present
 /// - A protection check fails
 /// - A reserved bit is set in the page directory or page table
+///
+/// Writes to copy-on-write pages and faults on demand-paged areas of the
+/// running process are resolved by fixing up the mapping. Other faults
+/// terminate the process if they come from user mode and panic otherwise.
 pub extern "x86-interrupt" fn page_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
     // Read CR2 register to get the faulting address
     let fault_addr: u64;
+    // SAFETY: reading CR2 has no side effects
     unsafe {
         core::arch::asm!("mov {}, cr2", out(reg) fault_addr, options(nomem, nostack, preserves_flags));
     }
 
+    // Write to a copy-on-write page, or first touch of a demand-paged
+    // area: fix up the mapping and retry
+    let addr = crate::memory::VirtAddr::new(fault_addr);
+    if crate::memory::cow::handle_page_fault(addr, error_code)
+        || crate::process::vm::handle_page_fault(addr, error_code)
+    {
+        return;
+    }
+
     crate::log_error!("EXCEPTION: PAGE FAULT");
     crate::log_error!("  Accessed Address: {:#x}", fault_addr);
     crate::log_error!("  Error Code: {:#x}", error_code);
@@ -189,8 +283,17 @@ pub extern "x86-interrupt" fn page_fault_handler(
         reserved,
         instruction
     );
+    if crate::cpu::security::is_smep_smap_violation(error_code, fault_addr) {
+        crate::log_error!("  SMEP/SMAP violation: kernel access to a user page");
+    }
     crate::log_error!("  RIP: {:#x}", stack_frame.instruction_pointer);
 
+    // A fault in user code only takes down the process
+    if user {
+        crate::log_error!("  Terminating the faulting process");
+        crate::process::exit(PAGE_FAULT_EXIT_CODE);
+    }
+
     core::hint::black_box(fault_addr);
     core::hint::black_box(error_code);
     panic_with_stack_frame("PAGE FAULT", stack_frame);
@@ -240,6 +343,16 @@ pub extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: Interrupt
 /// This function logs detailed stack frame information before panicking.
 #[inline(never)]
 fn panic_with_stack_frame(exception_name: &str, stack_frame: InterruptStackFrame) -> ! {
+    log_stack_frame(&stack_frame);
+
+    // Prevent optimization from removing the stack_frame
+    core::hint::black_box(&stack_frame);
+
+    panic!("EXCEPTION: {}", exception_name);
+}
+
+/// Logs the registers saved in an interrupt stack frame
+fn log_stack_frame(stack_frame: &InterruptStackFrame) {
     crate::log_error!("Stack Frame:");
     crate::log_error!(
         "  Instruction Pointer: {:#x}",
@@ -249,9 +362,4 @@ fn panic_with_stack_frame(exception_name: &str, stack_frame: InterruptStackFrame
     crate::log_error!("  CPU Flags:           {:#x}", stack_frame.cpu_flags);
     crate::log_error!("  Stack Pointer:       {:#x}", stack_frame.stack_pointer);
     crate::log_error!("  Stack Segment:       {:#x}", stack_frame.stack_segment);
-
-    // Prevent optimization from removing the stack_frame
-    core::hint::black_box(&stack_frame);
-
-    panic!("EXCEPTION: {}", exception_name);
 }
@@ -38,6 +38,39 @@ pub struct InterruptDescriptorTable {
     interrupts: [Entry; 224],            // 32-255 (IRQs and user-defined)
 }
 
+/// Borrows the field of an IDT holding `vector`, with `&` or `&mut`
+macro_rules! vector_entry {
+    ($idt:expr, $vector:expr, $($borrow:tt)+) => {
+        match $vector {
+            0 => $($borrow)+ $idt.divide_error,
+            1 => $($borrow)+ $idt.debug,
+            2 => $($borrow)+ $idt.non_maskable_interrupt,
+            3 => $($borrow)+ $idt.breakpoint,
+            4 => $($borrow)+ $idt.overflow,
+            5 => $($borrow)+ $idt.bound_range_exceeded,
+            6 => $($borrow)+ $idt.invalid_opcode,
+            7 => $($borrow)+ $idt.device_not_available,
+            8 => $($borrow)+ $idt.double_fault,
+            9 => $($borrow)+ $idt.reserved_1,
+            10 => $($borrow)+ $idt.invalid_tss,
+            11 => $($borrow)+ $idt.segment_not_present,
+            12 => $($borrow)+ $idt.stack_segment_fault,
+            13 => $($borrow)+ $idt.general_protection_fault,
+            14 => $($borrow)+ $idt.page_fault,
+            15 => $($borrow)+ $idt.reserved_2,
+            16 => $($borrow)+ $idt.x87_floating_point,
+            17 => $($borrow)+ $idt.alignment_check,
+            18 => $($borrow)+ $idt.machine_check,
+            19 => $($borrow)+ $idt.simd_floating_point,
+            20 => $($borrow)+ $idt.virtualization,
+            vector @ 21..=29 => $($borrow)+ $idt.reserved_3[usize::from(vector - 21)],
+            30 => $($borrow)+ $idt.security_exception,
+            31 => $($borrow)+ $idt.reserved_4,
+            vector @ 32..=255 => $($borrow)+ $idt.interrupts[usize::from(vector - 32)],
+        }
+    };
+}
+
 impl InterruptDescriptorTable {
     /// Creates a new IDT with all entries marked as missing
     #[allow(clippy::new_without_default)]
@@ -83,22 +116,56 @@ impl InterruptDescriptorTable {
             base: self as *const _ as u64,
         };
 
+        // SAFETY: the IDT is static and every entry points at a valid handler
         unsafe {
             core::arch::asm!("lidt [{}]", in(reg) &ptr, options(readonly, nostack, preserves_flags));
         }
     }
 
-    /// Returns a mutable reference to the interrupt entry at the specified
-    /// index
+    /// Returns whether IDTR points at this IDT
+    pub fn is_loaded(&self) -> bool {
+        let mut idtr = DescriptorTablePointer { limit: 0, base: 0 };
+        // SAFETY: sidt only stores IDTR into `idtr`
+        unsafe {
+            core::arch::asm!("sidt [{}]", in(reg) &mut idtr, options(nostack, preserves_flags));
+        }
+        let (limit, base) = (idtr.limit, idtr.base);
+        base == self as *const _ as u64 && usize::from(limit) == mem::size_of::<Self>() - 1
+    }
+
+    /// Returns the entry for an interrupt vector
     ///
     /// # Arguments
     ///
-    /// * `index` - Index into the interrupts array (0-223 for vectors 32-255)
+    /// * `vector` - IDT index: 0-31 are the exceptions, 32-255 the IRQs and
+    ///   software interrupts
+    pub fn get_interrupt_entry(&self, vector: u8) -> &Entry {
+        vector_entry!(self, vector, &)
+    }
+
+    /// Returns a mutable reference to the entry for an interrupt vector
+    ///
+    /// See `get_interrupt_entry`.
+    pub fn get_interrupt_entry_mut(&mut self, vector: u8) -> &mut Entry {
+        vector_entry!(self, vector, &mut)
+    }
+
+    /// Sets the handler function for an interrupt vector
+    pub fn set_handler_fn_for_vector(
+        &mut self,
+        vector: u8,
+        handler: HandlerFunc,
+    ) -> &mut EntryOptions {
+        self.get_interrupt_entry_mut(vector).set_handler_fn(handler)
+    }
+
+    /// Returns a mutable reference to the entry for vector `index + 32`
     ///
     /// # Panics
     ///
-    /// Panics if the index is out of bounds.
-    pub fn get_interrupt_entry_mut(&mut self, index: usize) -> &mut Entry {
+    /// Panics if the index is greater than 223.
+    #[deprecated(note = "use `get_interrupt_entry_mut` with the vector number")]
+    pub fn get_irq_entry_mut(&mut self, index: usize) -> &mut Entry {
         &mut self.interrupts[index]
     }
 }
@@ -269,16 +336,16 @@ impl EntryOptions {
 
     /// Sets the IST (Interrupt Stack Table) index
     ///
-    /// IST provides dedicated stacks for critical interrupts.
-    /// Valid values are 0 (no IST) or 1-7 (IST index).
+    /// The CPU switches to TSS stack `index` before calling the handler.
+    /// Entries start out with index 0, which keeps the current stack.
     ///
     /// # Panics
     ///
-    /// Panics if `ist_index` is greater than 7.
-    pub fn set_ist(&mut self, ist_index: u8) -> &mut Self {
-        assert!(ist_index <= 7, "IST index must be in range 0-7");
+    /// Panics if `index` is not in the range 1-7.
+    pub fn set_stack_index(&mut self, index: u8) -> &mut Self {
+        assert!((1..=7).contains(&index), "IST index must be in range 1-7");
         self.0 &= !0b111; // Clear IST bits (bits 0-2)
-        self.0 |= ist_index as u16; // Set new IST index
+        self.0 |= u16::from(index); // Set new IST index
         self
     }
 }
@@ -291,3 +358,77 @@ struct DescriptorTablePointer {
     /// Virtual address of the IDT
     base: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "x86-interrupt" fn test_handler(_stack_frame: InterruptStackFrame) {}
+
+    #[test_case]
+    fn test_get_interrupt_entry_exceptions() {
+        let idt = InterruptDescriptorTable::new();
+        assert!(core::ptr::eq(idt.get_interrupt_entry(3), &idt.breakpoint));
+        assert!(core::ptr::eq(idt.get_interrupt_entry(14), &idt.page_fault));
+        assert!(core::ptr::eq(
+            idt.get_interrupt_entry(30),
+            &idt.security_exception
+        ));
+    }
+
+    #[test_case]
+    fn test_get_interrupt_entry_covers_every_vector() {
+        let idt = InterruptDescriptorTable::new();
+        let base = &idt as *const InterruptDescriptorTable as usize;
+        for vector in 0..=255u8 {
+            let entry = idt.get_interrupt_entry(vector) as *const Entry as usize;
+            assert_eq!(entry - base, usize::from(vector) * mem::size_of::<Entry>());
+        }
+    }
+
+    #[test_case]
+    fn test_set_handler_fn_for_vector() {
+        let mut idt = InterruptDescriptorTable::new();
+        let handler: HandlerFunc = test_handler;
+        idt.set_handler_fn_for_vector(255, handler);
+        let entry = &idt.interrupts[223];
+        let addr = u64::from(entry.pointer_low)
+            | u64::from(entry.pointer_middle) << 16
+            | u64::from(entry.pointer_high) << 32;
+        assert_eq!(addr, handler as usize as u64);
+    }
+
+    #[test_case]
+    fn test_set_stack_index() {
+        let mut options = EntryOptions::minimal();
+        options.set_stack_index(3);
+        assert_eq!(options.0 & 0b111, 0b011);
+        assert_eq!(options.0 & !0b111, EntryOptions::minimal().0);
+    }
+
+    #[test_case]
+    fn test_fault_handlers_use_their_stacks() {
+        let idt = super::super::IDT.get().expect("IDT not initialized");
+        assert_eq!(
+            idt.double_fault.options.0 & 0b111,
+            u16::from(super::super::tss::DOUBLE_FAULT_IST_INDEX)
+        );
+        assert_eq!(
+            idt.stack_segment_fault.options.0 & 0b111,
+            u16::from(super::super::tss::STACK_FAULT_IST_INDEX)
+        );
+        assert_eq!(
+            idt.non_maskable_interrupt.options.0 & 0b111,
+            u16::from(super::super::tss::NMI_IST_INDEX)
+        );
+        assert_eq!(
+            idt.debug.options.0 & 0b111,
+            u16::from(super::super::tss::DEBUG_IST_INDEX)
+        );
+        assert_eq!(
+            idt.machine_check.options.0 & 0b111,
+            u16::from(super::super::tss::MACHINE_CHECK_IST_INDEX)
+        );
+        assert_eq!(idt.page_fault.options.0 & 0b111, 0);
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! US-QWERTY keymap for PS/2 scan code set 2
+//!
+//! Scan codes are first mapped to layout-independent `Keycode`s, one per
+//! key of a standard 104-key keyboard; the keycodes are then mapped to the
+//! characters they type without and with Shift.
+
+/// A key of a standard 104-key US keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum Keycode {
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Backtick,
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+    Key0,
+    Minus,
+    Equals,
+    Backspace,
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    Enter,
+    LeftShift,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+    LeftCtrl,
+    LeftWin,
+    LeftAlt,
+    Space,
+    RightAlt,
+    RightWin,
+    Menu,
+    RightCtrl,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Insert,
+    Home,
+    PageUp,
+    Delete,
+    End,
+    PageDown,
+    ArrowUp,
+    ArrowLeft,
+    ArrowDown,
+    ArrowRight,
+    NumLock,
+    NumpadSlash,
+    NumpadStar,
+    NumpadMinus,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+    NumpadPlus,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    NumpadEnter,
+    Numpad0,
+    NumpadPeriod,
+}
+
+/// Number of keycodes
+pub const KEYCODE_COUNT: usize = Keycode::NumpadPeriod as usize + 1;
+
+impl Keycode {
+    /// Returns `true` for the keys of the numeric keypad that depend on
+    /// Num Lock (digits and the decimal point)
+    pub const fn is_numpad_navigation(self) -> bool {
+        matches!(
+            self,
+            Keycode::Numpad0
+                | Keycode::Numpad1
+                | Keycode::Numpad2
+                | Keycode::Numpad3
+                | Keycode::Numpad4
+                | Keycode::Numpad5
+                | Keycode::Numpad6
+                | Keycode::Numpad7
+                | Keycode::Numpad8
+                | Keycode::Numpad9
+                | Keycode::NumpadPeriod
+        )
+    }
+}
+
+/// Builds a scan code table from `(scan code, keycode)` pairs
+const fn build_keymap<const N: usize>(keys: &[(u8, Keycode)]) -> [Option<Keycode>; N] {
+    let mut table = [None; N];
+    let mut i = 0;
+    while i < keys.len() {
+        table[keys[i].0 as usize] = Some(keys[i].1);
+        i += 1;
+    }
+    table
+}
+
+/// Builds the keycode to character table from `(keycode, plain, shifted)`
+/// triples
+const fn build_chars(keys: &[(Keycode, u8, u8)]) -> [(u8, u8); KEYCODE_COUNT] {
+    let mut table = [(0, 0); KEYCODE_COUNT];
+    let mut i = 0;
+    while i < keys.len() {
+        table[keys[i].0 as usize] = (keys[i].1, keys[i].2);
+        i += 1;
+    }
+    table
+}
+
+/// Set 2 single-byte scan codes to keycodes
+pub const SET2_KEYMAP: [Option<Keycode>; 0x84] = build_keymap(&[
+    (0x76, Keycode::Escape),
+    (0x05, Keycode::F1),
+    (0x06, Keycode::F2),
+    (0x04, Keycode::F3),
+    (0x0c, Keycode::F4),
+    (0x03, Keycode::F5),
+    (0x0b, Keycode::F6),
+    (0x83, Keycode::F7),
+    (0x0a, Keycode::F8),
+    (0x01, Keycode::F9),
+    (0x09, Keycode::F10),
+    (0x78, Keycode::F11),
+    (0x07, Keycode::F12),
+    (0x0e, Keycode::Backtick),
+    (0x16, Keycode::Key1),
+    (0x1e, Keycode::Key2),
+    (0x26, Keycode::Key3),
+    (0x25, Keycode::Key4),
+    (0x2e, Keycode::Key5),
+    (0x36, Keycode::Key6),
+    (0x3d, Keycode::Key7),
+    (0x3e, Keycode::Key8),
+    (0x46, Keycode::Key9),
+    (0x45, Keycode::Key0),
+    (0x4e, Keycode::Minus),
+    (0x55, Keycode::Equals),
+    (0x66, Keycode::Backspace),
+    (0x0d, Keycode::Tab),
+    (0x15, Keycode::Q),
+    (0x1d, Keycode::W),
+    (0x24, Keycode::E),
+    (0x2d, Keycode::R),
+    (0x2c, Keycode::T),
+    (0x35, Keycode::Y),
+    (0x3c, Keycode::U),
+    (0x43, Keycode::I),
+    (0x44, Keycode::O),
+    (0x4d, Keycode::P),
+    (0x54, Keycode::LeftBracket),
+    (0x5b, Keycode::RightBracket),
+    (0x5d, Keycode::Backslash),
+    (0x58, Keycode::CapsLock),
+    (0x1c, Keycode::A),
+    (0x1b, Keycode::S),
+    (0x23, Keycode::D),
+    (0x2b, Keycode::F),
+    (0x34, Keycode::G),
+    (0x33, Keycode::H),
+    (0x3b, Keycode::J),
+    (0x42, Keycode::K),
+    (0x4b, Keycode::L),
+    (0x4c, Keycode::Semicolon),
+    (0x52, Keycode::Quote),
+    (0x5a, Keycode::Enter),
+    (0x12, Keycode::LeftShift),
+    (0x1a, Keycode::Z),
+    (0x22, Keycode::X),
+    (0x21, Keycode::C),
+    (0x2a, Keycode::V),
+    (0x32, Keycode::B),
+    (0x31, Keycode::N),
+    (0x3a, Keycode::M),
+    (0x41, Keycode::Comma),
+    (0x49, Keycode::Period),
+    (0x4a, Keycode::Slash),
+    (0x59, Keycode::RightShift),
+    (0x14, Keycode::LeftCtrl),
+    (0x11, Keycode::LeftAlt),
+    (0x29, Keycode::Space),
+    (0x7e, Keycode::ScrollLock),
+    (0x77, Keycode::NumLock),
+    (0x7c, Keycode::NumpadStar),
+    (0x7b, Keycode::NumpadMinus),
+    (0x6c, Keycode::Numpad7),
+    (0x75, Keycode::Numpad8),
+    (0x7d, Keycode::Numpad9),
+    (0x79, Keycode::NumpadPlus),
+    (0x6b, Keycode::Numpad4),
+    (0x73, Keycode::Numpad5),
+    (0x74, Keycode::Numpad6),
+    (0x69, Keycode::Numpad1),
+    (0x72, Keycode::Numpad2),
+    (0x7a, Keycode::Numpad3),
+    (0x70, Keycode::Numpad0),
+    (0x71, Keycode::NumpadPeriod),
+]);
+
+/// Set 2 scan codes following the `0xE0` prefix to keycodes
+///
+/// The fake Shift codes (`0x12`, `0x59`) some keyboards wrap around Print
+/// Screen and the navigation keys are left unmapped.
+pub const SET2_EXTENDED_KEYMAP: [Option<Keycode>; 0x80] = build_keymap(&[
+    (0x1f, Keycode::LeftWin),
+    (0x11, Keycode::RightAlt),
+    (0x27, Keycode::RightWin),
+    (0x2f, Keycode::Menu),
+    (0x14, Keycode::RightCtrl),
+    (0x7c, Keycode::PrintScreen),
+    (0x70, Keycode::Insert),
+    (0x6c, Keycode::Home),
+    (0x7d, Keycode::PageUp),
+    (0x71, Keycode::Delete),
+    (0x69, Keycode::End),
+    (0x7a, Keycode::PageDown),
+    (0x75, Keycode::ArrowUp),
+    (0x6b, Keycode::ArrowLeft),
+    (0x72, Keycode::ArrowDown),
+    (0x74, Keycode::ArrowRight),
+    (0x4a, Keycode::NumpadSlash),
+    (0x5a, Keycode::NumpadEnter),
+]);
+
+/// ASCII typed by each keycode without and with Shift (0 for none),
+/// indexed by keycode
+pub const KEY_CHARS: [(u8, u8); KEYCODE_COUNT] = build_chars(&[
+    (Keycode::Escape, 0x1b, 0x1b),
+    (Keycode::Backtick, b'`', b'~'),
+    (Keycode::Key1, b'1', b'!'),
+    (Keycode::Key2, b'2', b'@'),
+    (Keycode::Key3, b'3', b'#'),
+    (Keycode::Key4, b'4', b'$'),
+    (Keycode::Key5, b'5', b'%'),
+    (Keycode::Key6, b'6', b'^'),
+    (Keycode::Key7, b'7', b'&'),
+    (Keycode::Key8, b'8', b'*'),
+    (Keycode::Key9, b'9', b'('),
+    (Keycode::Key0, b'0', b')'),
+    (Keycode::Minus, b'-', b'_'),
+    (Keycode::Equals, b'=', b'+'),
+    (Keycode::Backspace, 0x08, 0x08),
+    (Keycode::Tab, b'\t', b'\t'),
+    (Keycode::Q, b'q', b'Q'),
+    (Keycode::W, b'w', b'W'),
+    (Keycode::E, b'e', b'E'),
+    (Keycode::R, b'r', b'R'),
+    (Keycode::T, b't', b'T'),
+    (Keycode::Y, b'y', b'Y'),
+    (Keycode::U, b'u', b'U'),
+    (Keycode::I, b'i', b'I'),
+    (Keycode::O, b'o', b'O'),
+    (Keycode::P, b'p', b'P'),
+    (Keycode::LeftBracket, b'[', b'{'),
+    (Keycode::RightBracket, b']', b'}'),
+    (Keycode::Backslash, b'\\', b'|'),
+    (Keycode::A, b'a', b'A'),
+    (Keycode::S, b's', b'S'),
+    (Keycode::D, b'd', b'D'),
+    (Keycode::F, b'f', b'F'),
+    (Keycode::G, b'g', b'G'),
+    (Keycode::H, b'h', b'H'),
+    (Keycode::J, b'j', b'J'),
+    (Keycode::K, b'k', b'K'),
+    (Keycode::L, b'l', b'L'),
+    (Keycode::Semicolon, b';', b':'),
+    (Keycode::Quote, b'\'', b'"'),
+    (Keycode::Enter, b'\n', b'\n'),
+    (Keycode::Z, b'z', b'Z'),
+    (Keycode::X, b'x', b'X'),
+    (Keycode::C, b'c', b'C'),
+    (Keycode::V, b'v', b'V'),
+    (Keycode::B, b'b', b'B'),
+    (Keycode::N, b'n', b'N'),
+    (Keycode::M, b'm', b'M'),
+    (Keycode::Comma, b',', b'<'),
+    (Keycode::Period, b'.', b'>'),
+    (Keycode::Slash, b'/', b'?'),
+    (Keycode::Space, b' ', b' '),
+    (Keycode::NumpadSlash, b'/', b'/'),
+    (Keycode::NumpadStar, b'*', b'*'),
+    (Keycode::NumpadMinus, b'-', b'-'),
+    (Keycode::NumpadPlus, b'+', b'+'),
+    (Keycode::NumpadEnter, b'\n', b'\n'),
+    (Keycode::Numpad0, b'0', b'0'),
+    (Keycode::Numpad1, b'1', b'1'),
+    (Keycode::Numpad2, b'2', b'2'),
+    (Keycode::Numpad3, b'3', b'3'),
+    (Keycode::Numpad4, b'4', b'4'),
+    (Keycode::Numpad5, b'5', b'5'),
+    (Keycode::Numpad6, b'6', b'6'),
+    (Keycode::Numpad7, b'7', b'7'),
+    (Keycode::Numpad8, b'8', b'8'),
+    (Keycode::Numpad9, b'9', b'9'),
+    (Keycode::NumpadPeriod, b'.', b'.'),
+]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_keymap_covers_104_keys() {
+        assert_eq!(KEYCODE_COUNT, 104);
+
+        let mut mapped = [false; KEYCODE_COUNT];
+        for keycode in SET2_KEYMAP
+            .iter()
+            .chain(SET2_EXTENDED_KEYMAP.iter())
+            .flatten()
+        {
+            assert!(!mapped[*keycode as usize], "{:?} mapped twice", keycode);
+            mapped[*keycode as usize] = true;
+        }
+        // Pause has no make code of its own; it is decoded from its
+        // 0xE1 sequence
+        mapped[Keycode::Pause as usize] = true;
+        assert!(mapped.iter().all(|&m| m));
+    }
+}
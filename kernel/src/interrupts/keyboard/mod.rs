@@ -0,0 +1,486 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! PS/2 keyboard driver
+//!
+//! The keyboard raises IRQ 1 (vector 33) for every scan code byte. Scan
+//! codes are decoded from set 2 with the US-QWERTY `keymap` and the
+//! resulting key presses are queued in a ring buffer for `poll_event` and
+//! `read_char`. `init` turns off the controller's translation to set 1,
+//! so the keyboard's native set 2 codes reach port 0x60 unchanged.
+
+pub mod keymap;
+
+use bitflags::bitflags;
+use spin::Mutex;
+
+pub use self::keymap::Keycode;
+use self::keymap::{
+    KEY_CHARS,
+    SET2_EXTENDED_KEYMAP,
+    SET2_KEYMAP,
+};
+use super::{
+    idt::InterruptStackFrame,
+    pic::PICS,
+    port::Port,
+};
+
+/// IRQ line of the PS/2 keyboard
+pub const KEYBOARD_IRQ: u8 = 1;
+
+/// Capacity of the keyboard buffer in bytes
+pub const BUFFER_CAPACITY: usize = 256;
+
+/// PS/2 controller data port
+const DATA_PORT: u16 = 0x60;
+/// PS/2 controller status (read) and command (write) port
+const COMMAND_PORT: u16 = 0x64;
+
+/// Controller command reading the configuration byte
+const CMD_READ_CONFIG: u8 = 0x20;
+/// Controller command writing the configuration byte
+const CMD_WRITE_CONFIG: u8 = 0x60;
+/// Configuration bit enabling translation of scan codes to set 1
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+/// Status bit set when the output buffer holds data
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+/// Status bit set while the input buffer is not yet consumed
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+/// Prefix of extended scan codes
+const EXTENDED_PREFIX: u8 = 0xe0;
+/// Prefix of key release scan codes
+const RELEASE_PREFIX: u8 = 0xf0;
+/// Prefix of the Pause key's sequence
+const PAUSE_PREFIX: u8 = 0xe1;
+/// Bytes following `PAUSE_PREFIX` in the Pause sequence
+const PAUSE_SEQUENCE_LEN: u8 = 7;
+
+bitflags! {
+    /// Modifier keys held and lock keys toggled on
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Modifiers: u8 {
+        /// Either Shift key is held
+        const SHIFT =     1 << 0;
+        /// Either Ctrl key is held
+        const CTRL =      1 << 1;
+        /// Either Alt key is held
+        const ALT =       1 << 2;
+        /// Caps Lock is on
+        const CAPS_LOCK = 1 << 3;
+        /// Num Lock is on
+        const NUM_LOCK =  1 << 4;
+    }
+}
+
+/// A key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The key pressed
+    pub keycode: Keycode,
+    /// Modifiers active when the key was pressed
+    pub modifiers: Modifiers,
+    /// The character the key types with these modifiers, if any
+    pub char: Option<char>,
+}
+
+/// Ring buffer of decoded key events
+#[derive(Debug)]
+pub struct KeyboardBuffer<T> {
+    data: [Option<T>; BUFFER_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl<T> KeyboardBuffer<T> {
+    /// Creates an empty buffer
+    pub const fn new() -> Self {
+        Self {
+            data: [const { None }; BUFFER_CAPACITY],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends an item
+    ///
+    /// # Returns
+    ///
+    /// `false` if the buffer is full and the item was dropped
+    pub fn push(&mut self, item: T) -> bool {
+        if self.len == BUFFER_CAPACITY {
+            return false;
+        }
+        self.data[(self.head + self.len) % BUFFER_CAPACITY] = Some(item);
+        self.len += 1;
+        true
+    }
+
+    /// Removes and returns the oldest item
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.data[self.head].take();
+        self.head = (self.head + 1) % BUFFER_CAPACITY;
+        self.len -= 1;
+        item
+    }
+
+    /// Returns the number of buffered items
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no items are buffered
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> Default for KeyboardBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Set 2 scan code decoder
+///
+/// Tracks the prefixes of multi-byte scan codes and the modifier state.
+/// Num Lock starts on, as most firmware leaves it.
+#[derive(Debug)]
+pub struct ScancodeDecoder {
+    extended: bool,
+    release: bool,
+    /// Bytes of the Pause sequence still to be skipped
+    pause_remaining: u8,
+    left_shift: bool,
+    right_shift: bool,
+    left_ctrl: bool,
+    right_ctrl: bool,
+    left_alt: bool,
+    right_alt: bool,
+    /// Lock keys toggled on (`CAPS_LOCK` and `NUM_LOCK` only)
+    locks: Modifiers,
+}
+
+impl ScancodeDecoder {
+    /// Creates a decoder with no modifiers held and Num Lock on
+    pub const fn new() -> Self {
+        Self {
+            extended: false,
+            release: false,
+            pause_remaining: 0,
+            left_shift: false,
+            right_shift: false,
+            left_ctrl: false,
+            right_ctrl: false,
+            left_alt: false,
+            right_alt: false,
+            locks: Modifiers::NUM_LOCK,
+        }
+    }
+
+    /// Returns the modifiers currently active
+    pub fn modifiers(&self) -> Modifiers {
+        let mut modifiers = self.locks;
+        modifiers.set(Modifiers::SHIFT, self.left_shift || self.right_shift);
+        modifiers.set(Modifiers::CTRL, self.left_ctrl || self.right_ctrl);
+        modifiers.set(Modifiers::ALT, self.left_alt || self.right_alt);
+        modifiers
+    }
+
+    /// Feeds one scan code byte to the decoder
+    ///
+    /// # Returns
+    ///
+    /// The event of a completed key press. Releases only update the
+    /// modifier state.
+    pub fn decode(&mut self, byte: u8) -> Option<KeyEvent> {
+        if self.pause_remaining > 0 {
+            self.pause_remaining -= 1;
+            return None;
+        }
+        match byte {
+            EXTENDED_PREFIX => {
+                self.extended = true;
+                return None;
+            }
+            RELEASE_PREFIX => {
+                self.release = true;
+                return None;
+            }
+            // Pause sends a fixed sequence on press and nothing on release
+            PAUSE_PREFIX => {
+                self.pause_remaining = PAUSE_SEQUENCE_LEN;
+                return Some(self.event(Keycode::Pause));
+            }
+            _ => {}
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+        let pressed = !core::mem::take(&mut self.release);
+        let keycode = if extended {
+            SET2_EXTENDED_KEYMAP.get(usize::from(byte))
+        } else {
+            SET2_KEYMAP.get(usize::from(byte))
+        };
+        let keycode = (*keycode?)?;
+
+        match keycode {
+            Keycode::LeftShift => self.left_shift = pressed,
+            Keycode::RightShift => self.right_shift = pressed,
+            Keycode::LeftCtrl => self.left_ctrl = pressed,
+            Keycode::RightCtrl => self.right_ctrl = pressed,
+            Keycode::LeftAlt => self.left_alt = pressed,
+            Keycode::RightAlt => self.right_alt = pressed,
+            Keycode::CapsLock if pressed => self.locks.toggle(Modifiers::CAPS_LOCK),
+            Keycode::NumLock if pressed => self.locks.toggle(Modifiers::NUM_LOCK),
+            _ => {}
+        }
+        pressed.then(|| self.event(keycode))
+    }
+
+    /// Builds the event for a press of `keycode` with the current modifiers
+    fn event(&self, keycode: Keycode) -> KeyEvent {
+        let modifiers = self.modifiers();
+        KeyEvent {
+            keycode,
+            modifiers,
+            char: translate(keycode, modifiers),
+        }
+    }
+}
+
+impl Default for ScancodeDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the character `keycode` types with `modifiers`
+fn translate(keycode: Keycode, modifiers: Modifiers) -> Option<char> {
+    if keycode.is_numpad_navigation() && !modifiers.contains(Modifiers::NUM_LOCK) {
+        return None;
+    }
+
+    let (plain, shifted) = KEY_CHARS[keycode as usize];
+    let mut ascii = if modifiers.contains(Modifiers::SHIFT) {
+        shifted
+    } else {
+        plain
+    };
+    if modifiers.contains(Modifiers::CAPS_LOCK) && ascii.is_ascii_alphabetic() {
+        ascii ^= 0x20;
+    }
+    (ascii != 0).then_some(char::from(ascii))
+}
+
+/// Key presses not yet read
+static BUFFER: Mutex<KeyboardBuffer<KeyEvent>> = Mutex::new(KeyboardBuffer::new());
+
+/// Decoder state shared by successive interrupts
+static DECODER: Mutex<ScancodeDecoder> = Mutex::new(ScancodeDecoder::new());
+
+/// Decodes a scan code byte and queues the resulting key press
+///
+/// Called by the interrupt handler for every byte read from the keyboard.
+/// Key presses are dropped when the buffer is full. Ctrl+F requests a heap
+/// usage report instead of being queued.
+pub fn handle_scancode(byte: u8) {
+    if let Some(event) = DECODER.lock().decode(byte) {
+        if event.keycode == Keycode::F && event.modifiers.contains(Modifiers::CTRL) {
+            crate::memory::heap::request_usage_report();
+            return;
+        }
+        BUFFER.lock().push(event);
+    }
+}
+
+/// Removes and returns the oldest key press
+pub fn poll_event() -> Option<KeyEvent> {
+    super::without_interrupts(|| BUFFER.lock().pop())
+}
+
+/// Removes and returns the oldest typed character
+///
+/// Key presses that do not type a character are discarded.
+pub fn read_char() -> Option<char> {
+    loop {
+        if let Some(c) = poll_event()?.char {
+            return Some(c);
+        }
+    }
+}
+
+/// Switches the PS/2 controller to raw set 2 scan codes and unmasks IRQ 1
+///
+/// Must be called after the interrupt controller is set up.
+pub fn init() {
+    let mut data = Port::<u8>::new(DATA_PORT);
+    let mut command = Port::<u8>::new(COMMAND_PORT);
+
+    // SAFETY: standard PS/2 controller protocol on its fixed ports
+    unsafe {
+        // Discard anything left over from the firmware
+        while command.read() & STATUS_OUTPUT_FULL != 0 {
+            data.read();
+        }
+
+        wait_input_empty(&mut command);
+        command.write(CMD_READ_CONFIG);
+        while command.read() & STATUS_OUTPUT_FULL == 0 {
+            core::hint::spin_loop();
+        }
+        let config = data.read() & !CONFIG_TRANSLATION;
+
+        wait_input_empty(&mut command);
+        command.write(CMD_WRITE_CONFIG);
+        wait_input_empty(&mut command);
+        data.write(config);
+
+        PICS.lock().unmask(KEYBOARD_IRQ);
+    }
+}
+
+/// Waits until the controller has consumed the last byte written to it
+unsafe fn wait_input_empty(command: &mut Port<u8>) {
+    while command.read() & STATUS_INPUT_FULL != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Keyboard interrupt handler (IRQ 1, vector 33)
+pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // SAFETY: reading the data port acknowledges the byte to the controller
+    let byte = unsafe { Port::<u8>::new(DATA_PORT).read() };
+    handle_scancode(byte);
+
+    // SAFETY: called from the IRQ 1 handler
+    unsafe {
+        super::end_of_interrupt(KEYBOARD_IRQ);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(decoder: &mut ScancodeDecoder, bytes: &[u8]) -> alloc::string::String {
+        bytes
+            .iter()
+            .filter_map(|&b| decoder.decode(b))
+            .filter_map(|event| event.char)
+            .collect()
+    }
+
+    #[test_case]
+    fn test_decode_hello() {
+        let mut decoder = ScancodeDecoder::new();
+        // Shift+h, e, l, l, o, Enter, each pressed and released
+        let bytes = [
+            0x12, 0x33, 0xf0, 0x33, 0xf0, 0x12, 0x24, 0xf0, 0x24, 0x4b, 0xf0, 0x4b, 0x4b, 0xf0,
+            0x4b, 0x44, 0xf0, 0x44, 0x5a, 0xf0, 0x5a,
+        ];
+        assert_eq!(decode_all(&mut decoder, &bytes), "Hello\n");
+    }
+
+    #[test_case]
+    fn test_key_events() {
+        let mut decoder = ScancodeDecoder::new();
+        // Right Ctrl (extended) held while pressing c
+        assert_eq!(decoder.decode(0xe0), None, "prefix completes no key press");
+        let ctrl = decoder.decode(0x14).unwrap();
+        assert_eq!(ctrl.keycode, Keycode::RightCtrl);
+        assert_eq!(ctrl.char, None);
+
+        let c = decoder.decode(0x21).unwrap();
+        assert_eq!(c.keycode, Keycode::C);
+        assert_eq!(c.modifiers, Modifiers::CTRL | Modifiers::NUM_LOCK);
+        assert_eq!(c.char, Some('c'));
+
+        for byte in [0xe0, 0xf0, 0x14] {
+            assert_eq!(decoder.decode(byte), None);
+        }
+        assert_eq!(decoder.modifiers(), Modifiers::NUM_LOCK);
+
+        // Without Num Lock the keypad digits only move the cursor
+        for byte in [0x77, 0xf0, 0x77] {
+            decoder.decode(byte);
+        }
+        let numpad_7 = decoder.decode(0x6c).unwrap();
+        assert_eq!(numpad_7.keycode, Keycode::Numpad7);
+        assert_eq!(numpad_7.char, None);
+
+        // Pause sends its whole sequence on press and nothing else
+        let events: alloc::vec::Vec<_> = [0xe1, 0x14, 0x77, 0xe1, 0xf0, 0x14, 0xf0, 0x77]
+            .iter()
+            .filter_map(|&b| decoder.decode(b))
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].keycode, Keycode::Pause);
+    }
+
+    #[test_case]
+    fn test_decode_with_shift() {
+        let mut decoder = ScancodeDecoder::new();
+        // Shift+H, release, Shift up, i, release, 1, Shift+1, Enter
+        let bytes = [
+            0x12, 0x33, 0xf0, 0x33, 0xf0, 0x12, 0x43, 0xf0, 0x43, 0x16, 0xf0, 0x16, 0x59, 0x16,
+            0xf0, 0x16, 0xf0, 0x59, 0x5a,
+        ];
+        assert_eq!(decode_all(&mut decoder, &bytes), "Hi1!\n");
+    }
+
+    #[test_case]
+    fn test_decode_caps_lock_and_extended() {
+        let mut decoder = ScancodeDecoder::new();
+        // Caps Lock, a, Caps Lock, a, keypad '/', right arrow, keypad Enter
+        let bytes = [
+            0x58, 0xf0, 0x58, 0x1c, 0x58, 0x1c, 0xe0, 0x4a, 0xe0, 0xf0, 0x4a, 0xe0, 0x74, 0xe0,
+            0x5a,
+        ];
+        assert_eq!(decode_all(&mut decoder, &bytes), "Aa/\n");
+    }
+
+    #[test_case]
+    fn test_buffer_wraps_and_drops_when_full() {
+        let mut buffer = KeyboardBuffer::new();
+        for i in 0..BUFFER_CAPACITY {
+            assert!(buffer.push(i as u8));
+        }
+        assert!(!buffer.push(0xff));
+        assert_eq!(buffer.pop(), Some(0));
+        assert!(buffer.push(0xff));
+        assert_eq!(buffer.len(), BUFFER_CAPACITY);
+
+        for i in 1..BUFFER_CAPACITY {
+            assert_eq!(buffer.pop(), Some(i as u8));
+        }
+        assert_eq!(buffer.pop(), Some(0xff));
+        assert!(buffer.is_empty());
+    }
+
+    #[test_case]
+    fn test_read_char() {
+        while read_char().is_some() {}
+        for byte in [0x2c, 0xf0, 0x2c, 0x29] {
+            handle_scancode(byte);
+        }
+        assert_eq!(read_char(), Some('t'));
+        assert_eq!(read_char(), Some(' '));
+        assert_eq!(read_char(), None);
+    }
+}
@@ -16,15 +16,25 @@
 //!
 //! This module provides interrupt and exception handling for the kernel.
 
+pub mod apic;
+pub mod apic_timer;
 pub mod gdt;
 pub mod handlers;
 pub mod idt;
+pub mod keyboard;
+pub mod nmi;
 pub mod pic;
 pub mod pit;
 pub mod port;
+pub mod syscall;
 pub mod timer;
 pub mod tss;
 
+use core::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
 use idt::InterruptDescriptorTable;
 use spin::Once;
 
@@ -38,13 +48,40 @@ static IDT: Once<InterruptDescriptorTable> = Once::new();
 /// Hardware interrupts (IRQs) are mapped to interrupt vectors 32-47.
 /// - IRQ 0-7: Master PIC (vectors 32-39)
 /// - IRQ 8-15: Slave PIC (vectors 40-47)
-#[allow(dead_code)]
-const IRQ_OFFSET: usize = 32;
+const IRQ_OFFSET: u8 = 32;
+
+/// Number of interrupts handled, indexed by IRQ number
+///
+/// Counted when the interrupt is acknowledged with `end_of_interrupt`, so
+/// spurious interrupts are not included.
+static IRQ_COUNTS: [AtomicU64; 16] = [const { AtomicU64::new(0) }; 16];
+
+/// Returns the number of interrupts handled on `irq`
+pub fn irq_count(irq: u8) -> u64 {
+    IRQ_COUNTS
+        .get(irq as usize)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Sets the kernel stack used on entry from ring 3
+///
+/// Points both the TSS ring 0 stack, which interrupts and exceptions from
+/// user mode switch to, and the `syscall` entry stack at `top`.
+pub fn set_kernel_stack(top: u64) {
+    tss::set_privilege_stack(top);
+    syscall::set_kernel_stack(top);
+}
+
+/// Returns whether the kernel's IDT has been set up and loaded
+pub fn idt_is_loaded() -> bool {
+    IDT.get().is_some_and(InterruptDescriptorTable::is_loaded)
+}
 
 /// Initializes the Interrupt Descriptor Table
 ///
-/// This function sets up the GDT, TSS with IST stacks, and all exception
-/// handlers. It should be called early in the kernel initialization process.
+/// This function sets up the GDT, TSS with IST stacks, the `syscall` MSRs and
+/// all exception handlers. It should be called early in the kernel
+/// initialization process.
 ///
 /// # Panics
 ///
@@ -54,20 +91,35 @@ pub fn init() {
     tss::init();
 
     // Step 2: Load GDT with TSS descriptor
+    // SAFETY: the TSS is only modified through `tss`, which the CPU sees
+    // through the descriptor anyway
     unsafe {
         gdt::init(tss::get_tss());
     }
 
-    // Step 3: Initialize and load IDT
+    // Step 3: Enable the syscall instruction, which needs the GDT's user
+    // segments
+    syscall::init();
+
+    // Step 4: Detect CPU features, which decide the interrupt controller
+    crate::cpu::init();
+
+    // Step 5: Initialize and load IDT
     let idt = IDT.call_once(|| {
         let mut idt = InterruptDescriptorTable::new();
 
         // CPU Exception handlers
         idt.divide_error
             .set_handler_fn(handlers::divide_error_handler);
-        idt.debug.set_handler_fn(handlers::debug_handler);
+        // IF does not mask #DB, NMIs and machine checks, so they can arrive
+        // while the system call path runs in ring 0 on the user stack. They
+        // get their own stacks.
+        idt.debug
+            .set_handler_fn(handlers::debug_handler)
+            .set_stack_index(tss::DEBUG_IST_INDEX);
         idt.non_maskable_interrupt
-            .set_handler_fn(handlers::non_maskable_interrupt_handler);
+            .set_handler_fn(handlers::non_maskable_interrupt_handler)
+            .set_stack_index(tss::NMI_IST_INDEX);
         idt.breakpoint.set_handler_fn(handlers::breakpoint_handler);
         idt.overflow.set_handler_fn(handlers::overflow_handler);
         idt.bound_range_exceeded
@@ -78,19 +130,22 @@ pub fn init() {
             .set_handler_fn(handlers::device_not_available_handler);
 
         // Double Fault handler (diverging)
-        // Use IST index 1 for dedicated stack to prevent triple-fault on stack
+        // Use a dedicated IST stack to prevent triple-fault on stack
         // corruption
         idt.double_fault
             .set_handler_fn_diverging_with_error_code(handlers::double_fault_handler)
-            .set_ist(1);
+            .set_stack_index(tss::DOUBLE_FAULT_IST_INDEX);
 
         // Handlers with error codes
         idt.invalid_tss
             .set_handler_fn_with_error_code(handlers::invalid_tss_handler);
         idt.segment_not_present
             .set_handler_fn_with_error_code(handlers::segment_not_present_handler);
+        // A stack segment fault may come from a bad kernel stack, so it
+        // gets its own stack too
         idt.stack_segment_fault
-            .set_handler_fn_with_error_code(handlers::stack_segment_fault_handler);
+            .set_handler_fn_with_error_code(handlers::stack_segment_fault_handler)
+            .set_stack_index(tss::STACK_FAULT_IST_INDEX);
         idt.general_protection_fault
             .set_handler_fn_with_error_code(handlers::general_protection_fault_handler);
         idt.page_fault
@@ -102,15 +157,32 @@ pub fn init() {
         idt.alignment_check
             .set_handler_fn_with_error_code(handlers::alignment_check_handler);
         idt.machine_check
-            .set_handler_fn_diverging(handlers::machine_check_handler);
+            .set_handler_fn_diverging(handlers::machine_check_handler)
+            .set_stack_index(tss::MACHINE_CHECK_IST_INDEX);
         idt.simd_floating_point
             .set_handler_fn(handlers::simd_floating_point_handler);
 
         // Hardware interrupt handlers (IRQs)
         // Timer (IRQ 0 → vector 32)
-        // Note: get_interrupt_entry_mut expects relative index 0-223 for vectors 32-255
-        idt.get_interrupt_entry_mut(0)
-            .set_handler_fn(timer::timer_interrupt_handler);
+        idt.set_handler_fn_for_vector(IRQ_OFFSET, timer::timer_interrupt_handler);
+
+        // Keyboard (IRQ 1 → vector 33)
+        idt.set_handler_fn_for_vector(
+            IRQ_OFFSET + keyboard::KEYBOARD_IRQ,
+            keyboard::keyboard_interrupt_handler,
+        );
+
+        // COM1 (IRQ 4 → vector 36)
+        idt.set_handler_fn_for_vector(
+            IRQ_OFFSET + crate::serial::COM1_IRQ,
+            crate::serial::serial_interrupt_handler,
+        );
+
+        // Spurious IRQs (IRQ 7 → vector 39, IRQ 15 → vector 47) can be
+        // raised even while masked
+        idt.set_handler_fn_for_vector(IRQ_OFFSET + 7, pic::spurious_master_handler);
+        idt.set_handler_fn_for_vector(IRQ_OFFSET + 15, pic::spurious_slave_handler);
+        idt.set_handler_fn_for_vector(apic::SPURIOUS_VECTOR, apic::spurious_handler);
 
         idt
     });
@@ -125,39 +197,90 @@ pub fn init() {
 ///
 /// This function:
 /// 1. Initializes the PIC (Programmable Interrupt Controller)
-/// 2. Configures the PIT (Programmable Interval Timer) to the desired frequency
-/// 3. Unmasks the timer interrupt (IRQ 0)
+/// 2. If CPUID reports an APIC, masks the PIC and starts the APIC timer;
+///    otherwise configures the PIT (Programmable Interval Timer) to the desired
+///    frequency and unmasks the timer interrupt (IRQ 0)
+/// 3. Enables the COM1 receive interrupt and unmasks it (IRQ 4)
 /// 4. Enables interrupts globally
 ///
+/// Either way the timer fires vector 32 at `timer::TIMER_FREQUENCY`.
+///
 /// # Safety
 ///
 /// This function should only be called once during kernel initialization,
-/// after the IDT has been set up.
+/// after the IDT and the heap have been set up.
 ///
 /// # Panics
 ///
 /// Panics if called before `init()`.
 pub fn enable_timer_interrupts() {
-    // Step 1: Initialize PIC
+    // Step 1: Initialize PIC, which remaps it even if the APIC takes over
+    // SAFETY: the IDT is loaded, so the remapped vectors have handlers
     unsafe {
         pic::PICS.lock().initialize();
     }
 
-    // Step 2: Configure PIT to desired frequency
-    let mut pit = pit::Pit::new();
-    pit.set_frequency(timer::TIMER_FREQUENCY);
+    if apic::is_available() {
+        // Step 2a: Hand interrupt delivery over to the APIC and its timer
+        // SAFETY: the APIC is available, and masking the PIC first keeps
+        // the two from delivering the same interrupts
+        let apic = unsafe {
+            pic::PICS.lock().disable();
+            apic::init()
+        };
+        apic_timer::start_periodic(apic, timer::TIMER_FREQUENCY);
+    } else {
+        // Step 2b: Configure PIT to desired frequency and enable IRQ 0
+        let mut pit = pit::Pit::new();
+        pit.set_frequency(timer::TIMER_FREQUENCY);
+        // SAFETY: the timer handler is installed in the IDT
+        unsafe {
+            pic::PICS.lock().unmask(0);
+        }
+    }
 
-    // Step 3: Enable timer interrupt (IRQ 0)
+    // Step 3: Collect serial input through the COM1 interrupt
+    crate::serial::enable_rx_interrupts();
+    // SAFETY: the COM1 handler is installed in the IDT
     unsafe {
-        pic::PICS.lock().unmask(0);
+        pic::PICS.lock().unmask(crate::serial::COM1_IRQ);
     }
 
     // Step 4: Enable interrupts globally
+    // SAFETY: the IDT, the GDT and the interrupt controllers are set up
     unsafe {
         core::arch::asm!("sti");
     }
 
-    crate::log_debug!("PIC and PIT initialized, interrupts enabled");
+    if apic::is_enabled() {
+        crate::log_debug!(
+            "APIC timer initialized ({} ticks/ms), interrupts enabled",
+            apic_timer::APIC_TICKS_PER_MS.load(Ordering::Relaxed)
+        );
+    } else {
+        crate::log_debug!("PIC and PIT initialized, interrupts enabled");
+    }
+}
+
+/// Acknowledges a hardware interrupt
+///
+/// Once the local APIC is enabled it raises the timer interrupt (IRQ 0),
+/// which it must acknowledge. The other IRQs still come from the PIC,
+/// passed through the APIC in virtual wire mode, and are acknowledged to
+/// the PIC.
+///
+/// # Safety
+///
+/// Must be called from interrupt context after handling the interrupt.
+pub unsafe fn end_of_interrupt(irq: u8) {
+    if let Some(count) = IRQ_COUNTS.get(irq as usize) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+    pic::IRQ_COUNTER.record(irq);
+    match apic::local_apic() {
+        Some(apic) if irq == 0 => apic.end_of_interrupt(),
+        _ => pic::PICS.lock().notify_end_of_interrupt(irq),
+    }
 }
 
 /// Disables interrupts
@@ -190,6 +313,7 @@ pub unsafe fn enable() {
 #[inline]
 pub fn are_enabled() -> bool {
     let flags: u64;
+    // SAFETY: only reads RFLAGS through the stack
     unsafe {
         core::arch::asm!("pushfq; pop {}", out(reg) flags, options(nomem, preserves_flags));
     }
@@ -217,6 +341,7 @@ where F: FnOnce() -> R {
     let enabled = are_enabled();
 
     if enabled {
+        // SAFETY: the previous state is restored below
         unsafe {
             disable();
         }
@@ -225,6 +350,7 @@ where F: FnOnce() -> R {
     let result = f();
 
     if enabled {
+        // SAFETY: interrupts were enabled when this function was entered
         unsafe {
             enable();
         }
@@ -232,3 +358,71 @@ where F: FnOnce() -> R {
 
     result
 }
+
+/// Executes a fallible closure with interrupts disabled
+///
+/// Behaves like `without_interrupts`, but the signature lets the closure
+/// use `?`. The previous interrupt state is restored on both the `Ok` and
+/// the `Err` path.
+///
+/// # Examples
+///
+/// ```
+/// let value = interrupts::try_without_interrupts(|| {
+///     let entry = table.get(index).ok_or(Error::NotFound)?;
+///     Ok(entry.value)
+/// })?;
+/// ```
+pub fn try_without_interrupts<F, R, E>(f: F) -> Result<R, E>
+where F: FnOnce() -> Result<R, E> {
+    without_interrupts(f)
+}
+
+/// Executes a closure with interrupts disabled and returns its result
+///
+/// Same as `without_interrupts`; the name makes it clear at call sites
+/// that a value is handed back out of the critical section.
+pub fn without_interrupts_ref<F, R>(f: F) -> R
+where F: FnOnce() -> R {
+    without_interrupts(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `f` with interrupts enabled, then restores the previous state
+    fn with_interrupts_enabled(f: impl FnOnce()) {
+        let enabled = are_enabled();
+        // SAFETY: the IDT is loaded by `init` before the tests run
+        unsafe { enable() };
+        f();
+        if !enabled {
+            // SAFETY: restores the state the test started with
+            unsafe { disable() };
+        }
+    }
+
+    #[test_case]
+    fn test_try_without_interrupts_propagates_err() {
+        with_interrupts_enabled(|| {
+            let result: Result<u32, &str> = try_without_interrupts(|| {
+                assert!(!are_enabled());
+                let value: u32 = "x".parse().map_err(|_| "not a number")?;
+                Ok(value)
+            });
+            assert_eq!(result, Err("not a number"));
+            assert!(are_enabled());
+        });
+    }
+
+    #[test_case]
+    fn test_try_without_interrupts_returns_ok() {
+        with_interrupts_enabled(|| {
+            let result: Result<u32, &str> = try_without_interrupts(|| Ok(7));
+            assert_eq!(result, Ok(7));
+            assert!(are_enabled());
+            assert!(!without_interrupts_ref(are_enabled));
+        });
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! NMI watchdog
+//!
+//! A periodic non-maskable interrupt checks that the timer is still
+//! ticking. NMIs are delivered even with interrupts disabled, so a CPU
+//! stuck in a loop with `cli` is still caught: if no tick happened since
+//! the previous NMI, the kernel has hung and the NMI handler reports it.
+//!
+//! The NMIs come from an HPET comparator delivering a message-signalled
+//! interrupt in NMI mode. The PIT cannot raise NMIs without an I/O APIC,
+//! so there is no watchdog on machines without a suitable HPET.
+
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+};
+
+use super::apic;
+use crate::time::hpet;
+
+/// Tick count seen by the previous NMI, `u64::MAX` before the first one
+pub static LAST_NMI_TICKS: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Number of NMIs that found the timer stalled
+pub static NMI_STALLS: AtomicU64 = AtomicU64::new(0);
+
+/// Instruction pointer interrupted by the most recent stalled NMI, 0 if
+/// there was none
+pub static LAST_STALL_RIP: AtomicU64 = AtomicU64::new(0);
+
+/// Watchdog period used at boot
+pub const DEFAULT_TIMEOUT_MS: u64 = 5_000;
+
+/// Base of the MSI address window, in which the destination APIC ID is
+/// encoded in bits 12-19
+const MSI_ADDRESS_BASE: u32 = 0xfee0_0000;
+/// NMI delivery mode in MSI data; the vector is ignored
+const MSI_DELIVERY_NMI: u32 = 0b100 << 8;
+
+/// Reasons the watchdog cannot be armed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogError {
+    /// There is no HPET
+    NoHpet,
+    /// No HPET comparator supports periodic MSI delivery
+    NoMsiTimer,
+    /// A zero timeout was given
+    InvalidTimeout,
+}
+
+impl fmt::Display for WatchdogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoHpet => write!(f, "no HPET"),
+            Self::NoMsiTimer => write!(f, "no HPET comparator can deliver NMIs"),
+            Self::InvalidTimeout => write!(f, "watchdog timeout must not be zero"),
+        }
+    }
+}
+
+/// Starts raising an NMI every `timeout_ms`
+///
+/// The NMIs go to this CPU's local APIC, or APIC ID 0 before the APIC is
+/// enabled. Requires `hpet::init`.
+pub fn arm_watchdog(timeout_ms: u64) -> Result<(), WatchdogError> {
+    if timeout_ms == 0 {
+        return Err(WatchdogError::InvalidTimeout);
+    }
+    let hpet = hpet::hpet().ok_or(WatchdogError::NoHpet)?;
+    let apic_id = apic::local_apic().map_or(0, |apic| apic.id());
+
+    // A new watchdog period starts now, not at some stale earlier NMI
+    LAST_NMI_TICKS.store(u64::MAX, Ordering::Relaxed);
+    let timer = hpet
+        .start_periodic_msi(
+            timeout_ms.saturating_mul(1_000_000),
+            MSI_ADDRESS_BASE | (apic_id & 0xff) << 12,
+            MSI_DELIVERY_NMI,
+        )
+        .ok_or(WatchdogError::NoMsiTimer)?;
+    crate::log_debug!(
+        "NMI watchdog on HPET timer {}, every {} ms",
+        timer,
+        timeout_ms
+    );
+    Ok(())
+}
+
+/// Records the tick count seen by an NMI
+///
+/// # Returns
+///
+/// `true` if `ticks` has not advanced since the previous NMI, meaning the
+/// timer interrupt has not run for a whole watchdog period
+pub fn check_progress(ticks: u64) -> bool {
+    let last = LAST_NMI_TICKS.swap(ticks, Ordering::Relaxed);
+    let stalled = last == ticks;
+    if stalled {
+        NMI_STALLS.fetch_add(1, Ordering::Relaxed);
+    }
+    stalled
+}
+
+/// Records where the CPU was stuck when an NMI found the timer stalled
+///
+/// Only touches atomics, so it is safe in NMI context.
+pub fn record_stall(instruction_pointer: u64) {
+    LAST_STALL_RIP.store(instruction_pointer, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_identical_ticks_are_a_stall() {
+        let saved_last = LAST_NMI_TICKS.load(Ordering::Relaxed);
+        let saved_stalls = NMI_STALLS.load(Ordering::Relaxed);
+
+        assert!(!check_progress(1234));
+        assert!(check_progress(1234));
+        assert_eq!(NMI_STALLS.load(Ordering::Relaxed), saved_stalls + 1);
+
+        LAST_NMI_TICKS.store(saved_last, Ordering::Relaxed);
+        NMI_STALLS.store(saved_stalls, Ordering::Relaxed);
+    }
+
+    #[test_case]
+    fn test_advancing_ticks_are_progress() {
+        let saved_last = LAST_NMI_TICKS.load(Ordering::Relaxed);
+
+        assert!(!check_progress(10));
+        assert!(!check_progress(11));
+        assert_eq!(LAST_NMI_TICKS.load(Ordering::Relaxed), 11);
+
+        LAST_NMI_TICKS.store(saved_last, Ordering::Relaxed);
+    }
+
+    #[test_case]
+    fn test_zero_timeout_is_rejected() {
+        assert_eq!(arm_watchdog(0), Err(WatchdogError::InvalidTimeout));
+    }
+}
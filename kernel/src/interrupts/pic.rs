@@ -4,9 +4,23 @@
 //! This module provides initialization and management of the Master/Slave PIC
 //! pair.
 
-use spin::Mutex;
+use alloc::string::String;
+use core::{
+    fmt::Write,
+    sync::atomic::{
+        AtomicU16,
+        AtomicU32,
+        AtomicU64,
+        Ordering,
+    },
+};
 
-use super::port::Port;
+use super::{
+    idt::InterruptStackFrame,
+    port::Port,
+    timer::TIMER_FREQUENCY,
+};
+use crate::sync::DetectMutex;
 
 /// PIC port numbers
 const PIC1_COMMAND: u16 = 0x20;
@@ -22,6 +36,126 @@ const ICW4_8086: u8 = 0x01;
 /// EOI (End of Interrupt) command
 const EOI: u8 = 0x20;
 
+/// OCW3 command selecting the In-Service Register for the next read
+const OCW3_READ_ISR: u8 = 0x0b;
+
+/// Master IRQ line the slave PIC is cascaded on
+const CASCADE_IRQ: u8 = 2;
+
+/// Lowest-priority line of each PIC, where spurious IRQs are reported
+const SPURIOUS_LINE: u8 = 7;
+
+/// Spurious interrupt counters, indexed by IRQ number
+///
+/// Only IRQ7 (master) and IRQ15 (slave) can be spurious; the other entries
+/// stay zero.
+pub static IRQ_STATS: [AtomicU64; 16] = [const { AtomicU64::new(0) }; 16];
+
+/// Returns the number of spurious interrupts seen on `irq`
+pub fn spurious_count(irq: u8) -> u64 {
+    IRQ_STATS
+        .get(irq as usize)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Interrupts per second on one IRQ line above which it is considered a storm
+pub const STORM_THRESHOLD: u32 = 10_000;
+
+/// IRQ lines masked because of an interrupt storm, one bit per IRQ
+pub static MASKED_IRQS: AtomicU16 = AtomicU16::new(0);
+
+/// Per-IRQ interrupt counts over the current one-second window
+pub struct IrqCounter {
+    counts: [AtomicU32; 16],
+    last_reset_tick: AtomicU64,
+}
+
+impl IrqCounter {
+    /// Creates a counter with every IRQ at zero
+    pub const fn new() -> Self {
+        Self {
+            counts: [const { AtomicU32::new(0) }; 16],
+            last_reset_tick: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts one interrupt on `irq`
+    pub fn record(&self, irq: u8) {
+        if let Some(count) = self.counts.get(irq as usize) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the interrupts counted on `irq` in the current window
+    pub fn count(&self, irq: u8) -> u32 {
+        self.counts
+            .get(irq as usize)
+            .map_or(0, |count| count.load(Ordering::Relaxed))
+    }
+
+    /// Returns the timer tick at which the current window started
+    pub fn last_reset_tick(&self) -> u64 {
+        self.last_reset_tick.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for IrqCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Interrupt counts used for storm detection
+pub static IRQ_COUNTER: IrqCounter = IrqCounter::new();
+
+/// Masks IRQ lines that raised more than `STORM_THRESHOLD` interrupts in
+/// the last second
+///
+/// Called from the timer interrupt with the current tick count. Does
+/// nothing until a full second has passed since the last check; then every
+/// counter is reset for the next window. The PICs are only try-locked, as
+/// the timer may have interrupted a holder of the lock; a storm that could
+/// not be masked is seen again one second later.
+pub fn check_irq_storms(ticks: u64) {
+    let last = IRQ_COUNTER.last_reset_tick.load(Ordering::Relaxed);
+    if ticks.saturating_sub(last) < u64::from(TIMER_FREQUENCY) {
+        return;
+    }
+    IRQ_COUNTER.last_reset_tick.store(ticks, Ordering::Relaxed);
+
+    for (irq, count) in (0u8..).zip(IRQ_COUNTER.counts.iter()) {
+        if count.swap(0, Ordering::Relaxed) <= STORM_THRESHOLD || is_storm_masked(irq) {
+            continue;
+        }
+        if let Some(mut pics) = PICS.try_lock() {
+            // SAFETY: masking a line only stops its interrupts from being
+            // delivered
+            unsafe {
+                pics.mask(irq);
+            }
+            MASKED_IRQS.fetch_or(1 << irq, Ordering::Relaxed);
+            crate::log_error!("IRQ storm on IRQ {}", irq);
+        }
+    }
+}
+
+/// Returns whether `irq` was masked because of an interrupt storm
+pub fn is_storm_masked(irq: u8) -> bool {
+    irq < 16 && MASKED_IRQS.load(Ordering::Relaxed) & (1 << irq) != 0
+}
+
+/// Formats the per-IRQ counts of the current window for serial output
+pub fn debug_irq_counts() -> String {
+    let mut out = String::from("IRQ counts:");
+    for irq in 0..16 {
+        let _ = write!(out, " {}={}", irq, IRQ_COUNTER.count(irq));
+        if is_storm_masked(irq) {
+            out.push_str("(masked)");
+        }
+    }
+    out
+}
+
 /// 8259 PIC (Programmable Interrupt Controller)
 struct Pic {
     offset: u8,
@@ -65,6 +199,17 @@ impl Pic {
     unsafe fn read_mask(&mut self) -> u8 {
         self.data.read()
     }
+
+    /// Reads the In-Service Register (IRQs currently being serviced)
+    ///
+    /// # Safety
+    ///
+    /// Writes OCW3 to the command port; must not race with other PIC
+    /// command port accesses.
+    unsafe fn read_isr(&mut self) -> u8 {
+        self.command.write(OCW3_READ_ISR);
+        self.command.read()
+    }
 }
 
 /// ChainedPics (Master + Slave PIC pair)
@@ -136,8 +281,35 @@ impl ChainedPics {
         self.pics[1].set_mask(mask2);
     }
 
+    /// Returns the interrupt mask registers of the master and slave PIC
+    pub fn masks(&mut self) -> [u8; 2] {
+        // SAFETY: reading the mask register has no side effects
+        unsafe { [self.pics[0].read_mask(), self.pics[1].read_mask()] }
+    }
+
+    /// Masks every IRQ line on both PICs
+    ///
+    /// Called after `initialize` when the APIC takes over, so the PICs no
+    /// longer deliver IRQs until drivers unmask their lines again. They are
+    /// still remapped away from the CPU exception vectors, where spurious
+    /// IRQs could otherwise land.
+    ///
+    /// # Safety
+    ///
+    /// Modifies interrupt mask registers.
+    pub unsafe fn disable(&mut self) {
+        self.pics[0].set_mask(0xff);
+        self.pics[1].set_mask(0xff);
+    }
+
     /// Sends EOI (End of Interrupt) to the appropriate PIC(s)
     ///
+    /// Spurious IRQs are detected here: a PIC raises IRQ7 (or IRQ15 on the
+    /// slave) when the request it signalled was withdrawn before the CPU
+    /// acknowledged it. In that case the ISR bit is clear and the PIC must
+    /// not receive an EOI, since it has nothing in service. A spurious IRQ15
+    /// still requires an EOI to the master, which did see the cascade IRQ.
+    ///
     /// # Arguments
     ///
     /// * `irq` - IRQ number (0-15)
@@ -146,9 +318,18 @@ impl ChainedPics {
     ///
     /// Must be called from interrupt context after handling an IRQ.
     pub unsafe fn notify_end_of_interrupt(&mut self, irq: u8) {
+        if irq == SPURIOUS_LINE && self.pics[0].read_isr() & (1 << SPURIOUS_LINE) == 0 {
+            IRQ_STATS[irq as usize].fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
         // If IRQ came from slave PIC, send EOI to both PICs
         if irq >= 8 {
-            self.pics[1].end_of_interrupt();
+            if irq == 8 + SPURIOUS_LINE && self.pics[1].read_isr() & (1 << SPURIOUS_LINE) == 0 {
+                IRQ_STATS[irq as usize].fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.pics[1].end_of_interrupt();
+            }
         }
         // Always send EOI to master PIC
         self.pics[0].end_of_interrupt();
@@ -167,7 +348,7 @@ impl ChainedPics {
         debug_assert!(irq < 16);
         if irq >= 8 {
             // Ensure master's cascade line (IRQ2) is unmasked
-            let m = self.pics[0].read_mask() & !(1u8 << 2);
+            let m = self.pics[0].read_mask() & !(1u8 << CASCADE_IRQ);
             self.pics[0].set_mask(m);
         }
         let (pic, line) = if irq < 8 {
@@ -181,6 +362,9 @@ impl ChainedPics {
 
     /// Disables (masks) a specific IRQ line
     ///
+    /// Masking the last unmasked slave IRQ also masks the cascade line
+    /// (IRQ2) on the master, undoing what `unmask` opened.
+    ///
     /// # Arguments
     ///
     /// * `irq` - IRQ number (0-15)
@@ -188,8 +372,8 @@ impl ChainedPics {
     /// # Safety
     ///
     /// Modifies interrupt mask registers.
-    #[allow(dead_code)]
     pub unsafe fn mask(&mut self, irq: u8) {
+        debug_assert!(irq < 16);
         let pic = if irq < 8 {
             &mut self.pics[0]
         } else {
@@ -198,6 +382,33 @@ impl ChainedPics {
         let line = irq % 8;
         let mask = pic.read_mask() | (1 << line);
         pic.set_mask(mask);
+
+        if irq >= 8 && mask == 0xff {
+            // No slave IRQ left enabled; close the cascade path
+            let m = self.pics[0].read_mask() | (1u8 << CASCADE_IRQ);
+            self.pics[0].set_mask(m);
+        }
+    }
+}
+
+/// Handler for IRQ7 (vector 39), the master PIC's spurious IRQ line
+///
+/// Nothing is connected to IRQ7, so this only needs to acknowledge genuine
+/// interrupts; `notify_end_of_interrupt` filters out spurious ones.
+pub extern "x86-interrupt" fn spurious_master_handler(_stack_frame: InterruptStackFrame) {
+    IRQ_COUNTER.record(SPURIOUS_LINE);
+    // SAFETY: called from the IRQ7 handler, as required for EOI
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(SPURIOUS_LINE);
+    }
+}
+
+/// Handler for IRQ15 (vector 47), the slave PIC's spurious IRQ line
+pub extern "x86-interrupt" fn spurious_slave_handler(_stack_frame: InterruptStackFrame) {
+    IRQ_COUNTER.record(8 + SPURIOUS_LINE);
+    // SAFETY: called from the IRQ15 handler, as required for EOI
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(8 + SPURIOUS_LINE);
     }
 }
 
@@ -218,4 +429,72 @@ unsafe fn io_wait() {
 /// The PICs are initialized with:
 /// - Master PIC offset: 32 (IRQ 0-7 → interrupts 32-39)
 /// - Slave PIC offset: 40 (IRQ 8-15 → interrupts 40-47)
-pub static PICS: Mutex<ChainedPics> = Mutex::new(unsafe { ChainedPics::new(32, 40) });
+// SAFETY: the offsets lie above the CPU exception vectors
+pub static PICS: DetectMutex<ChainedPics> = DetectMutex::new(unsafe { ChainedPics::new(32, 40) });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// IRQ5 has no device in this kernel, so masking it is harmless
+    const TEST_IRQ: u8 = 5;
+
+    #[test_case]
+    fn test_storm_masks_irq() {
+        crate::interrupts::without_interrupts(|| {
+            let saved_tick = IRQ_COUNTER.last_reset_tick();
+            let was_masked = PICS.lock().masks()[0] & (1 << TEST_IRQ) != 0;
+
+            for _ in 0..STORM_THRESHOLD + 1 {
+                IRQ_COUNTER.record(TEST_IRQ);
+            }
+            assert_eq!(IRQ_COUNTER.count(TEST_IRQ), STORM_THRESHOLD + 1);
+            check_irq_storms(saved_tick + u64::from(TIMER_FREQUENCY));
+
+            assert!(is_storm_masked(TEST_IRQ));
+            assert_eq!(IRQ_COUNTER.count(TEST_IRQ), 0);
+            assert!(debug_irq_counts().contains("5=0(masked)"));
+            assert_ne!(PICS.lock().masks()[0] & (1 << TEST_IRQ), 0);
+
+            // Leave the line as the test found it
+            if !was_masked {
+                // SAFETY: the line was unmasked before the test masked it
+                unsafe {
+                    PICS.lock().unmask(TEST_IRQ);
+                }
+            }
+            MASKED_IRQS.fetch_and(!(1 << TEST_IRQ), Ordering::Relaxed);
+            IRQ_COUNTER
+                .last_reset_tick
+                .store(saved_tick, Ordering::Relaxed);
+        });
+    }
+
+    #[test_case]
+    fn test_below_threshold_is_not_masked() {
+        crate::interrupts::without_interrupts(|| {
+            let saved_tick = IRQ_COUNTER.last_reset_tick();
+
+            IRQ_COUNTER.record(TEST_IRQ);
+            check_irq_storms(saved_tick + u64::from(TIMER_FREQUENCY));
+            assert!(!is_storm_masked(TEST_IRQ));
+
+            IRQ_COUNTER
+                .last_reset_tick
+                .store(saved_tick, Ordering::Relaxed);
+        });
+    }
+
+    #[test_case]
+    fn test_check_waits_for_full_second() {
+        crate::interrupts::without_interrupts(|| {
+            let saved_tick = IRQ_COUNTER.last_reset_tick();
+
+            IRQ_COUNTER.record(TEST_IRQ);
+            let before = IRQ_COUNTER.count(TEST_IRQ);
+            check_irq_storms(saved_tick + u64::from(TIMER_FREQUENCY) - 1);
+            assert_eq!(IRQ_COUNTER.count(TEST_IRQ), before);
+            assert_eq!(IRQ_COUNTER.last_reset_tick(), saved_tick);
+        });
+    }
+}
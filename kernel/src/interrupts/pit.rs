@@ -2,7 +2,7 @@
 //!
 //! The 8253/8254 PIT is a legacy timer chip used to generate periodic
 //! interrupts. This module provides initialization and configuration for timer
-//! interrupts.
+//! interrupts, and hardware-timed delays on channel 2.
 
 use super::port::Port;
 
@@ -13,12 +13,27 @@ const PIT_FREQUENCY: u32 = 1193182;
 
 /// PIT I/O ports
 const PIT_CHANNEL_0: u16 = 0x40;
+const PIT_CHANNEL_2: u16 = 0x42;
 const PIT_COMMAND: u16 = 0x43;
 
+/// Keyboard controller port B, which gates PIT channel 2
+///
+/// Bit 0 is the channel 2 gate, bit 1 enables the PC speaker and bit 5
+/// reflects the channel 2 output.
+const PORT_B: u16 = 0x61;
+
+/// Largest count the 16-bit channel 2 counter takes (about 54.9 ms)
+const MAX_ONE_SHOT_COUNT: u64 = 0xffff;
+
+/// Microseconds per second
+const MICROS_PER_SECOND: u64 = 1_000_000;
+
 /// PIT (Programmable Interval Timer)
 pub struct Pit {
     channel_0: Port<u8>,
+    channel_2: Port<u8>,
     command: Port<u8>,
+    pc_speaker: Port<u8>,
 }
 
 impl Pit {
@@ -26,7 +41,53 @@ impl Pit {
     pub const fn new() -> Self {
         Self {
             channel_0: Port::new(PIT_CHANNEL_0),
+            channel_2: Port::new(PIT_CHANNEL_2),
             command: Port::new(PIT_COMMAND),
+            pc_speaker: Port::new(PORT_B),
+        }
+    }
+
+    /// Waits for `delay_us` microseconds, timed by channel 2
+    ///
+    /// Channel 2 counts down in mode 0 (interrupt on terminal count) with
+    /// the speaker disabled, and its OUT bit in port B is polled, so
+    /// neither the tick counter nor interrupts are needed. A count is
+    /// 838 ns. Delays longer than one count of the 16-bit counter
+    /// (about 54.9 ms) are split into several one-shots.
+    ///
+    /// Interrupts are disabled while a one-shot runs, since an interrupt
+    /// handler waiting on channel 2 would reprogram it.
+    pub fn one_shot(&mut self, delay_us: u64) {
+        let mut remaining = delay_us
+            .saturating_mul(u64::from(PIT_FREQUENCY))
+            .div_ceil(MICROS_PER_SECOND);
+        while remaining != 0 {
+            let count = remaining.min(MAX_ONE_SHOT_COUNT);
+            remaining -= count;
+            super::without_interrupts(|| self.count_down(count as u16));
+        }
+    }
+
+    /// Runs channel 2 for `count` PIT clocks and waits for it to finish
+    fn count_down(&mut self, count: u16) {
+        // SAFETY: channel 2 and the speaker port only drive the PC speaker,
+        // which stays silent with the speaker output off
+        unsafe {
+            // Gate off and speaker off while programming
+            let control = self.pc_speaker.read() & !0x03;
+            self.pc_speaker.write(control);
+
+            // Channel 2, Mode 0 (interrupt on terminal count), lobyte/hibyte
+            self.command.write(0xb0);
+            self.channel_2.write((count & 0xff) as u8);
+            self.channel_2.write((count >> 8) as u8);
+
+            // Raising the gate starts the count; OUT2 goes high at zero
+            self.pc_speaker.write(control | 0x01);
+            while self.pc_speaker.read() & 0x20 == 0 {
+                core::hint::spin_loop();
+            }
+            self.pc_speaker.write(control);
         }
     }
 
@@ -66,10 +127,11 @@ impl Pit {
             frequency
         );
 
+        // SAFETY: channel 0 only drives IRQ 0
         unsafe {
             // Channel 0, Mode 3 (square wave generator), 16-bit binary
-            // Command byte: 00 (Channel 0) | 11 (access mode: lobyte/hibyte) | 011 (mode 3)
-            // | 0 (binary) = 0x36 (00110110)
+            // Command byte: 00 (Channel 0) | 11 (access mode: lobyte/hibyte) |
+            // 011 (mode 3) | 0 (binary) = 0x36 (00110110)
             self.command.write(0x36);
 
             // Send divisor (low byte, then high byte)
@@ -80,8 +142,61 @@ impl Pit {
     }
 }
 
+/// Busy-waits for `ms` milliseconds using PIT channel 2
+///
+/// A single `Pit::one_shot`, so this does not disturb the channel 0 timer
+/// or need interrupts. Used to calibrate other timers.
+///
+/// # Panics
+///
+/// Panics if `ms` is 0 or exceeds 54, the longest one-shot the 16-bit
+/// counter allows.
+pub fn busy_wait_ms(ms: u32) {
+    assert!(
+        (1..=54).contains(&ms),
+        "PIT one-shot of {} ms out of range",
+        ms
+    );
+    Pit::new().one_shot(u64::from(ms) * 1000);
+}
+
 impl Default for Pit {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::time::tsc::{
+        self,
+        TSC_FREQ_KHZ,
+    };
+
+    #[test_case]
+    fn test_one_shot_waits_one_millisecond() {
+        if TSC_FREQ_KHZ.load(Ordering::Relaxed) == 0 {
+            tsc::calibrate();
+        }
+        let start = tsc::tsc_cycles();
+        Pit::new().one_shot(1000);
+        let elapsed_us = tsc::cycles_to_ns(tsc::tsc_cycles() - start) / 1000;
+        // QEMU may be descheduled, so only the lower bound is tight
+        assert!(elapsed_us >= 950, "one-shot took {} us", elapsed_us);
+        assert!(elapsed_us < 10_000, "one-shot took {} us", elapsed_us);
+    }
+
+    #[test_case]
+    fn test_one_shot_longer_than_counter() {
+        if TSC_FREQ_KHZ.load(Ordering::Relaxed) == 0 {
+            tsc::calibrate();
+        }
+        let start = tsc::tsc_cycles();
+        Pit::new().one_shot(60_000);
+        let elapsed_us = tsc::cycles_to_ns(tsc::tsc_cycles() - start) / 1000;
+        assert!(elapsed_us >= 59_000, "one-shot took {} us", elapsed_us);
+    }
+}
@@ -20,74 +20,78 @@ pub trait PortValue: Copy {
     ///
     /// Writing to an I/O port can have side effects.
     unsafe fn write_to_port(port: u16, value: Self);
-}
 
-impl PortValue for u8 {
-    unsafe fn read_from_port(port: u16) -> Self {
-        let value: u8;
-        core::arch::asm!(
-            "in al, dx",
-            out("al") value,
-            in("dx") port,
-            options(nomem, nostack, preserves_flags)
-        );
-        value
-    }
+    /// Reads a value from the specified port, ordered with memory accesses
+    ///
+    /// # Safety
+    ///
+    /// Reading from an I/O port can have side effects.
+    unsafe fn read_from_port_volatile(port: u16) -> Self;
 
-    unsafe fn write_to_port(port: u16, value: Self) {
-        core::arch::asm!(
-            "out dx, al",
-            in("dx") port,
-            in("al") value,
-            options(nomem, nostack, preserves_flags)
-        );
-    }
+    /// Writes a value to the specified port, ordered with memory accesses
+    ///
+    /// # Safety
+    ///
+    /// Writing to an I/O port can have side effects.
+    unsafe fn write_to_port_volatile(port: u16, value: Self);
 }
 
-impl PortValue for u16 {
-    unsafe fn read_from_port(port: u16) -> Self {
-        let value: u16;
-        core::arch::asm!(
-            "in ax, dx",
-            out("ax") value,
-            in("dx") port,
-            options(nomem, nostack, preserves_flags)
-        );
-        value
-    }
+/// Implements `PortValue` for an integer type using the given register
+///
+/// The plain accessors are marked `nomem`, so the compiler may move memory
+/// accesses across them. The volatile ones are not: the compiler has to
+/// assume they read and write memory, as `read_volatile` and
+/// `write_volatile` do.
+macro_rules! impl_port_value {
+    ($type:ty, $reg:tt, $in:literal, $out:literal) => {
+        impl PortValue for $type {
+            unsafe fn read_from_port(port: u16) -> Self {
+                let value: $type;
+                core::arch::asm!(
+                    $in,
+                    out($reg) value,
+                    in("dx") port,
+                    options(nomem, nostack, preserves_flags)
+                );
+                value
+            }
 
-    unsafe fn write_to_port(port: u16, value: Self) {
-        core::arch::asm!(
-            "out dx, ax",
-            in("dx") port,
-            in("ax") value,
-            options(nomem, nostack, preserves_flags)
-        );
-    }
-}
+            unsafe fn write_to_port(port: u16, value: Self) {
+                core::arch::asm!(
+                    $out,
+                    in("dx") port,
+                    in($reg) value,
+                    options(nomem, nostack, preserves_flags)
+                );
+            }
 
-impl PortValue for u32 {
-    unsafe fn read_from_port(port: u16) -> Self {
-        let value: u32;
-        core::arch::asm!(
-            "in eax, dx",
-            out("eax") value,
-            in("dx") port,
-            options(nomem, nostack, preserves_flags)
-        );
-        value
-    }
+            unsafe fn read_from_port_volatile(port: u16) -> Self {
+                let value: $type;
+                core::arch::asm!(
+                    $in,
+                    out($reg) value,
+                    in("dx") port,
+                    options(nostack, preserves_flags)
+                );
+                value
+            }
 
-    unsafe fn write_to_port(port: u16, value: Self) {
-        core::arch::asm!(
-            "out dx, eax",
-            in("dx") port,
-            in("eax") value,
-            options(nomem, nostack, preserves_flags)
-        );
-    }
+            unsafe fn write_to_port_volatile(port: u16, value: Self) {
+                core::arch::asm!(
+                    $out,
+                    in("dx") port,
+                    in($reg) value,
+                    options(nostack, preserves_flags)
+                );
+            }
+        }
+    };
 }
 
+impl_port_value!(u8, "al", "in al, dx", "out dx, al");
+impl_port_value!(u16, "ax", "in ax, dx", "out dx, ax");
+impl_port_value!(u32, "eax", "in eax, dx", "out dx, eax");
+
 /// A wrapper for I/O port access
 ///
 /// # Type Parameter
@@ -130,4 +134,112 @@ impl<T: PortValue> Port<T> {
     pub unsafe fn write(&mut self, value: T) {
         T::write_to_port(self.port, value);
     }
+
+    /// Reads a value from this port without reordering memory accesses
+    /// around it
+    ///
+    /// # Safety
+    ///
+    /// Same as `read`.
+    pub unsafe fn read_volatile(&mut self) -> T {
+        T::read_from_port_volatile(self.port)
+    }
+
+    /// Writes a value to this port without reordering memory accesses
+    /// around it
+    ///
+    /// # Safety
+    ///
+    /// Same as `write`.
+    pub unsafe fn write_volatile(&mut self, value: T) {
+        T::write_to_port_volatile(self.port, value);
+    }
+}
+
+/// A port that can only be read, such as a status register
+///
+/// Writing is a compile-time error:
+///
+/// ```compile_fail
+/// use yomi_kernel::interrupts::port::PortReadOnly;
+///
+/// let mut status = PortReadOnly::<u8>::new(0x3fd);
+/// unsafe { status.write(0) };
+/// ```
+pub struct PortReadOnly<T: PortValue> {
+    port: u16,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: PortValue> PortReadOnly<T> {
+    /// Creates a new read-only port
+    pub const fn new(port: u16) -> Self {
+        Self {
+            port,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Reads a value from this port
+    ///
+    /// # Safety
+    ///
+    /// Same as `Port::read`.
+    pub unsafe fn read(&mut self) -> T {
+        T::read_from_port(self.port)
+    }
+
+    /// Reads a value from this port without reordering memory accesses
+    /// around it
+    ///
+    /// # Safety
+    ///
+    /// Same as `Port::read`.
+    pub unsafe fn read_volatile(&mut self) -> T {
+        T::read_from_port_volatile(self.port)
+    }
+}
+
+/// A port that can only be written, such as a command register
+///
+/// Reading is a compile-time error:
+///
+/// ```compile_fail
+/// use yomi_kernel::interrupts::port::PortWriteOnly;
+///
+/// let mut command = PortWriteOnly::<u8>::new(0x43);
+/// let _ = unsafe { command.read() };
+/// ```
+pub struct PortWriteOnly<T: PortValue> {
+    port: u16,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: PortValue> PortWriteOnly<T> {
+    /// Creates a new write-only port
+    pub const fn new(port: u16) -> Self {
+        Self {
+            port,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Writes a value to this port
+    ///
+    /// # Safety
+    ///
+    /// Same as `Port::write`.
+    pub unsafe fn write(&mut self, value: T) {
+        T::write_to_port(self.port, value);
+    }
+
+    /// Writes a value to this port without reordering memory accesses
+    /// around it
+    ///
+    /// # Safety
+    ///
+    /// Same as `Port::write`.
+    pub unsafe fn write_volatile(&mut self, value: T) {
+        T::write_to_port_volatile(self.port, value);
+    }
 }
@@ -0,0 +1,386 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! System call entry and dispatch
+//!
+//! User code enters the kernel with the `syscall` instruction, which jumps
+//! to `syscall_handler` as configured by `init`. Arguments are passed as in
+//! the Linux x86_64 ABI: the number in RAX and up to six arguments in RDI,
+//! RSI, RDX, R10, R8 and R9. The result is returned in RAX; RCX and R11 are
+//! clobbered and all other registers are preserved.
+//!
+//! Syscall numbers follow the Linux x86_64 ABI where an equivalent call
+//! exists; Yomi-specific calls start at 0x1000. Handlers return a
+//! non-negative value on success or a negated errno on failure.
+
+use core::mem::offset_of;
+
+use super::gdt::{
+    KERNEL_CODE_SELECTOR,
+    SYSRET_BASE_SELECTOR,
+};
+use crate::{
+    cpu::msr::Msr,
+    memory::{
+        Page,
+        PageTableManager,
+        USER_SPACE_END,
+        USER_SPACE_START,
+        VirtAddr,
+    },
+};
+
+/// Bad file descriptor
+pub const EBADF: i64 = -9;
+/// Bad address
+pub const EFAULT: i64 = -14;
+/// Invalid argument
+pub const EINVAL: i64 = -22;
+/// Function not implemented
+pub const ENOSYS: i64 = -38;
+
+/// Standard output file descriptor
+const STDOUT: u64 = 1;
+/// Standard error file descriptor
+const STDERR: u64 = 2;
+
+/// System call enable bit in `IA32_EFER`
+const EFER_SCE: u64 = 1 << 0;
+
+/// RFLAGS bits cleared on `syscall`: TF, IF, DF and AC
+///
+/// Clearing IF keeps interrupts off until the handler is on the kernel
+/// stack.
+const SYSCALL_RFLAGS_MASK: u64 = (1 << 8) | (1 << 9) | (1 << 10) | (1 << 18);
+
+/// Size of the default kernel stack for system calls
+const SYSCALL_STACK_SIZE: usize = 16 * 1024;
+
+/// Per-CPU state found through GS after `swapgs`
+#[repr(C)]
+struct SyscallCpuData {
+    /// Stack pointer loaded on entry
+    kernel_rsp: u64,
+    /// User stack pointer, saved while switching stacks
+    user_rsp: u64,
+}
+
+/// Per-CPU data of the only CPU
+///
+/// `syscall_handler` only reaches it through GS after `swapgs`, with IF
+/// cleared by `SYSCALL_RFLAGS_MASK`, and `set_kernel_stack` writes it from
+/// kernel code that no system call can interrupt. No reference to it is
+/// ever formed, so the `static mut` is sound.
+static mut CPU_DATA: SyscallCpuData = SyscallCpuData {
+    kernel_rsp: 0,
+    user_rsp: 0,
+};
+
+/// Default kernel stack for system calls
+#[repr(C, align(16))]
+struct SyscallStack([u8; SYSCALL_STACK_SIZE]);
+
+/// Storage of the default system call stack
+///
+/// Rust code never reads or writes it: `init` only takes its address to
+/// hand the top to `set_kernel_stack`, and from then on the CPU alone uses
+/// it as a stack. That keeps the `static mut` sound.
+static mut SYSCALL_STACK: SyscallStack = SyscallStack([0; SYSCALL_STACK_SIZE]);
+
+/// System call numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum SyscallNumber {
+    /// Write a buffer to a file descriptor (only stdout and stderr)
+    Write = 1,
+    /// Give up the CPU to the next ready process
+    Yield = 24,
+    /// Terminate the calling process with the exit code in the first argument
+    Exit = 60,
+    /// Copy the kernel version string to a user buffer
+    Uname = 63,
+    /// Return CPU utilization since boot as a percentage (Yomi-specific)
+    CpuStats = 0x1000,
+}
+
+impl SyscallNumber {
+    /// Converts a raw syscall number into a `SyscallNumber`
+    pub const fn from_u64(value: u64) -> Option<Self> {
+        match value {
+            1 => Some(Self::Write),
+            24 => Some(Self::Yield),
+            60 => Some(Self::Exit),
+            63 => Some(Self::Uname),
+            0x1000 => Some(Self::CpuStats),
+            _ => None,
+        }
+    }
+}
+
+/// Enables the `syscall` instruction
+///
+/// Programs `IA32_STAR` with the kernel and user selectors, `IA32_LSTAR`
+/// with `syscall_handler` and `IA32_FMASK` with `SYSCALL_RFLAGS_MASK`,
+/// points `IA32_KERNEL_GS_BASE` at the per-CPU data and sets EFER.SCE.
+/// System calls, and interrupts from ring 3, run on a static kernel stack
+/// until `interrupts::set_kernel_stack` installs another one.
+///
+/// Must be called after the GDT is loaded.
+pub fn init() {
+    let stack = core::ptr::addr_of!(SYSCALL_STACK) as u64;
+    let cpu_data = core::ptr::addr_of!(CPU_DATA) as u64;
+    super::set_kernel_stack(stack + SYSCALL_STACK_SIZE as u64);
+
+    // SAFETY: these MSRs exist on every x86_64 CPU, and the values match
+    // the GDT layout and the handler below.
+    unsafe {
+        Msr::Ia32KernelGsBase.write(cpu_data);
+        Msr::Ia32Star.write(
+            (u64::from(SYSRET_BASE_SELECTOR) << 48) | (u64::from(KERNEL_CODE_SELECTOR) << 32),
+        );
+        Msr::Ia32Lstar.write(syscall_handler as *const () as u64);
+        Msr::Ia32Sfmask.write(SYSCALL_RFLAGS_MASK);
+        Msr::Ia32Efer.write(Msr::Ia32Efer.read() | EFER_SCE);
+    }
+}
+
+/// Sets the stack `syscall_handler` switches to on entry
+///
+/// All system calls share this stack, so it must be switched along with
+/// the process once more than one user process can be inside a system
+/// call at a time.
+pub fn set_kernel_stack(top: u64) {
+    // SAFETY: the handler only reads the field with interrupts disabled
+    unsafe { (*core::ptr::addr_of_mut!(CPU_DATA)).kernel_rsp = top };
+}
+
+/// Entry point of the `syscall` instruction
+///
+/// Swaps in the kernel GS base, switches to the kernel stack, saves the
+/// user RSP, RIP (RCX) and RFLAGS (R11) and the argument registers, and
+/// calls `handle_syscall` with a pointer to the saved arguments. The
+/// registers are then restored and `sysretq` returns to user mode with
+/// the result in RAX.
+///
+/// The saved RCX is always canonical: user code cannot run in the page
+/// below the kernel half (see `USER_SPACE_END`), so `sysretq` never
+/// faults in ring 0 on the user stack.
+#[unsafe(naked)]
+unsafe extern "C" fn syscall_handler() {
+    core::arch::naked_asm!(
+        "swapgs",
+        "mov gs:[{user_rsp}], rsp",
+        "mov rsp, gs:[{kernel_rsp}]",
+        "push qword ptr gs:[{user_rsp}]",
+        "push rcx",
+        "push r11",
+        // Keep the stack 16-byte aligned at the call
+        "sub rsp, 8",
+        // Pushed in reverse, so RSP points at `[u64; 6]` in argument order
+        "push r9",
+        "push r8",
+        "push r10",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "mov rdi, rax",
+        "mov rsi, rsp",
+        "call {dispatch}",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop r10",
+        "pop r8",
+        "pop r9",
+        "add rsp, 8",
+        "pop r11",
+        "pop rcx",
+        "pop rsp",
+        "swapgs",
+        "sysretq",
+        user_rsp = const offset_of!(SyscallCpuData, user_rsp),
+        kernel_rsp = const offset_of!(SyscallCpuData, kernel_rsp),
+        dispatch = sym syscall_dispatch,
+    );
+}
+
+/// Called by `syscall_handler` with the saved argument registers
+extern "C" fn syscall_dispatch(number: u64, args: &[u64; 6]) -> i64 {
+    handle_syscall(number, *args)
+}
+
+/// Dispatches a system call
+///
+/// # Arguments
+///
+/// * `number` - Syscall number (RAX)
+/// * `args` - Syscall arguments (RDI, RSI, RDX, R10, R8, R9)
+///
+/// # Returns
+///
+/// The syscall result, or a negated errno on failure
+pub fn handle_syscall(number: u64, args: [u64; 6]) -> i64 {
+    match SyscallNumber::from_u64(number) {
+        Some(SyscallNumber::Write) => sys_write(args[0], args[1], args[2]),
+        Some(SyscallNumber::Yield) => {
+            crate::process::yield_now();
+            0
+        }
+        Some(SyscallNumber::Exit) => crate::process::exit(args[0] as i32),
+        Some(SyscallNumber::Uname) => sys_uname(args[0], args[1]),
+        Some(SyscallNumber::CpuStats) => crate::process::cpu_utilization() as i64,
+        None => ENOSYS,
+    }
+}
+
+/// Writes a user buffer to the serial console
+///
+/// Only stdout and stderr exist; both go to the serial port. The return
+/// value is the number of bytes written.
+fn sys_write(fd: u64, buf: u64, len: u64) -> i64 {
+    if fd != STDOUT && fd != STDERR {
+        return EBADF;
+    }
+    if len == 0 {
+        return 0;
+    }
+    if !is_user_range(buf, len, false) {
+        return EFAULT;
+    }
+
+    // SAFETY: every page of the range is mapped readable for user mode,
+    // and interrupts stay off until the copy is done, so nothing can unmap
+    // it in between.
+    let src = unsafe { core::slice::from_raw_parts(buf as *const u8, len as usize) };
+    crate::cpu::security::with_user_access(|| crate::serial::SERIAL1.lock().write_bytes(src));
+    len as i64
+}
+
+/// Copies the kernel version string into a user buffer
+///
+/// The string is truncated to fit and is not NUL-terminated; the return
+/// value is the number of bytes written.
+fn sys_uname(buf: u64, len: u64) -> i64 {
+    if len == 0 {
+        return EINVAL;
+    }
+    if !is_user_range(buf, len, true) {
+        return EFAULT;
+    }
+
+    // SAFETY: every page of the range is mapped writable for user mode,
+    // and interrupts stay off until the copy is done, so nothing can unmap
+    // it in between.
+    let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len as usize) };
+    crate::cpu::security::with_user_access(|| copy_version(dst)) as i64
+}
+
+/// Copies as much of the kernel version string as fits into `dst`
+///
+/// # Returns
+///
+/// The number of bytes copied
+pub fn copy_version(dst: &mut [u8]) -> usize {
+    let version = crate::kernel_version_string().as_bytes();
+    let n = version.len().min(dst.len());
+    dst[..n].copy_from_slice(&version[..n]);
+    n
+}
+
+/// Checks that `[addr, addr + len)` lies in user space and is mapped for
+/// user mode, writable if `write` is set
+fn is_user_range(addr: u64, len: u64, write: bool) -> bool {
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+    if addr < USER_SPACE_START || end > USER_SPACE_END {
+        return false;
+    }
+
+    // SAFETY: CR3 holds the active, identity-accessible P4 table
+    let mapper = unsafe { PageTableManager::current() };
+    let mut page = addr & !(Page::SIZE - 1);
+    while page < end {
+        if !mapper.is_user_accessible(VirtAddr::new(page), write) {
+            return false;
+        }
+        page += Page::SIZE;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_copy_version() {
+        let mut buf = [0u8; 128];
+        let n = copy_version(&mut buf);
+        assert_eq!(&buf[..n], crate::kernel_version_string().as_bytes());
+
+        let mut short = [0u8; 3];
+        assert_eq!(copy_version(&mut short), 3);
+        assert_eq!(&short, &crate::kernel_version_string().as_bytes()[..3]);
+    }
+
+    #[test_case]
+    fn test_uname_rejects_kernel_pointer() {
+        let args = [0xffff_ffff_8000_0000, 64, 0, 0, 0, 0];
+        assert_eq!(handle_syscall(SyscallNumber::Uname as u64, args), EFAULT);
+        assert_eq!(
+            handle_syscall(SyscallNumber::Uname as u64, [0, 64, 0, 0, 0, 0]),
+            EFAULT
+        );
+    }
+
+    #[test_case]
+    fn test_syscall_numbers() {
+        for number in [
+            SyscallNumber::Write,
+            SyscallNumber::Yield,
+            SyscallNumber::Exit,
+            SyscallNumber::Uname,
+            SyscallNumber::CpuStats,
+        ] {
+            assert_eq!(SyscallNumber::from_u64(number as u64), Some(number));
+        }
+    }
+
+    #[test_case]
+    fn test_write_dispatch() {
+        let write = SyscallNumber::Write as u64;
+        assert_eq!(handle_syscall(write, [3, 0x1000, 1, 0, 0, 0]), EBADF);
+        assert_eq!(handle_syscall(write, [STDOUT, 0x1000, 0, 0, 0, 0]), 0);
+        assert_eq!(
+            handle_syscall(write, [STDERR, 0xffff_ffff_8000_0000, 8, 0, 0, 0]),
+            EFAULT
+        );
+        // The identity map below user space is mapped, but only for the
+        // kernel
+        assert_eq!(handle_syscall(write, [STDOUT, 0x1000, 8, 0, 0, 0]), EFAULT);
+    }
+
+    #[test_case]
+    fn test_yield_dispatch() {
+        // No process is running in the test kernel, so there is nothing
+        // to switch to
+        assert_eq!(handle_syscall(SyscallNumber::Yield as u64, [0; 6]), 0);
+    }
+
+    #[test_case]
+    fn test_unknown_syscall() {
+        assert_eq!(handle_syscall(0xffff, [0; 6]), ENOSYS);
+    }
+}
@@ -1,7 +1,11 @@
 //! Timer interrupt handler
 //!
-//! This module handles the timer interrupt (IRQ 0) from the PIT.
+//! This module handles the timer interrupt (vector 32), raised by the APIC
+//! timer or by the PIT on IRQ 0.
 //! The timer is used to generate periodic scheduler ticks.
+//!
+//! Each tick also measures interrupt latency: how late, by the TSC, the
+//! handler starts compared to one tick interval after the previous one.
 
 #![allow(dead_code)]
 
@@ -12,7 +16,14 @@ use core::sync::atomic::{
 
 use super::{
     idt::InterruptStackFrame,
-    pic::PICS,
+    pic,
+};
+use crate::time::{
+    periodic::PeriodicReport,
+    tsc::{
+        self,
+        TSC_FREQ_KHZ,
+    },
 };
 
 /// Timer tick counter
@@ -23,29 +34,107 @@ static TICKS: AtomicU64 = AtomicU64::new(0);
 
 /// Timer frequency in Hz
 ///
-/// This should match the frequency configured in the PIT.
+/// This should match the frequency configured in the PIT or APIC timer.
 /// 100 Hz = 10ms tick interval
 pub const TIMER_FREQUENCY: u32 = 100;
 
+/// Highest timer interrupt latency seen, in microseconds
+pub static TIMER_LATENCY_MAX_US: AtomicU64 = AtomicU64::new(0);
+
+/// Number of timer interrupts whose latency was measured
+pub static TIMER_LATENCY_SAMPLES: AtomicU64 = AtomicU64::new(0);
+
+/// TSC value at which the next timer interrupt is due
+///
+/// Set by every tick once the TSC is calibrated; 0 while no tick is
+/// expected, so the first one is not measured.
+pub static EXPECTED_NEXT_TICK_TSC: AtomicU64 = AtomicU64::new(0);
+
+/// Interval between interrupt latency reports
+pub const LATENCY_REPORT_INTERVAL_MS: u64 = 10_000;
+
+/// Periodic `report_latency`, polled by the idle task
+pub static LATENCY_REPORT: PeriodicReport = PeriodicReport::new(report_latency);
+
 /// Timer interrupt handler (IRQ 0)
 ///
-/// This handler is called whenever the PIT generates a timer interrupt.
-/// It increments the tick counter and sends EOI to the PIC.
+/// This handler is called whenever the timer generates an interrupt.
+/// It increments the tick counter, sends EOI to the interrupt controller,
+/// masks IRQ lines in an interrupt storm, runs expired timer wheel
+/// callbacks, periodically flushes the VGA shadow buffer, defers process
+/// cleanup to the work queue and lets the scheduler preempt the current
+/// process.
 ///
 /// # Note
 ///
 /// This function is registered as the handler for interrupt vector 32 (IRQ 0).
 pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    // Increment tick counter
-    TICKS.fetch_add(1, Ordering::Relaxed);
+    // Read the TSC first so the handler's own work is not counted, and arm
+    // the next measurement now: the scheduler may switch away below and
+    // not return here until much later
+    measure_latency(tsc::tsc_cycles(), TSC_FREQ_KHZ.load(Ordering::Relaxed));
 
-    // TODO: Call scheduler here when implemented
-    // scheduler::tick();
+    // Increment tick counter
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
 
-    // Send EOI to PIC
+    // Send EOI before scheduling: the next process may not return through
+    // this handler until much later
+    // SAFETY: called from the timer handler
     unsafe {
-        PICS.lock().notify_end_of_interrupt(0);
+        super::end_of_interrupt(0);
+    }
+
+    pic::check_irq_storms(ticks);
+    crate::time::timer_wheel::timer_tick(ticks);
+    if ticks.is_multiple_of(crate::vga::FLUSH_INTERVAL_TICKS) {
+        crate::vga::flush();
     }
+    // Cleaning up terminated processes frees memory, so it is deferred to
+    // the work queue
+    crate::process::defer_reap();
+    crate::process::scheduler::timer_tick();
+}
+
+/// Records the latency of a tick that arrived at TSC value `now` and
+/// sets the time the next one is due
+///
+/// `khz` is the TSC frequency; nothing is measured before it is known. A
+/// tick arriving early counts as zero latency.
+fn measure_latency(now: u64, khz: u64) {
+    if khz == 0 {
+        return;
+    }
+
+    let expected = EXPECTED_NEXT_TICK_TSC.load(Ordering::Relaxed);
+    if expected != 0 {
+        let late_us = tsc::cycles_to_ns_at(now.saturating_sub(expected), khz) / 1000;
+        TIMER_LATENCY_MAX_US.fetch_max(late_us, Ordering::Relaxed);
+        TIMER_LATENCY_SAMPLES.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let cycles_per_tick = khz * 1000 / u64::from(TIMER_FREQUENCY);
+    EXPECTED_NEXT_TICK_TSC.store(now.wrapping_add(cycles_per_tick), Ordering::Relaxed);
+}
+
+/// Returns the highest timer interrupt latency seen, in microseconds
+pub fn max_latency_us() -> u64 {
+    TIMER_LATENCY_MAX_US.load(Ordering::Relaxed)
+}
+
+/// Logs the interrupt latency statistics
+pub fn report_latency() {
+    crate::log_info!(
+        "Timer interrupt latency: max {} us over {} ticks",
+        max_latency_us(),
+        TIMER_LATENCY_SAMPLES.load(Ordering::Relaxed)
+    );
+}
+
+/// Reports the interrupt latency every `LATENCY_REPORT_INTERVAL_MS`
+///
+/// The reports are written by the idle task, not the timer interrupt.
+pub fn start_latency_reports() {
+    LATENCY_REPORT.start(LATENCY_REPORT_INTERVAL_MS, uptime_ms());
 }
 
 /// Returns the current tick count
@@ -105,6 +194,7 @@ mod tests {
 
         // Wait a bit (in a real test with timer enabled)
         for _ in 0..1000000 {
+            // SAFETY: nop has no effect
             unsafe { core::arch::asm!("nop") }
         }
 
@@ -115,6 +205,37 @@ mod tests {
         assert!(final_ticks >= initial_ticks);
     }
 
+    #[test]
+    fn test_latency_measurement() {
+        const KHZ: u64 = 1_000_000;
+
+        crate::interrupts::without_interrupts(|| {
+            let max = TIMER_LATENCY_MAX_US.load(Ordering::Relaxed);
+            let samples = TIMER_LATENCY_SAMPLES.load(Ordering::Relaxed);
+
+            // A tick 2.5 s late at 1 GHz
+            let expected = 1_000_000_000;
+            EXPECTED_NEXT_TICK_TSC.store(expected, Ordering::Relaxed);
+            measure_latency(expected + 2_500_000_000, KHZ);
+            assert_eq!(max_latency_us(), 2_500_000);
+            assert_eq!(TIMER_LATENCY_SAMPLES.load(Ordering::Relaxed), samples + 1);
+
+            // The next tick is due one interval (10 ms) later
+            let next = EXPECTED_NEXT_TICK_TSC.load(Ordering::Relaxed);
+            assert_eq!(next, expected + 2_500_000_000 + 10_000_000);
+
+            // Smaller and early ticks leave the maximum alone
+            measure_latency(next + 1000, KHZ);
+            measure_latency(next, KHZ);
+            assert_eq!(max_latency_us(), 2_500_000);
+
+            // Restore the real measurements
+            TIMER_LATENCY_MAX_US.store(max, Ordering::Relaxed);
+            TIMER_LATENCY_SAMPLES.store(samples, Ordering::Relaxed);
+            EXPECTED_NEXT_TICK_TSC.store(0, Ordering::Relaxed);
+        });
+    }
+
     #[test]
     fn test_uptime_calculation() {
         // Test uptime calculation with known tick values
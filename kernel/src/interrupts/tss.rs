@@ -2,11 +2,35 @@
 //!
 //! The TSS is used to store stack pointers for privilege level changes
 //! and Interrupt Stack Table (IST) entries for critical interrupts.
+//! It is followed by the I/O permission bitmap, which controls which
+//! I/O ports ring 3 code may access.
 
 use core::mem;
 
-/// Size of the double fault stack (16 KiB)
-const DOUBLE_FAULT_STACK_SIZE: usize = 16 * 1024;
+use crate::memory::{
+    HeapFrameAllocator,
+    Page,
+    PageTableManager,
+    VirtAddr,
+};
+
+/// Size of each IST stack (16 KiB)
+const IST_STACK_SIZE: usize = 16 * 1024;
+
+/// IST index of the double fault stack
+pub const DOUBLE_FAULT_IST_INDEX: u8 = 1;
+
+/// IST index of the stack segment fault stack
+pub const STACK_FAULT_IST_INDEX: u8 = 2;
+
+/// IST index of the NMI stack
+pub const NMI_IST_INDEX: u8 = 3;
+
+/// IST index of the debug exception stack
+pub const DEBUG_IST_INDEX: u8 = 4;
+
+/// IST index of the machine check stack
+pub const MACHINE_CHECK_IST_INDEX: u8 = 5;
 
 /// Task State Segment structure for x86_64
 ///
@@ -42,49 +66,269 @@ impl TaskStateSegment {
     }
 }
 
+/// Number of I/O ports covered by the I/O permission bitmap
+pub const IO_PORT_COUNT: usize = 65536;
+
+/// Size of the I/O permission bitmap, one bit per port
+pub const IOPB_SIZE: usize = IO_PORT_COUNT / 8;
+
+/// TSS followed by its I/O permission bitmap (IOPB)
+///
+/// A set bit denies ring 3 access to the port, a clear bit allows it. The
+/// CPU reads two bytes of the bitmap for every access, so the bitmap is
+/// followed by a 0xFF byte that keeps accesses to the last ports from
+/// reading past the TSS limit.
+#[repr(C, packed)]
+pub struct TssWithIopb {
+    /// The TSS itself
+    pub tss: TaskStateSegment,
+    iopb: [u8; IOPB_SIZE + 1],
+}
+
+impl TssWithIopb {
+    /// Creates a TSS whose bitmap denies every port
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            tss: TaskStateSegment::new(),
+            iopb: [0xff; IOPB_SIZE + 1],
+        }
+    }
+
+    /// Allows or denies ring 3 access to `port`
+    pub fn set_io_permission(&mut self, port: u16, allowed: bool) {
+        let byte = &mut self.iopb[usize::from(port) / 8];
+        let bit = 1 << (port % 8);
+        if allowed {
+            *byte &= !bit;
+        } else {
+            *byte |= bit;
+        }
+    }
+
+    /// Returns whether ring 3 may access `port`
+    pub fn io_permission(&self, port: u16) -> bool {
+        self.iopb[usize::from(port) / 8] & (1 << (port % 8)) == 0
+    }
+
+    /// Returns the I/O permission bitmap, terminator included
+    pub fn iopb(&self) -> &[u8; IOPB_SIZE + 1] {
+        &self.iopb
+    }
+}
+
 /// Static TSS instance
-static mut TSS: TaskStateSegment = TaskStateSegment::new();
+///
+/// Only this module writes it: `init` before the TSS is loaded, and later
+/// only fields the CPU reads on a switch from ring 3 or for ring 3 I/O,
+/// neither of which can happen while ring 0 code runs. That keeps the
+/// `static mut` sound.
+static mut TSS: TssWithIopb = TssWithIopb::new();
 
-/// Static stack for double fault handler
+/// Size of the guard page below each IST stack
+const GUARD_PAGE_SIZE: usize = 4096;
+
+/// Static stack for an IST entry
 ///
-/// This stack is dedicated to the double fault handler to prevent triple-faults
-/// caused by stack corruption.
-#[repr(align(16))]
-struct DoubleStackStorage {
-    storage: [u8; DOUBLE_FAULT_STACK_SIZE],
+/// IST stacks are used by handlers for faults that may be caused by a bad
+/// stack, to prevent triple-faults caused by stack corruption. The page
+/// below the stack is turned into a guard page so an overflow faults
+/// instead of corrupting adjacent memory.
+#[repr(C, align(4096))]
+struct IstStackStorage {
+    guard: [u8; GUARD_PAGE_SIZE],
+    storage: [u8; IST_STACK_SIZE],
 }
 
-static mut DOUBLE_FAULT_STACK: DoubleStackStorage = DoubleStackStorage {
-    storage: [0; DOUBLE_FAULT_STACK_SIZE],
-};
+impl IstStackStorage {
+    const fn new() -> Self {
+        Self {
+            guard: [0; GUARD_PAGE_SIZE],
+            storage: [0; IST_STACK_SIZE],
+        }
+    }
+}
+
+/// Stack for the double fault handler
+///
+/// Only handed to the TSS, which keeps it sound; see `NMI_STACK`.
+static mut DOUBLE_FAULT_STACK: IstStackStorage = IstStackStorage::new();
+
+/// Stack for the stack segment fault handler
+///
+/// Only handed to the TSS, which keeps it sound; see `NMI_STACK`.
+static mut STACK_FAULT_STACK: IstStackStorage = IstStackStorage::new();
+
+/// Stack for the NMI handler
+///
+/// `init` only hands its address to the TSS and maps its guard page, and
+/// the CPU alone uses it, so the `static mut` is sound.
+static mut NMI_STACK: IstStackStorage = IstStackStorage::new();
+
+/// Stack for the debug exception handler
+///
+/// Only handed to the TSS, which keeps it sound; see `NMI_STACK`.
+static mut DEBUG_STACK: IstStackStorage = IstStackStorage::new();
+
+/// Stack for the machine check handler
+///
+/// Only handed to the TSS, which keeps it sound; see `NMI_STACK`.
+static mut MACHINE_CHECK_STACK: IstStackStorage = IstStackStorage::new();
 
 /// Initializes the TSS with IST entries
 ///
 /// This function sets up the Interrupt Stack Table (IST) with dedicated stacks
-/// for critical interrupts like double fault.
+/// for the double fault and stack segment fault handlers, and places a guard
+/// page below each.
+///
+/// NMIs, debug exceptions and machine checks get their own stacks as well:
+/// IF does not mask them, so they can arrive in the instructions around
+/// `swapgs` in the system call path, where RSP still holds the user stack
+/// pointer.
+///
+/// Must be called after the heap is initialized.
 pub fn init() {
+    let stacks = [
+        (
+            DOUBLE_FAULT_IST_INDEX,
+            core::ptr::addr_of_mut!(DOUBLE_FAULT_STACK),
+        ),
+        (
+            STACK_FAULT_IST_INDEX,
+            core::ptr::addr_of_mut!(STACK_FAULT_STACK),
+        ),
+        (NMI_IST_INDEX, core::ptr::addr_of_mut!(NMI_STACK)),
+        (DEBUG_IST_INDEX, core::ptr::addr_of_mut!(DEBUG_STACK)),
+        (
+            MACHINE_CHECK_IST_INDEX,
+            core::ptr::addr_of_mut!(MACHINE_CHECK_STACK),
+        ),
+    ];
+
+    for (index, stack) in stacks {
+        map_stack_guard(stack);
+
+        // SAFETY: `init` runs once during boot, before the TSS is loaded
+        unsafe {
+            // Calculate the top of the stack
+            let stack_start = VirtAddr::from_ptr((*stack).storage.as_ptr());
+            let stack_end = (stack_start + IST_STACK_SIZE as u64).as_u64();
+
+            // IST indices are 1-based in hardware but 0-based in our array
+            let tss_ptr = core::ptr::addr_of_mut!(TSS.tss);
+            (*tss_ptr).interrupt_stack_table[usize::from(index - 1)] = stack_end;
+        }
+    }
+
+    // SAFETY: as above
     unsafe {
-        // Calculate the top of the double fault stack
-        let stack_ptr = core::ptr::addr_of!(DOUBLE_FAULT_STACK);
-        let stack_start = (*stack_ptr).storage.as_ptr() as u64;
-        let stack_end = stack_start + DOUBLE_FAULT_STACK_SIZE as u64;
-
-        // Set IST entry 1 for double fault handler
-        // IST indices are 1-based in hardware but 0-based in our array
-        let tss_ptr = core::ptr::addr_of_mut!(TSS);
-        (*tss_ptr).interrupt_stack_table[0] = stack_end;
-
-        // Set IOMAP base to the size of TSS (no I/O permission bitmap)
-        (*tss_ptr).iomap_base = mem::size_of::<TaskStateSegment>() as u16;
+        // The I/O permission bitmap follows the TSS
+        let tss_ptr = core::ptr::addr_of_mut!(TSS.tss);
+        (*tss_ptr).iomap_base = mem::offset_of!(TssWithIopb, iopb) as u16;
+    }
+}
+
+/// Turns the page below an IST stack into a guard page
+fn map_stack_guard(stack: *mut IstStackStorage) {
+    // SAFETY: only the address is taken, nothing is accessed
+    let guard = VirtAddr::from_ptr(unsafe { core::ptr::addr_of!((*stack).guard) });
+    let page = Page::containing_address(guard);
+
+    // SAFETY: CR3 holds the active, identity-accessible P4 table
+    let mut mapper = unsafe { PageTableManager::current() };
+    if let Err(e) = mapper.map_guard_page(page, &mut HeapFrameAllocator::new()) {
+        crate::log_warn!(
+            "IST stack at {:#x} has no guard page: {}",
+            guard.as_u64(),
+            e
+        );
     }
 }
 
+/// Sets the stack the CPU switches to when an interrupt or exception
+/// arrives in ring 3 (`privilege_stack_table[0]`)
+pub fn set_privilege_stack(top: u64) {
+    // SAFETY: the CPU only reads the field on a switch from ring 3, which
+    // cannot happen while ring 0 code runs
+    unsafe {
+        let tss_ptr = core::ptr::addr_of_mut!(TSS.tss);
+        (*tss_ptr).privilege_stack_table[0] = top;
+    }
+}
+
+/// Allows or denies ring 3 access to the I/O port `port`
+///
+/// Every port is denied until allowed here. The change applies to all
+/// processes, since there is only one TSS.
+pub fn set_io_permission(port: u16, allowed: bool) {
+    // SAFETY: the CPU only reads the bitmap for I/O instructions in ring 3,
+    // which cannot run while ring 0 code runs
+    unsafe {
+        (*core::ptr::addr_of_mut!(TSS)).set_io_permission(port, allowed);
+    }
+}
+
+/// Returns whether ring 3 may access the I/O port `port`
+pub fn io_permission(port: u16) -> bool {
+    // SAFETY: the bitmap is only written by `set_io_permission`
+    unsafe { (*core::ptr::addr_of!(TSS)).io_permission(port) }
+}
+
 /// Returns a reference to the static TSS
 ///
 /// # Safety
 ///
 /// This function is unsafe because it returns a reference to a static mutable
 /// variable.
-pub unsafe fn get_tss() -> &'static TaskStateSegment {
+pub unsafe fn get_tss() -> &'static TssWithIopb {
     &*core::ptr::addr_of!(TSS)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COM1: u16 = 0x3f8;
+
+    #[test_case]
+    fn test_allowing_port_clears_its_bit() {
+        assert!(!io_permission(COM1));
+        set_io_permission(COM1, true);
+
+        // SAFETY: only read while no other test changes the bitmap
+        let tss = unsafe { get_tss() };
+        assert_eq!(tss.iopb()[usize::from(COM1) / 8] & (1 << (COM1 % 8)), 0);
+        assert!(io_permission(COM1));
+        assert!(!io_permission(COM1 + 1));
+
+        set_io_permission(COM1, false);
+        assert!(!io_permission(COM1));
+        assert_eq!(tss.iopb()[usize::from(COM1) / 8], 0xff);
+    }
+
+    #[test_case]
+    fn test_ist_stacks_are_separate() {
+        // SAFETY: the IST is only written by `init`
+        let tss = unsafe { get_tss() };
+        let ist = tss.tss.interrupt_stack_table;
+        let double_fault = ist[usize::from(DOUBLE_FAULT_IST_INDEX - 1)];
+        let stack_fault = ist[usize::from(STACK_FAULT_IST_INDEX - 1)];
+        assert_ne!(double_fault, 0);
+        assert_ne!(stack_fault, 0);
+        assert!(double_fault.abs_diff(stack_fault) >= IST_STACK_SIZE as u64);
+        assert!(double_fault.is_multiple_of(16) && stack_fault.is_multiple_of(16));
+    }
+
+    #[test_case]
+    fn test_iopb_layout() {
+        // SAFETY: only read while no other test changes the bitmap
+        let tss = unsafe { get_tss() };
+        let iomap_base = tss.tss.iomap_base;
+        assert_eq!(usize::from(iomap_base), mem::size_of::<TaskStateSegment>());
+        assert_eq!(
+            mem::size_of::<TssWithIopb>(),
+            mem::size_of::<TaskStateSegment>() + IOPB_SIZE + 1
+        );
+        assert_eq!(tss.iopb()[IOPB_SIZE], 0xff);
+    }
+}
@@ -4,7 +4,11 @@
 //! - Log levels (DEBUG, INFO, WARN, ERROR, FATAL)
 //! - Timestamps (seconds.milliseconds format)
 //! - ANSI color coding for different levels
-//! - Log level filtering
+//! - Log level filtering, globally and per module
+//! - Pluggable sinks, for ANSI text and JSON lines on serial ports
+//! - Optional mirroring of the ANSI output to a second serial port
+//! - A ring buffer of recent messages that can be replayed after boot
+//! - A queue that defers writing messages out to the idle task
 //!
 //! # Examples
 //!
@@ -20,11 +24,28 @@
 use core::{
     fmt,
     sync::atomic::{
+        AtomicBool,
         AtomicU8,
+        AtomicU64,
         Ordering,
     },
 };
 
+use spin::Mutex;
+
+use crate::{
+    serial::{
+        self,
+        ComPort,
+        SerialError,
+        SerialPort,
+    },
+    sync::{
+        DetectMutex,
+        KernelRwLock,
+    },
+};
+
 /// Log level enumeration
 ///
 /// Log levels are ordered by severity:
@@ -110,16 +131,595 @@ pub fn get_log_level() -> LogLevel {
     }
 }
 
+/// Maximum number of per-module log levels
+pub const MAX_MODULE_FILTERS: usize = 16;
+
+/// Per-module log levels
+///
+/// Modules are identified by their path below the crate root, e.g.
+/// `"interrupts::timer"`. A tag also covers the modules nested in it; if
+/// several tags match, the longest one applies.
+#[derive(Debug, Clone, Copy)]
+pub struct LogFilter {
+    entries: [Option<(&'static str, LogLevel)>; MAX_MODULE_FILTERS],
+}
+
+impl LogFilter {
+    /// Creates a filter without module levels
+    pub const fn new() -> Self {
+        Self {
+            entries: [None; MAX_MODULE_FILTERS],
+        }
+    }
+
+    /// Sets the level of `module`
+    ///
+    /// # Returns
+    ///
+    /// `false` if all `MAX_MODULE_FILTERS` slots hold other modules
+    pub fn set(&mut self, module: &'static str, level: LogLevel) -> bool {
+        let slot = match self
+            .entries
+            .iter()
+            .position(|entry| matches!(entry, Some((tag, _)) if *tag == module))
+        {
+            Some(index) => index,
+            None => match self.entries.iter().position(Option::is_none) {
+                Some(index) => index,
+                None => return false,
+            },
+        };
+        self.entries[slot] = Some((module, level));
+        true
+    }
+
+    /// Removes the level of `module`, so the global level applies again
+    pub fn remove(&mut self, module: &str) {
+        for entry in &mut self.entries {
+            if matches!(entry, Some((tag, _)) if *tag == module) {
+                *entry = None;
+            }
+        }
+    }
+
+    /// Looks up the level for a module
+    ///
+    /// # Arguments
+    ///
+    /// * `module_path` - Full module path as given by `module_path!()`,
+    ///   including the crate name
+    ///
+    /// # Returns
+    ///
+    /// The level of the longest matching tag, or `None` if no tag matches
+    pub fn level_for(&self, module_path: &str) -> Option<LogLevel> {
+        // Tags are relative to the crate root
+        let path = module_path.split_once("::").map_or("", |(_, path)| path);
+        self.entries
+            .iter()
+            .flatten()
+            .filter(|(tag, _)| {
+                path.strip_prefix(tag)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(tag, _)| tag.len())
+            .map(|&(_, level)| level)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-module log levels, consulted before `LOG_LEVEL`
+static LOG_FILTER: Mutex<LogFilter> = Mutex::new(LogFilter::new());
+
+/// Sets the minimum log level of a module, overriding the global level
+///
+/// # Arguments
+///
+/// * `module` - Module path below the crate root, e.g. `"interrupts::timer"`;
+///   nested modules are covered as well
+/// * `level` - Minimum level logged from the module
+///
+/// # Examples
+///
+/// ```
+/// set_module_log_level("interrupts::timer", LogLevel::WARN);
+/// set_module_log_level("memory", LogLevel::DEBUG);
+/// ```
+pub fn set_module_log_level(module: &'static str, level: LogLevel) {
+    let stored = crate::interrupts::without_interrupts(|| LOG_FILTER.lock().set(module, level));
+    if !stored {
+        crate::log_warn!("Log filter full, cannot set level of module {}", module);
+    }
+}
+
+/// Removes the log level of a module, so the global level applies again
+pub fn clear_module_log_level(module: &str) {
+    crate::interrupts::without_interrupts(|| LOG_FILTER.lock().remove(module));
+}
+
+/// Returns `true` if a message of `level` from `module_path` is logged
+///
+/// The level of a matching module filter takes precedence over the global
+/// level.
+pub fn is_enabled(module_path: &str, level: LogLevel) -> bool {
+    let module_level =
+        crate::interrupts::without_interrupts(|| LOG_FILTER.lock().level_for(module_path));
+    level >= module_level.unwrap_or_else(get_log_level)
+}
+
+/// Number of entries kept in `LOG_BUFFER`
+pub const LOG_BUFFER_CAPACITY: usize = 4096;
+
+/// Bytes of message text kept per entry; longer messages are truncated
+///
+/// Only buffered and queued messages are truncated; messages written to the
+/// sinks directly are passed on whole.
+pub const LOG_MESSAGE_LEN: usize = 128;
+
+/// A logged message, as kept in the log ring buffer
+#[derive(Debug, Clone, Copy)]
+pub struct LogEntry {
+    /// Log level of the message
+    pub level: LogLevel,
+    /// Uptime at which the message was logged
+    pub timestamp_ms: u64,
+    message: [u8; LOG_MESSAGE_LEN],
+    message_len: usize,
+}
+
+impl LogEntry {
+    /// Placeholder for unused ring buffer slots
+    const EMPTY: Self = Self {
+        level: LogLevel::DEBUG,
+        timestamp_ms: 0,
+        message: [0; LOG_MESSAGE_LEN],
+        message_len: 0,
+    };
+
+    /// Formats a message into a new entry
+    ///
+    /// The message is truncated to `LOG_MESSAGE_LEN` bytes, at a character
+    /// boundary.
+    pub fn new(level: LogLevel, timestamp_ms: u64, args: fmt::Arguments) -> Self {
+        let mut entry = Self {
+            level,
+            timestamp_ms,
+            ..Self::EMPTY
+        };
+        let _ = fmt::Write::write_fmt(&mut entry, args);
+        entry
+    }
+
+    /// Returns the (possibly truncated) message text
+    pub fn message(&self) -> &str {
+        // Only whole characters are ever copied in
+        core::str::from_utf8(&self.message[..self.message_len]).unwrap_or_default()
+    }
+
+    /// Calls `f` with the entry as a `LogRecord`, e.g. to write it to a sink
+    pub fn with_record<R>(&self, f: impl FnOnce(&LogRecord) -> R) -> R {
+        f(&LogRecord {
+            level: self.level,
+            timestamp_ms: self.timestamp_ms,
+            message: format_args!("{}", self.message()),
+        })
+    }
+}
+
+impl fmt::Write for LogEntry {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let end = self.message_len + c.len_utf8();
+            if end > LOG_MESSAGE_LEN {
+                break;
+            }
+            c.encode_utf8(&mut self.message[self.message_len..end]);
+            self.message_len = end;
+        }
+        Ok(())
+    }
+}
+
+/// Circular buffer of the most recent `N` log entries
+///
+/// Entries are numbered in logging order; once the buffer is full, each
+/// new entry overwrites the oldest one.
+pub struct LogRingBuffer<const N: usize = LOG_BUFFER_CAPACITY> {
+    entries: [LogEntry; N],
+    /// Number of entries ever pushed, which is the number of the next one
+    next_seq: u64,
+}
+
+impl<const N: usize> LogRingBuffer<N> {
+    /// Creates an empty buffer
+    pub const fn new() -> Self {
+        Self {
+            entries: [LogEntry::EMPTY; N],
+            next_seq: 0,
+        }
+    }
+
+    /// Appends an entry, overwriting the oldest one if the buffer is full
+    pub fn push(&mut self, entry: LogEntry) {
+        self.entries[(self.next_seq % N as u64) as usize] = entry;
+        self.next_seq += 1;
+    }
+
+    /// Returns the number of buffered entries
+    pub fn len(&self) -> usize {
+        self.next_seq.min(N as u64) as usize
+    }
+
+    /// Returns `true` if no entries are buffered
+    pub fn is_empty(&self) -> bool {
+        self.next_seq == 0
+    }
+
+    /// Returns the number of the oldest buffered entry
+    pub fn first_seq(&self) -> u64 {
+        self.next_seq - self.len() as u64
+    }
+
+    /// Returns the number the next entry will get
+    pub fn end_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Returns entry number `seq`, or `None` if it was overwritten or not
+    /// logged yet
+    pub fn get(&self, seq: u64) -> Option<&LogEntry> {
+        (self.first_seq()..self.next_seq)
+            .contains(&seq)
+            .then(|| &self.entries[(seq % N as u64) as usize])
+    }
+
+    /// Iterates over the buffered entries, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &LogEntry> {
+        (self.first_seq()..self.next_seq).filter_map(|seq| self.get(seq))
+    }
+}
+
+impl<const N: usize> Default for LogRingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Recent log messages, filled by `log`
+pub static LOG_BUFFER: KernelRwLock<LogRingBuffer> = KernelRwLock::new(LogRingBuffer::new());
+
+/// Iterates over the buffered log messages, oldest first
+///
+/// Entries are copied out one at a time, so messages can be logged while
+/// iterating. Iteration stops at the last message logged before the call;
+/// entries overwritten in the meantime are skipped.
+pub fn iter_log() -> impl Iterator<Item = LogEntry> {
+    let (mut seq, end) = crate::interrupts::without_interrupts(|| {
+        let buffer = LOG_BUFFER.read();
+        (buffer.first_seq(), buffer.end_seq())
+    });
+    core::iter::from_fn(move || {
+        crate::interrupts::without_interrupts(|| {
+            let buffer = LOG_BUFFER.read();
+            seq = seq.max(buffer.first_seq());
+            if seq >= end {
+                return None;
+            }
+            let entry = buffer.get(seq).copied();
+            seq += 1;
+            entry
+        })
+    })
+}
+
+/// Number of entries `LOG_QUEUE` holds before dropping messages
+pub const LOG_QUEUE_CAPACITY: usize = 256;
+
+/// Fixed-size FIFO of log entries waiting to be written to the sinks
+pub struct LogQueue<const N: usize = LOG_QUEUE_CAPACITY> {
+    entries: [LogEntry; N],
+    /// Index of the oldest entry
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> LogQueue<N> {
+    /// Creates an empty queue
+    pub const fn new() -> Self {
+        Self {
+            entries: [LogEntry::EMPTY; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends an entry
+    ///
+    /// # Errors
+    ///
+    /// Returns the entry back if the queue is full.
+    pub fn push(&mut self, entry: LogEntry) -> Result<(), LogEntry> {
+        if self.len == N {
+            return Err(entry);
+        }
+        self.entries[(self.head + self.len) % N] = entry;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the oldest entry
+    pub fn pop(&mut self) -> Option<LogEntry> {
+        if self.len == 0 {
+            return None;
+        }
+        let entry = self.entries[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(entry)
+    }
+
+    /// Number of queued entries
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no entries are queued
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drops all queued entries
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for LogQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Messages logged but not yet written to the sinks
+pub static LOG_QUEUE: Mutex<LogQueue> = Mutex::new(LogQueue::new());
+
+/// Number of messages dropped because `LOG_QUEUE` was full
+///
+/// Dropped messages are still recorded in `LOG_BUFFER`.
+pub static LOG_DROPS: AtomicU64 = AtomicU64::new(0);
+
+/// Whether `log` queues messages instead of writing them out directly
+static LOG_QUEUE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Switches `log` from writing to the sinks directly to queueing messages
+///
+/// Until this is called, e.g. during early boot, every message is written
+/// out before `log` returns. Afterwards something must call
+/// `flush_log_queue` regularly; the idle task does.
+pub fn enable_log_queue() {
+    LOG_QUEUE_ENABLED.store(true, Ordering::Release);
+}
+
+/// Writes the queued messages to the sinks, oldest first
+///
+/// Takes the queue lock once per entry, so sinks write with the lock
+/// released and interrupt handlers can keep logging.
+pub fn flush_log_queue() {
+    while let Some(entry) = crate::interrupts::without_interrupts(|| LOG_QUEUE.lock().pop()) {
+        entry.with_record(write_to_sinks);
+    }
+}
+
+/// Maximum number of registered log sinks
+pub const MAX_LOG_SINKS: usize = 4;
+
+/// A logged message, as written to the sinks
+///
+/// Unlike `LogEntry`, the message is not stored, so it is never truncated.
+#[derive(Debug, Clone, Copy)]
+pub struct LogRecord<'a> {
+    /// Log level of the message
+    pub level: LogLevel,
+    /// Uptime at which the message was logged
+    pub timestamp_ms: u64,
+    /// The message text
+    pub message: fmt::Arguments<'a>,
+}
+
+/// Destination of log messages
+///
+/// Sinks are shared through `&'static` references, so they synchronize
+/// their output themselves, e.g. through the lock of their serial port.
+pub trait LogSink: Sync {
+    /// Writes one log message
+    fn write(&self, record: &LogRecord);
+}
+
+/// Sink writing human-readable lines with ANSI colored levels
+///
+/// Lines look like `[1.234] [ INFO] message`.
+pub struct AnsiSerialSink {
+    serial: &'static DetectMutex<SerialPort>,
+}
+
+impl AnsiSerialSink {
+    /// Creates a sink writing to `serial`
+    pub const fn new(serial: &'static DetectMutex<SerialPort>) -> Self {
+        Self { serial }
+    }
+
+    /// Formats `record` as one ANSI log line
+    pub fn format(out: &mut impl fmt::Write, record: &LogRecord) -> fmt::Result {
+        let secs = record.timestamp_ms / 1000;
+        let ms = record.timestamp_ms % 1000;
+        let level = record.level;
+
+        // Write timestamp
+        write!(out, "[{}.{:03}] ", secs, ms)?;
+
+        // Write log level with color
+        write!(out, "{}[{}]\x1b[0m ", level.color_code(), level.as_str())?;
+
+        // Write message and newline
+        writeln!(out, "{}", record.message)
+    }
+}
+
+impl LogSink for AnsiSerialSink {
+    fn write(&self, record: &LogRecord) {
+        let _ = Self::format(&mut *self.serial.lock(), record);
+    }
+}
+
+/// Sink writing one JSON object per line
+///
+/// Lines look like `{"ts":1234,"level":"INFO","msg":"message"}`, with the
+/// uptime in milliseconds as `ts`.
+pub struct JsonSerialSink {
+    serial: &'static DetectMutex<SerialPort>,
+}
+
+impl JsonSerialSink {
+    /// Creates a sink writing to `serial`
+    pub const fn new(serial: &'static DetectMutex<SerialPort>) -> Self {
+        Self { serial }
+    }
+
+    /// Formats `record` as one line of JSON
+    pub fn format(out: &mut impl fmt::Write, record: &LogRecord) -> fmt::Result {
+        write!(
+            out,
+            "{{\"ts\":{},\"level\":\"{}\",\"msg\":\"",
+            record.timestamp_ms,
+            record.level.as_str().trim_start()
+        )?;
+        fmt::Write::write_fmt(&mut JsonEscape(out), record.message)?;
+        out.write_str("\"}\n")
+    }
+}
+
+impl LogSink for JsonSerialSink {
+    fn write(&self, record: &LogRecord) {
+        let _ = Self::format(&mut *self.serial.lock(), record);
+    }
+}
+
+/// Writer escaping text for a JSON string
+struct JsonEscape<'a, W: fmt::Write>(&'a mut W);
+
+impl<W: fmt::Write> fmt::Write for JsonEscape<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '"' => self.0.write_str("\\\"")?,
+                '\\' => self.0.write_str("\\\\")?,
+                '\n' => self.0.write_str("\\n")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\t' => self.0.write_str("\\t")?,
+                c if c.is_control() => write!(self.0, "\\u{:04x}", u32::from(c))?,
+                c => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Human-readable log output on COM1
+pub static COM1_ANSI_SINK: AnsiSerialSink = AnsiSerialSink::new(&serial::SERIAL1);
+
+/// Machine-readable log output on COM3
+///
+/// Only register this sink once `serial::open(ComPort::Com3)` succeeded.
+/// COM2 is left to `set_mirror_port`.
+pub static COM3_JSON_SINK: JsonSerialSink = JsonSerialSink::new(&serial::SERIAL3);
+
+/// Port log messages are mirrored to, as its `ComPort` index plus one
+///
+/// Zero disables mirroring.
+static MIRROR_PORT: AtomicU8 = AtomicU8::new(0);
+
+/// Mirrors log messages to a second serial port
+///
+/// Messages go to the registered sinks as usual; with a mirror port set
+/// they are also written there in the ANSI format, e.g. to COM2 for a QEMU
+/// `virtio-serial` log channel.
+///
+/// # Arguments
+///
+/// * `port` - Port to mirror to, or `None` to stop mirroring. COM1 is accepted
+///   but not written twice.
+///
+/// # Errors
+///
+/// Returns `SerialError::LoopbackFailed` if the port could not be opened;
+/// the previous mirror setting is kept.
+pub fn set_mirror_port(port: Option<ComPort>) -> Result<(), SerialError> {
+    if let Some(port) = port {
+        serial::open(port)?;
+    }
+    MIRROR_PORT.store(port.map_or(0, |port| port as u8 + 1), Ordering::Relaxed);
+    Ok(())
+}
+
+/// Gets the port log messages are mirrored to
+///
+/// # Returns
+///
+/// The mirror port, or `None` if mirroring is disabled
+pub fn mirror_port() -> Option<ComPort> {
+    match MIRROR_PORT.load(Ordering::Relaxed) {
+        0 => None,
+        index => ComPort::ALL.get(usize::from(index) - 1).copied(),
+    }
+}
+
+/// Sinks every logged message is written to
+static LOG_SINKS: Mutex<[Option<&'static dyn LogSink>; MAX_LOG_SINKS]> =
+    Mutex::new([None; MAX_LOG_SINKS]);
+
+/// Adds a sink that receives every subsequently logged message
+///
+/// Messages logged before are available through `iter_log`.
+///
+/// # Returns
+///
+/// `false` if all `MAX_LOG_SINKS` slots are taken
+pub fn register_sink(sink: &'static dyn LogSink) -> bool {
+    crate::interrupts::without_interrupts(|| {
+        let mut sinks = LOG_SINKS.lock();
+        match sinks.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(sink);
+                true
+            }
+            None => false,
+        }
+    })
+}
+
 /// Logs a message with the specified log level
 ///
 /// This function is the core logging implementation. It:
-/// 1. Checks if the message should be logged based on the current log level
-/// 2. Formats the timestamp
-/// 3. Adds ANSI color codes
-/// 4. Writes to the serial port
+/// 1. Checks if the message should be logged based on the module's log level,
+///    or the global one if the module has none
+/// 2. Timestamps the message and records it in `LOG_BUFFER`
+/// 3. Queues it in `LOG_QUEUE` for `flush_log_queue` to write to every
+///    registered `LogSink`, or writes it out directly until `enable_log_queue`
+///    is called
+///
+/// Buffered and queued messages are truncated to `LOG_MESSAGE_LEN` bytes;
+/// messages written out directly are not. If the queue is full the message
+/// is not written out and `LOG_DROPS` is incremented.
 ///
 /// # Arguments
 ///
+/// * `module` - Path of the logging module, as given by `module_path!()`
 /// * `level` - The log level for this message
 /// * `args` - The formatted message arguments
 ///
@@ -131,39 +731,60 @@ pub fn get_log_level() -> LogLevel {
 ///     log,
 /// };
 ///
-/// log(LogLevel::INFO, format_args!("System initialized"));
-/// log(LogLevel::ERROR, format_args!("Error code: {}", error_code));
+/// log(
+///     module_path!(),
+///     LogLevel::INFO,
+///     format_args!("System initialized"),
+/// );
+/// log(
+///     module_path!(),
+///     LogLevel::ERROR,
+///     format_args!("Error code: {}", error_code),
+/// );
 /// ```
-pub fn log(level: LogLevel, args: fmt::Arguments) {
-    // Filter out messages below the current log level
-    if level < get_log_level() {
+pub fn log(module: &str, level: LogLevel, args: fmt::Arguments) {
+    // Filter out messages below the module's or the global log level
+    if !is_enabled(module, level) {
         return;
     }
 
-    use core::fmt::Write;
-
     // Disable interrupts while logging to avoid race conditions
-    crate::interrupts::without_interrupts(|| {
+    let queued = crate::interrupts::try_without_interrupts(|| {
         // Get system uptime for timestamp
         let uptime_ms = crate::interrupts::timer::uptime_ms();
-        let secs = uptime_ms / 1000;
-        let ms = uptime_ms % 1000;
-
-        // Acquire serial port lock
-        let mut serial = crate::serial::SERIAL1.lock();
+        let entry = LogEntry::new(level, uptime_ms, args);
 
-        // Write timestamp
-        let _ = write!(serial, "[{}.{:03}] ", secs, ms);
+        LOG_BUFFER.write().push(entry);
 
-        // Write log level with color
-        let _ = write!(serial, "{}[{}]\x1b[0m ", level.color_code(), level.as_str());
+        if !LOG_QUEUE_ENABLED.load(Ordering::Acquire) {
+            write_to_sinks(&LogRecord {
+                level,
+                timestamp_ms: uptime_ms,
+                message: args,
+            });
+            return Ok(());
+        }
+        LOG_QUEUE.lock().push(entry)
+    });
+    if queued.is_err() {
+        LOG_DROPS.fetch_add(1, Ordering::Relaxed);
+    }
+}
 
-        // Write message
-        let _ = serial.write_fmt(args);
+/// Writes `record` to every registered `LogSink` and the mirror port
+fn write_to_sinks(record: &LogRecord) {
+    // Copied out, so sinks may register further sinks
+    let sinks = crate::interrupts::without_interrupts(|| *LOG_SINKS.lock());
+    for sink in sinks.iter().flatten() {
+        sink.write(record);
+    }
 
-        // Write newline
-        let _ = writeln!(serial);
-    });
+    if let Some(port) = mirror_port().filter(|&port| port != ComPort::Com1) {
+        // The mirror port was opened by `set_mirror_port`
+        if let Ok(mirror) = serial::open(port) {
+            let _ = AnsiSerialSink::format(&mut *mirror.lock(), record);
+        }
+    }
 }
 
 /// Macro for logging debug messages
@@ -180,6 +801,7 @@ pub fn log(level: LogLevel, args: fmt::Arguments) {
 macro_rules! log_debug {
     ($($arg:tt)*) => {
         $crate::io::logging::log(
+            module_path!(),
             $crate::io::logging::LogLevel::DEBUG,
             format_args!($($arg)*)
         )
@@ -198,6 +820,7 @@ macro_rules! log_debug {
 macro_rules! log_info {
     ($($arg:tt)*) => {
         $crate::io::logging::log(
+            module_path!(),
             $crate::io::logging::LogLevel::INFO,
             format_args!($($arg)*)
         )
@@ -216,6 +839,7 @@ macro_rules! log_info {
 macro_rules! log_warn {
     ($($arg:tt)*) => {
         $crate::io::logging::log(
+            module_path!(),
             $crate::io::logging::LogLevel::WARN,
             format_args!($($arg)*)
         )
@@ -234,6 +858,7 @@ macro_rules! log_warn {
 macro_rules! log_error {
     ($($arg:tt)*) => {
         $crate::io::logging::log(
+            module_path!(),
             $crate::io::logging::LogLevel::ERROR,
             format_args!($($arg)*)
         )
@@ -254,6 +879,7 @@ macro_rules! log_error {
 macro_rules! log_fatal {
     ($($arg:tt)*) => {
         $crate::io::logging::log(
+            module_path!(),
             $crate::io::logging::LogLevel::FATAL,
             format_args!($($arg)*)
         )
@@ -310,3 +936,212 @@ macro_rules! println {
     ($fmt:expr) => ($crate::print!(concat!($fmt, "\n")));
     ($fmt:expr, $($arg:tt)*) => ($crate::print!(concat!($fmt, "\n"), $($arg)*));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_ring_buffer_overwrites_oldest() {
+        let mut buffer = LogRingBuffer::<4>::new();
+        for i in 0..6u64 {
+            buffer.push(LogEntry::new(
+                LogLevel::INFO,
+                i,
+                format_args!("entry {}", i),
+            ));
+        }
+
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.first_seq(), 2);
+        assert!(buffer.get(1).is_none());
+        let timestamps: alloc::vec::Vec<u64> = buffer.iter().map(|e| e.timestamp_ms).collect();
+        assert_eq!(timestamps, [2, 3, 4, 5]);
+        assert_eq!(buffer.iter().next().unwrap().message(), "entry 2");
+    }
+
+    #[test_case]
+    fn test_log_queue_is_fifo() {
+        let mut queue = LogQueue::<2>::new();
+        for i in 0..2 {
+            queue
+                .push(LogEntry::new(LogLevel::INFO, i, format_args!("{}", i)))
+                .unwrap();
+        }
+        let overflow = LogEntry::new(LogLevel::INFO, 2, format_args!("2"));
+        assert!(queue.push(overflow).is_err());
+
+        assert_eq!(queue.pop().unwrap().timestamp_ms, 0);
+        queue.push(overflow).unwrap();
+        assert_eq!(queue.pop().unwrap().timestamp_ms, 1);
+        assert_eq!(queue.pop().unwrap().timestamp_ms, 2);
+        assert!(queue.pop().is_none());
+    }
+
+    #[test_case]
+    fn test_full_log_queue_drops_messages() {
+        let was_enabled = LOG_QUEUE_ENABLED.swap(true, Ordering::AcqRel);
+        crate::interrupts::without_interrupts(|| LOG_QUEUE.lock().clear());
+        let drops = LOG_DROPS.load(Ordering::Relaxed);
+
+        for i in 0..300 {
+            log(module_path!(), LogLevel::FATAL, format_args!("flood {}", i));
+        }
+
+        let dropped = LOG_DROPS.load(Ordering::Relaxed) - drops;
+        let queued = crate::interrupts::without_interrupts(|| LOG_QUEUE.lock().len());
+        // Discard the flood instead of writing it out
+        crate::interrupts::without_interrupts(|| LOG_QUEUE.lock().clear());
+        LOG_QUEUE_ENABLED.store(was_enabled, Ordering::Release);
+
+        assert!(dropped >= 44);
+        assert_eq!(queued, LOG_QUEUE_CAPACITY);
+    }
+
+    #[test_case]
+    fn test_entry_message_is_truncated() {
+        let long = [b'x'; LOG_MESSAGE_LEN + 10];
+        let long = core::str::from_utf8(&long).unwrap();
+        let entry = LogEntry::new(LogLevel::WARN, 0, format_args!("{}", long));
+        assert_eq!(entry.message().len(), LOG_MESSAGE_LEN);
+
+        // A character straddling the limit is dropped whole
+        let entry = LogEntry::new(
+            LogLevel::WARN,
+            0,
+            format_args!("{}é", &long[1..LOG_MESSAGE_LEN]),
+        );
+        assert_eq!(entry.message().len(), LOG_MESSAGE_LEN - 1);
+    }
+
+    #[test_case]
+    fn test_log_is_recorded() {
+        log(
+            module_path!(),
+            LogLevel::INFO,
+            format_args!("ring buffer test marker"),
+        );
+        assert!(iter_log().any(|entry| entry.message() == "ring buffer test marker"));
+    }
+
+    #[test_case]
+    fn test_module_filter_matches_path_prefix() {
+        let mut filter = LogFilter::new();
+        assert!(filter.set("memory", LogLevel::DEBUG));
+        assert!(filter.set("memory::heap", LogLevel::ERROR));
+
+        assert_eq!(
+            filter.level_for("yomi_kernel::memory"),
+            Some(LogLevel::DEBUG)
+        );
+        assert_eq!(
+            filter.level_for("yomi_kernel::memory::frame"),
+            Some(LogLevel::DEBUG)
+        );
+        assert_eq!(
+            filter.level_for("yomi_kernel::memory::heap"),
+            Some(LogLevel::ERROR)
+        );
+        assert_eq!(filter.level_for("yomi_kernel::memoryless"), None);
+
+        filter.remove("memory::heap");
+        assert_eq!(
+            filter.level_for("yomi_kernel::memory::heap"),
+            Some(LogLevel::DEBUG)
+        );
+    }
+
+    #[test_case]
+    fn test_module_log_level_overrides_global() {
+        const TIMER: &str = "yomi_kernel::interrupts::timer";
+        set_module_log_level("interrupts::timer", LogLevel::WARN);
+
+        log(TIMER, LogLevel::DEBUG, format_args!("filtered timer debug"));
+        log(
+            TIMER,
+            LogLevel::WARN,
+            format_args!("unfiltered timer warning"),
+        );
+        assert!(!is_enabled(TIMER, LogLevel::DEBUG));
+        assert!(is_enabled(TIMER, LogLevel::WARN));
+        assert!(!iter_log().any(|entry| entry.message() == "filtered timer debug"));
+        assert!(iter_log().any(|entry| entry.message() == "unfiltered timer warning"));
+
+        // Other modules still use the global level
+        assert!(is_enabled("yomi_kernel::memory", LogLevel::DEBUG));
+
+        clear_module_log_level("interrupts::timer");
+        assert!(is_enabled(TIMER, LogLevel::DEBUG));
+    }
+
+    #[test_case]
+    fn test_json_sink_format() {
+        let entry = LogEntry::new(LogLevel::INFO, 1234, format_args!("say \"hi\"\tto C:\\\n"));
+        let mut out = alloc::string::String::new();
+        entry
+            .with_record(|record| JsonSerialSink::format(&mut out, record))
+            .unwrap();
+        assert_eq!(
+            out,
+            "{\"ts\":1234,\"level\":\"INFO\",\"msg\":\"say \\\"hi\\\"\\tto C:\\\\\\n\"}\n"
+        );
+
+        let record = LogRecord {
+            level: LogLevel::ERROR,
+            timestamp_ms: 5,
+            message: format_args!("bell\x07"),
+        };
+        out.clear();
+        JsonSerialSink::format(&mut out, &record).unwrap();
+        assert_eq!(
+            out,
+            "{\"ts\":5,\"level\":\"ERROR\",\"msg\":\"bell\\u0007\"}\n"
+        );
+    }
+
+    #[test_case]
+    fn test_ansi_sink_format() {
+        let entry = LogEntry::new(LogLevel::WARN, 61_005, format_args!("low memory"));
+        let mut out = alloc::string::String::new();
+        entry
+            .with_record(|record| AnsiSerialSink::format(&mut out, record))
+            .unwrap();
+        assert_eq!(out, "[61.005] \x1b[33m[ WARN]\x1b[0m low memory\n");
+    }
+
+    #[test_case]
+    fn test_direct_writes_are_not_truncated() {
+        use core::sync::atomic::AtomicUsize;
+
+        /// Records the length of the longest message written to it
+        struct LengthSink(AtomicUsize);
+
+        impl LogSink for LengthSink {
+            fn write(&self, record: &LogRecord) {
+                struct Counter(usize);
+
+                impl fmt::Write for Counter {
+                    fn write_str(&mut self, s: &str) -> fmt::Result {
+                        self.0 += s.len();
+                        Ok(())
+                    }
+                }
+
+                let mut counter = Counter(0);
+                let _ = fmt::Write::write_fmt(&mut counter, record.message);
+                self.0.fetch_max(counter.0, Ordering::Relaxed);
+            }
+        }
+
+        static SINK: LengthSink = LengthSink(AtomicUsize::new(0));
+        assert!(register_sink(&SINK));
+
+        let was_enabled = LOG_QUEUE_ENABLED.swap(false, Ordering::AcqRel);
+        let long = [b'y'; LOG_MESSAGE_LEN * 2];
+        let long = core::str::from_utf8(&long).unwrap();
+        log(module_path!(), LogLevel::INFO, format_args!("{}", long));
+        LOG_QUEUE_ENABLED.store(was_enabled, Ordering::Release);
+
+        assert_eq!(SINK.0.load(Ordering::Relaxed), LOG_MESSAGE_LEN * 2);
+    }
+}
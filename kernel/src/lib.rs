@@ -24,14 +24,23 @@ extern crate alloc;
 use core::panic::PanicInfo;
 
 pub mod boot;
+pub mod cpu;
+pub mod debug;
+pub mod drivers;
+pub mod elf;
+pub mod error;
+pub mod fs;
 pub mod interrupts;
 pub mod io;
 pub mod memory;
 pub mod panic;
+pub mod process;
 pub mod serial;
+pub mod sync;
 pub mod testing;
 pub mod time;
 pub mod vga;
+pub mod wq;
 
 pub use boot::{
     MemoryRegion,
@@ -49,14 +58,74 @@ pub use memory::{
     VirtAddr,
 };
 
+/// Full kernel version string
+///
+/// Combines the crate version with the git commit hash and build timestamp
+/// captured by `build.rs`, e.g. `0.1.0-1a2b3c4 built 2025-01-01T00:00:00Z`.
+pub const KERNEL_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "-",
+    env!("KERNEL_GIT_HASH"),
+    " built ",
+    env!("KERNEL_BUILD_TIME")
+);
+
+/// Returns the full kernel version string
+pub fn kernel_version_string() -> &'static str {
+    KERNEL_VERSION
+}
+
+// Embed the version string in the kernel ELF as a note so external tools
+// (and `readelf -n`) can identify a kernel image without booting it.
+//
+// Layout follows the standard ELF note format: namesz, descsz, type, the
+// NUL-terminated owner name "YomiOS" and the NUL-terminated version string,
+// each padded to 4 bytes. Type 1 is the YomiOS build-id note.
+core::arch::global_asm!(
+    ".pushsection .note.yomios.build-id, \"a\", @note",
+    ".balign 4",
+    ".long 4f - 3f",
+    ".long 6f - 5f",
+    ".long 1",
+    "3: .asciz \"YomiOS\"",
+    "4: .balign 4",
+    concat!(
+        "5: .asciz \"",
+        env!("CARGO_PKG_VERSION"),
+        "-",
+        env!("KERNEL_GIT_HASH"),
+        " built ",
+        env!("KERNEL_BUILD_TIME"),
+        "\""
+    ),
+    "6: .balign 4",
+    ".popsection",
+);
+
 /// Kernel initialization function
 pub fn init() {
+    use boot::{
+        BootPhase,
+        set_boot_phase,
+    };
+
+    // SAFETY: the kernel is initialized only once
     unsafe {
         vga::init();
     }
+    set_boot_phase(BootPhase::PreSerial);
     serial::init();
+    io::logging::register_sink(&io::logging::COM1_ANSI_SINK);
+    set_boot_phase(BootPhase::SerialReady);
     memory::init_heap();
+    wq::init();
+    set_boot_phase(BootPhase::HeapReady);
+    time::init();
+    time::hpet::init(None);
     interrupts::init();
+    cpu::security::enable_smep_smap();
+    cpu::fpu::init();
+    set_boot_phase(BootPhase::IdtReady);
 }
 
 #[cfg(test)]
@@ -65,6 +134,7 @@ pub extern "C" fn _start() -> ! {
     init();
     test_main();
     loop {
+        // SAFETY: halting only waits for the next interrupt
         unsafe { core::arch::asm!("hlt") }
     }
 }
@@ -14,8 +14,6 @@
 
 #![no_std]
 #![no_main]
-#![feature(abi_x86_interrupt)]
-#![feature(alloc_error_handler)]
 
 extern crate alloc;
 
@@ -28,8 +26,16 @@ use alloc::{
 
 use interrupts::timer;
 use yomi_kernel::{
-    boot,
+    boot::{
+        self,
+        BootPhase,
+        set_boot_phase,
+    },
+    cpu,
+    fs,
     interrupts,
+    io,
+    kernel_version_string,
     log_debug,
     log_error,
     log_fatal,
@@ -37,11 +43,19 @@ use yomi_kernel::{
     log_warn,
     memory,
     printk,
+    process,
+    profile_section,
     serial,
     serial_println,
+    testing,
+    time::{
+        self,
+        profiler::BootProfiler,
+    },
     vga,
     // Import macros exported by the library
     vga_println,
+    wq,
 };
 
 /// Kernel entry point called from boot.asm
@@ -53,53 +67,201 @@ use yomi_kernel::{
 pub extern "C" fn kernel_main(magic: u32, info_addr: usize) -> ! {
     // Initialize VGA for early boot debugging
     // This must come first as it provides fallback output if serial fails
+    // SAFETY: the kernel entry point runs only once
     unsafe {
         vga::init();
     }
     vga_println!("YomiOS Boot");
-    vga::write_diagnostic("[BOOT]");
+    set_boot_phase(BootPhase::PreSerial);
 
-    // Initialize serial port for logging
-    serial::init();
+    // Initialize serial port for logging. Sections are timed with the TSC,
+    // which counts from reset; the durations are reported once it has
+    // been calibrated.
+    profile_section!("serial", {
+        serial::init();
+        io::logging::register_sink(&io::logging::COM1_ANSI_SINK);
+    });
 
-    // Test raw serial output immediately after init
-    serial_println!("=== YomiOS Serial Console Test ===");
+    // The version line is the first thing on the serial console so that
+    // xtask and log scrapers can identify the running build.
+    log_info!("YomiOS Kernel v{}", kernel_version_string());
     serial_println!("Serial port initialized successfully!");
 
-    log_info!("YomiOS Kernel v{}", env!("CARGO_PKG_VERSION"));
     log_debug!("Debug logging enabled");
-    vga::write_diagnostic("[SERIAL]");
+
+    // Mirror boot messages to COM2 when one is attached (QEMU virtio-serial)
+    match io::logging::set_mirror_port(Some(serial::ComPort::Com2)) {
+        Ok(()) => log_info!("Mirroring log output to COM2"),
+        Err(e) => log_debug!("COM2 not available for log mirroring: {}", e),
+    }
+
+    // Emit JSON log lines on COM3 when one is attached
+    match serial::open(serial::ComPort::Com3) {
+        Ok(_) => {
+            io::logging::register_sink(&io::logging::COM3_JSON_SINK);
+            log_info!("JSON log output on COM3");
+        }
+        Err(e) => log_debug!("COM3 not available for JSON logging: {}", e),
+    }
+    set_boot_phase(BootPhase::SerialReady);
 
     // Validate Multiboot2 boot
-    unsafe {
-        if let Some(mbi) = boot::multiboot2::Multiboot2Info::from_ptr(magic, info_addr) {
-            log_info!("Multiboot2 boot validated");
-            // Store multiboot info for later use (e.g., memory map parsing)
-            core::hint::black_box(mbi);
-        } else {
-            log_fatal!("Invalid Multiboot2 magic: 0x{:08x}", magic);
-            panic!("Invalid Multiboot2 boot (magic mismatch)");
+    // SAFETY: the bootloader passes the address of the boot information,
+    // which lies in identity-mapped memory.
+    let Some(mbi) = (unsafe { boot::multiboot2::Multiboot2Info::from_ptr(magic, info_addr) })
+    else {
+        log_fatal!("Invalid Multiboot2 magic: 0x{:08x}", magic);
+        panic!("Invalid Multiboot2 boot (magic mismatch)");
+    };
+    log_info!("Multiboot2 boot validated");
+    if let Some(cmdline) = mbi.boot_cmdline() {
+        log_info!("Boot command line: {}", cmdline);
+    }
+    if let Some(total) = mbi.total_memory() {
+        log_info!("Usable memory: {} KiB", total / 1024);
+    }
+    for module in mbi.modules() {
+        log_info!(
+            "Boot module '{}' at {:#x} ({} bytes)",
+            module.cmdline,
+            module.start.as_u64(),
+            module.size()
+        );
+    }
+    match mbi.acpi_rsdp() {
+        Some(rsdp) if rsdp.validate_checksum() => {
+            log_info!(
+                "ACPI revision {}: RSDT at {:#x}, XSDT at {:#x}",
+                rsdp.revision,
+                rsdp.rsdt_address,
+                rsdp.xsdt_address
+            );
+            boot::acpi::parse_rsdt(rsdp.root_table());
         }
+        Some(_) => log_warn!("ACPI RSDP has a bad checksum"),
+        None => log_warn!("No ACPI RSDP passed by the bootloader"),
     }
 
     // Initialize heap allocator
     log_info!("Initializing memory subsystem...");
-    memory::init_heap();
+    profile_section!("memory", {
+        memory::init_heap();
+        memory::phys_map::init(&mbi);
+        wq::init();
+    });
     log_info!("Memory subsystem initialized");
+    set_boot_phase(BootPhase::HeapReady);
+
+    // Calibrate the TSC against the PIT
+    profile_section!("tsc calibration", {
+        time::init();
+    });
+
+    // Look for the HPET; ACPI tables are not parsed yet, so only the
+    // address QEMU uses is tried
+    if time::hpet::init(None) {
+        log_info!("HPET enabled");
+    } else {
+        log_warn!("No HPET found, falling back to timer ticks");
+    }
+
+    // Anchor wall-clock time to the RTC
+    let rtc = time::rtc::read_rtc();
+    time::rtc::set_epoch_offset(rtc);
+    log_info!(
+        "RTC: {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        rtc.year,
+        rtc.month,
+        rtc.day,
+        rtc.hour,
+        rtc.minute,
+        rtc.second
+    );
 
     // Initialize Interrupt Descriptor Table
     log_info!("Initializing interrupt handlers...");
-    interrupts::init();
+    profile_section!("idt", {
+        interrupts::init();
+        cpu::security::enable_smep_smap();
+        cpu::fpu::init();
+    });
     log_info!("IDT initialized");
+    set_boot_phase(BootPhase::IdtReady);
 
     // Enable timer interrupts
     log_info!("Enabling timer interrupts...");
-    interrupts::enable_timer_interrupts();
+    profile_section!("timer", {
+        time::timer_wheel::init();
+        memory::heap::start_usage_reports();
+        timer::start_latency_reports();
+        interrupts::enable_timer_interrupts();
+    });
     log_info!("Timer interrupts enabled at {} Hz", timer::TIMER_FREQUENCY);
+    match interrupts::nmi::arm_watchdog(interrupts::nmi::DEFAULT_TIMEOUT_MS) {
+        Ok(()) => log_info!("NMI watchdog armed"),
+        Err(e) => log_warn!("NMI watchdog unavailable: {}", e),
+    }
+    set_boot_phase(BootPhase::TimerReady);
+
+    // Enable keyboard input (IRQ 1)
+    interrupts::keyboard::init();
+    log_info!("Keyboard enabled");
+
+    // The first userspace program passed by GRUB
+    let init_image = mbi
+        .modules()
+        .find(|module| module.cmdline == "init")
+        .map(|module| {
+            // SAFETY: boot modules stay identity-mapped and nothing has
+            // allocated over them; the heap is a static region.
+            unsafe { module.data() }
+        });
+
+    // Init is queued from here on but must not run before the filesystem
+    // is mounted, so it sets `UserSpaceReady` last
+    interrupts::without_interrupts(|| {
+        // Initialize process management (spawns the idle task as PID 1,
+        // loads init as PID 2 and adopts this thread)
+        log_info!("Initializing process management...");
+        match profile_section!("processes", { process::init(init_image) }) {
+            Some(Ok(pid)) => log_info!("Loaded init as PID {}", pid),
+            Some(Err(e)) => log_error!("Failed to load init: {}", e),
+            None => log_warn!("No init module found"),
+        }
+        set_boot_phase(BootPhase::ProcessesReady);
+
+        // Mount the in-memory root filesystem
+        fs::init();
+        {
+            let mut vfs = fs::VFS.lock();
+            vfs.create("/proc", fs::INodeKind::Dir)
+                .expect("failed to create /proc");
+            vfs.mount("/proc", Box::new(fs::ProcFs))
+                .expect("/proc already mounted");
+        }
+        log_info!("procfs mounted at /proc");
+        set_boot_phase(BootPhase::FileSystemReady);
+    });
+
+    let selftest = testing::run_selftest();
+    match selftest.name_of_first_failure {
+        Some(name) => log_fatal!(
+            "Boot self-test: {} of {} checks failed, first: {}",
+            selftest.failed,
+            selftest.passed + selftest.failed,
+            name
+        ),
+        None => log_info!("Boot self-test: {} checks passed", selftest.passed),
+    }
+
+    // The idle task flushes the log queue from now on, so logging no longer
+    // waits for the serial port
+    io::logging::enable_log_queue();
 
     // Test breakpoint exception
     // This should be caught by the breakpoint handler and return normally
     log_debug!("Testing breakpoint exception...");
+    // SAFETY: the breakpoint handler is installed and returns
     unsafe {
         core::arch::asm!("int3");
     }
@@ -125,14 +287,13 @@ pub extern "C" fn kernel_main(magic: u32, info_addr: usize) -> ! {
     core::hint::black_box(vec);
     core::hint::black_box(boxed);
 
-    log_info!("Entering idle loop...");
+    BootProfiler::report();
 
-    // Hang - timer interrupts will continue to fire
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
-    }
+    log_info!("Handing over to the scheduler...");
+
+    // The boot thread is done; the idle task takes over when nothing else
+    // is ready
+    process::exit(0)
 }
 
 // Panic handler is provided by the library (yomi_kernel::panic)
@@ -1,5 +1,25 @@
 #![allow(dead_code)]
 
+use crate::boot::MemoryRegion;
+
+/// Highest physical address x86_64 allows (MAXPHYADDR is at most 52 bits)
+const MAX_PHYS_ADDR: u64 = 0x000f_ffff_ffff_ffff;
+
+/// Start of the addresses user mode may use
+///
+/// The first P4 entry below it holds the kernel's identity map of physical
+/// memory, which every address space shares so that the kernel keeps
+/// running after a switch of CR3.
+pub const USER_SPACE_START: u64 = 1 << 39;
+
+/// End of the addresses user mode may use
+///
+/// User addresses lie entirely below it. It stops one page short of the
+/// kernel half: a `syscall` in the last bytes of that page would leave a
+/// non-canonical return address in RCX, and `sysretq` raises #GP for it in
+/// ring 0 while RSP still holds the user stack pointer.
+pub const USER_SPACE_END: u64 = 0x0000_7fff_ffff_f000;
+
 /// Physical address
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(transparent)]
@@ -11,6 +31,22 @@ impl PhysAddr {
         Self(addr)
     }
 
+    /// Create a physical address, clearing the top 12 bits
+    ///
+    /// x86_64 physical addresses have at most 52 bits; the bits above are
+    /// flags or garbage, such as in a CR3 value or a page table entry.
+    pub const fn from_u64_truncate(addr: u64) -> Self {
+        Self(addr & MAX_PHYS_ADDR)
+    }
+
+    /// Add `offset`, or `None` if the result does not fit in 52 bits
+    pub const fn checked_add(self, offset: u64) -> Option<Self> {
+        match self.0.checked_add(offset) {
+            Some(addr) if addr <= MAX_PHYS_ADDR => Some(Self(addr)),
+            _ => None,
+        }
+    }
+
     /// Get the address as u64
     pub const fn as_u64(self) -> u64 {
         self.0
@@ -36,7 +72,9 @@ impl core::ops::Add<u64> for PhysAddr {
     type Output = Self;
 
     fn add(self, rhs: u64) -> Self::Output {
-        Self(self.0 + rhs)
+        let addr = self.0 + rhs;
+        debug_assert!(addr <= MAX_PHYS_ADDR, "physical address overflow");
+        Self(addr)
     }
 }
 
@@ -62,11 +100,42 @@ impl VirtAddr {
         Self(canonical)
     }
 
+    /// Check that bits 48-63 of `addr` are copies of bit 47
+    const fn is_canonical(addr: u64) -> bool {
+        ((addr << 16) as i64 >> 16) as u64 == addr
+    }
+
+    /// Add `offset`, or `None` if the result overflows or is not canonical
+    ///
+    /// Unlike `+`, which sign-extends the sum, an address stepping from
+    /// the lower half into the non-canonical hole is an error.
+    pub const fn checked_add(self, offset: u64) -> Option<Self> {
+        match self.0.checked_add(offset) {
+            Some(addr) if Self::is_canonical(addr) => Some(Self(addr)),
+            _ => None,
+        }
+    }
+
+    /// Create a virtual address from a pointer
+    pub fn from_ptr<T: ?Sized>(ptr: *const T) -> Self {
+        Self::new(ptr as *const u8 as u64)
+    }
+
     /// Get the address as u64
     pub const fn as_u64(self) -> u64 {
         self.0
     }
 
+    /// Get the address as a raw pointer
+    pub fn as_ptr<T>(self) -> *const T {
+        self.0 as *const T
+    }
+
+    /// Get the address as a mutable raw pointer
+    pub fn as_mut_ptr<T>(self) -> *mut T {
+        self.0 as *mut T
+    }
+
     /// P4 table index (bits 39-47)
     pub const fn p4_index(self) -> usize {
         ((self.0 >> 39) & 0x1ff) as usize
@@ -92,6 +161,11 @@ impl VirtAddr {
         (self.0 & 0xfff) as usize
     }
 
+    /// Offset into a 2MB huge page (bits 0-20)
+    pub const fn p2_offset(self) -> usize {
+        (self.0 & 0x1f_ffff) as usize
+    }
+
     /// Check if the address is aligned to the given alignment
     pub fn is_aligned(self, align: u64) -> bool {
         self.0.is_multiple_of(align)
@@ -112,7 +186,9 @@ impl core::ops::Add<u64> for VirtAddr {
     type Output = Self;
 
     fn add(self, rhs: u64) -> Self::Output {
-        Self::new(self.0 + rhs)
+        let addr = self.0 + rhs;
+        debug_assert!(Self::is_canonical(addr), "virtual address is not canonical");
+        Self::new(addr)
     }
 }
 
@@ -173,6 +249,22 @@ impl Page {
     pub const fn p1_index(self) -> usize {
         self.start_address.p1_index()
     }
+
+    /// Pages from `start` up to but not including `end`
+    pub fn range(start: Page, end: Page) -> PageRange {
+        debug_assert!(end >= start, "page range ends before it starts");
+        PageRange { start, end }
+    }
+
+    /// Pages from `start` up to and including `end`
+    pub fn range_inclusive(start: Page, end: Page) -> PageRangeInclusive {
+        debug_assert!(end >= start, "page range ends before it starts");
+        PageRangeInclusive {
+            start,
+            end,
+            done: false,
+        }
+    }
 }
 
 impl core::ops::Add<u64> for Page {
@@ -185,6 +277,58 @@ impl core::ops::Add<u64> for Page {
     }
 }
 
+/// Iterator over the pages of a half-open range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRange {
+    /// Next page to yield
+    pub start: Page,
+    /// First page past the range
+    pub end: Page,
+}
+
+impl Iterator for PageRange {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        if self.start >= self.end {
+            return None;
+        }
+        let page = self.start;
+        self.start = page + 1;
+        Some(page)
+    }
+}
+
+/// Iterator over the pages of a closed range
+///
+/// The last page is yielded without stepping past it, so a range can end
+/// at the top of the address space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRangeInclusive {
+    /// Next page to yield
+    pub start: Page,
+    /// Last page of the range
+    pub end: Page,
+    done: bool,
+}
+
+impl Iterator for PageRangeInclusive {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        if self.done || self.start > self.end {
+            return None;
+        }
+        let page = self.start;
+        if page == self.end {
+            self.done = true;
+        } else {
+            self.start = page + 1;
+        }
+        Some(page)
+    }
+}
+
 /// Physical frame (4KB physical page)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PhysFrame {
@@ -214,6 +358,22 @@ impl PhysFrame {
     pub const fn start_address(self) -> PhysAddr {
         self.start_address
     }
+
+    /// Frames from `start` up to but not including `end`
+    pub fn range(start: PhysFrame, end: PhysFrame) -> FrameRange {
+        debug_assert!(end >= start, "frame range ends before it starts");
+        FrameRange { start, end }
+    }
+
+    /// Frames from `start` up to and including `end`
+    pub fn range_inclusive(start: PhysFrame, end: PhysFrame) -> FrameRangeInclusive {
+        debug_assert!(end >= start, "frame range ends before it starts");
+        FrameRangeInclusive {
+            start,
+            end,
+            done: false,
+        }
+    }
 }
 
 impl core::ops::Add<u64> for PhysFrame {
@@ -226,6 +386,112 @@ impl core::ops::Add<u64> for PhysFrame {
     }
 }
 
+/// Iterator over the frames of a half-open range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameRange {
+    /// Next frame to yield
+    pub start: PhysFrame,
+    /// First frame past the range
+    pub end: PhysFrame,
+}
+
+impl Iterator for FrameRange {
+    type Item = PhysFrame;
+
+    fn next(&mut self) -> Option<PhysFrame> {
+        if self.start >= self.end {
+            return None;
+        }
+        let frame = self.start;
+        self.start = frame + 1;
+        Some(frame)
+    }
+}
+
+/// Iterator over the frames of a closed range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameRangeInclusive {
+    /// Next frame to yield
+    pub start: PhysFrame,
+    /// Last frame of the range
+    pub end: PhysFrame,
+    done: bool,
+}
+
+impl Iterator for FrameRangeInclusive {
+    type Item = PhysFrame;
+
+    fn next(&mut self) -> Option<PhysFrame> {
+        if self.done || self.start > self.end {
+            return None;
+        }
+        let frame = self.start;
+        if frame == self.end {
+            self.done = true;
+        } else {
+            self.start = frame + 1;
+        }
+        Some(frame)
+    }
+}
+
+/// Iterator over the whole frames of a physical address range
+///
+/// Unlike `FrameRange`, it is built from byte addresses, which are rounded
+/// inwards to frame boundaries and clamped to the 52-bit physical address
+/// space, so memory map entries can be walked without overflow checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhysRange {
+    /// Start of the next frame to yield
+    pub start: PhysAddr,
+    /// End of the range, frame-aligned and exclusive
+    pub end: PhysAddr,
+}
+
+impl PhysRange {
+    /// Frames lying entirely in `start..end`
+    pub fn new(start: PhysAddr, end: PhysAddr) -> Self {
+        let limit = MAX_PHYS_ADDR + 1;
+        let start = PhysAddr::new(start.as_u64().min(limit)).align_up(PhysFrame::SIZE);
+        let end = PhysAddr::new(end.as_u64().min(limit)).align_down(PhysFrame::SIZE);
+        Self {
+            start,
+            end: end.max(start),
+        }
+    }
+
+    /// Frames lying entirely in a memory map region
+    pub fn from_region(region: &MemoryRegion) -> Self {
+        Self::new(
+            PhysAddr::new(region.base_addr),
+            PhysAddr::new(region.base_addr.saturating_add(region.length)),
+        )
+    }
+
+    /// Bytes covered by the frames not yet yielded
+    pub fn byte_count(&self) -> u64 {
+        self.end.as_u64() - self.start.as_u64()
+    }
+}
+
+impl Iterator for PhysRange {
+    type Item = PhysFrame;
+
+    fn next(&mut self) -> Option<PhysFrame> {
+        if self.start >= self.end {
+            return None;
+        }
+        let frame = PhysFrame::from_start_address(self.start);
+        // The last frame below the top of the address space has no
+        // successor `PhysAddr`, so the range is exhausted instead
+        self.start = self
+            .start
+            .checked_add(PhysFrame::SIZE)
+            .map_or(self.end, |next| next.min(self.end));
+        Some(frame)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -238,6 +504,7 @@ mod tests {
         assert_eq!(addr.p2_index(), 0);
         assert_eq!(addr.p1_index(), 1);
         assert_eq!(addr.page_offset(), 0x234);
+        assert_eq!(addr.p2_offset(), 0x1234);
     }
 
     #[test]
@@ -254,6 +521,87 @@ mod tests {
         assert_eq!(frame.start_address().as_u64(), 0x5000);
     }
 
+    #[test]
+    fn test_virt_addr_pointer_round_trip() {
+        let value = 42u64;
+        let addr = VirtAddr::from_ptr(&value);
+        assert_eq!(addr.as_u64(), &value as *const u64 as u64);
+        assert_eq!(addr.as_ptr::<u64>(), &value as *const u64);
+        // SAFETY: the pointer came from a live reference
+        assert_eq!(unsafe { *addr.as_ptr::<u64>() }, 42);
+
+        let mut words = [0u32; 4];
+        let addr = VirtAddr::from_ptr(words.as_mut_ptr());
+        // SAFETY: the pointer came from a live, exclusive borrow
+        unsafe { (addr + 4).as_mut_ptr::<u32>().write(7) };
+        assert_eq!(words, [0, 7, 0, 0]);
+    }
+
+    #[test]
+    fn test_phys_addr_truncate() {
+        assert_eq!(
+            PhysAddr::from_u64_truncate(0xfff0_0000_1234_5000).as_u64(),
+            0x1234_5000
+        );
+        assert_eq!(
+            PhysAddr::from_u64_truncate(0x000f_ffff_ffff_ffff).as_u64(),
+            0x000f_ffff_ffff_ffff
+        );
+    }
+
+    #[test]
+    fn test_virt_addr_checked_add() {
+        assert_eq!(VirtAddr::new(u64::MAX).checked_add(1), None);
+        assert_eq!(VirtAddr::new(0x7fff_ffff_f000).checked_add(0x1000), None);
+        assert_eq!(
+            VirtAddr::new(0x7fff_ffff_e000).checked_add(0xfff),
+            Some(VirtAddr::new(0x7fff_ffff_efff))
+        );
+        assert_eq!(
+            VirtAddr::new(0xffff_8000_0000_0000).checked_add(0x1000),
+            Some(VirtAddr::new(0xffff_8000_0000_1000))
+        );
+    }
+
+    #[test]
+    fn test_phys_addr_checked_add() {
+        assert_eq!(PhysAddr::new(u64::MAX).checked_add(1), None);
+        assert_eq!(PhysAddr::new(MAX_PHYS_ADDR).checked_add(1), None);
+        assert_eq!(
+            PhysAddr::new(MAX_PHYS_ADDR - 0x1000).checked_add(0x1000),
+            Some(PhysAddr::new(MAX_PHYS_ADDR))
+        );
+    }
+
+    #[test]
+    fn test_page_range() {
+        let page = Page::containing_address(VirtAddr::new(0x1000_0000));
+        assert_eq!(Page::range(page, page + 3).count(), 3);
+        assert_eq!(Page::range(page, page).count(), 0);
+        assert_eq!(Page::range(page, page + 3).last(), Some(page + 2));
+
+        let mut single = Page::range_inclusive(page, page);
+        assert_eq!(single.next(), Some(page));
+        assert_eq!(single.next(), None);
+        assert_eq!(Page::range_inclusive(page, page + 3).count(), 4);
+
+        // The top page of the address space ends the range without
+        // stepping past it
+        let top = Page::containing_address(VirtAddr::new(u64::MAX));
+        assert_eq!(Page::range_inclusive(top, top).count(), 1);
+    }
+
+    #[test]
+    fn test_frame_range() {
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x20_0000));
+        let frames = PhysFrame::range(frame, frame + 2);
+        assert!(frames.eq([frame, frame + 1]));
+
+        let mut single = PhysFrame::range_inclusive(frame, frame);
+        assert_eq!(single.next(), Some(frame));
+        assert_eq!(single.next(), None);
+    }
+
     #[test]
     fn test_address_alignment() {
         let addr = PhysAddr::new(0x1234);
@@ -261,4 +609,44 @@ mod tests {
         assert_eq!(addr.align_down(4096).as_u64(), 0x1000);
         assert_eq!(addr.align_up(4096).as_u64(), 0x2000);
     }
+
+    #[test]
+    fn test_phys_range_from_region() {
+        let region = MemoryRegion {
+            base_addr: 0x1000,
+            length: 0x3fff,
+            region_type: crate::boot::MemoryRegionType::Usable,
+        };
+        let range = PhysRange::from_region(&region);
+        assert_eq!(range.byte_count(), 0x3000);
+        let starts = [0x1000, 0x2000, 0x3000];
+        assert!(
+            range.eq(starts
+                .into_iter()
+                .map(|addr| PhysFrame::from_start_address(PhysAddr::new(addr))))
+        );
+        assert_eq!(region.into_iter().count(), 3);
+    }
+
+    #[test]
+    fn test_phys_range_rounds_inwards() {
+        let range = PhysRange::new(PhysAddr::new(0x1001), PhysAddr::new(0x3000));
+        assert_eq!(range.start, PhysAddr::new(0x2000));
+        assert_eq!(range.count(), 1);
+
+        // A region smaller than a frame yields nothing
+        let range = PhysRange::new(PhysAddr::new(0x1800), PhysAddr::new(0x1900));
+        assert_eq!(range.byte_count(), 0);
+        assert_eq!(range.count(), 0);
+
+        // A region running to the top of memory does not overflow
+        let region = MemoryRegion {
+            base_addr: MAX_PHYS_ADDR - 0x1fff,
+            length: u64::MAX,
+            region_type: crate::boot::MemoryRegionType::Reserved,
+        };
+        let range = PhysRange::from_region(&region);
+        assert_eq!(range.end, PhysAddr::new(MAX_PHYS_ADDR + 1));
+        assert_eq!(range.count(), 2);
+    }
 }
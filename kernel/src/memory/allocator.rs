@@ -1,11 +1,16 @@
 //! Kernel Heap Allocator
 //!
-//! This module implements a simple Bump Allocator for kernel heap allocation.
-//! The Bump Allocator is a linear allocator that allocates memory by bumping
-//! a pointer forward. It's simple but cannot reuse freed memory.
+//! This module implements the allocators available for the kernel heap:
 //!
-//! This will be replaced with more advanced allocators (Slab, Buddy) in the
-//! future.
+//! - `BumpAllocator`: a linear allocator that allocates memory by bumping a
+//!   pointer forward. It's simple but cannot reuse freed memory.
+//! - `BuddyAllocator`: splits the heap into power-of-two blocks and coalesces
+//!   buddies on free, so freed memory is reused.
+//!
+//! `heap.rs` selects which one backs `#[global_allocator]`.
+//!
+//! Both implement the `Allocator` trait, which subsystems managing their
+//! own memory use for typed allocation instead of `GlobalAlloc`.
 
 #![allow(dead_code)]
 
@@ -14,11 +19,39 @@ use core::{
         GlobalAlloc,
         Layout,
     },
-    ptr,
+    ptr::{
+        self,
+        NonNull,
+    },
 };
 
 use spin::Mutex;
 
+/// An allocator handing out memory for a `Layout`
+pub trait Allocator {
+    /// Allocate memory for `layout`
+    ///
+    /// Returns `None` if no memory is left.
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Free memory returned by `allocate`
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate` on this allocator with
+    /// the same `layout`, and must not be freed twice.
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// Allocate memory for one `T` from `alloc`
+///
+/// The memory is uninitialized.
+pub fn allocate_one<T>(alloc: &mut impl Allocator) -> Option<*mut T> {
+    alloc
+        .allocate(Layout::new::<T>())
+        .map(|ptr| ptr.as_ptr().cast())
+}
+
 /// Bump Allocator (linear allocator)
 ///
 /// Allocates memory by moving a pointer forward. Very simple but cannot reuse
@@ -66,9 +99,14 @@ impl BumpAllocator {
 
     /// Get the current heap usage statistics
     pub fn usage(&self) -> HeapUsage {
+        let total = self.heap_end - self.heap_start;
+        let used = self.next - self.heap_start;
         HeapUsage {
-            total: self.heap_end - self.heap_start,
-            used: self.next - self.heap_start,
+            total,
+            used,
+            free: total - used,
+            // Everything past `next` is one contiguous region
+            largest_free: total - used,
             allocations: self.allocations,
         }
     }
@@ -79,48 +117,291 @@ impl BumpAllocator {
 pub struct HeapUsage {
     pub total: usize,
     pub used: usize,
+    pub free: usize,
+    /// Size of the largest contiguous free block
+    pub largest_free: usize,
     pub allocations: usize,
 }
 
-unsafe impl GlobalAlloc for Locked<BumpAllocator> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut allocator = self.lock();
+impl HeapUsage {
+    /// External fragmentation of the free memory
+    ///
+    /// Returns 0.0 when all free memory is one contiguous block and
+    /// approaches 1.0 as free memory is split into small blocks.
+    pub fn fragmentation_ratio(&self) -> f32 {
+        if self.free == 0 {
+            return 0.0;
+        }
+        1.0 - self.largest_free as f32 / self.free as f32
+    }
+}
 
+impl Allocator for BumpAllocator {
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
         // Align the allocation start address (checked)
-        let alloc_start = match align_up_checked(allocator.next, layout.align()) {
-            Some(a) => a,
-            None => return ptr::null_mut(),
-        };
+        let alloc_start = align_up_checked(self.next, layout.align())?;
 
         // Check for overflow
-        let alloc_end = match alloc_start.checked_add(layout.size()) {
-            Some(end) => end,
-            None => return ptr::null_mut(),
-        };
+        let alloc_end = alloc_start.checked_add(layout.size())?;
 
         // Check if we have enough space
-        if alloc_end > allocator.heap_end {
+        if alloc_end > self.heap_end {
             // Out of memory
-            ptr::null_mut()
-        } else {
-            allocator.next = alloc_end;
-            allocator.allocations += 1;
-            alloc_start as *mut u8
+            return None;
         }
+        let ptr = NonNull::new(alloc_start as *mut u8)?;
+        self.next = alloc_end;
+        self.allocations += 1;
+        Some(ptr)
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        let mut allocator = self.lock();
-        allocator.allocations -= 1;
+    unsafe fn deallocate(&mut self, _ptr: NonNull<u8>, _layout: Layout) {
+        self.allocations -= 1;
 
         // Bump Allocator essentially does nothing for deallocation
         // We can reset the entire heap when allocation count reaches 0
-        if allocator.allocations == 0 {
-            allocator.next = allocator.heap_start;
+        if self.allocations == 0 {
+            self.next = self.heap_start;
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<BumpAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock()
+            .allocate(layout)
+            .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            self.lock().deallocate(ptr, layout);
         }
     }
 }
 
+/// Order of the smallest buddy block (16 bytes, enough for a free-list link)
+const MIN_ORDER: usize = 4;
+
+/// Number of block orders, from 16 bytes up to 2 GiB
+const ORDERS: usize = 28;
+
+/// Buddy Allocator
+///
+/// Memory is managed in power-of-two blocks aligned to their own size. An
+/// allocation takes the smallest free block that fits, splitting larger
+/// blocks in half as needed. On free, a block is merged with its buddy (the
+/// other half of the block it was split from) whenever the buddy is free
+/// too, so freed memory becomes available for larger allocations again.
+///
+/// Each free list is a singly linked list threaded through the free blocks
+/// themselves; `free_lists[order]` holds the address of the first block, or
+/// 0 if the list is empty.
+pub struct BuddyAllocator {
+    heap_start: usize,
+    heap_end: usize,
+    free_lists: [usize; ORDERS],
+    used: usize,
+    allocations: usize,
+}
+
+impl BuddyAllocator {
+    /// Create a new, empty BuddyAllocator
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            heap_start: 0,
+            heap_end: 0,
+            free_lists: [0; ORDERS],
+            used: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Initialize the heap with a memory region
+    ///
+    /// The region is carved into the largest naturally aligned blocks that
+    /// fit; bytes that cannot form a minimum-sized block are left unused.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that:
+    /// - `heap_start` points to valid, unused memory
+    /// - The memory region `[heap_start, heap_start + heap_size)` is valid
+    /// - This function is called only once
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        debug_assert!(
+            self.heap_start == 0 && self.heap_end == 0,
+            "BuddyAllocator::init called more than once"
+        );
+        let end = heap_start
+            .checked_add(heap_size)
+            .expect("heap region overflow");
+
+        let mut addr = align_up_checked(heap_start, block_size(0)).expect("heap region overflow");
+        let end = end & !(block_size(0) - 1);
+        self.heap_start = addr;
+        self.heap_end = end.max(addr);
+
+        while addr < end {
+            let mut order = ORDERS - 1;
+            while !addr.is_multiple_of(block_size(order)) || end - addr < block_size(order) {
+                order -= 1;
+            }
+            self.push(order, addr);
+            addr += block_size(order);
+        }
+    }
+
+    /// Allocate a block for `layout`
+    ///
+    /// Returns a null pointer if no block large enough is free.
+    ///
+    /// # Safety
+    ///
+    /// The allocator must have been initialized with `init`.
+    pub unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let order = match order_for(layout) {
+            Some(order) => order,
+            None => return ptr::null_mut(),
+        };
+
+        // Find the smallest non-empty free list that can satisfy the request
+        let mut current = match (order..ORDERS).find(|&o| self.free_lists[o] != 0) {
+            Some(o) => o,
+            None => return ptr::null_mut(),
+        };
+        let addr = self.pop(current);
+
+        // Split the block, returning the upper halves to the free lists
+        while current > order {
+            current -= 1;
+            self.push(current, addr + block_size(current));
+        }
+
+        self.used += block_size(order);
+        self.allocations += 1;
+        addr as *mut u8
+    }
+
+    /// Free a block previously returned by `allocate`
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate` on this allocator with the
+    /// same `layout`, and must not be freed twice.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let Some(mut order) = order_for(layout) else {
+            return;
+        };
+        let mut addr = ptr as usize;
+
+        self.used -= block_size(order);
+        self.allocations -= 1;
+
+        // Merge with the buddy for as long as it is free
+        while order < ORDERS - 1 {
+            let buddy = addr ^ block_size(order);
+            if !self.remove(order, buddy) {
+                break;
+            }
+            addr = addr.min(buddy);
+            order += 1;
+        }
+
+        self.push(order, addr);
+    }
+
+    /// Get the current heap usage statistics
+    pub fn usage(&self) -> HeapUsage {
+        let total = self.heap_end - self.heap_start;
+        let largest_free = (0..ORDERS)
+            .rev()
+            .find(|&o| self.free_lists[o] != 0)
+            .map_or(0, block_size);
+
+        HeapUsage {
+            total,
+            used: self.used,
+            free: total - self.used,
+            largest_free,
+            allocations: self.allocations,
+        }
+    }
+
+    /// Push a free block onto the list for `order`
+    unsafe fn push(&mut self, order: usize, addr: usize) {
+        // SAFETY: free blocks are unused heap memory of at least 16 bytes,
+        // aligned to their size, so they can hold the list link.
+        unsafe { (addr as *mut usize).write(self.free_lists[order]) };
+        self.free_lists[order] = addr;
+    }
+
+    /// Pop a free block from the (non-empty) list for `order`
+    unsafe fn pop(&mut self, order: usize) -> usize {
+        let addr = self.free_lists[order];
+        // SAFETY: `addr` is the head of a free list, see `push`
+        self.free_lists[order] = unsafe { (addr as *const usize).read() };
+        addr
+    }
+
+    /// Remove `addr` from the list for `order`, if it is there
+    unsafe fn remove(&mut self, order: usize, addr: usize) -> bool {
+        let mut link: *mut usize = &mut self.free_lists[order];
+        // SAFETY: every non-zero link points to a free block, see `push`
+        unsafe {
+            while *link != 0 {
+                if *link == addr {
+                    *link = (addr as *const usize).read();
+                    return true;
+                }
+                link = *link as *mut usize;
+            }
+        }
+        false
+    }
+}
+
+impl Allocator for BuddyAllocator {
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        // SAFETY: before `init` every free list is empty, so nothing is
+        // allocated
+        NonNull::new(unsafe { BuddyAllocator::allocate(self, layout) })
+    }
+
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        // SAFETY: guaranteed by the caller
+        unsafe { BuddyAllocator::deallocate(self, ptr.as_ptr(), layout) }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<BuddyAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock().allocate(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock().deallocate(ptr, layout)
+    }
+}
+
+/// Size in bytes of a block of the given order
+const fn block_size(order: usize) -> usize {
+    1 << (order + MIN_ORDER)
+}
+
+/// Smallest block order that satisfies both the size and alignment of
+/// `layout`, or `None` if it is larger than the largest block
+fn order_for(layout: Layout) -> Option<usize> {
+    let size = layout
+        .size()
+        .max(layout.align())
+        .max(block_size(0))
+        .checked_next_power_of_two()?;
+    let order = size.trailing_zeros() as usize - MIN_ORDER;
+    (order < ORDERS).then_some(order)
+}
+
 /// Align address upward to the given alignment (checked for overflow)
 ///
 /// # Arguments
@@ -158,3 +439,192 @@ impl<A> Locked<A> {
         self.inner.lock()
     }
 }
+
+impl<A: Allocator> Allocator for Locked<A> {
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        self.inner.get_mut().allocate(layout)
+    }
+
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        // SAFETY: guaranteed by the caller
+        unsafe { self.inner.get_mut().deallocate(ptr, layout) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::alloc::{
+        alloc,
+        dealloc,
+    };
+
+    use super::*;
+
+    const TEST_HEAP_SIZE: usize = 8 * 1024;
+
+    /// Runs `f` with a fresh, naturally aligned region of `TEST_HEAP_SIZE`
+    /// bytes
+    fn with_region(f: impl FnOnce(usize)) {
+        let layout = Layout::from_size_align(TEST_HEAP_SIZE, TEST_HEAP_SIZE).unwrap();
+        // SAFETY: the layout has a non-zero size
+        let region = unsafe { alloc(layout) };
+        assert!(!region.is_null());
+        f(region as usize);
+        // SAFETY: `region` was allocated above with `layout`
+        unsafe { dealloc(region, layout) };
+    }
+
+    #[test_case]
+    fn test_buddy_reuses_freed_memory() {
+        with_region(|start| {
+            let mut buddy = BuddyAllocator::new();
+            let mut bump = BumpAllocator::new();
+            // SAFETY: each allocator gets its own half of the region, which
+            // `with_region` owns until `f` returns
+            unsafe {
+                buddy.init(start, TEST_HEAP_SIZE / 2);
+                bump.init(start + TEST_HEAP_SIZE / 2, TEST_HEAP_SIZE / 2);
+            }
+            let layout = Layout::from_size_align(48, 8).unwrap();
+            let bump = Locked::new(bump);
+
+            // A long-lived allocation keeps the bump allocator from resetting
+            // SAFETY: the buddy allocator was initialized above
+            let pinned = unsafe { buddy.allocate(layout) };
+            // SAFETY: the layout has a non-zero size
+            let bump_pinned = unsafe { bump.alloc(layout) };
+
+            let mut peak = 0;
+            for _ in 0..1000 {
+                // SAFETY: the buddy allocator was initialized above
+                let ptr = unsafe { buddy.allocate(layout) };
+                assert!(!ptr.is_null());
+                peak = peak.max(buddy.usage().used);
+                // SAFETY: `ptr` was just allocated with `layout`
+                unsafe { buddy.deallocate(ptr, layout) };
+
+                // SAFETY: the layout has a non-zero size
+                let ptr = unsafe { bump.alloc(layout) };
+                if !ptr.is_null() {
+                    // SAFETY: `ptr` was just allocated with `layout`
+                    unsafe { bump.dealloc(ptr, layout) };
+                }
+            }
+
+            assert_eq!(peak, 2 * 64);
+            // The bump allocator never reused anything and ran out
+            assert!(bump.lock().usage().free < layout.size());
+
+            // SAFETY: both were allocated with `layout` and are freed once
+            unsafe {
+                buddy.deallocate(pinned, layout);
+                bump.dealloc(bump_pinned, layout);
+            }
+            assert_eq!(buddy.usage().used, 0);
+            assert_eq!(buddy.usage().allocations, 0);
+        });
+    }
+
+    #[test_case]
+    fn test_buddy_coalesces_on_free() {
+        with_region(|start| {
+            let mut buddy = BuddyAllocator::new();
+            // SAFETY: the region is owned by `with_region` until `f` returns
+            unsafe { buddy.init(start, 4096) };
+            assert_eq!(buddy.usage().largest_free, 4096);
+
+            let layout = Layout::from_size_align(1024, 8).unwrap();
+            // SAFETY: the allocator was initialized above
+            let blocks: [*mut u8; 4] = core::array::from_fn(|_| unsafe { buddy.allocate(layout) });
+            assert!(blocks.iter().all(|b| !b.is_null()));
+            // SAFETY: as above
+            assert!(unsafe { buddy.allocate(layout) }.is_null());
+
+            // Free every other block: 2 KiB free, but no 2 KiB block
+            // SAFETY: the blocks were allocated with `layout` and each is
+            // freed once
+            unsafe {
+                buddy.deallocate(blocks[0], layout);
+                buddy.deallocate(blocks[2], layout);
+            }
+            let usage = buddy.usage();
+            assert_eq!(usage.free, 2048);
+            assert_eq!(usage.largest_free, 1024);
+            assert!(usage.fragmentation_ratio() > 0.4);
+
+            // SAFETY: as above, for the other two blocks
+            unsafe {
+                buddy.deallocate(blocks[1], layout);
+                buddy.deallocate(blocks[3], layout);
+            }
+            assert_eq!(buddy.usage().largest_free, 4096);
+            assert_eq!(buddy.usage().fragmentation_ratio(), 0.0);
+        });
+    }
+
+    #[test_case]
+    fn test_allocate_one_aligns() {
+        with_region(|start| {
+            let mut bump = BumpAllocator::new();
+            // Start off an 8-byte boundary
+            // SAFETY: the region is owned by `with_region` until `f` returns
+            unsafe { bump.init(start + 1, 64) };
+
+            let byte = allocate_one::<u8>(&mut bump).unwrap();
+            assert_eq!(byte as usize, start + 1);
+            let word = allocate_one::<u64>(&mut bump).unwrap();
+            assert_eq!(word as usize, start + 8);
+            // SAFETY: `word` is an aligned allocation of a `u64`
+            unsafe { word.write(0x0123_4567_89ab_cdef) };
+            // SAFETY: as above, and it was just written
+            assert_eq!(unsafe { word.read() }, 0x0123_4567_89ab_cdef);
+
+            // 49 bytes are left: room for six words but not seven
+            let mut locked = Locked::new(bump);
+            assert!(allocate_one::<[u64; 6]>(&mut locked).is_some());
+            assert!(allocate_one::<u64>(&mut locked).is_none());
+            assert_eq!(locked.lock().usage().allocations, 3);
+        });
+    }
+
+    #[test_case]
+    fn test_buddy_allocator_trait() {
+        with_region(|start| {
+            let mut buddy = BuddyAllocator::new();
+            assert!(allocate_one::<u64>(&mut buddy).is_none());
+            // SAFETY: the region is owned by `with_region` until `f` returns
+            unsafe { buddy.init(start, TEST_HEAP_SIZE) };
+
+            let layout = Layout::new::<[u64; 4]>();
+            let ptr = Allocator::allocate(&mut buddy, layout).unwrap();
+            assert!(ptr.as_ptr().cast::<u64>().is_aligned());
+            assert_eq!(buddy.usage().allocations, 1);
+            // SAFETY: `ptr` was allocated above with `layout`
+            unsafe { Allocator::deallocate(&mut buddy, ptr, layout) };
+            assert_eq!(buddy.usage().largest_free, TEST_HEAP_SIZE);
+        });
+    }
+
+    #[test_case]
+    fn test_buddy_alignment() {
+        with_region(|start| {
+            let mut buddy = BuddyAllocator::new();
+            // SAFETY: the region is owned by `with_region` until `f` returns
+            unsafe { buddy.init(start, TEST_HEAP_SIZE) };
+
+            let small = Layout::from_size_align(16, 16).unwrap();
+            let aligned = Layout::from_size_align(100, 1024).unwrap();
+            // SAFETY: the allocator was initialized above
+            let a = unsafe { buddy.allocate(small) };
+            // SAFETY: as above
+            let b = unsafe { buddy.allocate(aligned) };
+            assert_eq!(b as usize % 1024, 0);
+            // SAFETY: `a` and `b` were allocated with these layouts
+            unsafe {
+                buddy.deallocate(a, small);
+                buddy.deallocate(b, aligned);
+            }
+            assert_eq!(buddy.usage().largest_free, TEST_HEAP_SIZE);
+        });
+    }
+}
@@ -0,0 +1,520 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copy-on-write frame sharing
+//!
+//! A frame shared copy-on-write is mapped read-only, with the
+//! `COPY_ON_WRITE` flag, into every address space that uses it. The first
+//! write from any of them faults; `cow_fault` then gives the writer a
+//! private copy of the frame, or hands it the frame itself if nobody else
+//! uses it any more.
+//!
+//! Only writable pages become copy-on-write. A read-only page, such as ELF
+//! text, is shared as it is with the `SHARED` flag, and writes to it stay
+//! protection faults. `COW_FRAMES` counts the users of each shared frame
+//! of either kind; `cow_release` drops the references of an address space
+//! that goes away.
+//!
+//! `pin_frame` counts a user that does not map the frame, such as an IPC
+//! message carrying it, and `release_frame` gives any use up again.
+
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{
+    AtomicU32,
+    Ordering,
+};
+
+use spin::Mutex;
+
+use super::{
+    FrameAllocator,
+    HeapFrameAllocator,
+    MapError,
+    Page,
+    PageTableFlags,
+    PageTableManager,
+    PhysFrame,
+    VirtAddr,
+};
+
+/// Page fault error code bit: the page was present (protection violation)
+const PF_PRESENT: u64 = 1 << 0;
+/// Page fault error code bit: the access was a write
+const PF_WRITE: u64 = 1 << 1;
+
+/// Shared frames by frame
+pub type CowFramePool = Mutex<BTreeMap<PhysFrame, CowFrame>>;
+
+/// Frames currently shared copy-on-write
+pub static COW_FRAMES: CowFramePool = Mutex::new(BTreeMap::new());
+
+/// A frame shared copy-on-write between address spaces
+#[derive(Debug)]
+pub struct CowFrame {
+    frame: PhysFrame,
+    /// Number of pages mapping the frame
+    refs: AtomicU32,
+}
+
+impl CowFrame {
+    /// Creates a shared frame with `refs` users
+    pub const fn new(frame: PhysFrame, refs: u32) -> Self {
+        Self {
+            frame,
+            refs: AtomicU32::new(refs),
+        }
+    }
+
+    /// Returns the shared frame
+    pub fn frame(&self) -> PhysFrame {
+        self.frame
+    }
+
+    /// Returns the number of pages mapping the frame
+    pub fn ref_count(&self) -> u32 {
+        self.refs.load(Ordering::Relaxed)
+    }
+}
+
+/// Flags of a shared mapping derived from the page's own flags
+///
+/// Writable pages become copy-on-write; read-only pages stay read-only and
+/// are only marked `SHARED`.
+fn cow_flags(flags: PageTableFlags) -> PageTableFlags {
+    if flags.contains(PageTableFlags::WRITABLE) {
+        (flags - PageTableFlags::WRITABLE) | PageTableFlags::COPY_ON_WRITE
+    } else {
+        flags | PageTableFlags::SHARED
+    }
+}
+
+/// Returns the flags a shared page had before it was shared
+fn private_flags(flags: PageTableFlags) -> PageTableFlags {
+    if flags.contains(PageTableFlags::COPY_ON_WRITE) {
+        (flags - PageTableFlags::COPY_ON_WRITE) | PageTableFlags::WRITABLE
+    } else {
+        flags - PageTableFlags::SHARED
+    }
+}
+
+/// Returns whether `flags` belong to a page sharing its frame
+fn is_shared(flags: PageTableFlags) -> bool {
+    flags.intersects(PageTableFlags::COPY_ON_WRITE | PageTableFlags::SHARED)
+}
+
+/// Maps `page` to `frame` shared and counts the new user
+///
+/// If `flags` include `WRITABLE` the page is mapped copy-on-write and gets
+/// them back once it is written to. Otherwise it is mapped read-only and
+/// writes to it are not resolved.
+///
+/// # Errors
+///
+/// Returns `MapError::AlreadyMapped` if the page is mapped, or
+/// `MapError::FrameAllocationFailed` if a page table could not be
+/// allocated.
+pub fn cow_map(
+    address_space: &mut PageTableManager,
+    page: Page,
+    frame: PhysFrame,
+    flags: PageTableFlags,
+    frame_allocator: &mut impl FrameAllocator,
+) -> Result<(), MapError> {
+    if address_space.translate_addr(page.start_address()).is_some() {
+        return Err(MapError::AlreadyMapped);
+    }
+    address_space
+        .map_page(page, frame, cow_flags(flags), frame_allocator)
+        .map_err(|_| MapError::FrameAllocationFailed)?;
+
+    let mut pool = COW_FRAMES.lock();
+    pool.entry(frame)
+        .or_insert_with(|| CowFrame::new(frame, 0))
+        .refs
+        .fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Shares the frame behind `page` in `parent` with `child`
+///
+/// Both address spaces end up mapping the frame read-only at `page`, as
+/// after a fork: copy-on-write if the parent's page was writable, plainly
+/// read-only otherwise.
+///
+/// # Errors
+///
+/// Returns `MapError::NotMapped` if `page` is not mapped by a 4 KiB entry
+/// in `parent`, or the `cow_map` error for `child`.
+pub fn cow_share(
+    parent: &mut PageTableManager,
+    child: &mut PageTableManager,
+    page: Page,
+    frame_allocator: &mut impl FrameAllocator,
+) -> Result<(), MapError> {
+    let flags = parent.page_flags(page).ok_or(MapError::NotMapped)?;
+    let frame = PhysFrame::containing_address(
+        parent
+            .translate_addr(page.start_address())
+            .ok_or(MapError::NotMapped)?,
+    );
+    // A pinned frame already counts the parent's page
+    let counted = is_shared(flags) || COW_FRAMES.lock().contains_key(&frame);
+    // The parent's writable flag is what both sides get back on a write
+    cow_map(child, page, frame, private_flags(flags), frame_allocator)?;
+
+    if !counted {
+        COW_FRAMES
+            .lock()
+            .get(&frame)
+            .expect("shared frame missing")
+            .refs
+            .fetch_add(1, Ordering::Relaxed);
+    }
+    if !is_shared(flags) {
+        parent.set_flags(page, cow_flags(flags))?;
+    }
+    Ok(())
+}
+
+/// Resolves a write to the copy-on-write `page`
+///
+/// If other pages still map the frame, the page is moved to a private copy
+/// of it; otherwise the page keeps the frame. Either way it ends up
+/// writable.
+///
+/// # Errors
+///
+/// Returns `MapError::NotMapped` if `page` is not a copy-on-write page,
+/// including a shared page that was never writable, or
+/// `MapError::FrameAllocationFailed` if there is no frame for the copy.
+pub fn cow_fault(
+    address_space: &mut PageTableManager,
+    page: Page,
+    frame_allocator: &mut impl FrameAllocator,
+) -> Result<(), MapError> {
+    resolve_fault(&mut COW_FRAMES.lock(), address_space, page, frame_allocator)
+}
+
+/// Does the work of `cow_fault` with the pool already locked
+fn resolve_fault(
+    pool: &mut BTreeMap<PhysFrame, CowFrame>,
+    address_space: &mut PageTableManager,
+    page: Page,
+    frame_allocator: &mut impl FrameAllocator,
+) -> Result<(), MapError> {
+    // Only pages that were writable when they were shared are
+    // copy-on-write; a write to a read-only shared page is a genuine fault
+    let flags = address_space
+        .page_flags(page)
+        .filter(|flags| flags.contains(PageTableFlags::COPY_ON_WRITE))
+        .ok_or(MapError::NotMapped)?;
+    let writable = private_flags(flags);
+    let frame = PhysFrame::containing_address(
+        address_space
+            .translate_addr(page.start_address())
+            .ok_or(MapError::NotMapped)?,
+    );
+
+    let shared = pool
+        .get(&frame)
+        .is_some_and(|cow| cow.refs.load(Ordering::Relaxed) > 1);
+    if !shared {
+        pool.remove(&frame);
+        return address_space.set_flags(page, writable);
+    }
+
+    let copy = frame_allocator
+        .allocate_frame()
+        .ok_or(MapError::FrameAllocationFailed)?;
+    // SAFETY: both frames are identity-accessible; the copy is unused
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            frame.start_address().as_u64() as *const u8,
+            copy.start_address().as_u64() as *mut u8,
+            PhysFrame::SIZE as usize,
+        );
+    }
+    address_space
+        .unmap_page(page)
+        .map_err(|_| MapError::NotMapped)?;
+    address_space
+        .map_page(page, copy, writable, frame_allocator)
+        .map_err(|_| MapError::FrameAllocationFailed)?;
+    if let Some(cow) = pool.get(&frame) {
+        cow.refs.fetch_sub(1, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Resolves a write fault on a copy-on-write page of the active address
+/// space
+///
+/// Called by the page fault handler with the faulting address from CR2.
+///
+/// # Returns
+///
+/// `true` if the page was made writable and the write can be retried
+pub fn handle_page_fault(addr: VirtAddr, error_code: u64) -> bool {
+    if error_code & (PF_PRESENT | PF_WRITE) != PF_PRESENT | PF_WRITE {
+        return false;
+    }
+    // The fault may have interrupted code holding the pool lock; waiting
+    // for it would deadlock
+    let Some(mut pool) = COW_FRAMES.try_lock() else {
+        return false;
+    };
+    // SAFETY: CR3 holds the active, identity-accessible P4 table
+    let mut address_space = unsafe { PageTableManager::current() };
+    resolve_fault(
+        &mut pool,
+        &mut address_space,
+        Page::containing_address(addr),
+        &mut HeapFrameAllocator::new(),
+    )
+    .is_ok()
+}
+
+/// Unmaps the shared pages among `count` pages from `start` and drops
+/// their references
+///
+/// Used when an address space is torn down. A frame whose last user goes
+/// away is freed; pages that are not shared are left alone.
+///
+/// # Returns
+///
+/// The number of pages unmapped
+pub fn cow_release(address_space: &mut PageTableManager, start: Page, count: usize) -> usize {
+    let mut frames = HeapFrameAllocator::new();
+    let mut released = 0;
+    for i in 0..count {
+        let page = start + i as u64;
+        if !address_space.page_flags(page).is_some_and(is_shared) {
+            continue;
+        }
+        let Ok(frame) = address_space.unmap_page(page) else {
+            continue;
+        };
+        released += 1;
+        // SAFETY: the page that mapped the frame was just unmapped
+        unsafe { release_frame(frame, &mut frames) };
+    }
+    released
+}
+
+/// Counts a user of `frame` that does not map it, such as an IPC message
+/// carrying it
+///
+/// A frame that is not shared yet is added to `COW_FRAMES` with its owner
+/// counted as well. The frame then stays allocated until both have given
+/// it up with `release_frame`.
+pub fn pin_frame(frame: PhysFrame) {
+    COW_FRAMES
+        .lock()
+        .entry(frame)
+        .or_insert_with(|| CowFrame::new(frame, 1))
+        .refs
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// Gives up one use of `frame`
+///
+/// A frame in `COW_FRAMES` drops a reference and is freed with the last
+/// one. Any other frame had a single user and is freed right away.
+///
+/// # Safety
+///
+/// The caller must no longer map or otherwise use `frame`, and the frame
+/// must have come from a `HeapFrameAllocator`.
+pub unsafe fn release_frame(frame: PhysFrame, frame_allocator: &mut HeapFrameAllocator) {
+    let mut pool = COW_FRAMES.lock();
+    if let Some(cow) = pool.get(&frame) {
+        if cow.refs.fetch_sub(1, Ordering::Relaxed) > 1 {
+            return;
+        }
+        pool.remove(&frame);
+    }
+    drop(pool);
+    // SAFETY: nothing uses the frame any more, as guaranteed by the caller
+    unsafe { frame_allocator.deallocate_frame(frame) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::PageTable;
+
+    const PAGE: u64 = 0x2800_0000_0000;
+
+    fn empty_address_space(allocator: &mut HeapFrameAllocator) -> PageTableManager {
+        let frame = allocator.allocate_frame().unwrap();
+        // SAFETY: the frame is zeroed, unused and identity-accessible
+        unsafe {
+            PageTableManager::from_p4_table(
+                &mut *(frame.start_address().as_u64() as *mut PageTable),
+            )
+        }
+    }
+
+    /// Reads the first word of the page as seen through `space`
+    fn read(space: &PageTableManager, page: Page) -> u64 {
+        let phys = space.translate_addr(page.start_address()).unwrap();
+        // SAFETY: the frame is identity-accessible
+        unsafe { (phys.as_u64() as *const u64).read_volatile() }
+    }
+
+    /// Writes the first word of the page as a process would: resolving the
+    /// copy-on-write fault first if the page is read-only
+    fn write(
+        space: &mut PageTableManager,
+        page: Page,
+        value: u64,
+        allocator: &mut HeapFrameAllocator,
+    ) {
+        if !space
+            .page_flags(page)
+            .unwrap()
+            .contains(PageTableFlags::WRITABLE)
+        {
+            cow_fault(space, page, allocator).unwrap();
+        }
+        let phys = space.translate_addr(page.start_address()).unwrap();
+        // SAFETY: the frame is identity-accessible and owned by this test
+        unsafe { (phys.as_u64() as *mut u64).write_volatile(value) };
+    }
+
+    #[test_case]
+    fn test_fork_then_write_separates_pages() {
+        let mut allocator = HeapFrameAllocator::new();
+        let mut parent = empty_address_space(&mut allocator);
+        let mut child = empty_address_space(&mut allocator);
+        let page = Page::from_start_address(VirtAddr::new(PAGE));
+        let frame = allocator.allocate_frame().unwrap();
+        parent
+            .map_page(page, frame, PageTableFlags::WRITABLE, &mut allocator)
+            .unwrap();
+        write(&mut parent, page, 1, &mut allocator);
+
+        // Fork: both map the same frame read-only
+        cow_share(&mut parent, &mut child, page, &mut allocator).unwrap();
+        assert_eq!(COW_FRAMES.lock().get(&frame).unwrap().ref_count(), 2);
+        for space in [&mut parent, &mut child] {
+            let flags = space.page_flags(page).unwrap();
+            assert!(!flags.contains(PageTableFlags::WRITABLE));
+            assert!(flags.contains(PageTableFlags::COPY_ON_WRITE));
+        }
+        assert_eq!(read(&child, page), 1);
+
+        // The child's write moves it to a copy
+        write(&mut child, page, 2, &mut allocator);
+        assert_eq!(read(&parent, page), 1);
+        assert_eq!(read(&child, page), 2);
+        assert_ne!(
+            child.translate_addr(page.start_address()),
+            Some(frame.start_address())
+        );
+        assert_eq!(COW_FRAMES.lock().get(&frame).unwrap().ref_count(), 1);
+
+        // The parent is the last user and keeps the frame
+        write(&mut parent, page, 3, &mut allocator);
+        assert_eq!(
+            parent.translate_addr(page.start_address()),
+            Some(frame.start_address())
+        );
+        assert_eq!((read(&parent, page), read(&child, page)), (3, 2));
+        assert!(COW_FRAMES.lock().get(&frame).is_none());
+        assert!(
+            !parent
+                .page_flags(page)
+                .unwrap()
+                .contains(PageTableFlags::COPY_ON_WRITE)
+        );
+    }
+
+    #[test_case]
+    fn test_release_drops_references() {
+        let mut allocator = HeapFrameAllocator::new();
+        let mut parent = empty_address_space(&mut allocator);
+        let mut child = empty_address_space(&mut allocator);
+        let page = Page::from_start_address(VirtAddr::new(PAGE));
+        let frame = allocator.allocate_frame().unwrap();
+        parent
+            .map_page(page, frame, PageTableFlags::WRITABLE, &mut allocator)
+            .unwrap();
+        cow_share(&mut parent, &mut child, page, &mut allocator).unwrap();
+
+        assert_eq!(cow_release(&mut child, page, 2), 1);
+        assert!(child.translate_addr(page.start_address()).is_none());
+        assert_eq!(COW_FRAMES.lock().get(&frame).unwrap().ref_count(), 1);
+
+        // The last user frees the frame
+        assert_eq!(cow_release(&mut parent, page, 2), 1);
+        assert!(COW_FRAMES.lock().get(&frame).is_none());
+    }
+
+    #[test_case]
+    fn test_share_read_only_page() {
+        let mut allocator = HeapFrameAllocator::new();
+        let mut parent = empty_address_space(&mut allocator);
+        let mut child = empty_address_space(&mut allocator);
+        let page = Page::from_start_address(VirtAddr::new(PAGE));
+        let frame = allocator.allocate_frame().unwrap();
+        parent
+            .map_page(page, frame, PageTableFlags::USER_ACCESSIBLE, &mut allocator)
+            .unwrap();
+
+        cow_share(&mut parent, &mut child, page, &mut allocator).unwrap();
+        assert_eq!(COW_FRAMES.lock().get(&frame).unwrap().ref_count(), 2);
+        for space in [&mut parent, &mut child] {
+            let flags = space.page_flags(page).unwrap();
+            assert!(!flags.contains(PageTableFlags::WRITABLE));
+            assert!(!flags.contains(PageTableFlags::COPY_ON_WRITE));
+
+            // A write fault on text or read-only data is not resolved
+            assert_eq!(
+                cow_fault(space, page, &mut allocator),
+                Err(MapError::NotMapped)
+            );
+            assert!(
+                !space
+                    .page_flags(page)
+                    .unwrap()
+                    .contains(PageTableFlags::WRITABLE)
+            );
+        }
+
+        assert_eq!(cow_release(&mut child, page, 1), 1);
+        assert_eq!(cow_release(&mut parent, page, 1), 1);
+        assert!(COW_FRAMES.lock().get(&frame).is_none());
+    }
+
+    #[test_case]
+    fn test_cow_fault_rejects_private_pages() {
+        let mut allocator = HeapFrameAllocator::new();
+        let mut space = empty_address_space(&mut allocator);
+        let page = Page::from_start_address(VirtAddr::new(PAGE));
+        assert_eq!(
+            cow_fault(&mut space, page, &mut allocator),
+            Err(MapError::NotMapped)
+        );
+
+        let frame = allocator.allocate_frame().unwrap();
+        space
+            .map_page(page, frame, PageTableFlags::empty(), &mut allocator)
+            .unwrap();
+        assert_eq!(
+            cow_fault(&mut space, page, &mut allocator),
+            Err(MapError::NotMapped)
+        );
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Physical frame allocation
+//!
+//! Until a physical memory manager exists, frames are carved out of the
+//! kernel heap. The heap's backing memory lives in the kernel image, which
+//! is loaded below 1 GiB, so these frames are reachable through the
+//! identity mapping.
+
+use alloc::alloc::{
+    Layout,
+    alloc_zeroed,
+    dealloc,
+};
+use core::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+
+use super::{
+    address::{
+        PhysAddr,
+        PhysFrame,
+    },
+    paging::FrameAllocator,
+};
+
+/// Virtual base of the higher-half kernel mapping (see `linker.ld`)
+pub const KERNEL_VIRTUAL_BASE: u64 = 0xffff_ffff_8000_0000;
+
+/// Size of a physical frame
+pub const FRAME_SIZE: usize = 4096;
+
+/// Frames handed out by all `HeapFrameAllocator`s and not yet returned
+static FRAMES_IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of frames handed out by all `HeapFrameAllocator`s
+/// and not yet returned
+pub fn frames_in_use() -> usize {
+    FRAMES_IN_USE.load(Ordering::Relaxed)
+}
+
+/// Frame allocator backed by the kernel heap
+///
+/// Frames are zeroed. They stay allocated unless handed back with
+/// `deallocate_frame`.
+#[derive(Debug, Default)]
+pub struct HeapFrameAllocator {
+    allocated: usize,
+}
+
+impl HeapFrameAllocator {
+    /// Create a new heap frame allocator
+    pub const fn new() -> Self {
+        Self { allocated: 0 }
+    }
+
+    /// Number of frames handed out by this allocator
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// Returns a frame to the heap
+    ///
+    /// Frames outside the heap are ignored.
+    ///
+    /// # Safety
+    ///
+    /// `frame` must have come from a `HeapFrameAllocator`, must not be
+    /// freed twice and must no longer be mapped or otherwise in use.
+    pub unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let Some(page) = super::heap::heap_phys_to_virt(frame.start_address()) else {
+            return;
+        };
+        let layout = Layout::from_size_align(FRAME_SIZE, FRAME_SIZE).expect("frame layout");
+        // SAFETY: `allocate_frame` allocated the frame with this layout, as
+        // guaranteed by the caller
+        unsafe { dealloc(page as *mut u8, layout) };
+        self.allocated = self.allocated.saturating_sub(1);
+        FRAMES_IN_USE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl FrameAllocator for HeapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let layout = Layout::from_size_align(FRAME_SIZE, FRAME_SIZE).ok()?;
+        // SAFETY: the layout has a non-zero size
+        let page = unsafe { alloc_zeroed(layout) };
+        if page.is_null() {
+            return None;
+        }
+
+        self.allocated += 1;
+        FRAMES_IN_USE.fetch_add(1, Ordering::Relaxed);
+        Some(PhysFrame::from_start_address(kernel_virt_to_phys(
+            page as u64,
+        )))
+    }
+}
+
+/// Translate a kernel address to its physical address
+///
+/// Heap addresses are translated through the heap mapping. Other addresses
+/// in the higher half are in the kernel image and offset by
+/// `KERNEL_VIRTUAL_BASE`; lower addresses are identity-mapped.
+pub fn kernel_virt_to_phys(addr: u64) -> PhysAddr {
+    if let Some(phys) = super::heap::heap_virt_to_phys(addr) {
+        phys
+    } else if addr >= KERNEL_VIRTUAL_BASE {
+        PhysAddr::new(addr - KERNEL_VIRTUAL_BASE)
+    } else {
+        PhysAddr::new(addr)
+    }
+}
@@ -1,64 +1,330 @@
 //! Kernel Heap Initialization
 //!
 //! This module provides heap initialization functionality for the kernel.
-//! It sets up a global allocator using the BumpAllocator implementation.
+//! It sets up a global allocator using the BuddyAllocator implementation.
+//! The heap is mapped into the kernel half of the address space at a range
+//! allocated from `KVMA`. Every allocation also updates `HEAP_STATS`,
+//! which `report_usage` logs together with the current usage.
 
-use super::allocator::{
-    BumpAllocator,
-    Locked,
+use core::{
+    alloc::{
+        GlobalAlloc,
+        Layout,
+    },
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
 };
 
-/// Heap size (100 KB)
+use spin::Mutex;
+
+#[cfg(debug_assertions)]
+use super::kasan::Kasan;
+use super::{
+    FrameAllocator,
+    Page,
+    PageTable,
+    PageTableFlags,
+    PageTableManager,
+    PhysAddr,
+    PhysFrame,
+    VirtAddr,
+    allocator::{
+        BuddyAllocator,
+        Locked,
+    },
+    frame::kernel_virt_to_phys,
+    kvma::{
+        self,
+        KVMA,
+    },
+};
+use crate::time::periodic::PeriodicReport;
+
+/// Heap size (1 MiB)
+///
+/// Besides kernel objects, the heap backs the frames handed out by
+/// `HeapFrameAllocator`, including a 16 KiB kernel stack per process.
+pub const HEAP_SIZE: usize = 1024 * 1024;
+
+/// Heap allocator
 ///
-/// This is a reasonable initial heap size for the kernel.
-/// It will be increased as needed in future milestones.
-pub const HEAP_SIZE: usize = 100 * 1024;
+/// It uses the BuddyAllocator implementation so that freed memory is reused.
+/// `BumpAllocator` can be swapped in here for debugging.
+static ALLOCATOR: Locked<BuddyAllocator> = Locked::new(BuddyAllocator::new());
 
 /// Global allocator
 ///
 /// This is the global allocator used by all heap allocations in the kernel.
-/// It uses the BumpAllocator implementation, which is simple but sufficient
-/// for initial development.
+/// It forwards to `ALLOCATOR` and records every allocation in `HEAP_STATS`.
+/// Debug builds route allocations through `KASAN_ALLOCATOR` instead.
+#[cfg_attr(not(debug_assertions), global_allocator)]
+static HEAP_ALLOCATOR: HeapAllocator = HeapAllocator;
+
+/// `HEAP_ALLOCATOR` instrumented with redzones (see `kasan`)
+#[cfg(debug_assertions)]
 #[global_allocator]
-static ALLOCATOR: Locked<BumpAllocator> = Locked::new(BumpAllocator::new());
+static KASAN_ALLOCATOR: Kasan<HeapAllocator> = Kasan::new(&HEAP_ALLOCATOR);
+
+/// Heap statistics kept since boot
+pub static HEAP_STATS: Mutex<HeapStats> = Mutex::new(HeapStats::new());
+
+/// Interval between the periodic heap usage reports
+pub const REPORT_INTERVAL_MS: u64 = 10_000;
+
+/// Periodic `report_usage`, polled by the idle task
+pub static USAGE_REPORT: PeriodicReport = PeriodicReport::new(report_usage);
+
+/// Counters that `HeapUsage` cannot derive from the allocator's state
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStats {
+    /// Highest number of bytes in use at any one time
+    pub peak_used: usize,
+    /// Bytes requested by all allocations so far, freed ones included
+    pub total_allocated_ever: usize,
+}
+
+impl HeapStats {
+    /// Creates zeroed statistics
+    pub const fn new() -> Self {
+        Self {
+            peak_used: 0,
+            total_allocated_ever: 0,
+        }
+    }
+
+    /// Records an allocation of `size` bytes that brought the heap to
+    /// `used` bytes in use
+    fn record_allocation(&mut self, size: usize, used: usize) {
+        self.peak_used = self.peak_used.max(used);
+        self.total_allocated_ever = self.total_allocated_ever.saturating_add(size);
+    }
+}
+
+/// Forwards to `ALLOCATOR` and keeps `HEAP_STATS` up to date
+struct HeapAllocator;
+
+unsafe impl GlobalAlloc for HeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = ALLOCATOR.lock();
+        // SAFETY: forwarded from the caller
+        let ptr = unsafe { heap.allocate(layout) };
+        if !ptr.is_null() {
+            HEAP_STATS
+                .lock()
+                .record_allocation(layout.size(), heap.usage().used);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: forwarded from the caller
+        unsafe { ALLOCATOR.lock().deallocate(ptr, layout) }
+    }
+}
+
+/// Whether `init_heap` leaves unmapped guard pages at both ends of the heap
+pub const HEAP_GUARD_PAGES: bool = true;
+
+/// Size of each heap guard page
+const GUARD_PAGE_SIZE: usize = 4096;
+
+/// Flags the heap pages are mapped with
+const HEAP_FLAGS: PageTableFlags = PageTableFlags::WRITABLE.union(PageTableFlags::NO_EXECUTE);
+
+/// Page tables available for mapping the heap: a P3, a P2 and a P1 table,
+/// plus one more in case the heap straddles two P1 tables
+const HEAP_TABLE_FRAMES: usize = 4;
+
+/// Physical memory backing the heap (allocated in BSS section)
+///
+/// The BSS section is automatically zeroed by the bootloader. The heap is
+/// mapped from here to a range of the kernel address space taken from
+/// `KVMA`. It is page aligned so the buddy allocator can form large blocks.
+/// Only its address is taken here; the memory is accessed through the heap
+/// mapping alone, so the `static mut` is sound.
+static mut HEAP_BACKING: HeapBacking = HeapBacking([0; HEAP_SIZE]);
+
+/// Page-aligned backing storage for the heap
+#[repr(C, align(4096))]
+struct HeapBacking([u8; HEAP_SIZE]);
 
-/// Heap area (allocated in BSS section)
+/// Page tables for the heap mapping, which is set up before any frame can
+/// be taken from the heap
 ///
-/// This static array reserves memory for the heap in the BSS section.
-/// The BSS section is automatically zeroed by the bootloader.
-static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+/// Only their addresses are taken, to hand the frames to the page table
+/// code, which keeps the `static mut` sound.
+static mut HEAP_TABLES: [PageTable; HEAP_TABLE_FRAMES] =
+    [const { PageTable::new() }; HEAP_TABLE_FRAMES];
+
+/// Virtual start address of the heap, 0 until `init_heap` runs
+static HEAP_START: AtomicUsize = AtomicUsize::new(0);
 
 /// Initialize the kernel heap
 ///
-/// This function must be called early in the kernel initialization process,
-/// before any heap allocations are made.
+/// Initializes `KVMA`, takes a range of the kernel address space from it
+/// and maps the heap's backing memory there. This function must be called
+/// early in the kernel initialization process, before any heap allocations
+/// are made. If `HEAP_GUARD_PAGES` is set, the pages just below and above
+/// the heap are reserved but left unmapped, so any access to them faults.
 ///
-/// # Safety
+/// # Panics
 ///
-/// This function is safe to call, but it must be called only once during
-/// kernel initialization, before any heap allocations occur.
+/// Panics if called more than once or if the heap cannot be mapped.
 pub fn init_heap() {
+    kvma::init();
+
+    let guard = if HEAP_GUARD_PAGES {
+        GUARD_PAGE_SIZE as u64
+    } else {
+        0
+    };
+    let base = KVMA
+        .lock()
+        .allocate(HEAP_SIZE as u64 + 2 * guard, Page::SIZE)
+        .expect("no kernel address space for the heap");
+    let start = base + guard;
+    map_heap(start);
+    HEAP_START.store(start.as_u64() as usize, Ordering::Release);
+
+    // SAFETY: the range was just mapped to the heap's backing memory and is
+    // used for nothing else
     unsafe {
-        let heap_start = core::ptr::addr_of!(HEAP) as usize;
-        ALLOCATOR.lock().init(heap_start, HEAP_SIZE);
+        ALLOCATOR.lock().init(start.as_u64() as usize, HEAP_SIZE);
     }
 
     crate::log_debug!(
         "Heap initialized: start = {:#x}, size = {} KB",
-        core::ptr::addr_of!(HEAP) as usize,
+        start.as_u64(),
         HEAP_SIZE / 1024
     );
 }
 
+/// Physical address of a heap address, or `None` if `addr` is outside the
+/// heap
+pub fn heap_virt_to_phys(addr: u64) -> Option<PhysAddr> {
+    let start = HEAP_START.load(Ordering::Acquire) as u64;
+    if start == 0 || !(start..start + HEAP_SIZE as u64).contains(&addr) {
+        return None;
+    }
+    Some(kernel_virt_to_phys(backing_start() + (addr - start)))
+}
+
+/// Heap address of a physical address in the heap's backing memory, or
+/// `None` if `addr` lies outside it
+pub fn heap_phys_to_virt(addr: PhysAddr) -> Option<u64> {
+    let start = HEAP_START.load(Ordering::Acquire) as u64;
+    let backing = kernel_virt_to_phys(backing_start()).as_u64();
+    let offset = addr.as_u64().checked_sub(backing)?;
+    (start != 0 && offset < HEAP_SIZE as u64).then_some(start + offset)
+}
+
+/// Physical address and size of the heap's backing memory
+pub fn backing_phys_range() -> (PhysAddr, u64) {
+    (kernel_virt_to_phys(backing_start()), HEAP_SIZE as u64)
+}
+
+/// Physical address and size of the page tables mapping the heap
+pub fn table_phys_range() -> (PhysAddr, u64) {
+    let tables = VirtAddr::from_ptr(core::ptr::addr_of!(HEAP_TABLES));
+    (
+        kernel_virt_to_phys(tables.as_u64()),
+        HEAP_TABLE_FRAMES as u64 * Page::SIZE,
+    )
+}
+
+/// Start address of the heap's backing memory in the kernel image
+fn backing_start() -> u64 {
+    VirtAddr::from_ptr(core::ptr::addr_of!(HEAP_BACKING)).as_u64()
+}
+
+/// Maps the heap's backing memory to `start`
+fn map_heap(start: VirtAddr) {
+    // SAFETY: CR3 holds the active, identity-accessible P4 table
+    let mut mapper = unsafe { PageTableManager::current() };
+    let mut tables = HeapTableAllocator { next: 0 };
+
+    let first = Page::containing_address(start);
+    for i in 0..(HEAP_SIZE as u64 / Page::SIZE) {
+        let frame =
+            PhysFrame::from_start_address(kernel_virt_to_phys(backing_start() + i * Page::SIZE));
+        mapper
+            .map_page(first + i, frame, HEAP_FLAGS, &mut tables)
+            .expect("failed to map the heap");
+    }
+}
+
+/// Hands out the `HEAP_TABLES` frames
+struct HeapTableAllocator {
+    next: usize,
+}
+
+impl FrameAllocator for HeapTableAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if self.next == HEAP_TABLE_FRAMES {
+            return None;
+        }
+        // SAFETY: only the address is taken; `init_heap` runs once, so each
+        // table is handed out at most once
+        let table = VirtAddr::from_ptr(unsafe { core::ptr::addr_of!(HEAP_TABLES[self.next]) });
+        self.next += 1;
+        Some(PhysFrame::from_start_address(kernel_virt_to_phys(
+            table.as_u64(),
+        )))
+    }
+}
+
 /// Get current heap usage statistics
 ///
 /// Returns information about heap usage including total size, used size,
 /// and number of active allocations.
-#[allow(dead_code)]
 pub fn heap_usage() -> super::allocator::HeapUsage {
     ALLOCATOR.lock().usage()
 }
 
+/// Snapshot of `HEAP_STATS`
+pub fn heap_stats() -> HeapStats {
+    crate::interrupts::without_interrupts(|| *HEAP_STATS.lock())
+}
+
+/// Logs the current heap usage and `HEAP_STATS`
+///
+/// Takes the allocator lock, so interrupt handlers use
+/// `request_usage_report` instead.
+pub fn report_usage() {
+    let usage = heap_usage();
+    let stats = heap_stats();
+    crate::log_info!("Heap usage:");
+    crate::log_info!("  total:       {} bytes", usage.total);
+    crate::log_info!("  used:        {} bytes", usage.used);
+    crate::log_info!("  free:        {} bytes", usage.free);
+    crate::log_info!("  allocations: {}", usage.allocations);
+    crate::log_info!(
+        "  fragmentation: {}%",
+        (usage.fragmentation_ratio() * 100.0) as u32
+    );
+    crate::log_info!("  peak used:   {} bytes", stats.peak_used);
+    crate::log_info!(
+        "  allocated since boot: {} bytes",
+        stats.total_allocated_ever
+    );
+}
+
+/// Has the idle task run `report_usage` on its next wakeup
+///
+/// Only sets a flag, so interrupt handlers can call it.
+pub fn request_usage_report() {
+    USAGE_REPORT.request();
+}
+
+/// Reports the heap usage every `REPORT_INTERVAL_MS`
+///
+/// The reports are written by the idle task, not the timer interrupt.
+pub fn start_usage_reports() {
+    USAGE_REPORT.start(REPORT_INTERVAL_MS, crate::interrupts::timer::uptime_ms());
+}
+
 /// OOM (Out Of Memory) handler
 ///
 /// This function is called when a memory allocation fails.
@@ -67,3 +333,20 @@ pub fn heap_usage() -> super::allocator::HeapUsage {
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
     panic!("allocation error: {:?}", layout)
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+
+    #[test_case]
+    fn test_heap_stats_track_allocations() {
+        let value = Box::new(42i32);
+        drop(value);
+
+        let stats = heap_stats();
+        assert!(stats.peak_used >= core::mem::size_of::<i32>());
+        assert!(stats.total_allocated_ever >= 1);
+    }
+}
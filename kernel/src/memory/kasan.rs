@@ -0,0 +1,313 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kernel address sanitizer, lite edition
+//!
+//! In debug builds every heap allocation is surrounded by two `REDZONE`
+//! byte redzones filled with `REDZONE_PATTERN`, and recorded together with
+//! the stack that allocated it. `kasan_check` verifies an access against
+//! the allocation it falls into, and freeing an allocation checks its
+//! redzones and fills it with `FREED_PATTERN` so use-after-free reads
+//! stand out. A corrupted redzone or out-of-bounds access is logged with
+//! the allocation stack and panics, which halts the kernel.
+//!
+//! The allocation records live in a `BTreeMap` on the heap they describe.
+//! Its own nodes are allocated while KASAN is busy and bypass it; any
+//! allocation without a record is passed straight to the heap.
+//!
+//! Release builds compile all of this out and `kasan_check` does nothing.
+
+/// Whether KASAN instruments the heap in this build
+pub const ENABLED: bool = cfg!(debug_assertions);
+
+/// Bytes of redzone on either side of an allocation
+pub const REDZONE: usize = 8;
+
+/// Value the redzones are filled with
+pub const REDZONE_PATTERN: u64 = 0xdead_beef_deaf_cafe;
+
+/// Value freed allocations are filled with
+pub const FREED_PATTERN: u32 = 0xfeed_face;
+
+/// Number of return addresses recorded per allocation
+pub const STACK_DEPTH: usize = 4;
+
+/// Returns `true` if `redzone` holds `REDZONE_PATTERN`
+pub fn redzone_intact(redzone: &[u8]) -> bool {
+    redzone.len() == REDZONE && redzone == REDZONE_PATTERN.to_le_bytes()
+}
+
+/// Fills `bytes` with `FREED_PATTERN`
+pub fn poison(bytes: &mut [u8]) {
+    let pattern = FREED_PATTERN.to_le_bytes();
+    for (byte, value) in bytes.iter_mut().zip(pattern.iter().cycle()) {
+        *byte = *value;
+    }
+}
+
+#[cfg(debug_assertions)]
+pub use instrumented::{
+    Kasan,
+    kasan_check,
+};
+
+/// Checks an access of `size` bytes at `ptr` (no-op in release builds)
+#[cfg(not(debug_assertions))]
+#[inline(always)]
+pub fn kasan_check(_ptr: *const u8, _size: usize) {}
+
+#[cfg(debug_assertions)]
+mod instrumented {
+    use alloc::collections::BTreeMap;
+    use core::{
+        alloc::{
+            GlobalAlloc,
+            Layout,
+        },
+        sync::atomic::{
+            AtomicBool,
+            Ordering,
+        },
+    };
+
+    use spin::Mutex;
+
+    use super::{
+        REDZONE,
+        REDZONE_PATTERN,
+        STACK_DEPTH,
+        poison,
+        redzone_intact,
+    };
+
+    /// An instrumented allocation
+    #[derive(Debug, Clone, Copy)]
+    struct AllocationRecord {
+        /// Size requested by the caller
+        size: usize,
+        /// Return addresses of the allocating call stack, innermost first
+        stack: [u64; STACK_DEPTH],
+    }
+
+    /// Live allocations, keyed by the address handed to the caller
+    static RECORDS: Mutex<BTreeMap<usize, AllocationRecord>> = Mutex::new(BTreeMap::new());
+
+    /// Set while KASAN updates `RECORDS`, whose own allocations must bypass
+    /// it
+    static BUSY: AtomicBool = AtomicBool::new(false);
+
+    /// Global allocator wrapper adding redzones around every allocation
+    pub struct Kasan<A: 'static> {
+        inner: &'static A,
+    }
+
+    impl<A: GlobalAlloc> Kasan<A> {
+        /// Instruments the allocations of `inner`
+        pub const fn new(inner: &'static A) -> Self {
+            Self { inner }
+        }
+    }
+
+    /// Layout of the block holding an allocation and its redzones, and the
+    /// offset of the caller's memory in it
+    fn padded(layout: Layout) -> Option<(Layout, usize)> {
+        let align = layout.align().max(REDZONE);
+        let offset = align;
+        let size = offset.checked_add(layout.size())?.checked_add(REDZONE)?;
+        Some((Layout::from_size_align(size, align).ok()?, offset))
+    }
+
+    /// Runs `f` with `BUSY` set and interrupts disabled
+    ///
+    /// Returns `None` without running `f` if KASAN is already busy.
+    fn exclusive<R>(f: impl FnOnce() -> R) -> Option<R> {
+        crate::interrupts::without_interrupts(|| {
+            if BUSY.swap(true, Ordering::Acquire) {
+                return None;
+            }
+            let result = f();
+            BUSY.store(false, Ordering::Release);
+            Some(result)
+        })
+    }
+
+    /// Captures the innermost `STACK_DEPTH` return addresses
+    fn capture_stack() -> [u64; STACK_DEPTH] {
+        let mut stack = [0; STACK_DEPTH];
+        crate::debug::unwind::walk_stack(|index, frame| {
+            if let Some(slot) = stack.get_mut(index) {
+                *slot = frame.pc;
+            }
+        });
+        stack
+    }
+
+    /// Redzones of the allocation at `ptr`
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a recorded allocation of `size` bytes.
+    unsafe fn redzones(ptr: usize, size: usize) -> (&'static [u8], &'static [u8]) {
+        // SAFETY: the redzones are part of the padded block
+        unsafe {
+            (
+                core::slice::from_raw_parts((ptr - REDZONE) as *const u8, REDZONE),
+                core::slice::from_raw_parts((ptr + size) as *const u8, REDZONE),
+            )
+        }
+    }
+
+    /// Describes what is wrong with the allocation at `ptr`, if anything
+    fn corruption(ptr: usize, record: &AllocationRecord) -> Option<&'static str> {
+        // SAFETY: `ptr` is recorded with `record.size`
+        let (front, back) = unsafe { redzones(ptr, record.size) };
+        if !redzone_intact(front) {
+            Some("heap underflow: front redzone overwritten")
+        } else if !redzone_intact(back) {
+            Some("heap overflow: back redzone overwritten")
+        } else {
+            None
+        }
+    }
+
+    /// Logs `what` with the allocation's stack and halts via panic
+    fn report(what: &str, ptr: usize, record: &AllocationRecord) -> ! {
+        crate::log_fatal!(
+            "KASAN: {} in allocation at {:#x} ({} bytes)",
+            what,
+            ptr,
+            record.size
+        );
+        crate::log_fatal!("KASAN: allocated from:");
+        for (index, pc) in record.stack.iter().enumerate().filter(|(_, pc)| **pc != 0) {
+            crate::log_fatal!("  #{}: RIP={:#018x}", index, pc);
+        }
+        panic!("KASAN: {} at {:#x}", what, ptr);
+    }
+
+    /// Checks an access of `size` bytes at `ptr`
+    ///
+    /// An access that touches an allocation's redzones, or an allocation
+    /// whose redzones were overwritten, is reported and halts the kernel.
+    /// Addresses outside the heap's allocations are not checked.
+    pub fn kasan_check(ptr: *const u8, size: usize) {
+        let addr = ptr as usize;
+        let found = exclusive(|| {
+            let records = RECORDS.lock();
+            // The allocation starting at or below the access, or the next
+            // one if the access begins in its front redzone
+            let below = records.range(..=addr).next_back();
+            let above = records.range(addr + 1..).next();
+            below
+                .into_iter()
+                .chain(above)
+                .find(|(&start, record)| {
+                    addr < start + record.size + REDZONE && addr + size > start - REDZONE
+                })
+                .map(|(&start, record)| (start, *record))
+        })
+        .flatten();
+
+        let Some((start, record)) = found else {
+            return;
+        };
+        if let Some(what) = corruption(start, &record) {
+            report(what, start, &record);
+        }
+        if addr < start || addr + size > start + record.size {
+            report("out-of-bounds access", start, &record);
+        }
+    }
+
+    unsafe impl<A: GlobalAlloc> GlobalAlloc for Kasan<A> {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let Some((padded, offset)) = padded(layout) else {
+                return core::ptr::null_mut();
+            };
+            let record = exclusive(|| {
+                // SAFETY: forwarded from the caller
+                let base = unsafe { self.inner.alloc(padded) };
+                if base.is_null() {
+                    return base;
+                }
+                let ptr = base as usize + offset;
+                // SAFETY: both redzones lie within the padded block
+                unsafe {
+                    ((ptr - REDZONE) as *mut u64).write_unaligned(REDZONE_PATTERN);
+                    ((ptr + layout.size()) as *mut u64).write_unaligned(REDZONE_PATTERN);
+                }
+                let record = AllocationRecord {
+                    size: layout.size(),
+                    stack: capture_stack(),
+                };
+                RECORDS.lock().insert(ptr, record);
+                ptr as *mut u8
+            });
+            // SAFETY: forwarded from the caller
+            record.unwrap_or_else(|| unsafe { self.inner.alloc(layout) })
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let handled = exclusive(|| {
+                let Some(record) = RECORDS.lock().remove(&(ptr as usize)) else {
+                    return false;
+                };
+                if let Some(what) = corruption(ptr as usize, &record) {
+                    BUSY.store(false, Ordering::Release);
+                    report(what, ptr as usize, &record);
+                }
+                // SAFETY: the caller owns the allocation until now
+                poison(unsafe { core::slice::from_raw_parts_mut(ptr, record.size) });
+                let (padded, offset) = padded(layout).expect("recorded layout is valid");
+                // SAFETY: recorded allocations were made with this layout
+                unsafe { self.inner.dealloc(ptr.sub(offset), padded) };
+                true
+            });
+            if handled != Some(true) {
+                // SAFETY: unrecorded allocations came straight from `inner`
+                unsafe { self.inner.dealloc(ptr, layout) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_redzone_pattern() {
+        let mut redzone = REDZONE_PATTERN.to_le_bytes();
+        assert!(redzone_intact(&redzone));
+        redzone[0] ^= 1;
+        assert!(!redzone_intact(&redzone));
+        assert!(!redzone_intact(&redzone[..4]));
+    }
+
+    #[test_case]
+    fn test_poison_repeats_pattern() {
+        let mut bytes = [0u8; 10];
+        poison(&mut bytes);
+        assert_eq!(bytes, [
+            0xce, 0xfa, 0xed, 0xfe, 0xce, 0xfa, 0xed, 0xfe, 0xce, 0xfa
+        ]);
+    }
+
+    #[test_case]
+    fn test_intact_allocation_passes_check() {
+        let buffer = alloc::vec![0u8; 13];
+        kasan_check(buffer.as_ptr(), buffer.len());
+        kasan_check(buffer[12..].as_ptr(), 1);
+    }
+}
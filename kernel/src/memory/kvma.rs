@@ -0,0 +1,307 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kernel virtual address space allocator
+//!
+//! Hands out ranges of the kernel half of the address space for mappings
+//! that do not live in the kernel image, such as the heap. Only virtual
+//! addresses are managed; callers map the ranges themselves.
+//!
+//! The allocator runs before the heap exists, so free ranges are kept in a
+//! fixed-size table rather than a heap-allocated list.
+
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+
+use spin::Mutex;
+
+use super::{
+    Page,
+    VirtAddr,
+};
+
+/// Start of the kernel half of the 48-bit address space
+pub const KVMA_START: u64 = 0xffff_8000_0000_0000;
+
+/// End of the managed range: the last P4 slot holds the kernel image
+pub const KVMA_END: u64 = 0xffff_ff80_0000_0000;
+
+/// Maximum number of disjoint free ranges tracked
+pub const KVMA_MAX_RANGES: usize = 64;
+
+/// Global kernel virtual address space allocator
+///
+/// Empty until `init` is called.
+pub static KVMA: Mutex<KernelVma> = Mutex::new(KernelVma::new());
+
+/// Set once `init` has handed the kernel half to `KVMA`
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// A free range `[base, end)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FreeRange {
+    base: u64,
+    end: u64,
+}
+
+/// Allocator of page-aligned virtual address ranges
+///
+/// Free ranges are kept sorted and coalesced, so freeing a range next to
+/// free space merges them back together.
+#[derive(Debug)]
+pub struct KernelVma {
+    free: [FreeRange; KVMA_MAX_RANGES],
+    len: usize,
+}
+
+impl KernelVma {
+    /// Creates an allocator with no free space
+    pub const fn new() -> Self {
+        Self {
+            free: [FreeRange { base: 0, end: 0 }; KVMA_MAX_RANGES],
+            len: 0,
+        }
+    }
+
+    /// Allocates `size` bytes aligned to `align`
+    ///
+    /// `size` is rounded up to whole pages and `align` to at least a page.
+    /// The lowest fitting range is used.
+    ///
+    /// # Returns
+    ///
+    /// The start of the range, or `None` if no free range fits or `align`
+    /// is not a power of two
+    pub fn allocate(&mut self, size: u64, align: u64) -> Option<VirtAddr> {
+        if size == 0 || !align.is_power_of_two() {
+            return None;
+        }
+        let size = size.checked_next_multiple_of(Page::SIZE)?;
+        let align = align.max(Page::SIZE);
+
+        for i in 0..self.len {
+            let range = self.free[i];
+            let Some(start) = range.base.checked_next_multiple_of(align) else {
+                continue;
+            };
+            let Some(end) = start.checked_add(size) else {
+                continue;
+            };
+            if end > range.end {
+                continue;
+            }
+
+            let head = FreeRange {
+                base: range.base,
+                end: start,
+            };
+            let tail = FreeRange {
+                base: end,
+                end: range.end,
+            };
+            match (head.base < head.end, tail.base < tail.end) {
+                (false, false) => self.remove(i),
+                (true, false) => self.free[i] = head,
+                (false, true) => self.free[i] = tail,
+                (true, true) => {
+                    // Splitting needs a table slot; try the next range
+                    if self.len == KVMA_MAX_RANGES {
+                        continue;
+                    }
+                    self.free[i] = head;
+                    self.insert(i + 1, tail);
+                }
+            }
+            return Some(VirtAddr::new(start));
+        }
+        None
+    }
+
+    /// Returns `size` bytes from `base` to the free space
+    ///
+    /// `size` is rounded up to whole pages, as in `allocate`. If the range
+    /// cannot be merged with a neighbour and the table is full, it is
+    /// leaked with a warning.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base` is not page-aligned or the range overlaps free
+    /// space, such as when it is freed twice.
+    pub fn free(&mut self, base: VirtAddr, size: u64) {
+        assert!(
+            base.is_aligned(Page::SIZE),
+            "unaligned range {:#x}",
+            base.as_u64()
+        );
+        let Some(end) = size
+            .checked_next_multiple_of(Page::SIZE)
+            .and_then(|size| base.as_u64().checked_add(size))
+        else {
+            panic!("range at {:#x} wraps around", base.as_u64());
+        };
+        let range = FreeRange {
+            base: base.as_u64(),
+            end,
+        };
+        if range.base == range.end {
+            return;
+        }
+
+        let index = self.free[..self.len].partition_point(|r| r.base < range.base);
+        let prev = index.checked_sub(1).map(|i| self.free[i]);
+        let next = (index < self.len).then(|| self.free[index]);
+        assert!(
+            prev.is_none_or(|prev| prev.end <= range.base)
+                && next.is_none_or(|next| range.end <= next.base),
+            "range {:#x}..{:#x} is already free",
+            range.base,
+            range.end
+        );
+
+        let merge_prev = prev.is_some_and(|prev| prev.end == range.base);
+        let merge_next = next.is_some_and(|next| next.base == range.end);
+        match (merge_prev, merge_next) {
+            (true, true) => {
+                self.free[index - 1].end = self.free[index].end;
+                self.remove(index);
+            }
+            (true, false) => self.free[index - 1].end = range.end,
+            (false, true) => self.free[index].base = range.base,
+            (false, false) if self.len == KVMA_MAX_RANGES => {
+                crate::log_warn!(
+                    "KVMA table full, leaking {:#x}..{:#x}",
+                    range.base,
+                    range.end
+                );
+            }
+            (false, false) => self.insert(index, range),
+        }
+    }
+
+    /// Total number of free bytes
+    pub fn free_bytes(&self) -> u64 {
+        self.free[..self.len]
+            .iter()
+            .map(|range| range.end - range.base)
+            .sum()
+    }
+
+    /// Number of disjoint free ranges
+    pub fn free_ranges(&self) -> usize {
+        self.len
+    }
+
+    /// Inserts `range` at `index`, shifting later ranges up
+    fn insert(&mut self, index: usize, range: FreeRange) {
+        self.free.copy_within(index..self.len, index + 1);
+        self.free[index] = range;
+        self.len += 1;
+    }
+
+    /// Removes the range at `index`, shifting later ranges down
+    fn remove(&mut self, index: usize) {
+        self.free.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+    }
+}
+
+impl Default for KernelVma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hands the kernel half of the address space to `KVMA`
+///
+/// Called by `init_heap`, which takes the first range.
+///
+/// # Panics
+///
+/// Panics if called more than once.
+pub fn init() {
+    assert!(
+        !INITIALIZED.swap(true, Ordering::AcqRel),
+        "KVMA already initialized"
+    );
+    KVMA.lock()
+        .free(VirtAddr::new(KVMA_START), KVMA_END - KVMA_START);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = 0xffff_9000_0000_0000;
+    const SIZE: u64 = 64 * Page::SIZE;
+
+    fn vma() -> KernelVma {
+        let mut vma = KernelVma::new();
+        vma.free(VirtAddr::new(BASE), SIZE);
+        vma
+    }
+
+    #[test_case]
+    fn test_allocations_do_not_overlap() {
+        let mut vma = vma();
+        let a = vma.allocate(Page::SIZE, Page::SIZE).unwrap();
+        let b = vma.allocate(3 * Page::SIZE, Page::SIZE).unwrap();
+        let c = vma.allocate(100, 1).unwrap();
+        assert_eq!(a.as_u64(), BASE);
+        assert_eq!(b.as_u64(), BASE + Page::SIZE);
+        assert_eq!(c.as_u64(), BASE + 4 * Page::SIZE);
+        assert_eq!(vma.free_bytes(), SIZE - 5 * Page::SIZE);
+
+        assert!(vma.allocate(SIZE, Page::SIZE).is_none());
+        assert!(vma.allocate(0, Page::SIZE).is_none());
+        assert!(vma.allocate(Page::SIZE, 3).is_none());
+    }
+
+    #[test_case]
+    fn test_free_middle_and_reallocate() {
+        let mut vma = vma();
+        let a = vma.allocate(2 * Page::SIZE, Page::SIZE).unwrap();
+        let b = vma.allocate(2 * Page::SIZE, Page::SIZE).unwrap();
+        let c = vma.allocate(2 * Page::SIZE, Page::SIZE).unwrap();
+
+        vma.free(b, 2 * Page::SIZE);
+        assert_eq!(vma.free_ranges(), 2);
+        assert_eq!(vma.allocate(2 * Page::SIZE, Page::SIZE), Some(b));
+        assert_eq!(vma.free_ranges(), 1);
+
+        // Freeing everything coalesces back into the original range
+        vma.free(a, 2 * Page::SIZE);
+        vma.free(c, 2 * Page::SIZE);
+        vma.free(b, 2 * Page::SIZE);
+        assert_eq!(vma.free_ranges(), 1);
+        assert_eq!(vma.free_bytes(), SIZE);
+        assert_eq!(vma.allocate(SIZE, Page::SIZE), Some(VirtAddr::new(BASE)));
+    }
+
+    #[test_case]
+    fn test_alignment_splits_range() {
+        let mut vma = vma();
+        vma.allocate(Page::SIZE, Page::SIZE).unwrap();
+        let aligned = vma.allocate(Page::SIZE, 16 * Page::SIZE).unwrap();
+        assert_eq!(aligned.as_u64(), BASE + 16 * Page::SIZE);
+        // The gap below the aligned range stays free
+        assert_eq!(vma.free_ranges(), 2);
+        assert_eq!(
+            vma.allocate(15 * Page::SIZE, Page::SIZE),
+            Some(VirtAddr::new(BASE + Page::SIZE))
+        );
+        assert_eq!(vma.free_ranges(), 1);
+    }
+}
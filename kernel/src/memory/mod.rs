@@ -14,19 +14,39 @@
 
 pub mod address;
 pub mod allocator;
+pub mod cow;
+pub mod frame;
 pub mod heap;
+pub mod kasan;
+pub mod kvma;
 pub mod paging;
+pub mod phys_map;
+pub mod slab;
 
 #[allow(unused_imports)]
 pub use address::{
+    FrameRange,
+    FrameRangeInclusive,
     Page,
+    PageRange,
+    PageRangeInclusive,
     PhysAddr,
     PhysFrame,
+    PhysRange,
+    USER_SPACE_END,
+    USER_SPACE_START,
     VirtAddr,
 };
+pub use frame::{
+    HeapFrameAllocator,
+    frames_in_use,
+};
 pub use heap::init_heap;
 #[allow(unused_imports)]
 pub use paging::{
+    FrameAllocator,
+    MMIO_FLAGS,
+    MapError,
     PageTable,
     PageTableEntry,
     PageTableFlags,
@@ -1,11 +1,15 @@
 #![allow(dead_code)]
 
+use alloc::vec::Vec;
+use core::fmt;
+
 use bitflags::bitflags;
 
 use super::address::{
     Page,
     PhysAddr,
     PhysFrame,
+    USER_SPACE_START,
     VirtAddr,
 };
 
@@ -31,6 +35,11 @@ bitflags! {
         const HUGE_PAGE =       1 << 7;
         /// Page is global
         const GLOBAL =          1 << 8;
+        /// Page shares its frame copy-on-write (available to software)
+        const COPY_ON_WRITE =   1 << 9;
+        /// Page shares its read-only frame with other address spaces
+        /// (available to software)
+        const SHARED =          1 << 10;
         /// Disable execution on this page
         const NO_EXECUTE =      1 << 63;
     }
@@ -63,9 +72,10 @@ impl PageTableEntry {
     /// Get the physical frame mapped by this entry
     pub fn frame(&self) -> Option<PhysFrame> {
         if self.flags().contains(PageTableFlags::PRESENT) {
-            // Extract the physical address (bits 12-51)
-            let addr = self.entry & 0x000f_ffff_ffff_f000;
-            Some(PhysFrame::containing_address(PhysAddr::new(addr)))
+            // The physical address is in bits 12-51
+            Some(PhysFrame::containing_address(PhysAddr::from_u64_truncate(
+                self.entry,
+            )))
         } else {
             None
         }
@@ -156,6 +166,70 @@ impl core::fmt::Debug for PageTable {
     }
 }
 
+/// Flags of a guard page
+///
+/// The page stays present so that it still translates, but it is read-only
+/// and non-executable: with CR0.WP set, any write to it (such as a stack
+/// push past the end of a stack) raises a page fault. x86 has no way to
+/// make a present page unreadable.
+pub const GUARD_PAGE_FLAGS: PageTableFlags =
+    PageTableFlags::PRESENT.union(PageTableFlags::NO_EXECUTE);
+
+/// Flags of memory-mapped device registers: uncached and non-executable
+pub const MMIO_FLAGS: PageTableFlags = PageTableFlags::WRITABLE
+    .union(PageTableFlags::NO_CACHE)
+    .union(PageTableFlags::WRITE_THROUGH)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// First P4 entry of user space; the entries below hold the kernel's
+/// identity map and are shared by every address space
+const USER_P4_START: usize = (USER_SPACE_START >> 39) as usize;
+
+/// Size of the region mapped by a huge P3 entry
+const HUGE_1GIB: u64 = 1 << 30;
+/// Size of the region mapped by a huge P2 entry
+pub const HUGE_2MIB: u64 = 1 << 21;
+
+/// Errors returned by bulk mapping operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// A page in the range is already mapped
+    AlreadyMapped,
+    /// A page in the range is not mapped
+    NotMapped,
+    /// The frame allocator ran out of frames for a page table
+    FrameAllocationFailed,
+    /// The start page or frame is not 4 KiB aligned, or the range does not
+    /// fit in the canonical or physical address space
+    InvalidAlignment,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyMapped => write!(f, "page is already mapped"),
+            Self::NotMapped => write!(f, "page is not mapped"),
+            Self::FrameAllocationFailed => write!(f, "out of frames for page tables"),
+            Self::InvalidAlignment => write!(f, "address is unaligned or the range does not fit"),
+        }
+    }
+}
+
+/// Source of physical frames for new page tables
+pub trait FrameAllocator {
+    /// Allocate a 4 KiB frame, or `None` if no memory is left
+    ///
+    /// The frame must be accessible through the identity mapping, since
+    /// page tables are walked through physical addresses.
+    fn allocate_frame(&mut self) -> Option<PhysFrame>;
+}
+
+extern "C" {
+    /// P4 table set up by the boot code, in the `.boot` section, which is
+    /// linked at its physical address
+    static boot_p4_table: PageTable;
+}
+
 /// Page table manager
 pub struct PageTableManager {
     p4_table: &'static mut PageTable,
@@ -171,9 +245,9 @@ impl PageTableManager {
         let cr3: u64;
         core::arch::asm!("mov {}, cr3", out(reg) cr3);
 
-        // Extract the page table address (bits 12-51)
-        let p4_table_addr = cr3 & 0x000f_ffff_ffff_f000;
-        let p4_table = &mut *(p4_table_addr as *mut PageTable);
+        // The page table address is in bits 12-51
+        let p4_table_addr = PhysAddr::from_u64_truncate(cr3).align_down(Page::SIZE);
+        let p4_table = &mut *Self::table_ptr(p4_table_addr);
 
         Self { p4_table }
     }
@@ -187,25 +261,142 @@ impl PageTableManager {
         Self { p4_table }
     }
 
+    /// Create a new address space sharing this one's kernel mappings
+    ///
+    /// User space (P4 entries from `USER_SPACE_START` up to 255) of the new
+    /// P4 table is empty. The other entries, the upper half and the
+    /// identity map below `USER_SPACE_START`, point to the same P3 tables
+    /// as this page table, so kernel mappings stay in sync and the kernel
+    /// keeps running when the new table is loaded into CR3.
+    pub fn new_address_space(
+        &self,
+        frame_allocator: &mut impl FrameAllocator,
+    ) -> Result<Self, &'static str> {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or("Out of frames for P4 table")?;
+        // SAFETY: the allocator hands out unused, identity-accessible frames
+        let p4_table = unsafe { &mut *Self::table_ptr(frame.start_address()) };
+
+        p4_table.zero();
+        for index in (0..USER_P4_START).chain(256..512) {
+            p4_table[index] = self.p4_table[index];
+        }
+        Ok(Self { p4_table })
+    }
+
+    /// Physical address of the P4 table, as loaded into CR3
+    pub fn p4_address(&self) -> PhysAddr {
+        PhysAddr::new(VirtAddr::from_ptr(&*self.p4_table).as_u64())
+    }
+
+    /// Physical address of the kernel's own P4 table, set up at boot
+    ///
+    /// Kernel threads run on it; every address space made from it by
+    /// `new_address_space` shares its kernel mappings.
+    pub fn kernel_p4_address() -> PhysAddr {
+        PhysAddr::new(VirtAddr::from_ptr(core::ptr::addr_of!(boot_p4_table)).as_u64())
+    }
+
+    /// Load the P4 table at `p4` into CR3, unless it is already loaded
+    ///
+    /// Reloading the active table would needlessly flush the TLB.
+    ///
+    /// # Safety
+    ///
+    /// `p4` must be the kernel's P4 table or one made from it by
+    /// `new_address_space`, so that the kernel stays mapped, and it must
+    /// not be torn down while loaded.
+    pub unsafe fn activate(p4: PhysAddr) {
+        let cr3: u64;
+        // SAFETY: reading CR3 has no side effects
+        unsafe { core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack)) };
+        if PhysAddr::from_u64_truncate(cr3).align_down(Page::SIZE) == p4 {
+            return;
+        }
+        // SAFETY: the table keeps the kernel mapped, as guaranteed by the
+        // caller
+        unsafe {
+            core::arch::asm!("mov cr3, {}", in(reg) p4.as_u64(), options(nostack));
+        }
+    }
+
+    /// Tear down an address space created by `new_address_space`
+    ///
+    /// Every 4 KiB page mapped in user space is passed to `release` with
+    /// its frame and flags. The user-space page tables and the P4 table are
+    /// then passed to `free_table`. The kernel mappings are shared with
+    /// every other address space and left alone, as are huge pages.
+    ///
+    /// # Safety
+    ///
+    /// The address space must not be loaded in CR3 or used afterwards, and
+    /// no other address space may share its user-space tables.
+    pub unsafe fn destroy(
+        self,
+        mut release: impl FnMut(Page, PhysFrame, PageTableFlags),
+        mut free_table: impl FnMut(PhysFrame),
+    ) {
+        for p4_index in USER_P4_START..256 {
+            let Some(p3_frame) = Self::table_frame(&self.p4_table[p4_index]) else {
+                continue;
+            };
+            // SAFETY: the entry points to a P3 table of this address space
+            let p3 = unsafe { &*Self::table_ptr(p3_frame.start_address()) };
+            for p3_index in 0..512 {
+                let Some(p2_frame) = Self::table_frame(&p3[p3_index]) else {
+                    continue;
+                };
+                // SAFETY: as above, for a P2 table
+                let p2 = unsafe { &*Self::table_ptr(p2_frame.start_address()) };
+                for p2_index in 0..512 {
+                    let Some(p1_frame) = Self::table_frame(&p2[p2_index]) else {
+                        continue;
+                    };
+                    // SAFETY: as above, for a P1 table
+                    let p1 = unsafe { &*Self::table_ptr(p1_frame.start_address()) };
+                    for (p1_index, entry) in p1.iter().enumerate() {
+                        let Some(frame) = entry.frame() else {
+                            continue;
+                        };
+                        let addr = (p4_index as u64) << 39
+                            | (p3_index as u64) << 30
+                            | (p2_index as u64) << 21
+                            | (p1_index as u64) << 12;
+                        release(
+                            Page::from_start_address(VirtAddr::new(addr)),
+                            frame,
+                            entry.flags(),
+                        );
+                    }
+                    free_table(p1_frame);
+                }
+                free_table(p2_frame);
+            }
+            free_table(p3_frame);
+        }
+        free_table(PhysFrame::containing_address(self.p4_address()));
+    }
+
     /// Map a page to a physical frame
+    ///
+    /// Missing intermediate tables are allocated from `frame_allocator`.
+    /// They are writable, and user-accessible if `flags` contains
+    /// `USER_ACCESSIBLE`, so that the leaf entry alone decides access.
     pub fn map_page(
         &mut self,
         page: Page,
         frame: PhysFrame,
         flags: PageTableFlags,
+        frame_allocator: &mut impl FrameAllocator,
     ) -> Result<(), &'static str> {
-        // Get the P4 table address
-        let p4_addr = self.p4_table as *mut PageTable;
-
-        // Traverse the page table hierarchy, creating tables as needed
-        // We use raw pointers to avoid multiple mutable borrows
-        let p4 = unsafe { &mut *p4_addr };
-        let p3_addr = Self::next_table_create_ptr(p4, page.p4_index())?;
-        let p3 = unsafe { &mut *p3_addr };
-        let p2_addr = Self::next_table_create_ptr(p3, page.p3_index())?;
-        let p2 = unsafe { &mut *p2_addr };
-        let p1_addr = Self::next_table_create_ptr(p2, page.p2_index())?;
-        let p1 = unsafe { &mut *p1_addr };
+        let parent_flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | (flags & PageTableFlags::USER_ACCESSIBLE);
+        // SAFETY: the pointer is to a P1 table of this address space,
+        // reached through the identity map, and no other reference to it
+        // is live while `self` is borrowed mutably
+        let p1 = unsafe { &mut *self.p1_table_create_ptr(page, parent_flags, frame_allocator)? };
 
         // Check if the page is already mapped
         if !p1[page.p1_index()].is_unused() {
@@ -221,15 +412,258 @@ impl PageTableManager {
         Ok(())
     }
 
+    /// Identity-map a page of device registers with `MMIO_FLAGS`
+    ///
+    /// Succeeds without changes if the frame is already identity-mapped,
+    /// such as by the boot page tables.
+    pub fn identity_map_mmio(
+        &mut self,
+        frame: PhysFrame,
+        frame_allocator: &mut impl FrameAllocator,
+    ) -> Result<(), &'static str> {
+        let addr = VirtAddr::new(frame.start_address().as_u64());
+        if self.translate_addr(addr) == Some(frame.start_address()) {
+            return Ok(());
+        }
+        self.map_page(
+            Page::containing_address(addr),
+            frame,
+            MMIO_FLAGS,
+            frame_allocator,
+        )
+    }
+
+    /// Map a 2MB huge page
+    ///
+    /// The mapping is a `HUGE_PAGE` entry in the P2 table, so no P1 table is
+    /// allocated. Missing P3 and P2 tables are allocated from
+    /// `frame_allocator`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `page` or `frame` is not 2MB aligned, or if any part of the
+    /// 2MB region is already mapped.
+    pub fn map_huge_page(
+        &mut self,
+        page: Page,
+        frame: PhysFrame,
+        flags: PageTableFlags,
+        frame_allocator: &mut impl FrameAllocator,
+    ) -> Result<(), &'static str> {
+        if !page.start_address().is_aligned(HUGE_2MIB)
+            || !frame.start_address().is_aligned(HUGE_2MIB)
+        {
+            return Err("Huge page is not 2MB aligned");
+        }
+
+        let parent_flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | (flags & PageTableFlags::USER_ACCESSIBLE);
+        let p4 = &mut *self.p4_table;
+        let p3 =
+            Self::next_table_create_ptr(p4, page.p4_index(), 0, parent_flags, frame_allocator)?;
+        // SAFETY: `next_table_create_ptr` returns an identity-accessible
+        // table of this address space, which `self` borrows mutably
+        let p3 = unsafe { &mut *p3 };
+        let p2 = Self::next_table_create_ptr(
+            p3,
+            page.p3_index(),
+            HUGE_1GIB,
+            parent_flags,
+            frame_allocator,
+        )?;
+        // SAFETY: as above, for the P2 table
+        let p2 = unsafe { &mut *p2 };
+
+        let entry = &mut p2[page.p2_index()];
+        if !entry.is_unused() {
+            return Err("Page already mapped");
+        }
+        entry.set_frame(
+            frame,
+            flags | PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE,
+        );
+
+        Self::flush_tlb(page.start_address());
+        Ok(())
+    }
+
+    /// Map `count` contiguous pages to contiguous frames
+    ///
+    /// Page `start + i` is mapped to frame `frame_start + i`. Either all
+    /// pages are mapped or none are: on failure, the pages already mapped
+    /// by this call are unmapped again. Page tables allocated on the way
+    /// are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MapError::InvalidAlignment` if the range is misaligned,
+    /// `MapError::AlreadyMapped` if a page in the range is mapped, and
+    /// `MapError::FrameAllocationFailed` if a page table could not be
+    /// allocated.
+    pub fn map_range(
+        &mut self,
+        start: Page,
+        frame_start: PhysFrame,
+        count: usize,
+        flags: PageTableFlags,
+        frame_allocator: &mut impl FrameAllocator,
+    ) -> Result<(), MapError> {
+        let end = Self::check_range(start, count)?;
+        if !frame_start.start_address().is_aligned(PhysFrame::SIZE) {
+            return Err(MapError::InvalidAlignment);
+        }
+        let frame_end = frame_start
+            .start_address()
+            .checked_add(count as u64 * PhysFrame::SIZE)
+            .ok_or(MapError::InvalidAlignment)?;
+
+        let parent_flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | (flags & PageTableFlags::USER_ACCESSIBLE);
+        let frames = PhysFrame::range(frame_start, PhysFrame::from_start_address(frame_end));
+        for (page, frame) in Page::range(start, end).zip(frames) {
+            let result = self
+                .p1_table_create_ptr(page, parent_flags, frame_allocator)
+                .map_err(|_| MapError::FrameAllocationFailed)
+                .and_then(|p1| {
+                    // SAFETY: the pointer comes from walking our own tables
+                    let p1 = unsafe { &mut *p1 };
+                    let entry = &mut p1[page.p1_index()];
+                    if !entry.is_unused() {
+                        return Err(MapError::AlreadyMapped);
+                    }
+                    entry.set_frame(frame, flags | PageTableFlags::PRESENT);
+                    Ok(())
+                });
+
+            if let Err(err) = result {
+                for mapped in Page::range(start, page) {
+                    let _ = self.unmap_page(mapped);
+                }
+                return Err(err);
+            }
+            Self::flush_tlb(page.start_address());
+        }
+        Ok(())
+    }
+
+    /// Unmap `count` contiguous pages
+    ///
+    /// Nothing is unmapped unless every page in the range is mapped.
+    ///
+    /// # Returns
+    ///
+    /// The frames the pages were mapped to, in page order
+    ///
+    /// # Errors
+    ///
+    /// Returns `MapError::InvalidAlignment` if the range is misaligned, or
+    /// `MapError::NotMapped` if a page in the range is not mapped.
+    pub fn unmap_range(&mut self, start: Page, count: usize) -> Result<Vec<PhysFrame>, MapError> {
+        let end = Self::check_range(start, count)?;
+        let pages = Page::range(start, end);
+        if pages
+            .clone()
+            .any(|page| self.translate_addr(page.start_address()).is_none())
+        {
+            return Err(MapError::NotMapped);
+        }
+
+        pages
+            .map(|page| self.unmap_page(page).map_err(|_| MapError::NotMapped))
+            .collect()
+    }
+
+    /// Turn a mapped page into a guard page
+    ///
+    /// The page keeps its frame but gets `GUARD_PAGE_FLAGS`, so writes to
+    /// it fault. A huge page containing it is split into 4 KiB pages first,
+    /// with page tables allocated from `frame_allocator`.
+    pub fn map_guard_page(
+        &mut self,
+        page: Page,
+        frame_allocator: &mut impl FrameAllocator,
+    ) -> Result<(), &'static str> {
+        let parent_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        // SAFETY: as in `map_page`, the P1 table belongs to this address
+        // space and `self` is borrowed mutably
+        let p1 = unsafe { &mut *self.p1_table_create_ptr(page, parent_flags, frame_allocator)? };
+
+        let entry = &mut p1[page.p1_index()];
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return Err("Guard page is not mapped");
+        }
+        let frame = entry.frame().ok_or("Entry not present")?;
+        entry.set_frame(frame, GUARD_PAGE_FLAGS);
+
+        Self::flush_tlb(page.start_address());
+        Ok(())
+    }
+
+    /// Change the flags of a mapped page
+    ///
+    /// The page keeps its frame; only the flag bits of its entry are
+    /// replaced. `PRESENT` is always set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MapError::NotMapped` if the page is not mapped by a 4 KiB
+    /// entry. Huge pages have to be split first, as `map_guard_page` does.
+    pub fn set_flags(&mut self, page: Page, flags: PageTableFlags) -> Result<(), MapError> {
+        let entry = self.p1_entry_mut(page).ok_or(MapError::NotMapped)?;
+        entry.set_flags(flags | PageTableFlags::PRESENT);
+        Self::flush_tlb(page.start_address());
+        Ok(())
+    }
+
+    /// Get the flags of a page mapped by a 4 KiB entry
+    pub fn page_flags(&mut self, page: Page) -> Option<PageTableFlags> {
+        self.p1_entry_mut(page).map(|entry| entry.flags())
+    }
+
+    /// Find the present 4 KiB entry mapping `page` without creating tables
+    fn p1_entry_mut(&mut self, page: Page) -> Option<&mut PageTableEntry> {
+        let p4 = &*self.p4_table;
+        // SAFETY: `next_table_ptr` returns an identity-accessible table of
+        // this address space, which `self` borrows mutably
+        let p3 = unsafe { &*Self::next_table_ptr(p4, page.p4_index())? };
+        if p3[page.p3_index()]
+            .flags()
+            .contains(PageTableFlags::HUGE_PAGE)
+        {
+            return None;
+        }
+        // SAFETY: as above, for the P2 table
+        let p2 = unsafe { &*Self::next_table_ptr(p3, page.p3_index())? };
+        if p2[page.p2_index()]
+            .flags()
+            .contains(PageTableFlags::HUGE_PAGE)
+        {
+            return None;
+        }
+        let p1 = Self::next_table_ptr(p2, page.p2_index())?;
+        // SAFETY: the pointer comes from walking our own tables
+        let p1 = unsafe { &mut *(p1 as *mut PageTable) };
+        let entry = &mut p1[page.p1_index()];
+        entry
+            .flags()
+            .contains(PageTableFlags::PRESENT)
+            .then_some(entry)
+    }
+
     /// Unmap a page
     pub fn unmap_page(&mut self, page: Page) -> Result<PhysFrame, &'static str> {
         // Traverse the page table hierarchy
         let p4 = &*self.p4_table;
         let p3 = Self::next_table_ptr(p4, page.p4_index()).ok_or("P3 table not present")?;
+        // SAFETY: the pointers come from walking our own tables
         let p3 = unsafe { &*p3 };
         let p2 = Self::next_table_ptr(p3, page.p3_index()).ok_or("P2 table not present")?;
+        // SAFETY: as above
         let p2 = unsafe { &*p2 };
         let p1 = Self::next_table_ptr(p2, page.p2_index()).ok_or("P1 table not present")?;
+        // SAFETY: as above
         let p1 = unsafe { &mut *(p1 as *mut PageTable) };
 
         // Get the entry
@@ -252,14 +686,30 @@ impl PageTableManager {
     }
 
     /// Translate a virtual address to a physical address
+    ///
+    /// Huge pages are handled at the P3 (1GB) and P2 (2MB) levels.
     pub fn translate_addr(&self, addr: VirtAddr) -> Option<PhysAddr> {
         // Traverse the page table hierarchy
         let p4 = &*self.p4_table;
         let p3 = Self::next_table_ptr(p4, addr.p4_index())?;
+        // SAFETY: the pointers come from walking our own tables
         let p3 = unsafe { &*p3 };
+        let p3_entry = &p3[addr.p3_index()];
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            let base = p3_entry.frame()?.start_address().align_down(HUGE_1GIB);
+            return Some(base + (addr.as_u64() & (HUGE_1GIB - 1)));
+        }
         let p2 = Self::next_table_ptr(p3, addr.p3_index())?;
+        // SAFETY: as above
         let p2 = unsafe { &*p2 };
+        let p2_entry = &p2[addr.p2_index()];
+        if p2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            // Bit 12 of a huge entry is the PAT bit, not part of the address
+            let base = p2_entry.frame()?.start_address().align_down(HUGE_2MIB);
+            return Some(base + addr.p2_offset() as u64);
+        }
         let p1 = Self::next_table_ptr(p2, addr.p2_index())?;
+        // SAFETY: as above
         let p1 = unsafe { &*p1 };
 
         // Get the entry
@@ -276,21 +726,163 @@ impl PageTableManager {
         Some(frame.start_address() + offset)
     }
 
+    /// Check that code running in ring 3 may access `addr`
+    ///
+    /// As the CPU requires, the entry mapping `addr` and every table entry
+    /// above it must be present and `USER_ACCESSIBLE`, and also `WRITABLE`
+    /// if `write` is set.
+    pub fn is_user_accessible(&self, addr: VirtAddr, write: bool) -> bool {
+        let mut required = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        if write {
+            required |= PageTableFlags::WRITABLE;
+        }
+
+        let indices = [
+            addr.p4_index(),
+            addr.p3_index(),
+            addr.p2_index(),
+            addr.p1_index(),
+        ];
+        let mut table: &PageTable = &*self.p4_table;
+        for (level, index) in indices.into_iter().enumerate() {
+            let entry = &table[index];
+            if !entry.flags().contains(required) {
+                return false;
+            }
+            if level == indices.len() - 1 || entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+                return true;
+            }
+            let Some(next) = Self::next_table_ptr(table, index) else {
+                return false;
+            };
+            // SAFETY: the pointer comes from walking our own tables
+            table = unsafe { &*next };
+        }
+        false
+    }
+
+    /// Check that `count` pages from `start` are aligned and stay in one
+    /// canonical half of the address space
+    ///
+    /// # Returns
+    ///
+    /// The first page past the range
+    fn check_range(start: Page, count: usize) -> Result<Page, MapError> {
+        if !start.start_address().is_aligned(Page::SIZE) {
+            return Err(MapError::InvalidAlignment);
+        }
+        (count as u64)
+            .checked_mul(Page::SIZE)
+            .and_then(|len| start.start_address().checked_add(len))
+            .map(Page::from_start_address)
+            .ok_or(MapError::InvalidAlignment)
+    }
+
+    /// Get or create the P1 table for `page` (returns raw pointer)
+    ///
+    /// Huge pages on the way are split, so the returned table holds the
+    /// 4 KiB entry for `page`.
+    fn p1_table_create_ptr(
+        &mut self,
+        page: Page,
+        flags: PageTableFlags,
+        frame_allocator: &mut impl FrameAllocator,
+    ) -> Result<*mut PageTable, &'static str> {
+        // Get the P4 table address
+        let p4_addr = self.p4_table as *mut PageTable;
+
+        // Traverse the page table hierarchy, creating tables as needed
+        // We use raw pointers to avoid multiple mutable borrows
+        // SAFETY: `self.p4_table` is a valid table borrowed mutably through
+        // `self`, and each reference below is dropped before the next level
+        let p4 = unsafe { &mut *p4_addr };
+        let p3_addr = Self::next_table_create_ptr(p4, page.p4_index(), 0, flags, frame_allocator)?;
+        // SAFETY: `next_table_create_ptr` returns an identity-accessible
+        // table of this address space
+        let p3 = unsafe { &mut *p3_addr };
+        let p2_addr =
+            Self::next_table_create_ptr(p3, page.p3_index(), HUGE_1GIB, flags, frame_allocator)?;
+        // SAFETY: as above, for the P2 table
+        let p2 = unsafe { &mut *p2_addr };
+        Self::next_table_create_ptr(p2, page.p2_index(), HUGE_2MIB, flags, frame_allocator)
+    }
+
     /// Get or create the next level page table (returns raw pointer)
+    ///
+    /// `huge_size` is the size mapped by a huge entry in `table`, used to
+    /// split such an entry; 0 if the level cannot hold huge pages.
     fn next_table_create_ptr(
         table: &mut PageTable,
         index: usize,
+        huge_size: u64,
+        flags: PageTableFlags,
+        frame_allocator: &mut impl FrameAllocator,
     ) -> Result<*mut PageTable, &'static str> {
         // Check if the entry is already present
         if !table[index].is_unused() {
+            if table[index].flags().contains(PageTableFlags::HUGE_PAGE) {
+                if huge_size == 0 {
+                    return Err("Invalid huge page entry");
+                }
+                Self::split_huge_page(&mut table[index], huge_size, frame_allocator)?;
+            }
+            let entry_flags = table[index].flags();
+            table[index].set_flags(entry_flags | flags);
             return Self::next_table_ptr(table, index)
                 .map(|ptr| ptr as *mut PageTable)
                 .ok_or("Failed to get next table");
         }
 
-        // TODO: Allocate a new frame from the frame allocator
-        // For now, we return an error since frame allocation is not implemented yet
-        Err("Frame allocation not implemented")
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or("Out of frames for page table")?;
+        let next = Self::table_ptr(frame.start_address());
+        // SAFETY: the allocator hands out unused, identity-accessible frames
+        unsafe { (*next).zero() };
+        table[index].set_frame(frame, flags);
+        Ok(next)
+    }
+
+    /// Replace a huge page entry by a table of 512 smaller entries
+    ///
+    /// The new entries map the same physical range with the same flags, so
+    /// the split is invisible to code running in the region.
+    fn split_huge_page(
+        entry: &mut PageTableEntry,
+        huge_size: u64,
+        frame_allocator: &mut impl FrameAllocator,
+    ) -> Result<(), &'static str> {
+        let flags = entry.flags();
+        // Bit 12 of a huge entry is the PAT bit, not part of the address
+        let base = PhysAddr::from_u64_truncate(entry.entry).align_down(huge_size);
+        let child_size = huge_size / 512;
+        let child_flags = if child_size == 4096 {
+            flags - PageTableFlags::HUGE_PAGE
+        } else {
+            flags
+        };
+
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or("Out of frames for page table")?;
+        // SAFETY: the allocator hands out unused, identity-accessible frames
+        let table = unsafe { &mut *Self::table_ptr(frame.start_address()) };
+
+        let mut addr = Some(base);
+        for child in table.iter_mut() {
+            let Some(child_addr) = addr else {
+                return Err("Huge page runs past the physical address space");
+            };
+            child.set_frame(PhysFrame::containing_address(child_addr), child_flags);
+            addr = child_addr.checked_add(child_size);
+        }
+
+        entry.set_frame(
+            frame,
+            flags - PageTableFlags::HUGE_PAGE - PageTableFlags::DIRTY,
+        );
+        Self::flush_tlb_all();
+        Ok(())
     }
 
     /// Get the next level page table (returns raw pointer)
@@ -302,13 +894,29 @@ impl PageTableManager {
         }
 
         let frame = entry.frame()?;
-        let ptr = frame.start_address().as_u64() as *const PageTable;
+        Some(Self::table_ptr(frame.start_address()).cast_const())
+    }
 
-        Some(ptr)
+    /// Frame of the next level table an entry points to, or `None` if the
+    /// entry is unused or maps a huge page
+    fn table_frame(entry: &PageTableEntry) -> Option<PhysFrame> {
+        if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return None;
+        }
+        entry.frame()
+    }
+
+    /// Pointer to the page table at physical address `addr`
+    ///
+    /// Page tables are accessed through the identity mapping, so the
+    /// pointer has the same value as the physical address.
+    fn table_ptr(addr: PhysAddr) -> *mut PageTable {
+        VirtAddr::new(addr.as_u64()).as_mut_ptr()
     }
 
     /// Flush the TLB for a single page
     fn flush_tlb(addr: VirtAddr) {
+        // SAFETY: invalidating a TLB entry only forces a fresh table walk
         unsafe {
             core::arch::asm!(
                 "invlpg [{}]",
@@ -320,6 +928,7 @@ impl PageTableManager {
 
     /// Flush the entire TLB by reloading CR3
     pub fn flush_tlb_all() {
+        // SAFETY: reloading CR3 with its own value only flushes the TLB
         unsafe {
             core::arch::asm!(
                 "mov {0}, cr3",
@@ -369,4 +978,274 @@ mod tests {
         assert!(flags.contains(PageTableFlags::USER_ACCESSIBLE));
         assert!(!flags.contains(PageTableFlags::NO_EXECUTE));
     }
+
+    /// Frame allocator that fails after handing out `remaining` frames
+    struct LimitedFrameAllocator {
+        inner: crate::memory::HeapFrameAllocator,
+        remaining: usize,
+    }
+
+    impl FrameAllocator for LimitedFrameAllocator {
+        fn allocate_frame(&mut self) -> Option<PhysFrame> {
+            self.remaining = self.remaining.checked_sub(1)?;
+            self.inner.allocate_frame()
+        }
+    }
+
+    /// Creates an address space with an empty lower half
+    fn empty_address_space() -> PageTableManager {
+        let frame = crate::memory::HeapFrameAllocator::new()
+            .allocate_frame()
+            .unwrap();
+        // SAFETY: the frame is zeroed, unused and identity-accessible
+        unsafe {
+            PageTableManager::from_p4_table(&mut *PageTableManager::table_ptr(
+                frame.start_address(),
+            ))
+        }
+    }
+
+    /// Two pages before the end of a P1 table, so a four-page range needs
+    /// a second P1 table half-way through
+    const RANGE_START: u64 = 0x1000_0000_0000 + 510 * Page::SIZE;
+    const FRAME_START: u64 = 0x20_0000;
+
+    #[test_case]
+    fn test_map_huge_page() {
+        let mut space = empty_address_space();
+        let mut allocator = crate::memory::HeapFrameAllocator::new();
+        let page = Page::from_start_address(VirtAddr::new(0x1000_0020_0000));
+        let frame = PhysFrame::from_start_address(PhysAddr::new(0x40_0000));
+
+        space
+            .map_huge_page(page, frame, PageTableFlags::WRITABLE, &mut allocator)
+            .unwrap();
+        // Only the P3 and P2 tables are allocated
+        assert_eq!(allocator.allocated(), 2);
+
+        let addr = page.start_address() + 0x12_3456;
+        assert_eq!(space.translate_addr(addr), Some(PhysAddr::new(0x52_3456)));
+        assert_eq!(
+            space.map_huge_page(page, frame, PageTableFlags::WRITABLE, &mut allocator),
+            Err("Page already mapped")
+        );
+        assert_eq!(
+            space.map_huge_page(page + 1, frame, PageTableFlags::WRITABLE, &mut allocator),
+            Err("Huge page is not 2MB aligned")
+        );
+    }
+
+    #[test_case]
+    fn test_map_range_and_unmap_range() {
+        let mut space = empty_address_space();
+        let start = Page::from_start_address(VirtAddr::new(RANGE_START));
+        let frame = PhysFrame::from_start_address(PhysAddr::new(FRAME_START));
+        let mut allocator = crate::memory::HeapFrameAllocator::new();
+
+        space
+            .map_range(start, frame, 4, PageTableFlags::WRITABLE, &mut allocator)
+            .unwrap();
+        for i in 0..4 {
+            assert_eq!(
+                space.translate_addr((start + i).start_address() + 8),
+                Some(PhysAddr::new(FRAME_START + i * Page::SIZE + 8))
+            );
+        }
+
+        let frames = space.unmap_range(start, 4).unwrap();
+        assert_eq!(frames, [frame, frame + 1, frame + 2, frame + 3]);
+        assert_eq!(space.unmap_range(start, 1), Err(MapError::NotMapped));
+    }
+
+    #[test_case]
+    fn test_destroy_releases_pages_and_tables() {
+        let mut space = empty_address_space();
+        let p4 = PhysFrame::containing_address(space.p4_address());
+        let start = Page::from_start_address(VirtAddr::new(RANGE_START));
+        let frame = PhysFrame::from_start_address(PhysAddr::new(FRAME_START));
+        let mut allocator = crate::memory::HeapFrameAllocator::new();
+        space
+            .map_range(start, frame, 4, PageTableFlags::WRITABLE, &mut allocator)
+            .unwrap();
+
+        let mut released = Vec::new();
+        let mut tables = Vec::new();
+        // SAFETY: the address space is not active and nothing else uses it
+        unsafe {
+            space.destroy(
+                |page, frame, flags| {
+                    assert!(flags.contains(PageTableFlags::WRITABLE));
+                    released.push((page, frame));
+                },
+                |table| tables.push(table),
+            );
+        }
+        let expected: Vec<(Page, PhysFrame)> = (0..4).map(|i| (start + i, frame + i)).collect();
+        assert_eq!(released, expected);
+        // Two P1 tables, a P2, a P3 and the P4 table itself
+        assert_eq!(tables.len(), 5);
+        assert_eq!(tables.last(), Some(&p4));
+        for table in tables {
+            // SAFETY: the tables came from the heap frame allocator above
+            unsafe { allocator.deallocate_frame(table) };
+        }
+    }
+
+    #[test_case]
+    fn test_new_address_space_keeps_kernel_mappings() {
+        let mut allocator = crate::memory::HeapFrameAllocator::new();
+        // SAFETY: CR3 holds the active, identity-accessible P4 table
+        let current = unsafe { PageTableManager::current() };
+        let space = current.new_address_space(&mut allocator).unwrap();
+
+        // The identity map and the kernel image translate as before
+        let table = VirtAddr::new(space.p4_address().as_u64());
+        assert_eq!(space.translate_addr(table), Some(space.p4_address()));
+        let code = VirtAddr::from_ptr(test_new_address_space_keeps_kernel_mappings as *const u8);
+        assert_eq!(space.translate_addr(code), current.translate_addr(code));
+        assert_eq!(space.translate_addr(VirtAddr::new(USER_SPACE_START)), None);
+
+        // Only the P4 table belongs to the new address space
+        let mut tables = Vec::new();
+        // SAFETY: the address space is not active and nothing else uses it
+        unsafe { space.destroy(|_, _, _| panic!("kernel page released"), |t| tables.push(t)) };
+        assert_eq!(tables.len(), 1);
+        // SAFETY: the table came from the heap frame allocator above
+        unsafe { allocator.deallocate_frame(tables[0]) };
+    }
+
+    #[test_case]
+    fn test_map_range_rolls_back_when_frames_run_out() {
+        let mut space = empty_address_space();
+        let start = Page::from_start_address(VirtAddr::new(RANGE_START));
+        let frame = PhysFrame::from_start_address(PhysAddr::new(FRAME_START));
+        // Enough for the P3, P2 and first P1 table, but not the second P1
+        let mut allocator = LimitedFrameAllocator {
+            inner: crate::memory::HeapFrameAllocator::new(),
+            remaining: 3,
+        };
+
+        assert_eq!(
+            space.map_range(start, frame, 4, PageTableFlags::WRITABLE, &mut allocator),
+            Err(MapError::FrameAllocationFailed)
+        );
+        for i in 0..4 {
+            assert_eq!(space.translate_addr((start + i).start_address()), None);
+        }
+    }
+
+    #[test_case]
+    fn test_set_flags_makes_page_read_only() {
+        let mut space = empty_address_space();
+        let mut allocator = crate::memory::HeapFrameAllocator::new();
+        let page = Page::from_start_address(VirtAddr::new(RANGE_START));
+        let frame = allocator.allocate_frame().unwrap();
+
+        space
+            .map_page(page, frame, PageTableFlags::WRITABLE, &mut allocator)
+            .unwrap();
+        // SAFETY: the frame is identity-accessible and owned by this test
+        unsafe { (frame.start_address().as_u64() as *mut u64).write_volatile(0x1234) };
+
+        space.set_flags(page, PageTableFlags::NO_EXECUTE).unwrap();
+        assert_eq!(
+            space.translate_addr(page.start_address() + 8),
+            Some(frame.start_address() + 8)
+        );
+        let flags = space.page_flags(page).unwrap();
+        assert!(!flags.contains(PageTableFlags::WRITABLE));
+        assert!(flags.contains(PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE));
+        assert_eq!(
+            // SAFETY: as above
+            unsafe { (frame.start_address().as_u64() as *const u64).read_volatile() },
+            0x1234
+        );
+
+        assert_eq!(
+            space.set_flags(page + 1, PageTableFlags::empty()),
+            Err(MapError::NotMapped)
+        );
+        assert_eq!(
+            space.set_flags(
+                Page::from_start_address(VirtAddr::new(0x10_0000)),
+                PageTableFlags::empty()
+            ),
+            Err(MapError::NotMapped)
+        );
+    }
+
+    #[test_case]
+    fn test_map_range_rolls_back_on_mapped_page() {
+        let mut space = empty_address_space();
+        let start = Page::from_start_address(VirtAddr::new(RANGE_START));
+        let frame = PhysFrame::from_start_address(PhysAddr::new(FRAME_START));
+        let mut allocator = crate::memory::HeapFrameAllocator::new();
+
+        let other = PhysFrame::from_start_address(PhysAddr::new(0x40_0000));
+        space
+            .map_page(start + 2, other, PageTableFlags::empty(), &mut allocator)
+            .unwrap();
+
+        assert_eq!(
+            space.map_range(start, frame, 4, PageTableFlags::empty(), &mut allocator),
+            Err(MapError::AlreadyMapped)
+        );
+        assert_eq!(space.translate_addr(start.start_address()), None);
+        assert_eq!(
+            space.translate_addr((start + 2).start_address()),
+            Some(other.start_address())
+        );
+        assert_eq!(space.unmap_range(start, 4), Err(MapError::NotMapped));
+        assert!(space.translate_addr((start + 2).start_address()).is_some());
+    }
+
+    #[test_case]
+    fn test_map_range_rejects_misaligned_range() {
+        let mut space = empty_address_space();
+        let mut allocator = crate::memory::HeapFrameAllocator::new();
+        let page = Page::from_start_address(VirtAddr::new(RANGE_START + 8));
+        let frame = PhysFrame::from_start_address(PhysAddr::new(FRAME_START));
+
+        assert_eq!(
+            space.map_range(page, frame, 1, PageTableFlags::empty(), &mut allocator),
+            Err(MapError::InvalidAlignment)
+        );
+        assert_eq!(
+            space.map_range(
+                Page::containing_address(VirtAddr::new(RANGE_START)),
+                PhysFrame::from_start_address(PhysAddr::new(FRAME_START + 8)),
+                1,
+                PageTableFlags::empty(),
+                &mut allocator,
+            ),
+            Err(MapError::InvalidAlignment)
+        );
+        assert_eq!(allocator.allocated(), 0);
+    }
+
+    #[test_case]
+    fn test_map_range_rejects_range_past_canonical_half() {
+        let mut space = empty_address_space();
+        let mut allocator = crate::memory::HeapFrameAllocator::new();
+        let frame = PhysFrame::from_start_address(PhysAddr::new(FRAME_START));
+
+        // The first page past the range would be in the non-canonical hole
+        let top = Page::containing_address(VirtAddr::new(0x7fff_ffff_f000));
+        assert_eq!(
+            space.map_range(top, frame, 1, PageTableFlags::empty(), &mut allocator),
+            Err(MapError::InvalidAlignment)
+        );
+        let last_frame = PhysFrame::containing_address(PhysAddr::new(0x000f_ffff_ffff_f000));
+        assert_eq!(
+            space.map_range(
+                Page::from_start_address(VirtAddr::new(RANGE_START)),
+                last_frame,
+                2,
+                PageTableFlags::empty(),
+                &mut allocator,
+            ),
+            Err(MapError::InvalidAlignment)
+        );
+        assert_eq!(allocator.allocated(), 0);
+    }
 }
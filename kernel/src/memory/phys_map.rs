@@ -0,0 +1,467 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Physical memory map
+//!
+//! Records what every known range of physical memory is used for. The map
+//! starts out as the firmware memory map passed by the bootloader; the
+//! kernel then reserves the ranges it occupies, such as its image and the
+//! heap, so the memory left `Usable` is free for a frame allocator.
+//!
+//! Regions never overlap. Reserving part of a region splits it, and
+//! adjacent regions of the same kind are merged.
+
+use alloc::{
+    collections::BTreeMap,
+    vec::Vec,
+};
+use core::fmt;
+
+use spin::{
+    Mutex,
+    Once,
+};
+
+use super::{
+    PhysAddr,
+    PhysFrame,
+    PhysRange,
+    VirtAddr,
+    frame::kernel_virt_to_phys,
+    heap,
+};
+use crate::boot::{
+    MemoryRegion,
+    MemoryRegionType,
+    Multiboot2Info,
+};
+
+/// Physical memory map of the machine, set up by `init`
+pub static PHYS_MAP: Once<Mutex<PhysMemoryMap>> = Once::new();
+
+extern "C" {
+    static __kernel_physical_start: u8;
+    static __data_start: u8;
+    static __kernel_virtual_end: u8;
+}
+
+/// What a region of physical memory holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysRegionKind {
+    /// Free RAM
+    Usable,
+    /// Reserved by hardware or firmware
+    Reserved,
+    /// ACPI tables, reclaimable once parsed
+    AcpiReclaimable,
+    /// ACPI non-volatile storage
+    AcpiNvs,
+    /// Defective RAM
+    BadMemory,
+    /// Kernel code and read-only data
+    KernelCode,
+    /// Kernel data and BSS
+    KernelData,
+    /// Memory backing the kernel heap
+    HeapAllocator,
+    /// Page tables
+    PageTables,
+}
+
+impl From<MemoryRegionType> for PhysRegionKind {
+    fn from(region_type: MemoryRegionType) -> Self {
+        match region_type {
+            MemoryRegionType::Usable => Self::Usable,
+            MemoryRegionType::Reserved => Self::Reserved,
+            MemoryRegionType::AcpiReclaimable => Self::AcpiReclaimable,
+            MemoryRegionType::AcpiNvs => Self::AcpiNvs,
+            MemoryRegionType::BadMemory => Self::BadMemory,
+        }
+    }
+}
+
+/// A region of physical memory, keyed by its base address in the map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysRegion {
+    /// Length of the region in bytes
+    pub length: u64,
+    /// What the region holds
+    pub kind: PhysRegionKind,
+}
+
+/// Errors returned by `PhysMemoryMap::reserve`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysMapError {
+    /// The range is empty or wraps around the address space
+    InvalidRange,
+    /// Part of the range is not covered by any region
+    NotInMap,
+}
+
+impl fmt::Display for PhysMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRange => write!(f, "range is empty or wraps around"),
+            Self::NotInMap => write!(f, "range is not covered by the memory map"),
+        }
+    }
+}
+
+/// Map from base addresses to the regions of physical memory
+#[derive(Debug, Default)]
+pub struct PhysMemoryMap {
+    regions: BTreeMap<PhysAddr, PhysRegion>,
+}
+
+impl PhysMemoryMap {
+    /// Creates an empty map
+    pub const fn new() -> Self {
+        Self {
+            regions: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a map from the memory map tag of the boot information
+    ///
+    /// Overlapping entries are resolved in favour of the later one.
+    pub fn from_multiboot(info: &Multiboot2Info) -> Self {
+        Self::from_regions(info.memory_map())
+    }
+
+    /// Creates a map from firmware memory map entries
+    fn from_regions(regions: impl Iterator<Item = MemoryRegion>) -> Self {
+        let mut map = Self::new();
+        for region in regions.filter(|region| region.length > 0) {
+            let end = region.base_addr.saturating_add(region.length);
+            map.set(region.base_addr, end, region.region_type.into());
+        }
+        map
+    }
+
+    /// Marks `len` bytes from `base` as holding `kind`
+    ///
+    /// Regions overlapping the range are split, so only the part inside
+    /// the range changes kind.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PhysMapError::InvalidRange` if the range is empty or
+    /// overflows, or `PhysMapError::NotInMap` if part of it lies in a hole
+    /// of the map. The map is unchanged on error.
+    pub fn reserve(
+        &mut self,
+        base: PhysAddr,
+        len: u64,
+        kind: PhysRegionKind,
+    ) -> Result<(), PhysMapError> {
+        let start = base.as_u64();
+        let end = start
+            .checked_add(len)
+            .filter(|_| len > 0)
+            .ok_or(PhysMapError::InvalidRange)?;
+        if !self.covers(start, end) {
+            return Err(PhysMapError::NotInMap);
+        }
+        self.set(start, end, kind);
+        Ok(())
+    }
+
+    /// Returns the region containing `addr`, with its base address
+    pub fn region_at(&self, addr: PhysAddr) -> Option<(PhysAddr, PhysRegion)> {
+        self.regions
+            .range(..=addr)
+            .next_back()
+            .filter(|(base, region)| addr.as_u64() - base.as_u64() < region.length)
+            .map(|(base, region)| (*base, *region))
+    }
+
+    /// Iterates over the regions in address order
+    pub fn regions(&self) -> impl Iterator<Item = (PhysAddr, PhysRegion)> + '_ {
+        self.regions.iter().map(|(base, region)| (*base, *region))
+    }
+
+    /// Total size of the regions of the given kind in bytes
+    pub fn total(&self, kind: PhysRegionKind) -> u64 {
+        self.regions
+            .values()
+            .filter(|region| region.kind == kind)
+            .map(|region| region.length)
+            .sum()
+    }
+
+    /// Iterates over the frames lying entirely in `Usable` regions
+    pub fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> + '_ {
+        self.regions
+            .iter()
+            .filter(|(_, region)| region.kind == PhysRegionKind::Usable)
+            .flat_map(|(base, region)| {
+                PhysRange::new(*base, PhysAddr::new(base.as_u64() + region.length))
+            })
+    }
+
+    /// Returns `true` if regions cover `start..end` without gaps
+    fn covers(&self, start: u64, end: u64) -> bool {
+        let mut next = start;
+        let first = self.region_at(PhysAddr::new(start)).map(|(base, _)| base);
+        let Some(first) = first else {
+            return false;
+        };
+        for (base, region) in self.regions.range(first..PhysAddr::new(end)) {
+            if base.as_u64() > next {
+                return false;
+            }
+            next = base.as_u64() + region.length;
+        }
+        next >= end
+    }
+
+    /// Makes `start..end` a single region of `kind`
+    ///
+    /// Overlapping regions are cut back to the parts outside the range;
+    /// the new region is merged with neighbours of the same kind.
+    fn set(&mut self, start: u64, end: u64, kind: PhysRegionKind) {
+        // Regions do not overlap, so ends grow with bases and the
+        // overlapping ones are the last few starting below `end`
+        let overlapping: Vec<(PhysAddr, PhysRegion)> = self
+            .regions
+            .range(..PhysAddr::new(end))
+            .rev()
+            .take_while(|(base, region)| base.as_u64() + region.length > start)
+            .map(|(base, region)| (*base, *region))
+            .collect();
+        for (base, region) in overlapping {
+            self.regions.remove(&base);
+            let region_end = base.as_u64() + region.length;
+            if base.as_u64() < start {
+                self.insert(base.as_u64(), start, region.kind);
+            }
+            if region_end > end {
+                self.insert(end, region_end, region.kind);
+            }
+        }
+
+        let (mut start, mut end) = (start, end);
+        if let Some((&base, before)) = self.regions.range(..PhysAddr::new(start)).next_back() {
+            if before.kind == kind && base.as_u64() + before.length == start {
+                self.regions.remove(&base);
+                start = base.as_u64();
+            }
+        }
+        let next = PhysAddr::new(end);
+        if let Some(after) = self.regions.get(&next).copied() {
+            if after.kind == kind {
+                self.regions.remove(&next);
+                end += after.length;
+            }
+        }
+        self.insert(start, end, kind);
+    }
+
+    fn insert(&mut self, start: u64, end: u64, kind: PhysRegionKind) {
+        self.regions.insert(PhysAddr::new(start), PhysRegion {
+            length: end - start,
+            kind,
+        });
+    }
+}
+
+/// Builds `PHYS_MAP` from the boot information
+///
+/// The kernel image and the heap's backing memory and page tables are
+/// reserved, and boot modules are marked `Reserved` so their contents
+/// survive until they are loaded. Needs the heap for the map itself.
+///
+/// # Panics
+///
+/// Panics if called more than once.
+pub fn init(info: &Multiboot2Info) {
+    assert!(
+        PHYS_MAP.get().is_none(),
+        "physical memory map already built"
+    );
+    let mut map = PhysMemoryMap::from_multiboot(info);
+
+    let image_start = VirtAddr::from_ptr(core::ptr::addr_of!(__kernel_physical_start)).as_u64();
+    let data_start = VirtAddr::from_ptr(core::ptr::addr_of!(__data_start)).as_u64();
+    let image_end = VirtAddr::from_ptr(core::ptr::addr_of!(__kernel_virtual_end)).as_u64();
+    let data_start = kernel_virt_to_phys(data_start);
+    let image_end = kernel_virt_to_phys(image_end);
+    let reservations = [
+        (
+            PhysAddr::new(image_start),
+            data_start.as_u64() - image_start,
+            PhysRegionKind::KernelCode,
+        ),
+        (
+            data_start,
+            image_end.as_u64() - data_start.as_u64(),
+            PhysRegionKind::KernelData,
+        ),
+    ];
+    let (heap_base, heap_len) = heap::backing_phys_range();
+    let (tables_base, tables_len) = heap::table_phys_range();
+    let heap = [
+        (heap_base, heap_len, PhysRegionKind::HeapAllocator),
+        (tables_base, tables_len, PhysRegionKind::PageTables),
+    ];
+    let modules = info
+        .modules()
+        .map(|module| (module.start, module.size(), PhysRegionKind::Reserved));
+    // The heap lies in the kernel's BSS, so it is reserved after the image
+    for (base, len, kind) in reservations.into_iter().chain(heap).chain(modules) {
+        if let Err(e) = map.reserve(base, len, kind) {
+            crate::log_warn!(
+                "Cannot reserve {:#x}+{:#x} as {:?}: {}",
+                base.as_u64(),
+                len,
+                kind,
+                e
+            );
+        }
+    }
+
+    crate::log_info!(
+        "Physical memory: {} KiB usable, {} regions",
+        map.total(PhysRegionKind::Usable) / 1024,
+        map.regions.len()
+    );
+    PHYS_MAP.call_once(|| Mutex::new(map));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn region(base_addr: u64, length: u64, region_type: MemoryRegionType) -> MemoryRegion {
+        MemoryRegion {
+            base_addr,
+            length,
+            region_type,
+        }
+    }
+
+    /// 16 MiB of usable RAM above 1 MiB
+    fn map() -> PhysMemoryMap {
+        PhysMemoryMap::from_regions(
+            [
+                region(0, MIB, MemoryRegionType::Reserved),
+                region(MIB, 16 * MIB, MemoryRegionType::Usable),
+            ]
+            .into_iter(),
+        )
+    }
+
+    fn kinds(map: &PhysMemoryMap) -> Vec<(u64, u64, PhysRegionKind)> {
+        map.regions()
+            .map(|(base, region)| (base.as_u64(), region.length, region.kind))
+            .collect()
+    }
+
+    #[test_case]
+    fn test_reserve_splits_region() {
+        let mut map = map();
+        map.reserve(PhysAddr::new(2 * MIB), MIB, PhysRegionKind::KernelCode)
+            .unwrap();
+        assert_eq!(kinds(&map), [
+            (0, MIB, PhysRegionKind::Reserved),
+            (MIB, MIB, PhysRegionKind::Usable),
+            (2 * MIB, MIB, PhysRegionKind::KernelCode),
+            (3 * MIB, 14 * MIB, PhysRegionKind::Usable),
+        ]);
+        assert_eq!(
+            map.region_at(PhysAddr::new(2 * MIB + 5)).unwrap().1.kind,
+            PhysRegionKind::KernelCode
+        );
+        assert_eq!(map.total(PhysRegionKind::Usable), 15 * MIB);
+    }
+
+    #[test_case]
+    fn test_reserve_across_regions() {
+        let mut map = map();
+        map.reserve(PhysAddr::new(MIB / 2), MIB, PhysRegionKind::PageTables)
+            .unwrap();
+        assert_eq!(kinds(&map), [
+            (0, MIB / 2, PhysRegionKind::Reserved),
+            (MIB / 2, MIB, PhysRegionKind::PageTables),
+            (3 * MIB / 2, 31 * MIB / 2, PhysRegionKind::Usable),
+        ]);
+    }
+
+    #[test_case]
+    fn test_adjacent_regions_merge() {
+        // Adjacent firmware entries of the same type become one region
+        let mut map = PhysMemoryMap::from_regions(
+            [
+                region(0, MIB, MemoryRegionType::Usable),
+                region(MIB, MIB, MemoryRegionType::Usable),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(kinds(&map), [(0, 2 * MIB, PhysRegionKind::Usable)]);
+
+        map.reserve(PhysAddr::new(0), MIB / 2, PhysRegionKind::KernelData)
+            .unwrap();
+        map.reserve(PhysAddr::new(MIB), MIB / 2, PhysRegionKind::KernelData)
+            .unwrap();
+        map.reserve(PhysAddr::new(MIB / 2), MIB / 2, PhysRegionKind::KernelData)
+            .unwrap();
+        assert_eq!(kinds(&map), [
+            (0, 3 * MIB / 2, PhysRegionKind::KernelData),
+            (3 * MIB / 2, MIB / 2, PhysRegionKind::Usable),
+        ]);
+    }
+
+    #[test_case]
+    fn test_reserve_rejects_holes() {
+        let mut map = PhysMemoryMap::from_regions(
+            [
+                region(0, MIB, MemoryRegionType::Usable),
+                region(2 * MIB, MIB, MemoryRegionType::Usable),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            map.reserve(PhysAddr::new(MIB / 2), MIB, PhysRegionKind::KernelCode),
+            Err(PhysMapError::NotInMap)
+        );
+        assert_eq!(
+            map.reserve(PhysAddr::new(4 * MIB), 1, PhysRegionKind::KernelCode),
+            Err(PhysMapError::NotInMap)
+        );
+        assert_eq!(
+            map.reserve(PhysAddr::new(0), 0, PhysRegionKind::KernelCode),
+            Err(PhysMapError::InvalidRange)
+        );
+        assert_eq!(kinds(&map).len(), 2);
+    }
+
+    #[test_case]
+    fn test_usable_frames() {
+        let map = PhysMemoryMap::from_regions(
+            [
+                region(0x800, 0x3000, MemoryRegionType::Usable),
+                region(0x10_0000, 0x2000, MemoryRegionType::Reserved),
+            ]
+            .into_iter(),
+        );
+        // Only the whole frames at 0x1000 and 0x2000 fit
+        let frames: Vec<u64> = map
+            .usable_frames()
+            .map(|frame| frame.start_address().as_u64())
+            .collect();
+        assert_eq!(frames, [0x1000, 0x2000]);
+    }
+}
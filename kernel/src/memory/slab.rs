@@ -0,0 +1,433 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Slab allocator for fixed-size kernel objects
+//!
+//! A `SlabCache<T>` carves 4 KiB pages taken from the kernel heap into
+//! equally sized slots for `T` and keeps freed slots on a free list, so
+//! objects that are created and destroyed often (processes, messages,
+//! capabilities) reuse the same memory instead of fragmenting the heap.
+//!
+//! Caches are registered by name in the global `SLAB_ALLOCATOR` so that all
+//! users of a type share one cache. `SlabBox<T>` owns an object in a named
+//! cache and returns the slot when dropped.
+
+use alloc::{
+    alloc::{
+        Layout,
+        alloc,
+        handle_alloc_error,
+    },
+    boxed::Box,
+    collections::BTreeMap,
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    marker::PhantomData,
+    mem::{
+        align_of,
+        size_of,
+    },
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    ptr::NonNull,
+};
+
+use spin::Mutex;
+
+/// Size of the pages slabs are carved from
+pub const SLAB_PAGE_SIZE: usize = 4096;
+
+/// Largest object a slab cache accepts (at least 4 objects per page)
+pub const MAX_OBJECT_SIZE: usize = SLAB_PAGE_SIZE / 4;
+
+/// Pattern written after the free-list link of a free slot
+///
+/// Finding it in a slot that is being freed means the slot is already free.
+#[cfg(debug_assertions)]
+const FREE_CANARY: usize = 0x5ab5_f4ee_dead_c0de;
+
+/// Global registry of named slab caches
+pub static SLAB_ALLOCATOR: Mutex<SlabAllocator> = Mutex::new(SlabAllocator::new());
+
+/// Cache of fixed-size slots for objects of type `T`
+///
+/// Free slots form a singly linked list threaded through the slots
+/// themselves. Pages are kept for reuse once allocated.
+pub struct SlabCache<T> {
+    name: &'static str,
+    slot_size: usize,
+    free_list: *mut T,
+    pages: Vec<NonNull<u8>>,
+    in_use: usize,
+    _marker: PhantomData<T>,
+}
+
+// SAFETY: the cache exclusively owns its pages and the slots on its free
+// list; handing out slots to other threads is up to the caller.
+unsafe impl<T: Send> Send for SlabCache<T> {}
+
+impl<T> SlabCache<T> {
+    /// Create an empty cache
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is larger than `MAX_OBJECT_SIZE` or needs a larger
+    /// alignment than a page.
+    pub fn new(name: &'static str) -> Self {
+        assert!(
+            size_of::<T>() <= MAX_OBJECT_SIZE && align_of::<T>() <= SLAB_PAGE_SIZE,
+            "type too large for slab cache {}",
+            name
+        );
+        // A free slot holds the link and, in debug builds, the canary
+        let min_slot = 2 * size_of::<usize>();
+        let slot_size = size_of::<T>()
+            .max(min_slot)
+            .next_multiple_of(align_of::<T>().max(align_of::<usize>()));
+
+        Self {
+            name,
+            slot_size,
+            free_list: core::ptr::null_mut(),
+            pages: Vec::new(),
+            in_use: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Name the cache was registered under
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Size of each slot in bytes
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    /// Number of slots handed out and not yet freed
+    pub fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    /// Number of pages backing the cache
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Allocate an uninitialized slot
+    ///
+    /// Returns `None` if the heap has no page left to grow the cache.
+    pub fn alloc(&mut self) -> Option<NonNull<T>> {
+        if self.free_list.is_null() {
+            self.grow()?;
+        }
+
+        let slot = self.free_list;
+        // SAFETY: slots on the free list are owned by the cache and hold
+        // the link to the next free slot.
+        unsafe {
+            self.free_list = slot.cast::<*mut T>().read();
+            #[cfg(debug_assertions)]
+            slot.cast::<usize>().add(1).write(0);
+        }
+        self.in_use += 1;
+        NonNull::new(slot)
+    }
+
+    /// Return a slot to the cache
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `alloc` on this cache, and the
+    /// object in it must already have been dropped.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if the slot is already free or does not
+    /// belong to this cache.
+    pub unsafe fn dealloc(&mut self, ptr: NonNull<T>) {
+        let slot = ptr.as_ptr();
+        #[cfg(debug_assertions)]
+        {
+            assert!(
+                self.owns(slot.cast()),
+                "pointer {:p} not from slab cache {}",
+                slot,
+                self.name
+            );
+            // SAFETY: the slot is at least two words long
+            let canary = unsafe { slot.cast::<usize>().add(1).read() };
+            assert!(
+                canary != FREE_CANARY,
+                "double free of {:p} in slab cache {}",
+                slot,
+                self.name
+            );
+        }
+
+        // SAFETY: the caller hands the slot back, so it can hold the link
+        unsafe {
+            slot.cast::<*mut T>().write(self.free_list);
+            #[cfg(debug_assertions)]
+            slot.cast::<usize>().add(1).write(FREE_CANARY);
+        }
+        self.free_list = slot;
+        self.in_use -= 1;
+    }
+
+    /// Add a page of free slots
+    fn grow(&mut self) -> Option<()> {
+        let layout = Layout::from_size_align(SLAB_PAGE_SIZE, SLAB_PAGE_SIZE).ok()?;
+        // SAFETY: the layout has a non-zero size
+        let page = NonNull::new(unsafe { alloc(layout) })?;
+        self.pages.push(page);
+
+        let slots = SLAB_PAGE_SIZE / self.slot_size;
+        // Push in reverse so the free list hands out ascending addresses
+        for i in (0..slots).rev() {
+            // SAFETY: slot `i` lies within the new page
+            let slot = unsafe { page.as_ptr().add(i * self.slot_size) }.cast::<T>();
+            // SAFETY: the slot is unused memory owned by the cache
+            unsafe {
+                slot.cast::<*mut T>().write(self.free_list);
+                #[cfg(debug_assertions)]
+                slot.cast::<usize>().add(1).write(FREE_CANARY);
+            }
+            self.free_list = slot;
+        }
+        Some(())
+    }
+
+    /// Returns `true` if `ptr` is the start of a slot in one of our pages
+    #[cfg(debug_assertions)]
+    fn owns(&self, ptr: *const u8) -> bool {
+        let addr = ptr as usize;
+        self.pages.iter().any(|page| {
+            let start = page.as_ptr() as usize;
+            let slots_end = start + (SLAB_PAGE_SIZE / self.slot_size) * self.slot_size;
+            (start..slots_end).contains(&addr) && (addr - start).is_multiple_of(self.slot_size)
+        })
+    }
+}
+
+/// Registry of named slab caches
+///
+/// Each name maps to one cache for one type, so every user of a name
+/// shares the same slots.
+pub struct SlabAllocator {
+    caches: BTreeMap<&'static str, Box<dyn Any + Send>>,
+}
+
+impl SlabAllocator {
+    /// Create an empty registry
+    pub const fn new() -> Self {
+        Self {
+            caches: BTreeMap::new(),
+        }
+    }
+
+    /// Get the cache registered as `name`, creating it on first use
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is already registered for a different type.
+    pub fn cache<T: Send + 'static>(&mut self, name: &'static str) -> &mut SlabCache<T> {
+        self.caches
+            .entry(name)
+            .or_insert_with(|| Box::new(SlabCache::<T>::new(name)))
+            .downcast_mut()
+            .unwrap_or_else(|| panic!("slab cache {} registered for another type", name))
+    }
+
+    /// Number of registered caches
+    pub fn len(&self) -> usize {
+        self.caches.len()
+    }
+
+    /// Returns `true` if no cache is registered
+    pub fn is_empty(&self) -> bool {
+        self.caches.is_empty()
+    }
+}
+
+impl Default for SlabAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An object owned by a slot in a named slab cache
+///
+/// Like `Box`, but allocated from `SLAB_ALLOCATOR`. The object does not
+/// move while the `SlabBox` exists.
+pub struct SlabBox<T: Send + 'static> {
+    ptr: NonNull<T>,
+    cache: &'static str,
+}
+
+// SAFETY: a `SlabBox` owns its object like a `Box`
+unsafe impl<T: Send + 'static> Send for SlabBox<T> {}
+// SAFETY: shared access only hands out `&T`
+unsafe impl<T: Send + Sync + 'static> Sync for SlabBox<T> {}
+
+impl<T: Send + 'static> SlabBox<T> {
+    /// Move `value` into a slot of the cache named `cache`
+    pub fn new(cache: &'static str, value: T) -> Self {
+        let slot = crate::interrupts::without_interrupts(|| {
+            SLAB_ALLOCATOR.lock().cache::<T>(cache).alloc()
+        });
+        let ptr = slot.unwrap_or_else(|| handle_alloc_error(Layout::new::<T>()));
+        // SAFETY: the slot is fresh, suitably sized and aligned for `T`
+        unsafe { ptr.as_ptr().write(value) };
+        Self { ptr, cache }
+    }
+
+    /// Move the object out and return its slot to the cache
+    pub fn into_inner(self) -> T {
+        let this = core::mem::ManuallyDrop::new(self);
+        // SAFETY: the slot holds a live `T`; it is read exactly once and
+        // `Drop` does not run for `this`.
+        let value = unsafe { this.ptr.as_ptr().read() };
+        crate::interrupts::without_interrupts(|| {
+            // SAFETY: the object has been moved out of the slot
+            unsafe {
+                SLAB_ALLOCATOR
+                    .lock()
+                    .cache::<T>(this.cache)
+                    .dealloc(this.ptr)
+            }
+        });
+        value
+    }
+}
+
+impl<T: Send + 'static> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the slot holds a live `T` owned by this box
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: Send + 'static> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the slot holds a live `T` owned by this box
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: Send + 'static> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        // SAFETY: the object is live and dropped exactly once, before its
+        // slot goes back to the cache it came from.
+        unsafe {
+            self.ptr.as_ptr().drop_in_place();
+            crate::interrupts::without_interrupts(|| {
+                SLAB_ALLOCATOR
+                    .lock()
+                    .cache::<T>(self.cache)
+                    .dealloc(self.ptr)
+            });
+        }
+    }
+}
+
+impl<T: Send + core::fmt::Debug + 'static> core::fmt::Debug for SlabBox<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::heap::heap_usage;
+
+    #[allow(dead_code)]
+    struct Object {
+        id: u64,
+        payload: [u64; 7],
+    }
+
+    #[test_case]
+    fn test_reuse_without_heap_growth() {
+        let mut cache = SlabCache::<Object>::new("test-object");
+        let mut slots = Vec::with_capacity(512);
+
+        for _ in 0..512 {
+            slots.push(cache.alloc().unwrap());
+        }
+        let pages = cache.pages();
+        assert_eq!(cache.in_use(), 512);
+        for slot in slots.drain(..) {
+            // SAFETY: each slot came from this cache, holds no live object
+            // and is freed once
+            unsafe { cache.dealloc(slot) };
+        }
+
+        // A second round is served entirely from the free list
+        let before = heap_usage();
+        for _ in 0..512 {
+            slots.push(cache.alloc().unwrap());
+        }
+        for slot in slots.drain(..) {
+            // SAFETY: each slot came from this cache, holds no live object
+            // and is freed once
+            unsafe { cache.dealloc(slot) };
+        }
+        let after = heap_usage();
+
+        assert_eq!(cache.pages(), pages);
+        assert_eq!(cache.in_use(), 0);
+        assert_eq!(after.used, before.used);
+        assert_eq!(after.largest_free, before.largest_free);
+    }
+
+    #[test_case]
+    fn test_slots_are_aligned_and_distinct() {
+        let mut cache = SlabCache::<u8>::new("test-u8");
+        assert_eq!(cache.slot_size(), 2 * size_of::<usize>());
+
+        let a = cache.alloc().unwrap();
+        let b = cache.alloc().unwrap();
+        assert_ne!(a, b);
+        assert!((a.as_ptr() as usize).is_multiple_of(align_of::<usize>()));
+        // SAFETY: both slots came from this cache, were never written and
+        // are freed once
+        unsafe {
+            cache.dealloc(a);
+            cache.dealloc(b);
+        }
+    }
+
+    #[test_case]
+    fn test_registry_shares_caches() {
+        let first = SlabBox::new("test-shared", 1u64);
+        let second = SlabBox::new("test-shared", 2u64);
+        assert_eq!(*first + *second, 3);
+
+        let in_use = SLAB_ALLOCATOR.lock().cache::<u64>("test-shared").in_use();
+        assert_eq!(in_use, 2);
+        drop(first);
+        drop(second);
+        let in_use = SLAB_ALLOCATOR.lock().cache::<u64>("test-shared").in_use();
+        assert_eq!(in_use, 0);
+    }
+}
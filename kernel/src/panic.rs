@@ -16,38 +16,95 @@
 //!
 //! This module provides a detailed panic handler that displays:
 //! - Panic message and source location
-//! - Stack trace (via RBP chain unwinding)
+//! - Stack trace (via DWARF CFI unwinding)
 //! - CPU register state
 //! - Control register values
 //!
 //! The panic handler is designed to help debug kernel issues by providing
 //! as much context as possible about the system state at the time of panic.
-
-use core::panic::PanicInfo;
+//!
+//! Kernels built with `--cfg panic_reboot_secs="N"` count down N seconds
+//! and then reset the machine instead of halting, leaving time to read the
+//! message.
+
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
 
 use crate::{
+    interrupts::{
+        pit,
+        port::{
+            Port,
+            PortReadOnly,
+        },
+    },
     println,
     vga_println,
 };
 
+/// Seconds counted down before rebooting after a panic; 0 halts instead
+pub const PANIC_REBOOT_SECS: u64 = match u64::from_str_radix(env!("KERNEL_PANIC_REBOOT_SECS"), 10) {
+    Ok(secs) => secs,
+    Err(_) => panic!("KERNEL_PANIC_REBOOT_SECS is not a number"),
+};
+
+/// Keyboard controller status and command port
+const KBC_PORT: u16 = 0x64;
+/// Status bit set while the controller's input buffer is full
+const KBC_INPUT_FULL: u8 = 1 << 1;
+/// Command pulsing the CPU reset line
+const KBC_RESET: u8 = 0xfe;
+
+/// Longest single PIT busy-wait, see `pit::busy_wait_ms`
+const COUNTDOWN_STEP_MS: u32 = 50;
+
+/// Set once a panic handler has started
+///
+/// `DetectMutex` stops reporting deadlocks from then on: the panic handler
+/// takes the serial and VGA locks, so a deadlock found on one of them would
+/// otherwise panic again and recurse.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Records that a panic handler has started
+pub fn set_panicking() {
+    PANICKING.store(true, Ordering::Release);
+}
+
+/// Returns `true` once a panic handler has started
+pub fn is_panicking() -> bool {
+    PANICKING.load(Ordering::Acquire)
+}
+
 /// Main panic handler implementation
 ///
 /// This function is called when a kernel panic occurs. It:
-/// 1. Disables interrupts to prevent further corruption
-/// 2. Prints panic information (message, location)
+/// 1. Disables interrupts to prevent further corruption and flushes the log
+///    queue
+/// 2. Prints the boot phase reached and panic information (message, location)
 /// 3. Prints stack trace
 /// 4. Prints CPU register state
-/// 5. Halts the system
+/// 5. Halts the system, or reboots after `PANIC_REBOOT_SECS` seconds
 ///
 /// # Arguments
 ///
 /// * `info` - Panic information containing message and location
 pub fn panic_handler(info: &PanicInfo) -> ! {
+    set_panicking();
+
     // Disable interrupts to prevent further issues
+    // SAFETY: the system never resumes after a panic
     unsafe {
         crate::interrupts::disable();
     }
 
+    // Write out messages logged just before the panic
+    crate::io::logging::flush_log_queue();
+
     // Print panic banner (to both VGA and serial)
     println!();
     println!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
@@ -62,6 +119,11 @@ pub fn panic_handler(info: &PanicInfo) -> ! {
     vga_println!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
     vga_println!();
 
+    // Print how far boot got, so it is clear which subsystems are missing
+    let phase = crate::boot::boot_phase();
+    println!("Boot phase: {}", phase);
+    vga_println!("Boot phase: {}", phase);
+
     // Print panic location (to both VGA and serial)
     if let Some(location) = info.location() {
         println!(
@@ -85,6 +147,8 @@ pub fn panic_handler(info: &PanicInfo) -> ! {
     let message = info.message();
     println!("Message: {}", message);
     vga_println!("Message: {}", message);
+    // The timer no longer flushes the screen once interrupts are off
+    crate::vga::flush();
 
     println!();
 
@@ -98,16 +162,49 @@ pub fn panic_handler(info: &PanicInfo) -> ! {
     print_register_dump();
 
     println!();
+    if PANIC_REBOOT_SECS != 0 {
+        reboot_after_countdown(PANIC_REBOOT_SECS);
+    }
     println!("System halted.");
 
     // Halt the system
     loop {
+        // SAFETY: halting with interrupts disabled is exactly what is wanted
         unsafe {
             core::arch::asm!("cli; hlt");
         }
     }
 }
 
+/// Counts down `secs` seconds and resets the machine
+///
+/// Interrupts are disabled, so the seconds are timed with the PIT. Falls
+/// through if the reset has no effect.
+fn reboot_after_countdown(secs: u64) {
+    for remaining in (0..=secs).rev() {
+        println!("Rebooting in {} seconds...", remaining);
+        vga_println!("Rebooting in {} seconds...", remaining);
+        crate::vga::flush();
+        if remaining > 0 {
+            for _ in 0..1000 / COUNTDOWN_STEP_MS {
+                pit::busy_wait_ms(COUNTDOWN_STEP_MS);
+            }
+        }
+    }
+
+    let mut status = PortReadOnly::<u8>::new(KBC_PORT);
+    let mut command = Port::<u8>::new(KBC_PORT);
+    // SAFETY: pulsing the reset line is exactly what is wanted here
+    unsafe {
+        while status.read() & KBC_INPUT_FULL != 0 {
+            core::hint::spin_loop();
+        }
+        command.write(KBC_RESET);
+    }
+    // Give the controller time to act before giving up
+    pit::busy_wait_ms(COUNTDOWN_STEP_MS);
+}
+
 /// Print system uptime
 fn print_uptime() {
     let uptime_ms = crate::interrupts::timer::uptime_ms();
@@ -121,79 +218,26 @@ fn print_uptime() {
     println!("Uptime: {}h {}m {}s {}ms", hours, minutes, seconds, ms);
 }
 
-/// Print stack trace by walking the RBP chain
-///
-/// This function walks the stack frame chain by following RBP pointers.
-/// For each frame, it prints the frame number, return address (RIP), and
-/// frame pointer (RBP).
+/// Print stack trace using the DWARF CFI unwinder
 ///
-/// # Safety
-///
-/// This function performs unsafe pointer dereferences. It validates addresses
-/// before dereferencing to prevent further crashes during panic handling.
+/// The kernel is built without frame pointers, so the stack is walked with
+/// the unwind tables in `.eh_frame` (see `debug::unwind`). For each frame, it
+/// prints the frame number, return address (RIP) and stack pointer (RSP).
 fn print_stack_trace() {
     println!("Stack trace:");
 
-    let mut rbp: u64;
-    unsafe {
-        core::arch::asm!(
-            "mov {}, rbp",
-            out(reg) rbp,
-            options(nomem, nostack, preserves_flags)
+    let frames = crate::debug::unwind::walk_stack(|index, frame| {
+        println!(
+            "  #{}: RIP={:#018x} RSP={:#018x}",
+            index, frame.pc, frame.sp
         );
-    }
-
-    let mut frame = 0;
-    while frame < 10 && rbp != 0 {
-        // Read return address from stack frame
-        let rip = unsafe {
-            let rip_ptr = (rbp + 8) as *const u64;
-            if is_valid_kernel_address(rip_ptr as u64) {
-                *rip_ptr
-            } else {
-                break;
-            }
-        };
-
-        // Print frame
-        println!("  #{}: RIP={:#018x} RBP={:#018x}", frame, rip, rbp);
-
-        // Move to previous frame
-        rbp = unsafe {
-            let rbp_ptr = rbp as *const u64;
-            if is_valid_kernel_address(rbp_ptr as u64) {
-                *rbp_ptr
-            } else {
-                break;
-            }
-        };
-
-        frame += 1;
-    }
+    });
 
-    if frame == 0 {
+    if frames == 0 {
         println!("  (no stack trace available)");
     }
 }
 
-/// Check if an address is a valid kernel address
-///
-/// Kernel addresses should be in the higher half of the address space.
-/// On x86_64, kernel space typically starts at 0xFFFF800000000000.
-///
-/// # Arguments
-///
-/// * `addr` - The address to validate
-///
-/// # Returns
-///
-/// `true` if the address is in kernel space, `false` otherwise
-fn is_valid_kernel_address(addr: u64) -> bool {
-    // Kernel space: 0xFFFF800000000000 and above
-    // Also check it's not null and not too high
-    (0xffff_8000_0000_0000..0xffff_ffff_ffff_ffff).contains(&addr)
-}
-
 /// Print CPU register dump
 ///
 /// This function captures and displays the current state of:
@@ -216,6 +260,7 @@ fn print_register_dump() {
     let (rip, rflags): (u64, u64);
     let (cr0, cr2, cr3, cr4): (u64, u64, u64, u64);
 
+    // SAFETY: only reads registers
     unsafe {
         core::arch::asm!(
             "mov {}, rax",
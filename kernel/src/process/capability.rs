@@ -0,0 +1,584 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Capabilities
+//!
+//! A capability grants its holder a set of rights on one kernel object,
+//! identified by its type and object ID. Every process holds its
+//! capabilities in a `CapabilitySet`, and kernel operations acting on an
+//! object on behalf of a process check the matching capability first.
+//!
+//! Capabilities can be delegated to other processes with fewer rights.
+//! The `CapabilityTree` records which capability was derived from which,
+//! so revoking one also revokes everything derived from it.
+
+use alloc::{
+    collections::BTreeMap,
+    vec::Vec,
+};
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+};
+
+use bitflags::bitflags;
+
+use super::process::{
+    ProcessId,
+    ProcessState,
+    ProcessTable,
+};
+
+/// Next capability ID to hand out
+static NEXT_CAP_ID: AtomicU64 = AtomicU64::new(1);
+
+bitflags! {
+    /// Rights a capability grants on its object
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CapabilityRights: u64 {
+        /// Read from the object
+        const READ =        1 << 0;
+        /// Write to the object, including sending messages to an endpoint
+        const WRITE =       1 << 1;
+        /// Pass the capability on to another process
+        const GRANT =       1 << 2;
+        /// Revoke capabilities derived from this one
+        const REVOKE =      1 << 3;
+        /// Execute the object
+        const EXECUTE =     1 << 4;
+        /// Sleep on the timer
+        const SLEEP =       1 << 5;
+        /// Arm timer callbacks
+        const SET_TIMER =   1 << 6;
+        /// Read the system uptime
+        const READ_UPTIME = 1 << 7;
+    }
+}
+
+/// Kind of kernel object a capability refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilityType {
+    /// IPC endpoint of a process; the object ID is its PID
+    Endpoint,
+    /// Region of physical memory; the object ID is its start address
+    Memory,
+    /// The system timer; the object ID is `TIMER_OBJECT_ID`
+    Timer,
+}
+
+/// Object ID of the system timer in `Timer` capabilities
+pub const TIMER_OBJECT_ID: u64 = 0;
+
+/// Errors returned by capability checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilityError {
+    /// No capability for the object is held
+    NotFound,
+    /// The capability lacks a required right
+    PermissionDenied,
+    /// A capability for the object with rights the new one lacks is held
+    AlreadyHeld,
+}
+
+impl fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no capability for the object"),
+            Self::PermissionDenied => write!(f, "capability lacks a required right"),
+            Self::AlreadyHeld => write!(f, "a stronger capability for the object is held"),
+        }
+    }
+}
+
+/// A right-limited reference to a kernel object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capability {
+    cap_id: u64,
+    pub cap_type: CapabilityType,
+    pub object_id: u64,
+    pub rights: CapabilityRights,
+}
+
+impl Capability {
+    /// Creates a capability granting `rights` on an object
+    ///
+    /// Each capability gets a new, unique ID.
+    pub fn new(cap_type: CapabilityType, object_id: u64, rights: CapabilityRights) -> Self {
+        Self {
+            cap_id: NEXT_CAP_ID.fetch_add(1, Ordering::Relaxed),
+            cap_type,
+            object_id,
+            rights,
+        }
+    }
+
+    /// Returns the unique ID of the capability
+    pub fn cap_id(&self) -> u64 {
+        self.cap_id
+    }
+
+    /// Returns `true` if the capability grants all of `rights`
+    pub fn check(&self, rights: CapabilityRights) -> bool {
+        self.rights.contains(rights)
+    }
+}
+
+/// Capabilities held by a process
+///
+/// Holds at most one capability per object; inserting another replaces
+/// it.
+#[derive(Debug, Default, Clone)]
+pub struct CapabilitySet {
+    capabilities: Vec<Capability>,
+}
+
+impl CapabilitySet {
+    /// Creates an empty capability set
+    pub const fn new() -> Self {
+        Self {
+            capabilities: Vec::new(),
+        }
+    }
+
+    /// Adds a capability, replacing any held for the same object
+    pub fn insert(&mut self, capability: Capability) {
+        self.remove(capability.cap_type, capability.object_id);
+        self.capabilities.push(capability);
+    }
+
+    /// Removes and returns the capability for an object
+    pub fn remove(&mut self, cap_type: CapabilityType, object_id: u64) -> Option<Capability> {
+        let index = self
+            .capabilities
+            .iter()
+            .position(|cap| cap.cap_type == cap_type && cap.object_id == object_id)?;
+        Some(self.capabilities.swap_remove(index))
+    }
+
+    /// Removes and returns the capability with the given ID
+    pub fn remove_by_id(&mut self, cap_id: u64) -> Option<Capability> {
+        let index = self
+            .capabilities
+            .iter()
+            .position(|cap| cap.cap_id == cap_id)?;
+        Some(self.capabilities.swap_remove(index))
+    }
+
+    /// Returns the capability with the given ID
+    pub fn get_by_id(&self, cap_id: u64) -> Option<&Capability> {
+        self.capabilities.iter().find(|cap| cap.cap_id == cap_id)
+    }
+
+    /// Returns the capability for an object
+    pub fn get(&self, cap_type: CapabilityType, object_id: u64) -> Option<&Capability> {
+        self.capabilities
+            .iter()
+            .find(|cap| cap.cap_type == cap_type && cap.object_id == object_id)
+    }
+
+    /// Checks that the set grants `rights` on an object
+    ///
+    /// # Errors
+    ///
+    /// Returns `CapabilityError::NotFound` if there is no capability for
+    /// the object, or `CapabilityError::PermissionDenied` if it lacks one
+    /// of `rights`.
+    pub fn require(
+        &self,
+        cap_type: CapabilityType,
+        object_id: u64,
+        rights: CapabilityRights,
+    ) -> Result<(), CapabilityError> {
+        let cap = self
+            .get(cap_type, object_id)
+            .ok_or(CapabilityError::NotFound)?;
+        if !cap.check(rights) {
+            return Err(CapabilityError::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of capabilities held
+    pub fn len(&self) -> usize {
+        self.capabilities.len()
+    }
+
+    /// Returns `true` if no capabilities are held
+    pub fn is_empty(&self) -> bool {
+        self.capabilities.is_empty()
+    }
+
+    /// Iterates over the capabilities held
+    pub fn iter(&self) -> impl Iterator<Item = &Capability> {
+        self.capabilities.iter()
+    }
+}
+
+/// Delegation lineage of capabilities
+///
+/// Maps every delegated capability to the capabilities derived from it.
+#[derive(Debug, Default)]
+pub struct CapabilityTree {
+    children: BTreeMap<u64, Vec<u64>>,
+}
+
+impl CapabilityTree {
+    /// Creates an empty tree
+    pub const fn new() -> Self {
+        Self {
+            children: BTreeMap::new(),
+        }
+    }
+
+    /// Delegates `cap`, held by `from`, to `to`
+    ///
+    /// The derived capability refers to the same object with the rights of
+    /// `cap` limited to `rights_mask`. It replaces a capability `to`
+    /// already holds for the object only if that one grants no right the
+    /// derived one lacks, so delegation never weakens what `to` holds.
+    ///
+    /// # Returns
+    ///
+    /// The derived capability
+    ///
+    /// # Errors
+    ///
+    /// Returns `CapabilityError::NotFound` if `from` does not hold `cap` or
+    /// `to` does not exist, or `CapabilityError::PermissionDenied` if `cap`
+    /// lacks the `GRANT` right, or `CapabilityError::AlreadyHeld` if `to`
+    /// holds a capability for the object with rights the derived one lacks.
+    pub fn delegate(
+        &mut self,
+        table: &mut ProcessTable,
+        from: ProcessId,
+        cap: &Capability,
+        to: ProcessId,
+        rights_mask: CapabilityRights,
+    ) -> Result<Capability, CapabilityError> {
+        let parent = *table
+            .get(from)
+            .and_then(|p| p.capabilities().get_by_id(cap.cap_id))
+            .ok_or(CapabilityError::NotFound)?;
+        if !parent.check(CapabilityRights::GRANT) {
+            return Err(CapabilityError::PermissionDenied);
+        }
+
+        let derived = Capability::new(
+            parent.cap_type,
+            parent.object_id,
+            parent.rights & rights_mask,
+        );
+        let held = table
+            .get_mut(to)
+            .ok_or(CapabilityError::NotFound)?
+            .capabilities_mut();
+        if held
+            .get(derived.cap_type, derived.object_id)
+            .is_some_and(|existing| !derived.rights.contains(existing.rights))
+        {
+            return Err(CapabilityError::AlreadyHeld);
+        }
+        held.insert(derived);
+        self.children
+            .entry(parent.cap_id)
+            .or_default()
+            .push(derived.cap_id);
+        Ok(derived)
+    }
+
+    /// Revokes a capability and every capability derived from it
+    ///
+    /// The capabilities are removed from all processes holding them. A
+    /// process waiting for a reply through a revoked endpoint can no
+    /// longer get one and is blocked.
+    ///
+    /// # Returns
+    ///
+    /// The number of capabilities removed
+    pub fn revoke(&mut self, table: &mut ProcessTable, cap_id: u64) -> usize {
+        let mut revoked = Vec::new();
+        let mut pending = Vec::from([cap_id]);
+        while let Some(id) = pending.pop() {
+            revoked.push(id);
+            if let Some(children) = self.children.remove(&id) {
+                pending.extend(children);
+            }
+        }
+
+        let mut removed = 0;
+        let mut blocked = Vec::new();
+        for process in table.iter_mut() {
+            for &id in &revoked {
+                let Some(cap) = process.capabilities_mut().remove_by_id(id) else {
+                    continue;
+                };
+                removed += 1;
+                let waiting_on = ProcessState::WaitingForReply(ProcessId::new(cap.object_id));
+                if cap.cap_type == CapabilityType::Endpoint && process.state() == waiting_on {
+                    blocked.push(process.pid());
+                }
+            }
+        }
+        for pid in blocked {
+            let _ = table.mark_blocked(pid);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::Process;
+
+    fn table_with(count: usize) -> ProcessTable {
+        let mut table = ProcessTable::new();
+        for _ in 0..count {
+            let pid = table.allocate_pid().unwrap();
+            table.add_process(Process::new(pid, "p")).unwrap();
+        }
+        table
+    }
+
+    fn caps(table: &ProcessTable, pid: u64) -> &CapabilitySet {
+        table.get(ProcessId::new(pid)).unwrap().capabilities()
+    }
+
+    #[test_case]
+    fn test_rights_check() {
+        let cap = Capability::new(
+            CapabilityType::Memory,
+            0x1000,
+            CapabilityRights::READ | CapabilityRights::GRANT,
+        );
+        assert!(cap.check(CapabilityRights::READ));
+        assert!(cap.check(CapabilityRights::READ | CapabilityRights::GRANT));
+        assert!(!cap.check(CapabilityRights::WRITE));
+        assert!(!cap.check(CapabilityRights::READ | CapabilityRights::WRITE));
+    }
+
+    #[test_case]
+    fn test_get_filters_by_type_and_object() {
+        let mut caps = CapabilitySet::new();
+        caps.insert(Capability::new(
+            CapabilityType::Endpoint,
+            2,
+            CapabilityRights::WRITE,
+        ));
+        caps.insert(Capability::new(
+            CapabilityType::Memory,
+            2,
+            CapabilityRights::READ,
+        ));
+
+        assert!(
+            caps.get(CapabilityType::Endpoint, 2)
+                .unwrap()
+                .check(CapabilityRights::WRITE)
+        );
+        assert!(
+            !caps
+                .get(CapabilityType::Memory, 2)
+                .unwrap()
+                .check(CapabilityRights::WRITE)
+        );
+        assert!(caps.get(CapabilityType::Endpoint, 3).is_none());
+
+        // Inserting for the same object replaces the old capability
+        caps.insert(Capability::new(
+            CapabilityType::Memory,
+            2,
+            CapabilityRights::all(),
+        ));
+        assert_eq!(caps.len(), 2);
+        assert_eq!(
+            caps.require(CapabilityType::Memory, 2, CapabilityRights::WRITE),
+            Ok(())
+        );
+    }
+
+    #[test_case]
+    fn test_require_read_only() {
+        let mut caps = CapabilitySet::new();
+        caps.insert(Capability::new(
+            CapabilityType::Memory,
+            0x1000,
+            CapabilityRights::READ,
+        ));
+
+        assert_eq!(
+            caps.require(CapabilityType::Memory, 0x1000, CapabilityRights::READ),
+            Ok(())
+        );
+        assert_eq!(
+            caps.require(CapabilityType::Memory, 0x1000, CapabilityRights::WRITE),
+            Err(CapabilityError::PermissionDenied)
+        );
+        assert_eq!(
+            caps.require(CapabilityType::Memory, 0x2000, CapabilityRights::READ),
+            Err(CapabilityError::NotFound)
+        );
+    }
+
+    #[test_case]
+    fn test_cap_ids_are_unique() {
+        let a = Capability::new(CapabilityType::Memory, 0, CapabilityRights::READ);
+        let b = Capability::new(CapabilityType::Memory, 0, CapabilityRights::READ);
+        assert_ne!(a.cap_id(), b.cap_id());
+    }
+
+    #[test_case]
+    fn test_delegate_limits_rights() {
+        let mut table = table_with(3);
+        let mut tree = CapabilityTree::new();
+        let (a, b, c) = (ProcessId::new(1), ProcessId::new(2), ProcessId::new(3));
+
+        let root = Capability::new(
+            CapabilityType::Memory,
+            0x1000,
+            CapabilityRights::READ | CapabilityRights::GRANT,
+        );
+        table.get_mut(a).unwrap().capabilities_mut().insert(root);
+
+        // Rights outside the parent's are not gained by asking for them
+        let derived = tree
+            .delegate(&mut table, a, &root, b, CapabilityRights::all())
+            .unwrap();
+        assert_eq!(derived.rights, root.rights);
+        assert_ne!(derived.cap_id(), root.cap_id());
+
+        let read_only = tree
+            .delegate(&mut table, b, &derived, c, CapabilityRights::READ)
+            .unwrap();
+        assert_eq!(read_only.rights, CapabilityRights::READ);
+        assert_eq!(
+            caps(&table, 3).get_by_id(read_only.cap_id()),
+            Some(&read_only)
+        );
+
+        // Without GRANT the capability cannot be passed on
+        assert_eq!(
+            tree.delegate(&mut table, c, &read_only, a, CapabilityRights::READ),
+            Err(CapabilityError::PermissionDenied)
+        );
+        assert_eq!(
+            tree.delegate(&mut table, a, &read_only, b, CapabilityRights::READ),
+            Err(CapabilityError::NotFound)
+        );
+    }
+
+    #[test_case]
+    fn test_delegate_keeps_stronger_capability() {
+        let mut table = table_with(2);
+        let mut tree = CapabilityTree::new();
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+
+        let root = Capability::new(CapabilityType::Memory, 0x1000, CapabilityRights::all());
+        let held = Capability::new(
+            CapabilityType::Memory,
+            0x1000,
+            CapabilityRights::READ | CapabilityRights::WRITE,
+        );
+        table.get_mut(a).unwrap().capabilities_mut().insert(root);
+        table.get_mut(b).unwrap().capabilities_mut().insert(held);
+
+        assert_eq!(
+            tree.delegate(&mut table, a, &root, b, CapabilityRights::READ),
+            Err(CapabilityError::AlreadyHeld)
+        );
+        assert_eq!(caps(&table, 2).get_by_id(held.cap_id()), Some(&held));
+
+        // A delegation granting at least the held rights replaces it
+        let derived = tree
+            .delegate(&mut table, a, &root, b, CapabilityRights::all())
+            .unwrap();
+        assert_eq!(caps(&table, 2).len(), 1);
+        assert_eq!(caps(&table, 2).get_by_id(derived.cap_id()), Some(&derived));
+    }
+
+    #[test_case]
+    fn test_revoke_removes_two_levels_of_children() {
+        let mut table = table_with(4);
+        let mut tree = CapabilityTree::new();
+        let pids = [1, 2, 3, 4].map(ProcessId::new);
+
+        let root = Capability::new(CapabilityType::Memory, 0x2000, CapabilityRights::all());
+        let other = Capability::new(CapabilityType::Memory, 0x3000, CapabilityRights::all());
+        let owner = table.get_mut(pids[0]).unwrap().capabilities_mut();
+        owner.insert(root);
+        owner.insert(other);
+
+        let child = tree
+            .delegate(&mut table, pids[0], &root, pids[1], CapabilityRights::all())
+            .unwrap();
+        let grandchild = tree
+            .delegate(
+                &mut table,
+                pids[1],
+                &child,
+                pids[2],
+                CapabilityRights::all(),
+            )
+            .unwrap();
+        tree.delegate(&mut table, pids[1], &child, pids[3], CapabilityRights::READ)
+            .unwrap();
+        assert!(caps(&table, 3).get_by_id(grandchild.cap_id()).is_some());
+
+        assert_eq!(tree.revoke(&mut table, root.cap_id()), 4);
+        for pid in 2..=4 {
+            assert!(
+                caps(&table, pid).is_empty(),
+                "PID {} kept a capability",
+                pid
+            );
+        }
+        // Unrelated capabilities are kept
+        assert_eq!(caps(&table, 1).len(), 1);
+        assert!(caps(&table, 1).get_by_id(other.cap_id()).is_some());
+    }
+
+    #[test_case]
+    fn test_revoke_blocks_caller_waiting_on_endpoint() {
+        let mut table = table_with(3);
+        let mut tree = CapabilityTree::new();
+        let (server, owner, client) = (ProcessId::new(1), ProcessId::new(2), ProcessId::new(3));
+
+        let endpoint = Capability::new(
+            CapabilityType::Endpoint,
+            server.as_u64(),
+            CapabilityRights::WRITE | CapabilityRights::GRANT,
+        );
+        table
+            .get_mut(owner)
+            .unwrap()
+            .capabilities_mut()
+            .insert(endpoint);
+        tree.delegate(
+            &mut table,
+            owner,
+            &endpoint,
+            client,
+            CapabilityRights::WRITE,
+        )
+        .unwrap();
+        table.mark_waiting_for_reply(client, server).unwrap();
+
+        tree.revoke(&mut table, endpoint.cap_id());
+        assert_eq!(table.get(client).unwrap().state(), ProcessState::Blocked);
+        assert_eq!(table.get(owner).unwrap().state(), ProcessState::Ready);
+    }
+}
@@ -0,0 +1,440 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Saved CPU state of a process and the context switch
+//!
+//! The integer registers are switched by `switch_context`, the FPU and SSE
+//! registers by `switch_fpu`. Every process in the table has FPU state,
+//! allocated by `ProcessTable::add_process`. `switch_fpu` sets CR0.TS, and
+//! the #NM handler loads the state on the first FPU instruction (see
+//! `scheduler::restore_current_fpu`).
+//! `enter_usermode` drops from ring 0 into a user context.
+
+use core::mem::offset_of;
+
+use crate::{
+    cpu::fpu,
+    interrupts::gdt::{
+        KERNEL_CODE_SELECTOR,
+        KERNEL_DATA_SELECTOR,
+        USER_CODE_SELECTOR,
+        USER_DATA_SELECTOR,
+    },
+};
+
+/// RFLAGS value for new threads: reserved bit 1 and IF set
+const RFLAGS_DEFAULT: u64 = 0x202;
+
+/// Register state saved when a process is switched out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ProcessContext {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cs: u64,
+    pub ss: u64,
+}
+
+impl ProcessContext {
+    /// Creates a context that starts executing `entry` in ring 0
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - Address of the first instruction to execute
+    /// * `stack_top` - Top of the thread's kernel stack
+    pub const fn new_kernel(entry: u64, stack_top: u64) -> Self {
+        Self {
+            rax: 0,
+            rbx: 0,
+            rcx: 0,
+            rdx: 0,
+            rsi: 0,
+            rdi: 0,
+            rbp: 0,
+            // Entered as if called: RSP + 8 must be 16-byte aligned
+            rsp: (stack_top & !0xf) - 8,
+            r8: 0,
+            r9: 0,
+            r10: 0,
+            r11: 0,
+            r12: 0,
+            r13: 0,
+            r14: 0,
+            r15: 0,
+            rip: entry,
+            rflags: RFLAGS_DEFAULT,
+            cs: KERNEL_CODE_SELECTOR as u64,
+            ss: KERNEL_DATA_SELECTOR as u64,
+        }
+    }
+
+    /// Creates a context that starts executing `entry` in ring 3
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - User address of the first instruction to execute
+    /// * `stack_top` - Top of the user stack
+    pub const fn new_user(entry: u64, stack_top: u64) -> Self {
+        Self {
+            rsp: stack_top & !0xf,
+            cs: USER_CODE_SELECTOR as u64,
+            ss: USER_DATA_SELECTOR as u64,
+            ..Self::new_kernel(entry, stack_top)
+        }
+    }
+}
+
+/// Size of the `fxsave` area
+pub const FPU_CONTEXT_SIZE: usize = 512;
+
+/// Offset of the FPU control word in the `fxsave` area
+const FCW_OFFSET: usize = 0;
+/// Offset of MXCSR in the `fxsave` area
+const MXCSR_OFFSET: usize = 24;
+/// Offset of XMM0 in the `fxsave` area
+const XMM_OFFSET: usize = 160;
+/// FPU control word after `fninit`: all exceptions masked
+const FCW_DEFAULT: u16 = 0x037f;
+/// MXCSR at reset: all SIMD exceptions masked
+const MXCSR_DEFAULT: u32 = 0x1f80;
+
+/// FPU, MMX and SSE register state, as saved by `fxsave`
+#[derive(Clone)]
+#[repr(C, align(16))]
+pub struct FpuContext {
+    area: [u8; FPU_CONTEXT_SIZE],
+}
+
+impl FpuContext {
+    /// Creates the state of a freshly initialized FPU, with all registers
+    /// zeroed and all exceptions masked
+    pub const fn new() -> Self {
+        let mut area = [0; FPU_CONTEXT_SIZE];
+        let fcw = FCW_DEFAULT.to_le_bytes();
+        area[FCW_OFFSET] = fcw[0];
+        area[FCW_OFFSET + 1] = fcw[1];
+        let mxcsr = MXCSR_DEFAULT.to_le_bytes();
+        let mut i = 0;
+        while i < mxcsr.len() {
+            area[MXCSR_OFFSET + i] = mxcsr[i];
+            i += 1;
+        }
+        Self { area }
+    }
+
+    /// Saves the current FPU state into `self`
+    ///
+    /// Raises #NM if CR0.TS is set.
+    pub fn save(&mut self) {
+        // SAFETY: `area` is 512 bytes and 16-byte aligned
+        unsafe {
+            core::arch::asm!("fxsave64 [{}]", in(reg) self.area.as_mut_ptr(), options(nostack));
+        }
+    }
+
+    /// Loads the FPU state from `self`
+    ///
+    /// Raises #NM if CR0.TS is set.
+    pub fn restore(&self) {
+        // SAFETY: `area` is 512 bytes and 16-byte aligned, and only ever
+        // holds a valid state from `new` or `save`
+        unsafe {
+            core::arch::asm!("fxrstor64 [{}]", in(reg) self.area.as_ptr(), options(nostack));
+        }
+    }
+
+    /// Returns the saved value of XMM register `index` (0-15)
+    pub fn xmm(&self, index: usize) -> u128 {
+        let start = XMM_OFFSET + index * 16;
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&self.area[start..start + 16]);
+        u128::from_le_bytes(bytes)
+    }
+}
+
+impl Default for FpuContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for FpuContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FpuContext").finish_non_exhaustive()
+    }
+}
+
+/// Switches the FPU state from one process to another
+///
+/// Saves the current state into `from` and loads `to`; either may be null
+/// for a process that has not used the FPU. CR0.TS is set afterwards, so
+/// the next FPU instruction raises #NM.
+///
+/// # Safety
+///
+/// Non-null pointers must be valid, and `from` must not be accessed by
+/// anything else during the call.
+pub unsafe fn switch_fpu(from: *mut FpuContext, to: *const FpuContext) {
+    fpu::clear_task_switched();
+    // SAFETY: guaranteed by the caller
+    if let Some(from) = unsafe { from.as_mut() } {
+        from.save();
+    }
+    // SAFETY: guaranteed by the caller
+    if let Some(to) = unsafe { to.as_ref() } {
+        to.restore();
+    }
+    fpu::set_task_switched();
+}
+
+/// Enters ring 3 at `context.rip` with the stack at `context.rsp`
+///
+/// Pushes the interrupt stack frame `iretq` pops (the user data selector as
+/// SS, RSP, RFLAGS with IF set, the user code selector as CS and RIP) and
+/// returns through it. RDI is loaded from `context.rdi` as the first
+/// argument; the other general-purpose registers are cleared so no kernel
+/// values leak to user mode.
+///
+/// Interrupts, exceptions and system calls from the user code enter the
+/// kernel on the stack set with `interrupts::set_kernel_stack`.
+///
+/// # Safety
+///
+/// - `context.rip` and `context.rsp` must point into user-accessible pages of
+///   the current address space
+/// - `interrupts::set_kernel_stack` must have installed a valid stack, and
+///   nothing on the current stack may be needed again
+pub unsafe fn enter_usermode(context: &ProcessContext) -> ! {
+    // SAFETY: guaranteed by the caller
+    unsafe {
+        core::arch::asm!(
+            "push {ss}",
+            "push {rsp}",
+            "push {rflags}",
+            "push {cs}",
+            "push {rip}",
+            "xor eax, eax",
+            "xor ebx, ebx",
+            "xor ecx, ecx",
+            "xor edx, edx",
+            "xor esi, esi",
+            "xor ebp, ebp",
+            "xor r8d, r8d",
+            "xor r9d, r9d",
+            "xor r10d, r10d",
+            "xor r11d, r11d",
+            "xor r12d, r12d",
+            "xor r13d, r13d",
+            "xor r14d, r14d",
+            "xor r15d, r15d",
+            "iretq",
+            ss = in(reg) u64::from(USER_DATA_SELECTOR),
+            rsp = in(reg) context.rsp,
+            rflags = in(reg) RFLAGS_DEFAULT,
+            cs = in(reg) u64::from(USER_CODE_SELECTOR),
+            rip = in(reg) context.rip,
+            in("rdi") context.rdi,
+            options(noreturn)
+        );
+    }
+}
+
+/// Switches from the current context to another
+///
+/// The callee-saved registers (RBX, RBP, R12-R15) are pushed on the current
+/// stack, RSP, RFLAGS and a resume address are stored in `from`, and then
+/// RSP and RFLAGS are loaded from `to` and execution jumps to `to.rip`. When
+/// another `switch_context` later switches back to `from`, the registers
+/// are popped and this call returns normally.
+///
+/// A fresh context from `ProcessContext::new_kernel` starts at its entry
+/// point with `to.rdi` as the first argument, as if called with an empty
+/// stack.
+///
+/// # Arguments
+///
+/// * `from` - Context to save the current state into
+/// * `to` - Context to resume
+///
+/// # Safety
+///
+/// - Both pointers must be valid and `to` must hold a context that was either
+///   created for a live stack or saved by `switch_context`
+/// - `from` must not be accessed by anything else until it is resumed
+#[unsafe(naked)]
+pub unsafe extern "C" fn switch_context(from: *mut ProcessContext, to: *const ProcessContext) {
+    core::arch::naked_asm!(
+        // Save the callee-saved registers on the outgoing stack
+        "push rbp",
+        "push rbx",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "pushfq",
+        "pop rax",
+        "mov [rdi + {rflags}], rax",
+        "mov [rdi + {rsp}], rsp",
+        "lea rax, [rip + 2f]",
+        "mov [rdi + {rip}], rax",
+        // Load the incoming context and jump to it
+        "mov rsp, [rsi + {rsp}]",
+        "push qword ptr [rsi + {rflags}]",
+        "popfq",
+        "mov rdi, [rsi + {rdi}]",
+        "jmp qword ptr [rsi + {rip}]",
+        // Resume point for contexts saved above
+        "2:",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbx",
+        "pop rbp",
+        "ret",
+        rsp = const offset_of!(ProcessContext, rsp),
+        rdi = const offset_of!(ProcessContext, rdi),
+        rip = const offset_of!(ProcessContext, rip),
+        rflags = const offset_of!(ProcessContext, rflags),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        boxed::Box,
+        vec,
+    };
+
+    use super::*;
+
+    /// A closure running on its own stack
+    struct Task {
+        context: ProcessContext,
+        caller: ProcessContext,
+        body: Option<Box<dyn FnOnce()>>,
+        done: bool,
+        _stack: Box<[u8]>,
+    }
+
+    impl Task {
+        fn new(body: impl FnOnce() + 'static) -> Box<Self> {
+            let stack = vec![0u8; 16 * 1024].into_boxed_slice();
+            let stack_top = stack.as_ptr() as u64 + stack.len() as u64;
+            let mut task = Box::new(Self {
+                context: ProcessContext::new_kernel(task_entry as *const () as u64, stack_top),
+                caller: ProcessContext::default(),
+                body: Some(Box::new(body)),
+                done: false,
+                _stack: stack,
+            });
+            task.context.rdi = &*task as *const Self as u64;
+            task
+        }
+
+        /// Runs the task until it switches back to the caller
+        fn run(&mut self) {
+            // SAFETY: both contexts live in `self`, which outlives the call
+            unsafe { switch_context(&mut self.caller, &self.context) };
+        }
+    }
+
+    extern "C" fn task_entry(task: *mut Task) -> ! {
+        // SAFETY: `Task::new` passes a pointer to the boxed task
+        let task = unsafe { &mut *task };
+        if let Some(body) = task.body.take() {
+            body();
+        }
+        task.done = true;
+        // SAFETY: `caller` was saved by the `switch_context` in `run`
+        unsafe { switch_context(&mut task.context, &task.caller) };
+        unreachable!("finished task resumed");
+    }
+
+    #[test_case]
+    fn test_switch_runs_closures_to_completion() {
+        use core::sync::atomic::{
+            AtomicU64,
+            Ordering,
+        };
+        static A: AtomicU64 = AtomicU64::new(0);
+        static B: AtomicU64 = AtomicU64::new(0);
+
+        let mut a = Task::new(|| {
+            let sum: u64 = (1..=10).sum();
+            A.store(sum, Ordering::SeqCst);
+        });
+        let mut b = Task::new(|| {
+            let words = ["switch", "context"];
+            B.store(words.iter().map(|w| w.len() as u64).sum(), Ordering::SeqCst);
+        });
+
+        a.run();
+        b.run();
+
+        assert!(a.done && b.done);
+        assert_eq!(A.load(Ordering::SeqCst), 55);
+        assert_eq!(B.load(Ordering::SeqCst), 13);
+    }
+
+    fn write_xmm0(value: u64) {
+        // SAFETY: the kernel does not use XMM0 itself
+        unsafe { core::arch::asm!("movq xmm0, {}", in(reg) value, options(nomem, nostack)) };
+    }
+
+    fn read_xmm0() -> u64 {
+        let value: u64;
+        // SAFETY: reading XMM0 has no side effects
+        unsafe { core::arch::asm!("movq {}, xmm0", out(reg) value, options(nomem, nostack)) };
+        value
+    }
+
+    #[test_case]
+    fn test_xmm_survives_fpu_round_trip() {
+        const A: u64 = 0x0123_4567_89ab_cdef;
+        const B: u64 = 0xfedc_ba98_7654_3210;
+        let mut a = Box::new(FpuContext::new());
+        let mut b = Box::new(FpuContext::new());
+
+        write_xmm0(A);
+        // SAFETY: both contexts are owned here
+        unsafe { switch_fpu(&mut *a, &*b) };
+        assert!(fpu::task_switched());
+        // Traps to #NM, whose handler clears TS
+        assert_eq!(read_xmm0(), 0);
+
+        write_xmm0(B);
+        // SAFETY: as above
+        unsafe { switch_fpu(&mut *b, &*a) };
+        assert_eq!(read_xmm0(), A);
+        assert_eq!(a.xmm(0) as u64, A);
+        assert_eq!(b.xmm(0) as u64, B);
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! File descriptor tables
+//!
+//! Every process refers to its open files through small integers, the
+//! file descriptors. Descriptors 0, 1 and 2 are the standard streams and
+//! start out on the serial console.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::fs::{
+    FileHandle,
+    console,
+};
+
+/// A file descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fd(pub u32);
+
+impl Fd {
+    /// Standard error
+    pub const STDERR: Fd = Fd(2);
+    /// Standard input
+    pub const STDIN: Fd = Fd(0);
+    /// Standard output
+    pub const STDOUT: Fd = Fd(1);
+}
+
+impl fmt::Display for Fd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Errors returned by file descriptor operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdError {
+    /// The descriptor is not open
+    BadFd,
+}
+
+impl fmt::Display for FdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadFd => write!(f, "file descriptor is not open"),
+        }
+    }
+}
+
+/// Open files of a process, indexed by file descriptor
+#[derive(Debug, Default)]
+pub struct FdTable {
+    handles: Vec<Option<FileHandle>>,
+}
+
+impl FdTable {
+    /// Creates a table without open files
+    pub const fn new() -> Self {
+        Self {
+            handles: Vec::new(),
+        }
+    }
+
+    /// Creates a table with stdin, stdout and stderr on the serial console
+    pub fn with_stdio() -> Self {
+        let mut table = Self::new();
+        for _ in [Fd::STDIN, Fd::STDOUT, Fd::STDERR] {
+            table.open(console::open_console());
+        }
+        table
+    }
+
+    /// Adds an open file
+    ///
+    /// # Returns
+    ///
+    /// The lowest descriptor that was not open
+    pub fn open(&mut self, handle: FileHandle) -> Fd {
+        let index = match self.handles.iter().position(Option::is_none) {
+            Some(index) => {
+                self.handles[index] = Some(handle);
+                index
+            }
+            None => {
+                self.handles.push(Some(handle));
+                self.handles.len() - 1
+            }
+        };
+        Fd(index as u32)
+    }
+
+    /// Closes a descriptor, dropping its handle
+    ///
+    /// # Errors
+    ///
+    /// Returns `FdError::BadFd` if `fd` is not open.
+    pub fn close(&mut self, fd: Fd) -> Result<(), FdError> {
+        self.handles
+            .get_mut(fd.0 as usize)
+            .and_then(Option::take)
+            .map(drop)
+            .ok_or(FdError::BadFd)
+    }
+
+    /// Returns the handle of an open descriptor
+    pub fn get(&self, fd: Fd) -> Option<&FileHandle> {
+        self.handles.get(fd.0 as usize)?.as_ref()
+    }
+
+    /// Returns the handle of an open descriptor mutably, e.g. to read or
+    /// write through it
+    pub fn get_mut(&mut self, fd: Fd) -> Option<&mut FileHandle> {
+        self.handles.get_mut(fd.0 as usize)?.as_mut()
+    }
+
+    /// Returns the number of open descriptors
+    pub fn len(&self) -> usize {
+        self.handles.iter().flatten().count()
+    }
+
+    /// Returns `true` if no descriptors are open
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+    use crate::fs::{
+        INodeKind,
+        RamFs,
+        Vfs,
+    };
+
+    fn vfs() -> Vfs {
+        let mut fs = RamFs::new();
+        fs.mount();
+        let mut vfs = Vfs::new();
+        vfs.mount("/", Box::new(fs)).unwrap();
+        vfs
+    }
+
+    #[test_case]
+    fn test_stdio_is_preallocated() {
+        let table = FdTable::with_stdio();
+        assert_eq!(table.len(), 3);
+        assert!(table.get(Fd::STDIN).is_some());
+        assert!(table.get(Fd::STDERR).is_some());
+        assert!(table.get(Fd(3)).is_none());
+    }
+
+    #[test_case]
+    fn test_write_through_fd_reaches_ramfs() {
+        let vfs = vfs();
+        let mut table = FdTable::with_stdio();
+        let fd = table.open(vfs.create("/notes", INodeKind::File).unwrap());
+        assert_eq!(fd, Fd(3));
+
+        let handle = table.get_mut(fd).unwrap();
+        assert_eq!(handle.write(b"written via fd"), Ok(14));
+        assert_eq!(table.close(fd), Ok(()));
+        assert!(table.get(fd).is_none());
+        assert_eq!(table.close(fd), Err(FdError::BadFd));
+
+        let mut buf = [0; 32];
+        let n = vfs.open("/notes").unwrap().read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"written via fd");
+    }
+
+    #[test_case]
+    fn test_lowest_descriptor_is_reused() {
+        let vfs = vfs();
+        let mut table = FdTable::new();
+        vfs.create("/f", INodeKind::File).unwrap();
+        for expected in 0..3 {
+            assert_eq!(table.open(vfs.open("/f").unwrap()), Fd(expected));
+        }
+        table.close(Fd(1)).unwrap();
+        assert_eq!(table.open(vfs.open("/f").unwrap()), Fd(1));
+        assert_eq!(table.open(vfs.open("/f").unwrap()), Fd(3));
+    }
+}
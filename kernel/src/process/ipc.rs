@@ -0,0 +1,809 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Inter-process communication
+//!
+//! Every process has a bounded queue of incoming messages. Sending to it
+//! requires an `Endpoint` capability with `WRITE` rights for its PID. A
+//! receiver with an empty queue waits in `WaitingForMessage` until a
+//! message is queued for it. A sender that finds the queue full is blocked
+//! and recorded in the recipient's `senders_waiting`; receiving a message
+//! frees a slot and makes the first waiting sender ready to retry.
+//!
+//! `call` and `reply` add synchronous, seL4-style IPC on top: the caller's
+//! message is queued like any other, but the caller then waits in
+//! `WaitingForReply` until the callee answers with `reply`, which hands the
+//! reply straight to the caller instead of queueing it. A callee that has
+//! received several calls answers them in the order it received them.
+//!
+//! Messages too large for the data words can carry a page instead, built
+//! with `Message::with_pages`. Sending one requires a `Memory` capability
+//! with `READ` rights for the frame. The frame is not copied: `send` pins
+//! it until the message is dropped, and `receive` maps it read-only into
+//! the payload window of the receiver's address space, where the receiver
+//! reads it at `Message::payload_addr`.
+//!
+//! These functions only update process states. `process::send_message`,
+//! `process::receive_message`, `process::call` and `process::reply` add the
+//! scheduling around them.
+
+use core::fmt;
+
+use super::{
+    capability::{
+        CapabilityError,
+        CapabilityRights,
+        CapabilityType,
+    },
+    process::{
+        ProcessId,
+        ProcessState,
+        ProcessTable,
+    },
+};
+use crate::memory::{
+    HeapFrameAllocator,
+    Page,
+    PageTable,
+    PageTableFlags,
+    PageTableManager,
+    PhysFrame,
+    VirtAddr,
+    cow::{
+        cow_map,
+        cow_release,
+        pin_frame,
+        release_frame,
+    },
+};
+
+/// Maximum number of messages queued for a process
+pub const MESSAGE_QUEUE_CAPACITY: usize = 16;
+
+/// Number of data words carried by a message
+pub const MESSAGE_WORDS: usize = 4;
+
+/// First page of the user range `map_payload` maps shared payloads into
+///
+/// Memory areas should stay out of it. Pages something else already maps
+/// are skipped.
+pub const PAYLOAD_WINDOW_START: u64 = 0x0000_7f00_0000_0000;
+
+/// Number of pages in the payload window
+pub const PAYLOAD_WINDOW_PAGES: u64 = 512;
+
+/// Flags a shared payload page is mapped with in the receiver
+///
+/// Read-only, so the receiver cannot change the sender's data under it.
+const PAYLOAD_FLAGS: PageTableFlags =
+    PageTableFlags::USER_ACCESSIBLE.union(PageTableFlags::NO_EXECUTE);
+
+/// Where the bytes of a message live
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessagePayload {
+    /// Only the data words carried in the message itself
+    Inline,
+    /// The first `len` bytes of a frame owned by the sender
+    Shared {
+        /// Frame holding the payload
+        frame: PhysFrame,
+        /// Number of valid bytes in the frame
+        len: usize,
+    },
+}
+
+/// An IPC message
+///
+/// A sent message with a shared payload pins the frame in `COW_FRAMES`
+/// until it is dropped, so the frame outlives the sender giving it up.
+/// Clones pin it again.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Message {
+    sender: ProcessId,
+    /// Set for messages sent by `call`, which expect a reply
+    call: bool,
+    /// Message payload
+    pub data: [u64; MESSAGE_WORDS],
+    payload: MessagePayload,
+    /// Set once `send` has pinned the shared payload frame
+    pinned: bool,
+    /// Where `map_payload` mapped the shared payload
+    mapped_at: Option<VirtAddr>,
+}
+
+impl Clone for Message {
+    fn clone(&self) -> Self {
+        if let (MessagePayload::Shared { frame, .. }, true) = (self.payload, self.pinned) {
+            pin_frame(frame);
+        }
+        Self {
+            sender: self.sender,
+            call: self.call,
+            data: self.data,
+            payload: self.payload,
+            pinned: self.pinned,
+            mapped_at: self.mapped_at,
+        }
+    }
+}
+
+impl Drop for Message {
+    fn drop(&mut self) {
+        if let (MessagePayload::Shared { frame, .. }, true) = (self.payload, self.pinned) {
+            // SAFETY: the pin taken by `send` or `clone` is the message's
+            // only use of the frame, which came from a `HeapFrameAllocator`
+            unsafe { release_frame(frame, &mut HeapFrameAllocator::new()) };
+        }
+    }
+}
+
+impl Message {
+    /// Creates a message carrying `data`
+    ///
+    /// The sender is filled in by `send`.
+    pub const fn new(data: [u64; MESSAGE_WORDS]) -> Self {
+        Self {
+            sender: ProcessId::new(0),
+            call: false,
+            data,
+            payload: MessagePayload::Inline,
+            pinned: false,
+            mapped_at: None,
+        }
+    }
+
+    /// Creates a message from `sender` carrying `tag` in its first data
+    /// word and the first `len` bytes of `frame`
+    ///
+    /// The frame stays owned by the sender, which must hold a `Memory`
+    /// capability for it. Once the message is sent, the sender gives the
+    /// frame up with `cow::release_frame`; it is freed when the message and
+    /// every mapping of the payload are gone as well.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is larger than a page.
+    pub fn with_pages(sender: ProcessId, tag: u64, frame: PhysFrame, len: usize) -> Self {
+        assert!(len as u64 <= Page::SIZE, "payload larger than a page");
+        Self {
+            sender,
+            call: false,
+            data: [tag, 0, 0, 0],
+            payload: MessagePayload::Shared { frame, len },
+            pinned: false,
+            mapped_at: None,
+        }
+    }
+
+    /// Returns the PID of the process that sent the message
+    pub fn sender(&self) -> ProcessId {
+        self.sender
+    }
+
+    /// Returns the tag, the first data word
+    pub fn tag(&self) -> u64 {
+        self.data[0]
+    }
+
+    /// Returns where the message's bytes live
+    pub fn payload(&self) -> MessagePayload {
+        self.payload
+    }
+
+    /// Returns the address the shared payload was mapped at
+    ///
+    /// `None` for inline messages, or if the payload is not mapped.
+    pub fn payload_addr(&self) -> Option<VirtAddr> {
+        self.mapped_at
+    }
+
+    /// Maps the shared payload read-only into `table`, the receiver's
+    /// address space
+    ///
+    /// The page is taken from the payload window and counts as a user of
+    /// the frame until it is unmapped with `unmap_payload` or the address
+    /// space is torn down. `receive` calls this for the receiver.
+    ///
+    /// # Returns
+    ///
+    /// The user address of the payload, or `None` for inline messages or if
+    /// the window or page tables ran out
+    pub fn map_payload(&mut self, table: &mut PageTableManager) -> Option<VirtAddr> {
+        let MessagePayload::Shared { frame, .. } = self.payload else {
+            return None;
+        };
+        if self.mapped_at.is_some() {
+            return self.mapped_at;
+        }
+        let window = Page::containing_address(VirtAddr::new(PAYLOAD_WINDOW_START));
+        let page = (0..PAYLOAD_WINDOW_PAGES)
+            .map(|i| window + i)
+            .find(|page| table.translate_addr(page.start_address()).is_none())?;
+        cow_map(
+            table,
+            page,
+            frame,
+            PAYLOAD_FLAGS,
+            &mut HeapFrameAllocator::new(),
+        )
+        .ok()?;
+        self.mapped_at = Some(page.start_address());
+        self.mapped_at
+    }
+
+    /// Unmaps the payload `map_payload` mapped into `table`
+    ///
+    /// The frame stays allocated while the message or another mapping
+    /// still uses it.
+    pub fn unmap_payload(&mut self, table: &mut PageTableManager) {
+        if let Some(addr) = self.mapped_at.take() {
+            cow_release(table, Page::containing_address(addr), 1);
+        }
+    }
+}
+
+/// Errors returned by IPC operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcError {
+    /// No live process with the recipient PID exists
+    RecipientNotFound,
+    /// The recipient's queue is full; the sender has been blocked
+    QueueFull,
+    /// The call was delivered and the caller is waiting for the reply
+    WouldBlock,
+    /// `reply` was used without a call to answer
+    NoPendingCall,
+    /// The sender lacks a capability to write to the recipient
+    Capability(CapabilityError),
+}
+
+impl From<CapabilityError> for IpcError {
+    fn from(err: CapabilityError) -> Self {
+        Self::Capability(err)
+    }
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RecipientNotFound => write!(f, "recipient does not exist or has exited"),
+            Self::QueueFull => write!(f, "recipient's message queue is full"),
+            Self::WouldBlock => write!(f, "call delivered, waiting for the reply"),
+            Self::NoPendingCall => write!(f, "no call to reply to"),
+            Self::Capability(err) => write!(f, "cannot send to recipient: {}", err),
+        }
+    }
+}
+
+/// Sends `msg` from `from` to `to`
+///
+/// Wakes the recipient if it is waiting for a message.
+///
+/// # Errors
+///
+/// Returns `IpcError::Capability` if `from` holds no `Endpoint` capability
+/// for `to` with `WRITE` rights or, for a shared payload, no `Memory`
+/// capability for its frame with `READ` rights,
+/// `IpcError::RecipientNotFound` if `to` does not exist or has terminated,
+/// or `IpcError::QueueFull` if its queue is full. In the latter case `from`
+/// is blocked until `to` receives a message and should retry the send once
+/// it runs again.
+pub fn send(
+    table: &mut ProcessTable,
+    from: ProcessId,
+    to: ProcessId,
+    mut msg: Message,
+) -> Result<(), IpcError> {
+    let capabilities = table
+        .get(from)
+        .ok_or(CapabilityError::NotFound)?
+        .capabilities();
+    capabilities.require(
+        CapabilityType::Endpoint,
+        to.as_u64(),
+        CapabilityRights::WRITE,
+    )?;
+    if let MessagePayload::Shared { frame, .. } = msg.payload {
+        capabilities.require(
+            CapabilityType::Memory,
+            frame.start_address().as_u64(),
+            CapabilityRights::READ,
+        )?;
+    }
+
+    let recipient = table
+        .get_mut(to)
+        .filter(|p| !p.is_zombie())
+        .ok_or(IpcError::RecipientNotFound)?;
+
+    msg.sender = from;
+    msg.mapped_at = None;
+    if let (MessagePayload::Shared { frame, .. }, false) = (msg.payload, msg.pinned) {
+        pin_frame(frame);
+        msg.pinned = true;
+    }
+    if recipient.queue_message(msg).is_err() {
+        if !recipient.senders_waiting.contains(&from) {
+            recipient.senders_waiting.push_back(from);
+        }
+        let _ = table.mark_blocked(from);
+        return Err(IpcError::QueueFull);
+    }
+
+    if recipient.state() == ProcessState::WaitingForMessage {
+        let _ = table.mark_ready(to);
+    }
+    Ok(())
+}
+
+/// Receives the oldest message queued for `pid`
+///
+/// Taking a message wakes the first sender blocked on the full queue. A
+/// shared payload is mapped into the address space of `pid` with
+/// `Message::map_payload`, unless `pid` has none.
+///
+/// # Returns
+///
+/// The message, or `None` if the queue is empty, in which case `pid` is
+/// marked as waiting for a message and should retry once it runs again
+pub fn receive(table: &mut ProcessTable, pid: ProcessId) -> Option<Message> {
+    let process = table.get_mut(pid)?;
+    let Some(mut msg) = process.pop_message() else {
+        let _ = table.mark_waiting_for_message(pid);
+        return None;
+    };
+    if let Some(p4) = process.page_table() {
+        // SAFETY: the P4 table was set up by the ELF loader and is
+        // identity-accessible
+        let mut address_space =
+            unsafe { PageTableManager::from_p4_table(&mut *(p4.as_u64() as *mut PageTable)) };
+        msg.map_payload(&mut address_space);
+    }
+    if msg.call {
+        process.reply_to.push_back(msg.sender);
+    }
+
+    // Skip senders that have exited or been woken some other way
+    while let Some(sender) = table.get_mut(pid)?.senders_waiting.pop_front() {
+        if table.get(sender).map(|p| p.state()) == Some(ProcessState::Blocked) {
+            let _ = table.mark_ready(sender);
+            break;
+        }
+    }
+    Some(msg)
+}
+
+/// Sends `msg` from `caller` to `callee` and waits for the reply
+///
+/// The first call queues the message, wakes `callee` if it is waiting for
+/// a message and blocks `caller` until `callee` replies. Once `caller`
+/// runs again, repeating the call returns the reply.
+///
+/// # Errors
+///
+/// Returns `IpcError::WouldBlock` once the message is delivered, or an
+/// error from `send` if it cannot be delivered.
+pub fn call(
+    table: &mut ProcessTable,
+    caller: ProcessId,
+    callee: ProcessId,
+    mut msg: Message,
+) -> Result<Message, IpcError> {
+    if let Some(reply) = table.get_mut(caller).and_then(|p| p.reply.take()) {
+        return Ok(reply);
+    }
+
+    msg.call = true;
+    send(table, caller, callee, msg)?;
+    let _ = table.mark_waiting_for_reply(caller, callee);
+    Err(IpcError::WouldBlock)
+}
+
+/// Answers the oldest call `callee` received but has not answered with `msg`
+///
+/// The reply is handed directly to the caller, which is made ready.
+///
+/// # Errors
+///
+/// Returns `IpcError::NoPendingCall` if `callee` has no call to answer, or
+/// `IpcError::RecipientNotFound` if the caller is no longer waiting for
+/// the reply.
+pub fn reply(
+    table: &mut ProcessTable,
+    callee: ProcessId,
+    mut msg: Message,
+) -> Result<(), IpcError> {
+    let caller = table
+        .get_mut(callee)
+        .and_then(|p| p.reply_to.pop_front())
+        .ok_or(IpcError::NoPendingCall)?;
+
+    let waiting = table
+        .get_mut(caller)
+        .filter(|p| p.state() == ProcessState::WaitingForReply(callee))
+        .ok_or(IpcError::RecipientNotFound)?;
+    msg.sender = callee;
+    msg.call = false;
+    waiting.reply = Some(msg);
+    let _ = table.mark_ready(caller);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        elf::tests::minimal_elf,
+        memory::{
+            FrameAllocator,
+            cow::COW_FRAMES,
+            frames_in_use,
+            heap::heap_phys_to_virt,
+        },
+        process::{
+            Capability,
+            Process,
+            Scheduler,
+        },
+    };
+
+    /// Creates a process that may send to PIDs 1 to `count`
+    fn process_with_endpoints(pid: ProcessId, count: usize) -> Process {
+        let mut process = Process::new(pid, "p");
+        for target in 1..=count as u64 {
+            process.capabilities_mut().insert(Capability::new(
+                CapabilityType::Endpoint,
+                target,
+                CapabilityRights::WRITE,
+            ));
+        }
+        process
+    }
+
+    fn table_with(count: usize) -> ProcessTable {
+        let mut table = ProcessTable::new();
+        for _ in 0..count {
+            let pid = table.allocate_pid().unwrap();
+            table
+                .add_process(process_with_endpoints(pid, count))
+                .unwrap();
+        }
+        table
+    }
+
+    fn state(table: &ProcessTable, pid: ProcessId) -> ProcessState {
+        table.get(pid).unwrap().state()
+    }
+
+    #[test_case]
+    fn test_send_pages() {
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+        let mut table = ProcessTable::new();
+        table.add_process(process_with_endpoints(a, 2)).unwrap();
+        table
+            .add_process(Process::from_elf(b, "b", &minimal_elf()).unwrap())
+            .unwrap();
+
+        let mut frames = HeapFrameAllocator::new();
+        let frame = frames.allocate_frame().unwrap();
+        let len = Page::SIZE as usize;
+        let source = heap_phys_to_virt(frame.start_address()).unwrap() as *mut u8;
+        for i in 0..len {
+            // SAFETY: the frame is ours and mapped in the heap
+            unsafe { source.add(i).write(i as u8 ^ 0x5a) };
+        }
+
+        // Sharing the frame requires a capability for it
+        assert_eq!(
+            send(&mut table, a, b, Message::with_pages(a, 7, frame, len)),
+            Err(IpcError::Capability(CapabilityError::NotFound))
+        );
+        table
+            .get_mut(a)
+            .unwrap()
+            .capabilities_mut()
+            .insert(Capability::new(
+                CapabilityType::Memory,
+                frame.start_address().as_u64(),
+                CapabilityRights::READ,
+            ));
+        send(&mut table, a, b, Message::with_pages(a, 7, frame, len)).unwrap();
+
+        // The message keeps the frame alive after the sender gives it up
+        // SAFETY: the sender no longer uses the frame
+        unsafe { release_frame(frame, &mut frames) };
+        assert!(COW_FRAMES.lock().contains_key(&frame));
+
+        let mut msg = receive(&mut table, b).unwrap();
+        assert_eq!(msg.sender(), a);
+        assert_eq!(msg.tag(), 7);
+        assert_eq!(msg.payload(), MessagePayload::Shared { frame, len });
+        let addr = msg.payload_addr().unwrap();
+        assert_eq!(addr.as_u64(), PAYLOAD_WINDOW_START);
+
+        let p4 = table.get(b).unwrap().page_table().unwrap();
+        // SAFETY: the P4 table was set up by the ELF loader and is
+        // identity-accessible
+        let mut space =
+            unsafe { PageTableManager::from_p4_table(&mut *(p4.as_u64() as *mut PageTable)) };
+        let flags = space.page_flags(Page::containing_address(addr)).unwrap();
+        assert!(flags.contains(PageTableFlags::USER_ACCESSIBLE));
+        assert!(!flags.contains(PageTableFlags::WRITABLE));
+        let phys = space.translate_addr(addr).unwrap();
+        assert_eq!(phys, frame.start_address());
+        let received = heap_phys_to_virt(phys).unwrap() as *const u8;
+        // SAFETY: the frame is still allocated and mapped in the heap
+        let received = unsafe { core::slice::from_raw_parts(received, len) };
+        assert!(
+            received
+                .iter()
+                .enumerate()
+                .all(|(i, &byte)| byte == i as u8 ^ 0x5a)
+        );
+
+        // A second payload takes the next page of the window
+        let mut clone = msg.clone();
+        clone.mapped_at = None;
+        assert_eq!(clone.map_payload(&mut space), Some(addr + Page::SIZE));
+        clone.unmap_payload(&mut space);
+        drop(clone);
+
+        msg.unmap_payload(&mut space);
+        assert!(msg.payload_addr().is_none());
+        assert!(space.translate_addr(addr).is_none());
+
+        // Dropping the message frees the frame
+        let in_use = frames_in_use();
+        drop(msg);
+        assert!(!COW_FRAMES.lock().contains_key(&frame));
+        assert_eq!(frames_in_use(), in_use - 1);
+    }
+
+    #[test_case]
+    fn test_inline_message_is_not_mapped() {
+        let mut table = table_with(2);
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+
+        send(&mut table, a, b, Message::new([1, 0, 0, 0])).unwrap();
+        let msg = receive(&mut table, b).unwrap();
+        assert_eq!(msg.payload(), MessagePayload::Inline);
+        assert!(msg.payload_addr().is_none());
+    }
+
+    #[test_case]
+    fn test_send_receive() {
+        let mut table = table_with(2);
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+
+        send(&mut table, a, b, Message::new([1, 2, 3, 4])).unwrap();
+        let msg = receive(&mut table, b).unwrap();
+        assert_eq!(msg.sender(), a);
+        assert_eq!(msg.data, [1, 2, 3, 4]);
+
+        let missing = ProcessId::new(99);
+        table
+            .get_mut(a)
+            .unwrap()
+            .capabilities_mut()
+            .insert(Capability::new(
+                CapabilityType::Endpoint,
+                missing.as_u64(),
+                CapabilityRights::WRITE,
+            ));
+        assert_eq!(
+            send(&mut table, a, missing, msg),
+            Err(IpcError::RecipientNotFound)
+        );
+    }
+
+    #[test_case]
+    fn test_receive_waits_and_send_wakes() {
+        let mut table = table_with(2);
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+
+        assert_eq!(receive(&mut table, b), None);
+        assert_eq!(state(&table, b), ProcessState::WaitingForMessage);
+
+        send(&mut table, a, b, Message::new([7; MESSAGE_WORDS])).unwrap();
+        assert_eq!(state(&table, b), ProcessState::Ready);
+        assert_eq!(receive(&mut table, b).unwrap().data, [7; MESSAGE_WORDS]);
+    }
+
+    #[test_case]
+    fn test_full_queue_blocks_sender_until_receive() {
+        let mut table = table_with(3);
+        let (a, b, c) = (ProcessId::new(1), ProcessId::new(2), ProcessId::new(3));
+
+        for i in 0..MESSAGE_QUEUE_CAPACITY {
+            send(&mut table, a, b, Message::new([i as u64; MESSAGE_WORDS])).unwrap();
+        }
+        let msg = Message::new([0; MESSAGE_WORDS]);
+        assert_eq!(
+            send(&mut table, a, b, msg.clone()),
+            Err(IpcError::QueueFull)
+        );
+        assert_eq!(
+            send(&mut table, c, b, msg.clone()),
+            Err(IpcError::QueueFull)
+        );
+        assert_eq!(state(&table, a), ProcessState::Blocked);
+        assert_eq!(state(&table, c), ProcessState::Blocked);
+
+        // Each receive frees one slot and wakes one sender, in order
+        assert_eq!(receive(&mut table, b).unwrap().data[0], 0);
+        assert_eq!(state(&table, a), ProcessState::Ready);
+        assert_eq!(state(&table, c), ProcessState::Blocked);
+        send(&mut table, a, b, msg).unwrap();
+
+        receive(&mut table, b).unwrap();
+        assert_eq!(state(&table, c), ProcessState::Ready);
+        assert!(table.get(b).unwrap().senders_waiting.is_empty());
+    }
+
+    #[test_case]
+    fn test_call_reply_cycle() {
+        let mut table = table_with(2);
+        let (client, server) = (ProcessId::new(1), ProcessId::new(2));
+
+        assert_eq!(receive(&mut table, server), None);
+        let request = Message::new([1, 2, 3, 4]);
+        assert_eq!(
+            call(&mut table, client, server, request.clone()),
+            Err(IpcError::WouldBlock)
+        );
+        assert_eq!(state(&table, client), ProcessState::WaitingForReply(server));
+        assert_eq!(state(&table, server), ProcessState::Ready);
+
+        // The server sees the request and answers it
+        let received = receive(&mut table, server).unwrap();
+        assert_eq!(received.sender(), client);
+        assert_eq!(received.data, [1, 2, 3, 4]);
+        let answer = Message::new(received.data.map(|word| word * 10));
+        reply(&mut table, server, answer).unwrap();
+        assert_eq!(state(&table, client), ProcessState::Ready);
+        assert_eq!(table.get(client).unwrap().pending_messages(), 0);
+
+        // The client, running again, gets the reply from its call
+        let answer = call(&mut table, client, server, request).unwrap();
+        assert_eq!(answer.sender(), server);
+        assert_eq!(answer.data, [10, 20, 30, 40]);
+
+        assert_eq!(
+            reply(&mut table, server, answer),
+            Err(IpcError::NoPendingCall)
+        );
+    }
+
+    #[test_case]
+    fn test_replies_follow_received_calls() {
+        let mut table = table_with(3);
+        let (first, second, server) = (ProcessId::new(1), ProcessId::new(2), ProcessId::new(3));
+
+        for client in [first, second] {
+            let request = Message::new([client.as_u64(); MESSAGE_WORDS]);
+            assert_eq!(
+                call(&mut table, client, server, request),
+                Err(IpcError::WouldBlock)
+            );
+        }
+
+        // Receiving the second call must not lose the first caller
+        let a = receive(&mut table, server).unwrap();
+        let b = receive(&mut table, server).unwrap();
+        reply(
+            &mut table,
+            server,
+            Message::new(a.data.map(|word| word * 10)),
+        )
+        .unwrap();
+        reply(
+            &mut table,
+            server,
+            Message::new(b.data.map(|word| word * 10)),
+        )
+        .unwrap();
+
+        for client in [first, second] {
+            assert_eq!(state(&table, client), ProcessState::Ready);
+            let answer = call(&mut table, client, server, Message::new([0; MESSAGE_WORDS]));
+            assert_eq!(answer.unwrap().data, [client.as_u64() * 10; MESSAGE_WORDS]);
+        }
+        assert_eq!(
+            reply(&mut table, server, Message::new([0; MESSAGE_WORDS])),
+            Err(IpcError::NoPendingCall)
+        );
+    }
+
+    #[test_case]
+    fn test_reply_requires_call() {
+        let mut table = table_with(2);
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+
+        send(&mut table, a, b, Message::new([0; MESSAGE_WORDS])).unwrap();
+        receive(&mut table, b).unwrap();
+        assert_eq!(
+            reply(&mut table, b, Message::new([0; MESSAGE_WORDS])),
+            Err(IpcError::NoPendingCall)
+        );
+    }
+
+    #[test_case]
+    fn test_send_requires_write_right() {
+        let mut table = ProcessTable::new();
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+        let mut sender = Process::new(a, "sender");
+        sender.capabilities_mut().insert(Capability::new(
+            CapabilityType::Endpoint,
+            b.as_u64(),
+            CapabilityRights::READ,
+        ));
+        table.add_process(sender).unwrap();
+        table.add_process(Process::new(b, "receiver")).unwrap();
+
+        let msg = Message::new([0; MESSAGE_WORDS]);
+        assert_eq!(
+            send(&mut table, a, b, msg.clone()),
+            Err(IpcError::Capability(CapabilityError::PermissionDenied))
+        );
+        assert_eq!(
+            call(&mut table, a, b, msg.clone()),
+            Err(IpcError::Capability(CapabilityError::PermissionDenied))
+        );
+        assert_eq!(table.get(b).unwrap().pending_messages(), 0);
+        assert_eq!(state(&table, a), ProcessState::Ready);
+
+        // The receiver holds no capability for `a` at all
+        assert_eq!(
+            send(&mut table, b, a, msg),
+            Err(IpcError::Capability(CapabilityError::NotFound))
+        );
+    }
+
+    #[test_case]
+    fn test_woken_receiver_is_dispatched() {
+        let mut scheduler = Scheduler::new();
+        for _ in 0..2 {
+            let pid = scheduler.table_mut().allocate_pid().unwrap();
+            scheduler
+                .add_process(process_with_endpoints(pid, 2))
+                .unwrap();
+        }
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+
+        // `b` blocks in receive, so `a` is dispatched again
+        let _ = scheduler.tick();
+        assert_eq!(scheduler.current(), Some(a));
+        let _ = scheduler.tick();
+        assert_eq!(scheduler.current(), Some(b));
+        assert_eq!(receive(scheduler.table_mut(), b), None);
+        let _ = scheduler.schedule();
+        assert_eq!(scheduler.current(), Some(a));
+        assert_eq!(scheduler.run_queue().count(), 0);
+
+        send(
+            scheduler.table_mut(),
+            a,
+            b,
+            Message::new([5; MESSAGE_WORDS]),
+        )
+        .unwrap();
+        scheduler.requeue_ready();
+        let _ = scheduler.schedule();
+        assert_eq!(scheduler.current(), Some(b));
+        assert_eq!(
+            receive(scheduler.table_mut(), b).unwrap().data,
+            [5; MESSAGE_WORDS]
+        );
+    }
+}
@@ -0,0 +1,489 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Process management
+//!
+//! This module provides process control blocks, the process table and the
+//! scheduler. The idle task always occupies PID 1. The boot thread running
+//! `kernel_main` is adopted as the next process so the scheduler can switch
+//! away from it; it hands the CPU over for good with `exit`.
+
+pub mod capability;
+pub mod context;
+pub mod fd;
+pub mod ipc;
+#[allow(clippy::module_inception)]
+pub mod process;
+pub mod scheduler;
+pub mod stack;
+pub mod vm;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+
+pub use capability::{
+    Capability,
+    CapabilityError,
+    CapabilityRights,
+    CapabilitySet,
+    CapabilityTree,
+    CapabilityType,
+    TIMER_OBJECT_ID,
+};
+pub use context::{
+    FpuContext,
+    ProcessContext,
+    enter_usermode,
+    switch_context,
+    switch_fpu,
+};
+pub use fd::{
+    Fd,
+    FdError,
+    FdTable,
+};
+pub use ipc::{
+    IpcError,
+    Message,
+    MessagePayload,
+};
+pub use process::{
+    DEFAULT_PROCESS_LIMIT,
+    Entry,
+    MAX_PROCESSES,
+    PidParseError,
+    Process,
+    ProcessError,
+    ProcessId,
+    ProcessState,
+    ProcessTable,
+    VacantEntry,
+    wait,
+};
+pub use scheduler::{
+    ContextSwitch,
+    MLFQ_LEVELS,
+    MlfqScheduler,
+    SCHEDULER,
+    SCHEDULER_BOOST_INTERVAL,
+    Scheduler,
+    cpu_utilization,
+    restore_current_fpu,
+};
+pub use stack::{
+    KERNEL_STACK_SIZE,
+    KernelStack,
+    StackError,
+};
+pub use vm::{
+    VmArea,
+    VmAreaList,
+    VmError,
+    VmKind,
+};
+
+use crate::{
+    elf::ElfError,
+    interrupts::timer,
+};
+
+/// Interval between CPU utilization reports from the idle task
+const CPU_REPORT_INTERVAL_MS: u64 = 10_000;
+
+/// Set while a `reap_terminated` job sits on the work queue
+static REAP_QUEUED: AtomicBool = AtomicBool::new(false);
+
+/// Body of the idle task
+///
+/// Enables interrupts and halts until the next one arrives, so an idle CPU
+/// does not spin. Runs deferred work, frees fired timer wheel entries and
+/// writes out queued log messages after every wakeup, runs the periodic
+/// reports that are due, and reports CPU utilization every
+/// `CPU_REPORT_INTERVAL_MS`.
+extern "C" fn idle_task() -> ! {
+    let mut last_report = timer::uptime_ms();
+    loop {
+        // SAFETY: halting with interrupts enabled only waits for the next
+        // interrupt.
+        unsafe { core::arch::asm!("sti; hlt") };
+
+        crate::wq::drain();
+        crate::time::timer_wheel::free_spent();
+        crate::io::logging::flush_log_queue();
+
+        let now = timer::uptime_ms();
+        crate::memory::heap::USAGE_REPORT.poll(now);
+        timer::LATENCY_REPORT.poll(now);
+        if now - last_report >= CPU_REPORT_INTERVAL_MS {
+            last_report = now;
+            crate::log_info!("CPU utilization: {}%", cpu_utilization());
+        }
+    }
+}
+
+/// Creates the idle task and registers it with the scheduler
+///
+/// The idle task is always `Ready` but the scheduler only picks it when no
+/// other process is ready.
+///
+/// # Panics
+///
+/// Panics if the idle task already exists or does not get PID 1.
+pub fn spawn_idle_task() -> ProcessId {
+    crate::interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        assert!(scheduler.idle_pid().is_none(), "idle task already spawned");
+
+        let pid = scheduler
+            .table_mut()
+            .allocate_pid()
+            .expect("process table full");
+        assert_eq!(pid, ProcessId::IDLE, "idle task must be PID 1");
+        let idle = Process::new_kernel_thread(pid, "idle", idle_task)
+            .expect("failed to allocate the idle task's stack");
+        scheduler
+            .add_process(idle)
+            .expect("idle PID already in use");
+
+        scheduler.set_idle(pid);
+        pid
+    })
+}
+
+/// Registers the calling thread as the running process
+///
+/// The thread keeps running on its own stack; its registers are saved into
+/// the new process the first time the scheduler switches away from it.
+pub fn adopt_current_thread(name: &'static str) -> ProcessId {
+    crate::interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let pid = scheduler
+            .table_mut()
+            .allocate_pid()
+            .expect("process table full");
+        scheduler
+            .add_process(Process::new(pid, name))
+            .expect("failed to add the adopted thread");
+        scheduler.set_current(pid).expect("adopted process missing");
+        pid
+    })
+}
+
+/// Loads an ELF executable and queues it with the scheduler
+///
+/// The process enters user mode the first time it is dispatched, see
+/// `Process::from_elf`.
+///
+/// # Errors
+///
+/// Returns the `ElfError` if the image cannot be loaded.
+///
+/// # Panics
+///
+/// Panics if the process table is full.
+pub fn spawn_elf(name: &'static str, image: &[u8]) -> Result<ProcessId, ElfError> {
+    crate::interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let pid = scheduler
+            .table_mut()
+            .allocate_pid()
+            .expect("process table full");
+        let process = Process::from_elf(pid, name, image)?;
+        scheduler
+            .add_process(process)
+            .expect("failed to add the ELF process");
+        Ok(pid)
+    })
+}
+
+/// Gives `pid` the capability `cap`
+///
+/// Replaces any capability the process holds for the same object.
+///
+/// # Errors
+///
+/// Returns `ProcessError::NotFound` if there is no process `pid`.
+pub fn grant_capability(pid: ProcessId, cap: Capability) -> Result<(), ProcessError> {
+    crate::interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .table_mut()
+            .get_mut(pid)
+            .ok_or(ProcessError::NotFound)?
+            .capabilities_mut()
+            .insert(cap);
+        Ok(())
+    })
+}
+
+/// Sets the most processes the system-wide table holds
+///
+/// # Errors
+///
+/// Returns `ProcessError::InvalidLimit` if `max` exceeds `MAX_PROCESSES`
+/// or is below the number of processes in the table.
+pub fn set_process_limit(max: usize) -> Result<(), ProcessError> {
+    crate::interrupts::without_interrupts(|| SCHEDULER.lock().table_mut().set_limit(max))
+}
+
+/// Gives up the CPU to the next ready process
+///
+/// The calling process stays ready and runs again when its turn comes
+/// round. Returns immediately if no other process is ready. Counted as a
+/// voluntary switch of the calling process.
+pub fn yield_now() {
+    crate::interrupts::without_interrupts(|| {
+        let switch = SCHEDULER.lock().yield_current();
+        if let Some(switch) = switch {
+            // SAFETY: interrupts are disabled, so the table cannot change
+            // before the switch.
+            unsafe { switch.perform() };
+        }
+    });
+}
+
+/// Sends `msg` from the running process to `to`
+///
+/// Blocks while the recipient's queue is full.
+///
+/// # Errors
+///
+/// Returns `IpcError::RecipientNotFound` if `to` does not exist or has
+/// terminated.
+///
+/// # Panics
+///
+/// Panics if there is no running process.
+pub fn send_message(to: ProcessId, msg: Message) -> Result<(), IpcError> {
+    crate::interrupts::without_interrupts(|| {
+        loop {
+            let switch = {
+                let mut scheduler = SCHEDULER.lock();
+                let pid = scheduler.current().expect("send without a running process");
+                let result = ipc::send(scheduler.table_mut(), pid, to, msg.clone());
+                scheduler.requeue_ready();
+                if result != Err(IpcError::QueueFull) {
+                    return result;
+                }
+                scheduler.schedule()
+            };
+            if let Some(switch) = switch {
+                // SAFETY: interrupts are disabled, so the table cannot change
+                // before the switch.
+                unsafe { switch.perform() };
+            }
+        }
+    })
+}
+
+/// Receives the next message for the running process
+///
+/// Blocks until a message arrives.
+///
+/// # Panics
+///
+/// Panics if there is no running process.
+pub fn receive_message() -> Message {
+    crate::interrupts::without_interrupts(|| {
+        loop {
+            let switch = {
+                let mut scheduler = SCHEDULER.lock();
+                let pid = scheduler
+                    .current()
+                    .expect("receive without a running process");
+                let msg = ipc::receive(scheduler.table_mut(), pid);
+                scheduler.requeue_ready();
+                if let Some(msg) = msg {
+                    return msg;
+                }
+                scheduler.schedule()
+            };
+            if let Some(switch) = switch {
+                // SAFETY: interrupts are disabled, so the table cannot change
+                // before the switch.
+                unsafe { switch.perform() };
+            }
+        }
+    })
+}
+
+/// Calls `callee` from the running process and returns its reply
+///
+/// Blocks until `callee` has received the message and answered it with
+/// `reply`.
+///
+/// # Errors
+///
+/// Returns `IpcError::RecipientNotFound` if `callee` does not exist or has
+/// terminated.
+///
+/// # Panics
+///
+/// Panics if there is no running process.
+pub fn call(callee: ProcessId, msg: Message) -> Result<Message, IpcError> {
+    crate::interrupts::without_interrupts(|| {
+        loop {
+            let switch = {
+                let mut scheduler = SCHEDULER.lock();
+                let pid = scheduler.current().expect("call without a running process");
+                let result = ipc::call(scheduler.table_mut(), pid, callee, msg.clone());
+                scheduler.requeue_ready();
+                if !matches!(result, Err(IpcError::WouldBlock | IpcError::QueueFull)) {
+                    return result;
+                }
+                scheduler.schedule()
+            };
+            if let Some(switch) = switch {
+                // SAFETY: interrupts are disabled, so the table cannot change
+                // before the switch.
+                unsafe { switch.perform() };
+            }
+        }
+    })
+}
+
+/// Answers the oldest unanswered call the running process received
+///
+/// # Errors
+///
+/// Returns the `ipc::reply` error if there is no caller to answer.
+///
+/// # Panics
+///
+/// Panics if there is no running process.
+pub fn reply(msg: Message) -> Result<(), IpcError> {
+    crate::interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let pid = scheduler
+            .current()
+            .expect("reply without a running process");
+        let result = ipc::reply(scheduler.table_mut(), pid, msg);
+        scheduler.requeue_ready();
+        result
+    })
+}
+
+/// Terminates the running process with `exit_code` and switches to the
+/// next one
+///
+/// The process stays in the table as a zombie until its parent collects
+/// the exit code with `wait`.
+///
+/// # Panics
+///
+/// Panics if there is no running process or nothing to switch to.
+pub fn exit(exit_code: i32) -> ! {
+    crate::interrupts::without_interrupts(|| {
+        let switch = {
+            let mut scheduler = SCHEDULER.lock();
+            let pid = scheduler.current().expect("exit without a running process");
+            scheduler
+                .terminate(pid, exit_code)
+                .expect("running process missing");
+            scheduler.schedule()
+        };
+        let switch = switch.expect("no process to switch to");
+        // SAFETY: interrupts are disabled, so the table cannot change
+        // before the switch.
+        unsafe { switch.perform() };
+    });
+    unreachable!("terminated process was resumed");
+}
+
+/// Removes terminated processes no parent will wait for from the table
+///
+/// Frees their kernel stacks and other resources. Runs from the work queue
+/// rather than the timer interrupt, since dropping a process frees memory.
+/// Zombies whose parent is still alive are left for `wait`.
+///
+/// # Returns
+///
+/// The number of processes removed.
+pub fn reap_terminated() -> usize {
+    REAP_QUEUED.store(false, Ordering::Release);
+    let reaped: Vec<Process> = crate::interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current();
+        let pids: Vec<ProcessId> = scheduler
+            .table()
+            .iter()
+            .map(Process::pid)
+            .filter(|&pid| scheduler.table().is_orphan_zombie(pid) && Some(pid) != current)
+            .collect();
+        pids.into_iter()
+            .filter_map(|pid| scheduler.table_mut().remove(pid))
+            .collect()
+    });
+    // Dropped here, outside the lock
+    reaped.len()
+}
+
+/// Queues `reap_terminated` if there are terminated processes to reap
+///
+/// Called from the timer interrupt, so it only uses `try_lock` and gives up
+/// if the scheduler lock is held, and queues the job with
+/// `wq::submit_from_irq`, which does not allocate. If the job cannot be
+/// queued the next tick tries again.
+pub fn defer_reap() {
+    let terminated = SCHEDULER.try_lock().is_some_and(|scheduler| {
+        let table = scheduler.table();
+        table.iter().any(|p| table.is_orphan_zombie(p.pid()))
+    });
+    if !terminated || REAP_QUEUED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let reap: fn() = || {
+        reap_terminated();
+    };
+    if crate::wq::submit_from_irq(reap).is_err() {
+        REAP_QUEUED.store(false, Ordering::Release);
+    }
+}
+
+/// Initializes process management
+///
+/// Spawns the idle task as PID 1 and, if `init_image` is given, loads it
+/// as `init`, the first user process, with PID 2. The calling boot thread
+/// is then adopted as the running process. Interrupts stay disabled
+/// throughout, so init cannot run before it holds its timer capability.
+/// Must be called after the heap is initialized.
+///
+/// # Returns
+///
+/// The result of loading init, or `None` if there is no init image
+pub fn init(init_image: Option<&[u8]>) -> Option<Result<ProcessId, ElfError>> {
+    crate::interrupts::without_interrupts(|| {
+        let idle = spawn_idle_task();
+        crate::log_debug!("Idle task spawned with PID {}", idle);
+        let init = init_image.map(spawn_init);
+        let boot = adopt_current_thread("kernel_main");
+        crate::log_debug!("Boot thread adopted as PID {}", boot);
+        init
+    })
+}
+
+/// Loads init and grants it the timer capability
+fn spawn_init(image: &[u8]) -> Result<ProcessId, ElfError> {
+    let pid = spawn_elf("init", image)?;
+    let timer = Capability::new(
+        CapabilityType::Timer,
+        TIMER_OBJECT_ID,
+        CapabilityRights::all(),
+    );
+    grant_capability(pid, timer).expect("init was just spawned");
+    Ok(pid)
+}
@@ -0,0 +1,1255 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Process control blocks and the process table
+
+use alloc::{
+    boxed::Box,
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    vec::Vec,
+};
+use core::{
+    fmt,
+    num::IntErrorKind,
+    str::FromStr,
+};
+
+use super::{
+    capability::CapabilitySet,
+    context::{
+        FpuContext,
+        ProcessContext,
+        enter_usermode,
+    },
+    fd::FdTable,
+    ipc::{
+        MESSAGE_QUEUE_CAPACITY,
+        Message,
+    },
+    stack::{
+        KernelStack,
+        StackError,
+    },
+    vm::VmAreaList,
+};
+use crate::{
+    boot::{
+        BootPhase,
+        boot_phase,
+        set_boot_phase,
+    },
+    elf::{
+        Elf64Loader,
+        ElfError,
+        USER_STACK_TOP,
+    },
+    interrupts::timer::TIMER_FREQUENCY,
+    memory::{
+        HeapFrameAllocator,
+        PageTable,
+        PageTableManager,
+        PhysAddr,
+        VirtAddr,
+        cow,
+        slab::SlabBox,
+    },
+    sync::MpscQueue,
+};
+
+/// Highest limit a process table can be given
+pub const MAX_PROCESSES: usize = 65536;
+
+/// Limit of a process table created with `ProcessTable::new`
+pub const DEFAULT_PROCESS_LIMIT: usize = 1024;
+
+/// Name of the slab cache process control blocks are allocated from
+pub const PROCESS_CACHE: &str = "process";
+
+/// Process identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct ProcessId(u64);
+
+impl ProcessId {
+    /// PID reserved for the idle task
+    pub const IDLE: Self = Self(1);
+
+    /// Create a process ID from a raw value
+    pub const fn new(pid: u64) -> Self {
+        Self(pid)
+    }
+
+    /// Get the PID as u64
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ProcessId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for ProcessId {
+    type Err = PidParseError;
+
+    /// Parses a PID typed by the user, in decimal or with a `0x` prefix in
+    /// hexadecimal
+    fn from_str(s: &str) -> Result<Self, PidParseError> {
+        let (digits, radix) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => (hex, 16),
+            None => (s, 10),
+        };
+        // from_str_radix accepts a leading '+', which a PID never has
+        if digits.is_empty() || digits.starts_with('+') {
+            return Err(PidParseError::InvalidDigit);
+        }
+        let pid = u64::from_str_radix(digits, radix).map_err(|err| match err.kind() {
+            IntErrorKind::PosOverflow => PidParseError::OutOfRange,
+            _ => PidParseError::InvalidDigit,
+        })?;
+        if pid > MAX_PROCESSES as u64 {
+            return Err(PidParseError::OutOfRange);
+        }
+        Ok(Self(pid))
+    }
+}
+
+/// Errors returned when parsing a `ProcessId` from a string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PidParseError {
+    /// The string is empty or not a decimal or `0x` hexadecimal number
+    InvalidDigit,
+    /// The number exceeds `MAX_PROCESSES`
+    OutOfRange,
+}
+
+impl fmt::Display for PidParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidDigit => write!(f, "invalid PID"),
+            Self::OutOfRange => write!(f, "PID above {}", MAX_PROCESSES),
+        }
+    }
+}
+
+/// Scheduling state of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    /// Runnable, waiting for the CPU
+    Ready,
+    /// Currently executing
+    Running,
+    /// Waiting for an event other than a message
+    Blocked,
+    /// Waiting in `receive` for an IPC message
+    WaitingForMessage,
+    /// Waiting in `call` for the reply from the given process
+    WaitingForReply(ProcessId),
+    /// Exited with the given code, waiting for the parent to collect it
+    /// with `wait`
+    Zombie(i32),
+}
+
+/// Errors returned by process table operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessError {
+    /// No process with the given PID exists
+    NotFound,
+    /// The table has reached its limit
+    TableFull,
+    /// The limit is above `MAX_PROCESSES` or below the number of processes
+    InvalidLimit(usize),
+    /// A process with the given PID already exists
+    AlreadyExists,
+    /// The process's kernel stack could not be allocated
+    KernelStack(StackError),
+}
+
+impl From<StackError> for ProcessError {
+    fn from(err: StackError) -> Self {
+        Self::KernelStack(err)
+    }
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no such process"),
+            Self::TableFull => write!(f, "process table is full"),
+            Self::InvalidLimit(limit) => write!(f, "invalid process limit: {}", limit),
+            Self::AlreadyExists => write!(f, "a process with that PID already exists"),
+            Self::KernelStack(err) => write!(f, "cannot allocate kernel stack: {}", err),
+        }
+    }
+}
+
+/// Process control block
+#[derive(Debug)]
+pub struct Process {
+    pid: ProcessId,
+    name: &'static str,
+    state: ProcessState,
+    /// Saved registers while the process is not running
+    pub context: ProcessContext,
+    /// Saved FPU and SSE registers, allocated when the process is added to
+    /// the table
+    pub fpu: Option<Box<FpuContext>>,
+    /// Stack the process runs on in the kernel, and enters the kernel on
+    /// from user mode
+    kernel_stack: Option<KernelStack>,
+    /// P4 table of the process's own address space, if it has one
+    page_table: Option<PhysAddr>,
+    /// Capabilities the process holds
+    capabilities: CapabilitySet,
+    /// Open files
+    fd_table: FdTable,
+    /// Areas of the address space the process may access
+    vm_areas: VmAreaList,
+    /// IPC messages waiting to be received, oldest first
+    messages: MpscQueue<Message, { MESSAGE_QUEUE_CAPACITY + 1 }>,
+    /// Processes blocked in `ipc::send` because `messages` was full
+    pub senders_waiting: VecDeque<ProcessId>,
+    /// Reply delivered by `ipc::reply`, not yet returned from `ipc::call`
+    pub(super) reply: Option<Message>,
+    /// Callers of the calls received but not yet answered, oldest first
+    pub(super) reply_to: VecDeque<ProcessId>,
+    /// Scheduler ticks spent running, up to the last switch away
+    cpu_time_ticks: u64,
+    /// Scheduler tick at which the process was last dispatched
+    last_scheduled_tick: u64,
+    /// Number of times the process gave up the CPU with `yield`
+    voluntary_switches: u64,
+    /// Scheduling priority, 0 being the highest; the scheduler keeps it at
+    /// the process's MLFQ level
+    priority: u8,
+    /// Process that created this one and collects its exit code
+    parent: Option<ProcessId>,
+}
+
+impl Process {
+    /// Creates a process in the `Ready` state with an empty context
+    pub fn new(pid: ProcessId, name: &'static str) -> Self {
+        Self {
+            pid,
+            name,
+            state: ProcessState::Ready,
+            context: ProcessContext::default(),
+            fpu: None,
+            kernel_stack: None,
+            page_table: None,
+            capabilities: CapabilitySet::new(),
+            fd_table: FdTable::with_stdio(),
+            vm_areas: VmAreaList::new(),
+            messages: MpscQueue::new(),
+            senders_waiting: VecDeque::new(),
+            reply: None,
+            reply_to: VecDeque::new(),
+            cpu_time_ticks: 0,
+            last_scheduled_tick: 0,
+            voluntary_switches: 0,
+            priority: 0,
+            parent: None,
+        }
+    }
+
+    /// Creates a child of `parent` with the PID `pid`
+    ///
+    /// The child gets copies of the parent's capabilities and memory areas
+    /// and shares its address space and priority. It starts with an empty
+    /// context, like `new`, and gets its own kernel stack when added to the
+    /// table.
+    pub fn fork_from(pid: ProcessId, parent: &Process) -> Self {
+        Self {
+            page_table: parent.page_table,
+            capabilities: parent.capabilities.clone(),
+            vm_areas: parent.vm_areas.clone(),
+            priority: parent.priority,
+            parent: Some(parent.pid),
+            ..Self::new(pid, parent.name)
+        }
+    }
+
+    /// Creates a kernel thread that starts executing at `entry`
+    ///
+    /// The thread runs on its kernel stack, which is allocated here rather
+    /// than when the thread is added to the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `StackError` if the kernel stack cannot be allocated.
+    pub fn new_kernel_thread(
+        pid: ProcessId,
+        name: &'static str,
+        entry: extern "C" fn() -> !,
+    ) -> Result<Self, StackError> {
+        let stack = KernelStack::allocate()?;
+
+        Ok(Self {
+            context: ProcessContext::new_kernel(entry as usize as u64, stack.top().as_u64()),
+            kernel_stack: Some(stack),
+            ..Self::new(pid, name)
+        })
+    }
+
+    /// Creates a process from an ELF executable
+    ///
+    /// The image is loaded into a new address space. The process starts in
+    /// ring 0 on its kernel stack, which is allocated here, and drops to
+    /// the ELF entry point in ring 3 on the user stack the loader mapped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ElfError` if the image cannot be loaded, or
+    /// `ElfError::OutOfMemory` if the kernel stack cannot be allocated.
+    pub fn from_elf(pid: ProcessId, name: &'static str, image: &[u8]) -> Result<Self, ElfError> {
+        let loader = Elf64Loader::new(image)?;
+        let stack = KernelStack::allocate().map_err(|_| ElfError::OutOfMemory)?;
+        let loaded = loader.load(&mut HeapFrameAllocator::new())?;
+
+        let mut context = ProcessContext::new_kernel(
+            enter_user_process as *const () as u64,
+            stack.top().as_u64(),
+        );
+        context.rdi = loaded.entry_point.as_u64();
+        Ok(Self {
+            context,
+            kernel_stack: Some(stack),
+            page_table: Some(loaded.page_table),
+            ..Self::new(pid, name)
+        })
+    }
+
+    /// Returns the process ID
+    pub fn pid(&self) -> ProcessId {
+        self.pid
+    }
+
+    /// Returns the process name
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the current scheduling state
+    pub fn state(&self) -> ProcessState {
+        self.state
+    }
+
+    /// Returns `true` if the process has exited
+    pub fn is_zombie(&self) -> bool {
+        matches!(self.state, ProcessState::Zombie(_))
+    }
+
+    /// Returns the PID of the parent process, if it has one
+    pub fn parent(&self) -> Option<ProcessId> {
+        self.parent
+    }
+
+    /// Returns the code the process exited with, if it has exited
+    pub fn exit_code(&self) -> Option<i32> {
+        match self.state {
+            ProcessState::Zombie(code) => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Allocates the process's kernel stack
+    ///
+    /// Does nothing if the process already owns one.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `StackError` if the stack cannot be allocated.
+    pub fn allocate_kernel_stack(&mut self) -> Result<(), StackError> {
+        if self.kernel_stack.is_none() {
+            self.kernel_stack = Some(KernelStack::allocate()?);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the process owns a kernel stack
+    pub fn has_kernel_stack(&self) -> bool {
+        self.kernel_stack.is_some()
+    }
+
+    /// Returns the process's kernel stack, if it owns one
+    pub fn kernel_stack(&self) -> Option<&KernelStack> {
+        self.kernel_stack.as_ref()
+    }
+
+    /// Returns the top of the process's kernel stack, if it owns one
+    pub fn kernel_stack_top(&self) -> Option<VirtAddr> {
+        self.kernel_stack.as_ref().map(KernelStack::top)
+    }
+
+    /// Returns the P4 table of the process's address space
+    ///
+    /// Kernel threads share the kernel address space and return `None`.
+    pub fn page_table(&self) -> Option<PhysAddr> {
+        self.page_table
+    }
+
+    /// Returns the capabilities the process holds
+    pub fn capabilities(&self) -> &CapabilitySet {
+        &self.capabilities
+    }
+
+    /// Returns the capabilities the process holds mutably
+    pub fn capabilities_mut(&mut self) -> &mut CapabilitySet {
+        &mut self.capabilities
+    }
+
+    /// Returns the memory areas of the process
+    pub fn vm_areas(&self) -> &VmAreaList {
+        &self.vm_areas
+    }
+
+    /// Returns the memory areas of the process mutably
+    pub fn vm_areas_mut(&mut self) -> &mut VmAreaList {
+        &mut self.vm_areas
+    }
+
+    /// Returns the open files of the process
+    pub fn fd_table(&self) -> &FdTable {
+        &self.fd_table
+    }
+
+    /// Returns the open files of the process mutably
+    pub fn fd_table_mut(&mut self) -> &mut FdTable {
+        &mut self.fd_table
+    }
+
+    /// Returns the scheduler ticks the process has run for
+    ///
+    /// Only counts up to the last time it was switched away from, so time
+    /// in the current run is not included yet.
+    pub fn cpu_time_ticks(&self) -> u64 {
+        self.cpu_time_ticks
+    }
+
+    /// Returns the CPU time the process has used, in milliseconds
+    pub fn cpu_time_ms(&self) -> u64 {
+        self.cpu_time_ticks * 1000 / u64::from(TIMER_FREQUENCY)
+    }
+
+    /// Returns the scheduler tick at which the process was last dispatched
+    pub fn last_scheduled_tick(&self) -> u64 {
+        self.last_scheduled_tick
+    }
+
+    /// Returns the number of times the process yielded the CPU
+    pub fn voluntary_switches(&self) -> u64 {
+        self.voluntary_switches
+    }
+
+    /// Returns the scheduling priority, 0 being the highest
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// Sets the scheduling priority, 0 being the highest
+    pub fn set_priority(&mut self, priority: u8) {
+        self.priority = priority;
+    }
+
+    /// Records that the process was dispatched at scheduler tick `tick`
+    pub(super) fn mark_scheduled(&mut self, tick: u64) {
+        self.last_scheduled_tick = tick;
+    }
+
+    /// Adds the time since the process was dispatched to its CPU time, as
+    /// it is switched away from at scheduler tick `tick`
+    pub(super) fn charge_cpu_time(&mut self, tick: u64) {
+        self.cpu_time_ticks += tick.saturating_sub(self.last_scheduled_tick);
+        self.last_scheduled_tick = tick;
+    }
+
+    /// Counts a voluntary switch away from the process
+    pub(super) fn record_voluntary_switch(&mut self) {
+        self.voluntary_switches += 1;
+    }
+
+    /// Returns the number of IPC messages waiting to be received
+    pub fn pending_messages(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Appends a message to the IPC queue
+    ///
+    /// The queue is lock-free, so senders only need shared access.
+    ///
+    /// # Errors
+    ///
+    /// Returns the message back if `MESSAGE_QUEUE_CAPACITY` messages are
+    /// already queued.
+    pub fn queue_message(&self, msg: Message) -> Result<(), Message> {
+        self.messages.push(msg)
+    }
+
+    /// Removes the oldest message from the IPC queue
+    pub fn pop_message(&self) -> Option<Message> {
+        self.messages.pop()
+    }
+}
+
+/// Table of all processes, ordered by PID
+///
+/// Process control blocks live in the `PROCESS_CACHE` slab cache, so they
+/// do not move while in the table and spawning and reaping processes does
+/// not fragment the heap.
+///
+/// There is no separate global table: the system-wide one is owned by
+/// `SCHEDULER`, since the run queue must agree with it and both are
+/// updated under the same lock. It starts with `DEFAULT_PROCESS_LIMIT`,
+/// and `process::set_process_limit` changes the limit at runtime.
+pub struct ProcessTable {
+    processes: BTreeMap<ProcessId, SlabBox<Process>>,
+    next_pid: u64,
+    /// Most processes the table holds
+    limit: usize,
+}
+
+impl ProcessTable {
+    /// Creates an empty process table holding up to
+    /// `DEFAULT_PROCESS_LIMIT` processes
+    ///
+    /// The first allocated PID is 1, which the idle task takes.
+    pub const fn new() -> Self {
+        Self::with_limit(DEFAULT_PROCESS_LIMIT)
+    }
+
+    /// Creates an empty process table holding up to `max` processes
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` exceeds `MAX_PROCESSES`.
+    pub const fn with_limit(max: usize) -> Self {
+        assert!(max <= MAX_PROCESSES, "process limit above MAX_PROCESSES");
+        Self {
+            processes: BTreeMap::new(),
+            next_pid: 1,
+            limit: max,
+        }
+    }
+
+    /// Returns the most processes the table holds
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Changes the most processes the table holds
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProcessError::InvalidLimit` if `max` exceeds
+    /// `MAX_PROCESSES` or is below the number of processes in the table.
+    pub fn set_limit(&mut self, max: usize) -> Result<(), ProcessError> {
+        if max > MAX_PROCESSES || self.len() > max {
+            return Err(ProcessError::InvalidLimit(max));
+        }
+        self.limit = max;
+        Ok(())
+    }
+
+    /// Allocates an unused PID
+    ///
+    /// PIDs are handed out in increasing order and wrap around to 1 after
+    /// `MAX_PROCESSES`, skipping those still in use. The table never holds
+    /// more than `MAX_PROCESSES` processes, so a free one always exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProcessError::TableFull` if the table is at its limit.
+    pub fn allocate_pid(&mut self) -> Result<ProcessId, ProcessError> {
+        if self.processes.len() >= self.limit {
+            return Err(ProcessError::TableFull);
+        }
+
+        loop {
+            let pid = ProcessId(self.next_pid);
+            self.next_pid = if self.next_pid >= MAX_PROCESSES as u64 {
+                1
+            } else {
+                self.next_pid + 1
+            };
+            if !self.processes.contains_key(&pid) {
+                return Ok(pid);
+            }
+        }
+    }
+
+    /// Adds a process to the table
+    ///
+    /// A process without a kernel stack or FPU state gets them allocated,
+    /// so the #NM handler never has to.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProcessError::AlreadyExists` if the PID is taken,
+    /// `ProcessError::TableFull` if the table is full, or
+    /// `ProcessError::KernelStack` if the kernel stack cannot be allocated.
+    pub fn add_process(&mut self, mut process: Process) -> Result<ProcessId, ProcessError> {
+        let pid = process.pid;
+        if self.processes.contains_key(&pid) {
+            return Err(ProcessError::AlreadyExists);
+        }
+        if self.processes.len() >= self.limit {
+            return Err(ProcessError::TableFull);
+        }
+        process.allocate_kernel_stack()?;
+        process
+            .fpu
+            .get_or_insert_with(|| Box::new(FpuContext::new()));
+
+        self.processes
+            .insert(pid, SlabBox::new(PROCESS_CACHE, process));
+        Ok(pid)
+    }
+
+    /// Returns the entry for `pid`, to inspect or fill in place
+    pub fn entry(&mut self, pid: ProcessId) -> Entry<'_> {
+        if self.processes.contains_key(&pid) {
+            let process = self.get_mut(pid).expect("entry checked above");
+            Entry::Occupied(process)
+        } else {
+            Entry::Vacant(VacantEntry { table: self, pid })
+        }
+    }
+
+    /// Returns the process with the given PID, creating it with `factory`
+    /// if it does not exist
+    ///
+    /// A created process gets a kernel stack, as in `add_process`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `add_process` error if the process cannot be added.
+    pub fn get_or_create(
+        &mut self,
+        pid: ProcessId,
+        factory: impl FnOnce(ProcessId) -> Process,
+    ) -> Result<&mut Process, ProcessError> {
+        match self.entry(pid) {
+            Entry::Occupied(process) => Ok(process),
+            Entry::Vacant(entry) => entry.insert(factory(pid)),
+        }
+    }
+
+    /// Removes a process from the table
+    ///
+    /// If no process left in the table shares its address space, the
+    /// address space is torn down: its frames and page tables are freed and
+    /// the removed process keeps no page table.
+    pub fn remove(&mut self, pid: ProcessId) -> Option<Process> {
+        let mut process = self.processes.remove(&pid).map(SlabBox::into_inner)?;
+        let unshared = process
+            .page_table
+            .filter(|&p4| !self.iter().any(|other| other.page_table == Some(p4)));
+        if let Some(p4) = unshared {
+            process.page_table = None;
+            // SAFETY: the P4 table was set up by the ELF loader and no
+            // process uses it any more
+            unsafe { release_address_space(p4) };
+        }
+        Some(process)
+    }
+
+    /// Returns a reference to the process with the given PID
+    pub fn get(&self, pid: ProcessId) -> Option<&Process> {
+        self.processes.get(&pid).map(|p| &**p)
+    }
+
+    /// Returns a mutable reference to the process with the given PID
+    pub fn get_mut(&mut self, pid: ProcessId) -> Option<&mut Process> {
+        self.processes.get_mut(&pid).map(|p| &mut **p)
+    }
+
+    /// Returns `true` if a process with the given PID exists
+    pub fn contains(&self, pid: ProcessId) -> bool {
+        self.processes.contains_key(&pid)
+    }
+
+    /// Returns the number of processes
+    pub fn len(&self) -> usize {
+        self.processes.len()
+    }
+
+    /// Returns `true` if the table has no processes
+    pub fn is_empty(&self) -> bool {
+        self.processes.is_empty()
+    }
+
+    /// Iterates over all processes in PID order
+    pub fn iter(&self) -> impl Iterator<Item = &Process> {
+        self.processes.values().map(|p| &**p)
+    }
+
+    /// Returns all processes, the one with the most CPU time first
+    ///
+    /// Processes with equal CPU time stay in PID order.
+    pub fn processes_sorted_by_cpu_time(&self) -> Vec<&Process> {
+        let mut processes: Vec<&Process> = self.iter().collect();
+        processes.sort_by_key(|p| core::cmp::Reverse(p.cpu_time_ticks));
+        processes
+    }
+
+    /// Iterates over all processes, highest priority first
+    ///
+    /// Processes with equal priority stay in PID order.
+    pub fn processes_by_priority(&self) -> impl Iterator<Item = &Process> {
+        let mut processes: Vec<&Process> = self.iter().collect();
+        processes.sort_by_key(|p| p.priority);
+        processes.into_iter()
+    }
+
+    /// Iterates over the `Ready` processes, highest priority first
+    ///
+    /// Processes with equal priority stay in PID order.
+    pub fn ready_by_priority(&self) -> impl Iterator<Item = &Process> {
+        self.processes_by_priority()
+            .filter(|p| p.state == ProcessState::Ready)
+    }
+
+    /// Iterates mutably over all processes in PID order
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Process> {
+        self.processes.values_mut().map(|p| &mut **p)
+    }
+
+    /// Marks a process as ready to run
+    pub fn mark_ready(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
+        self.set_state(pid, ProcessState::Ready)
+    }
+
+    /// Marks a process as running
+    pub fn mark_running(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
+        self.set_state(pid, ProcessState::Running)
+    }
+
+    /// Marks a process as blocked
+    pub fn mark_blocked(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
+        self.set_state(pid, ProcessState::Blocked)
+    }
+
+    /// Marks a process as waiting for an IPC message
+    pub fn mark_waiting_for_message(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
+        self.set_state(pid, ProcessState::WaitingForMessage)
+    }
+
+    /// Marks a process as waiting for a reply from `callee`
+    pub fn mark_waiting_for_reply(
+        &mut self,
+        pid: ProcessId,
+        callee: ProcessId,
+    ) -> Result<(), ProcessError> {
+        self.set_state(pid, ProcessState::WaitingForReply(callee))
+    }
+
+    /// Marks a process as exited with `exit_code`
+    ///
+    /// It stays in the table as a zombie until its parent collects the exit
+    /// code with `wait`, or is reaped as an orphan if it has no parent. Its
+    /// address space is freed when it is removed from the table.
+    pub fn mark_terminated(&mut self, pid: ProcessId, exit_code: i32) -> Result<(), ProcessError> {
+        self.set_state(pid, ProcessState::Zombie(exit_code))
+    }
+
+    /// Returns `true` if `pid` is an exited process that no one will wait
+    /// for, because its parent is gone or it never had one
+    pub fn is_orphan_zombie(&self, pid: ProcessId) -> bool {
+        self.get(pid).is_some_and(|process| {
+            process.is_zombie()
+                && process
+                    .parent
+                    .and_then(|parent| self.get(parent))
+                    .is_none_or(Process::is_zombie)
+        })
+    }
+
+    fn set_state(&mut self, pid: ProcessId, state: ProcessState) -> Result<(), ProcessError> {
+        let process = self.processes.get_mut(&pid).ok_or(ProcessError::NotFound)?;
+        process.state = state;
+        Ok(())
+    }
+}
+
+impl Default for ProcessTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A PID's slot in a `ProcessTable`, returned by `ProcessTable::entry`
+pub enum Entry<'a> {
+    /// A process with the PID exists
+    Occupied(&'a mut Process),
+    /// No process has the PID
+    Vacant(VacantEntry<'a>),
+}
+
+impl Entry<'_> {
+    /// Returns the PID of the entry
+    pub fn pid(&self) -> ProcessId {
+        match self {
+            Self::Occupied(process) => process.pid(),
+            Self::Vacant(entry) => entry.pid,
+        }
+    }
+}
+
+/// A free PID in a `ProcessTable`
+pub struct VacantEntry<'a> {
+    table: &'a mut ProcessTable,
+    pid: ProcessId,
+}
+
+impl<'a> VacantEntry<'a> {
+    /// Returns the PID of the entry
+    pub fn pid(&self) -> ProcessId {
+        self.pid
+    }
+
+    /// Adds `process` under the entry's PID, which replaces its own
+    ///
+    /// A process without a kernel stack gets one allocated, as in
+    /// `ProcessTable::add_process`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProcessError::TableFull` if the table is full, or
+    /// `ProcessError::KernelStack` if the kernel stack cannot be allocated.
+    pub fn insert(self, mut process: Process) -> Result<&'a mut Process, ProcessError> {
+        process.pid = self.pid;
+        let pid = self.table.add_process(process)?;
+        Ok(self.table.get_mut(pid).expect("process was just added"))
+    }
+}
+
+/// Collects an exited child of `parent_pid`
+///
+/// The first child in PID order that has exited is removed from the table.
+///
+/// # Returns
+///
+/// The child's PID and exit code, or `None` if no child has exited yet
+pub fn wait(table: &mut ProcessTable, parent_pid: ProcessId) -> Option<(ProcessId, i32)> {
+    let (pid, exit_code) = table.iter().find_map(|process| match process.state {
+        ProcessState::Zombie(code) if process.parent == Some(parent_pid) => {
+            Some((process.pid, code))
+        }
+        _ => None,
+    })?;
+    table.remove(pid);
+    Some((pid, exit_code))
+}
+
+/// First code run by a process created by `Process::from_elf`
+///
+/// Runs in ring 0 on the process's kernel stack, with its address space
+/// loaded, and drops to `entry` in ring 3. The first process to get here,
+/// init, completes the boot with `BootPhase::UserSpaceReady`.
+extern "C" fn enter_user_process(entry: u64) -> ! {
+    crate::interrupts::without_interrupts(|| {
+        if boot_phase() < BootPhase::UserSpaceReady {
+            set_boot_phase(BootPhase::UserSpaceReady);
+        }
+    });
+    let context = ProcessContext::new_user(entry, USER_STACK_TOP);
+    // SAFETY: the scheduler loaded the address space, where the ELF loader
+    // mapped `entry` and the user stack, and pointed the TSS at this kernel
+    // stack, which holds nothing that is needed again
+    unsafe { enter_usermode(&context) }
+}
+
+/// Frees the address space whose P4 table is at `p4`
+///
+/// Private frames are freed, shared and copy-on-write frames drop their
+/// reference, and the user-space page tables go with the P4 table.
+///
+/// # Safety
+///
+/// `p4` must come from the ELF loader and no process may use it any more.
+unsafe fn release_address_space(p4: PhysAddr) {
+    let mut frames = HeapFrameAllocator::new();
+    let mut tables = HeapFrameAllocator::new();
+    // SAFETY: the P4 table is identity-accessible, as guaranteed by the
+    // caller
+    let address_space =
+        unsafe { PageTableManager::from_p4_table(&mut *(p4.as_u64() as *mut PageTable)) };
+    // SAFETY: nothing uses the address space any more, and its frames and
+    // tables came from a `HeapFrameAllocator`
+    unsafe {
+        address_space.destroy(
+            |_, frame, _| cow::release_frame(frame, &mut frames),
+            |table| tables.deallocate_frame(table),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_pid_allocation() {
+        let mut table = ProcessTable::new();
+        let first = table.allocate_pid().unwrap();
+        assert_eq!(first, ProcessId::IDLE);
+        table.add_process(Process::new(first, "first")).unwrap();
+
+        let second = table.allocate_pid().unwrap();
+        assert_eq!(second, ProcessId::new(2));
+        assert_eq!(
+            table.add_process(Process::new(first, "dup")),
+            Err(ProcessError::AlreadyExists)
+        );
+    }
+
+    #[test_case]
+    fn test_pid_allocation_wraps() {
+        let mut table = ProcessTable::new();
+        let first = table.allocate_pid().unwrap();
+        table.add_process(Process::new(first, "first")).unwrap();
+
+        table.next_pid = MAX_PROCESSES as u64;
+        let last = table.allocate_pid().unwrap();
+        assert_eq!(last, ProcessId::new(MAX_PROCESSES as u64));
+        assert_eq!(ProcessId::from_str(&alloc::format!("{}", last)), Ok(last));
+
+        // PID 1 is still in use, so the wrapped allocation skips it
+        assert_eq!(table.allocate_pid(), Ok(ProcessId::new(2)));
+    }
+
+    #[test_case]
+    fn test_add_process_allocates_fpu_state() {
+        let mut table = ProcessTable::new();
+        let pid = table.allocate_pid().unwrap();
+        let process = Process::new(pid, "fpu");
+        assert!(process.fpu.is_none());
+
+        table.add_process(process).unwrap();
+        assert!(table.get(pid).unwrap().fpu.is_some());
+    }
+
+    #[test_case]
+    fn test_pid_from_str() {
+        assert_eq!("255".parse(), Ok(ProcessId::new(255)));
+        assert_eq!(ProcessId::from_str("0xFF"), Ok(ProcessId::new(255)));
+        assert_eq!(ProcessId::from_str("0"), Ok(ProcessId::new(0)));
+        assert_eq!(
+            ProcessId::from_str(&alloc::format!("{}", MAX_PROCESSES)),
+            Ok(ProcessId::new(MAX_PROCESSES as u64))
+        );
+
+        for invalid in ["-1", "", "0x", "+1", "12a", "0xg"] {
+            assert_eq!(
+                ProcessId::from_str(invalid),
+                Err(PidParseError::InvalidDigit),
+                "{:?}",
+                invalid
+            );
+        }
+        assert_eq!(ProcessId::from_str("99999"), Err(PidParseError::OutOfRange));
+        assert_eq!(
+            ProcessId::from_str("99999999999999999999999"),
+            Err(PidParseError::OutOfRange)
+        );
+    }
+
+    #[test_case]
+    fn test_process_limit() {
+        let mut table = ProcessTable::new();
+        assert_eq!(table.limit(), DEFAULT_PROCESS_LIMIT);
+        table.set_limit(2).unwrap();
+        for _ in 0..2 {
+            let pid = table.allocate_pid().unwrap();
+            table.add_process(Process::new(pid, "proc")).unwrap();
+        }
+        assert_eq!(table.allocate_pid(), Err(ProcessError::TableFull));
+        assert_eq!(
+            table.add_process(Process::new(ProcessId::new(3), "third"),),
+            Err(ProcessError::TableFull)
+        );
+
+        assert_eq!(table.set_limit(1), Err(ProcessError::InvalidLimit(1)));
+        assert_eq!(
+            table.set_limit(MAX_PROCESSES + 1),
+            Err(ProcessError::InvalidLimit(MAX_PROCESSES + 1))
+        );
+        assert_eq!(
+            ProcessTable::with_limit(MAX_PROCESSES).limit(),
+            MAX_PROCESSES
+        );
+    }
+
+    #[test_case]
+    fn test_from_elf() {
+        use crate::elf::tests::{
+            TEST_ENTRY,
+            minimal_elf,
+        };
+
+        let process = Process::from_elf(ProcessId::new(2), "init", &minimal_elf()).unwrap();
+        // The process enters user mode from its kernel stack
+        assert_eq!(process.context.rip, enter_user_process as *const () as u64);
+        assert_eq!(process.context.rdi, TEST_ENTRY);
+        assert_eq!(process.state(), ProcessState::Ready);
+        assert!(process.page_table().is_some());
+        let stack_top = process.kernel_stack_top().unwrap().as_u64();
+        assert!(process.context.rsp < stack_top);
+
+        assert_eq!(
+            Process::from_elf(ProcessId::new(3), "bad", &[0; 64]).err(),
+            Some(ElfError::BadMagic)
+        );
+    }
+
+    #[test_case]
+    fn test_state_transitions() {
+        let mut table = ProcessTable::new();
+        let pid = table.allocate_pid().unwrap();
+        table.add_process(Process::new(pid, "p")).unwrap();
+
+        assert_eq!(table.get(pid).unwrap().state(), ProcessState::Ready);
+        table.mark_running(pid).unwrap();
+        assert_eq!(table.get(pid).unwrap().state(), ProcessState::Running);
+        table.mark_blocked(pid).unwrap();
+        assert_eq!(table.get(pid).unwrap().state(), ProcessState::Blocked);
+
+        assert_eq!(
+            table.mark_ready(ProcessId::new(99)),
+            Err(ProcessError::NotFound)
+        );
+    }
+
+    #[test_case]
+    fn test_entry() {
+        let mut table = ProcessTable::new();
+        let pid = ProcessId::new(5);
+
+        match table.entry(pid) {
+            Entry::Occupied(_) => panic!("empty table has no occupied entry"),
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.pid(), pid);
+                let process = entry
+                    .insert(Process::new(ProcessId::new(0), "new"))
+                    .unwrap();
+                assert_eq!(process.pid(), pid);
+                assert!(process.has_kernel_stack());
+            }
+        }
+        assert_eq!(table.len(), 1);
+
+        match table.entry(pid) {
+            Entry::Occupied(process) => assert_eq!(process.name(), "new"),
+            Entry::Vacant(_) => panic!("inserted process is missing"),
+        }
+    }
+
+    #[test_case]
+    fn test_get_or_create() {
+        let mut table = ProcessTable::new();
+        let pid = ProcessId::new(2);
+
+        let created = table
+            .get_or_create(pid, |pid| Process::new(pid, "first"))
+            .unwrap();
+        assert_eq!(created.name(), "first");
+        created.mark_scheduled(7);
+
+        // The existing process is returned and the factory is not called
+        let existing = table
+            .get_or_create(pid, |_| panic!("factory called for an existing process"))
+            .unwrap();
+        assert_eq!(existing.name(), "first");
+        assert_eq!(existing.last_scheduled_tick(), 7);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test_case]
+    fn test_fork_from() {
+        use crate::process::capability::{
+            Capability,
+            CapabilityRights,
+            CapabilityType,
+        };
+
+        let mut parent = Process::new(ProcessId::new(2), "parent");
+        parent.page_table = Some(PhysAddr::new(0x1000));
+        parent.capabilities_mut().insert(Capability::new(
+            CapabilityType::Endpoint,
+            3,
+            CapabilityRights::WRITE,
+        ));
+
+        let child = Process::fork_from(ProcessId::new(3), &parent);
+        assert_eq!(child.parent(), Some(parent.pid()));
+        assert_eq!(child.page_table(), parent.page_table());
+        assert_eq!(child.capabilities().len(), 1);
+        assert!(
+            child
+                .capabilities()
+                .require(CapabilityType::Endpoint, 3, CapabilityRights::WRITE)
+                .is_ok()
+        );
+        assert_eq!(child.exit_code(), None);
+    }
+
+    #[test_case]
+    fn test_zombie_stays_until_wait() {
+        let mut table = ProcessTable::new();
+        let parent_pid = table.allocate_pid().unwrap();
+        let parent = Process::new(parent_pid, "parent");
+        let child_pid = table.allocate_pid().unwrap();
+        let child = Process::fork_from(child_pid, &parent);
+        table.add_process(parent).unwrap();
+        table.add_process(child).unwrap();
+
+        assert_eq!(wait(&mut table, parent_pid), None);
+
+        table.mark_terminated(child_pid, 42).unwrap();
+        let child = table.get(child_pid).unwrap();
+        assert_eq!(child.state(), ProcessState::Zombie(42));
+        assert_eq!(child.exit_code(), Some(42));
+        assert!(!table.is_orphan_zombie(child_pid));
+
+        // Only the parent can collect it
+        assert_eq!(wait(&mut table, child_pid), None);
+        assert!(table.contains(child_pid));
+        assert_eq!(wait(&mut table, parent_pid), Some((child_pid, 42)));
+        assert!(!table.contains(child_pid));
+        assert_eq!(wait(&mut table, parent_pid), None);
+    }
+
+    #[test_case]
+    fn test_orphan_zombie() {
+        let mut table = ProcessTable::new();
+        let parent_pid = table.allocate_pid().unwrap();
+        let parent = Process::new(parent_pid, "parent");
+        let child_pid = table.allocate_pid().unwrap();
+        let child = Process::fork_from(child_pid, &parent);
+        table.add_process(child).unwrap();
+
+        // The parent never joined the table
+        table.mark_terminated(child_pid, 0).unwrap();
+        assert!(table.is_orphan_zombie(child_pid));
+    }
+
+    #[test_case]
+    fn test_wait_frees_address_space() {
+        use crate::{
+            elf::tests::minimal_elf,
+            memory::frames_in_use,
+        };
+
+        let mut table = ProcessTable::new();
+        let parent_pid = table.allocate_pid().unwrap();
+        table
+            .add_process(Process::new(parent_pid, "parent"))
+            .unwrap();
+        // Map the kernel stack ranges of the two children first, so page
+        // tables the kernel half keeps do not count against them
+        drop([
+            KernelStack::allocate().unwrap(),
+            KernelStack::allocate().unwrap(),
+        ]);
+        let before = frames_in_use();
+
+        let child_pid = table.allocate_pid().unwrap();
+        let mut child = Process::from_elf(child_pid, "child", &minimal_elf()).unwrap();
+        child.parent = Some(parent_pid);
+        table.add_process(child).unwrap();
+        let grandchild_pid = table.allocate_pid().unwrap();
+        let grandchild = Process::fork_from(grandchild_pid, table.get(child_pid).unwrap());
+        table.add_process(grandchild).unwrap();
+        assert!(frames_in_use() > before);
+
+        // The grandchild still uses the address space
+        table.mark_terminated(child_pid, 0).unwrap();
+        assert_eq!(wait(&mut table, parent_pid), Some((child_pid, 0)));
+        assert!(table.get(grandchild_pid).unwrap().page_table().is_some());
+
+        table.mark_terminated(grandchild_pid, 0).unwrap();
+        assert!(table.remove(grandchild_pid).is_some());
+        assert_eq!(frames_in_use(), before);
+    }
+
+    #[test_case]
+    fn test_cpu_time_accounting() {
+        let mut process = Process::new(ProcessId::new(2), "p");
+        process.mark_scheduled(10);
+        process.charge_cpu_time(15);
+        process.mark_scheduled(20);
+        process.charge_cpu_time(20 + u64::from(TIMER_FREQUENCY));
+
+        assert_eq!(process.cpu_time_ticks(), 5 + u64::from(TIMER_FREQUENCY));
+        assert_eq!(
+            process.cpu_time_ms(),
+            1000 + 5000 / u64::from(TIMER_FREQUENCY)
+        );
+    }
+
+    #[test_case]
+    fn test_processes_sorted_by_cpu_time() {
+        let mut table = ProcessTable::new();
+        for ticks in [3, 7, 3, 0] {
+            let pid = table.allocate_pid().unwrap();
+            let mut process = Process::new(pid, "p");
+            process.charge_cpu_time(ticks);
+            table.add_process(process).unwrap();
+        }
+
+        let order: Vec<u64> = table
+            .processes_sorted_by_cpu_time()
+            .iter()
+            .map(|p| p.pid().as_u64())
+            .collect();
+        assert_eq!(order, [2, 1, 3, 4]);
+    }
+
+    #[test_case]
+    fn test_processes_by_priority() {
+        let mut table = ProcessTable::new();
+        for priority in [2, 0, 1, 0] {
+            let pid = table.allocate_pid().unwrap();
+            let mut process = Process::new(pid, "p");
+            process.set_priority(priority);
+            table.add_process(process).unwrap();
+        }
+        table.mark_blocked(ProcessId::new(4)).unwrap();
+
+        let order = |processes: &mut dyn Iterator<Item = &Process>| -> Vec<(u64, u8)> {
+            processes
+                .map(|p| (p.pid().as_u64(), p.priority()))
+                .collect()
+        };
+        assert_eq!(order(&mut table.processes_by_priority()), [
+            (2, 0),
+            (4, 0),
+            (3, 1),
+            (1, 2)
+        ]);
+        assert_eq!(order(&mut table.ready_by_priority()), [
+            (2, 0),
+            (3, 1),
+            (1, 2)
+        ]);
+
+        let child = Process::fork_from(ProcessId::new(5), table.get(ProcessId::new(3)).unwrap());
+        assert_eq!(child.priority(), 1);
+    }
+}
@@ -0,0 +1,846 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Process scheduler
+//!
+//! Ready processes wait in a multi-level feedback queue (MLFQ). There are
+//! `MLFQ_LEVELS` FIFO queues; the highest-priority non-empty one is served
+//! first. A process starts at level 0 with a timeslice of
+//! `BASE_TIMESLICE` ticks, and each time it uses up its whole timeslice it
+//! drops one level, where the timeslice doubles. CPU-bound processes thus
+//! sink while interactive ones, which block before their timeslice ends,
+//! stay on top: a process that unblocks re-enters at level 0. Every
+//! `SCHEDULER_BOOST_INTERVAL` ticks all processes are moved back to level
+//! 0, so the lower levels cannot starve.
+//!
+//! Each process's `priority` follows its MLFQ level, so the table can be
+//! listed in scheduling order without the run queue.
+//!
+//! The idle task is never queued: it has implicit lowest priority and is
+//! only selected when every queue is empty, and it is preempted as soon as
+//! another process becomes ready. Ticks spent in the idle task are counted
+//! to report CPU utilization.
+
+use alloc::collections::{
+    BTreeMap,
+    VecDeque,
+};
+use core::{
+    ptr,
+    sync::atomic::{
+        AtomicPtr,
+        Ordering,
+    },
+};
+
+use spin::Mutex;
+
+use super::{
+    context::{
+        FpuContext,
+        ProcessContext,
+        switch_context,
+        switch_fpu,
+    },
+    process::{
+        Process,
+        ProcessError,
+        ProcessId,
+        ProcessState,
+        ProcessTable,
+    },
+};
+use crate::memory::{
+    PageTableManager,
+    PhysAddr,
+    VirtAddr,
+};
+
+/// Global scheduler instance
+pub static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+
+/// FPU state of the running process, null if it has none
+///
+/// Kept outside `SCHEDULER` so the #NM handler can load it without taking
+/// a lock. It points into the running process's `Process::fpu`, which is
+/// not freed while the process runs.
+static CURRENT_FPU: AtomicPtr<FpuContext> = AtomicPtr::new(ptr::null_mut());
+
+/// Number of MLFQ priority levels; level 0 is the highest
+pub const MLFQ_LEVELS: usize = 3;
+
+/// Timeslice at level 0, in ticks; it doubles with every level below
+pub const BASE_TIMESLICE: u32 = 1;
+
+/// Ticks between moves of every process back to level 0
+pub const SCHEDULER_BOOST_INTERVAL: u64 = 500;
+
+/// Returns the timeslice of MLFQ level `level`, in ticks
+pub const fn timeslice(level: usize) -> u32 {
+    BASE_TIMESLICE << level
+}
+
+/// Priority level of a process and what is left of its timeslice
+#[derive(Debug, Clone, Copy)]
+struct MlfqSlot {
+    level: usize,
+    remaining: u32,
+    /// Whether the process waits in the queue of `level`
+    queued: bool,
+}
+
+impl MlfqSlot {
+    const TOP: Self = Self {
+        level: 0,
+        remaining: timeslice(0),
+        queued: false,
+    };
+}
+
+/// Multi-level feedback run queue
+///
+/// Holds the ready processes of each priority level and remembers the
+/// level and remaining timeslice of every process it has seen, including
+/// the running one, which is not queued. Each slot also records whether
+/// its process is queued, so membership is checked without scanning the
+/// queues.
+pub struct MlfqScheduler {
+    queues: [VecDeque<ProcessId>; MLFQ_LEVELS],
+    slots: BTreeMap<ProcessId, MlfqSlot>,
+}
+
+impl MlfqScheduler {
+    /// Creates an empty run queue
+    pub const fn new() -> Self {
+        Self {
+            queues: [const { VecDeque::new() }; MLFQ_LEVELS],
+            slots: BTreeMap::new(),
+        }
+    }
+
+    /// Queues `pid` at the back of its level, unless it is already queued
+    ///
+    /// A process seen for the first time starts at level 0.
+    pub fn enqueue(&mut self, pid: ProcessId) {
+        let slot = self.slots.entry(pid).or_insert(MlfqSlot::TOP);
+        if slot.queued {
+            return;
+        }
+        slot.queued = true;
+        self.queues[slot.level].push_back(pid);
+    }
+
+    /// Moves `pid` to level 0 with a full timeslice and queues it there
+    ///
+    /// Used when a process becomes ready after blocking.
+    pub fn enqueue_boosted(&mut self, pid: ProcessId) {
+        self.remove(pid);
+        self.slots.insert(pid, MlfqSlot {
+            queued: true,
+            ..MlfqSlot::TOP
+        });
+        self.queues[0].push_back(pid);
+    }
+
+    /// Removes `pid` from its queue, keeping its level
+    pub fn remove(&mut self, pid: ProcessId) {
+        if let Some(slot) = self.slots.get_mut(&pid).filter(|slot| slot.queued) {
+            slot.queued = false;
+            self.queues[slot.level].retain(|&queued| queued != pid);
+        }
+    }
+
+    /// Removes `pid` from its queue and forgets its level
+    pub fn forget(&mut self, pid: ProcessId) {
+        self.remove(pid);
+        self.slots.remove(&pid);
+    }
+
+    /// Returns `true` if `pid` is queued
+    pub fn contains(&self, pid: ProcessId) -> bool {
+        self.slots.get(&pid).is_some_and(|slot| slot.queued)
+    }
+
+    /// Returns the priority level of `pid`, if it has one
+    pub fn level(&self, pid: ProcessId) -> Option<usize> {
+        self.slots.get(&pid).map(|slot| slot.level)
+    }
+
+    /// Pops the first PID of the highest-priority non-empty queue
+    pub fn pop_highest(&mut self) -> Option<ProcessId> {
+        let pid = self.queues.iter_mut().find_map(VecDeque::pop_front)?;
+        if let Some(slot) = self.slots.get_mut(&pid) {
+            slot.queued = false;
+        }
+        Some(pid)
+    }
+
+    /// Returns `true` if a process is queued at a level above `level`
+    pub fn has_ready_above(&self, level: usize) -> bool {
+        self.queues[..level].iter().any(|queue| !queue.is_empty())
+    }
+
+    /// Charges one tick to the running process `pid`
+    ///
+    /// # Returns
+    ///
+    /// `true` if its timeslice is used up. It is then demoted one level,
+    /// unless already at the lowest, with that level's full timeslice.
+    pub fn charge_tick(&mut self, pid: ProcessId) -> bool {
+        let slot = self.slots.entry(pid).or_insert(MlfqSlot::TOP);
+        slot.remaining = slot.remaining.saturating_sub(1);
+        if slot.remaining > 0 {
+            return false;
+        }
+        slot.level = (slot.level + 1).min(MLFQ_LEVELS - 1);
+        slot.remaining = timeslice(slot.level);
+        true
+    }
+
+    /// Moves every process back to level 0 with a full timeslice
+    ///
+    /// Queued processes keep their relative order, higher levels first.
+    pub fn boost(&mut self) {
+        for level in 1..MLFQ_LEVELS {
+            let demoted = core::mem::take(&mut self.queues[level]);
+            self.queues[0].extend(demoted);
+        }
+        for slot in self.slots.values_mut() {
+            *slot = MlfqSlot {
+                queued: slot.queued,
+                ..MlfqSlot::TOP
+            };
+        }
+    }
+
+    /// Returns the queued PIDs in dispatch order
+    pub fn iter(&self) -> impl Iterator<Item = ProcessId> + '_ {
+        self.queues.iter().flatten().copied()
+    }
+}
+
+impl Default for MlfqScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Process scheduler
+pub struct Scheduler {
+    table: ProcessTable,
+    current: Option<ProcessId>,
+    run_queue: MlfqScheduler,
+    idle_pid: Option<ProcessId>,
+    total_ticks: u64,
+    idle_ticks: u64,
+}
+
+/// A pending switch between two process contexts
+///
+/// Returned by the scheduler so the switch can be performed after the
+/// scheduler lock is released; the switched-out process would otherwise
+/// keep holding it.
+#[must_use]
+pub struct ContextSwitch {
+    from: *mut ProcessContext,
+    to: *const ProcessContext,
+    /// FPU state of the outgoing process, null if it has none
+    from_fpu: *mut FpuContext,
+    /// FPU state of the incoming process, null if it has none
+    to_fpu: *const FpuContext,
+    /// Top of the incoming process's kernel stack, if it owns one
+    kernel_stack: Option<u64>,
+    /// P4 table of the incoming process's address space, the kernel's own
+    /// for kernel threads
+    page_table: PhysAddr,
+}
+
+impl ContextSwitch {
+    /// Saves the current CPU state and resumes the next process
+    ///
+    /// The FPU state is switched first, leaving CR0.TS set, and recorded
+    /// for `current_fpu`. Entries from ring 3 are pointed at the incoming
+    /// process's kernel stack, and its address space is loaded into CR3.
+    ///
+    /// # Safety
+    ///
+    /// Must be called with interrupts disabled and before the process table
+    /// is modified, since the contexts are stored in the table.
+    pub unsafe fn perform(self) {
+        // SAFETY: all pointers and the page table were taken from the table
+        // by the scheduler and the caller guarantees it has not changed
+        // since, so the incoming address space is still alive.
+        unsafe {
+            CURRENT_FPU.store(self.to_fpu.cast_mut(), Ordering::Release);
+            switch_fpu(self.from_fpu, self.to_fpu);
+            if let Some(top) = self.kernel_stack {
+                crate::interrupts::set_kernel_stack(top);
+            }
+            PageTableManager::activate(self.page_table);
+            switch_context(self.from, self.to);
+        }
+    }
+}
+
+impl Scheduler {
+    /// Creates a scheduler with an empty process table
+    pub const fn new() -> Self {
+        Self {
+            table: ProcessTable::new(),
+            current: None,
+            run_queue: MlfqScheduler::new(),
+            idle_pid: None,
+            total_ticks: 0,
+            idle_ticks: 0,
+        }
+    }
+
+    /// Returns the process table
+    pub fn table(&self) -> &ProcessTable {
+        &self.table
+    }
+
+    /// Returns the process table mutably
+    ///
+    /// State changes made through the table bypass the run queue; use
+    /// `make_ready`, `block` and `terminate` instead.
+    pub fn table_mut(&mut self) -> &mut ProcessTable {
+        &mut self.table
+    }
+
+    /// Adds a process and queues it at level 0 if it is ready
+    ///
+    /// Its kernel stack is allocated if it has none yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ProcessTable::add_process` error if it cannot be added.
+    pub fn add_process(&mut self, process: Process) -> Result<ProcessId, ProcessError> {
+        let ready = process.state() == ProcessState::Ready;
+        let pid = self.table.add_process(process)?;
+        if ready && !self.is_idle(pid) {
+            self.run_queue.enqueue_boosted(pid);
+            self.sync_priority(pid);
+        }
+        Ok(pid)
+    }
+
+    /// Marks a process ready and queues it at level 0
+    pub fn make_ready(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
+        self.table.mark_ready(pid)?;
+        if !self.is_idle(pid) && Some(pid) != self.current && !self.run_queue.contains(pid) {
+            self.run_queue.enqueue_boosted(pid);
+            self.sync_priority(pid);
+        }
+        Ok(())
+    }
+
+    /// Marks a process blocked and removes it from the run queue
+    pub fn block(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
+        self.table.mark_blocked(pid)?;
+        self.run_queue.remove(pid);
+        Ok(())
+    }
+
+    /// Marks a process exited with `exit_code` and removes it from the run
+    /// queue
+    pub fn terminate(&mut self, pid: ProcessId, exit_code: i32) -> Result<(), ProcessError> {
+        self.table.mark_terminated(pid, exit_code)?;
+        self.run_queue.forget(pid);
+        Ok(())
+    }
+
+    /// Queues every ready process missing from the run queue at level 0
+    ///
+    /// Picks up processes made ready through `table_mut`, such as IPC
+    /// receivers and senders woken by `ipc::send` and `ipc::receive`. Only
+    /// the priorities of the processes queued here are updated.
+    pub fn requeue_ready(&mut self) {
+        for process in self.table.iter_mut() {
+            let pid = process.pid();
+            if process.state() == ProcessState::Ready
+                && self.idle_pid != Some(pid)
+                && self.current != Some(pid)
+                && !self.run_queue.contains(pid)
+            {
+                self.run_queue.enqueue_boosted(pid);
+                process.set_priority(0);
+            }
+        }
+    }
+
+    /// Returns the PID of the running process
+    pub fn current(&self) -> Option<ProcessId> {
+        self.current
+    }
+
+    /// Records `pid` as the running process without switching to it
+    ///
+    /// Used for the thread that is already executing when scheduling
+    /// starts.
+    pub fn set_current(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
+        self.table.mark_running(pid)?;
+        self.run_queue.remove(pid);
+        if let Some(process) = self.table.get_mut(pid) {
+            process.mark_scheduled(self.total_ticks);
+            let fpu = process
+                .fpu
+                .as_deref_mut()
+                .map_or(ptr::null_mut(), |fpu| fpu as *mut FpuContext);
+            CURRENT_FPU.store(fpu, Ordering::Release);
+        }
+        self.current = Some(pid);
+        Ok(())
+    }
+
+    /// Returns the PIDs waiting in the run queue, in dispatch order
+    pub fn run_queue(&self) -> impl Iterator<Item = ProcessId> + '_ {
+        self.run_queue.iter()
+    }
+
+    /// Returns the MLFQ priority level of `pid`, if it has one
+    pub fn priority_level(&self, pid: ProcessId) -> Option<usize> {
+        self.run_queue.level(pid)
+    }
+
+    /// Returns the PID of the idle task, if it has been spawned
+    pub fn idle_pid(&self) -> Option<ProcessId> {
+        self.idle_pid
+    }
+
+    /// Registers `pid` as the idle task
+    pub fn set_idle(&mut self, pid: ProcessId) {
+        self.run_queue.forget(pid);
+        self.idle_pid = Some(pid);
+    }
+
+    /// Returns `true` if `pid` is the idle task
+    pub fn is_idle(&self, pid: ProcessId) -> bool {
+        self.idle_pid == Some(pid)
+    }
+
+    /// Total number of ticks seen by the scheduler
+    pub fn total_ticks(&self) -> u64 {
+        self.total_ticks
+    }
+
+    /// Number of ticks spent in the idle task
+    pub fn idle_ticks(&self) -> u64 {
+        self.idle_ticks
+    }
+
+    /// CPU utilization since boot as a percentage (0-100)
+    pub fn cpu_utilization(&self) -> u32 {
+        if self.total_ticks == 0 {
+            return 0;
+        }
+        let busy = self.total_ticks - self.idle_ticks;
+        (busy * 100 / self.total_ticks) as u32
+    }
+
+    /// Handles a timer tick
+    ///
+    /// Accounts the tick to the current process. It keeps running until its
+    /// timeslice is used up or a process of a higher priority level is
+    /// ready; it is then preempted and the next process dispatched, see
+    /// `schedule`. Every `SCHEDULER_BOOST_INTERVAL` ticks all processes are
+    /// moved back to level 0.
+    ///
+    /// # Returns
+    ///
+    /// The context switch to perform, if another process was dispatched
+    pub fn tick(&mut self) -> Option<ContextSwitch> {
+        self.total_ticks += 1;
+        if self.current.is_some_and(|pid| self.is_idle(pid)) {
+            self.idle_ticks += 1;
+        }
+
+        let keep_running = self
+            .current
+            .filter(|&pid| !self.is_idle(pid) && self.state(pid) == Some(ProcessState::Running))
+            .is_some_and(|pid| {
+                let exhausted = self.run_queue.charge_tick(pid);
+                let level = self.run_queue.level(pid).unwrap_or(0);
+                !exhausted && !self.run_queue.has_ready_above(level)
+            });
+        // Boosting after charging the tick gives the current process a
+        // full level 0 timeslice too
+        if self.total_ticks.is_multiple_of(SCHEDULER_BOOST_INTERVAL) {
+            self.run_queue.boost();
+            self.sync_priorities();
+        } else if let Some(pid) = self.current {
+            self.sync_priority(pid);
+        }
+        if keep_running {
+            return None;
+        }
+
+        self.schedule()
+    }
+
+    /// Gives up the CPU on behalf of the running process
+    ///
+    /// Counts a voluntary switch for it, then picks the next process as
+    /// `schedule` does.
+    pub fn yield_current(&mut self) -> Option<ContextSwitch> {
+        if let Some(process) = self.current.and_then(|pid| self.table.get_mut(pid)) {
+            process.record_voluntary_switch();
+        }
+        self.schedule()
+    }
+
+    /// Picks the process to run next
+    ///
+    /// A running non-idle process is moved to the back of the queue of its
+    /// priority level and the first process of the highest-priority
+    /// non-empty queue is dispatched; the idle task runs only if every
+    /// queue is empty.
+    ///
+    /// # Returns
+    ///
+    /// The context switch to perform, if another process was dispatched.
+    /// No switch is returned for the first dispatch, when there is no
+    /// current process to save.
+    ///
+    /// The time since the outgoing process was dispatched is added to its
+    /// CPU time.
+    pub fn schedule(&mut self) -> Option<ContextSwitch> {
+        let prev = self.current;
+        if let Some(pid) = prev {
+            if self.state(pid) == Some(ProcessState::Running) {
+                let _ = self.table.mark_ready(pid);
+                if !self.is_idle(pid) {
+                    self.run_queue.enqueue(pid);
+                }
+            }
+        }
+
+        let next = self.pop_ready().or(self.idle_pid)?;
+        let _ = self.table.mark_running(next);
+        self.current = Some(next);
+
+        let now = self.total_ticks;
+        if prev != Some(next) {
+            if let Some(incoming) = self.table.get_mut(next) {
+                incoming.mark_scheduled(now);
+            }
+        }
+
+        let prev = prev.filter(|&pid| pid != next)?;
+        let outgoing = self.table.get_mut(prev)?;
+        outgoing.charge_cpu_time(now);
+        let from = &mut outgoing.context as *mut ProcessContext;
+        let from_fpu = outgoing
+            .fpu
+            .as_deref_mut()
+            .map_or(ptr::null_mut(), |fpu| fpu as *mut FpuContext);
+        let incoming = self.table.get(next)?;
+        let to = &incoming.context as *const ProcessContext;
+        let to_fpu = incoming
+            .fpu
+            .as_deref()
+            .map_or(ptr::null(), |fpu| fpu as *const FpuContext);
+        Some(ContextSwitch {
+            from,
+            to,
+            from_fpu,
+            to_fpu,
+            kernel_stack: incoming.kernel_stack_top().map(VirtAddr::as_u64),
+            page_table: incoming
+                .page_table()
+                .unwrap_or_else(PageTableManager::kernel_p4_address),
+        })
+    }
+
+    /// Pops the first ready PID, dropping stale queue entries
+    fn pop_ready(&mut self) -> Option<ProcessId> {
+        while let Some(pid) = self.run_queue.pop_highest() {
+            if self.state(pid) == Some(ProcessState::Ready) {
+                return Some(pid);
+            }
+        }
+        None
+    }
+
+    /// Copies the MLFQ level of `pid` into its process's priority
+    fn sync_priority(&mut self, pid: ProcessId) {
+        if let (Some(level), Some(process)) = (self.run_queue.level(pid), self.table.get_mut(pid)) {
+            process.set_priority(level as u8);
+        }
+    }
+
+    /// Copies the MLFQ level of every process into its priority
+    fn sync_priorities(&mut self) {
+        for process in self.table.iter_mut() {
+            if let Some(level) = self.run_queue.level(process.pid()) {
+                process.set_priority(level as u8);
+            }
+        }
+    }
+
+    fn state(&self, pid: ProcessId) -> Option<ProcessState> {
+        self.table.get(pid).map(|p| p.state())
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Timer interrupt hook
+///
+/// Must be called with interrupts disabled and after the interrupt has been
+/// acknowledged, since it may switch to another process. Skips the tick if
+/// the scheduler lock is held by the interrupted code, which would
+/// otherwise deadlock.
+pub fn timer_tick() {
+    let switch = SCHEDULER
+        .try_lock()
+        .and_then(|mut scheduler| scheduler.tick());
+    if let Some(switch) = switch {
+        // SAFETY: we are in the timer interrupt handler with interrupts
+        // disabled, so the table cannot change before the switch.
+        unsafe { switch.perform() };
+    }
+}
+
+/// Loads the FPU state of the running process
+///
+/// Called by the #NM handler after it cleared CR0.TS. It takes no lock and
+/// does not allocate, so it is safe whatever the interrupted code holds.
+/// Nothing happens if the running process has no FPU state.
+pub fn restore_current_fpu() {
+    let fpu = CURRENT_FPU.load(Ordering::Acquire);
+    // SAFETY: `CURRENT_FPU` points into the running process, which is not
+    // freed while it runs
+    if let Some(fpu) = unsafe { fpu.as_ref() } {
+        fpu.restore();
+    }
+}
+
+/// CPU utilization since boot as a percentage (0-100)
+pub fn cpu_utilization() -> u32 {
+    crate::interrupts::without_interrupts(|| SCHEDULER.lock().cpu_utilization())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler_with(names: &[&'static str]) -> Scheduler {
+        let mut scheduler = Scheduler::new();
+        let idle = scheduler.table_mut().allocate_pid().unwrap();
+        scheduler.add_process(Process::new(idle, "idle")).unwrap();
+        scheduler.set_idle(idle);
+
+        for name in names {
+            let pid = scheduler.table_mut().allocate_pid().unwrap();
+            scheduler.add_process(Process::new(pid, name)).unwrap();
+        }
+        scheduler
+    }
+
+    /// Ticks once and returns the dispatched process
+    fn dispatch(scheduler: &mut Scheduler) -> Option<ProcessId> {
+        // The test processes have no real contexts to switch to
+        let _ = scheduler.tick();
+        scheduler.current()
+    }
+
+    #[test_case]
+    fn test_idle_only_when_nothing_ready() {
+        let mut scheduler = scheduler_with(&["a"]);
+        let a = ProcessId::new(2);
+
+        assert_eq!(dispatch(&mut scheduler), Some(a));
+        assert_eq!(dispatch(&mut scheduler), Some(a));
+
+        scheduler.block(a).unwrap();
+        assert_eq!(dispatch(&mut scheduler), Some(ProcessId::IDLE));
+
+        // The idle task is preempted as soon as `a` is ready again
+        scheduler.make_ready(a).unwrap();
+        assert_eq!(dispatch(&mut scheduler), Some(a));
+    }
+
+    #[test_case]
+    fn test_round_robin_skips_idle() {
+        let mut scheduler = scheduler_with(&["a", "b"]);
+
+        assert_eq!(dispatch(&mut scheduler), Some(ProcessId::new(2)));
+        assert_eq!(dispatch(&mut scheduler), Some(ProcessId::new(3)));
+        assert_eq!(dispatch(&mut scheduler), Some(ProcessId::new(2)));
+    }
+
+    #[test_case]
+    fn test_n_ticks_dispatch_n_processes() {
+        const N: u64 = 5;
+        let mut scheduler = scheduler_with(&["p2", "p3", "p4", "p5", "p6"]);
+
+        // Each round runs one level lower, with a longer timeslice
+        for round in 0..2 {
+            for i in 0..N {
+                let pid = ProcessId::new(2 + i);
+                for _ in 0..timeslice(round) {
+                    assert_eq!(dispatch(&mut scheduler), Some(pid), "round {}", round);
+                    assert_eq!(
+                        scheduler.table().get(pid).unwrap().state(),
+                        ProcessState::Running
+                    );
+                    // Everyone else is ready and queued behind it
+                    assert_eq!(scheduler.run_queue().count(), N as usize - 1);
+                }
+            }
+        }
+    }
+
+    #[test_case]
+    fn test_demotion_after_timeslice() {
+        let mut scheduler = scheduler_with(&["cpu"]);
+        let cpu = ProcessId::new(2);
+
+        assert_eq!(dispatch(&mut scheduler), Some(cpu));
+        for level in 0..MLFQ_LEVELS {
+            assert_eq!(scheduler.priority_level(cpu), Some(level));
+            for _ in 0..timeslice(level) {
+                assert_eq!(dispatch(&mut scheduler), Some(cpu));
+            }
+        }
+        // The lowest level is never left by demotion
+        assert_eq!(scheduler.priority_level(cpu), Some(MLFQ_LEVELS - 1));
+        let process = scheduler.table().get(cpu).unwrap();
+        assert_eq!(usize::from(process.priority()), MLFQ_LEVELS - 1);
+    }
+
+    #[test_case]
+    fn test_unblocked_process_is_boosted() {
+        let mut scheduler = scheduler_with(&["io", "cpu"]);
+        let io = ProcessId::new(2);
+        let cpu = ProcessId::new(3);
+
+        // Both use up their level 0 timeslice and drop to level 1
+        assert_eq!(dispatch(&mut scheduler), Some(io));
+        assert_eq!(dispatch(&mut scheduler), Some(cpu));
+        assert_eq!(dispatch(&mut scheduler), Some(io));
+        assert_eq!(scheduler.priority_level(io), Some(1));
+
+        scheduler.block(io).unwrap();
+        assert_eq!(scheduler.schedule().map(|_| ()), Some(()));
+        assert_eq!(scheduler.current(), Some(cpu));
+        assert_eq!(scheduler.priority_level(cpu), Some(1));
+
+        // Waking up puts `io` back on top, preempting `cpu` mid-timeslice
+        scheduler.make_ready(io).unwrap();
+        assert_eq!(scheduler.priority_level(io), Some(0));
+        assert_eq!(dispatch(&mut scheduler), Some(io));
+    }
+
+    #[test_case]
+    fn test_run_queue_tracks_membership() {
+        let mut queue = MlfqScheduler::new();
+        let (a, b) = (ProcessId::new(2), ProcessId::new(3));
+
+        queue.enqueue(a);
+        queue.enqueue(a);
+        queue.enqueue_boosted(b);
+        assert!(queue.contains(a) && queue.contains(b));
+        assert_eq!(queue.iter().count(), 2);
+
+        assert_eq!(queue.pop_highest(), Some(a));
+        assert!(!queue.contains(a));
+        queue.boost();
+        assert!(queue.contains(b));
+        queue.remove(b);
+        assert!(!queue.contains(b));
+        assert_eq!(queue.pop_highest(), None);
+
+        // A popped process can be queued again
+        queue.enqueue(a);
+        assert_eq!(queue.pop_highest(), Some(a));
+    }
+
+    #[test_case]
+    fn test_periodic_boost() {
+        let mut scheduler = scheduler_with(&["cpu"]);
+        let cpu = ProcessId::new(2);
+
+        while scheduler.total_ticks() < SCHEDULER_BOOST_INTERVAL - 1 {
+            assert_eq!(dispatch(&mut scheduler), Some(cpu));
+        }
+        assert_eq!(scheduler.priority_level(cpu), Some(MLFQ_LEVELS - 1));
+
+        assert_eq!(dispatch(&mut scheduler), Some(cpu));
+        assert_eq!(scheduler.priority_level(cpu), Some(0));
+        assert_eq!(scheduler.table().get(cpu).unwrap().priority(), 0);
+    }
+
+    #[test_case]
+    fn test_tick_returns_switch_between_contexts() {
+        let mut scheduler = scheduler_with(&["a", "b"]);
+        assert!(
+            scheduler.tick().is_none(),
+            "nothing to save on first dispatch"
+        );
+
+        let switch = scheduler.tick().unwrap();
+        let a = &scheduler.table().get(ProcessId::new(2)).unwrap().context;
+        let b = &scheduler.table().get(ProcessId::new(3)).unwrap().context;
+        assert_eq!(switch.from as *const ProcessContext, a as *const _);
+        assert_eq!(switch.to, b as *const _);
+    }
+
+    #[test_case]
+    fn test_cpu_time_accounting() {
+        let mut scheduler = scheduler_with(&["a", "b"]);
+        let a = ProcessId::new(2);
+        let b = ProcessId::new(3);
+        let cpu_time =
+            |scheduler: &Scheduler, pid| scheduler.table().get(pid).unwrap().cpu_time_ticks();
+
+        // a runs for tick 1, b for tick 2, then a for its two level 1
+        // ticks before b takes over again
+        assert_eq!(dispatch(&mut scheduler), Some(a));
+        assert_eq!(dispatch(&mut scheduler), Some(b));
+        assert_eq!(cpu_time(&scheduler, a), 1);
+        assert_eq!(dispatch(&mut scheduler), Some(a));
+        assert_eq!(cpu_time(&scheduler, b), 1);
+        assert_eq!(dispatch(&mut scheduler), Some(a));
+        assert_eq!(dispatch(&mut scheduler), Some(b));
+        assert_eq!(cpu_time(&scheduler, a), 3);
+
+        // a is now at level 2, so b keeps the CPU
+        assert!(scheduler.yield_current().is_none());
+        assert_eq!(scheduler.current(), Some(b));
+        let b = scheduler.table().get(b).unwrap();
+        assert_eq!(b.voluntary_switches(), 1);
+        assert_eq!(b.cpu_time_ticks(), 1);
+        assert_eq!(scheduler.table().processes_sorted_by_cpu_time()[0].pid(), a);
+    }
+
+    #[test_case]
+    fn test_cpu_utilization() {
+        let mut scheduler = scheduler_with(&[]);
+        assert_eq!(scheduler.cpu_utilization(), 0);
+
+        // First tick switches to idle; the following three are idle ticks
+        for _ in 0..4 {
+            let _ = scheduler.tick();
+        }
+        assert_eq!(scheduler.idle_ticks(), 3);
+        assert_eq!(scheduler.total_ticks(), 4);
+        assert_eq!(scheduler.cpu_utilization(), 25);
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-process kernel stacks
+//!
+//! Every process owns a `KernelStack`: kernel threads run on it, and user
+//! processes enter the kernel on it when an interrupt, exception or system
+//! call arrives in ring 3. The stack pages are taken from the kernel heap
+//! and mapped into a range of the kernel address space from `KVMA`, with a
+//! guard page below them so an overflow faults instead of corrupting
+//! whatever lies underneath.
+
+use core::fmt;
+
+use crate::memory::{
+    FrameAllocator,
+    HeapFrameAllocator,
+    Page,
+    PageTableFlags,
+    PageTableManager,
+    PhysFrame,
+    VirtAddr,
+    frame::kernel_virt_to_phys,
+    kvma::KVMA,
+    paging::GUARD_PAGE_FLAGS,
+};
+
+/// Number of usable pages in a kernel stack
+pub const KERNEL_STACK_PAGES: usize = 4;
+
+/// Usable size of a kernel stack in bytes (16 KiB)
+pub const KERNEL_STACK_SIZE: usize = KERNEL_STACK_PAGES * Page::SIZE as usize;
+
+/// Flags the stack pages are mapped with
+const STACK_FLAGS: PageTableFlags = PageTableFlags::WRITABLE.union(PageTableFlags::NO_EXECUTE);
+
+/// Frame every guard page maps to
+///
+/// Guard pages are read-only, so they can all share one zeroed frame.
+static GUARD_FRAME: GuardFrame = GuardFrame([0; Page::SIZE as usize]);
+
+/// Page-aligned storage for `GUARD_FRAME`
+#[repr(C, align(4096))]
+struct GuardFrame([u8; Page::SIZE as usize]);
+
+/// Errors returned by `KernelStack::allocate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    /// The frame allocator ran out of frames
+    OutOfFrames,
+    /// `KVMA` has no free range for the stack
+    OutOfAddressSpace,
+    /// A stack page could not be mapped
+    MapFailed,
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfFrames => write!(f, "out of frames for the kernel stack"),
+            Self::OutOfAddressSpace => write!(f, "no kernel address space for the kernel stack"),
+            Self::MapFailed => write!(f, "failed to map the kernel stack"),
+        }
+    }
+}
+
+/// A guarded kernel stack mapped into the kernel address space
+///
+/// Dropping the stack unmaps it, returns its frames to the heap and its
+/// address range to `KVMA`.
+#[derive(Debug)]
+pub struct KernelStack {
+    /// Guard page at the bottom; the stack pages follow it
+    guard: Page,
+    /// Frames of the stack pages, bottom first
+    frames: [Option<PhysFrame>; KERNEL_STACK_PAGES],
+}
+
+impl KernelStack {
+    /// Allocates and maps a `KERNEL_STACK_SIZE` stack
+    ///
+    /// The stack pages and any page tables needed to map them come from
+    /// `HeapFrameAllocator`, which `Drop` returns the stack pages to.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StackError` if frames, address space or page tables run
+    /// out. Whatever was set up by then is released again.
+    pub fn allocate() -> Result<Self, StackError> {
+        let frame_allocator = &mut HeapFrameAllocator::new();
+        let base = KVMA
+            .lock()
+            .allocate(Self::range_size(), Page::SIZE)
+            .ok_or(StackError::OutOfAddressSpace)?;
+        // Dropped on error, which undoes the mappings made so far
+        let mut stack = Self {
+            guard: Page::containing_address(base),
+            frames: [None; KERNEL_STACK_PAGES],
+        };
+
+        // SAFETY: CR3 holds the active, identity-accessible P4 table
+        let mut mapper = unsafe { PageTableManager::current() };
+        let guard_frame = PhysFrame::from_start_address(kernel_virt_to_phys(
+            VirtAddr::from_ptr(&GUARD_FRAME).as_u64(),
+        ));
+        mapper
+            .map_page(stack.guard, guard_frame, GUARD_PAGE_FLAGS, frame_allocator)
+            .map_err(|_| StackError::MapFailed)?;
+
+        for (i, slot) in stack.frames.iter_mut().enumerate() {
+            let frame = frame_allocator
+                .allocate_frame()
+                .ok_or(StackError::OutOfFrames)?;
+            let page = stack.guard + 1 + i as u64;
+            if mapper
+                .map_page(page, frame, STACK_FLAGS, frame_allocator)
+                .is_err()
+            {
+                // SAFETY: the frame was just allocated and never mapped
+                unsafe { frame_allocator.deallocate_frame(frame) };
+                return Err(StackError::MapFailed);
+            }
+            *slot = Some(frame);
+        }
+        Ok(stack)
+    }
+
+    /// Returns the guard page below the stack
+    pub fn guard_page(&self) -> Page {
+        self.guard
+    }
+
+    /// Returns the lowest usable address of the stack
+    pub fn bottom(&self) -> VirtAddr {
+        (self.guard + 1).start_address()
+    }
+
+    /// Returns the address just above the stack, where it starts growing
+    /// down from
+    pub fn top(&self) -> VirtAddr {
+        self.bottom() + KERNEL_STACK_SIZE as u64
+    }
+
+    /// Size of the address range, including the guard page
+    fn range_size() -> u64 {
+        (KERNEL_STACK_PAGES as u64 + 1) * Page::SIZE
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        // SAFETY: CR3 holds the active, identity-accessible P4 table
+        let mut mapper = unsafe { PageTableManager::current() };
+        let _ = mapper.unmap_page(self.guard);
+        for (i, frame) in self.frames.iter_mut().enumerate() {
+            if let Some(frame) = frame.take() {
+                let _ = mapper.unmap_page(self.guard + 1 + i as u64);
+                // SAFETY: the frame backed this stack only and is unmapped
+                unsafe { HeapFrameAllocator::new().deallocate_frame(frame) };
+            }
+        }
+        KVMA.lock()
+            .free(self.guard.start_address(), Self::range_size());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_guard_page_is_mapped() {
+        let stack = KernelStack::allocate().unwrap();
+        // SAFETY: CR3 holds the active, identity-accessible P4 table
+        let mut mapper = unsafe { PageTableManager::current() };
+
+        let guard = mapper.page_flags(stack.guard_page()).unwrap();
+        assert!(guard.contains(PageTableFlags::PRESENT));
+        assert!(!guard.contains(PageTableFlags::WRITABLE));
+
+        let bottom = Page::containing_address(stack.bottom());
+        assert!(
+            mapper
+                .page_flags(bottom)
+                .unwrap()
+                .contains(PageTableFlags::WRITABLE)
+        );
+        assert_eq!(
+            stack.top().as_u64() - stack.bottom().as_u64(),
+            KERNEL_STACK_SIZE as u64
+        );
+
+        // The whole stack is usable
+        let top = (stack.top().as_u64() - 8) as *mut u64;
+        let bottom = stack.bottom().as_u64() as *mut u64;
+        // SAFETY: both words lie in the mapped stack pages
+        unsafe {
+            top.write_volatile(1);
+            bottom.write_volatile(2);
+        }
+    }
+
+    #[test_case]
+    fn test_drop_unmaps_stack() {
+        let stack = KernelStack::allocate().unwrap();
+        let guard = stack.guard_page();
+        let top = stack.top() - 8u64;
+        drop(stack);
+
+        // SAFETY: CR3 holds the active, identity-accessible P4 table
+        let mapper = unsafe { PageTableManager::current() };
+        assert!(mapper.translate_addr(guard.start_address()).is_none());
+        assert!(mapper.translate_addr(top).is_none());
+    }
+}
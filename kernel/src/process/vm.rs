@@ -0,0 +1,553 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Virtual memory areas of a process
+//!
+//! A process describes the parts of its address space it may touch as a
+//! list of areas. Demand areas start out unmapped: the first access to a
+//! page raises a page fault, and `handle_page_fault` backs the page with a
+//! zeroed frame before the faulting instruction is retried.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::SCHEDULER;
+use crate::memory::{
+    FrameAllocator,
+    HeapFrameAllocator,
+    MapError,
+    Page,
+    PageTableFlags,
+    PageTableManager,
+    USER_SPACE_END,
+    USER_SPACE_START,
+    VirtAddr,
+};
+
+/// Page fault error code bit: the page was present (protection violation)
+const PF_PRESENT: u64 = 1 << 0;
+/// Page fault error code bit: the access was a write
+const PF_WRITE: u64 = 1 << 1;
+
+/// How the pages of an area are backed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmKind {
+    /// Mapped when the area is set up, such as ELF segments
+    Fixed,
+    /// Mapped to a zeroed frame on first access
+    Demand,
+}
+
+/// Errors returned when adding areas or changing their protection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    /// The area is empty, not page-aligned or reaches past user space
+    InvalidArea,
+    /// The area overlaps an existing one
+    Overlap,
+    /// Part of the range lies outside every area
+    NotCovered,
+    /// The page tables could not be updated
+    Map(MapError),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidArea => {
+                write!(f, "memory area is empty, unaligned or outside user space")
+            }
+            Self::Overlap => write!(f, "memory area overlaps an existing one"),
+            Self::NotCovered => write!(f, "memory range is not covered by areas"),
+            Self::Map(e) => write!(f, "failed to update page tables: {}", e),
+        }
+    }
+}
+
+/// A contiguous range of a process's address space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmArea {
+    /// First address of the area, page-aligned
+    pub base: VirtAddr,
+    /// Length in bytes, a multiple of the page size
+    pub length: u64,
+    /// Flags the pages of the area are mapped with
+    pub flags: PageTableFlags,
+    /// How the pages are backed
+    pub kind: VmKind,
+}
+
+impl VmArea {
+    /// Creates an area of `length` bytes from `base`
+    pub const fn new(base: VirtAddr, length: u64, flags: PageTableFlags, kind: VmKind) -> Self {
+        Self {
+            base,
+            length,
+            flags,
+            kind,
+        }
+    }
+
+    /// Address one past the end of the area
+    pub fn end(&self) -> u64 {
+        self.base.as_u64() + self.length
+    }
+
+    /// Returns `true` if `addr` lies within the area
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        (self.base.as_u64()..self.end()).contains(&addr.as_u64())
+    }
+
+    /// Changes the protection of the area to `flags`
+    ///
+    /// Pages already mapped get the new flags right away; demand pages not
+    /// touched yet get them when they are first mapped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `MapError` of the first page that could not be updated.
+    /// Pages before it already have the new flags.
+    pub fn protect(
+        &mut self,
+        address_space: &mut PageTableManager,
+        flags: PageTableFlags,
+    ) -> Result<(), MapError> {
+        self.flags = flags;
+        let first = Page::containing_address(self.base);
+        for i in 0..self.length / Page::SIZE {
+            let page = first + i;
+            if address_space.translate_addr(page.start_address()).is_some() {
+                address_space.set_flags(page, flags)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Backs the page containing `addr` with a fresh zeroed frame
+    ///
+    /// # Errors
+    ///
+    /// Returns `MapError::NotMapped` if `addr` is outside the area or the
+    /// area is not a demand area, `MapError::FrameAllocationFailed` if no
+    /// frame is left for the page or its page tables and
+    /// `MapError::AlreadyMapped` if the page is already backed.
+    pub fn map_demand_page(
+        &self,
+        address_space: &mut PageTableManager,
+        addr: VirtAddr,
+        frame_allocator: &mut impl FrameAllocator,
+    ) -> Result<(), MapError> {
+        if self.kind != VmKind::Demand || !self.contains(addr) {
+            return Err(MapError::NotMapped);
+        }
+        let page = Page::containing_address(addr);
+        if address_space.translate_addr(page.start_address()).is_some() {
+            return Err(MapError::AlreadyMapped);
+        }
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapError::FrameAllocationFailed)?;
+        // SAFETY: the allocator hands out unused, identity-accessible frames
+        unsafe {
+            core::ptr::write_bytes(
+                frame.start_address().as_u64() as *mut u8,
+                0,
+                Page::SIZE as usize,
+            );
+        }
+        address_space.map_range(page, frame, 1, self.flags, frame_allocator)
+    }
+}
+
+/// Areas of an address space, sorted by base address and disjoint
+#[derive(Debug, Default, Clone)]
+pub struct VmAreaList {
+    areas: Vec<VmArea>,
+}
+
+impl VmAreaList {
+    /// Creates an empty list
+    pub const fn new() -> Self {
+        Self { areas: Vec::new() }
+    }
+
+    /// Adds `area` to the list
+    ///
+    /// # Errors
+    ///
+    /// Returns `VmError::InvalidArea` if the area is empty, unaligned or
+    /// lies outside `USER_SPACE_START..USER_SPACE_END`, or `VmError::Overlap`
+    /// if it overlaps an area already in the list.
+    pub fn insert(&mut self, area: VmArea) -> Result<(), VmError> {
+        if !is_valid_range(area.base, area.length) {
+            return Err(VmError::InvalidArea);
+        }
+
+        let index = self
+            .areas
+            .partition_point(|a| a.base.as_u64() < area.base.as_u64());
+        let overlaps_prev = index > 0 && self.areas[index - 1].end() > area.base.as_u64();
+        let overlaps_next = self
+            .areas
+            .get(index)
+            .is_some_and(|next| next.base.as_u64() < area.end());
+        if overlaps_prev || overlaps_next {
+            return Err(VmError::Overlap);
+        }
+        self.areas.insert(index, area);
+        Ok(())
+    }
+
+    /// Removes the area starting at `base`
+    pub fn remove(&mut self, base: VirtAddr) -> Option<VmArea> {
+        let index = self.areas.iter().position(|a| a.base == base)?;
+        Some(self.areas.remove(index))
+    }
+
+    /// Changes the protection of `length` bytes from `base` to `flags`
+    ///
+    /// Areas reaching past either end of the range are split there, so
+    /// only the pages in the range change. Afterwards, adjacent areas of the
+    /// same kind and flags around the range are merged.
+    ///
+    /// # Errors
+    ///
+    /// Returns `VmError::InvalidArea` if the range is empty, unaligned or
+    /// lies outside user space, `VmError::NotCovered` if part of it lies
+    /// outside every area, or `VmError::Map` if the page tables could not be
+    /// updated. In the last case the areas before the failing page have the
+    /// new flags.
+    pub fn protect(
+        &mut self,
+        address_space: &mut PageTableManager,
+        base: VirtAddr,
+        length: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), VmError> {
+        if !is_valid_range(base, length) {
+            return Err(VmError::InvalidArea);
+        }
+        let start = base.as_u64();
+        let end = start + length;
+
+        // Areas first..last must cover the range without gaps
+        let first = self.areas.partition_point(|a| a.end() <= start);
+        let mut last = first;
+        let mut covered = start;
+        while covered < end {
+            match self.areas.get(last) {
+                Some(area) if area.base.as_u64() <= covered => {
+                    covered = area.end();
+                    last += 1;
+                }
+                _ => return Err(VmError::NotCovered),
+            }
+        }
+
+        let mut first = first;
+        if self.areas[first].base.as_u64() < start {
+            self.split(first, start);
+            first += 1;
+            last += 1;
+        }
+        if self.areas[last - 1].end() > end {
+            self.split(last - 1, end);
+        }
+
+        for area in &mut self.areas[first..last] {
+            area.protect(address_space, flags).map_err(VmError::Map)?;
+        }
+        self.merge(first.saturating_sub(1), last + 1);
+        Ok(())
+    }
+
+    /// Splits the area at `index` in two at `at`, which lies inside it
+    fn split(&mut self, index: usize, at: u64) {
+        let area = &mut self.areas[index];
+        let tail = VmArea {
+            base: VirtAddr::new(at),
+            length: area.end() - at,
+            ..*area
+        };
+        area.length = at - area.base.as_u64();
+        self.areas.insert(index + 1, tail);
+    }
+
+    /// Merges adjacent areas of the same kind and flags among the areas
+    /// `from..to`
+    fn merge(&mut self, from: usize, to: usize) {
+        let mut to = to.min(self.areas.len());
+        let mut index = from;
+        while index + 1 < to {
+            let next = self.areas[index + 1];
+            let area = &mut self.areas[index];
+            if area.end() == next.base.as_u64()
+                && area.kind == next.kind
+                && area.flags == next.flags
+            {
+                area.length += next.length;
+                self.areas.remove(index + 1);
+                to -= 1;
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Finds the area containing `addr`
+    pub fn find(&self, addr: VirtAddr) -> Option<&VmArea> {
+        let index = self
+            .areas
+            .partition_point(|a| a.base.as_u64() <= addr.as_u64());
+        let area = self.areas.get(index.checked_sub(1)?)?;
+        area.contains(addr).then_some(area)
+    }
+
+    /// Iterates over the areas in address order
+    pub fn iter(&self) -> impl Iterator<Item = &VmArea> {
+        self.areas.iter()
+    }
+
+    /// Returns the number of areas
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+
+    /// Returns `true` if the list holds no areas
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+}
+
+/// Returns `true` if `length` bytes from `base` are a non-empty,
+/// page-aligned range in user space
+fn is_valid_range(base: VirtAddr, length: u64) -> bool {
+    length != 0
+        && base.as_u64() >= USER_SPACE_START
+        && base.is_aligned(Page::SIZE)
+        && length.is_multiple_of(Page::SIZE)
+        && base
+            .as_u64()
+            .checked_add(length)
+            .is_some_and(|end| end <= USER_SPACE_END)
+}
+
+/// Resolves a page fault on a demand area of the running process
+///
+/// Called by the page fault handler with the faulting address from CR2.
+/// The page is mapped into the active address space.
+///
+/// # Returns
+///
+/// `true` if the page was mapped and the faulting instruction can be
+/// retried, `false` if the fault is genuine: a protection violation, a
+/// write to a read-only area or an address outside every demand area.
+pub fn handle_page_fault(addr: VirtAddr, error_code: u64) -> bool {
+    if error_code & PF_PRESENT != 0 {
+        return false;
+    }
+    // The fault may have interrupted code holding the scheduler lock;
+    // waiting for it would deadlock
+    let Some(scheduler) = SCHEDULER.try_lock() else {
+        return false;
+    };
+    let Some(area) = scheduler
+        .current()
+        .and_then(|pid| scheduler.table().get(pid))
+        .and_then(|process| process.vm_areas().find(addr))
+    else {
+        return false;
+    };
+    if error_code & PF_WRITE != 0 && !area.flags.contains(PageTableFlags::WRITABLE) {
+        return false;
+    }
+
+    // SAFETY: CR3 holds the active, identity-accessible P4 table
+    let mut address_space = unsafe { PageTableManager::current() };
+    area.map_demand_page(&mut address_space, addr, &mut HeapFrameAllocator::new())
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::PageTable;
+
+    const BASE: u64 = 0x2000_0000_0000;
+
+    fn area(base: u64, pages: u64, kind: VmKind) -> VmArea {
+        VmArea::new(
+            VirtAddr::new(base),
+            pages * Page::SIZE,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+            kind,
+        )
+    }
+
+    #[test_case]
+    fn test_insert_rejects_overlap_and_bad_areas() {
+        let mut list = VmAreaList::new();
+        list.insert(area(BASE + 4 * Page::SIZE, 2, VmKind::Demand))
+            .unwrap();
+        list.insert(area(BASE, 2, VmKind::Fixed)).unwrap();
+        assert_eq!(
+            list.insert(area(BASE + Page::SIZE, 2, VmKind::Demand)),
+            Err(VmError::Overlap)
+        );
+        assert_eq!(
+            list.insert(area(BASE + 5 * Page::SIZE, 1, VmKind::Demand)),
+            Err(VmError::Overlap)
+        );
+        assert_eq!(
+            list.insert(area(BASE + 8, 1, VmKind::Demand)),
+            Err(VmError::InvalidArea)
+        );
+        assert_eq!(
+            list.insert(area(BASE, 0, VmKind::Demand)),
+            Err(VmError::InvalidArea)
+        );
+        assert_eq!(
+            list.insert(area(USER_SPACE_END, 1, VmKind::Demand)),
+            Err(VmError::InvalidArea)
+        );
+        assert_eq!(
+            list.insert(area(USER_SPACE_START - Page::SIZE, 1, VmKind::Demand)),
+            Err(VmError::InvalidArea)
+        );
+        list.insert(area(USER_SPACE_END - Page::SIZE, 1, VmKind::Demand))
+            .unwrap();
+        list.remove(VirtAddr::new(USER_SPACE_END - Page::SIZE));
+        list.insert(area(BASE + 2 * Page::SIZE, 2, VmKind::Demand))
+            .unwrap();
+
+        let bases: Vec<u64> = list.iter().map(|a| a.base.as_u64()).collect();
+        assert_eq!(bases, [BASE, BASE + 2 * Page::SIZE, BASE + 4 * Page::SIZE]);
+    }
+
+    #[test_case]
+    fn test_find_and_remove() {
+        let mut list = VmAreaList::new();
+        list.insert(area(BASE, 1, VmKind::Fixed)).unwrap();
+        list.insert(area(BASE + 2 * Page::SIZE, 2, VmKind::Demand))
+            .unwrap();
+
+        assert_eq!(
+            list.find(VirtAddr::new(BASE + 0xfff)).map(|a| a.kind),
+            Some(VmKind::Fixed)
+        );
+        assert!(list.find(VirtAddr::new(BASE + Page::SIZE)).is_none());
+        assert_eq!(
+            list.find(VirtAddr::new(BASE + 3 * Page::SIZE + 8))
+                .map(|a| a.kind),
+            Some(VmKind::Demand)
+        );
+        assert!(list.find(VirtAddr::new(BASE + 4 * Page::SIZE)).is_none());
+        assert!(list.find(VirtAddr::new(0)).is_none());
+
+        assert!(list.remove(VirtAddr::new(BASE)).is_some());
+        assert!(list.remove(VirtAddr::new(BASE)).is_none());
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test_case]
+    fn test_list_protect_splits_and_merges() {
+        let mut allocator = HeapFrameAllocator::new();
+        let frame = allocator.allocate_frame().unwrap();
+        // SAFETY: the frame is zeroed, unused and identity-accessible
+        let mut space = unsafe {
+            PageTableManager::from_p4_table(
+                &mut *(frame.start_address().as_u64() as *mut PageTable),
+            )
+        };
+        let read_only = PageTableFlags::PRESENT;
+        let writable = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        let mut list = VmAreaList::new();
+        list.insert(area(BASE, 4, VmKind::Demand)).unwrap();
+        let touched = VirtAddr::new(BASE + Page::SIZE);
+        list.find(touched)
+            .unwrap()
+            .map_demand_page(&mut space, touched, &mut allocator)
+            .unwrap();
+
+        // The middle two pages become read-only
+        list.protect(&mut space, touched, 2 * Page::SIZE, read_only)
+            .unwrap();
+        let areas: Vec<(u64, u64, PageTableFlags)> = list
+            .iter()
+            .map(|a| (a.base.as_u64(), a.length / Page::SIZE, a.flags))
+            .collect();
+        assert_eq!(areas, [
+            (BASE, 1, writable),
+            (BASE + Page::SIZE, 2, read_only),
+            (BASE + 3 * Page::SIZE, 1, writable),
+        ]);
+        assert_eq!(
+            space.page_flags(Page::containing_address(touched)),
+            Some(read_only)
+        );
+
+        // Restoring the flags merges the pieces again
+        list.protect(&mut space, touched, 2 * Page::SIZE, writable)
+            .unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.find(touched).unwrap().length, 4 * Page::SIZE);
+
+        assert_eq!(
+            list.protect(&mut space, VirtAddr::new(BASE), 5 * Page::SIZE, read_only),
+            Err(VmError::NotCovered)
+        );
+        assert_eq!(
+            list.protect(&mut space, VirtAddr::new(BASE + 8), Page::SIZE, read_only),
+            Err(VmError::InvalidArea)
+        );
+    }
+
+    #[test_case]
+    fn test_map_demand_page() {
+        let mut allocator = HeapFrameAllocator::new();
+        let frame = allocator.allocate_frame().unwrap();
+        // SAFETY: the frame is zeroed, unused and identity-accessible
+        let mut space = unsafe {
+            PageTableManager::from_p4_table(
+                &mut *(frame.start_address().as_u64() as *mut PageTable),
+            )
+        };
+
+        let demand = area(BASE, 2, VmKind::Demand);
+        let addr = VirtAddr::new(BASE + Page::SIZE + 0x10);
+        assert!(space.translate_addr(addr).is_none());
+        demand
+            .map_demand_page(&mut space, addr, &mut allocator)
+            .unwrap();
+        assert!(space.translate_addr(addr).is_some());
+        assert!(space.translate_addr(VirtAddr::new(BASE)).is_none());
+        assert_eq!(
+            demand.map_demand_page(&mut space, addr, &mut allocator),
+            Err(MapError::AlreadyMapped)
+        );
+
+        let mut demand = demand;
+        demand.protect(&mut space, PageTableFlags::PRESENT).unwrap();
+        assert_eq!(demand.flags, PageTableFlags::PRESENT);
+        let touched = Page::containing_address(addr);
+        assert_eq!(space.page_flags(touched), Some(PageTableFlags::PRESENT));
+
+        let fixed = area(BASE + 4 * Page::SIZE, 1, VmKind::Fixed);
+        assert_eq!(
+            fixed.map_demand_page(&mut space, fixed.base, &mut allocator),
+            Err(MapError::NotMapped)
+        );
+    }
+}
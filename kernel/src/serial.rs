@@ -16,17 +16,87 @@
 //!
 //! This module provides a driver for the 16550 UART serial port,
 //! which is used for kernel debugging output via QEMU serial console.
+//! Each of the four standard ports has its own global instance; COM1 is
+//! set up by `init`, the others on demand through `open`.
 
 #![allow(dead_code)]
 
-use core::fmt;
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicBool,
+        AtomicU64,
+        Ordering,
+    },
+};
 
 use spin::Mutex;
 
-use crate::interrupts::port::Port;
+use crate::{
+    interrupts::{
+        idt::InterruptStackFrame,
+        port::{
+            Port,
+            PortReadOnly,
+        },
+    },
+    sync::DetectMutex,
+};
+
+/// IRQ line of COM1 (vector 36)
+pub const COM1_IRQ: u8 = 4;
+
+/// Capacity of the receive buffer in bytes
+pub const RX_BUFFER_CAPACITY: usize = 4096;
+
+/// Standard PC serial ports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComPort {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
+
+impl ComPort {
+    /// All ports, in index order
+    pub const ALL: [ComPort; 4] = [ComPort::Com1, ComPort::Com2, ComPort::Com3, ComPort::Com4];
+
+    /// Returns the I/O base address of the port
+    pub const fn base_addr(self) -> u16 {
+        match self {
+            ComPort::Com1 => 0x3f8,
+            ComPort::Com2 => 0x2f8,
+            ComPort::Com3 => 0x3e8,
+            ComPort::Com4 => 0x2e8,
+        }
+    }
 
-/// Serial port port numbers
-const COM1: u16 = 0x3f8;
+    /// Returns the global driver instance of the port
+    fn instance(self) -> &'static DetectMutex<SerialPort> {
+        match self {
+            ComPort::Com1 => &SERIAL1,
+            ComPort::Com2 => &SERIAL2,
+            ComPort::Com3 => &SERIAL3,
+            ComPort::Com4 => &SERIAL4,
+        }
+    }
+}
+
+/// Serial port errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    /// The loopback self-test failed, so no working UART is present
+    LoopbackFailed,
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LoopbackFailed => write!(f, "no working UART: loopback self-test failed"),
+        }
+    }
+}
 
 /// UART register offsets
 const DATA: u16 = 0; // Data register (R/W)
@@ -36,6 +106,9 @@ const LINE_CTRL: u16 = 3; // Line control register
 const MODEM_CTRL: u16 = 4; // Modem control register
 const LINE_STATUS: u16 = 5; // Line status register
 
+/// Interrupt enable flags
+const INT_ENABLE_DATA_READY: u8 = 0x01;
+
 /// Line status flags
 const LINE_STATUS_OUTPUT_EMPTY: u8 = 0x20;
 const LINE_STATUS_DATA_READY: u8 = 0x01;
@@ -47,19 +120,23 @@ pub struct SerialPort {
     fifo_ctrl: Port<u8>,
     line_ctrl: Port<u8>,
     modem_ctrl: Port<u8>,
-    line_status: Port<u8>,
+    line_status: PortReadOnly<u8>,
 }
 
 impl SerialPort {
     /// Create new serial port (uninitialized)
-    const fn new(base: u16) -> Self {
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - I/O base address of the UART
+    pub const fn new(base: u16) -> Self {
         Self {
             data: Port::new(base + DATA),
             int_enable: Port::new(base + INT_ENABLE),
             fifo_ctrl: Port::new(base + FIFO_CTRL),
             line_ctrl: Port::new(base + LINE_CTRL),
             modem_ctrl: Port::new(base + MODEM_CTRL),
-            line_status: Port::new(base + LINE_STATUS),
+            line_status: PortReadOnly::new(base + LINE_STATUS),
         }
     }
 
@@ -68,12 +145,18 @@ impl SerialPort {
     /// This function attempts to initialize the serial port up to `max_retries`
     /// times. It includes proper reset sequences and delays to handle
     /// timing issues with serial hardware or emulators like QEMU.
-    pub fn init(&mut self) {
+    ///
+    /// # Errors
+    ///
+    /// Returns `SerialError::LoopbackFailed` if every attempt failed the
+    /// loopback test. The port is left in normal mode, but output to it
+    /// will most likely be lost.
+    pub fn init(&mut self) -> Result<(), SerialError> {
         const MAX_RETRIES: u32 = 3;
 
         for _attempt in 0..MAX_RETRIES {
             if self.try_init() {
-                return; // Initialization successful
+                return Ok(());
             }
 
             // Add delay between retries
@@ -82,14 +165,14 @@ impl SerialPort {
             }
         }
 
-        // All retries failed - serial port is not functional
-        // Continue anyway, but output will be lost
+        Err(SerialError::LoopbackFailed)
     }
 
     /// Attempt to initialize the serial port once
     ///
     /// Returns true if initialization was successful, false otherwise.
     fn try_init(&mut self) -> bool {
+        // SAFETY: the ports belong to this UART, which nothing else drives
         unsafe {
             // Reset sequence: Disable all interrupts and FIFO first
             self.int_enable.write(0x00);
@@ -113,7 +196,8 @@ impl SerialPort {
             // Enable FIFO, clear both FIFOs, 14-byte threshold
             self.fifo_ctrl.write(0xc7);
 
-            // Data Terminal Ready, Request To Send, Output 2 (enables interrupts)
+            // Data Terminal Ready, Request To Send, Output 2 (enables
+            // interrupts)
             self.modem_ctrl.write(0x0b);
 
             // Another small delay after configuration
@@ -162,6 +246,7 @@ impl SerialPort {
 
     /// Send 1 byte
     fn send(&mut self, byte: u8) {
+        // SAFETY: the ports belong to this UART, which nothing else drives
         unsafe {
             // Wait until transmission buffer is empty
             let mut timeout = 100000;
@@ -184,8 +269,27 @@ impl SerialPort {
         }
     }
 
+    /// Send raw bytes
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.send(byte);
+        }
+    }
+
+    /// Raises the port's IRQ whenever received data is ready
+    ///
+    /// Received bytes are then collected by `serial_interrupt_handler`
+    /// instead of being polled with `receive`.
+    pub fn enable_rx_interrupt(&mut self) {
+        // SAFETY: only enables the data-ready interrupt of this UART
+        unsafe {
+            self.int_enable.write(INT_ENABLE_DATA_READY);
+        }
+    }
+
     /// Receive 1 byte (None if no data)
     pub fn receive(&mut self) -> Option<u8> {
+        // SAFETY: reading the data register only consumes received bytes
         unsafe {
             if self.line_status.read() & LINE_STATUS_DATA_READY != 0 {
                 Some(self.data.read())
@@ -204,11 +308,182 @@ impl fmt::Write for SerialPort {
 }
 
 /// Global serial port (COM1)
-pub static SERIAL1: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1));
+pub static SERIAL1: DetectMutex<SerialPort> =
+    DetectMutex::new(SerialPort::new(ComPort::Com1.base_addr()));
+/// Global serial port (COM2)
+pub static SERIAL2: DetectMutex<SerialPort> =
+    DetectMutex::new(SerialPort::new(ComPort::Com2.base_addr()));
+/// Global serial port (COM3)
+pub static SERIAL3: DetectMutex<SerialPort> =
+    DetectMutex::new(SerialPort::new(ComPort::Com3.base_addr()));
+/// Global serial port (COM4)
+pub static SERIAL4: DetectMutex<SerialPort> =
+    DetectMutex::new(SerialPort::new(ComPort::Com4.base_addr()));
+
+/// Bytes received on COM1 by the interrupt handler
+pub static SERIAL_RX_BUFFER: Mutex<SerialRxBuffer> = Mutex::new(SerialRxBuffer::new());
+
+/// Number of received bytes dropped because the receive buffer was full
+pub static SERIAL_RX_OVERRUNS: AtomicU64 = AtomicU64::new(0);
+
+/// Whether each port, indexed by `ComPort`, passed initialization
+static OPENED: [AtomicBool; 4] = [const { AtomicBool::new(false) }; 4];
+
+/// Ring buffer of received bytes
+#[derive(Debug)]
+pub struct SerialRxBuffer {
+    data: [u8; RX_BUFFER_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl SerialRxBuffer {
+    /// Creates an empty buffer
+    pub const fn new() -> Self {
+        Self {
+            data: [0; RX_BUFFER_CAPACITY],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends a byte
+    ///
+    /// # Returns
+    ///
+    /// `false` if the buffer is full and the byte was dropped
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.len == RX_BUFFER_CAPACITY {
+            return false;
+        }
+        self.data[(self.head + self.len) % RX_BUFFER_CAPACITY] = byte;
+        self.len += 1;
+        true
+    }
+
+    /// Removes and returns the oldest byte
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.data[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_CAPACITY;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /// Returns the number of buffered bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no bytes are buffered
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for SerialRxBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// Initialize serial port
+///
+/// Sets up COM1. A failed loopback test is ignored: output is written to
+/// COM1 regardless, as there is nowhere else to report the failure.
 pub fn init() {
-    SERIAL1.lock().init();
+    let _ = open(ComPort::Com1);
+}
+
+/// Opens a serial port, initializing it on first use
+///
+/// # Arguments
+///
+/// * `port` - Port to open
+///
+/// # Returns
+///
+/// The global instance of the port
+///
+/// # Errors
+///
+/// Returns `SerialError::LoopbackFailed` if the port failed its loopback
+/// test; initialization is retried on the next call.
+pub fn open(port: ComPort) -> Result<&'static DetectMutex<SerialPort>, SerialError> {
+    let serial = port.instance();
+    let opened = &OPENED[port as usize];
+    if !opened.load(Ordering::Acquire) {
+        // Holding the lock keeps concurrent opens from initializing twice
+        let mut guard = serial.lock();
+        if !opened.load(Ordering::Acquire) {
+            guard.init()?;
+            opened.store(true, Ordering::Release);
+        }
+    }
+    Ok(serial)
+}
+
+/// Enables the COM1 receive interrupt
+///
+/// Must be called after the IDT is loaded; IRQ 4 still has to be unmasked
+/// for the interrupt to reach the CPU.
+pub fn enable_rx_interrupts() {
+    crate::interrupts::without_interrupts(|| SERIAL1.lock().enable_rx_interrupt());
+}
+
+/// Removes and returns the oldest byte received on COM1
+pub fn read_byte() -> Option<u8> {
+    crate::interrupts::without_interrupts(|| SERIAL_RX_BUFFER.lock().pop())
+}
+
+/// Stores a received byte, counting it as an overrun if `buffer` is full
+fn store_rx_byte(buffer: &mut SerialRxBuffer, byte: u8) {
+    if !buffer.push(byte) {
+        SERIAL_RX_OVERRUNS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// COM1 interrupt handler (IRQ 4, vector 36)
+///
+/// Drains the receive FIFO into `SERIAL_RX_BUFFER`. The UART registers are
+/// accessed directly rather than through `SERIAL1`, whose lock may be held
+/// by the interrupted code.
+pub extern "x86-interrupt" fn serial_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    let base = ComPort::Com1.base_addr();
+    let mut data = Port::<u8>::new(base + DATA);
+    let mut line_status = PortReadOnly::<u8>::new(base + LINE_STATUS);
+
+    let mut buffer = SERIAL_RX_BUFFER.lock();
+    // SAFETY: reading the data register only consumes received bytes
+    unsafe {
+        while line_status.read() & LINE_STATUS_DATA_READY != 0 {
+            store_rx_byte(&mut buffer, data.read());
+        }
+    }
+    drop(buffer);
+
+    // SAFETY: called from the IRQ 4 handler
+    unsafe {
+        crate::interrupts::end_of_interrupt(COM1_IRQ);
+    }
+}
+
+/// Replays the buffered log messages to COM1
+///
+/// Entries are formatted like live log output.
+pub fn dump_log() {
+    use crate::io::logging::{
+        COM1_ANSI_SINK,
+        LogSink,
+    };
+
+    for entry in crate::io::logging::iter_log() {
+        crate::interrupts::without_interrupts(|| {
+            entry.with_record(|record| COM1_ANSI_SINK.write(record))
+        });
+    }
 }
 
 /// Print string to serial port
@@ -218,6 +493,19 @@ pub fn _print(args: fmt::Arguments) {
     SERIAL1.lock().write_fmt(args).ok(); // Silently ignore failures to prevent double-panic in panic handler
 }
 
+/// Print to COM1 without taking the `SERIAL1` lock
+///
+/// For contexts that must never wait for a lock, such as the NMI handler.
+/// The UART registers are written directly, so the output may interleave
+/// with that of a concurrent `SERIAL1` user.
+pub fn _print_unlocked(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    SerialPort::new(ComPort::Com1.base_addr())
+        .write_fmt(args)
+        .ok();
+}
+
 /// Serial output macro
 #[macro_export]
 macro_rules! serial_print {
@@ -234,3 +522,50 @@ macro_rules! serial_println {
     ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(
         concat!($fmt, "\n"), $($arg)*));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_com_port_base_addresses() {
+        assert_eq!(ComPort::Com1.base_addr(), 0x3f8);
+        assert_eq!(ComPort::Com2.base_addr(), 0x2f8);
+        assert_eq!(ComPort::Com3.base_addr(), 0x3e8);
+        assert_eq!(ComPort::Com4.base_addr(), 0x2e8);
+    }
+
+    #[test_case]
+    fn test_open_com1_returns_global_port() {
+        let serial = open(ComPort::Com1).expect("COM1 should pass its loopback test");
+        assert!(core::ptr::eq(serial, &SERIAL1));
+    }
+
+    #[test_case]
+    fn test_rx_buffer_is_fifo() {
+        let mut buffer = SerialRxBuffer::new();
+        for byte in b"abc" {
+            assert!(buffer.push(*byte));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop(), Some(b'a'));
+        assert_eq!(buffer.pop(), Some(b'b'));
+        assert_eq!(buffer.pop(), Some(b'c'));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test_case]
+    fn test_rx_overflow_counts_overrun() {
+        let mut buffer = SerialRxBuffer::new();
+        for byte in 0..RX_BUFFER_CAPACITY {
+            store_rx_byte(&mut buffer, byte as u8);
+        }
+        let overruns = SERIAL_RX_OVERRUNS.load(Ordering::Relaxed);
+
+        store_rx_byte(&mut buffer, 0xff);
+        assert_eq!(SERIAL_RX_OVERRUNS.load(Ordering::Relaxed), overruns + 1);
+        // The dropped byte did not displace the oldest one
+        assert_eq!(buffer.len(), RX_BUFFER_CAPACITY);
+        assert_eq!(buffer.pop(), Some(0));
+    }
+}
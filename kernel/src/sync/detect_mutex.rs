@@ -0,0 +1,359 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Spinlock with deadlock detection
+//!
+//! In debug builds `DetectMutex` wraps `spin::Mutex` and records every lock
+//! a CPU waits for or holds in a small table. Before spinning on a lock it
+//! checks the table and panics instead of hanging if
+//!
+//! - the same CPU already holds or waits for the lock, as when an interrupt
+//!   handler takes a lock held by the code it interrupted, or
+//! - the holder of the lock is, directly or through other CPUs, waiting for a
+//!   lock this CPU holds.
+//!
+//! The panic message names both locks by address and the places they were
+//! acquired. Detection stops once the kernel is panicking, so a deadlock on
+//! a lock the panic handler needs cannot recurse. In release builds
+//! `DetectMutex` is `spin::Mutex` itself.
+
+#[cfg(debug_assertions)]
+pub use detect::{
+    DetectMutex,
+    DetectMutexGuard,
+};
+
+/// Lock type used by kernel statics prone to deadlocks
+#[cfg(not(debug_assertions))]
+pub type DetectMutex<T> = spin::Mutex<T>;
+
+/// Guard of a locked `DetectMutex`
+#[cfg(not(debug_assertions))]
+pub type DetectMutexGuard<'a, T> = spin::MutexGuard<'a, T>;
+
+/// Number of locks that can be tracked at once
+pub const MAX_LOCK_RECORDS: usize = 16;
+
+/// A lock some CPU holds or waits for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockRecord {
+    /// Address of the lock
+    pub lock_address: usize,
+    /// APIC ID of the CPU, 0 before the APIC is enabled
+    pub cpu_id: u32,
+    /// Timer tick of the `lock` call
+    pub acquire_tick: u64,
+    /// `true` once the lock is held, `false` while waiting for it
+    pub held: bool,
+    /// Source location of the `lock` call
+    pub location: &'static core::panic::Location<'static>,
+}
+
+/// A lock request that can never succeed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadlock {
+    /// The requesting CPU already holds or waits for the lock
+    Reentrant(LockRecord),
+    /// The lock's holder waits, possibly through other CPUs, for `waiting`,
+    /// which is held by the requesting CPU
+    Cycle {
+        /// Record of the requested lock
+        holder: LockRecord,
+        /// Record of the lock held by the requesting CPU
+        waiting: LockRecord,
+    },
+}
+
+/// Checks whether `cpu_id` may wait for the lock at `lock_address`
+///
+/// # Returns
+///
+/// The deadlock waiting would cause, if any
+pub fn find_deadlock(
+    records: &[Option<LockRecord>],
+    lock_address: usize,
+    cpu_id: u32,
+) -> Option<Deadlock> {
+    let count = records.len();
+    let records = || records.iter().flatten();
+    if let Some(record) = records().find(|r| r.lock_address == lock_address && r.cpu_id == cpu_id) {
+        return Some(Deadlock::Reentrant(*record));
+    }
+
+    // Follow holder -> lock it waits for -> holder ... back to `cpu_id`;
+    // every step visits a different record, so the walk is bounded
+    let holder = *records().find(|r| r.lock_address == lock_address && r.held)?;
+    let mut current = holder;
+    for _ in 0..count {
+        let wanted = records().find(|r| r.cpu_id == current.cpu_id && !r.held)?;
+        let next = records().find(|r| r.lock_address == wanted.lock_address && r.held)?;
+        if next.cpu_id == cpu_id {
+            return Some(Deadlock::Cycle {
+                holder,
+                waiting: *next,
+            });
+        }
+        current = *next;
+    }
+    None
+}
+
+#[cfg(debug_assertions)]
+mod detect {
+    use core::{
+        mem::ManuallyDrop,
+        ops::{
+            Deref,
+            DerefMut,
+        },
+        panic::Location,
+    };
+
+    use super::{
+        Deadlock,
+        LockRecord,
+        MAX_LOCK_RECORDS,
+        find_deadlock,
+    };
+
+    /// Locks waited for or held by any CPU
+    static LOCK_RECORDS: spin::Mutex<[Option<LockRecord>; MAX_LOCK_RECORDS]> =
+        spin::Mutex::new([None; MAX_LOCK_RECORDS]);
+
+    /// Spinlock that panics instead of deadlocking
+    pub struct DetectMutex<T: ?Sized> {
+        inner: spin::Mutex<T>,
+    }
+
+    /// Guard of a locked `DetectMutex`; unlocks when dropped
+    pub struct DetectMutexGuard<'a, T: ?Sized + 'a> {
+        guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+        /// Slot of the lock's entry in `LOCK_RECORDS`, if there was room
+        slot: Option<usize>,
+    }
+
+    impl<T> DetectMutex<T> {
+        /// Creates an unlocked mutex holding `value`
+        pub const fn new(value: T) -> Self {
+            Self {
+                inner: spin::Mutex::new(value),
+            }
+        }
+    }
+
+    impl<T: ?Sized> DetectMutex<T> {
+        /// Acquires the lock, spinning until it is free
+        ///
+        /// # Panics
+        ///
+        /// Panics if waiting for the lock would deadlock.
+        #[track_caller]
+        pub fn lock(&self) -> DetectMutexGuard<'_, T> {
+            let location = Location::caller();
+            let slot = self.record(location, false);
+            let guard = self.inner.lock();
+            if let Some(slot) = slot {
+                set_held(slot);
+            }
+            DetectMutexGuard {
+                guard: ManuallyDrop::new(guard),
+                slot,
+            }
+        }
+
+        /// Acquires the lock if it is free
+        #[track_caller]
+        pub fn try_lock(&self) -> Option<DetectMutexGuard<'_, T>> {
+            let guard = self.inner.try_lock()?;
+            let slot = self.record(Location::caller(), true);
+            Some(DetectMutexGuard {
+                guard: ManuallyDrop::new(guard),
+                slot,
+            })
+        }
+
+        /// Returns `true` if the lock is held
+        pub fn is_locked(&self) -> bool {
+            self.inner.is_locked()
+        }
+
+        /// Adds the lock to `LOCK_RECORDS` after checking for deadlocks
+        ///
+        /// Returns the slot used, or `None` if the table is full.
+        fn record(&self, location: &'static Location<'static>, held: bool) -> Option<usize> {
+            let lock_address = self as *const Self as *const () as usize;
+            let cpu_id = crate::interrupts::apic::local_apic().map_or(0, |apic| apic.id());
+            let record = LockRecord {
+                lock_address,
+                cpu_id,
+                acquire_tick: crate::interrupts::timer::ticks(),
+                held,
+                location,
+            };
+
+            crate::interrupts::without_interrupts(|| {
+                let mut records = LOCK_RECORDS.lock();
+                // Once panicking, a report would re-enter the panic handler,
+                // which takes the very locks a deadlock is likely found on
+                if !held && !crate::panic::is_panicking() {
+                    if let Some(deadlock) = find_deadlock(&*records, lock_address, cpu_id) {
+                        drop(records);
+                        report(&record, deadlock);
+                    }
+                }
+                let slot = records.iter().position(Option::is_none)?;
+                records[slot] = Some(record);
+                Some(slot)
+            })
+        }
+    }
+
+    // SAFETY: as for `spin::Mutex`, access to the value is serialized
+    unsafe impl<T: ?Sized + Send> Sync for DetectMutex<T> {}
+    // SAFETY: as for `spin::Mutex`
+    unsafe impl<T: ?Sized + Send> Send for DetectMutex<T> {}
+
+    impl<T: Default> Default for DetectMutex<T> {
+        fn default() -> Self {
+            Self::new(T::default())
+        }
+    }
+
+    impl<T: ?Sized> Deref for DetectMutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.guard
+        }
+    }
+
+    impl<T: ?Sized> DerefMut for DetectMutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.guard
+        }
+    }
+
+    impl<T: ?Sized> Drop for DetectMutexGuard<'_, T> {
+        fn drop(&mut self) {
+            // Unlock before forgetting the record, so that nothing can wait
+            // for the lock unchecked
+            // SAFETY: the guard is not used again
+            unsafe { ManuallyDrop::drop(&mut self.guard) };
+            if let Some(slot) = self.slot {
+                crate::interrupts::without_interrupts(|| LOCK_RECORDS.lock()[slot] = None);
+            }
+        }
+    }
+
+    /// Marks the record in `slot` as held
+    fn set_held(slot: usize) {
+        crate::interrupts::without_interrupts(|| {
+            if let Some(record) = LOCK_RECORDS.lock()[slot].as_mut() {
+                record.held = true;
+            }
+        });
+    }
+
+    /// Panics with a description of `deadlock`, found when `request` was
+    /// about to wait
+    fn report(request: &LockRecord, deadlock: Deadlock) -> ! {
+        match deadlock {
+            Deadlock::Reentrant(first) => panic!(
+                "deadlock: reentrant lock of {:#x} at {} on CPU {}; already acquired at {} (tick \
+                 {})",
+                request.lock_address,
+                request.location,
+                request.cpu_id,
+                first.location,
+                first.acquire_tick
+            ),
+            Deadlock::Cycle { holder, waiting } => panic!(
+                "deadlock: lock {:#x} at {} is held by CPU {} (acquired at {}), which waits for \
+                 lock {:#x} held by CPU {} (acquired at {})",
+                request.lock_address,
+                request.location,
+                holder.cpu_id,
+                holder.location,
+                waiting.lock_address,
+                waiting.cpu_id,
+                waiting.location
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::panic::Location;
+
+    use super::*;
+
+    fn record(lock_address: usize, cpu_id: u32, held: bool) -> Option<LockRecord> {
+        Some(LockRecord {
+            lock_address,
+            cpu_id,
+            acquire_tick: 0,
+            held,
+            location: Location::caller(),
+        })
+    }
+
+    #[test_case]
+    fn test_reentrant_lock_is_a_deadlock() {
+        let records = [record(0x1000, 0, true), None, record(0x2000, 1, true)];
+        assert!(matches!(
+            find_deadlock(&records, 0x1000, 0),
+            Some(Deadlock::Reentrant(r)) if r.lock_address == 0x1000
+        ));
+        // Another CPU merely waits for the holder to finish
+        assert_eq!(find_deadlock(&records, 0x1000, 1), None);
+        assert_eq!(find_deadlock(&records, 0x3000, 0), None);
+    }
+
+    #[test_case]
+    fn test_wait_cycle_is_a_deadlock() {
+        // CPU 0 holds A, CPU 1 holds B and waits for A
+        let records = [
+            record(0xa000, 0, true),
+            record(0xb000, 1, true),
+            record(0xa000, 1, false),
+        ];
+        match find_deadlock(&records, 0xb000, 0) {
+            Some(Deadlock::Cycle { holder, waiting }) => {
+                assert_eq!((holder.lock_address, holder.cpu_id), (0xb000, 1));
+                assert_eq!((waiting.lock_address, waiting.cpu_id), (0xa000, 0));
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+
+        // Without CPU 1 waiting there is no cycle
+        assert_eq!(find_deadlock(&records[..2], 0xb000, 0), None);
+    }
+
+    #[test_case]
+    fn test_three_cpu_cycle() {
+        // 0 holds A; 1 holds B, waits for C; 2 holds C, waits for A
+        let records = [
+            record(0xa000, 0, true),
+            record(0xb000, 1, true),
+            record(0xc000, 1, false),
+            record(0xc000, 2, true),
+            record(0xa000, 2, false),
+        ];
+        assert!(matches!(
+            find_deadlock(&records, 0xb000, 0),
+            Some(Deadlock::Cycle { .. })
+        ));
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Synchronization primitives
+//!
+//! The kernel's locks come from the `spin` crate; this module adds
+//! debugging aids on top of them, primitives that block processes and a
+//! lock-free queue.
+
+pub mod detect_mutex;
+pub mod mpsc;
+pub mod rwlock;
+pub mod semaphore;
+
+pub use detect_mutex::{
+    DetectMutex,
+    DetectMutexGuard,
+};
+pub use mpsc::{
+    MpscQueue,
+    NodePool,
+};
+pub use rwlock::{
+    KernelRwLock,
+    ReadGuard,
+    WriteGuard,
+};
+pub use semaphore::Semaphore;
@@ -0,0 +1,401 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lock-free multi-producer, single-consumer queue
+//!
+//! `MpscQueue` is a Michael-Scott style linked queue with a dummy node:
+//! `head` points at the dummy, whose successor holds the oldest value, and
+//! `tail` at the newest node. Producers link a node by swapping it into
+//! `tail` and then pointing the previous tail at it, which takes a fixed
+//! number of steps, so pushing never waits for other producers. Only the
+//! consumer moves `head`.
+//!
+//! Nodes come from a `NodePool` allocated once with the queue, so pushing
+//! does not touch the heap and the queue holds at most `N - 1` values. The
+//! pool's free list is a Treiber stack whose head carries a tag that is
+//! bumped on every update, so a producer holding a stale view of the list
+//! cannot pop a node that was reused in the meantime (the ABA problem).
+
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{
+        AtomicBool,
+        AtomicPtr,
+        AtomicU32,
+        AtomicU64,
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// Free list index marking the end of the list
+const NIL: u32 = u32::MAX;
+
+/// Queue node
+pub struct Node<T> {
+    /// Next node in the queue
+    next: AtomicPtr<Node<T>>,
+    /// Next node in the pool's free list
+    free_next: AtomicU32,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Fixed array of queue nodes with a lock-free free list
+pub struct NodePool<T, const N: usize> {
+    nodes: [Node<T>; N],
+    /// Index of the first free node in the low half, tag in the high half
+    free: AtomicU64,
+}
+
+/// Packs a free list index and its tag
+const fn pack(index: u32, tag: u32) -> u64 {
+    (tag as u64) << 32 | index as u64
+}
+
+/// Splits a free list head into index and tag
+const fn unpack(head: u64) -> (u32, u32) {
+    (head as u32, (head >> 32) as u32)
+}
+
+impl<T, const N: usize> NodePool<T, N> {
+    /// Creates a pool with all nodes free
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is 0 or does not fit the free list indices.
+    pub fn new() -> Self {
+        assert!(N > 0 && N < NIL as usize, "invalid node pool size");
+        Self {
+            nodes: core::array::from_fn(|i| Node {
+                next: AtomicPtr::new(ptr::null_mut()),
+                free_next: AtomicU32::new(if i + 1 < N { i as u32 + 1 } else { NIL }),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }),
+            free: AtomicU64::new(pack(0, 0)),
+        }
+    }
+
+    /// Takes a node off the free list
+    pub fn alloc(&self) -> Option<&Node<T>> {
+        let mut observed = self.free.load(Ordering::Acquire);
+        loop {
+            match self.try_alloc(observed) {
+                Ok(node) => return node,
+                Err(current) => observed = current,
+            }
+        }
+    }
+
+    /// One attempt to pop the free list, given the head seen as `observed`
+    ///
+    /// # Errors
+    ///
+    /// Returns the current head if it is no longer `observed`.
+    fn try_alloc(&self, observed: u64) -> Result<Option<&Node<T>>, u64> {
+        let (index, tag) = unpack(observed);
+        if index == NIL {
+            return Ok(None);
+        }
+        let node = &self.nodes[index as usize];
+        let next = node.free_next.load(Ordering::Acquire);
+        self.free
+            .compare_exchange(
+                observed,
+                pack(next, tag.wrapping_add(1)),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .map(|_| Some(node))
+    }
+
+    /// Returns `node` to the free list
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` does not belong to this pool.
+    pub fn release(&self, node: &Node<T>) {
+        let index = self.index_of(node);
+        let mut observed = self.free.load(Ordering::Acquire);
+        loop {
+            let (next, tag) = unpack(observed);
+            node.free_next.store(next, Ordering::Release);
+            match self.free.compare_exchange(
+                observed,
+                pack(index, tag.wrapping_add(1)),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(current) => observed = current,
+            }
+        }
+    }
+
+    /// Number of free nodes; racy while the pool is in use
+    pub fn free_count(&self) -> usize {
+        let mut count = 0;
+        let (mut index, _) = unpack(self.free.load(Ordering::Acquire));
+        while index != NIL && count < N {
+            count += 1;
+            index = self.nodes[index as usize].free_next.load(Ordering::Acquire);
+        }
+        count
+    }
+
+    fn index_of(&self, node: &Node<T>) -> u32 {
+        let offset = (node as *const Node<T> as usize).wrapping_sub(self.nodes.as_ptr() as usize);
+        let index = offset / size_of::<Node<T>>();
+        assert!(index < N, "node from another pool");
+        index as u32
+    }
+}
+
+impl<T, const N: usize> Default for NodePool<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lock-free bounded MPSC queue holding up to `N - 1` values
+pub struct MpscQueue<T, const N: usize> {
+    /// Dummy node; its successor holds the oldest value
+    head: AtomicPtr<Node<T>>,
+    /// Most recently pushed node
+    tail: AtomicPtr<Node<T>>,
+    /// Values pushed and not yet popped
+    len: AtomicUsize,
+    /// Set while a consumer is in `pop`
+    popping: AtomicBool,
+    /// Boxed so node addresses stay valid when the queue moves
+    pool: Box<NodePool<T, N>>,
+}
+
+impl<T, const N: usize> MpscQueue<T, N> {
+    /// Creates an empty queue, allocating its node pool
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is less than 2.
+    pub fn new() -> Self {
+        assert!(N >= 2, "queue needs a dummy node and one value node");
+        let pool = Box::new(NodePool::new());
+        let dummy = pool.alloc().expect("new pool is empty") as *const Node<T> as *mut Node<T>;
+        Self {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            len: AtomicUsize::new(0),
+            popping: AtomicBool::new(false),
+            pool,
+        }
+    }
+
+    /// Appends `value`
+    ///
+    /// # Errors
+    ///
+    /// Returns `value` back if the queue is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let Some(node) = self.pool.alloc() else {
+            return Err(value);
+        };
+        // SAFETY: the node is off the free list and not yet linked, so
+        // nothing else accesses it
+        unsafe { (*node.value.get()).write(value) };
+        node.next.store(ptr::null_mut(), Ordering::Relaxed);
+        // Counted before linking, so `pop` never sees a value it would
+        // count below zero
+        self.len.fetch_add(1, Ordering::AcqRel);
+
+        let node = node as *const Node<T> as *mut Node<T>;
+        let prev = self.tail.swap(node, Ordering::AcqRel);
+        // SAFETY: `prev` stays allocated until the consumer has seen its
+        // `next` pointer, which is only set here
+        unsafe { (*prev).next.store(node, Ordering::Release) };
+        Ok(())
+    }
+
+    /// Removes the oldest value
+    ///
+    /// Values whose push has not finished linking are not visible yet.
+    /// Only one consumer runs at a time; a concurrent call returns `None`.
+    pub fn pop(&self) -> Option<T> {
+        if self.popping.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        let value = self.pop_consumer();
+        self.popping.store(false, Ordering::Release);
+        value
+    }
+
+    /// `pop` for the sole consumer
+    fn pop_consumer(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        // SAFETY: `head` is the dummy node, owned by the consumer
+        let next = unsafe { (*head).next.load(Ordering::Acquire) };
+        if next.is_null() {
+            return None;
+        }
+        self.head.store(next, Ordering::Relaxed);
+        // SAFETY: `next` was fully initialized before being linked and
+        // becomes the new dummy, so its value is read exactly once
+        let value = unsafe { (*(*next).value.get()).assume_init_read() };
+        self.len.fetch_sub(1, Ordering::AcqRel);
+        // SAFETY: the old dummy is unreachable: its successor is linked, so
+        // no producer will write to it again
+        self.pool.release(unsafe { &*head });
+        Some(value)
+    }
+
+    /// Number of values queued
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if no values are queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of values the queue holds
+    pub const fn capacity(&self) -> usize {
+        N - 1
+    }
+}
+
+impl<T, const N: usize> Default for MpscQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for MpscQueue<T, N> {
+    fn drop(&mut self) {
+        while self.pop_consumer().is_some() {}
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for MpscQueue<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MpscQueue")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+// SAFETY: values move between CPUs but are only accessed by one side at a
+// time; nodes are shared through atomics
+unsafe impl<T: Send, const N: usize> Send for MpscQueue<T, N> {}
+// SAFETY: as above; `pop` admits one consumer at a time
+unsafe impl<T: Send, const N: usize> Sync for MpscQueue<T, N> {}
+// SAFETY: as for the queue
+unsafe impl<T: Send, const N: usize> Send for NodePool<T, N> {}
+// SAFETY: nodes are handed out to one owner at a time
+unsafe impl<T: Send, const N: usize> Sync for NodePool<T, N> {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::*;
+
+    #[test_case]
+    fn test_values_pop_in_push_order() {
+        let queue: MpscQueue<u32, 8> = MpscQueue::new();
+        assert_eq!(queue.capacity(), 7);
+        for i in 0..7 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.push(7), Err(7));
+        assert_eq!(queue.len(), 7);
+
+        for i in 0..7 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test_case]
+    fn test_nodes_are_reused() {
+        let queue: MpscQueue<u64, 4> = MpscQueue::new();
+        for i in 0..1000 {
+            queue.push(i).unwrap();
+            queue.push(i + 1).unwrap();
+            assert_eq!(queue.pop(), Some(i));
+            assert_eq!(queue.pop(), Some(i + 1));
+        }
+        assert_eq!(queue.pool.free_count(), 3);
+    }
+
+    #[test_case]
+    fn test_interleaved_producers_keep_order() {
+        // Two producers push in lock step, as if preempted between each
+        // other's pushes; every producer's values stay in order
+        let queue: MpscQueue<(u8, u32), 16> = MpscQueue::new();
+        let mut expected = [0u32; 2];
+        for round in 0..50 {
+            for producer in 0..2 {
+                queue.push((producer, round)).unwrap();
+            }
+            if round % 3 == 2 {
+                while let Some((producer, value)) = queue.pop() {
+                    assert_eq!(value, expected[producer as usize]);
+                    expected[producer as usize] += 1;
+                }
+            }
+        }
+        while let Some((producer, value)) = queue.pop() {
+            assert_eq!(value, expected[producer as usize]);
+            expected[producer as usize] += 1;
+        }
+        assert_eq!(expected, [50, 50]);
+    }
+
+    #[test_case]
+    fn test_stale_free_list_head_is_rejected() {
+        let pool: NodePool<u32, 4> = NodePool::new();
+        // A producer reads the free list head, then is preempted
+        let stale = pool.free.load(Ordering::Acquire);
+
+        // Others pop two nodes and return the first, so the same index
+        // is at the head again, now followed by a different node
+        let a = pool.alloc().unwrap();
+        let b = pool.alloc().unwrap();
+        pool.release(a);
+        assert_eq!(unpack(stale).0, unpack(pool.free.load(Ordering::Acquire)).0);
+
+        // Without the tag this would succeed and hand out `b` twice
+        assert!(pool.try_alloc(stale).is_err());
+        assert_eq!(pool.free_count(), 3);
+        pool.release(b);
+        assert_eq!(pool.free_count(), 4);
+    }
+
+    #[test_case]
+    fn test_remaining_values_are_dropped() {
+        let value = Arc::new(());
+        {
+            let queue: MpscQueue<Arc<()>, 4> = MpscQueue::new();
+            queue.push(value.clone()).unwrap();
+            queue.push(value.clone()).unwrap();
+            assert_eq!(Arc::strong_count(&value), 3);
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reader-writer spinlock
+//!
+//! `KernelRwLock` lets any number of readers in at once, while a writer
+//! gets exclusive access. Readers only ever wait for a writer that holds
+//! the lock, never for one that is merely waiting, so a read guard can be
+//! taken in an interrupt handler even if the interrupted code holds one.
+//! The flip side is that a steady stream of readers can starve writers.
+//!
+//! Neither side sleeps; both spin. As with `spin::Mutex`, a writer must
+//! not be interrupted by a handler that takes the same lock, so writers
+//! shared with interrupt handlers hold the lock with interrupts disabled.
+
+use core::{
+    cell::UnsafeCell,
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    sync::atomic::{
+        AtomicBool,
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// Reader-writer spinlock
+pub struct KernelRwLock<T: ?Sized> {
+    /// Number of read guards alive or being taken
+    readers: AtomicUsize,
+    /// Set while a write guard is alive or being taken
+    writer: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+/// Shared access to the value of a `KernelRwLock`
+pub struct ReadGuard<'a, T: ?Sized> {
+    lock: &'a KernelRwLock<T>,
+}
+
+/// Exclusive access to the value of a `KernelRwLock`
+pub struct WriteGuard<'a, T: ?Sized> {
+    lock: &'a KernelRwLock<T>,
+}
+
+impl<T> KernelRwLock<T> {
+    /// Creates an unlocked lock holding `value`
+    pub const fn new(value: T) -> Self {
+        Self {
+            readers: AtomicUsize::new(0),
+            writer: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consumes the lock and returns the value
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> KernelRwLock<T> {
+    /// Takes a read guard, spinning while a writer holds the lock
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            while self.writer.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Takes a read guard if no writer holds the lock
+    pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+        // Announce the reader before checking for a writer; the writer does
+        // the same the other way round, so one of them always backs off
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        if self.writer.load(Ordering::SeqCst) {
+            self.readers.fetch_sub(1, Ordering::Release);
+            return None;
+        }
+        Some(ReadGuard { lock: self })
+    }
+
+    /// Takes the write guard, spinning until there are no readers or writer
+    pub fn write(&self) -> WriteGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            while self.writer.load(Ordering::Relaxed) || self.readers.load(Ordering::Relaxed) > 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Takes the write guard if the lock is free
+    pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+        if self
+            .writer
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+        if self.readers.load(Ordering::SeqCst) > 0 {
+            self.writer.store(false, Ordering::Release);
+            return None;
+        }
+        Some(WriteGuard { lock: self })
+    }
+
+    /// Number of read guards currently held
+    pub fn reader_count(&self) -> usize {
+        self.readers.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if a writer holds the lock
+    pub fn is_write_locked(&self) -> bool {
+        self.writer.load(Ordering::Relaxed)
+    }
+
+    /// Returns a mutable reference to the value; no locking is needed
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+// SAFETY: readers on several CPUs share `&T`, so `T` must be `Sync`; the
+// writer gets `&mut T`, so `T` must be `Send`
+unsafe impl<T: ?Sized + Send + Sync> Sync for KernelRwLock<T> {}
+// SAFETY: the lock owns its value
+unsafe impl<T: ?Sized + Send> Send for KernelRwLock<T> {}
+
+impl<T: Default> Default for KernelRwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> Deref for ReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: no writer holds the lock while the guard lives
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.readers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T: ?Sized> Deref for WriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard has exclusive access
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard has exclusive access
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.writer.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_readers_do_not_block_each_other() {
+        let lock = KernelRwLock::new(7);
+        let first = lock.read();
+        let second = lock.read();
+        let third = lock.read();
+        assert_eq!(lock.reader_count(), 3);
+        assert_eq!(*first + *second + *third, 21);
+    }
+
+    #[test_case]
+    fn test_writer_waits_for_all_readers() {
+        let lock = KernelRwLock::new(0);
+        let first = lock.read();
+        let second = lock.read();
+        assert!(lock.try_write().is_none());
+
+        drop(first);
+        assert!(lock.try_write().is_none());
+        drop(second);
+
+        *lock.write() += 1;
+        assert_eq!(*lock.read(), 1);
+        assert_eq!(lock.reader_count(), 0);
+    }
+
+    #[test_case]
+    fn test_readers_wait_for_writer() {
+        let lock = KernelRwLock::new(0);
+        let mut writer = lock.write();
+        assert!(lock.is_write_locked());
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        // A failed attempt leaves no reader behind
+        assert_eq!(lock.reader_count(), 0);
+
+        *writer = 5;
+        drop(writer);
+        assert_eq!(*lock.try_read().unwrap(), 5);
+    }
+}
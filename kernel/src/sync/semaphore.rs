@@ -0,0 +1,259 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Counting semaphore
+//!
+//! Follows POSIX semantics: `wait` takes a unit if one is available and
+//! otherwise blocks the process until `signal` hands it one. While
+//! processes wait the count is negative, its magnitude being the number of
+//! waiters. Waiters are woken in FIFO order.
+//!
+//! `wait` and `signal` only update process states, like the functions in
+//! `process::ipc`; `acquire` and `release` add the scheduling around them
+//! for the running process.
+
+use alloc::collections::VecDeque;
+use core::sync::atomic::{
+    AtomicI64,
+    Ordering,
+};
+
+use spin::Mutex;
+
+use crate::process::{
+    ProcessError,
+    ProcessId,
+    ProcessState,
+    ProcessTable,
+    SCHEDULER,
+};
+
+/// Counting semaphore for processes
+pub struct Semaphore {
+    count: AtomicI64,
+    waiters: Mutex<VecDeque<ProcessId>>,
+}
+
+impl Semaphore {
+    /// Creates a semaphore with `initial` available units
+    pub const fn new(initial: i64) -> Self {
+        Self {
+            count: AtomicI64::new(initial),
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Takes a unit for `pid`
+    ///
+    /// # Returns
+    ///
+    /// `true` if a unit was available. Otherwise `pid` is marked `Blocked`
+    /// and queued; the unit is handed over by `signal`, which makes it
+    /// ready again, so it must not wait a second time.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProcessError::NotFound` if `pid` is not in `table`; the
+    /// count is left unchanged.
+    pub fn wait(&self, pid: ProcessId, table: &mut ProcessTable) -> Result<bool, ProcessError> {
+        // Updating the count under the waiter lock keeps a concurrent
+        // `signal` from missing a waiter about to be queued
+        let mut waiters = self.waiters.lock();
+        if self.count.fetch_sub(1, Ordering::AcqRel) > 0 {
+            return Ok(true);
+        }
+        if let Err(e) = table.mark_blocked(pid) {
+            self.count.fetch_add(1, Ordering::AcqRel);
+            return Err(e);
+        }
+        waiters.push_back(pid);
+        Ok(false)
+    }
+
+    /// Takes a unit if one is available, without blocking
+    pub fn try_wait(&self) -> bool {
+        let _waiters = self.waiters.lock();
+        if self.count.load(Ordering::Acquire) <= 0 {
+            return false;
+        }
+        self.count.fetch_sub(1, Ordering::AcqRel);
+        true
+    }
+
+    /// Returns a unit, handing it to the first waiter if there is one
+    ///
+    /// Waiters that no longer exist or were woken otherwise are skipped.
+    ///
+    /// # Returns
+    ///
+    /// The PID of the process made ready, if any.
+    pub fn signal(&self, table: &mut ProcessTable) -> Option<ProcessId> {
+        let mut waiters = self.waiters.lock();
+        self.count.fetch_add(1, Ordering::AcqRel);
+        while let Some(pid) = waiters.pop_front() {
+            let blocked = table
+                .get(pid)
+                .is_some_and(|p| p.state() == ProcessState::Blocked);
+            if blocked && table.mark_ready(pid).is_ok() {
+                return Some(pid);
+            }
+            // The waiter is gone; drop its claim on a unit
+            self.count.fetch_add(1, Ordering::AcqRel);
+        }
+        None
+    }
+
+    /// Current count: available units, or minus the number of waiters
+    pub fn value(&self) -> i64 {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Number of processes waiting
+    pub fn waiters(&self) -> usize {
+        self.waiters.lock().len()
+    }
+
+    /// Takes a unit for the running process, blocking until one is free
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no running process.
+    pub fn acquire(&self) {
+        crate::interrupts::without_interrupts(|| {
+            let switch = {
+                let mut scheduler = SCHEDULER.lock();
+                let pid = scheduler
+                    .current()
+                    .expect("semaphore wait without a running process");
+                if self.wait(pid, scheduler.table_mut()) == Ok(true) {
+                    return;
+                }
+                scheduler.schedule()
+            };
+            if let Some(switch) = switch {
+                // SAFETY: interrupts are disabled, so the table cannot change
+                // before the switch.
+                unsafe { switch.perform() };
+            }
+        })
+    }
+
+    /// Returns a unit from the running process, waking the first waiter
+    pub fn release(&self) {
+        crate::interrupts::without_interrupts(|| {
+            let mut scheduler = SCHEDULER.lock();
+            self.signal(scheduler.table_mut());
+            scheduler.requeue_ready();
+        })
+    }
+}
+
+impl core::fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("count", &self.value())
+            .field("waiters", &self.waiters())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::Process;
+
+    fn table_with(count: usize) -> ProcessTable {
+        let mut table = ProcessTable::new();
+        for _ in 0..count {
+            let pid = table.allocate_pid().unwrap();
+            table.add_process(Process::new(pid, "p")).unwrap();
+        }
+        table
+    }
+
+    fn state(table: &ProcessTable, pid: ProcessId) -> ProcessState {
+        table.get(pid).unwrap().state()
+    }
+
+    #[test_case]
+    fn test_wait_and_signal_interleaved() {
+        let mut table = table_with(2);
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+        let sem = Semaphore::new(1);
+
+        // A takes the only unit, B has to wait
+        assert_eq!(sem.wait(a, &mut table), Ok(true));
+        assert_eq!(sem.value(), 0);
+        assert_eq!(sem.wait(b, &mut table), Ok(false));
+        assert_eq!(state(&table, b), ProcessState::Blocked);
+        assert_eq!(sem.value(), -1);
+        assert!(!sem.try_wait());
+
+        // A's signal hands the unit to B instead of making it available
+        assert_eq!(sem.signal(&mut table), Some(b));
+        assert_eq!(state(&table, b), ProcessState::Ready);
+        assert_eq!(sem.value(), 0);
+        assert!(!sem.try_wait());
+
+        // B's signal has no one to wake
+        assert_eq!(sem.signal(&mut table), None);
+        assert_eq!(sem.value(), 1);
+        assert!(sem.try_wait());
+        assert_eq!(sem.value(), 0);
+    }
+
+    #[test_case]
+    fn test_waiters_are_woken_in_fifo_order() {
+        let mut table = table_with(2);
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+        let sem = Semaphore::new(0);
+
+        assert_eq!(sem.wait(a, &mut table), Ok(false));
+        assert_eq!(sem.wait(b, &mut table), Ok(false));
+        assert_eq!(sem.value(), -2);
+        assert_eq!(sem.waiters(), 2);
+
+        assert_eq!(sem.signal(&mut table), Some(a));
+        assert_eq!(state(&table, b), ProcessState::Blocked);
+        assert_eq!(sem.signal(&mut table), Some(b));
+        assert_eq!(sem.value(), 0);
+    }
+
+    #[test_case]
+    fn test_signal_skips_vanished_waiter() {
+        let mut table = table_with(2);
+        let (a, b) = (ProcessId::new(1), ProcessId::new(2));
+        let sem = Semaphore::new(0);
+
+        sem.wait(a, &mut table).unwrap();
+        sem.wait(b, &mut table).unwrap();
+        table.remove(a);
+
+        assert_eq!(sem.signal(&mut table), Some(b));
+        assert_eq!(sem.value(), 0);
+        assert_eq!(sem.waiters(), 0);
+    }
+
+    #[test_case]
+    fn test_wait_for_unknown_process_fails() {
+        let mut table = table_with(1);
+        let sem = Semaphore::new(0);
+        assert_eq!(
+            sem.wait(ProcessId::new(9), &mut table),
+            Err(ProcessError::NotFound)
+        );
+        assert_eq!(sem.value(), 0);
+        assert_eq!(sem.waiters(), 0);
+    }
+}
@@ -16,8 +16,37 @@
 //!
 //! This module provides infrastructure for running tests in QEMU with
 //! programmatic exit codes.
+//!
+//! It also holds the boot self-test: a handful of quick checks of
+//! fundamental kernel state that `kernel_main` runs on every boot, release
+//! builds included.
+
+use alloc::boxed::Box;
+use core::{
+    fmt::{
+        self,
+        Write,
+    },
+    panic::PanicInfo,
+};
+
+use crate::{
+    interrupts::{
+        gdt,
+        pic,
+        pit,
+        timer,
+    },
+    memory::VirtAddr,
+};
+
+/// Longest time `check_timer_ticks` waits for a tick, in PIT waits of
+/// `TICK_WAIT_STEP_MS`
+const TICK_WAIT_STEPS: u32 = 10;
+const TICK_WAIT_STEP_MS: u32 = 10;
 
-use core::panic::PanicInfo;
+/// Capacity of `MessageBuffer`, in bytes
+const MESSAGE_BUFFER_SIZE: usize = 256;
 
 /// Exit codes for QEMU isa-debug-exit device
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -36,6 +65,7 @@ pub enum QemuExitCode {
 pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
     use crate::interrupts::port::Port;
 
+    // SAFETY: the isa-debug-exit device only exits QEMU
     unsafe {
         let mut port = Port::<u32>::new(0xf4);
         port.write(exit_code as u32);
@@ -43,6 +73,7 @@ pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
 
     // If QEMU exit fails, halt forever
     loop {
+        // SAFETY: halting only waits for the next interrupt
         unsafe {
             core::arch::asm!("hlt");
         }
@@ -78,11 +109,170 @@ pub fn test_runner(tests: &[&dyn Testable]) {
 
 /// Panic handler for test mode
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    crate::panic::set_panicking();
     crate::serial_println!("[FAILED]");
+    crate::serial_println!("Boot phase: {}", crate::boot::boot_phase());
     crate::serial_println!("Error: {}\n", info);
     exit_qemu(QemuExitCode::Failed);
 }
 
+/// Fixed-size buffer a panic message is formatted into
+///
+/// Integration tests whose panic handler checks the message use it instead
+/// of a `String`, so the check does not depend on the heap. Text beyond
+/// `MESSAGE_BUFFER_SIZE` bytes is dropped.
+#[derive(Debug)]
+pub struct MessageBuffer {
+    bytes: [u8; MESSAGE_BUFFER_SIZE],
+    len: usize,
+}
+
+impl MessageBuffer {
+    /// Creates an empty buffer
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; MESSAGE_BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Formats the message of `info` into a new buffer
+    pub fn from_panic(info: &PanicInfo) -> Self {
+        let mut buffer = Self::new();
+        let _ = write!(buffer, "{}", info.message());
+        buffer
+    }
+
+    /// Returns the text written so far
+    ///
+    /// Empty if truncation split a multi-byte character.
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Default for MessageBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// A boot self-test check
+#[derive(Debug, Clone, Copy)]
+pub struct SelfTest {
+    pub name: &'static str,
+    /// Returns whether the check passed
+    pub run: fn() -> bool,
+}
+
+/// Outcome of a self-test run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTestResult {
+    pub passed: u32,
+    pub failed: u32,
+    pub name_of_first_failure: Option<&'static str>,
+}
+
+/// Checks run by `run_selftest`
+///
+/// They need the heap, interrupts and the timer to be set up.
+pub const SELF_TESTS: &[SelfTest] = &[
+    SelfTest {
+        name: "boot phases in order",
+        run: crate::boot::phases_reached_in_order,
+    },
+    SelfTest {
+        name: "heap round-trip",
+        run: check_heap_round_trip,
+    },
+    SelfTest {
+        name: "canonical VirtAddr",
+        run: check_canonical_virt_addr,
+    },
+    SelfTest {
+        name: "GDT loaded",
+        run: gdt::is_loaded,
+    },
+    SelfTest {
+        name: "IDT loaded",
+        run: crate::interrupts::idt_is_loaded,
+    },
+    SelfTest {
+        name: "PIC masks set",
+        run: check_pic_masks,
+    },
+    SelfTest {
+        name: "timer ticking",
+        run: check_timer_ticks,
+    },
+];
+
+/// Runs the boot self-test
+pub fn run_selftest() -> SelfTestResult {
+    run_selftests(SELF_TESTS)
+}
+
+/// Runs `tests` in order, logging each failure
+pub fn run_selftests(tests: &[SelfTest]) -> SelfTestResult {
+    let mut result = SelfTestResult {
+        passed: 0,
+        failed: 0,
+        name_of_first_failure: None,
+    };
+    for test in tests {
+        if (test.run)() {
+            result.passed += 1;
+        } else {
+            crate::log_error!("Self-test failed: {}", test.name);
+            result.failed += 1;
+            result.name_of_first_failure.get_or_insert(test.name);
+        }
+    }
+    result
+}
+
+/// Allocates a block, fills it and reads it back
+fn check_heap_round_trip() -> bool {
+    let block = Box::new(core::array::from_fn::<u64, 64, _>(|i| i as u64 * 0x0101));
+    core::hint::black_box(&block)
+        .iter()
+        .enumerate()
+        .all(|(i, &word)| word == i as u64 * 0x0101)
+}
+
+/// Checks that `VirtAddr` sign-extends bit 47
+fn check_canonical_virt_addr() -> bool {
+    VirtAddr::new(0x0000_8000_0000_0000).as_u64() == 0xffff_8000_0000_0000
+        && VirtAddr::new(0xffff_7fff_ffff_ffff).as_u64() == 0x0000_7fff_ffff_ffff
+        && VirtAddr::new(0x1000).as_u64() == 0x1000
+}
+
+/// Checks that both PICs have lines masked, as they do once set up
+fn check_pic_masks() -> bool {
+    pic::PICS.lock().masks().iter().all(|&mask| mask != 0)
+}
+
+/// Waits briefly for the timer to tick
+fn check_timer_ticks() -> bool {
+    let start = timer::ticks();
+    for _ in 0..TICK_WAIT_STEPS {
+        pit::busy_wait_ms(TICK_WAIT_STEP_MS);
+        if timer::ticks() != start {
+            return true;
+        }
+    }
+    false
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,4 +287,51 @@ mod tests {
         assert_eq!(QemuExitCode::Success as u32, 0x10);
         assert_eq!(QemuExitCode::Failed as u32, 0x11);
     }
+
+    #[test_case]
+    fn test_selftest_reports_first_failure() {
+        let tests = [
+            SelfTest {
+                name: "passes",
+                run: || true,
+            },
+            SelfTest {
+                name: "known failure",
+                run: || false,
+            },
+            SelfTest {
+                name: "later failure",
+                run: || false,
+            },
+        ];
+        assert_eq!(run_selftests(&tests), SelfTestResult {
+            passed: 1,
+            failed: 2,
+            name_of_first_failure: Some("known failure"),
+        });
+    }
+
+    #[test_case]
+    fn test_message_buffer_truncates() {
+        let mut buffer = MessageBuffer::new();
+        write!(buffer, "KASAN: {}", "heap overflow").unwrap();
+        assert_eq!(buffer.as_str(), "KASAN: heap overflow");
+
+        for _ in 0..MESSAGE_BUFFER_SIZE {
+            buffer.write_str("x").unwrap();
+        }
+        assert_eq!(buffer.as_str().len(), MESSAGE_BUFFER_SIZE);
+        assert!(buffer.as_str().starts_with("KASAN: heap overflow"));
+    }
+
+    #[test_case]
+    fn test_boot_checks() {
+        // The PIC and the timer are not set up in the test kernel, which
+        // stops at `BootPhase::IdtReady`
+        assert!(crate::boot::phases_reached_in_order());
+        assert!(check_heap_round_trip());
+        assert!(check_canonical_virt_addr());
+        assert!(gdt::is_loaded());
+        assert!(crate::interrupts::idt_is_loaded());
+    }
 }
@@ -0,0 +1,264 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! High Precision Event Timer (HPET) driver
+//!
+//! The HPET has a free-running 64-bit main counter ticking at a fixed
+//! rate of at least 10 MHz, which gives timestamps with sub-microsecond
+//! resolution. Its register block is described by the ACPI HPET table;
+//! without one, the address QEMU uses is tried instead.
+
+use spin::Once;
+
+use crate::memory::{
+    HeapFrameAllocator,
+    PageTableManager,
+    PhysAddr,
+    PhysFrame,
+};
+
+/// Address of the HPET registers on QEMU's PC machines
+pub const QEMU_HPET_BASE: u64 = 0xfed0_0000;
+
+/// Signature of the ACPI HPET table
+const ACPI_SIGNATURE: &[u8; 4] = b"HPET";
+/// Offset of the base address in the ACPI HPET table's address structure
+const ACPI_BASE_ADDRESS_OFFSET: usize = 44;
+/// Address space ID of system memory in an ACPI generic address
+const ACPI_SYSTEM_MEMORY: u8 = 0;
+
+/// Register offsets from the HPET base
+const REG_CAPABILITIES: usize = 0x00;
+const REG_CONFIG: usize = 0x10;
+const REG_MAIN_COUNTER: usize = 0xf0;
+
+/// Register offsets of comparator `n`
+const fn reg_timer_config(n: usize) -> usize {
+    0x100 + 0x20 * n
+}
+const fn reg_timer_comparator(n: usize) -> usize {
+    0x108 + 0x20 * n
+}
+const fn reg_timer_fsb_route(n: usize) -> usize {
+    0x110 + 0x20 * n
+}
+
+/// Counter enable bit in the general configuration register
+const CONFIG_ENABLE: u64 = 1 << 0;
+
+/// Bits in a comparator's configuration register
+const TIMER_INT_ENABLE: u64 = 1 << 2;
+const TIMER_PERIODIC: u64 = 1 << 3;
+const TIMER_PERIODIC_CAP: u64 = 1 << 4;
+const TIMER_VAL_SET: u64 = 1 << 6;
+const TIMER_FSB_ENABLE: u64 = 1 << 14;
+const TIMER_FSB_CAP: u64 = 1 << 15;
+
+/// Longest counter period the specification allows (100 ns)
+const MAX_PERIOD_FS: u64 = 100_000_000;
+/// Femtoseconds per nanosecond
+const FS_PER_NS: u128 = 1_000_000;
+
+/// Result of `init`: the HPET, if one was found
+static HPET: Once<Option<Hpet>> = Once::new();
+
+/// Memory-mapped HPET
+#[derive(Debug)]
+pub struct Hpet {
+    base: u64,
+    /// Main counter period in femtoseconds
+    period_fs: u64,
+}
+
+impl Hpet {
+    /// Sets up the HPET at `base` and starts its main counter
+    ///
+    /// The register page is identity-mapped as uncached memory first.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the page cannot be mapped or the registers do not report
+    /// a valid counter period, meaning there is no HPET at `base`
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the physical address of an HPET or of unused
+    /// address space, since its registers are read and written.
+    pub unsafe fn new(base: u64) -> Option<Self> {
+        PageTableManager::current()
+            .identity_map_mmio(
+                PhysFrame::containing_address(PhysAddr::new(base)),
+                &mut HeapFrameAllocator::new(),
+            )
+            .ok()?;
+
+        let mut hpet = Self { base, period_fs: 0 };
+        hpet.period_fs = hpet.read(REG_CAPABILITIES) >> 32;
+        if hpet.period_fs == 0 || hpet.period_fs > MAX_PERIOD_FS {
+            return None;
+        }
+
+        let config = hpet.read(REG_CONFIG);
+        hpet.write(REG_CONFIG, config | CONFIG_ENABLE);
+        Some(hpet)
+    }
+
+    /// Main counter period in femtoseconds
+    pub fn period_fs(&self) -> u64 {
+        self.period_fs
+    }
+
+    /// Reads the main counter
+    pub fn counter(&self) -> u64 {
+        self.read(REG_MAIN_COUNTER)
+    }
+
+    /// Nanoseconds since the main counter was started
+    pub fn nanos(&self) -> u64 {
+        (u128::from(self.counter()) * u128::from(self.period_fs) / FS_PER_NS) as u64
+    }
+
+    /// Number of comparators
+    pub fn timer_count(&self) -> usize {
+        ((self.read(REG_CAPABILITIES) >> 8) & 0x1f) as usize + 1
+    }
+
+    /// Starts a periodic comparator that raises the message-signalled
+    /// interrupt `data` at `address` every `period_ns`
+    ///
+    /// Uses the first comparator capable of both periodic mode and direct
+    /// FSB (MSI) delivery.
+    ///
+    /// # Returns
+    ///
+    /// The comparator used, or `None` if none supports it
+    pub fn start_periodic_msi(&self, period_ns: u64, address: u32, data: u32) -> Option<usize> {
+        let timer = (0..self.timer_count()).find(|&n| {
+            let config = self.read(reg_timer_config(n));
+            config & TIMER_PERIODIC_CAP != 0 && config & TIMER_FSB_CAP != 0
+        })?;
+        let period = (u128::from(period_ns) * FS_PER_NS / u128::from(self.period_fs)).max(1) as u64;
+
+        self.write(
+            reg_timer_fsb_route(timer),
+            u64::from(address) << 32 | u64::from(data),
+        );
+        let config = self.read(reg_timer_config(timer));
+        self.write(
+            reg_timer_config(timer),
+            config | TIMER_FSB_ENABLE | TIMER_PERIODIC | TIMER_VAL_SET,
+        );
+        // With TIMER_VAL_SET, the first write sets the next deadline and
+        // the second the period
+        self.write(reg_timer_comparator(timer), self.counter() + period);
+        self.write(reg_timer_comparator(timer), period);
+        self.write(
+            reg_timer_config(timer),
+            self.read(reg_timer_config(timer)) | TIMER_INT_ENABLE,
+        );
+        Some(timer)
+    }
+
+    fn read(&self, reg: usize) -> u64 {
+        // SAFETY: the register page is mapped and `reg` is a register offset
+        unsafe { core::ptr::read_volatile((self.base as usize + reg) as *const u64) }
+    }
+
+    fn write(&self, reg: usize, value: u64) {
+        // SAFETY: the register page is mapped and `reg` is a register offset
+        unsafe { core::ptr::write_volatile((self.base as usize + reg) as *mut u64, value) }
+    }
+}
+
+/// Extracts the register base address from an ACPI HPET table
+///
+/// # Returns
+///
+/// `None` if `table` is not an HPET table or the registers are not in
+/// system memory
+pub fn base_from_acpi_table(table: &[u8]) -> Option<u64> {
+    if table.get(..4)? != ACPI_SIGNATURE {
+        return None;
+    }
+    // The generic address structure starts 4 bytes before the address
+    if *table.get(ACPI_BASE_ADDRESS_OFFSET - 4)? != ACPI_SYSTEM_MEMORY {
+        return None;
+    }
+    let raw = table.get(ACPI_BASE_ADDRESS_OFFSET..ACPI_BASE_ADDRESS_OFFSET + 8)?;
+    Some(u64::from_le_bytes(raw.try_into().ok()?))
+}
+
+/// Finds and enables the HPET
+///
+/// The base address is taken from `acpi_table`, the ACPI HPET table, if
+/// given; otherwise `QEMU_HPET_BASE` is tried. Requires the heap for new
+/// page tables.
+///
+/// # Returns
+///
+/// `true` if an HPET was found
+pub fn init(acpi_table: Option<&[u8]>) -> bool {
+    let base = acpi_table
+        .and_then(base_from_acpi_table)
+        .unwrap_or(QEMU_HPET_BASE);
+
+    // SAFETY: the address comes from ACPI or is QEMU's fixed HPET address
+    let hpet = HPET.call_once(|| unsafe { Hpet::new(base) });
+    match hpet {
+        Some(hpet) => {
+            crate::log_debug!("HPET at {:#x}, period {} fs", hpet.base, hpet.period_fs);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns `true` if `init` found an HPET
+pub fn is_available() -> bool {
+    matches!(HPET.get(), Some(Some(_)))
+}
+
+/// Returns the HPET found by `init`
+pub fn hpet() -> Option<&'static Hpet> {
+    HPET.get().and_then(Option::as_ref)
+}
+
+/// Nanoseconds since the HPET was enabled, or 0 without an HPET
+pub fn hpet_nanos() -> u64 {
+    match HPET.get() {
+        Some(Some(hpet)) => hpet.nanos(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_base_from_acpi_table() {
+        let mut table = [0u8; 56];
+        table[..4].copy_from_slice(b"HPET");
+        table[ACPI_BASE_ADDRESS_OFFSET..ACPI_BASE_ADDRESS_OFFSET + 8]
+            .copy_from_slice(&0xfed0_0000u64.to_le_bytes());
+        assert_eq!(base_from_acpi_table(&table), Some(0xfed0_0000));
+
+        // Registers in I/O space are not supported
+        table[ACPI_BASE_ADDRESS_OFFSET - 4] = 1;
+        assert_eq!(base_from_acpi_table(&table), None);
+
+        assert_eq!(base_from_acpi_table(b"APIC"), None);
+        assert_eq!(base_from_acpi_table(&table[..40]), None);
+    }
+}
@@ -0,0 +1,343 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time management subsystem
+//!
+//! This module provides time-related functionality including
+//! system uptime, timestamps, and time utilities. Nanosecond timestamps
+//! come from the HPET when one is available; `tsc` times short intervals
+//! in CPU cycles, which `profiler` uses to time the boot stages.
+//!
+//! The delay functions work with interrupts disabled: without timer ticks
+//! they fall back to waiting on PIT channel 2.
+
+#![allow(dead_code)]
+
+pub mod hpet;
+pub mod periodic;
+pub mod profiler;
+pub mod rtc;
+pub mod timer_wheel;
+pub mod tsc;
+
+use crate::{
+    interrupts::{
+        self,
+        pit,
+        timer,
+    },
+    process::{
+        CapabilityError,
+        CapabilityRights,
+        CapabilitySet,
+        CapabilityType,
+        TIMER_OBJECT_ID,
+    },
+};
+
+/// Nanoseconds per millisecond
+const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// Initializes the time subsystem
+///
+/// Calibrates the TSC against the PIT, which takes about 50 ms.
+pub fn init() {
+    let khz = tsc::calibrate();
+    crate::log_info!("TSC calibrated at {} kHz", khz);
+}
+
+/// Returns the current tick count
+///
+/// Each tick represents one timer interrupt. The frequency is determined
+/// by the PIT configuration (typically 100 Hz).
+///
+/// # Returns
+///
+/// The number of timer ticks since the system started.
+///
+/// # Example
+///
+/// ```
+/// let current_ticks = time::ticks();
+/// println!("Ticks: {}", current_ticks);
+/// ```
+pub fn ticks() -> u64 {
+    timer::ticks()
+}
+
+/// Returns the system uptime in milliseconds
+///
+/// # Returns
+///
+/// System uptime in milliseconds since boot.
+///
+/// # Example
+///
+/// ```
+/// let uptime = time::uptime_ms();
+/// println!("Uptime: {} ms", uptime);
+/// ```
+pub fn uptime_ms() -> u64 {
+    timer::uptime_ms()
+}
+
+/// Returns the system uptime in seconds
+///
+/// # Returns
+///
+/// System uptime in seconds since boot.
+///
+/// # Example
+///
+/// ```
+/// let uptime = time::uptime_seconds();
+/// println!("Uptime: {} seconds", uptime);
+/// ```
+pub fn uptime_seconds() -> u64 {
+    timer::uptime_seconds()
+}
+
+/// Waits for at least `micros` microseconds
+///
+/// Timed by a PIT one-shot, so it needs neither the timer nor interrupts
+/// and can be used early in boot and in interrupt handlers.
+///
+/// Interrupts are disabled while the PIT counts, for up to 54.9 ms at a
+/// time; longer delays are split into such chunks, with interrupts
+/// restored in between. Timer ticks are delayed accordingly, so keep
+/// delays short where latency matters.
+pub fn udelay(micros: u64) {
+    pit::Pit::new().one_shot(micros);
+}
+
+/// Waits for `n` timer ticks
+///
+/// Halts between ticks. With interrupts disabled, ticks do not advance, so
+/// the equivalent time is spun away with `udelay` instead.
+pub fn sleep_ticks(n: u64) {
+    if n == 0 {
+        return;
+    }
+    if !interrupts::are_enabled() {
+        udelay(n * 1_000_000 / u64::from(timer::TIMER_FREQUENCY));
+        return;
+    }
+
+    let target = ticks() + n;
+    while ticks() < target {
+        // SAFETY: interrupts are enabled, so the next timer tick ends the
+        // halt.
+        unsafe { core::arch::asm!("hlt", options(nomem, nostack)) };
+    }
+}
+
+/// Waits for at least `ms` milliseconds on behalf of a process holding
+/// `caps`
+///
+/// Halts until the uptime passes the target. With interrupts disabled it
+/// spins with `udelay` instead.
+///
+/// # Errors
+///
+/// Returns `CapabilityError::PermissionDenied` unless `caps` holds a
+/// `Timer` capability with the `SLEEP` right.
+pub fn sleep_ms(caps: &CapabilitySet, ms: u64) -> Result<(), CapabilityError> {
+    caps.require(
+        CapabilityType::Timer,
+        TIMER_OBJECT_ID,
+        CapabilityRights::SLEEP,
+    )
+    .map_err(|_| CapabilityError::PermissionDenied)?;
+
+    if !interrupts::are_enabled() {
+        udelay(ms * 1000);
+        return Ok(());
+    }
+
+    let target = uptime_ms() + ms;
+    while uptime_ms() <= target {
+        // SAFETY: interrupts are enabled, so the next timer tick ends the
+        // halt.
+        unsafe { core::arch::asm!("hlt", options(nomem, nostack)) };
+    }
+    Ok(())
+}
+
+/// Time duration with nanosecond precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration {
+    nanos: u64,
+}
+
+impl Duration {
+    /// Creates a new Duration from nanoseconds
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Self { nanos }
+    }
+
+    /// Creates a new Duration from milliseconds
+    pub const fn from_millis(millis: u64) -> Self {
+        Self {
+            nanos: millis * NANOS_PER_MILLI,
+        }
+    }
+
+    /// Creates a new Duration from seconds
+    pub const fn from_secs(secs: u64) -> Self {
+        Self::from_millis(secs * 1000)
+    }
+
+    /// Returns the duration in nanoseconds
+    pub const fn as_nanos(&self) -> u64 {
+        self.nanos
+    }
+
+    /// Returns the duration in milliseconds
+    pub const fn as_millis(&self) -> u64 {
+        self.nanos / NANOS_PER_MILLI
+    }
+
+    /// Returns the duration in seconds
+    pub const fn as_secs(&self) -> u64 {
+        self.as_millis() / 1000
+    }
+}
+
+/// System timestamp in milliseconds since boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    millis: u64,
+}
+
+impl Timestamp {
+    /// Returns the current timestamp
+    pub fn now() -> Self {
+        Self {
+            millis: uptime_ms(),
+        }
+    }
+
+    /// Returns the current timestamp in nanoseconds
+    ///
+    /// Backed by the HPET when available; otherwise the timer uptime is
+    /// used, which only has the resolution of a timer tick.
+    pub fn now_ns() -> TimestampNs {
+        let nanos = if hpet::is_available() {
+            hpet::hpet_nanos()
+        } else {
+            uptime_ms() * NANOS_PER_MILLI
+        };
+        TimestampNs { nanos }
+    }
+
+    /// Returns the elapsed time since this timestamp
+    pub fn elapsed(&self) -> Duration {
+        let current = uptime_ms();
+        Duration::from_millis(current.saturating_sub(self.millis))
+    }
+
+    /// Returns the timestamp in milliseconds
+    pub const fn as_millis(&self) -> u64 {
+        self.millis
+    }
+}
+
+/// High-resolution timestamp in nanoseconds, see `Timestamp::now_ns`
+///
+/// Only timestamps from the same source compare meaningfully: the HPET
+/// counter starts when the HPET is initialized, not at boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimestampNs {
+    nanos: u64,
+}
+
+impl TimestampNs {
+    /// Returns the elapsed time since this timestamp
+    pub fn elapsed(&self) -> Duration {
+        Timestamp::now_ns().duration_since(*self)
+    }
+
+    /// Returns the time from `earlier` to this timestamp, or zero if
+    /// `earlier` is later
+    pub fn duration_since(&self, earlier: TimestampNs) -> Duration {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+
+    /// Returns the timestamp in nanoseconds
+    pub const fn as_nanos(&self) -> u64 {
+        self.nanos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration_from_millis() {
+        let duration = Duration::from_millis(5000);
+        assert_eq!(duration.as_millis(), 5000);
+        assert_eq!(duration.as_secs(), 5);
+    }
+
+    #[test]
+    fn test_duration_from_secs() {
+        let duration = Duration::from_secs(10);
+        assert_eq!(duration.as_millis(), 10000);
+        assert_eq!(duration.as_secs(), 10);
+    }
+
+    #[test]
+    fn test_timestamp_ordering() {
+        let ts = Timestamp::now();
+        // Simulate time passing (in real tests, TICKS would increment)
+        let elapsed = ts.elapsed();
+        // Elapsed time should be non-negative
+        assert_eq!(elapsed.as_millis(), 0); // In unit tests without timer running
+    }
+
+    #[test]
+    fn test_duration_nanos() {
+        let duration = Duration::from_nanos(2_500_000);
+        assert_eq!(duration.as_nanos(), 2_500_000);
+        assert_eq!(duration.as_millis(), 2);
+        assert_eq!(Duration::from_millis(3).as_nanos(), 3_000_000);
+    }
+
+    #[test]
+    fn test_now_ns_is_monotonic() {
+        let first = Timestamp::now_ns();
+        let second = Timestamp::now_ns();
+        assert!(second >= first);
+        assert!(second.duration_since(first) < Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_udelay_waits() {
+        // The stopwatch reads zero until the TSC is calibrated
+        if tsc::TSC_FREQ_KHZ.load(core::sync::atomic::Ordering::Relaxed) == 0 {
+            tsc::calibrate();
+        }
+        let stopwatch = tsc::Stopwatch::start();
+        udelay(2000);
+        assert!(stopwatch.stop() >= Duration::from_nanos(1_900_000));
+    }
+
+    #[test]
+    fn test_sleep_ticks_zero_returns_immediately() {
+        let before = ticks();
+        sleep_ticks(0);
+        assert!(ticks() - before <= 1);
+    }
+}
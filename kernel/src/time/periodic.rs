@@ -0,0 +1,135 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Periodic reports
+//!
+//! Reports log statistics, which takes locks and may allocate, so they must
+//! not run in interrupt context. A `PeriodicReport` is instead polled by the
+//! idle task, which runs it once its deadline has passed. Interrupt
+//! handlers can ask for an early report with `request`, which only sets a
+//! flag.
+
+use core::sync::atomic::{
+    AtomicBool,
+    AtomicU64,
+    Ordering,
+};
+
+/// A report run from the idle task every `interval_ms`
+#[derive(Debug)]
+pub struct PeriodicReport {
+    /// Function writing the report
+    report: fn(),
+    /// Interval between reports, 0 while stopped
+    interval_ms: AtomicU64,
+    /// Uptime at which the next report is due
+    next_due_ms: AtomicU64,
+    /// Set by `request` to run the report on the next poll
+    requested: AtomicBool,
+}
+
+impl PeriodicReport {
+    /// Creates a stopped report running `report`
+    pub const fn new(report: fn()) -> Self {
+        Self {
+            report,
+            interval_ms: AtomicU64::new(0),
+            next_due_ms: AtomicU64::new(0),
+            requested: AtomicBool::new(false),
+        }
+    }
+
+    /// Runs the report every `interval_ms`, the first time `interval_ms`
+    /// after `now_ms`
+    pub fn start(&self, interval_ms: u64, now_ms: u64) {
+        self.next_due_ms
+            .store(now_ms.saturating_add(interval_ms), Ordering::Relaxed);
+        self.interval_ms.store(interval_ms, Ordering::Release);
+    }
+
+    /// Stops the periodic reports; requested ones still run
+    pub fn stop(&self) {
+        self.interval_ms.store(0, Ordering::Release);
+    }
+
+    /// Runs the report on the next poll, even before it is due
+    ///
+    /// Only sets a flag, so it is safe in interrupt context. Requests made
+    /// before the next poll are merged into one report.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Release);
+    }
+
+    /// Runs the report if it was requested or is due at `now_ms`
+    ///
+    /// A due report schedules the next one `interval_ms` after `now_ms`, so
+    /// reports missed while the idle task did not run are not made up for.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the report ran
+    pub fn poll(&self, now_ms: u64) -> bool {
+        let interval_ms = self.interval_ms.load(Ordering::Acquire);
+        let due = interval_ms != 0 && now_ms >= self.next_due_ms.load(Ordering::Relaxed);
+        if due {
+            self.next_due_ms
+                .store(now_ms.saturating_add(interval_ms), Ordering::Relaxed);
+        }
+        if self.requested.swap(false, Ordering::AcqRel) || due {
+            (self.report)();
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[test_case]
+    fn test_report_runs_when_due() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let report = PeriodicReport::new(|| {
+            RUNS.fetch_add(1, Ordering::Relaxed);
+        });
+
+        assert!(!report.poll(1_000));
+        report.start(100, 1_000);
+        assert!(!report.poll(1_099));
+        assert!(report.poll(1_100));
+        assert!(!report.poll(1_150));
+        assert!(report.poll(1_300));
+        assert_eq!(RUNS.load(Ordering::Relaxed), 2);
+
+        report.stop();
+        assert!(!report.poll(10_000));
+    }
+
+    #[test_case]
+    fn test_requests_are_merged() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let report = PeriodicReport::new(|| {
+            RUNS.fetch_add(1, Ordering::Relaxed);
+        });
+
+        report.request();
+        report.request();
+        assert!(report.poll(0));
+        assert!(!report.poll(0));
+        assert_eq!(RUNS.load(Ordering::Relaxed), 1);
+    }
+}
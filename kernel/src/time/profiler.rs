@@ -0,0 +1,233 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Boot time profiling
+//!
+//! `BootProfiler` records how long each stage of `kernel_main` takes, in
+//! time stamp counter cycles so that stages finishing within one timer
+//! tick, or running before the timer is set up, are still measured.
+//! Sections are wrapped with `profile_section!` and printed with
+//! `BootProfiler::report` once boot is done, by which time the TSC has
+//! been calibrated.
+
+use core::sync::atomic::Ordering;
+
+use spin::Mutex;
+
+use super::tsc::{
+    self,
+    TSC_FREQ_KHZ,
+};
+
+/// Most sections recorded; later ones are dropped
+pub const MAX_PROFILE_ENTRIES: usize = 32;
+
+/// The profiler used by `profile_section!`
+static PROFILER: Mutex<BootProfiler> = Mutex::new(BootProfiler::new());
+
+/// A profiled section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub name: &'static str,
+    /// TSC value when the section began
+    pub start_ticks: u64,
+    /// TSC value when the section ended
+    pub end_ticks: u64,
+    /// Set once `end` was called for the section
+    ended: bool,
+}
+
+impl ProfileEntry {
+    const EMPTY: Self = Self {
+        name: "",
+        start_ticks: 0,
+        end_ticks: 0,
+        ended: false,
+    };
+
+    /// Returns the section's duration in microseconds at a TSC frequency
+    /// of `khz`
+    pub fn duration_us(&self, khz: u64) -> u64 {
+        tsc::cycles_to_ns_at(self.end_ticks.wrapping_sub(self.start_ticks), khz) / 1000
+    }
+}
+
+/// Records the start and end of named sections
+///
+/// Sections may nest: `end` closes the innermost open one.
+pub struct BootProfiler {
+    entries: [ProfileEntry; MAX_PROFILE_ENTRIES],
+    len: usize,
+    /// Sections begun after the array filled up and not ended yet
+    dropped_open: usize,
+}
+
+impl BootProfiler {
+    /// Creates an empty profiler
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            entries: [ProfileEntry::EMPTY; MAX_PROFILE_ENTRIES],
+            len: 0,
+            dropped_open: 0,
+        }
+    }
+
+    /// Begins section `name` in the global profiler
+    pub fn begin(name: &'static str) {
+        PROFILER.lock().begin_at(name, tsc::tsc_cycles());
+    }
+
+    /// Ends the innermost open section in the global profiler
+    pub fn end() {
+        PROFILER.lock().end_at(tsc::tsc_cycles());
+    }
+
+    /// Logs the duration of every section of the global profiler, in the
+    /// order they began
+    pub fn report() {
+        let khz = TSC_FREQ_KHZ.load(Ordering::Relaxed);
+        for entry in PROFILER.lock().entries() {
+            crate::log_info!(
+                "Boot profile: {} took {} us",
+                entry.name,
+                entry.duration_us(khz)
+            );
+        }
+    }
+
+    /// Begins section `name` at TSC value `ticks`
+    pub fn begin_at(&mut self, name: &'static str, ticks: u64) {
+        if self.len == MAX_PROFILE_ENTRIES {
+            self.dropped_open += 1;
+            return;
+        }
+        self.entries[self.len] = ProfileEntry {
+            name,
+            start_ticks: ticks,
+            ..ProfileEntry::EMPTY
+        };
+        self.len += 1;
+    }
+
+    /// Ends the innermost open section at TSC value `ticks`
+    ///
+    /// Does nothing if no section is open.
+    pub fn end_at(&mut self, ticks: u64) {
+        if self.dropped_open > 0 {
+            self.dropped_open -= 1;
+            return;
+        }
+        if let Some(entry) = self.entries[..self.len]
+            .iter_mut()
+            .rev()
+            .find(|entry| !entry.ended)
+        {
+            entry.end_ticks = ticks;
+            entry.ended = true;
+        }
+    }
+
+    /// Returns the sections that have ended, in the order they began
+    pub fn entries(&self) -> impl Iterator<Item = &ProfileEntry> {
+        self.entries[..self.len].iter().filter(|entry| entry.ended)
+    }
+}
+
+/// Profiles a block as a named boot section
+///
+/// Evaluates to the value of the block.
+///
+/// # Examples
+///
+/// ```ignore
+/// profile_section!("memory", {
+///     memory::init_heap();
+/// });
+/// ```
+#[macro_export]
+macro_rules! profile_section {
+    ($name:expr, $body:block) => {{
+        $crate::time::profiler::BootProfiler::begin($name);
+        let value = $body;
+        $crate::time::profiler::BootProfiler::end();
+        value
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// 1 GHz, so one cycle is a nanosecond
+    const KHZ: u64 = 1_000_000;
+
+    fn durations(profiler: &BootProfiler) -> Vec<(&'static str, u64)> {
+        profiler
+            .entries()
+            .map(|entry| (entry.name, entry.duration_us(KHZ)))
+            .collect()
+    }
+
+    #[test_case]
+    fn test_profiler_durations() {
+        let mut profiler = BootProfiler::new();
+        profiler.begin_at("serial", 1_000);
+        profiler.end_at(251_000);
+        profiler.begin_at("memory", 300_000);
+        profiler.begin_at("heap", 400_000);
+        profiler.end_at(1_400_000);
+        profiler.end_at(2_300_000);
+        // Still open, so not reported
+        profiler.begin_at("timer", 3_000_000);
+
+        assert_eq!(durations(&profiler), [
+            ("serial", 250),
+            ("memory", 2_000),
+            ("heap", 1_000)
+        ]);
+    }
+
+    #[test_case]
+    fn test_profiler_drops_excess_sections() {
+        let mut profiler = BootProfiler::new();
+        profiler.begin_at("outer", 0);
+        for i in 1..MAX_PROFILE_ENTRIES as u64 {
+            profiler.begin_at("inner", i * 1_000);
+            profiler.end_at(i * 1_000 + 500);
+        }
+        profiler.begin_at("dropped", 100_000);
+        profiler.end_at(200_000);
+        profiler.end_at(1_000_000);
+
+        assert_eq!(profiler.entries().count(), MAX_PROFILE_ENTRIES);
+        let outer = profiler.entries().next().unwrap();
+        assert_eq!((outer.name, outer.duration_us(KHZ)), ("outer", 1_000));
+        assert!(profiler.entries().all(|entry| entry.name != "dropped"));
+    }
+
+    #[test_case]
+    fn test_profile_section_macro() {
+        let value = crate::profile_section!("macro test", { 6 * 7 });
+        assert_eq!(value, 42);
+        assert!(
+            PROFILER
+                .lock()
+                .entries()
+                .any(|entry| entry.name == "macro test" && entry.end_ticks >= entry.start_ticks)
+        );
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! CMOS Real-Time Clock (RTC) driver
+//!
+//! The RTC keeps the date and time while the machine is off. It is read
+//! once at boot to anchor uptime to wall-clock time: `wall_time_secs`
+//! returns the Unix time as the RTC reading plus the uptime since.
+
+use core::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+use super::uptime_seconds;
+use crate::interrupts::port::Port;
+
+/// CMOS register index port; bit 7 disables NMIs and is left clear
+const CMOS_INDEX: u16 = 0x70;
+/// CMOS register data port
+const CMOS_DATA: u16 = 0x71;
+
+/// CMOS register numbers
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+
+/// Status A bit set while the RTC updates its registers
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Status B bit set if the hours use the 24-hour format
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Status B bit set if the registers hold binary instead of BCD values
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Hours register bit marking PM in the 12-hour format
+const HOURS_PM: u8 = 1 << 7;
+
+/// Century assumed for the two-digit RTC year
+const CENTURY: u16 = 2000;
+
+/// Unix time of the RTC reading minus the uptime at which it was taken
+static EPOCH_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Date and time read from the RTC (UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl RtcTime {
+    /// Decodes raw RTC registers
+    ///
+    /// # Arguments
+    ///
+    /// * `raw` - Seconds, minutes, hours, day, month and year registers
+    /// * `status_b` - Status register B, which selects BCD or binary and the
+    ///   12- or 24-hour format
+    pub fn from_registers(raw: [u8; 6], status_b: u8) -> Self {
+        let [second, minute, hours, day, month, year] = raw;
+        let decode = |value: u8| {
+            if status_b & STATUS_B_BINARY != 0 {
+                value
+            } else {
+                decode_bcd(value)
+            }
+        };
+
+        let mut hour = decode(hours & !HOURS_PM);
+        if status_b & STATUS_B_24_HOUR == 0 {
+            // 12 AM is 0:00 and 12 PM is 12:00
+            hour %= 12;
+            if hours & HOURS_PM != 0 {
+                hour += 12;
+            }
+        }
+
+        Self {
+            year: CENTURY + u16::from(decode(year)),
+            month: decode(month),
+            day: decode(day),
+            hour,
+            minute: decode(minute),
+            second: decode(second),
+        }
+    }
+
+    /// Seconds since the Unix epoch (1970-01-01 00:00:00 UTC)
+    pub fn unix_timestamp(&self) -> u64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        days * 86_400
+            + u64::from(self.hour) * 3600
+            + u64::from(self.minute) * 60
+            + u64::from(self.second)
+    }
+}
+
+/// Converts a two-digit BCD value to binary
+pub const fn decode_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+/// Days from 1970-01-01 to the given date in the proleptic Gregorian
+/// calendar
+fn days_from_civil(year: u16, month: u8, day: u8) -> u64 {
+    // Count years from March, so the leap day is the last day of a year
+    let year = u64::from(year) - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month = u64::from(month);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + u64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    // 719468 days from 0000-03-01 to 1970-01-01
+    (era * 146_097 + day_of_era).saturating_sub(719_468)
+}
+
+/// Reads a CMOS register
+fn read_cmos(reg: u8) -> u8 {
+    let mut index = Port::<u8>::new(CMOS_INDEX);
+    let mut data = Port::<u8>::new(CMOS_DATA);
+    // SAFETY: selecting and reading a CMOS register has no side effects
+    unsafe {
+        index.write(reg);
+        data.read()
+    }
+}
+
+/// Reads the time registers once the RTC is not updating them
+fn read_registers() -> [u8; 6] {
+    while read_cmos(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+    [
+        REG_SECONDS,
+        REG_MINUTES,
+        REG_HOURS,
+        REG_DAY,
+        REG_MONTH,
+        REG_YEAR,
+    ]
+    .map(read_cmos)
+}
+
+/// Reads the current date and time from the RTC
+///
+/// The registers are read until two reads agree, so an update that starts
+/// during a read cannot produce a torn value.
+pub fn read_rtc() -> RtcTime {
+    let mut raw = read_registers();
+    loop {
+        let again = read_registers();
+        if again == raw {
+            break;
+        }
+        raw = again;
+    }
+    RtcTime::from_registers(raw, read_cmos(REG_STATUS_B))
+}
+
+/// Anchors wall-clock time to an RTC reading taken now
+pub fn set_epoch_offset(rtc: RtcTime) {
+    let offset = rtc.unix_timestamp().saturating_sub(uptime_seconds());
+    EPOCH_OFFSET.store(offset, Ordering::Relaxed);
+}
+
+/// Seconds since the Unix epoch, or uptime if the RTC was never read
+pub fn wall_time_secs() -> u64 {
+    EPOCH_OFFSET.load(Ordering::Relaxed) + uptime_seconds()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_decode_bcd_registers() {
+        assert_eq!(decode_bcd(0x59), 59);
+        assert_eq!(decode_bcd(0x00), 0);
+
+        // 2025-03-14 09:26:53 in BCD, 24-hour format
+        let time = RtcTime::from_registers([0x53, 0x26, 0x09, 0x14, 0x03, 0x25], STATUS_B_24_HOUR);
+        assert_eq!(time, RtcTime {
+            year: 2025,
+            month: 3,
+            day: 14,
+            hour: 9,
+            minute: 26,
+            second: 53,
+        });
+        assert_eq!(time.unix_timestamp(), 1_741_944_413);
+
+        // 12:05 AM and 11 PM in the 12-hour format
+        let midnight = RtcTime::from_registers([0, 0x05, 0x12, 1, 1, 0x70], 0);
+        assert_eq!(midnight.hour, 0);
+        let evening = RtcTime::from_registers([0, 0, 0x11 | HOURS_PM, 1, 1, 0x70], 0);
+        assert_eq!(evening.hour, 23);
+    }
+
+    #[test_case]
+    fn test_binary_registers() {
+        let time = RtcTime::from_registers([0, 0, 0, 1, 1, 0], STATUS_B_BINARY | STATUS_B_24_HOUR);
+        assert_eq!(time.unix_timestamp(), 946_684_800);
+    }
+
+    #[test_case]
+    fn test_wall_time_is_monotonic() {
+        set_epoch_offset(RtcTime::from_registers(
+            [0x53, 0x26, 0x09, 0x14, 0x03, 0x25],
+            STATUS_B_24_HOUR,
+        ));
+        let first = wall_time_secs();
+        let second = wall_time_secs();
+        assert!(first >= 1_741_944_413);
+        assert!(second >= first);
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timer wheel for deferred work
+//!
+//! Callbacks are hashed by their deadline tick into one of `WHEEL_SLOTS`
+//! buckets. Every timer tick only the bucket of that tick is inspected;
+//! entries whose deadline lies one or more turns of the wheel ahead stay
+//! in it until their turn comes.
+//!
+//! The wheel is advanced from the timer interrupt, which must not touch the
+//! heap: the allocator lock may be held by the interrupted code. Fired
+//! entries are therefore moved to a spent list whose capacity `schedule`
+//! reserves in advance, and their boxes are only freed by `free_spent`,
+//! which the idle task calls. Callbacks are `FnMut` so that running one
+//! leaves its captures in the box rather than dropping them in the
+//! interrupt handler.
+
+use alloc::{
+    boxed::Box,
+    vec::Vec,
+};
+
+use spin::Mutex;
+
+use crate::interrupts::timer::TIMER_FREQUENCY;
+
+/// Number of buckets in the wheel
+pub const WHEEL_SLOTS: usize = 256;
+
+/// Global timer wheel, advanced by the timer interrupt
+pub static TIMER_WHEEL: Mutex<TimerWheel> = Mutex::new(TimerWheel::new());
+
+/// A callback waiting for its deadline
+pub struct TimerEntry {
+    /// Tick at or after which the callback runs
+    pub deadline_ticks: u64,
+    /// Scheduling order, which breaks ties between equal deadlines
+    sequence: u64,
+    /// The callback, run once
+    ///
+    /// `FnMut` rather than `FnOnce` so that running it neither frees the
+    /// box nor drops the captures.
+    action: Box<dyn FnMut() + Send>,
+}
+
+/// Hashed timer wheel with `WHEEL_SLOTS` buckets
+pub struct TimerWheel {
+    buckets: [Vec<TimerEntry>; WHEEL_SLOTS],
+    /// Fired entries waiting to be freed, with room for every pending one
+    spent: Vec<TimerEntry>,
+    /// Last tick the wheel has been advanced to
+    current_ticks: u64,
+    /// Sequence number of the next scheduled entry
+    next_sequence: u64,
+}
+
+impl TimerWheel {
+    /// Creates an empty wheel at tick 0
+    pub const fn new() -> Self {
+        Self {
+            buckets: [const { Vec::new() }; WHEEL_SLOTS],
+            spent: Vec::new(),
+            current_ticks: 0,
+            next_sequence: 0,
+        }
+    }
+
+    /// Returns the last tick the wheel has been advanced to
+    pub fn current_ticks(&self) -> u64 {
+        self.current_ticks
+    }
+
+    /// Moves the wheel to `ticks` without running anything
+    ///
+    /// Used to start the wheel at the current tick count.
+    pub fn set_current_ticks(&mut self, ticks: u64) {
+        self.current_ticks = ticks;
+    }
+
+    /// Returns the number of callbacks waiting
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(Vec::len).sum()
+    }
+
+    /// Returns `true` if no callbacks are waiting
+    pub fn is_empty(&self) -> bool {
+        self.buckets.iter().all(Vec::is_empty)
+    }
+
+    /// Returns the number of fired entries not yet freed
+    pub fn spent_len(&self) -> usize {
+        self.spent.len()
+    }
+
+    /// Schedules `action` to run `delay_ms` milliseconds from now
+    ///
+    /// The delay is rounded up to whole ticks; the callback runs on the
+    /// next tick at the earliest. `action` runs once; its captures are
+    /// dropped with the entry by `free_spent`. Allocates, so it must not be
+    /// called from interrupt handlers or timer callbacks.
+    pub fn schedule(&mut self, delay_ms: u64, action: impl FnMut() + Send + 'static) {
+        let delay_ticks = (delay_ms * u64::from(TIMER_FREQUENCY))
+            .div_ceil(1000)
+            .max(1);
+        let deadline_ticks = self.current_ticks + delay_ticks;
+
+        // Room for every pending entry in `spent`, so `tick` never grows it
+        self.spent.reserve(self.len() + 1);
+        self.buckets[(deadline_ticks % WHEEL_SLOTS as u64) as usize].push(TimerEntry {
+            deadline_ticks,
+            sequence: self.next_sequence,
+            action: Box::new(action),
+        });
+        self.next_sequence += 1;
+    }
+
+    /// Advances the wheel to `current_ticks` and runs the expired callbacks
+    ///
+    /// Every bucket passed since the last advance is inspected, so ticks
+    /// the wheel missed are caught up on. Callbacks run in deadline order,
+    /// and in scheduling order for equal deadlines.
+    ///
+    /// Neither allocates nor frees: the fired entries stay in the spent
+    /// list until `free_spent`.
+    ///
+    /// # Returns
+    ///
+    /// The number of callbacks run
+    pub fn tick(&mut self, current_ticks: u64) -> usize {
+        let first_expired = self.spent.len();
+        let passed = current_ticks.saturating_sub(self.current_ticks);
+        for tick in self.current_ticks + 1..=self.current_ticks + passed.min(WHEEL_SLOTS as u64) {
+            let bucket = &mut self.buckets[(tick % WHEEL_SLOTS as u64) as usize];
+            let mut index = 0;
+            while index < bucket.len() {
+                if bucket[index].deadline_ticks <= current_ticks {
+                    // `schedule` reserved the capacity, so this cannot
+                    // reallocate
+                    self.spent.push(bucket.remove(index));
+                } else {
+                    index += 1;
+                }
+            }
+        }
+        self.current_ticks = self.current_ticks.max(current_ticks);
+
+        // In place, unlike the stable sort, which allocates a buffer
+        let expired = &mut self.spent[first_expired..];
+        expired.sort_unstable_by_key(|entry| (entry.deadline_ticks, entry.sequence));
+        for entry in expired.iter_mut() {
+            (entry.action)();
+        }
+        expired.len()
+    }
+
+    /// Frees the entries whose callbacks have run
+    pub fn free_spent(&mut self) {
+        self.spent.clear();
+    }
+
+    /// Moves the entries whose callbacks have run out of the wheel
+    ///
+    /// The spent list keeps its capacity, which `tick` relies on for the
+    /// entries still pending, so the caller can free the entries without
+    /// holding the wheel. Allocates the returned list.
+    pub fn take_spent(&mut self) -> Vec<TimerEntry> {
+        let mut spent = Vec::with_capacity(self.spent.len());
+        // Unlike `mem::take`, leaves the capacity with `self.spent`
+        spent.append(&mut self.spent);
+        spent
+    }
+}
+
+impl Default for TimerWheel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Starts `TIMER_WHEEL` at the current tick count
+pub fn init() {
+    crate::interrupts::without_interrupts(|| {
+        TIMER_WHEEL.lock().set_current_ticks(super::ticks());
+    });
+}
+
+/// Schedules `action` on `TIMER_WHEEL` to run `delay_ms` milliseconds
+/// from now
+///
+/// The callback runs in the timer interrupt handler with `TIMER_WHEEL`
+/// locked, so it must be short, must not block and must neither allocate
+/// nor free, which rules out scheduling further callbacks. Its captures
+/// are dropped later by `free_spent`.
+pub fn schedule(delay_ms: u64, action: impl FnMut() + Send + 'static) {
+    crate::interrupts::without_interrupts(|| TIMER_WHEEL.lock().schedule(delay_ms, action));
+}
+
+/// Timer interrupt hook
+///
+/// Skips the tick if the lock is held by the interrupted code; the next
+/// tick catches up.
+pub fn timer_tick(current_ticks: u64) {
+    if let Some(mut wheel) = TIMER_WHEEL.try_lock() {
+        wheel.tick(current_ticks);
+    }
+}
+
+/// Frees the fired entries of `TIMER_WHEEL`
+///
+/// Called by the idle task. The entries are dropped after the lock is
+/// released, with interrupts enabled.
+pub fn free_spent() {
+    let spent = crate::interrupts::without_interrupts(|| {
+        let mut wheel = TIMER_WHEEL.lock();
+        (wheel.spent_len() > 0).then(|| wheel.take_spent())
+    });
+    drop(spent);
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::*;
+
+    /// Milliseconds per tick
+    const TICK_MS: u64 = 1000 / TIMER_FREQUENCY as u64;
+
+    #[test_case]
+    fn test_callbacks_fire_in_deadline_order() {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut wheel = TimerWheel::new();
+        for delay in [3, 1, 2] {
+            let fired = fired.clone();
+            wheel.schedule(delay * TICK_MS, move || fired.lock().push(delay));
+        }
+        assert_eq!(wheel.len(), 3);
+
+        wheel.tick(1);
+        assert_eq!(*fired.lock(), [1]);
+        wheel.tick(3);
+        assert_eq!(*fired.lock(), [1, 2, 3]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test_case]
+    fn test_entries_wait_for_their_turn() {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut wheel = TimerWheel::new();
+        let far = WHEEL_SLOTS as u64 + 1;
+        for delay in [1, far] {
+            let fired = fired.clone();
+            wheel.schedule(delay * TICK_MS, move || fired.lock().push(delay));
+        }
+
+        // Both share a bucket, but the second is a full turn later
+        wheel.tick(1);
+        assert_eq!(*fired.lock(), [1]);
+        wheel.tick(far - 1);
+        assert_eq!(*fired.lock(), [1]);
+        wheel.tick(far);
+        assert_eq!(*fired.lock(), [1, far]);
+    }
+
+    #[test_case]
+    fn test_zero_delay_runs_on_next_tick() {
+        let fired = Arc::new(Mutex::new(false));
+        let mut wheel = TimerWheel::new();
+        wheel.set_current_ticks(10);
+        let flag = fired.clone();
+        wheel.schedule(0, move || *flag.lock() = true);
+
+        wheel.tick(10);
+        assert!(!*fired.lock());
+        wheel.tick(11);
+        assert!(*fired.lock());
+    }
+
+    #[test_case]
+    fn test_fired_entries_are_freed_later() {
+        let mut wheel = TimerWheel::new();
+        wheel.schedule(TICK_MS, || {});
+        wheel.schedule(2 * TICK_MS, || {});
+
+        assert_eq!(wheel.tick(1), 1);
+        assert_eq!(wheel.spent_len(), 1);
+        assert_eq!(wheel.len(), 1);
+        wheel.free_spent();
+        assert_eq!(wheel.spent_len(), 0);
+        assert_eq!(wheel.tick(2), 1);
+    }
+
+    #[test_case]
+    fn test_take_spent_keeps_room_for_pending() {
+        let mut wheel = TimerWheel::new();
+        wheel.schedule(10 * TICK_MS, || {});
+        wheel.schedule(TICK_MS, || {});
+
+        assert_eq!(wheel.tick(1), 1);
+        assert_eq!(wheel.take_spent().len(), 1);
+        assert_eq!(wheel.spent_len(), 0);
+        // The far entry still fires without growing the spent list
+        assert!(wheel.spent.capacity() >= wheel.len());
+        assert_eq!(wheel.tick(10), 1);
+    }
+
+    #[test_case]
+    fn test_captures_dropped_when_freed() {
+        let captured = Arc::new(());
+        let mut wheel = TimerWheel::new();
+        let held = captured.clone();
+        wheel.schedule(TICK_MS, move || {
+            let _ = &held;
+        });
+
+        assert_eq!(wheel.tick(1), 1);
+        assert_eq!(Arc::strong_count(&captured), 2);
+        wheel.free_spent();
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
+}
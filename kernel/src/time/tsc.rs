@@ -0,0 +1,154 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time stamp counter
+//!
+//! The TSC counts CPU cycles, so it resolves far finer than the 10 ms timer
+//! tick. Its rate is measured against the PIT by `calibrate`, which
+//! `time::init` runs at boot; `cycles_to_ns` converts counts with it.
+//! `Stopwatch` times short sections of code.
+
+use core::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+use super::Duration;
+use crate::interrupts::pit;
+
+/// TSC frequency in kHz (cycles per millisecond)
+///
+/// Set by `calibrate`; 0 until calibrated.
+pub static TSC_FREQ_KHZ: AtomicU64 = AtomicU64::new(0);
+
+/// Length of the PIT interval the TSC is measured against
+const CALIBRATION_MS: u32 = 50;
+
+/// Nanoseconds per millisecond
+const NANOS_PER_MILLI: u128 = 1_000_000;
+
+/// Reads the time stamp counter
+pub fn tsc_cycles() -> u64 {
+    // SAFETY: RDTSC only reads the counter
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Measures the TSC frequency against the PIT and stores it in
+/// `TSC_FREQ_KHZ`
+///
+/// Busy-waits for `CALIBRATION_MS`, so it should run once at boot.
+///
+/// # Returns
+///
+/// The TSC frequency in kHz, at least 1
+pub fn calibrate() -> u64 {
+    let start = tsc_cycles();
+    pit::busy_wait_ms(CALIBRATION_MS);
+    let khz = (tsc_cycles().wrapping_sub(start) / u64::from(CALIBRATION_MS)).max(1);
+    TSC_FREQ_KHZ.store(khz, Ordering::Relaxed);
+    khz
+}
+
+/// Converts a number of TSC cycles to nanoseconds
+///
+/// Returns 0 until the TSC is calibrated.
+pub fn cycles_to_ns(cycles: u64) -> u64 {
+    cycles_to_ns_at(cycles, TSC_FREQ_KHZ.load(Ordering::Relaxed))
+}
+
+/// Converts `cycles` to nanoseconds at a TSC frequency of `khz`
+pub(crate) fn cycles_to_ns_at(cycles: u64, khz: u64) -> u64 {
+    if khz == 0 {
+        return 0;
+    }
+    let nanos = u128::from(cycles) * NANOS_PER_MILLI / u128::from(khz);
+    u64::try_from(nanos).unwrap_or(u64::MAX)
+}
+
+/// Cycles from `start` to `end`, allowing for the counter wrapping in
+/// between
+fn elapsed_cycles(start: u64, end: u64) -> u64 {
+    end.wrapping_sub(start)
+}
+
+/// Measures elapsed time with the TSC
+///
+/// # Example
+///
+/// ```ignore
+/// let stopwatch = Stopwatch::start();
+/// do_work();
+/// log_info!("took {} ns", stopwatch.stop().as_nanos());
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    start: u64,
+}
+
+impl Stopwatch {
+    /// Starts timing now
+    pub fn start() -> Self {
+        Self {
+            start: tsc_cycles(),
+        }
+    }
+
+    /// Returns the time since `start`
+    pub fn stop(&self) -> Duration {
+        Duration::from_nanos(cycles_to_ns(elapsed_cycles(self.start, tsc_cycles())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_cycles_to_ns_one_second() {
+        if TSC_FREQ_KHZ.load(Ordering::Relaxed) == 0 {
+            calibrate();
+        }
+        let khz = TSC_FREQ_KHZ.load(Ordering::Relaxed);
+        assert_eq!(cycles_to_ns(khz * 1000), 1_000_000_000);
+    }
+
+    #[test_case]
+    fn test_cycles_to_ns_uncalibrated() {
+        assert_eq!(cycles_to_ns_at(12345, 0), 0);
+        assert_eq!(cycles_to_ns_at(3_000, 3_000_000), 1);
+        assert_eq!(cycles_to_ns_at(u64::MAX, 1), u64::MAX);
+    }
+
+    #[test_case]
+    fn test_elapsed_cycles_wraps() {
+        assert_eq!(elapsed_cycles(100, 250), 150);
+        assert_eq!(elapsed_cycles(u64::MAX - 9, 5), 15);
+        // A counter that only keeps its low 32 bits wraps the same way
+        let start = u64::from(u32::MAX - 4);
+        let end = u64::from(10u32);
+        assert_eq!(elapsed_cycles(start, end) as u32, 15);
+    }
+
+    #[test_case]
+    fn test_stopwatch_measures_busy_wait() {
+        if TSC_FREQ_KHZ.load(Ordering::Relaxed) == 0 {
+            calibrate();
+        }
+        let stopwatch = Stopwatch::start();
+        pit::busy_wait_ms(5);
+        let elapsed = stopwatch.stop();
+        assert!(elapsed >= Duration::from_millis(4));
+        assert!(elapsed < Duration::from_millis(50));
+    }
+}
@@ -19,13 +19,32 @@
 //! debugging boot issues when serial output isn't working.
 //!
 //! The VGA buffer is located at physical address 0xB8000 and provides
-//! an 80x25 character display with color attributes.
+//! an 80x25 character display with color attributes. The blinking
+//! hardware cursor is moved through the CRT controller registers.
+//!
+//! Text is drawn into a shadow buffer in normal RAM, which records the
+//! cells that changed. `flush` copies those to the screen; the timer
+//! interrupt does so every `FLUSH_INTERVAL_TICKS` ticks, so large updates
+//! appear at once instead of tearing.
+//!
+//! ANSI SGR color sequences such as `ESC [ 31 m`, which the serial output
+//! uses, select VGA colors; other CSI sequences are dropped.
 
 #![allow(dead_code)]
 
-use core::fmt;
+use alloc::string::String;
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
 
-use spin::Mutex;
+use crate::{
+    interrupts::port::Port,
+    sync::DetectMutex,
+};
 
 /// VGA buffer dimensions
 const VGA_WIDTH: usize = 80;
@@ -34,6 +53,59 @@ const VGA_HEIGHT: usize = 25;
 /// VGA buffer physical address
 const VGA_BUFFER_ADDR: usize = 0xb8000;
 
+/// Timer ticks between flushes of the shadow buffer to the screen
+pub const FLUSH_INTERVAL_TICKS: u64 = 10;
+
+/// CRT controller index and data ports
+const CRTC_INDEX: u16 = 0x3d4;
+const CRTC_DATA: u16 = 0x3d5;
+
+/// CRT controller registers
+const CRTC_CURSOR_START: u8 = 0x0a;
+const CRTC_CURSOR_END: u8 = 0x0b;
+const CRTC_CURSOR_HIGH: u8 = 0x0e;
+const CRTC_CURSOR_LOW: u8 = 0x0f;
+
+/// Cursor start register bit that hides the cursor
+const CURSOR_DISABLE: u8 = 1 << 5;
+
+/// Set by `init`; the CRT controller is left alone before that
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// ASCII escape, which starts ANSI escape sequences
+const ESC: u8 = 0x1b;
+
+/// Most CSI parameters kept per sequence; further ones are ignored
+const MAX_CSI_PARAMS: usize = 4;
+
+/// Colors restored by the SGR reset sequence
+const DEFAULT_FOREGROUND: Color = Color::White;
+const DEFAULT_BACKGROUND: Color = Color::Black;
+
+/// VGA colors for the eight ANSI colors, in SGR order
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
+/// VGA colors for the eight bright ANSI colors, in SGR order
+const ANSI_BRIGHT_COLORS: [Color; 8] = [
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::Yellow,
+    Color::LightBlue,
+    Color::Pink,
+    Color::LightCyan,
+    Color::White,
+];
+
 /// VGA color codes
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -56,6 +128,30 @@ pub enum Color {
     White = 15,
 }
 
+impl Color {
+    /// Returns the color with the given 4-bit VGA index
+    const fn from_index(index: u8) -> Self {
+        match index & 0x0f {
+            0 => Color::Black,
+            1 => Color::Blue,
+            2 => Color::Green,
+            3 => Color::Cyan,
+            4 => Color::Red,
+            5 => Color::Magenta,
+            6 => Color::Brown,
+            7 => Color::LightGray,
+            8 => Color::DarkGray,
+            9 => Color::LightBlue,
+            10 => Color::LightGreen,
+            11 => Color::LightCyan,
+            12 => Color::LightRed,
+            13 => Color::Pink,
+            14 => Color::Yellow,
+            _ => Color::White,
+        }
+    }
+}
+
 /// Color attribute combining foreground and background colors
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
@@ -66,6 +162,27 @@ impl ColorCode {
     pub const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
+
+    /// Returns the foreground color
+    pub const fn foreground(self) -> Color {
+        Color::from_index(self.0)
+    }
+
+    /// Returns the background color
+    pub const fn background(self) -> Color {
+        Color::from_index(self.0 >> 4)
+    }
+}
+
+/// State of the ANSI escape sequence parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiState {
+    /// Printing characters
+    Normal,
+    /// After `ESC`, expecting `[`
+    EscSeen,
+    /// Inside a CSI sequence, accumulating the given parameter
+    CsiParam(u8),
 }
 
 /// VGA character with color attribute
@@ -87,7 +204,16 @@ pub struct VgaWriter {
     column: usize,
     row: usize,
     color_code: ColorCode,
+    /// The screen itself, only written by `flush` and `flush_all`
     buffer: &'static mut VgaBuffer,
+    /// What the screen shows once flushed
+    shadow: VgaBuffer,
+    /// Cells of `shadow` not yet copied to the screen
+    dirty: [[bool; VGA_WIDTH]; VGA_HEIGHT],
+    ansi_state: AnsiState,
+    /// Completed parameters of the current CSI sequence
+    csi_params: [u8; MAX_CSI_PARAMS],
+    csi_param_count: usize,
 }
 
 impl VgaWriter {
@@ -98,21 +224,90 @@ impl VgaWriter {
     /// This function creates a mutable reference to VGA memory at 0xB8000.
     /// The caller must ensure this is only called once.
     pub unsafe fn new() -> Self {
+        let buffer = &mut *(VGA_BUFFER_ADDR as *mut VgaBuffer);
+        // Start from what is on screen, so nothing needs flushing yet
+        let shadow = VgaBuffer {
+            chars: buffer.chars,
+        };
         Self {
             column: 0,
             row: 0,
-            color_code: ColorCode::new(Color::White, Color::Black),
-            buffer: &mut *(VGA_BUFFER_ADDR as *mut VgaBuffer),
+            color_code: ColorCode::new(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+            buffer,
+            shadow,
+            dirty: [[false; VGA_WIDTH]; VGA_HEIGHT],
+            ansi_state: AnsiState::Normal,
+            csi_params: [0; MAX_CSI_PARAMS],
+            csi_param_count: 0,
         }
     }
 
     /// Set the foreground and background colors
+    ///
+    /// The hardware cursor takes the foreground color of the cell under
+    /// it, so that cell is recolored too.
     pub fn set_color(&mut self, foreground: Color, background: Color) {
         self.color_code = ColorCode::new(foreground, background);
+        if self.column < VGA_WIDTH {
+            let cell = ScreenChar {
+                color_code: self.color_code,
+                ..self.shadow.chars[self.row][self.column]
+            };
+            self.set_cell(self.row, self.column, cell);
+        }
+    }
+
+    /// Show the hardware cursor
+    ///
+    /// # Arguments
+    ///
+    /// * `start_scanline` - First scanline of the cursor within a cell (0-15)
+    /// * `end_scanline` - Last scanline of the cursor within a cell (0-15)
+    pub fn enable_cursor(&mut self, start_scanline: u8, end_scanline: u8) {
+        let start = crtc_read(CRTC_CURSOR_START) & 0xc0;
+        crtc_write(CRTC_CURSOR_START, start | (start_scanline & 0x1f));
+        let end = crtc_read(CRTC_CURSOR_END) & 0xe0;
+        crtc_write(CRTC_CURSOR_END, end | (end_scanline & 0x1f));
+    }
+
+    /// Hide the hardware cursor
+    pub fn disable_cursor(&mut self) {
+        crtc_write(CRTC_CURSOR_START, CURSOR_DISABLE);
+    }
+
+    /// Move the hardware cursor to a cell
+    pub fn update_cursor(&mut self, row: usize, col: usize) {
+        let position = cursor_position(row, col);
+        crtc_write(CRTC_CURSOR_LOW, position as u8);
+        crtc_write(CRTC_CURSOR_HIGH, (position >> 8) as u8);
     }
 
     /// Write a byte to the VGA buffer
+    ///
+    /// Bytes belonging to an ANSI escape sequence are interpreted instead
+    /// of printed.
     pub fn write_byte(&mut self, byte: u8) {
+        match self.ansi_state {
+            AnsiState::Normal if byte == ESC => {
+                self.ansi_state = AnsiState::EscSeen;
+                return;
+            }
+            AnsiState::Normal => {}
+            AnsiState::EscSeen => {
+                self.ansi_state = if byte == b'[' {
+                    self.csi_param_count = 0;
+                    AnsiState::CsiParam(0)
+                } else {
+                    AnsiState::Normal
+                };
+                return;
+            }
+            AnsiState::CsiParam(param) => {
+                self.handle_csi_byte(param, byte);
+                return;
+            }
+        }
+
         match byte {
             b'\n' => self.new_line(),
             byte => {
@@ -120,25 +315,24 @@ impl VgaWriter {
                     self.new_line();
                 }
 
-                let row = self.row;
-                let col = self.column;
-
-                self.buffer.chars[row][col] = ScreenChar {
+                let cell = ScreenChar {
                     ascii_character: byte,
                     color_code: self.color_code,
                 };
+                self.set_cell(self.row, self.column, cell);
 
                 self.column += 1;
             }
         }
+        self.update_cursor(self.row, self.column);
     }
 
     /// Write a string to the VGA buffer
     pub fn write_string(&mut self, s: &str) {
         for byte in s.bytes() {
             match byte {
-                // Printable ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
+                // Printable ASCII byte, newline or start of an escape
+                0x20..=0x7e | b'\n' | ESC => self.write_byte(byte),
                 // Not part of printable ASCII range
                 _ => self.write_byte(0xfe), // ■ character
             }
@@ -156,33 +350,187 @@ impl VgaWriter {
         self.write_string(s);
     }
 
+    /// Read a line typed on the keyboard
+    ///
+    /// Typed characters are echoed and Backspace erases the last one.
+    /// Halts between keystrokes until Enter is pressed, so interrupts
+    /// must be enabled.
+    ///
+    /// # Returns
+    ///
+    /// The line, without the trailing newline
+    pub fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        loop {
+            let Some(c) = crate::interrupts::keyboard::read_char() else {
+                // SAFETY: halting with interrupts enabled only waits for
+                // the next interrupt.
+                unsafe { core::arch::asm!("sti; hlt") };
+                continue;
+            };
+
+            match c {
+                '\n' => {
+                    self.new_line();
+                    return line;
+                }
+                '\x08' => {
+                    if line.pop().is_some() && self.column > 0 {
+                        self.column -= 1;
+                        let blank = ScreenChar {
+                            ascii_character: b' ',
+                            color_code: self.color_code,
+                        };
+                        self.set_cell(self.row, self.column, blank);
+                        self.update_cursor(self.row, self.column);
+                    }
+                }
+                ' '..='~' => {
+                    line.push(c);
+                    self.write_byte(c as u8);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Feeds a byte of a CSI sequence, `param` being the parameter so far
+    ///
+    /// Digits extend the parameter and `;` starts the next one. A final
+    /// byte ends the sequence; only `m` (select graphic rendition) has an
+    /// effect.
+    fn handle_csi_byte(&mut self, param: u8, byte: u8) {
+        match byte {
+            b'0'..=b'9' => {
+                let param = param.saturating_mul(10).saturating_add(byte - b'0');
+                self.ansi_state = AnsiState::CsiParam(param);
+            }
+            b';' => {
+                self.push_csi_param(param);
+                self.ansi_state = AnsiState::CsiParam(0);
+            }
+            // Final byte
+            0x40..=0x7e => {
+                self.push_csi_param(param);
+                if byte == b'm' {
+                    for index in 0..self.csi_param_count {
+                        self.apply_sgr(self.csi_params[index]);
+                    }
+                }
+                self.ansi_state = AnsiState::Normal;
+            }
+            // Intermediate and private bytes are skipped
+            _ => {}
+        }
+    }
+
+    fn push_csi_param(&mut self, param: u8) {
+        if self.csi_param_count < MAX_CSI_PARAMS {
+            self.csi_params[self.csi_param_count] = param;
+            self.csi_param_count += 1;
+        }
+    }
+
+    /// Applies an SGR parameter; unsupported ones are ignored
+    fn apply_sgr(&mut self, param: u8) {
+        let foreground = self.color_code.foreground();
+        let background = self.color_code.background();
+        match param {
+            0 => self.set_color(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+            30..=37 => self.set_color(ANSI_COLORS[usize::from(param - 30)], background),
+            39 => self.set_color(DEFAULT_FOREGROUND, background),
+            40..=47 => self.set_color(foreground, ANSI_COLORS[usize::from(param - 40)]),
+            49 => self.set_color(foreground, DEFAULT_BACKGROUND),
+            90..=97 => self.set_color(ANSI_BRIGHT_COLORS[usize::from(param - 90)], background),
+            _ => {}
+        }
+    }
+
     /// Move to the next line
     fn new_line(&mut self) {
         if self.row >= VGA_HEIGHT - 1 {
-            // Scroll up
-            for row in 1..VGA_HEIGHT {
-                for col in 0..VGA_WIDTH {
-                    self.buffer.chars[row - 1][col] = self.buffer.chars[row][col];
-                }
-            }
-            self.clear_row(VGA_HEIGHT - 1);
+            self.scroll_region(0, VGA_HEIGHT - 1, 1);
         } else {
             self.row += 1;
         }
         self.column = 0;
     }
 
+    /// Scroll the rows `top` to `bottom` (inclusive) by `lines`
+    ///
+    /// Positive `lines` scroll up, discarding rows at the top of the
+    /// region; negative ones scroll down. Rows scrolled in are blanked in
+    /// the current color. Rows outside the region are left alone, and the
+    /// region is clipped to the screen.
+    pub fn scroll_region(&mut self, top: usize, bottom: usize, lines: i32) {
+        let bottom = bottom.min(VGA_HEIGHT - 1);
+        if top > bottom || lines == 0 {
+            return;
+        }
+        let count = lines.unsigned_abs() as usize;
+        let blank = self.color_code;
+        if count > bottom - top {
+            self.fill_region(top, bottom, blank, b' ');
+        } else if lines > 0 {
+            self.shadow.chars.copy_within(top + count..=bottom, top);
+            self.dirty[top..=bottom - count].fill([true; VGA_WIDTH]);
+            self.fill_region(bottom + 1 - count, bottom, blank, b' ');
+        } else {
+            self.shadow
+                .chars
+                .copy_within(top..=bottom - count, top + count);
+            self.dirty[top + count..=bottom].fill([true; VGA_WIDTH]);
+            self.fill_region(top, top + count - 1, blank, b' ');
+        }
+    }
+
+    /// Fill the rows `top` to `bottom` (inclusive) with `character`
+    ///
+    /// The region is clipped to the screen.
+    pub fn fill_region(&mut self, top: usize, bottom: usize, color: ColorCode, character: u8) {
+        let cell = ScreenChar {
+            ascii_character: character,
+            color_code: color,
+        };
+        let bottom = bottom.min(VGA_HEIGHT - 1);
+        for row in top..=bottom {
+            for col in 0..VGA_WIDTH {
+                self.set_cell(row, col, cell);
+            }
+        }
+    }
+
     /// Clear a row
     fn clear_row(&mut self, row: usize) {
-        let blank = ScreenChar {
-            ascii_character: b' ',
-            color_code: self.color_code,
-        };
-        for col in 0..VGA_WIDTH {
-            self.buffer.chars[row][col] = blank;
+        self.fill_region(row, row, self.color_code, b' ');
+    }
+
+    /// Write a cell of the shadow buffer, marking it dirty if it changed
+    fn set_cell(&mut self, row: usize, col: usize, cell: ScreenChar) {
+        if self.shadow.chars[row][col] != cell {
+            self.shadow.chars[row][col] = cell;
+            self.dirty[row][col] = true;
+        }
+    }
+
+    /// Copy the cells changed since the last flush to the screen
+    pub fn flush(&mut self) {
+        for (row, dirty) in self.dirty.iter_mut().enumerate() {
+            for (col, dirty) in dirty.iter_mut().enumerate() {
+                if *dirty {
+                    self.buffer.chars[row][col] = self.shadow.chars[row][col];
+                    *dirty = false;
+                }
+            }
         }
     }
 
+    /// Copy the whole shadow buffer to the screen
+    pub fn flush_all(&mut self) {
+        self.buffer.chars = self.shadow.chars;
+        self.dirty = [[false; VGA_WIDTH]; VGA_HEIGHT];
+    }
+
     /// Clear the entire screen
     pub fn clear_screen(&mut self) {
         for row in 0..VGA_HEIGHT {
@@ -190,6 +538,36 @@ impl VgaWriter {
         }
         self.row = 0;
         self.column = 0;
+        self.update_cursor(0, 0);
+    }
+}
+
+/// Linear cell index of a screen position, as the CRT controller expects
+const fn cursor_position(row: usize, col: usize) -> u16 {
+    (row * VGA_WIDTH + col) as u16
+}
+
+/// Writes a CRT controller register, once `init` has been called
+fn crtc_write(index: u8, value: u8) {
+    if !INITIALIZED.load(Ordering::Relaxed) {
+        return;
+    }
+    // SAFETY: the CRT controller registers only affect the display
+    unsafe {
+        Port::<u8>::new(CRTC_INDEX).write(index);
+        Port::<u8>::new(CRTC_DATA).write(value);
+    }
+}
+
+/// Reads a CRT controller register, or 0 before `init`
+fn crtc_read(index: u8) -> u8 {
+    if !INITIALIZED.load(Ordering::Relaxed) {
+        return 0;
+    }
+    // SAFETY: the CRT controller registers only affect the display
+    unsafe {
+        Port::<u8>::new(CRTC_INDEX).write(index);
+        Port::<u8>::new(CRTC_DATA).read()
     }
 }
 
@@ -201,7 +579,7 @@ impl fmt::Write for VgaWriter {
 }
 
 /// Global VGA writer
-pub static VGA: Mutex<Option<VgaWriter>> = Mutex::new(None);
+pub static VGA: DetectMutex<Option<VgaWriter>> = DetectMutex::new(None);
 
 /// Initialize the VGA writer
 ///
@@ -212,8 +590,23 @@ pub static VGA: Mutex<Option<VgaWriter>> = Mutex::new(None);
 ///
 /// This function must only be called once during boot.
 pub unsafe fn init() {
-    let mut vga = VGA.lock();
-    *vga = Some(VgaWriter::new());
+    INITIALIZED.store(true, Ordering::Relaxed);
+    let mut writer = VgaWriter::new();
+    writer.enable_cursor(14, 15);
+    writer.update_cursor(0, 0);
+    *VGA.lock() = Some(writer);
+}
+
+/// Copy pending changes to the screen
+///
+/// Called from the timer interrupt, so this does nothing if the writer is
+/// locked; the changes are picked up by the next flush.
+pub fn flush() {
+    if let Some(mut vga) = VGA.try_lock() {
+        if let Some(writer) = vga.as_mut() {
+            writer.flush();
+        }
+    }
 }
 
 /// Write to VGA (for use in macros)
@@ -244,10 +637,213 @@ macro_rules! vga_println {
 /// Write a diagnostic message to the top-right corner of the screen
 ///
 /// This is useful for early boot debugging when you want to indicate
-/// that certain stages of boot have been reached.
+/// that certain stages of boot have been reached. The screen is flushed
+/// right away, as the timer may not be running yet.
 pub fn write_diagnostic(msg: &str) {
     if let Some(ref mut writer) = *VGA.lock() {
         let col = VGA_WIDTH.saturating_sub(msg.len());
         writer.write_at(msg, 0, col);
+        writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interrupts::keyboard;
+
+    #[test_case]
+    fn test_read_line() {
+        while keyboard::read_char().is_some() {}
+        // a, b, Backspace, c, Enter (each pressed and released)
+        for code in [0x1c, 0x32, 0x66, 0x21, 0x5a] {
+            keyboard::handle_scancode(code);
+            keyboard::handle_scancode(0xf0);
+            keyboard::handle_scancode(code);
+        }
+
+        let line = VGA.lock().as_mut().map(VgaWriter::read_line);
+        assert_eq!(line.as_deref(), Some("ac"));
+    }
+
+    #[test_case]
+    fn test_ansi_colors() {
+        let mut vga = VGA.lock();
+        let writer = vga.as_mut().expect("VGA not initialized");
+        writer.clear_screen();
+        writer.write_string("a\x1b[31mb\x1b[1;44mc\x1b[2Jd\x1b[0me\x1b[mf");
+
+        let default = ColorCode::new(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND);
+        let expected = [
+            (b'a', default),
+            (b'b', ColorCode::new(Color::Red, Color::Black)),
+            (b'c', ColorCode::new(Color::Red, Color::Blue)),
+            // The screen clear is consumed without effect
+            (b'd', ColorCode::new(Color::Red, Color::Blue)),
+            (b'e', default),
+            (b'f', default),
+        ];
+        for (col, (ascii, color)) in expected.into_iter().enumerate() {
+            let cell = writer.shadow.chars[0][col];
+            assert_eq!(cell.ascii_character, ascii, "column {}", col);
+            assert_eq!(cell.color_code, color, "column {}", col);
+        }
+        writer.clear_screen();
+    }
+
+    #[test_case]
+    fn test_update_cursor() {
+        assert_eq!(cursor_position(24, 79), 1999);
+
+        let mut vga = VGA.lock();
+        let writer = vga.as_mut().expect("VGA not initialized");
+        let (row, column) = (writer.row, writer.column);
+        writer.update_cursor(24, 79);
+        let position =
+            u16::from(crtc_read(CRTC_CURSOR_HIGH)) << 8 | u16::from(crtc_read(CRTC_CURSOR_LOW));
+        assert_eq!(position, 1999);
+        writer.update_cursor(row, column);
+    }
+
+    /// Fills each row with its own letter and color
+    fn fill_pattern(writer: &mut VgaWriter) {
+        for row in 0..VGA_HEIGHT {
+            writer.fill_region(row, row, pattern_color(row), b'A' + row as u8);
+        }
+    }
+
+    fn pattern_color(row: usize) -> ColorCode {
+        ColorCode::new(Color::from_index(row as u8), Color::Black)
+    }
+
+    /// Checks every cell of every row against `expected(row)`
+    fn assert_rows(writer: &VgaWriter, expected: impl Fn(usize) -> (u8, ColorCode)) {
+        for (row, cells) in writer.shadow.chars.iter().enumerate() {
+            let (ascii, color) = expected(row);
+            for (col, cell) in cells.iter().enumerate() {
+                assert_eq!(cell.ascii_character, ascii, "row {} column {}", row, col);
+                assert_eq!(cell.color_code, color, "row {} column {}", row, col);
+            }
+        }
+    }
+
+    /// Expected cell of an unscrolled pattern row
+    fn pattern(row: usize) -> (u8, ColorCode) {
+        (b'A' + row as u8, pattern_color(row))
+    }
+
+    #[test_case]
+    fn test_scroll_region_up() {
+        let mut vga = VGA.lock();
+        let writer = vga.as_mut().expect("VGA not initialized");
+        fill_pattern(writer);
+        let blank = (b' ', writer.color_code);
+
+        writer.scroll_region(5, 10, 2);
+        assert_rows(writer, |row| match row {
+            5..=8 => pattern(row + 2),
+            9 | 10 => blank,
+            _ => pattern(row),
+        });
+        writer.clear_screen();
+    }
+
+    #[test_case]
+    fn test_scroll_region_down() {
+        let mut vga = VGA.lock();
+        let writer = vga.as_mut().expect("VGA not initialized");
+        fill_pattern(writer);
+        let blank = (b' ', writer.color_code);
+
+        writer.scroll_region(20, 30, -3);
+        assert_rows(writer, |row| match row {
+            20..=22 => blank,
+            23 | 24 => pattern(row - 3),
+            _ => pattern(row),
+        });
+
+        fill_pattern(writer);
+        writer.scroll_region(2, 4, -7);
+        assert_rows(writer, |row| match row {
+            2..=4 => blank,
+            _ => pattern(row),
+        });
+        writer.clear_screen();
+    }
+
+    #[test_case]
+    fn test_new_line_scrolls_screen() {
+        let mut vga = VGA.lock();
+        let writer = vga.as_mut().expect("VGA not initialized");
+        fill_pattern(writer);
+        let blank = (b' ', writer.color_code);
+
+        writer.row = VGA_HEIGHT - 1;
+        writer.new_line();
+        assert_rows(writer, |row| match row {
+            24 => blank,
+            _ => pattern(row + 1),
+        });
+        writer.clear_screen();
+    }
+
+    /// Reads a cell straight from the screen
+    fn screen_cell(row: usize, col: usize) -> ScreenChar {
+        let cells = VGA_BUFFER_ADDR as *const ScreenChar;
+        // SAFETY: the cell lies within the VGA text buffer
+        unsafe { cells.add(row * VGA_WIDTH + col).read_volatile() }
+    }
+
+    #[test_case]
+    fn test_flush_copies_written_text() {
+        let mut vga = VGA.lock();
+        let writer = vga.as_mut().expect("VGA not initialized");
+        writer.clear_screen();
+        writer.flush_all();
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: writer.color_code,
+        };
+
+        writer.write_string("flushed");
+        for col in 0..VGA_WIDTH {
+            assert_eq!(screen_cell(0, col), blank, "column {}", col);
+        }
+
+        writer.flush();
+        for (col, ascii) in b"flushed".iter().enumerate() {
+            let cell = screen_cell(0, col);
+            assert_eq!(cell.ascii_character, *ascii, "column {}", col);
+            assert_eq!(cell.color_code, writer.color_code, "column {}", col);
+        }
+        assert_eq!(screen_cell(0, 7), blank);
+        assert!(writer.dirty.iter().flatten().all(|&dirty| !dirty));
+        writer.clear_screen();
+        writer.flush_all();
+    }
+
+    #[test_case]
+    fn test_flush_skips_clean_cells() {
+        let mut vga = VGA.lock();
+        let writer = vga.as_mut().expect("VGA not initialized");
+        writer.clear_screen();
+        writer.flush_all();
+
+        // A cell changed behind the writer's back stays until a full flush
+        let marker = ScreenChar {
+            ascii_character: b'#',
+            color_code: ColorCode::new(Color::Yellow, Color::Blue),
+        };
+        writer.buffer.chars[5][0] = marker;
+        writer.write_string("x");
+        writer.flush();
+        assert_eq!(screen_cell(0, 0).ascii_character, b'x');
+        assert_eq!(screen_cell(5, 0), marker);
+
+        writer.flush_all();
+        assert_eq!(screen_cell(5, 0), writer.shadow.chars[5][0]);
+        assert_eq!(screen_cell(5, 0).ascii_character, b' ');
+        writer.clear_screen();
+        writer.flush_all();
     }
 }
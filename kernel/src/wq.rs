@@ -0,0 +1,241 @@
+// Copyright 2025 Yomi OS Development Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kernel work queue
+//!
+//! Interrupt handlers must return quickly, so work that takes long or must
+//! not run in interrupt context is submitted to `WORK_QUEUE` instead. The
+//! idle task drains the queue whenever it wakes up.
+//!
+//! Submitting to `WORK_QUEUE` boxes the closure, and interrupt handlers must
+//! not allocate: the interrupted code may hold the allocator lock. They use
+//! `submit_from_irq` instead, which queues a plain function on a lock-free
+//! queue whose nodes are allocated once by `init`.
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+};
+
+use spin::{
+    Mutex,
+    Once,
+};
+
+use crate::sync::MpscQueue;
+
+/// Global work queue, drained by the idle task
+pub static WORK_QUEUE: Mutex<WorkQueue> = Mutex::new(WorkQueue::new());
+
+/// Number of functions interrupt handlers can have queued at once
+pub const IRQ_WORK_CAPACITY: usize = 32;
+
+/// Functions queued by interrupt handlers, drained along with `WORK_QUEUE`
+///
+/// One node more than `IRQ_WORK_CAPACITY`, which the queue keeps as its
+/// dummy.
+static IRQ_WORK: Once<MpscQueue<fn(), { IRQ_WORK_CAPACITY + 1 }>> = Once::new();
+
+/// Deferred unit of work
+pub type Work = Box<dyn FnOnce() + Send>;
+
+/// FIFO queue of deferred closures
+pub struct WorkQueue {
+    pending: VecDeque<Work>,
+}
+
+impl WorkQueue {
+    /// Creates an empty queue
+    pub const fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Queues `work` to run on the next drain
+    pub fn submit(&mut self, work: impl FnOnce() + Send + 'static) {
+        self.pending.push_back(Box::new(work));
+    }
+
+    /// Runs every pending closure in submission order
+    ///
+    /// Closures submitted while draining run as well.
+    ///
+    /// # Returns
+    ///
+    /// The number of closures run.
+    pub fn drain(&mut self) -> usize {
+        let mut count = 0;
+        while let Some(work) = self.pending.pop_front() {
+            work();
+            count += 1;
+        }
+        count
+    }
+
+    /// Takes the next pending closure without running it
+    pub fn pop(&mut self) -> Option<Work> {
+        self.pending.pop_front()
+    }
+
+    /// Number of pending closures
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns `true` if nothing is pending
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl Default for WorkQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Queues `work` on `WORK_QUEUE`
+///
+/// Must not be called from interrupt handlers: it takes the lock and boxes
+/// `work`, either of which can deadlock if the interrupted code holds the
+/// queue or allocator lock. They use `submit_from_irq` instead.
+pub fn submit(work: impl FnOnce() + Send + 'static) {
+    crate::interrupts::without_interrupts(|| WORK_QUEUE.lock().submit(work));
+}
+
+/// Allocates the queue behind `submit_from_irq`
+///
+/// Must be called once the heap is initialized; until then
+/// `submit_from_irq` fails.
+pub fn init() {
+    IRQ_WORK.call_once(MpscQueue::new);
+}
+
+/// Queues `work` from an interrupt handler
+///
+/// Takes no lock and does not allocate, so it is safe in any interrupt
+/// context. `work` runs on the next `drain`.
+///
+/// # Errors
+///
+/// Returns `work` back if `init` has not run or `IRQ_WORK_CAPACITY`
+/// functions are already queued.
+pub fn submit_from_irq(work: fn()) -> Result<(), fn()> {
+    match IRQ_WORK.get() {
+        Some(queue) => queue.push(work),
+        None => Err(work),
+    }
+}
+
+/// Runs the work pending on `WORK_QUEUE` and from `submit_from_irq`
+///
+/// The lock is only held to take each closure, so closures run with
+/// interrupts enabled and may submit more work. Work submitted meanwhile
+/// waits for the next call, so an interrupt storm cannot starve the caller.
+///
+/// # Returns
+///
+/// The number of closures and functions run.
+pub fn drain() -> usize {
+    let mut count = 0;
+    if let Some(queue) = IRQ_WORK.get() {
+        for _ in 0..queue.len() {
+            let Some(work) = queue.pop() else {
+                break;
+            };
+            work();
+            count += 1;
+        }
+    }
+
+    let pending = count + crate::interrupts::without_interrupts(|| WORK_QUEUE.lock().len());
+    while count < pending {
+        let Some(work) = crate::interrupts::without_interrupts(|| WORK_QUEUE.lock().pop()) else {
+            break;
+        };
+        work();
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+
+    use super::*;
+
+    #[test_case]
+    fn test_drain_runs_all_pending_work() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut queue = WorkQueue::new();
+        for _ in 0..5 {
+            let counter = counter.clone();
+            queue.submit(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        assert_eq!(queue.len(), 5);
+        assert_eq!(counter.load(Ordering::Relaxed), 0);
+
+        assert_eq!(queue.drain(), 5);
+        assert_eq!(counter.load(Ordering::Relaxed), 5);
+        assert!(queue.is_empty());
+        assert_eq!(queue.drain(), 0);
+    }
+
+    #[test_case]
+    fn test_work_runs_in_submission_order() {
+        let order = Arc::new(Mutex::new(alloc::vec::Vec::new()));
+        let mut queue = WorkQueue::new();
+        for i in 0..3 {
+            let order = order.clone();
+            queue.submit(move || order.lock().push(i));
+        }
+        queue.drain();
+        assert_eq!(*order.lock(), [0, 1, 2]);
+    }
+
+    #[test_case]
+    fn test_global_drain() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..5 {
+            let counter = counter.clone();
+            submit(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        assert!(drain() >= 5);
+        assert_eq!(counter.load(Ordering::Relaxed), 5);
+    }
+
+    #[test_case]
+    fn test_irq_work_runs_on_drain() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        fn work() {
+            RUNS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        init();
+        let before = RUNS.load(Ordering::Relaxed);
+        assert!(submit_from_irq(work).is_ok());
+        assert!(drain() >= 1);
+        assert_eq!(RUNS.load(Ordering::Relaxed), before + 1);
+    }
+}
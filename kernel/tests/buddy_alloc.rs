@@ -0,0 +1,156 @@
+//! Buddy allocator integration test
+//!
+//! This test drives a `BuddyAllocator` over a private arena with a mixed
+//! allocation pattern, checking that blocks never overlap, that the usage
+//! accounting stays consistent and that freed blocks coalesce again. It
+//! also checks alignment and exhaustion behavior of the kernel heap.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{
+    alloc::{
+        alloc,
+        dealloc,
+    },
+    vec::Vec,
+};
+use core::{
+    alloc::Layout,
+    panic::PanicInfo,
+};
+
+use yomi_kernel::memory::{
+    allocator::BuddyAllocator,
+    heap::HEAP_SIZE,
+};
+
+/// Number of objects allocated from the arena
+const OBJECTS: usize = 1024;
+
+/// Object sizes, cycled through; rounded up these average about 1 KiB
+const SIZES: [usize; 11] = [8, 16, 24, 64, 100, 256, 512, 1000, 2048, 3000, 4096];
+
+/// Arena size: one top-level block, comfortably above the ~1.1 MiB needed
+const ARENA_SIZE: usize = 2 * 1024 * 1024;
+
+#[repr(C, align(2097152))]
+struct Arena([u8; ARENA_SIZE]);
+
+static mut ARENA: Arena = Arena([0; ARENA_SIZE]);
+
+/// Entry point for buddy allocator test
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler for test mode
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    yomi_kernel::testing::test_panic_handler(info)
+}
+
+fn layout(i: usize) -> Layout {
+    Layout::from_size_align(SIZES[i % SIZES.len()], 8).unwrap()
+}
+
+/// Allocates object `i` and fills it with a byte derived from `i`
+fn allocate(buddy: &mut BuddyAllocator, i: usize) -> *mut u8 {
+    let ptr = unsafe { buddy.allocate(layout(i)) };
+    assert!(!ptr.is_null(), "arena exhausted at object {}", i);
+    unsafe { ptr.write_bytes(i as u8, layout(i).size()) };
+    ptr
+}
+
+/// Checks that the live objects do not overlap and kept their contents
+fn check_objects(objects: &[Option<*mut u8>]) {
+    let mut ranges: Vec<(usize, usize)> = objects
+        .iter()
+        .enumerate()
+        .filter_map(|(i, ptr)| ptr.map(|p| (p as usize, p as usize + layout(i).size())))
+        .collect();
+    ranges.sort_unstable();
+    for pair in ranges.windows(2) {
+        assert!(pair[0].1 <= pair[1].0, "objects overlap");
+    }
+
+    for (i, ptr) in objects.iter().enumerate() {
+        if let Some(ptr) = *ptr {
+            let bytes = unsafe { core::slice::from_raw_parts(ptr, layout(i).size()) };
+            assert!(
+                bytes.iter().all(|&b| b == i as u8),
+                "object {} corrupted",
+                i
+            );
+        }
+    }
+}
+
+#[test_case]
+fn test_mixed_allocation_pattern() {
+    let mut buddy = BuddyAllocator::new();
+    unsafe { buddy.init(core::ptr::addr_of_mut!(ARENA) as usize, ARENA_SIZE) };
+    let total = buddy.usage().total;
+    assert_eq!(total, ARENA_SIZE);
+
+    let mut objects: Vec<Option<*mut u8>> = (0..OBJECTS)
+        .map(|i| Some(allocate(&mut buddy, i)))
+        .collect();
+    check_objects(&objects);
+
+    // Free every other object, then allocate the same sizes again
+    for i in (0..OBJECTS).step_by(2) {
+        let ptr = objects[i].take().unwrap();
+        unsafe { buddy.deallocate(ptr, layout(i)) };
+    }
+    let usage = buddy.usage();
+    assert_eq!(usage.allocations, OBJECTS / 2);
+    assert_eq!(usage.used + usage.free, total);
+
+    for i in (0..OBJECTS).step_by(2) {
+        objects[i] = Some(allocate(&mut buddy, i));
+    }
+    check_objects(&objects);
+    let usage = buddy.usage();
+    assert_eq!(usage.allocations, OBJECTS);
+    assert_eq!(usage.used + usage.free, total);
+
+    // Freeing everything coalesces the arena back into one block
+    for (i, ptr) in objects.iter_mut().enumerate() {
+        unsafe { buddy.deallocate(ptr.take().unwrap(), layout(i)) };
+    }
+    let usage = buddy.usage();
+    assert_eq!(usage.used, 0);
+    assert_eq!(usage.allocations, 0);
+    assert_eq!(usage.largest_free, total);
+}
+
+#[test_case]
+fn test_page_aligned_allocation() {
+    let layout = Layout::from_size_align(4096, 4096).unwrap();
+    let ptr = unsafe { alloc(layout) };
+    assert!(!ptr.is_null());
+    assert_eq!(ptr as usize % 4096, 0);
+    unsafe { dealloc(ptr, layout) };
+}
+
+#[test_case]
+fn test_oversized_allocation_returns_null() {
+    let layout = Layout::from_size_align(HEAP_SIZE + 1, 8).unwrap();
+    let ptr = unsafe { alloc(layout) };
+    assert!(ptr.is_null());
+}
@@ -0,0 +1,82 @@
+//! Demand paging integration test
+//!
+//! Registers a demand area with the running process and touches it. The
+//! page fault handler must back each page with a zeroed frame and resume
+//! the access instead of panicking.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use yomi_kernel::{
+    Page,
+    PageTableFlags,
+    PageTableManager,
+    VirtAddr,
+    process::{
+        self,
+        SCHEDULER,
+        VmArea,
+        VmKind,
+    },
+};
+
+/// Start of the demand area, in an otherwise unused part of the lower half
+const DEMAND_BASE: u64 = 0x3000_0000_0000;
+
+/// Number of pages in the demand area
+const DEMAND_PAGES: u64 = 4;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+    process::init();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    yomi_kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_demand_area_is_mapped_on_access() {
+    let area = VmArea::new(
+        VirtAddr::new(DEMAND_BASE),
+        DEMAND_PAGES * Page::SIZE,
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+        VmKind::Demand,
+    );
+    yomi_kernel::interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let pid = scheduler.current().unwrap();
+        let process = scheduler.table_mut().get_mut(pid).unwrap();
+        process.vm_areas_mut().insert(area).unwrap();
+    });
+
+    let mapper = unsafe { PageTableManager::current() };
+    let last_page = DEMAND_BASE + (DEMAND_PAGES - 1) * Page::SIZE;
+    assert!(mapper.translate_addr(VirtAddr::new(last_page)).is_none());
+
+    let ptr = (last_page + 0x80) as *mut u64;
+    // SAFETY: the address lies in the demand area registered above
+    unsafe {
+        assert_eq!(ptr.read_volatile(), 0);
+        ptr.write_volatile(0xdead_beef);
+        assert_eq!(ptr.read_volatile(), 0xdead_beef);
+    }
+    assert!(mapper.translate_addr(VirtAddr::new(last_page)).is_some());
+    // Only the touched page was mapped
+    assert!(mapper.translate_addr(VirtAddr::new(DEMAND_BASE)).is_none());
+}
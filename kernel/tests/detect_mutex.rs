@@ -0,0 +1,75 @@
+//! Deadlock detection integration test
+//!
+//! This test locks a `DetectMutex` twice on the same CPU. In debug builds
+//! the second `lock` must panic instead of spinning forever; the panic
+//! handler checks the message and reports success. Release builds have no
+//! detection, so the test only checks that the lock works.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use yomi_kernel::{
+    serial_print,
+    serial_println,
+    sync::DetectMutex,
+    testing::{
+        MessageBuffer,
+        QemuExitCode,
+        exit_qemu,
+    },
+};
+
+static LOCK: DetectMutex<u32> = DetectMutex::new(0);
+
+/// Entry point for deadlock detection test
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler: the expected deadlock report ends up here
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let message = MessageBuffer::from_panic(info);
+    let message = message.as_str();
+
+    if message.contains("reentrant lock") && message.contains("tests/detect_mutex.rs") {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success)
+    }
+    serial_println!("[failed]");
+    serial_println!("unexpected panic: {}", message);
+    exit_qemu(QemuExitCode::Failed)
+}
+
+#[test_case]
+fn test_reentrant_lock_panics() {
+    serial_print!("detect_mutex::test_reentrant_lock_panics...\t");
+
+    let mut first = LOCK.lock();
+    *first += 1;
+
+    if cfg!(not(debug_assertions)) {
+        drop(first);
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    }
+
+    let _second = LOCK.lock();
+
+    serial_println!("[failed: second lock did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+}
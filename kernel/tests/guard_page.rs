@@ -0,0 +1,77 @@
+//! Guard page integration test
+//!
+//! This test turns a page into a guard page and writes to it. The write
+//! must raise a page fault, which panics; the panic handler reports
+//! success. Reaching the end of the test means the write went through.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use yomi_kernel::{
+    Page,
+    PageTableManager,
+    VirtAddr,
+    memory::HeapFrameAllocator,
+    serial_print,
+    serial_println,
+    testing::{
+        QemuExitCode,
+        exit_qemu,
+    },
+};
+
+/// Page-aligned page to turn into a guard page
+#[repr(C, align(4096))]
+struct GuardTarget([u8; 4096]);
+
+static mut TARGET: GuardTarget = GuardTarget([0; 4096]);
+
+/// Entry point for guard page test
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler: the expected page fault ends up here
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success)
+}
+
+#[test_case]
+fn test_write_to_guard_page_faults() {
+    serial_print!("guard_page::test_write_to_guard_page_faults...\t");
+
+    let addr = VirtAddr::new(core::ptr::addr_of!(TARGET) as u64);
+    let mut mapper = unsafe { PageTableManager::current() };
+    if mapper
+        .map_guard_page(
+            Page::containing_address(addr),
+            &mut HeapFrameAllocator::new(),
+        )
+        .is_err()
+        || mapper.translate_addr(addr).is_none()
+    {
+        serial_println!("[failed: guard page not mapped]");
+        exit_qemu(QemuExitCode::Failed);
+    }
+
+    unsafe { core::ptr::addr_of_mut!(TARGET.0[0]).write_volatile(1) };
+
+    serial_println!("[failed: write did not fault]");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -101,3 +101,28 @@ fn test_vec_with_strings() {
     assert_eq!(v[0], "Hello");
     assert_eq!(v[1], "World");
 }
+
+#[test_case]
+fn test_freed_memory_is_reused() {
+    use yomi_kernel::memory::{
+        heap::heap_usage,
+        kasan,
+    };
+
+    // Warm up first: KASAN may grow its allocation records once
+    drop(Box::new([0u64; 8]));
+
+    let before = heap_usage().used;
+    let mut peak = before;
+    for i in 0..1000 {
+        let b = Box::new([i as u64; 8]);
+        peak = peak.max(heap_usage().used);
+        drop(b);
+    }
+
+    // Each box is freed before the next one is allocated. With KASAN the
+    // redzones push a 64-byte box into the next block size.
+    let block = if kasan::ENABLED { 128 } else { 64 };
+    assert!(peak - before <= block);
+    assert_eq!(heap_usage().used, before);
+}
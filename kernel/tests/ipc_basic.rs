@@ -0,0 +1,124 @@
+//! IPC round-trip integration test
+//!
+//! This test sends messages between two processes in a process table and
+//! checks the state transitions and payloads along the way.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+
+use yomi_kernel::process::{
+    Capability,
+    CapabilityRights,
+    CapabilityType,
+    IpcError,
+    Message,
+    Process,
+    ProcessId,
+    ProcessState,
+    ProcessTable,
+    ipc::{
+        self,
+        MESSAGE_WORDS,
+    },
+};
+
+/// Entry point for IPC test
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    // Initialize kernel subsystems
+    yomi_kernel::init();
+
+    // Run tests
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler for test mode
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    yomi_kernel::testing::test_panic_handler(info)
+}
+
+/// Receiver
+const A: ProcessId = ProcessId::new(1);
+/// Sender
+const B: ProcessId = ProcessId::new(2);
+
+/// Creates a table with `A` and `B`, where `B` may send to `A` and to
+/// `missing`
+fn table_with_sender(missing: ProcessId) -> ProcessTable {
+    let mut table = ProcessTable::new();
+    table.add_process(Process::new(A, "a")).unwrap();
+
+    let mut sender = Process::new(B, "b");
+    for target in [A, missing] {
+        sender.capabilities_mut().insert(Capability::new(
+            CapabilityType::Endpoint,
+            target.as_u64(),
+            CapabilityRights::WRITE,
+        ));
+    }
+    table.add_process(sender).unwrap();
+    table
+}
+
+#[test_case]
+fn test_send_wakes_receiver() {
+    let mut table = table_with_sender(ProcessId::new(3));
+
+    // A finds its queue empty and waits
+    assert_eq!(ipc::receive(&mut table, A), None);
+    assert_eq!(
+        table.get(A).unwrap().state(),
+        ProcessState::WaitingForMessage
+    );
+
+    let msg = Message::new([42, 1, 2, 3]);
+    ipc::send(&mut table, B, A, msg.clone()).unwrap();
+    assert_eq!(table.get(A).unwrap().state(), ProcessState::Ready);
+
+    let received = ipc::receive(&mut table, A).unwrap();
+    assert_eq!(received.sender(), B);
+    assert_eq!(received.data, msg.data);
+    assert_eq!(table.get(A).unwrap().pending_messages(), 0);
+}
+
+#[test_case]
+fn test_full_payload_round_trip() {
+    let mut table = table_with_sender(ProcessId::new(3));
+
+    // Every word of the largest payload, with all bits in use
+    let mut data = [0; MESSAGE_WORDS];
+    for (i, word) in data.iter_mut().enumerate() {
+        *word = u64::MAX - i as u64;
+    }
+    ipc::send(&mut table, B, A, Message::new(data)).unwrap();
+
+    let received = ipc::receive(&mut table, A).unwrap();
+    assert_eq!(received.sender(), B);
+    assert_eq!(received.data, data);
+}
+
+#[test_case]
+fn test_send_to_missing_pid() {
+    let missing = ProcessId::new(99);
+    let mut table = table_with_sender(missing);
+
+    assert_eq!(
+        ipc::send(&mut table, B, missing, Message::new([0; MESSAGE_WORDS])),
+        Err(IpcError::RecipientNotFound)
+    );
+    assert_eq!(table.get(B).unwrap().state(), ProcessState::Ready);
+}
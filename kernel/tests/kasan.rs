@@ -0,0 +1,80 @@
+//! KASAN integration test
+//!
+//! This test overflows a heap buffer by one byte and checks it. In debug
+//! builds KASAN must find the overwritten redzone and panic; the panic
+//! handler checks the message and reports success. Release builds have no
+//! KASAN, so the test only checks that the check is a no-op.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec;
+use core::panic::PanicInfo;
+
+use yomi_kernel::{
+    memory::kasan::{
+        self,
+        kasan_check,
+    },
+    serial_print,
+    serial_println,
+    testing::{
+        MessageBuffer,
+        QemuExitCode,
+        exit_qemu,
+    },
+};
+
+/// Entry point for KASAN test
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler: the expected KASAN report ends up here
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let message = MessageBuffer::from_panic(info);
+    let message = message.as_str();
+
+    if message.contains("KASAN: heap overflow") {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success)
+    }
+    serial_println!("[failed]");
+    serial_println!("unexpected panic: {}", message);
+    exit_qemu(QemuExitCode::Failed)
+}
+
+#[test_case]
+fn test_one_byte_overflow_is_detected() {
+    serial_print!("kasan::test_one_byte_overflow_is_detected...\t");
+
+    let mut buffer = vec![0u8; 13];
+    kasan_check(buffer.as_ptr(), buffer.len());
+
+    if !kasan::ENABLED {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    }
+
+    // SAFETY: deliberately out of bounds; the byte lands in the redzone
+    unsafe { buffer.as_mut_ptr().add(13).write_volatile(0x41) };
+    kasan_check(buffer.as_ptr(), buffer.len());
+
+    serial_println!("[failed: overflow not detected]");
+    exit_qemu(QemuExitCode::Failed);
+}
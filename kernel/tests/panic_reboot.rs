@@ -0,0 +1,42 @@
+//! Panic reboot integration test
+//!
+//! `xtask test` builds this test with `--cfg panic_reboot_secs="1"`. The
+//! induced panic goes through the kernel's own panic handler, which counts
+//! down a second and resets the machine. QEMU runs with `-no-reboot`, so
+//! the reset makes it exit.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use yomi_kernel::panic::PANIC_REBOOT_SECS;
+
+/// Entry point for panic reboot test
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// The kernel panic handler, which is what this test exercises
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    yomi_kernel::panic::panic_handler(info)
+}
+
+#[test_case]
+fn test_panic_reboots() {
+    assert_eq!(PANIC_REBOOT_SECS, 1, "built without panic_reboot_secs");
+    panic!("induced panic");
+}
@@ -0,0 +1,70 @@
+//! PCI enumeration integration test
+//!
+//! This test enumerates the PCI buses of the QEMU machine, which has a
+//! PIIX3 PCI-ISA bridge and the VirtIO device added by the test runner.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+
+use yomi_kernel::drivers::pci;
+
+/// Intel's PCI vendor ID
+const VENDOR_INTEL: u16 = 0x8086;
+/// Red Hat's (VirtIO) PCI vendor ID
+const VENDOR_VIRTIO: u16 = 0x1af4;
+/// Device ID of the PIIX3 PCI-ISA bridge
+const DEVICE_PIIX3_ISA: u16 = 0x7000;
+
+/// Entry point for PCI enumeration test
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler for test mode
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    yomi_kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_finds_piix3_isa_bridge() {
+    let devices = pci::enumerate();
+    let bridge = devices
+        .iter()
+        .find(|d| d.vendor_id == VENDOR_INTEL && d.device_id == DEVICE_PIIX3_ISA)
+        .expect("PIIX3 PCI-ISA bridge not found");
+    // Bridge device, PCI-to-ISA bridge
+    assert_eq!((bridge.class, bridge.subclass), (0x06, 0x01));
+}
+
+#[test_case]
+fn test_finds_virtio_device() {
+    assert!(
+        pci::enumerate()
+            .iter()
+            .any(|d| d.vendor_id == VENDOR_VIRTIO)
+    );
+}
+
+#[test_case]
+fn test_host_bridge_is_first() {
+    let devices = pci::enumerate();
+    let first = devices.first().expect("no PCI devices found");
+    assert_eq!((first.bus, first.slot, first.func), (0, 0, 0));
+}
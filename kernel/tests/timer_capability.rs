@@ -0,0 +1,95 @@
+//! Timer capability integration test
+//!
+//! Sleeping requires a `Timer` capability with the `SLEEP` right. This
+//! test grants one to a process, sleeps on its behalf, then revokes it and
+//! checks that sleeping is refused.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+
+use yomi_kernel::{
+    process::{
+        Capability,
+        CapabilityError,
+        CapabilityRights,
+        CapabilityTree,
+        CapabilityType,
+        Process,
+        ProcessId,
+        ProcessTable,
+        TIMER_OBJECT_ID,
+    },
+    time,
+};
+
+/// Entry point for the timer capability test
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler for test mode
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    yomi_kernel::testing::test_panic_handler(info)
+}
+
+/// Returns a table holding one process with a timer capability
+fn table_with_timer_cap(rights: CapabilityRights) -> (ProcessTable, ProcessId, Capability) {
+    let mut table = ProcessTable::new();
+    let pid = table.allocate_pid().unwrap();
+    table.add_process(Process::new(pid, "sleeper")).unwrap();
+
+    let cap = Capability::new(CapabilityType::Timer, TIMER_OBJECT_ID, rights);
+    table.get_mut(pid).unwrap().capabilities_mut().insert(cap);
+    (table, pid, cap)
+}
+
+/// Revokes the timer capability `cap` held by `pid`
+fn revoke_timer_cap(table: &mut ProcessTable, cap: &Capability) {
+    assert_eq!(CapabilityTree::new().revoke(table, cap.cap_id()), 1);
+}
+
+#[test_case]
+fn test_sleep_with_timer_cap() {
+    let (table, pid, _) = table_with_timer_cap(CapabilityRights::all());
+    let caps = table.get(pid).unwrap().capabilities();
+    assert_eq!(time::sleep_ms(caps, 1), Ok(()));
+}
+
+#[test_case]
+fn test_sleep_after_revocation() {
+    let (mut table, pid, cap) = table_with_timer_cap(CapabilityRights::all());
+    revoke_timer_cap(&mut table, &cap);
+
+    let caps = table.get(pid).unwrap().capabilities();
+    assert_eq!(
+        time::sleep_ms(caps, 1),
+        Err(CapabilityError::PermissionDenied)
+    );
+}
+
+#[test_case]
+fn test_sleep_without_sleep_right() {
+    let (table, pid, _) =
+        table_with_timer_cap(CapabilityRights::READ_UPTIME | CapabilityRights::SET_TIMER);
+    let caps = table.get(pid).unwrap().capabilities();
+    assert_eq!(
+        time::sleep_ms(caps, 1),
+        Err(CapabilityError::PermissionDenied)
+    );
+}
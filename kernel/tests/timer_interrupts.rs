@@ -0,0 +1,65 @@
+//! Timer interrupt integration test
+//!
+//! Checks that the timer keeps firing once interrupt delivery has moved
+//! from the 8259 PIC to the local APIC.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{
+    panic::PanicInfo,
+    sync::atomic::Ordering,
+};
+
+use yomi_kernel::interrupts::{
+    self,
+    apic,
+    apic_timer,
+    timer,
+};
+
+/// Entry point for timer interrupt tests
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+    interrupts::enable_timer_interrupts();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler for integration tests
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    yomi_kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_apic_enabled() {
+    assert_eq!(apic::is_enabled(), apic::is_available());
+    if apic::is_enabled() {
+        assert!(apic_timer::APIC_TICKS_PER_MS.load(Ordering::Relaxed) > 0);
+    }
+}
+
+#[test_case]
+fn test_timer_fires() {
+    let start = timer::ticks();
+    // Each `hlt` returns on the next interrupt, so this waits for at
+    // least a few timer periods
+    for _ in 0..1000 {
+        unsafe { core::arch::asm!("hlt") };
+        if timer::ticks() >= start + 3 {
+            return;
+        }
+    }
+    panic!("timer did not fire: {} ticks", timer::ticks() - start);
+}
@@ -0,0 +1,170 @@
+//! User mode integration test
+//!
+//! Maps a small code stub and a stack into user pages, drops to ring 3 and
+//! lets the stub issue a `uname` system call followed by `hlt`. The kernel
+//! must serve the system call and then take the #GP that `hlt` raises in
+//! ring 3 on the stack installed with `set_kernel_stack`; the panic handler
+//! checks both and reports success.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yomi_kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use yomi_kernel::{
+    Page,
+    PageTableFlags,
+    PageTableManager,
+    VirtAddr,
+    cpu::security::with_user_access,
+    interrupts::{
+        self,
+        syscall::SyscallNumber,
+    },
+    memory::{
+        FrameAllocator,
+        HeapFrameAllocator,
+    },
+    process::{
+        ProcessContext,
+        enter_usermode,
+    },
+    serial_print,
+    serial_println,
+    testing::{
+        MessageBuffer,
+        QemuExitCode,
+        exit_qemu,
+    },
+};
+
+/// User page holding the code stub, in an otherwise unused part of the
+/// lower half
+const USER_CODE: u64 = 0x2000_0000_0000;
+
+/// User page holding the stack
+const USER_STACK: u64 = USER_CODE + Page::SIZE;
+
+/// Buffer the stub passes to `uname`, behind the code in the same page
+const USER_BUFFER: u64 = USER_CODE + 0x800;
+
+/// Length of `USER_BUFFER`
+const USER_BUFFER_LEN: u32 = 64;
+
+/// Size of the kernel stack entries from ring 3 run on
+const KERNEL_STACK_SIZE: usize = 16 * 1024;
+
+/// Stack installed with `interrupts::set_kernel_stack`
+static mut KERNEL_STACK: [u8; KERNEL_STACK_SIZE] = [0; KERNEL_STACK_SIZE];
+
+/// Set right before the switch to ring 3
+static ENTERED_USER_MODE: AtomicBool = AtomicBool::new(false);
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    yomi_kernel::init();
+
+    test_main();
+
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Panic handler: the #GP raised by `hlt` in ring 3 ends up here
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let message = MessageBuffer::from_panic(info);
+    let message = message.as_str();
+
+    if ENTERED_USER_MODE.load(Ordering::SeqCst) && message.contains("GENERAL PROTECTION FAULT") {
+        let version = yomi_kernel::kernel_version_string().as_bytes();
+        let n = version.len().min(USER_BUFFER_LEN as usize);
+        // SAFETY: `USER_BUFFER` lies in the user page mapped by the test
+        let copied = with_user_access(|| unsafe {
+            core::slice::from_raw_parts(USER_BUFFER as *const u8, n) == &version[..n]
+        });
+        if copied {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success)
+        }
+        serial_println!("[failed: uname did not reach the kernel]");
+        exit_qemu(QemuExitCode::Failed)
+    }
+    serial_println!("[failed]");
+    serial_println!("unexpected panic: {}", message);
+    exit_qemu(QemuExitCode::Failed)
+}
+
+/// Machine code of the user stub
+///
+/// ```text
+/// mov eax, SyscallNumber::Uname
+/// mov rdi, USER_BUFFER
+/// mov esi, USER_BUFFER_LEN
+/// syscall
+/// hlt
+/// ```
+fn stub() -> [u8; 23] {
+    let mut code = [0u8; 23];
+    code[0] = 0xb8;
+    code[1..5].copy_from_slice(&(SyscallNumber::Uname as u32).to_le_bytes());
+    code[5..7].copy_from_slice(&[0x48, 0xbf]);
+    code[7..15].copy_from_slice(&USER_BUFFER.to_le_bytes());
+    code[15] = 0xbe;
+    code[16..20].copy_from_slice(&USER_BUFFER_LEN.to_le_bytes());
+    code[20..23].copy_from_slice(&[0x0f, 0x05, 0xf4]);
+    code
+}
+
+/// Maps a zeroed user page at `addr`
+fn map_user_page(addr: u64, mapper: &mut PageTableManager) {
+    let mut frames = HeapFrameAllocator::new();
+    let frame = frames.allocate_frame().expect("out of frames");
+    let flags =
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    mapper
+        .map_page(
+            Page::containing_address(VirtAddr::new(addr)),
+            frame,
+            flags,
+            &mut frames,
+        )
+        .expect("failed to map user page");
+}
+
+#[test_case]
+fn test_syscall_from_ring3() {
+    serial_print!("usermode::test_syscall_from_ring3...\t");
+
+    // SAFETY: CR3 holds the active, identity-accessible P4 table
+    let mut mapper = unsafe { PageTableManager::current() };
+    map_user_page(USER_CODE, &mut mapper);
+    map_user_page(USER_STACK, &mut mapper);
+
+    let code = stub();
+    // SAFETY: the page was just mapped writable for user mode
+    with_user_access(|| unsafe {
+        core::ptr::copy_nonoverlapping(code.as_ptr(), USER_CODE as *mut u8, code.len());
+    });
+
+    // The array is only byte-aligned; the top must be 16-byte aligned
+    let stack_top = (core::ptr::addr_of!(KERNEL_STACK) as u64 + KERNEL_STACK_SIZE as u64) & !0xf;
+    interrupts::set_kernel_stack(stack_top);
+
+    let context = ProcessContext::new_user(USER_CODE, USER_STACK + Page::SIZE);
+    ENTERED_USER_MODE.store(true, Ordering::SeqCst);
+    // SAFETY: both pages are user-accessible and a kernel stack is set
+    unsafe { enter_usermode(&context) }
+}
@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::util::{
+    print_info,
+    print_step,
+    print_success,
+    project_root,
+    run_cmd,
+};
+
+/// What `xtask clean` removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleanScope {
+    /// The ISO image and its staging directory
+    pub iso: bool,
+    /// Cargo build artifacts
+    pub deps: bool,
+}
+
+impl CleanScope {
+    /// Everything, as with `--all`: Cargo build artifacts, the whole
+    /// `build` directory and the ISO and disk images
+    pub const ALL: Self = Self {
+        iso: true,
+        deps: true,
+    };
+
+    /// Scope selected by the `--iso`, `--deps` and `--all` flags, or `None`
+    /// if none was given
+    pub fn from_flags(iso: bool, deps: bool, all: bool) -> Option<Self> {
+        if all {
+            Some(Self::ALL)
+        } else if iso || deps {
+            Some(Self { iso, deps })
+        } else {
+            None
+        }
+    }
+}
+
+/// File system operations done by `clean`
+trait CleanFs {
+    fn exists(&self, path: &Path) -> bool;
+    fn remove_file(&mut self, path: &Path) -> Result<()>;
+    fn remove_dir_all(&mut self, path: &Path) -> Result<()>;
+    fn cargo_clean(&mut self) -> Result<()>;
+}
+
+/// Deletes for real
+struct RealFs;
+
+impl CleanFs for RealFs {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn remove_file(&mut self, path: &Path) -> Result<()> {
+        print_info(&format!("Removing {}...", path.display()));
+        Ok(std::fs::remove_file(path)?)
+    }
+
+    fn remove_dir_all(&mut self, path: &Path) -> Result<()> {
+        print_info(&format!("Removing {}...", path.display()));
+        Ok(std::fs::remove_dir_all(path)?)
+    }
+
+    fn cargo_clean(&mut self) -> Result<()> {
+        run_cmd("cargo", &["clean"])?;
+        Ok(())
+    }
+}
+
+/// Prints what would be deleted (`--dry-run`)
+struct DryRunFs;
+
+impl CleanFs for DryRunFs {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn remove_file(&mut self, path: &Path) -> Result<()> {
+        print_info(&format!("Would remove {}", path.display()));
+        Ok(())
+    }
+
+    fn remove_dir_all(&mut self, path: &Path) -> Result<()> {
+        print_info(&format!("Would remove {}", path.display()));
+        Ok(())
+    }
+
+    fn cargo_clean(&mut self) -> Result<()> {
+        print_info("Would run: cargo clean");
+        Ok(())
+    }
+}
+
+/// Clean build artifacts
+pub fn clean(scope: CleanScope, dry_run: bool) -> Result<()> {
+    print_step("Cleaning Build Artifacts");
+
+    let root = project_root()?;
+    if dry_run {
+        clean_with(&mut DryRunFs, &root, scope)?;
+    } else {
+        clean_with(&mut RealFs, &root, scope)?;
+        print_success("Clean complete");
+    }
+    Ok(())
+}
+
+fn clean_with(fs: &mut impl CleanFs, root: &Path, scope: CleanScope) -> Result<()> {
+    if scope.deps {
+        fs.cargo_clean()?;
+    }
+
+    let (dirs, files): (&[&str], &[&str]) = if scope == CleanScope::ALL {
+        (&["build"], &["yomios.iso", "yomios.img", "yomios.qcow2"])
+    } else if scope.iso {
+        (&["build/iso"], &["yomios.iso"])
+    } else {
+        (&[], &[])
+    };
+
+    for dir in dirs {
+        let dir = root.join(dir);
+        if fs.exists(&dir) {
+            fs.remove_dir_all(&dir)?;
+        }
+    }
+    for file in files {
+        let file = root.join(file);
+        if fs.exists(&file) {
+            fs.remove_file(&file)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Records removals instead of doing them
+    #[derive(Default)]
+    struct MockFs {
+        existing: Vec<PathBuf>,
+        removed_files: Vec<PathBuf>,
+        removed_dirs: Vec<PathBuf>,
+        cargo_cleaned: bool,
+    }
+
+    impl MockFs {
+        fn with_build_outputs(root: &Path) -> Self {
+            Self {
+                existing: vec![
+                    root.join("build"),
+                    root.join("build/iso"),
+                    root.join("build/disk"),
+                    root.join("yomios.iso"),
+                    root.join("yomios.img"),
+                    root.join("yomios.qcow2"),
+                    root.join("target"),
+                ],
+                ..Self::default()
+            }
+        }
+    }
+
+    impl CleanFs for MockFs {
+        fn exists(&self, path: &Path) -> bool {
+            self.existing.iter().any(|existing| existing == path)
+        }
+
+        fn remove_file(&mut self, path: &Path) -> Result<()> {
+            self.removed_files.push(path.to_path_buf());
+            Ok(())
+        }
+
+        fn remove_dir_all(&mut self, path: &Path) -> Result<()> {
+            self.removed_dirs.push(path.to_path_buf());
+            Ok(())
+        }
+
+        fn cargo_clean(&mut self) -> Result<()> {
+            self.cargo_cleaned = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn iso_scope_removes_only_iso_paths() {
+        let root = Path::new("/yomi");
+        let mut fs = MockFs::with_build_outputs(root);
+        let scope = CleanScope::from_flags(true, false, false).unwrap();
+        clean_with(&mut fs, root, scope).unwrap();
+
+        assert_eq!(fs.removed_files, [root.join("yomios.iso")]);
+        assert_eq!(fs.removed_dirs, [root.join("build/iso")]);
+        assert!(!fs.cargo_cleaned);
+    }
+
+    #[test]
+    fn all_scope_removes_build_dir_and_images() {
+        let root = Path::new("/yomi");
+        let mut fs = MockFs::with_build_outputs(root);
+        let scope = CleanScope::from_flags(false, false, true).unwrap();
+        clean_with(&mut fs, root, scope).unwrap();
+
+        assert_eq!(fs.removed_files, [
+            root.join("yomios.iso"),
+            root.join("yomios.img"),
+            root.join("yomios.qcow2"),
+        ]);
+        assert_eq!(fs.removed_dirs, [root.join("build")]);
+        assert!(fs.cargo_cleaned);
+    }
+
+    #[test]
+    fn deps_scope_only_runs_cargo_clean() {
+        let root = Path::new("/yomi");
+        let mut fs = MockFs::with_build_outputs(root);
+        let scope = CleanScope::from_flags(false, true, false).unwrap();
+        clean_with(&mut fs, root, scope).unwrap();
+
+        assert!(fs.removed_files.is_empty());
+        assert!(fs.removed_dirs.is_empty());
+        assert!(fs.cargo_cleaned);
+    }
+
+    #[test]
+    fn missing_iso_paths_are_skipped() {
+        let root = Path::new("/yomi");
+        let mut fs = MockFs::default();
+        clean_with(&mut fs, root, CleanScope::ALL).unwrap();
+
+        assert!(fs.removed_files.is_empty());
+        assert!(fs.removed_dirs.is_empty());
+        assert!(fs.cargo_cleaned);
+    }
+
+    #[test]
+    fn scope_flags() {
+        assert_eq!(CleanScope::from_flags(false, false, false), None);
+        assert_eq!(
+            CleanScope::from_flags(true, false, true),
+            Some(CleanScope::ALL)
+        );
+        assert_eq!(
+            CleanScope::from_flags(true, true, false),
+            Some(CleanScope::ALL)
+        );
+    }
+}
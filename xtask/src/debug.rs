@@ -11,6 +11,7 @@ use crate::{
         command_exists,
         ensure_file_exists,
         kernel_binary,
+        parse_memory_size,
         print_info,
         print_step,
         print_warning,
@@ -18,8 +19,9 @@ use crate::{
     },
 };
 
-/// Launch kernel in debug mode with GDB
-pub fn debug_kernel(release: bool) -> Result<()> {
+/// Launch kernel in debug mode with GDB, giving QEMU `memory` of RAM
+pub fn debug_kernel(release: bool, memory: &str) -> Result<()> {
+    parse_memory_size(memory)?;
     print_step("Launching Debug Session");
 
     // Ensure ISO exists
@@ -95,7 +97,7 @@ continue
             "stdio",
             "-no-reboot",
             "-m",
-            "256M",
+            memory,
         ])
         .status()
         .context("Failed to start QEMU")?;
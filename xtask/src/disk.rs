@@ -0,0 +1,570 @@
+use std::{
+    collections::hash_map::RandomState,
+    fs::{
+        self,
+        File,
+    },
+    hash::{
+        BuildHasher,
+        Hasher,
+    },
+    io::{
+        self,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    process::Command,
+};
+
+use anyhow::{
+    Context,
+    Result,
+};
+
+use crate::{
+    iso::prepare_iso_tree,
+    util::{
+        command_exists,
+        print_info,
+        print_step,
+        print_success,
+        project_root,
+        run_cmd,
+    },
+};
+
+/// Default image size in MiB
+pub const DEFAULT_SIZE_MB: u64 = 64;
+
+/// Smallest image that fits the kernel and the GRUB modules
+const MIN_SIZE_MB: u64 = 16;
+
+const SECTOR_SIZE: u64 = 512;
+
+/// First sector of the BIOS boot partition, aligned to 1 MiB
+const BIOS_BOOT_START: u64 = 2048;
+
+/// Size of the BIOS boot partition, which holds GRUB's core.img (1 MiB)
+const BIOS_BOOT_SECTORS: u64 = 2048;
+
+/// Number of GPT partition entries, the minimum the specification allows
+const GPT_ENTRY_COUNT: u32 = 128;
+
+/// Size of a GPT partition entry
+const GPT_ENTRY_SIZE: u32 = 128;
+
+/// Sectors taken by the GPT partition entries
+const GPT_ENTRIES_SECTORS: u64 = GPT_ENTRY_COUNT as u64 * GPT_ENTRY_SIZE as u64 / SECTOR_SIZE;
+
+/// Size of the GPT header
+const GPT_HEADER_SIZE: u32 = 92;
+
+/// Where GRUB finds its modules and grub.cfg on the data partition
+const GRUB_PREFIX: &str = "(hd0,gpt2)/boot/grub";
+
+/// Modules built into core.img, enough to read the data partition
+const CORE_MODULES: [&str; 3] = ["biosdisk", "part_gpt", "fat"];
+
+/// Encode a GUID in the mixed-endian layout GPT uses
+const fn guid(d1: u32, d2: u16, d3: u16, d4: [u8; 8]) -> [u8; 16] {
+    let a = d1.to_le_bytes();
+    let b = d2.to_le_bytes();
+    let c = d3.to_le_bytes();
+    [
+        a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d4[0], d4[1], d4[2], d4[3], d4[4], d4[5],
+        d4[6], d4[7],
+    ]
+}
+
+/// Partition type of the BIOS boot partition GRUB embeds core.img in
+const BIOS_BOOT_TYPE: [u8; 16] = guid(0x2168_6148, 0x6449, 0x6e6f, [
+    0x74, 0x4e, 0x65, 0x65, 0x64, 0x45, 0x46, 0x49,
+]);
+
+/// Partition type of the FAT data partition (Microsoft basic data)
+const BASIC_DATA_TYPE: [u8; 16] = guid(0xebd0_a0a2, 0xb9e5, 0x4433, [
+    0x87, 0xc0, 0x68, 0xb6, 0xb7, 0x26, 0x99, 0xc7,
+]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskFormat {
+    Raw,
+    Qcow2,
+}
+
+impl DiskFormat {
+    pub fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "qcow2" => Ok(Self::Qcow2),
+            _ => anyhow::bail!("Invalid disk format: {}. Valid formats: raw, qcow2", s),
+        }
+    }
+}
+
+/// Sectors of a partition, both ends included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Partition {
+    first_lba: u64,
+    last_lba: u64,
+}
+
+impl Partition {
+    fn size_bytes(self) -> u64 {
+        (self.last_lba - self.first_lba + 1) * SECTOR_SIZE
+    }
+}
+
+/// Where everything goes on the disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DiskLayout {
+    total_sectors: u64,
+    bios_boot: Partition,
+    data: Partition,
+}
+
+impl DiskLayout {
+    fn new(size_mb: u64) -> Result<Self> {
+        if size_mb < MIN_SIZE_MB {
+            anyhow::bail!("Disk size must be at least {} MB", MIN_SIZE_MB);
+        }
+        let total_sectors = size_mb
+            .checked_mul(1024 * 1024 / SECTOR_SIZE)
+            .context("Disk size is too large")?;
+
+        Ok(Self {
+            total_sectors,
+            bios_boot: Partition {
+                first_lba: BIOS_BOOT_START,
+                last_lba: BIOS_BOOT_START + BIOS_BOOT_SECTORS - 1,
+            },
+            data: Partition {
+                first_lba: BIOS_BOOT_START + BIOS_BOOT_SECTORS,
+                last_lba: Self::last_usable_lba(total_sectors),
+            },
+        })
+    }
+
+    /// The backup GPT header and entries take the end of the disk
+    fn last_usable_lba(total_sectors: u64) -> u64 {
+        total_sectors - 2 - GPT_ENTRIES_SECTORS
+    }
+}
+
+/// Create a bootable raw or qcow2 disk image (`cargo x disk`)
+///
+/// The disk has a GPT with a BIOS boot partition holding GRUB's core.img
+/// and a FAT data partition holding the ISO tree and the GRUB modules.
+/// Needs grub-mkimage, grub-bios-setup and GRUB's i386-pc files, mkfs.fat
+/// and mtools, plus qemu-img for qcow2.
+pub fn create_disk_image(release: bool, size_mb: u64, format: DiskFormat) -> Result<()> {
+    print_step("Creating Bootable Disk Image");
+
+    let layout = DiskLayout::new(size_mb)?;
+    let iso_dir = prepare_iso_tree(release)?;
+
+    let root = project_root()?;
+    let disk_dir = root.join("build/disk");
+    fs::create_dir_all(&disk_dir).context("Failed to create disk build directory")?;
+    let raw_path = match format {
+        DiskFormat::Raw => root.join("yomios.img"),
+        DiskFormat::Qcow2 => disk_dir.join("yomios.img"),
+    };
+
+    print_info(&format!("Creating {} MB sparse image...", size_mb));
+    let mut disk = File::create(&raw_path)
+        .with_context(|| format!("Failed to create {}", raw_path.display()))?;
+    disk.set_len(layout.total_sectors * SECTOR_SIZE)
+        .context("Failed to size disk image")?;
+
+    print_info("Writing protective MBR and GPT...");
+    write_partition_tables(&mut disk, &layout, random_guid(), [
+        random_guid(),
+        random_guid(),
+    ])
+    .context("Failed to write partition tables")?;
+
+    print_info("Copying ISO tree to the data partition...");
+    let grub_dir = grub_platform_dir()?;
+    let data_img = make_data_partition(&disk_dir, &iso_dir, &grub_dir, layout.data)?;
+    disk.seek(SeekFrom::Start(layout.data.first_lba * SECTOR_SIZE))?;
+    io::copy(&mut File::open(&data_img)?, &mut disk).context("Failed to copy data partition")?;
+    drop(disk);
+    fs::remove_file(&data_img)?;
+
+    print_info("Installing GRUB boot sector...");
+    make_core_image(&disk_dir)?;
+    fs::copy(grub_dir.join("boot.img"), disk_dir.join("boot.img"))
+        .context("Failed to copy GRUB boot.img")?;
+    install_grub(&disk_dir, &raw_path)?;
+
+    let image = match format {
+        DiskFormat::Raw => raw_path,
+        DiskFormat::Qcow2 => {
+            let qcow2_path = root.join("yomios.qcow2");
+            to_qcow2(&raw_path, &qcow2_path)?;
+            fs::remove_file(&raw_path)?;
+            qcow2_path
+        }
+    };
+
+    print_success(&format!("Disk image created: {}", image.display()));
+    print_info(&format!(
+        "Boot with: qemu-system-x86_64 -hda {}",
+        image.display()
+    ));
+    Ok(())
+}
+
+/// Convert a raw image to qcow2 with qemu-img
+pub fn to_qcow2(raw: &Path, out: &Path) -> Result<()> {
+    let raw = raw.to_str().context("Invalid raw image path")?;
+    let out = out.to_str().context("Invalid qcow2 image path")?;
+    run_cmd("qemu-img", &[
+        "convert", "-f", "raw", "-O", "qcow2", raw, out,
+    ])
+    .context("qemu-img failed. Install it with the QEMU tools package")?;
+    Ok(())
+}
+
+/// Directory with GRUB's BIOS boot.img and modules
+fn grub_platform_dir() -> Result<PathBuf> {
+    [
+        "/usr/lib/grub/i386-pc",
+        "/usr/lib/grub2/i386-pc",
+        "/usr/share/grub/i386-pc",
+        "/usr/share/grub2/i386-pc",
+    ]
+    .into_iter()
+    .map(PathBuf::from)
+    .find(|dir| dir.join("boot.img").exists())
+    .context("GRUB i386-pc files not found. Install with: sudo apt install grub-pc-bin")
+}
+
+/// Build `disk_dir`/core.img with `GRUB_PREFIX` and `CORE_MODULES`
+fn make_core_image(disk_dir: &Path) -> Result<()> {
+    let mkimage = ["grub-mkimage", "grub2-mkimage"]
+        .into_iter()
+        .find(|cmd| command_exists(cmd))
+        .context("grub-mkimage not found. Install with: sudo apt install grub-common")?;
+
+    let core_path = disk_dir.join("core.img");
+    let mut args = vec![
+        "-O",
+        "i386-pc",
+        "-p",
+        GRUB_PREFIX,
+        "-o",
+        core_path.to_str().context("Invalid core.img path")?,
+    ];
+    args.extend(CORE_MODULES);
+    run_cmd(mkimage, &args)?;
+    Ok(())
+}
+
+/// Install boot.img and core.img from `disk_dir` into the image with
+/// grub-bios-setup
+///
+/// This is the step `grub-install --target=i386-pc` ends with. It writes
+/// boot.img to the MBR, embeds core.img in the BIOS boot partition and
+/// fills in the sector lists between them. A device map names the image
+/// `(hd0)`, so no loop device is needed.
+fn install_grub(disk_dir: &Path, image: &Path) -> Result<()> {
+    let setup = ["grub-bios-setup", "grub2-bios-setup"]
+        .into_iter()
+        .find(|cmd| command_exists(cmd))
+        .context("grub-bios-setup not found. Install with: sudo apt install grub-pc-bin")?;
+
+    let image = fs::canonicalize(image).context("Failed to resolve disk image path")?;
+    let device_map = disk_dir.join("device.map");
+    fs::write(&device_map, device_map_entry(&image)).context("Failed to write GRUB device map")?;
+
+    run_cmd(setup, &[
+        "--directory",
+        disk_dir.to_str().context("Invalid disk build directory")?,
+        "--device-map",
+        device_map.to_str().context("Invalid device map path")?,
+        // The data partition is FAT, which GRUB must not try to embed into
+        "--skip-fs-probe",
+        image.to_str().context("Invalid disk image path")?,
+    ])
+    .context("grub-bios-setup failed")?;
+    Ok(())
+}
+
+/// Device map naming `image` as GRUB's first hard disk
+fn device_map_entry(image: &Path) -> String {
+    format!("(hd0) {}\n", image.display())
+}
+
+/// Format a FAT image the size of `partition` and copy the ISO tree and
+/// the GRUB modules into it
+fn make_data_partition(
+    disk_dir: &Path,
+    iso_dir: &Path,
+    grub_dir: &Path,
+    partition: Partition,
+) -> Result<PathBuf> {
+    let data_img = disk_dir.join("data.img");
+    File::create(&data_img)
+        .and_then(|file| file.set_len(partition.size_bytes()))
+        .context("Failed to create data partition image")?;
+    let image = data_img.to_str().context("Invalid data image path")?;
+
+    run_cmd("mkfs.fat", &["-n", "YOMIOS", image])
+        .context("mkfs.fat failed. Install with: sudo apt install dosfstools")?;
+
+    let boot_dir = iso_dir.join("boot");
+    mcopy(image, &boot_dir, "::/")?;
+    mcopy(image, grub_dir, "::/boot/grub/")?;
+    Ok(data_img)
+}
+
+/// Recursively copy `source` into the FAT image `image`
+fn mcopy(image: &str, source: &Path, dest: &str) -> Result<()> {
+    let source = source.to_str().context("Invalid path")?;
+    let status = Command::new("mcopy")
+        // The image has no floppy geometry
+        .env("MTOOLS_SKIP_CHECK", "1")
+        .args(["-s", "-i", image, source, dest])
+        .status()
+        .context("Failed to run mcopy. Install with: sudo apt install mtools")?;
+    if !status.success() {
+        anyhow::bail!("mcopy failed with exit code: {:?}", status.code());
+    }
+    Ok(())
+}
+
+/// A random version 4 GUID
+fn random_guid() -> [u8; 16] {
+    let mut bytes = [0; 16];
+    for half in bytes.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0);
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[7] = (bytes[7] & 0x0f) | 0x40; // version 4
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+    bytes
+}
+
+/// CRC-32 (IEEE), as used by GPT
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Write the protective MBR, both GPT headers and both copies of the
+/// partition entries
+///
+/// The MBR boot code area is left alone for `install_boot_code`.
+fn write_partition_tables(
+    disk: &mut (impl Write + Seek),
+    layout: &DiskLayout,
+    disk_guid: [u8; 16],
+    partition_guids: [[u8; 16]; 2],
+) -> io::Result<()> {
+    let last_lba = layout.total_sectors - 1;
+
+    let mut mbr_entry = [0u8; 16];
+    mbr_entry[1..4].copy_from_slice(&[0x00, 0x02, 0x00]); // CHS of LBA 1
+    mbr_entry[4] = 0xee; // GPT protective
+    mbr_entry[5..8].copy_from_slice(&[0xff, 0xff, 0xff]);
+    mbr_entry[8..12].copy_from_slice(&1u32.to_le_bytes());
+    let mbr_sectors = u32::try_from(last_lba).unwrap_or(u32::MAX);
+    mbr_entry[12..16].copy_from_slice(&mbr_sectors.to_le_bytes());
+    disk.seek(SeekFrom::Start(446))?;
+    disk.write_all(&mbr_entry)?;
+    disk.seek(SeekFrom::Start(510))?;
+    disk.write_all(&[0x55, 0xaa])?;
+
+    let mut entries = vec![0u8; (GPT_ENTRIES_SECTORS * SECTOR_SIZE) as usize];
+    let partitions = [
+        (BIOS_BOOT_TYPE, layout.bios_boot, "BIOS boot"),
+        (BASIC_DATA_TYPE, layout.data, "YomiOS"),
+    ];
+    for (i, ((type_guid, partition, name), unique_guid)) in
+        partitions.into_iter().zip(partition_guids).enumerate()
+    {
+        let entry = &mut entries[i * GPT_ENTRY_SIZE as usize..][..GPT_ENTRY_SIZE as usize];
+        entry[0..16].copy_from_slice(&type_guid);
+        entry[16..32].copy_from_slice(&unique_guid);
+        entry[32..40].copy_from_slice(&partition.first_lba.to_le_bytes());
+        entry[40..48].copy_from_slice(&partition.last_lba.to_le_bytes());
+        for (j, unit) in name.encode_utf16().enumerate() {
+            entry[56 + 2 * j..58 + 2 * j].copy_from_slice(&unit.to_le_bytes());
+        }
+    }
+    let entries_crc = crc32(&entries);
+
+    let backup_entries_lba = last_lba - GPT_ENTRIES_SECTORS;
+    let copies = [(1, last_lba, 2), (last_lba, 1, backup_entries_lba)];
+    for (header_lba, other_lba, entries_lba) in copies {
+        let mut header = [0u8; GPT_HEADER_SIZE as usize];
+        header[0..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&GPT_HEADER_SIZE.to_le_bytes());
+        header[24..32].copy_from_slice(&header_lba.to_le_bytes());
+        header[32..40].copy_from_slice(&other_lba.to_le_bytes());
+        header[40..48].copy_from_slice(&(2 + GPT_ENTRIES_SECTORS).to_le_bytes());
+        header[48..56]
+            .copy_from_slice(&DiskLayout::last_usable_lba(layout.total_sectors).to_le_bytes());
+        header[56..72].copy_from_slice(&disk_guid);
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&GPT_ENTRY_COUNT.to_le_bytes());
+        header[84..88].copy_from_slice(&GPT_ENTRY_SIZE.to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let header_crc = crc32(&header);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+
+        disk.seek(SeekFrom::Start(header_lba * SECTOR_SIZE))?;
+        disk.write_all(&header)?;
+        disk.seek(SeekFrom::Start(entries_lba * SECTOR_SIZE))?;
+        disk.write_all(&entries)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        thread,
+        time::{
+            Duration,
+            Instant,
+        },
+    };
+
+    use super::*;
+    use crate::qemu::KERNEL_VERSION_MARKER;
+
+    /// How long `disk_image_boots_in_qemu` waits for the kernel banner
+    const BOOT_TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn layout_of_default_disk() {
+        let layout = DiskLayout::new(DEFAULT_SIZE_MB).unwrap();
+        assert_eq!(layout.total_sectors, 131_072);
+        assert_eq!(layout.bios_boot, Partition {
+            first_lba: 2048,
+            last_lba: 4095
+        });
+        assert_eq!(layout.data, Partition {
+            first_lba: 4096,
+            last_lba: 131_072 - 34
+        });
+        assert!(DiskLayout::new(MIN_SIZE_MB - 1).is_err());
+    }
+
+    #[test]
+    fn partition_tables() {
+        let layout = DiskLayout::new(MIN_SIZE_MB).unwrap();
+        let size = (layout.total_sectors * SECTOR_SIZE) as usize;
+        let mut disk = Cursor::new(vec![0u8; size]);
+        write_partition_tables(&mut disk, &layout, [1; 16], [[2; 16], [3; 16]]).unwrap();
+        let disk = disk.into_inner();
+
+        // Protective MBR
+        assert_eq!(disk[446 + 4], 0xee);
+        assert_eq!(read_u32(&disk, 446 + 8), 1);
+        assert_eq!(&disk[510..512], [0x55, 0xaa]);
+
+        let last_lba = layout.total_sectors - 1;
+        let primary = &disk[512..512 + GPT_HEADER_SIZE as usize];
+        let backup_offset = (last_lba * SECTOR_SIZE) as usize;
+        let backup = &disk[backup_offset..backup_offset + GPT_HEADER_SIZE as usize];
+        for (header, lba, other) in [(primary, 1, last_lba), (backup, last_lba, 1)] {
+            assert_eq!(&header[..8], b"EFI PART");
+            assert_eq!(read_u64(header, 24), lba);
+            assert_eq!(read_u64(header, 32), other);
+
+            let mut zeroed = header.to_vec();
+            zeroed[16..20].fill(0);
+            assert_eq!(read_u32(header, 16), crc32(&zeroed));
+
+            let entries_offset = (read_u64(header, 72) * SECTOR_SIZE) as usize;
+            let entries = &disk[entries_offset..][..(GPT_ENTRIES_SECTORS * SECTOR_SIZE) as usize];
+            assert_eq!(read_u32(header, 88), crc32(entries));
+            assert_eq!(entries[..16], BIOS_BOOT_TYPE);
+            assert_eq!(read_u64(entries, 32), layout.bios_boot.first_lba);
+            assert_eq!(entries[128..144], BASIC_DATA_TYPE);
+            assert_eq!(read_u64(entries, 128 + 40), layout.data.last_lba);
+        }
+    }
+
+    #[test]
+    fn device_map_names_image_hd0() {
+        assert_eq!(
+            device_map_entry(Path::new("/yomi/yomios.img")),
+            "(hd0) /yomi/yomios.img\n"
+        );
+    }
+
+    #[test]
+    #[ignore = "needs GRUB, mtools, QEMU and a kernel build"]
+    fn disk_image_boots_in_qemu() {
+        create_disk_image(false, DEFAULT_SIZE_MB, DiskFormat::Raw).unwrap();
+        let root = project_root().unwrap();
+        let image = root.join("yomios.img");
+        let capture = root.join("build/disk/boot-test-serial.log");
+
+        let mut qemu = Command::new("qemu-system-x86_64")
+            .arg("-drive")
+            .arg(format!("file={},format=raw,if=ide", image.display()))
+            .args(["-display", "none", "-no-reboot", "-m", "256M"])
+            .arg("-serial")
+            .arg(format!("file:{}", capture.display()))
+            .spawn()
+            .unwrap();
+
+        // GRUB loads the kernel from the disk, which then prints its banner
+        let deadline = Instant::now() + BOOT_TIMEOUT;
+        let booted = loop {
+            let serial = fs::read_to_string(&capture).unwrap_or_default();
+            if serial.contains(KERNEL_VERSION_MARKER) || Instant::now() >= deadline {
+                break serial.contains(KERNEL_VERSION_MARKER);
+            }
+            thread::sleep(Duration::from_millis(500));
+        };
+        let _ = qemu.kill();
+        let _ = qemu.wait();
+
+        assert!(booted, "kernel banner missing from {}", capture.display());
+    }
+
+    #[test]
+    fn disk_formats() {
+        assert_eq!(DiskFormat::from_str("raw").unwrap(), DiskFormat::Raw);
+        assert_eq!(DiskFormat::from_str("qcow2").unwrap(), DiskFormat::Qcow2);
+        assert!(DiskFormat::from_str("vmdk").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use std::{
     fs,
+    path::PathBuf,
     process::Command,
 };
 
@@ -23,6 +24,23 @@ use crate::{
 pub fn create_iso(release: bool) -> Result<()> {
     print_step("Creating Bootable ISO Image");
 
+    let iso_dir = prepare_iso_tree(release)?;
+
+    // Create ISO using grub-mkrescue
+    let iso_path = project_root()?.join("yomios.iso");
+
+    run_grub_mkrescue(&iso_path, &iso_dir)?;
+
+    print_success(&format!("ISO created: {}", iso_path.display()));
+    Ok(())
+}
+
+/// Build the kernel and lay out the boot files in `build/iso`
+///
+/// The tree holds `boot/kernel.bin`, `boot/init` and
+/// `boot/grub/grub.cfg`; GRUB itself is added by whatever makes it
+/// bootable. Returns the path of the tree.
+pub fn prepare_iso_tree(release: bool) -> Result<PathBuf> {
     // First build the kernel
     build_kernel(release)?;
 
@@ -49,6 +67,13 @@ pub fn create_iso(release: bool) -> Result<()> {
     fs::copy(&kernel_bin, &kernel_dest)
         .with_context(|| format!("Failed to copy kernel to {}", kernel_dest.display()))?;
 
+    // Write the init program loaded as the first userspace process
+    print_info("Writing init program...");
+    let init_dest = boot_dir.join("init");
+    #[allow(clippy::disallowed_methods)]
+    fs::write(&init_dest, init_elf())
+        .with_context(|| format!("Failed to write init to {}", init_dest.display()))?;
+
     // Create grub.cfg
     print_info("Creating GRUB configuration...");
     let grub_cfg = grub_dir.join("grub.cfg");
@@ -62,6 +87,7 @@ terminal_output console serial
 
 menuentry "YomiOS" {
     multiboot2 /boot/kernel.bin
+    module2 /boot/init init
     boot
 }
 "#;
@@ -69,13 +95,84 @@ menuentry "YomiOS" {
     #[allow(clippy::disallowed_methods)]
     fs::write(&grub_cfg, grub_config).context("Failed to write GRUB configuration")?;
 
-    // Create ISO using grub-mkrescue
-    let iso_path = root.join("yomios.iso");
+    Ok(iso_dir)
+}
 
-    run_grub_mkrescue(&iso_path, &iso_dir)?;
+/// Virtual address the init program is linked at: the start of user
+/// space, above the kernel's identity map (`USER_SPACE_START`)
+const INIT_BASE: u64 = 0x80_0000_0000;
 
-    print_success(&format!("ISO created: {}", iso_path.display()));
-    Ok(())
+/// Size of the ELF64 file header
+const EHDR_SIZE: usize = 64;
+
+/// Size of an ELF64 program header
+const PHDR_SIZE: usize = 56;
+
+/// Machine code of the init program
+///
+/// Writes `INIT_MESSAGE` to stdout, then exits with status 0:
+///
+/// ```text
+/// lea  rsi, [rip + message]
+/// mov  eax, 1              ; write
+/// mov  edi, 1              ; stdout
+/// mov  edx, len
+/// syscall
+/// mov  eax, 60             ; exit
+/// xor  edi, edi
+/// syscall
+/// jmp  $
+/// ```
+#[rustfmt::skip]
+const INIT_CODE: [u8; 35] = [
+    0x48, 0x8d, 0x35, 0x1c, 0x00, 0x00, 0x00, // lea rsi, [rip + 28]
+    0xb8, 0x01, 0x00, 0x00, 0x00,             // mov eax, 1
+    0xbf, 0x01, 0x00, 0x00, 0x00,             // mov edi, 1
+    0xba, INIT_MESSAGE.len() as u8, 0x00, 0x00, 0x00, // mov edx, len
+    0x0f, 0x05,                               // syscall
+    0xb8, 0x3c, 0x00, 0x00, 0x00,             // mov eax, 60
+    0x31, 0xff,                               // xor edi, edi
+    0x0f, 0x05,                               // syscall
+    0xeb, 0xfe,                               // jmp $
+];
+
+/// Message printed by the init program
+const INIT_MESSAGE: &[u8] = b"Hello from init\n";
+
+/// Build the init program: a static ELF64 executable with a single R+X
+/// `PT_LOAD` segment holding the headers, `INIT_CODE` and `INIT_MESSAGE`
+fn init_elf() -> Vec<u8> {
+    let code_offset = (EHDR_SIZE + PHDR_SIZE) as u64;
+    let size = code_offset + (INIT_CODE.len() + INIT_MESSAGE.len()) as u64;
+
+    let mut elf = Vec::with_capacity(size as usize);
+    elf.extend_from_slice(b"\x7fELF");
+    elf.extend_from_slice(&[2, 1, 1, 0]); // ELFCLASS64, little-endian, version 1
+    elf.resize(16, 0);
+    elf.extend_from_slice(&2u16.to_le_bytes()); // e_type: ET_EXEC
+    elf.extend_from_slice(&62u16.to_le_bytes()); // e_machine: x86_64
+    elf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    elf.extend_from_slice(&(INIT_BASE + code_offset).to_le_bytes()); // e_entry
+    elf.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+    elf.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    elf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    elf.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    elf.extend_from_slice(&[0; 6]); // e_shentsize, e_shnum, e_shstrndx
+
+    elf.extend_from_slice(&1u32.to_le_bytes()); // p_type: PT_LOAD
+    elf.extend_from_slice(&5u32.to_le_bytes()); // p_flags: R+X
+    elf.extend_from_slice(&0u64.to_le_bytes()); // p_offset
+    elf.extend_from_slice(&INIT_BASE.to_le_bytes()); // p_vaddr
+    elf.extend_from_slice(&INIT_BASE.to_le_bytes()); // p_paddr
+    elf.extend_from_slice(&size.to_le_bytes()); // p_filesz
+    elf.extend_from_slice(&size.to_le_bytes()); // p_memsz
+    elf.extend_from_slice(&0x1000u64.to_le_bytes()); // p_align
+
+    elf.extend_from_slice(&INIT_CODE);
+    elf.extend_from_slice(INIT_MESSAGE);
+    elf
 }
 
 /// Run grub-mkrescue, using WSL on Windows
@@ -147,3 +244,27 @@ fn windows_to_wsl_path(path: &std::path::Path) -> Result<String> {
 
     anyhow::bail!("Could not convert Windows path to WSL path: {}", path_str)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_elf_layout() {
+        let elf = init_elf();
+        assert_eq!(&elf[..4], b"\x7fELF");
+        assert_eq!(
+            elf.len(),
+            EHDR_SIZE + PHDR_SIZE + INIT_CODE.len() + INIT_MESSAGE.len()
+        );
+
+        let entry = u64::from_le_bytes(elf[24..32].try_into().unwrap());
+        let code_offset = (entry - INIT_BASE) as usize;
+        assert_eq!(&elf[code_offset..code_offset + INIT_CODE.len()], &INIT_CODE);
+
+        // The lea displacement must point at the message
+        let lea_end = code_offset + 7;
+        let disp = u32::from_le_bytes(elf[code_offset + 3..lea_end].try_into().unwrap());
+        assert_eq!(&elf[lea_end + disp as usize..], INIT_MESSAGE);
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    fs,
+    path::Path,
+};
+
+use anyhow::{
+    Context,
+    Result,
+};
+use colored::Colorize;
+
+use crate::util::{
+    print_step,
+    print_success,
+    project_root,
+};
+
+/// Files allowed to use `println!` directly
+const PRINTLN_ALLOWED: &[&str] = &["panic.rs", "panic/mod.rs"];
+
+/// A single coding standard violation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub file: String,
+    pub line: usize,
+    pub message: &'static str,
+}
+
+/// Check all kernel sources against the project coding standards
+///
+/// These are rules clippy cannot express. Violations are reported as
+/// `ERROR file:line: description` and make the command fail.
+pub fn run_kernel_lints() -> Result<()> {
+    print_step("Linting Kernel Sources");
+
+    let root = project_root()?;
+    let src_dir = root.join("kernel/src");
+
+    let mut files = Vec::new();
+    collect_rust_files(&src_dir, &mut files)?;
+    files.sort();
+
+    let mut violations = Vec::new();
+    for path in &files {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let relative = path
+            .strip_prefix(&root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        violations.extend(lint_source(&relative, &source));
+    }
+
+    for v in &violations {
+        println!(
+            "{} {}:{}: {}",
+            "ERROR".red().bold(),
+            v.file,
+            v.line,
+            v.message
+        );
+    }
+
+    if !violations.is_empty() {
+        anyhow::bail!(
+            "{} violation(s) found in {} file(s)",
+            violations.len(),
+            files.len()
+        );
+    }
+
+    print_success(&format!("{} files checked, no violations", files.len()));
+    Ok(())
+}
+
+/// Print the recommended CI command sequence
+pub fn ci_steps() {
+    print_step("Recommended CI Steps");
+
+    let steps = [
+        "cargo fmt --all -- --check",
+        "cargo clippy --package yomi-kernel --target x86_64-unknown-none --lib -- -D warnings",
+        "cargo clippy --package xtask -- -D warnings",
+        "cargo x lint",
+        "cargo x build",
+        "cargo x test",
+    ];
+
+    for (i, step) in steps.iter().enumerate() {
+        println!("  {}. {}", i + 1, step);
+    }
+}
+
+/// Recursively collect all `.rs` files under `dir`
+fn collect_rust_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_rust_files(&path, files)?;
+        } else if path.extension().and_then(|s| s.to_str()) == Some("rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// An open `loop` block
+struct OpenLoop {
+    line: usize,
+    depth: i32,
+    /// The body halts, spins politely or can exit
+    ok: bool,
+}
+
+/// Check one source file, returning all violations found
+///
+/// This is a line-based scanner, not a parser: it tracks brace depth to know
+/// whether it is inside test code or a `loop` body, after stripping comments
+/// and string literals. Test code is everything inside the outermost item
+/// marked `#[cfg(test)]`, `#[test]` or `#[test_case]`, such as a whole
+/// `mod tests` with its helper functions.
+pub fn lint_source(file: &str, source: &str) -> Vec<Violation> {
+    let lines: Vec<&str> = source.lines().collect();
+    let println_allowed = PRINTLN_ALLOWED.iter().any(|f| file.ends_with(f));
+
+    let mut violations = Vec::new();
+    let mut report = |line: usize, message: &'static str| {
+        violations.push(Violation {
+            file: file.to_string(),
+            line,
+            message,
+        });
+    };
+
+    let mut depth = 0i32;
+    let mut pending_test = false;
+    let mut test_depth: Option<i32> = None;
+    let mut loops: Vec<OpenLoop> = Vec::new();
+
+    for (i, raw) in lines.iter().enumerate() {
+        let line_no = i + 1;
+        let trimmed = raw.trim();
+        let (code, literals) = strip_comments_and_strings(raw);
+
+        if trimmed.starts_with("#[cfg(test)]")
+            || trimmed.starts_with("#[test]")
+            || trimmed.starts_with("#[test_case]")
+        {
+            pending_test = true;
+        }
+        let in_test = pending_test || test_depth.is_some();
+
+        // (1) unwrap() outside tests
+        if !in_test && code.contains(".unwrap()") {
+            report(
+                line_no,
+                "bare unwrap() outside a test (use kernel_assert! or handle the error)",
+            );
+        }
+
+        // (2) unsafe blocks without a SAFETY comment
+        if has_unsafe_block(&code)
+            && !has_comment_above(&lines, statement_start(&lines, i), &["SAFETY:"])
+        {
+            report(
+                line_no,
+                "unsafe block without a `// SAFETY:` comment above its statement",
+            );
+        }
+
+        // (4) println! outside the panic handler
+        if !println_allowed && contains_macro(&code, "println!") {
+            report(
+                line_no,
+                "println! outside the panic handler (use log_info!)",
+            );
+        }
+
+        // (5) undocumented static mut
+        if declares_static_mut(&code) && !has_comment_above(&lines, i, &["SAFETY", "sound"]) {
+            report(
+                line_no,
+                "static mut without documentation explaining why it is sound",
+            );
+        }
+
+        // (3) loops that neither halt nor exit
+        let halts = literals.contains("hlt") || code.contains("spin_loop");
+        let exits = contains_word(&code, "return") || code.contains('?');
+        if halts || exits {
+            for open in loops.iter_mut() {
+                open.ok = true;
+            }
+        } else if contains_word(&code, "break") {
+            if let Some(open) = loops.last_mut() {
+                open.ok = true;
+            }
+        }
+
+        let bytes = code.as_bytes();
+        for (pos, &c) in bytes.iter().enumerate() {
+            match c {
+                b'{' => {
+                    if pending_test {
+                        // A test inside a test module does not end it
+                        test_depth.get_or_insert(depth);
+                        pending_test = false;
+                    }
+                    if opens_loop(&code[..pos]) {
+                        // Only the rest of this line belongs to the new body
+                        let rest = &code[pos + 1..];
+                        let ok = halts
+                            || contains_word(rest, "break")
+                            || contains_word(rest, "return")
+                            || rest.contains('?');
+                        loops.push(OpenLoop {
+                            line: line_no,
+                            depth,
+                            ok,
+                        });
+                    }
+                    depth += 1;
+                }
+                b'}' => {
+                    depth -= 1;
+                    if let Some(open) = loops.pop_if(|l| l.depth == depth) {
+                        if !open.ok {
+                            report(open.line, "loop without hlt or spin_loop() spins the CPU");
+                        }
+                    }
+                    if test_depth.is_some_and(|d| depth <= d) {
+                        test_depth = None;
+                    }
+                }
+                b';' if pending_test && !code[..pos].contains('{') => {
+                    // Attribute applied to an item without a body
+                    pending_test = false;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    violations
+}
+
+/// Whether `before` (the code preceding a `{`) ends with the `loop` keyword
+fn opens_loop(before: &str) -> bool {
+    let before = before.trim_end();
+    before.ends_with("loop")
+        && !before[..before.len() - 4]
+            .chars()
+            .next_back()
+            .is_some_and(is_ident_char)
+}
+
+/// Whether `code` contains an `unsafe { ... }` block
+fn has_unsafe_block(code: &str) -> bool {
+    code.match_indices("unsafe").any(|(pos, _)| {
+        let starts_word = !code[..pos].chars().next_back().is_some_and(is_ident_char);
+        starts_word && code[pos + "unsafe".len()..].trim_start().starts_with('{')
+    })
+}
+
+/// Whether `code` invokes `name` (e.g. `println!`) and not a longer macro
+/// such as `serial_println!`
+fn contains_macro(code: &str, name: &str) -> bool {
+    code.match_indices(name).any(|(pos, _)| {
+        let prev = code[..pos].chars().next_back();
+        !prev.is_some_and(|c| is_ident_char(c) || c == '$')
+    })
+}
+
+/// Whether `code` declares a `static mut` item, as opposed to using a
+/// `&'static mut` reference type
+fn declares_static_mut(code: &str) -> bool {
+    code.match_indices("static mut").any(|(pos, _)| {
+        let before = code[..pos].chars().next_back();
+        let after = code[pos + "static mut".len()..].chars().next();
+        !before.is_some_and(|c| is_ident_char(c) || c == '\'') && !after.is_some_and(is_ident_char)
+    })
+}
+
+/// Whether `code` contains `word` delimited by non-identifier characters
+fn contains_word(code: &str, word: &str) -> bool {
+    code.match_indices(word).any(|(pos, _)| {
+        let before = code[..pos].chars().next_back();
+        let after = code[pos + word.len()..].chars().next();
+        !before.is_some_and(is_ident_char) && !after.is_some_and(is_ident_char)
+    })
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Index of the first line of the statement that line `index` belongs to
+///
+/// rustfmt moves a long initializer such as `let x = unsafe { .. };` to its
+/// own line, leaving the comment above the `let`. A line continues the
+/// statement above it unless that line ends with `;`, `{`, `}` or `,` or
+/// holds no code.
+fn statement_start(lines: &[&str], mut index: usize) -> usize {
+    while index > 0 {
+        let (code, _) = strip_comments_and_strings(lines[index - 1]);
+        let code = code.trim_end();
+        if code.trim_start().is_empty()
+            || code.trim_start().starts_with("#[")
+            || code.ends_with([';', '{', '}', ','])
+        {
+            break;
+        }
+        index -= 1;
+    }
+    index
+}
+
+/// Whether the comment block directly above line `index` mentions any of
+/// `needles`
+///
+/// Attributes between the comment and the line are skipped.
+fn has_comment_above(lines: &[&str], index: usize, needles: &[&str]) -> bool {
+    for line in lines[..index].iter().rev() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("//") {
+            if needles.iter().any(|n| trimmed.contains(n)) {
+                return true;
+            }
+        } else if !trimmed.starts_with("#[") {
+            return false;
+        }
+    }
+    false
+}
+
+/// Remove `//` comments and the contents of string and char literals so that
+/// braces and keywords inside them are ignored
+///
+/// Returns the remaining code and the concatenated string literal contents
+/// (which is where `asm!` instructions such as `hlt` live).
+fn strip_comments_and_strings(line: &str) -> (String, String) {
+    let mut out = String::with_capacity(line.len());
+    let mut literals = String::new();
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '/' if chars.get(i + 1) == Some(&'/') => break,
+            '"' => {
+                out.push('"');
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    } else {
+                        literals.push(chars[i]);
+                    }
+                    i += 1;
+                }
+                literals.push(' ');
+                out.push('"');
+            }
+            // Char literals ('x', '\n'); lifetimes are left alone
+            '\'' if chars.get(i + 2) == Some(&'\'') => {
+                out.push_str("' '");
+                i += 2;
+            }
+            '\'' if chars.get(i + 1) == Some(&'\\') => {
+                out.push_str("' '");
+                i += 2;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+            }
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+
+    (out, literals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(file: &str, source: &str) -> Vec<(usize, &'static str)> {
+        lint_source(file, source)
+            .into_iter()
+            .map(|v| (v.line, v.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_unwrap_outside_tests() {
+        let source =
+            "fn f() {\n    x.unwrap();\n}\n\n#[cfg(test)]\nmod tests {\n    fn g() {\n        \
+             x.unwrap();\n    }\n}\n\nfn h() {\n    y.unwrap();\n}\n";
+        let lines: Vec<usize> = messages("kernel/src/a.rs", source)
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect();
+        assert_eq!(lines, vec![2, 13]);
+    }
+
+    #[test]
+    fn test_unwrap_in_test_module_helpers() {
+        // The helper after the first test is still inside the test module
+        let source = "#[cfg(test)]\nmod tests {\n    #[test_case]\n    fn a() {\n        \
+                      x.unwrap();\n    }\n\n    fn helper() {\n        y.unwrap();\n    \
+                      }\n}\n\nfn f() {\n    z.unwrap();\n}\n";
+        let lines: Vec<usize> = messages("kernel/src/a.rs", source)
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect();
+        assert_eq!(lines, vec![14]);
+    }
+
+    #[test]
+    fn test_unsafe_needs_safety_comment() {
+        let source = "fn f() {\n    unsafe { a() };\n    // SAFETY: b is fine\n    unsafe { b() \
+                      };\n}\nunsafe fn c() {}\n";
+        assert_eq!(messages("kernel/src/a.rs", source).len(), 1);
+        assert_eq!(messages("kernel/src/a.rs", source)[0].0, 2);
+    }
+
+    #[test]
+    fn test_safety_comment_above_wrapped_statement() {
+        let source =
+            "fn f() {\n    // SAFETY: a is fine\n    let x =\n        unsafe { a() };\n    let y \
+             = 1;\n    let z =\n        unsafe { b() };\n}\n";
+        assert_eq!(
+            messages("kernel/src/a.rs", source)
+                .into_iter()
+                .map(|(line, _)| line)
+                .collect::<Vec<_>>(),
+            vec![7]
+        );
+    }
+
+    #[test]
+    fn test_spinning_loop() {
+        let source = "fn a() {\n    loop {}\n}\nfn b() {\n    loop {\n        \
+                      core::hint::spin_loop();\n    }\n}\nfn d() {\n    loop {\n        \
+                      x86_64::instructions::hlt();\n        asm!(\"hlt\");\n    }\n}\nfn c() {\n    loop {\n        if x \
+                      {\n            break;\n        }\n    }\n}\n";
+        let found = messages("kernel/src/a.rs", source);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, 2);
+    }
+
+    #[test]
+    fn test_println_only_in_panic_handler() {
+        let source = "fn f() {\n    println!(\"x\");\n    serial_println!(\"y\");\n}\n";
+        assert_eq!(messages("kernel/src/a.rs", source).len(), 1);
+        assert!(messages("kernel/src/panic.rs", source).is_empty());
+    }
+
+    #[test]
+    fn test_static_mut_needs_docs() {
+        let source = "static mut A: u8 = 0;\n\n/// Only written before interrupts are enabled, so \
+                      this is sound\nstatic mut B: u8 = 0;\n";
+        assert_eq!(messages("kernel/src/a.rs", source), vec![(
+            1,
+            "static mut without documentation explaining why it is sound"
+        )]);
+
+        let source = "struct S {\n    table: &'static mut Table,\n}\n";
+        assert!(messages("kernel/src/a.rs", source).is_empty());
+    }
+
+    #[test]
+    fn test_strings_and_comments_ignored() {
+        let source =
+            "fn f() {\n    let s = \"loop {} .unwrap()\"; // unsafe { }\n    let c = '{';\n}\n";
+        assert!(messages("kernel/src/a.rs", source).is_empty());
+    }
+}
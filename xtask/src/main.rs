@@ -1,26 +1,49 @@
 mod build;
+mod clean;
 mod debug;
+mod disk;
 mod iso;
+mod lint;
 mod qemu;
 mod setup;
 mod test;
 mod util;
 
+use std::path::PathBuf;
+
 use anyhow::Result;
 use build::build_kernel;
 use clap::{
     Parser,
     Subcommand,
 };
+use clean::{
+    CleanScope,
+    clean,
+};
 use colored::Colorize;
 use debug::debug_kernel;
+use disk::{
+    DEFAULT_SIZE_MB,
+    DiskFormat,
+    create_disk_image,
+};
 use iso::create_iso;
+use lint::{
+    ci_steps,
+    run_kernel_lints,
+};
 use qemu::{
+    DEFAULT_MEMORY,
     QemuMode,
     run_qemu,
 };
-use setup::setup_environment;
+use setup::{
+    setup_environment,
+    verify_environment,
+};
 use test::run_tests;
+use util::print_warning;
 
 #[derive(Parser)]
 #[command(name = "xtask")]
@@ -47,6 +70,21 @@ enum Command {
         release: bool,
     },
 
+    /// Build a bootable hard disk image (boots in QEMU with -hda)
+    Disk {
+        /// Build in release mode
+        #[arg(long)]
+        release: bool,
+
+        /// Image size in MB
+        #[arg(long, value_name = "MB", default_value_t = DEFAULT_SIZE_MB)]
+        size: u64,
+
+        /// Image format: raw or qcow2
+        #[arg(long, default_value = "raw")]
+        format: String,
+    },
+
     /// Run kernel in QEMU
     Run {
         /// QEMU mode: run (normal), test, or debug
@@ -56,6 +94,14 @@ enum Command {
         /// Build in release mode
         #[arg(long)]
         release: bool,
+
+        /// Guest RAM size passed to QEMU, e.g. 512M or 2G
+        #[arg(long, default_value = DEFAULT_MEMORY)]
+        memory: String,
+
+        /// Write serial output to FILE instead of the console
+        #[arg(long, value_name = "FILE")]
+        capture: Option<PathBuf>,
     },
 
     /// Run integration tests
@@ -63,6 +109,14 @@ enum Command {
         /// Filter tests by name
         #[arg(long)]
         filter: Option<String>,
+
+        /// Write serial output of all tests to FILE
+        #[arg(long, value_name = "FILE")]
+        capture: Option<PathBuf>,
+
+        /// Kill a test still running after SECONDS (0 disables the timeout)
+        #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+        timeout: u64,
     },
 
     /// Launch kernel in debug mode with GDB
@@ -70,13 +124,44 @@ enum Command {
         /// Build in release mode
         #[arg(long)]
         release: bool,
+
+        /// Guest RAM size passed to QEMU, e.g. 512M or 2G
+        #[arg(long, default_value = DEFAULT_MEMORY)]
+        memory: String,
     },
 
-    /// Clean build artifacts
-    Clean,
+    /// Check kernel sources against project coding standards
+    Lint,
+
+    /// Print the recommended CI command sequence
+    Ci,
+
+    /// Clean build artifacts (everything unless --iso or --deps is given)
+    Clean {
+        /// Remove the ISO image and its staging directory
+        #[arg(long)]
+        iso: bool,
+
+        /// Run `cargo clean`
+        #[arg(long)]
+        deps: bool,
+
+        /// Remove the build directory, all images and the cargo build
+        /// artifacts
+        #[arg(long)]
+        all: bool,
+
+        /// Print what would be deleted without deleting it
+        #[arg(long)]
+        dry_run: bool,
+    },
 
     /// Setup development environment (install dependencies)
-    Setup,
+    Setup {
+        /// Only report missing tools, failing if any are missing
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 fn main() {
@@ -98,61 +183,112 @@ fn run() -> Result<()> {
             create_iso(release)?;
         }
 
-        Command::Run { mode, release } => {
+        Command::Disk {
+            release,
+            size,
+            format,
+        } => {
+            create_disk_image(release, size, DiskFormat::from_str(&format)?)?;
+        }
+
+        Command::Run {
+            mode,
+            release,
+            memory,
+            capture,
+        } => {
             let qemu_mode = QemuMode::from_str(&mode)?;
-            run_qemu(qemu_mode, release)?;
+            run_qemu(qemu_mode, release, &memory, capture.as_deref())?;
+        }
+
+        Command::Test {
+            filter,
+            capture,
+            timeout,
+        } => {
+            run_tests(filter.as_deref(), capture.as_deref(), timeout)?;
+        }
+
+        Command::Debug { release, memory } => {
+            debug_kernel(release, &memory)?;
         }
 
-        Command::Test { filter } => {
-            run_tests(filter.as_deref())?;
+        Command::Lint => {
+            run_kernel_lints()?;
         }
 
-        Command::Debug { release } => {
-            debug_kernel(release)?;
+        Command::Ci => {
+            ci_steps();
         }
 
-        Command::Clean => {
-            clean()?;
+        Command::Clean {
+            iso,
+            deps,
+            all,
+            dry_run,
+        } => {
+            let scope = CleanScope::from_flags(iso, deps, all).unwrap_or_else(|| {
+                print_warning("No --iso, --deps or --all given, cleaning everything");
+                CleanScope::ALL
+            });
+            clean(scope, dry_run)?;
         }
 
-        Command::Setup => {
-            setup_environment()?;
+        Command::Setup { check } => {
+            if check {
+                verify_environment()?;
+            } else {
+                setup_environment()?;
+            }
         }
     }
 
     Ok(())
 }
 
-fn clean() -> Result<()> {
-    use crate::util::{
-        print_info,
-        print_step,
-        print_success,
-        project_root,
-        run_cmd,
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_memory_size;
 
-    print_step("Cleaning Build Artifacts");
-
-    // Clean cargo build artifacts
-    run_cmd("cargo", &["clean"])?;
+    #[test]
+    fn memory_sizes() {
+        assert_eq!(parse_memory_size("512M").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_memory_size("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_memory_size("bad").is_err());
+        assert!(parse_memory_size("M").is_err());
+        assert!(parse_memory_size("0G").is_err());
+        assert!(parse_memory_size("1.5G").is_err());
+        assert!(parse_memory_size("512m").is_err());
+    }
 
-    // Clean ISO build directory
-    let root = project_root()?;
-    let build_dir = root.join("build");
+    #[test]
+    fn memory_flag_defaults_to_256m() {
+        let cli = Cli::parse_from(["xtask", "run"]);
+        let Command::Run { memory, .. } = cli.command else {
+            panic!("expected run command");
+        };
+        assert_eq!(memory, "256M");
 
-    if build_dir.exists() {
-        print_info("Removing build directory...");
-        std::fs::remove_dir_all(&build_dir)?;
+        let cli = Cli::parse_from(["xtask", "debug", "--memory", "1G"]);
+        let Command::Debug { memory, .. } = cli.command else {
+            panic!("expected debug command");
+        };
+        assert_eq!(memory, "1G");
     }
 
-    // Clean ISO image
-    let iso_path = root.join("yomios.iso");
-    if iso_path.exists() {
-        print_info("Removing ISO image...");
-        std::fs::remove_file(&iso_path)?;
+    #[test]
+    fn clean_flags() {
+        let cli = Cli::parse_from(["xtask", "clean", "--iso", "--dry-run"]);
+        let Command::Clean {
+            iso,
+            deps,
+            all,
+            dry_run,
+        } = cli.command
+        else {
+            panic!("expected clean command");
+        };
+        assert!(iso && dry_run && !deps && !all);
     }
-
-    print_success("Clean complete");
-    Ok(())
 }
@@ -1,14 +1,28 @@
-use std::process::Command;
+use std::{
+    fs::File,
+    io::{
+        BufRead,
+        BufReader,
+        Write,
+    },
+    path::Path,
+    process::{
+        Command,
+        Stdio,
+    },
+};
 
 use anyhow::{
     Context,
     Result,
 };
+use colored::Colorize;
 
 use crate::{
     iso::create_iso,
     util::{
         ensure_file_exists,
+        parse_memory_size,
         print_info,
         print_step,
         project_root,
@@ -87,8 +101,23 @@ fn which_qemu() -> Option<()> {
         .map(|_| ())
 }
 
-/// Run the kernel in QEMU
-pub fn run_qemu(mode: QemuMode, release: bool) -> Result<()> {
+/// Guest RAM size used unless `--memory` is given
+pub const DEFAULT_MEMORY: &str = "256M";
+
+/// Value of QEMU's `-serial` option: the console, or `capture` if given
+pub fn serial_arg(capture: Option<&Path>) -> String {
+    match capture {
+        Some(path) => format!("file:{}", path.display()),
+        None => "stdio".to_string(),
+    }
+}
+
+/// Run the kernel in QEMU with `memory` (e.g. `512M`) of guest RAM
+///
+/// With `capture`, serial output goes to that file instead of the console,
+/// so it survives QEMU exiting, and is echoed once QEMU is done.
+pub fn run_qemu(mode: QemuMode, release: bool, memory: &str, capture: Option<&Path>) -> Result<()> {
+    parse_memory_size(memory)?;
     print_step(&format!("Starting QEMU in {:?} mode", mode));
 
     // Get QEMU path
@@ -110,10 +139,10 @@ pub fn run_qemu(mode: QemuMode, release: bool) -> Result<()> {
     cmd.arg("-cdrom")
         .arg(&iso_path)
         .arg("-serial")
-        .arg("stdio")
+        .arg(serial_arg(capture))
         .arg("-no-reboot")
         .arg("-m")
-        .arg("256M");
+        .arg(memory);
 
     // Mode-specific options
     match mode {
@@ -139,8 +168,30 @@ pub fn run_qemu(mode: QemuMode, release: bool) -> Result<()> {
         }
     }
 
-    // Execute QEMU
-    let status = cmd.status().context("Failed to start QEMU")?;
+    // Execute QEMU, echoing serial output so the kernel version can be
+    // picked out of it
+    let status = match capture {
+        Some(path) => {
+            print_info(&format!("Capturing serial output to {}", path.display()));
+            let status = cmd.status().context("Failed to start QEMU")?;
+            if path.exists() {
+                let file = File::open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                echo_serial_output(file)?;
+            }
+            status
+        }
+        None => {
+            cmd.stdout(Stdio::piped());
+            let mut child = cmd.spawn().context("Failed to start QEMU")?;
+            let stdout = child
+                .stdout
+                .take()
+                .context("Failed to capture QEMU output")?;
+            echo_serial_output(stdout)?;
+            child.wait().context("Failed to wait for QEMU")?
+        }
+    };
 
     // Handle exit code for test mode
     if mode == QemuMode::Test {
@@ -161,3 +212,67 @@ pub fn run_qemu(mode: QemuMode, release: bool) -> Result<()> {
 
     Ok(())
 }
+
+/// Marker preceding the version string in the kernel's first log line
+pub const KERNEL_VERSION_MARKER: &str = "YomiOS Kernel v";
+
+/// Copy QEMU serial output to our stdout, highlighting the kernel version
+fn echo_serial_output(output: impl std::io::Read) -> Result<()> {
+    let mut reader = BufReader::new(output);
+    let mut stdout = std::io::stdout();
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+
+        // Serial output may contain arbitrary bytes, e.g. after a crash
+        let line = String::from_utf8_lossy(&buf);
+        stdout.write_all(line.as_bytes())?;
+        stdout.flush()?;
+
+        if let Some(version) = parse_kernel_version(&line) {
+            println!("{} {}", "Kernel version:".green().bold(), version.bold());
+        }
+    }
+
+    Ok(())
+}
+
+/// Extract the kernel version string from a line of serial output
+fn parse_kernel_version(line: &str) -> Option<&str> {
+    let start = line.find(KERNEL_VERSION_MARKER)? + KERNEL_VERSION_MARKER.len();
+    let version = line[start..].trim();
+    (!version.is_empty()).then_some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kernel_version() {
+        let line = "[INFO ] YomiOS Kernel v0.1.0-1a2b3c4 built 2025-01-01T00:00:00Z\r\n";
+        assert_eq!(
+            parse_kernel_version(line),
+            Some("0.1.0-1a2b3c4 built 2025-01-01T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_serial_arg() {
+        assert_eq!(serial_arg(None), "stdio");
+        assert_eq!(
+            serial_arg(Some(Path::new("/tmp/serial.log"))),
+            "file:/tmp/serial.log"
+        );
+    }
+
+    #[test]
+    fn test_parse_kernel_version_missing() {
+        assert_eq!(parse_kernel_version("Serial port initialized"), None);
+        assert_eq!(parse_kernel_version("YomiOS Kernel v   "), None);
+    }
+}
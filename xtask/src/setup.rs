@@ -14,6 +14,111 @@ use crate::util::{
     print_warning,
 };
 
+const NASM: &str = "nasm";
+const QEMU: &str = "qemu-system-x86_64";
+const LLD: &str = "ld.lld";
+const WSL: &str = "wsl";
+const WSL_GRUB: &str = "grub-mkrescue (WSL)";
+const GRUB: &str = "grub-mkrescue";
+const XORRISO: &str = "xorriso";
+const BREW: &str = "brew";
+const NIGHTLY: &str = "nightly toolchain";
+const KERNEL_TARGET: &str = "x86_64-unknown-none";
+const RUST_SRC: &str = "rust-src";
+const LLVM_TOOLS: &str = "llvm-tools";
+
+/// A tool the build needs that is not installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolCheck {
+    /// Name of the tool
+    pub name: String,
+    /// Command that installs the tool
+    pub install_hint: String,
+}
+
+/// Tools found and not found by `check_environment`
+#[derive(Debug, Default)]
+pub struct CheckResult {
+    pub missing: Vec<ToolCheck>,
+    pub present: Vec<String>,
+}
+
+impl CheckResult {
+    fn record(&mut self, name: &str, installed: bool, install_hint: impl Into<String>) {
+        if installed {
+            self.present.push(name.to_string());
+        } else {
+            self.missing.push(ToolCheck {
+                name: name.to_string(),
+                install_hint: install_hint.into(),
+            });
+        }
+    }
+
+    fn is_missing(&self, name: &str) -> bool {
+        self.missing.iter().any(|tool| tool.name == name)
+    }
+
+    /// Formats the result as a table with a row per tool
+    pub fn summary(&self) -> String {
+        let width = self
+            .present
+            .iter()
+            .chain(self.missing.iter().map(|tool| &tool.name))
+            .map(String::len)
+            .fold("Tool".len(), usize::max);
+
+        let mut rows = vec![format!("{:<width$}  {:<7}  Install with", "Tool", "Status")];
+        for name in &self.present {
+            rows.push(format!("{:<width$}  ok", name));
+        }
+        for tool in &self.missing {
+            rows.push(format!(
+                "{:<width$}  {:<7}  {}",
+                tool.name, "missing", tool.install_hint
+            ));
+        }
+        rows.join("\n")
+    }
+}
+
+/// Look for the tools the build needs without installing anything
+pub fn check_environment() -> Result<CheckResult> {
+    check_environment_with(&command_exists)
+}
+
+/// `check_environment`, with `exists` telling whether a command is
+/// installed
+fn check_environment_with(exists: &dyn Fn(&str) -> bool) -> Result<CheckResult> {
+    let mut result = CheckResult::default();
+    match std::env::consts::OS {
+        "windows" => check_windows(&mut result, exists),
+        "linux" => check_linux(&mut result, exists),
+        "macos" => check_macos(&mut result, exists),
+        os => anyhow::bail!("Unsupported operating system: {}", os),
+    }
+    check_rust_components(&mut result);
+    Ok(result)
+}
+
+/// Report missing tools, failing if any are missing (`xtask setup --check`)
+pub fn verify_environment() -> Result<()> {
+    print_step("Checking Development Environment");
+
+    let result = check_environment()?;
+    println!("{}", result.summary());
+
+    if !result.missing.is_empty() {
+        anyhow::bail!(
+            "{} of {} tools are missing",
+            result.missing.len(),
+            result.missing.len() + result.present.len()
+        );
+    }
+    print_success("All tools are installed");
+    Ok(())
+}
+
 /// Setup development environment
 pub fn setup_environment() -> Result<()> {
     print_step("Setting up Development Environment");
@@ -21,25 +126,147 @@ pub fn setup_environment() -> Result<()> {
     let os = std::env::consts::OS;
     print_info(&format!("Detected OS: {}", os));
 
+    let check = check_environment()?;
     match os {
-        "windows" => setup_windows()?,
-        "linux" => setup_linux()?,
-        "macos" => setup_macos()?,
-        _ => anyhow::bail!("Unsupported operating system: {}", os),
+        "windows" => setup_windows(&check)?,
+        "linux" => setup_linux(&check)?,
+        "macos" => setup_macos(&check)?,
+        _ => unreachable!("check_environment rejects other systems"),
     }
 
     // Common setup for all platforms
-    setup_rust_components()?;
+    setup_rust_components(&check)?;
 
     print_success("Development environment setup complete!");
     Ok(())
 }
 
-fn setup_windows() -> Result<()> {
+/// Whether `wsl --version` runs, i.e. WSL is installed
+fn wsl_available() -> bool {
+    Command::new("wsl")
+        .args(["--version"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn check_windows(result: &mut CheckResult, exists: &dyn Fn(&str) -> bool) {
+    let winget = |id: &str| format!("winget install --id {} -e", id);
+    result.record(NASM, exists("nasm"), winget("NASM.NASM"));
+    result.record(
+        QEMU,
+        exists("qemu-system-x86_64"),
+        winget("SoftwareFreedomConservancy.QEMU"),
+    );
+    result.record(LLD, exists("ld.lld"), winget("LLVM.LLVM"));
+
+    // grub-mkrescue runs inside WSL
+    let wsl = wsl_available();
+    result.record(WSL, wsl, "wsl --install");
+    if wsl {
+        let grub = Command::new("wsl")
+            .args(["which", "grub-mkrescue"])
+            .output()
+            .is_ok_and(|output| output.status.success());
+        result.record(
+            WSL_GRUB,
+            grub,
+            "wsl sudo apt install -y grub-pc-bin xorriso",
+        );
+    }
+}
+
+/// The Linux package manager and the command that installs a package with it
+fn linux_package_manager(
+    exists: &dyn Fn(&str) -> bool,
+) -> Option<(&'static str, Vec<&'static str>)> {
+    if exists("apt") {
+        Some(("apt", vec!["sudo", "apt", "install", "-y"]))
+    } else if exists("dnf") {
+        Some(("dnf", vec!["sudo", "dnf", "install", "-y"]))
+    } else if exists("pacman") {
+        Some(("pacman", vec!["sudo", "pacman", "-S", "--noconfirm"]))
+    } else {
+        None
+    }
+}
+
+/// Package providing grub-mkrescue
+fn grub_package(pkg_manager: &str) -> &'static str {
+    match pkg_manager {
+        "apt" => "grub-pc-bin",
+        "dnf" => "grub2-tools",
+        _ => "grub",
+    }
+}
+
+fn check_linux(result: &mut CheckResult, exists: &dyn Fn(&str) -> bool) {
+    let package_manager = linux_package_manager(exists);
+    let hint = |package: &str| match &package_manager {
+        Some((_, install_cmd)) => format!("{} {}", install_cmd.join(" "), package),
+        None => format!("install the {} package", package),
+    };
+    let grub = package_manager
+        .as_ref()
+        .map_or("grub", |(pkg_manager, _)| grub_package(pkg_manager));
+
+    result.record(NASM, exists("nasm"), hint("nasm"));
+    result.record(QEMU, exists("qemu-system-x86_64"), hint("qemu-system-x86"));
+    result.record(GRUB, exists("grub-mkrescue"), hint(grub));
+    result.record(XORRISO, exists("xorriso"), hint("xorriso"));
+}
+
+fn check_macos(result: &mut CheckResult, exists: &dyn Fn(&str) -> bool) {
+    result.record(BREW, exists("brew"), "see https://brew.sh");
+    result.record(NASM, exists("nasm"), "brew install nasm");
+    result.record(QEMU, exists("qemu-system-x86_64"), "brew install qemu");
+    result.record(XORRISO, exists("xorriso"), "brew install xorriso");
+}
+
+/// Whether a line of `rustup <args>` output starts with `item`
+fn rustup_lists(args: &[&str], item: &str) -> bool {
+    Command::new("rustup")
+        .args(args)
+        .output()
+        .is_ok_and(|output| {
+            output.status.success()
+                && String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .any(|line| line.starts_with(item))
+        })
+}
+
+fn check_rust_components(result: &mut CheckResult) {
+    result.record(
+        NIGHTLY,
+        rustup_lists(&["toolchain", "list"], "nightly"),
+        "rustup toolchain install nightly",
+    );
+    result.record(
+        KERNEL_TARGET,
+        rustup_lists(
+            &["target", "list", "--installed", "--toolchain", "nightly"],
+            KERNEL_TARGET,
+        ),
+        "rustup target add x86_64-unknown-none --toolchain nightly",
+    );
+    let components = ["component", "list", "--installed", "--toolchain", "nightly"];
+    result.record(
+        RUST_SRC,
+        rustup_lists(&components, RUST_SRC),
+        "rustup component add rust-src --toolchain nightly",
+    );
+    result.record(
+        LLVM_TOOLS,
+        rustup_lists(&components, LLVM_TOOLS),
+        "rustup component add llvm-tools --toolchain nightly",
+    );
+}
+
+fn setup_windows(check: &CheckResult) -> Result<()> {
     print_step("Windows Setup");
 
     // Check for NASM
-    if !command_exists("nasm") {
+    if check.is_missing(NASM) {
         print_info("NASM not found. Installing via winget...");
         let status = Command::new("winget")
             .args([
@@ -66,7 +293,7 @@ fn setup_windows() -> Result<()> {
     }
 
     // Check for QEMU
-    if !command_exists("qemu-system-x86_64") {
+    if check.is_missing(QEMU) {
         print_info("QEMU not found. Installing via winget...");
         let status = Command::new("winget")
             .args([
@@ -91,7 +318,7 @@ fn setup_windows() -> Result<()> {
     }
 
     // Check for LLVM (needed for ld.lld linker)
-    if !command_exists("ld.lld") {
+    if check.is_missing(LLD) {
         print_info("LLVM (ld.lld) not found. Installing via winget...");
         let status = Command::new("winget")
             .args([
@@ -117,34 +344,24 @@ fn setup_windows() -> Result<()> {
 
     // Check for WSL (needed for grub-mkrescue)
     print_info("Checking WSL availability for ISO creation...");
-    let wsl_check = Command::new("wsl").args(["--version"]).output();
-
-    match wsl_check {
-        Ok(output) if output.status.success() => {
-            print_success("WSL is available");
-            setup_wsl_dependencies()?;
-        }
-        _ => {
-            print_warning("WSL is not available or not installed");
-            print_info("WSL is required for creating bootable ISO images");
-            print_info("Install WSL: wsl --install");
-            print_info("Then run: cargo x setup");
-        }
+    if check.is_missing(WSL) {
+        print_warning("WSL is not available or not installed");
+        print_info("WSL is required for creating bootable ISO images");
+        print_info("Install WSL: wsl --install");
+        print_info("Then run: cargo x setup");
+    } else {
+        print_success("WSL is available");
+        setup_wsl_dependencies(check)?;
     }
 
     Ok(())
 }
 
-fn setup_wsl_dependencies() -> Result<()> {
+fn setup_wsl_dependencies(check: &CheckResult) -> Result<()> {
     print_info("Checking WSL dependencies for ISO creation...");
 
     // Check if grub-mkrescue is available in WSL
-    let grub_check = Command::new("wsl")
-        .args(["which", "grub-mkrescue"])
-        .output()
-        .context("Failed to check grub-mkrescue in WSL")?;
-
-    if !grub_check.status.success() {
+    if check.is_missing(WSL_GRUB) {
         print_info("Installing grub and xorriso in WSL...");
         let install_status = Command::new("wsl")
             .args(["sudo", "apt", "update"])
@@ -175,17 +392,11 @@ fn setup_wsl_dependencies() -> Result<()> {
     Ok(())
 }
 
-fn setup_linux() -> Result<()> {
+fn setup_linux(check: &CheckResult) -> Result<()> {
     print_step("Linux Setup");
 
     // Check for package manager
-    let (pkg_manager, install_cmd) = if command_exists("apt") {
-        ("apt", vec!["sudo", "apt", "install", "-y"])
-    } else if command_exists("dnf") {
-        ("dnf", vec!["sudo", "dnf", "install", "-y"])
-    } else if command_exists("pacman") {
-        ("pacman", vec!["sudo", "pacman", "-S", "--noconfirm"])
-    } else {
+    let Some((pkg_manager, install_cmd)) = linux_package_manager(&command_exists) else {
         print_warning("Could not detect package manager");
         print_info("Please install manually: nasm, qemu-system-x86, grub-pc-bin, xorriso");
         return Ok(());
@@ -194,7 +405,7 @@ fn setup_linux() -> Result<()> {
     print_info(&format!("Detected package manager: {}", pkg_manager));
 
     // Install NASM
-    if !command_exists("nasm") {
+    if check.is_missing(NASM) {
         print_info("Installing NASM...");
         let mut cmd = Command::new(install_cmd[0]);
         cmd.args(&install_cmd[1..]);
@@ -208,7 +419,7 @@ fn setup_linux() -> Result<()> {
     }
 
     // Install QEMU
-    if !command_exists("qemu-system-x86_64") {
+    if check.is_missing(QEMU) {
         print_info("Installing QEMU...");
         let qemu_pkg = match pkg_manager {
             "apt" => "qemu-system-x86",
@@ -228,15 +439,9 @@ fn setup_linux() -> Result<()> {
     }
 
     // Install grub tools
-    if !command_exists("grub-mkrescue") {
+    if check.is_missing(GRUB) || check.is_missing(XORRISO) {
         print_info("Installing GRUB tools...");
-        let grub_pkgs: &[&str] = match pkg_manager {
-            "apt" => &["grub-pc-bin", "xorriso"],
-            "dnf" => &["grub2-tools", "xorriso"],
-            "pacman" => &["grub", "xorriso"],
-            _ => &["grub", "xorriso"],
-        };
-        for pkg in grub_pkgs {
+        for pkg in [grub_package(pkg_manager), "xorriso"] {
             let mut cmd = Command::new(install_cmd[0]);
             cmd.args(&install_cmd[1..]);
             cmd.arg(pkg);
@@ -250,17 +455,17 @@ fn setup_linux() -> Result<()> {
     Ok(())
 }
 
-fn setup_macos() -> Result<()> {
+fn setup_macos(check: &CheckResult) -> Result<()> {
     print_step("macOS Setup");
 
-    if !command_exists("brew") {
+    if check.is_missing(BREW) {
         print_error("Homebrew is required but not installed");
         print_info("Install Homebrew: /bin/bash -c \"$(curl -fsSL https://raw.githubusercontent.com/Homebrew/install/HEAD/install.sh)\"");
         return Ok(());
     }
 
     // Install NASM
-    if !command_exists("nasm") {
+    if check.is_missing(NASM) {
         print_info("Installing NASM...");
         Command::new("brew")
             .args(["install", "nasm"])
@@ -272,7 +477,7 @@ fn setup_macos() -> Result<()> {
     }
 
     // Install QEMU
-    if !command_exists("qemu-system-x86_64") {
+    if check.is_missing(QEMU) {
         print_info("Installing QEMU...");
         Command::new("brew")
             .args(["install", "qemu"])
@@ -284,8 +489,10 @@ fn setup_macos() -> Result<()> {
     }
 
     // Install xorriso (grub-mkrescue depends on it)
-    print_info("Installing xorriso...");
-    let _ = Command::new("brew").args(["install", "xorriso"]).status();
+    if check.is_missing(XORRISO) {
+        print_info("Installing xorriso...");
+        let _ = Command::new("brew").args(["install", "xorriso"]).status();
+    }
 
     print_warning("grub-mkrescue may not be available on macOS");
     print_info("Consider using a Docker container or VM for ISO creation");
@@ -293,58 +500,86 @@ fn setup_macos() -> Result<()> {
     Ok(())
 }
 
-fn setup_rust_components() -> Result<()> {
+fn setup_rust_components(check: &CheckResult) -> Result<()> {
     print_step("Rust Components");
 
-    // Ensure nightly toolchain
-    print_info("Checking Rust nightly toolchain...");
-    let status = Command::new("rustup")
-        .args(["toolchain", "install", "nightly"])
-        .status()
-        .context("Failed to install nightly toolchain")?;
+    let steps: [(&str, &[&str], &str); 4] = [
+        (
+            NIGHTLY,
+            &["toolchain", "install", "nightly"],
+            "Nightly toolchain",
+        ),
+        (
+            KERNEL_TARGET,
+            &[
+                "target",
+                "add",
+                "x86_64-unknown-none",
+                "--toolchain",
+                "nightly",
+            ],
+            "Target x86_64-unknown-none",
+        ),
+        // Needed for build-std
+        (
+            RUST_SRC,
+            &["component", "add", "rust-src", "--toolchain", "nightly"],
+            "rust-src component",
+        ),
+        // Provides rust-lld
+        (
+            LLVM_TOOLS,
+            &["component", "add", "llvm-tools", "--toolchain", "nightly"],
+            "llvm-tools component",
+        ),
+    ];
+    for (name, args, description) in steps {
+        if !check.is_missing(name) {
+            print_success(&format!("{} is already installed", description));
+            continue;
+        }
 
-    if status.success() {
-        print_success("Nightly toolchain available");
+        print_info(&format!("Installing {}...", description));
+        let status = Command::new("rustup")
+            .args(args)
+            .status()
+            .with_context(|| format!("Failed to install {}", description))?;
+        if status.success() {
+            print_success(&format!("{} installed", description));
+        } else {
+            print_error(&format!("Failed to install {}", description));
+        }
     }
 
-    // Add x86_64-unknown-none target
-    print_info("Adding x86_64-unknown-none target...");
-    let status = Command::new("rustup")
-        .args([
-            "target",
-            "add",
-            "x86_64-unknown-none",
-            "--toolchain",
-            "nightly",
-        ])
-        .status()
-        .context("Failed to add target")?;
-
-    if status.success() {
-        print_success("Target x86_64-unknown-none added");
-    }
+    Ok(())
+}
 
-    // Add rust-src component (needed for build-std)
-    print_info("Adding rust-src component...");
-    let status = Command::new("rustup")
-        .args(["component", "add", "rust-src", "--toolchain", "nightly"])
-        .status()
-        .context("Failed to add rust-src")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if status.success() {
-        print_success("rust-src component added");
+    #[test]
+    fn check_records_installed_commands() {
+        let result = check_environment_with(&|cmd| cmd == "qemu-system-x86_64").unwrap();
+        assert!(result.present.iter().any(|name| name == QEMU));
+        assert!(!result.is_missing(QEMU));
+        assert!(result.is_missing(NASM));
     }
 
-    // Add llvm-tools for rust-lld
-    print_info("Adding llvm-tools component...");
-    let status = Command::new("rustup")
-        .args(["component", "add", "llvm-tools", "--toolchain", "nightly"])
-        .status()
-        .context("Failed to add llvm-tools")?;
-
-    if status.success() {
-        print_success("llvm-tools component added");
+    #[test]
+    fn summary_lists_install_hints_for_missing_tools() {
+        let mut result = CheckResult::default();
+        result.record(NASM, true, "unused");
+        result.record(QEMU, false, "sudo apt install -y qemu-system-x86");
+
+        assert_eq!(
+            result.summary(),
+            "Tool                Status   Install with\nnasm                \
+             ok\nqemu-system-x86_64  missing  sudo apt install -y qemu-system-x86"
+        );
+        assert_eq!(result.missing, [ToolCheck {
+            name: QEMU.to_string(),
+            install_hint: "sudo apt install -y qemu-system-x86".to_string(),
+        }]);
     }
-
-    Ok(())
 }
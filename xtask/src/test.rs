@@ -1,6 +1,20 @@
 use std::{
     fs,
-    process::Command,
+    io::Read,
+    path::Path,
+    process::{
+        Child,
+        Command,
+        ExitStatus,
+        Stdio,
+    },
+    sync::{
+        Arc,
+        Mutex,
+        mpsc,
+    },
+    thread,
+    time::Duration,
 };
 
 use anyhow::{
@@ -8,18 +22,47 @@ use anyhow::{
     Result,
 };
 
-use crate::util::{
-    kernel_target_dir,
-    print_error,
-    print_info,
-    print_step,
-    print_success,
-    print_warning,
-    project_root,
+use crate::{
+    qemu::serial_arg,
+    util::{
+        kernel_target_dir,
+        print_error,
+        print_info,
+        print_step,
+        print_success,
+        print_warning,
+        project_root,
+    },
 };
 
+/// Tests that pass by resetting the machine from the kernel panic
+/// handler, with the kernel cfg each is built with
+///
+/// QEMU runs with `-no-reboot`, so the reset makes it exit with status 0.
+const REBOOT_TESTS: &[(&str, &str)] = &[("panic_reboot", "panic_reboot_secs=\"1\"")];
+
+/// Seconds a reboot test has to reset the machine
+const REBOOT_TEST_TIMEOUT_SECS: u64 = 3;
+
+/// QEMU exit status of a test that passed through isa-debug-exit
+///
+/// isa-debug-exit returns `(exit_value << 1) | 1`, so success (0x10)
+/// becomes 33.
+const DEBUG_EXIT_SUCCESS: i32 = 33;
+
 /// Run integration tests
-pub fn run_tests(filter: Option<&str>) -> Result<()> {
+///
+/// With `capture`, each test's serial output is written to that file by
+/// QEMU; once all tests ran the file holds the output of every test, each
+/// under a `==> name <==` header. The output of failed tests is also
+/// printed to stderr.
+///
+/// A test still running after `timeout_secs` seconds is killed and counted
+/// as timed out; 0 disables the timeout. Reboot tests always get
+/// `REBOOT_TEST_TIMEOUT_SECS`.
+pub fn run_tests(filter: Option<&str>, capture: Option<&Path>, timeout_secs: u64) -> Result<()> {
+    let timeout = (timeout_secs > 0).then(|| Duration::from_secs(timeout_secs));
+
     print_step("Running Integration Tests");
 
     let root = project_root()?;
@@ -83,35 +126,17 @@ pub fn run_tests(filter: Option<&str>) -> Result<()> {
 
     let mut passed = 0;
     let mut failed = 0;
+    let mut timed_out = 0;
     let mut failed_tests = Vec::new();
+    let mut timed_out_tests = Vec::new();
+    let mut captured_log = String::new();
 
     for (test_name, _test_path) in test_files {
         print_info(&format!("Running test: {}", test_name));
 
         // Build the test binary
         let build_result = Command::new("cargo")
-            .args([
-                "rustc",
-                "--manifest-path",
-                "kernel/Cargo.toml",
-                "--test",
-                &test_name,
-                "--target",
-                "x86_64-unknown-none",
-                "-Z",
-                "build-std=core,compiler_builtins,alloc",
-                "-Z",
-                "build-std-features=compiler-builtins-mem",
-                "--",
-                "-C",
-                "link-arg=--nmagic",
-                "-C",
-                "link-arg=--no-dynamic-linker",
-                "-C",
-                "link-arg=-Tkernel/linker.ld",
-                "-C",
-                "relocation-model=static",
-            ])
+            .args(test_build_args(&test_name))
             .current_dir(&root)
             .output()
             .context("Failed to build test")?;
@@ -147,29 +172,43 @@ pub fn run_tests(filter: Option<&str>) -> Result<()> {
         };
 
         // Run the test in QEMU
-        let test_result = Command::new("qemu-system-x86_64")
-            .args([
-                "-kernel",
-                test_bin.to_str().context("Invalid test binary path")?,
-                "-serial",
-                "stdio",
-                "-device",
-                "isa-debug-exit,iobase=0xf4,iosize=0x04",
-                "-no-reboot",
-                "-no-shutdown",
-                "-display",
-                "none",
-                "-m",
-                "256M",
-            ])
-            .output()
-            .context("Failed to run test in QEMU")?;
+        if let Some(path) = capture {
+            // A stale file would pass off an old log as this test's
+            let _ = fs::remove_file(path);
+        }
+        let reboot_cfg = reboot_cfg(&test_name);
+        let (test_timeout, expected_exit_code) = match reboot_cfg {
+            Some(_) => (Some(Duration::from_secs(REBOOT_TEST_TIMEOUT_SECS)), 0),
+            None => (timeout, DEBUG_EXIT_SUCCESS),
+        };
+        let mut qemu = Command::new("qemu-system-x86_64");
+        qemu.args(qemu_test_args(&test_bin, capture));
+        let test_result =
+            run_with_watchdog(qemu, test_timeout).context("Failed to run test in QEMU")?;
+        let serial_output = match capture {
+            Some(path) => {
+                let output = fs::read(path).unwrap_or_default();
+                let output = String::from_utf8_lossy(&output).into_owned();
+                captured_log.push_str(&format!("==> {} <==\n{}\n", test_name, output));
+                output
+            }
+            None => String::from_utf8_lossy(&test_result.stdout).into_owned(),
+        };
 
         let exit_code = test_result.status.code().unwrap_or(1);
 
-        // QEMU isa-debug-exit returns: (exit_value << 1) | 1
-        // Success (0x10) becomes 33
-        if exit_code == 33 {
+        if test_result.timed_out {
+            print_error(&format!(
+                "✗ Test timed out: {} (after {}s)",
+                test_name,
+                test_timeout.map_or(0, |timeout| timeout.as_secs())
+            ));
+            if let Some(phase) = parse_boot_phase(&serial_output) {
+                print_info(&format!("Kernel reached boot phase: {}", phase));
+            }
+            timed_out += 1;
+            timed_out_tests.push(test_name);
+        } else if exit_code == expected_exit_code {
             print_success(&format!("✓ Test passed: {}", test_name));
             passed += 1;
         } else {
@@ -177,16 +216,31 @@ pub fn run_tests(filter: Option<&str>) -> Result<()> {
                 "✗ Test failed: {} (exit code: {})",
                 test_name, exit_code
             ));
+            if let Some(phase) = parse_boot_phase(&serial_output) {
+                print_info(&format!("Kernel reached boot phase: {}", phase));
+            }
+            if capture.is_some() {
+                eprintln!("--- serial output of {} ---", test_name);
+                eprintln!("{}", serial_output.trim_end());
+                eprintln!("--- end of serial output ---");
+            }
             failed += 1;
             failed_tests.push(test_name);
         }
     }
 
+    if let Some(path) = capture {
+        fs::write(path, &captured_log)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        print_info(&format!("Serial output saved to {}", path.display()));
+    }
+
     // Print summary
     println!("\n{}", "=".repeat(50));
     println!("Test Results:");
     println!("  Passed: {}", passed);
     println!("  Failed: {}", failed);
+    println!("  Timed out: {}", timed_out);
     println!("{}", "=".repeat(50));
 
     if failed > 0 {
@@ -194,9 +248,280 @@ pub fn run_tests(filter: Option<&str>) -> Result<()> {
         for test in &failed_tests {
             println!("  - {}", test);
         }
-        anyhow::bail!("{} test(s) failed", failed);
+    }
+    if timed_out > 0 {
+        println!("\nTimed out tests:");
+        for test in &timed_out_tests {
+            println!("  - {}", test);
+        }
+    }
+    if failed + timed_out > 0 {
+        anyhow::bail!("{} test(s) failed, {} timed out", failed, timed_out);
     }
 
     print_success("All tests passed!");
     Ok(())
 }
+
+/// Returns the kernel cfg a reboot test is built with, or `None` for
+/// ordinary tests
+fn reboot_cfg(test_name: &str) -> Option<&'static str> {
+    REBOOT_TESTS
+        .iter()
+        .find(|(name, _)| *name == test_name)
+        .map(|(_, cfg)| *cfg)
+}
+
+/// Cargo arguments building the integration test `test_name`
+///
+/// Reboot tests add their cfg to the target rustflags through `--config`,
+/// which cargo appends to the flags from `.cargo/config.toml` rather than
+/// replacing them. The kernel library is rebuilt with it too, which is
+/// where the panic handler lives.
+fn test_build_args(test_name: &str) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "rustc",
+        "--manifest-path",
+        "kernel/Cargo.toml",
+        "--test",
+        test_name,
+        "--target",
+        "x86_64-unknown-none",
+        "-Z",
+        "build-std=core,compiler_builtins,alloc",
+        "-Z",
+        "build-std-features=compiler-builtins-mem",
+    ]
+    .map(String::from)
+    .to_vec();
+    if let Some(cfg) = reboot_cfg(test_name) {
+        args.push("--config".to_string());
+        args.push(format!(
+            "target.x86_64-unknown-none.rustflags=[\"--cfg\", {:?}]",
+            cfg
+        ));
+    }
+    args.extend(
+        [
+            "--",
+            "-C",
+            "link-arg=--nmagic",
+            "-C",
+            "link-arg=--no-dynamic-linker",
+            "-C",
+            "link-arg=-Tkernel/linker.ld",
+            "-C",
+            "relocation-model=static",
+        ]
+        .map(String::from),
+    );
+    args
+}
+
+/// Result of a command run under `run_with_watchdog`
+struct WatchdogOutput {
+    status: ExitStatus,
+    stdout: Vec<u8>,
+    /// The watchdog killed the command
+    timed_out: bool,
+}
+
+/// Interval at which `run_with_watchdog` checks whether the child exited
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Runs `cmd`, capturing stdout, and kills it if it runs longer than
+/// `timeout`
+///
+/// A watchdog thread waits for the child to finish for up to `timeout`;
+/// if it has not, the watchdog kills it. `None` runs without a watchdog.
+fn run_with_watchdog(mut cmd: Command, timeout: Option<Duration>) -> Result<WatchdogOutput> {
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn")?;
+
+    // Read stdout concurrently so a chatty child cannot fill the pipe and
+    // block
+    let mut stdout = child.stdout.take().context("Failed to capture stdout")?;
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+
+    let child = Arc::new(Mutex::new(child));
+    let (done, exited) = mpsc::channel::<()>();
+    let watchdog = timeout.map(|timeout| {
+        let child = Arc::clone(&child);
+        thread::spawn(move || {
+            // Woken early, or disconnected, once the child exits
+            if exited.recv_timeout(timeout) != Err(mpsc::RecvTimeoutError::Timeout) {
+                return false;
+            }
+            let mut child = lock(&child);
+            if matches!(child.try_wait(), Ok(None)) {
+                let _ = child.kill();
+                return true;
+            }
+            false
+        })
+    });
+
+    let status = loop {
+        if let Some(status) = lock(&child).try_wait().context("Failed to wait")? {
+            break status;
+        }
+        thread::sleep(WAIT_POLL_INTERVAL);
+    };
+    let _ = done.send(());
+    let timed_out = watchdog.is_some_and(|watchdog| watchdog.join().unwrap_or(false));
+    let stdout = reader.join().unwrap_or_default();
+
+    Ok(WatchdogOutput {
+        status,
+        stdout,
+        timed_out,
+    })
+}
+
+/// Locks the shared child, ignoring poisoning by a panicked thread
+fn lock(child: &Mutex<Child>) -> std::sync::MutexGuard<'_, Child> {
+    child.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// QEMU arguments for running the test kernel `test_bin`
+///
+/// Serial output goes to stdout, or to `capture` if given.
+fn qemu_test_args(test_bin: &Path, capture: Option<&Path>) -> Vec<String> {
+    let mut args = vec![
+        "-kernel".to_string(),
+        test_bin.display().to_string(),
+        "-serial".to_string(),
+        serial_arg(capture),
+    ];
+    args.extend(
+        [
+            "-device",
+            "isa-debug-exit,iobase=0xf4,iosize=0x04",
+            // VirtIO device for the PCI enumeration test
+            "-device",
+            "virtio-rng-pci",
+            "-no-reboot",
+            "-no-shutdown",
+            "-display",
+            "none",
+            "-m",
+            "256M",
+        ]
+        .map(String::from),
+    );
+    args
+}
+
+/// Extract the boot phase reported by the kernel panic handler
+///
+/// The kernel prints `Boot phase: <name>` when it panics. If several lines
+/// match, the last one wins since it is closest to the failure.
+fn parse_boot_phase(serial_output: &str) -> Option<&str> {
+    const MARKER: &str = "Boot phase:";
+
+    serial_output
+        .lines()
+        .rev()
+        .find_map(|line| {
+            line.find(MARKER)
+                .map(|idx| line[idx + MARKER.len()..].trim())
+        })
+        .filter(|phase| !phase.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qemu_args_use_console_without_capture() {
+        let args = qemu_test_args(Path::new("/build/basic_boot"), None);
+        assert_eq!(args[..4], [
+            "-kernel",
+            "/build/basic_boot",
+            "-serial",
+            "stdio"
+        ]);
+    }
+
+    #[test]
+    fn qemu_args_redirect_serial_to_capture_file() {
+        let capture = std::env::temp_dir().join("yomi-serial.log");
+        let args = qemu_test_args(Path::new("/build/basic_boot"), Some(&capture));
+
+        let serial = args.iter().position(|arg| arg == "-serial").unwrap();
+        assert_eq!(args[serial + 1], format!("file:{}", capture.display()));
+        assert!(!args.iter().any(|arg| arg == "stdio"));
+        assert_eq!(args.iter().filter(|arg| *arg == "-serial").count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watchdog_kills_hung_child() {
+        let mut cmd = Command::new("sleep");
+        cmd.arg("60");
+
+        let start = std::time::Instant::now();
+        let output = run_with_watchdog(cmd, Some(Duration::from_millis(200))).unwrap();
+
+        assert!(output.timed_out);
+        assert!(!output.status.success());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watchdog_leaves_finished_child_alone() {
+        let mut cmd = Command::new("echo");
+        cmd.arg("done");
+
+        let output = run_with_watchdog(cmd, Some(Duration::from_secs(30))).unwrap();
+        assert!(!output.timed_out);
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"done\n");
+
+        let mut cmd = Command::new("echo");
+        cmd.arg("no watchdog");
+        assert!(!run_with_watchdog(cmd, None).unwrap().timed_out);
+    }
+
+    #[test]
+    fn build_args_add_cfg_for_reboot_tests() {
+        let args = test_build_args("panic_reboot");
+        let config = args.iter().position(|arg| arg == "--config").unwrap();
+        assert_eq!(
+            args[config + 1],
+            r#"target.x86_64-unknown-none.rustflags=["--cfg", "panic_reboot_secs=\"1\""]"#
+        );
+        // Cargo options must come before the rustc ones
+        assert!(config < args.iter().position(|arg| arg == "--").unwrap());
+
+        assert!(!test_build_args("basic_boot").contains(&"--config".to_string()));
+        assert_eq!(reboot_cfg("basic_boot"), None);
+    }
+
+    #[test]
+    fn parse_boot_phase_finds_marker() {
+        let output = "Running 3 tests\n[FAILED]\nBoot phase: HeapReady\nError: oops\n";
+        assert_eq!(parse_boot_phase(output), Some("HeapReady"));
+    }
+
+    #[test]
+    fn parse_boot_phase_uses_last_marker() {
+        let output = "Boot phase: SerialReady\nBoot phase: IdtReady\r\n";
+        assert_eq!(parse_boot_phase(output), Some("IdtReady"));
+    }
+
+    #[test]
+    fn parse_boot_phase_missing() {
+        assert_eq!(parse_boot_phase("no panic here\n"), None);
+        assert_eq!(parse_boot_phase("Boot phase:   \n"), None);
+    }
+}
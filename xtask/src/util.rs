@@ -93,6 +93,31 @@ pub fn ensure_file_exists(path: &Path, build_hint: &str) -> Result<()> {
     Ok(())
 }
 
+/// Parse a QEMU memory size such as `512M` or `2G` into a byte count
+///
+/// Only whole, non-zero amounts of mebibytes (`M`) or gibibytes (`G`) are
+/// accepted, matching what `qemu -m` is given.
+pub fn parse_memory_size(s: &str) -> Result<u64> {
+    let invalid = || anyhow::anyhow!("invalid memory size '{}' (expected e.g. 256M or 2G)", s);
+
+    let (digits, shift) = if let Some(digits) = s.strip_suffix('M') {
+        (digits, 20)
+    } else if let Some(digits) = s.strip_suffix('G') {
+        (digits, 30)
+    } else {
+        return Err(invalid());
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let amount: u64 = digits.parse().map_err(|_| invalid())?;
+    if amount == 0 {
+        return Err(invalid());
+    }
+    amount.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
 /// Print a success message
 pub fn print_success(msg: &str) {
     println!("{} {}", "✓".green().bold(), msg.green());